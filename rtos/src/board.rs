@@ -69,6 +69,113 @@ assign_resources! {
         tx_dma: DMA2_CH0,
         rx_dma: DMA2_CH1,
         rtc_power_key: PG10,
+        wifi_reset: PH15,   // D46, active-low module reset
+        wifi_boot: PJ12,    // D22, active-low module boot/download select
+    },
+    // GIGA R1 WiFi: Serial4 / D14 (TX3), D15 (RX3). Host adapter side of the UART bridge.
+    host_uart: HostUartResource {
+        peri: USART6,
+        tx: PG14,
+        rx: PC7,
+    },
+    // GIGA R1 WiFi: passive sniffer inputs, RX only. Line A on D19 (RX1), line B on D17 (RX2).
+    sniffer: SnifferResource {
+        a_peri: USART2,
+        a_rx: PD6,
+        a_rx_dma: DMA1_CH0,
+        b_peri: UART4,
+        b_rx: PI9,
+        b_rx_dma: DMA1_CH1,
+    },
+    // GIGA R1 WiFi: PPP link to a cellular modem or host. TX on D4, RX on D57. The RNG seeds the
+    // network stack and the LCP magic number.
+    ppp: PppResource {
+        peri: UART8,
+        tx: PJ8,
+        rx: PJ9,
+        rng: RNG,
+    },
+    // GIGA R1 WiFi: Bluetooth HCI of the Murata 1DX (CYW4343W), wired on the board, not to a
+    // header. BT_REG_ON powers the Bluetooth half of the module.
+    bluetooth: BluetoothResource {
+        peri: UART7,
+        tx: PF7,
+        rx: PA8,
+        rts: PF8,
+        cts: PF9,
+        power: PA10,        // BT_REG_ON
+    },
+    // GIGA R1 WiFi: driver enable of an RS-485 transceiver on D0/D1 (USART1), on D2.
+    rs485: Rs485Resource {
+        de: PA3,
+    },
+    // GIGA R1 WiFi: pulse per second of a GPS receiver on USART1, on D3.
+    gps: GpsResource {
+        pps: PA2,
+        pps_exti: EXTI2,
+    },
+    // On-chip RTC, clocked from the LSE set up in `init`. Tamper 1, when enabled, takes PC13.
+    rtc: RtcResource {
+        peri: RTC,
+    },
+    // Portenta H7: I2C0 on the high-density connector is I2C3 (SCL PH7, SDA PH8), I2C1 is I2C1
+    // (SCL PB6, SDA PB7) and I2C2 is I2C4 (SCL PH11, SDA PH12).
+    // i2c1: I2c1Resource {
+    //     peri: I2C1,
+    //     scl: PB6,
+    //     sda: PB7,
+    //     tx_dma: DMA1_CH2,
+    //     rx_dma: DMA1_CH3,
+    // },
+
+    // GIGA R1 WiFi: Wire2, SDA2 on D9 and SCL2 on D8.
+    i2c1: I2c1Resource {
+        peri: I2C1,
+        scl: PB8,
+        sda: PB9,
+        tx_dma: DMA1_CH2,
+        rx_dma: DMA1_CH3,
+    },
+    // GIGA R1 WiFi: Wire, SDA on D20 and SCL on D21.
+    i2c2: I2c2Resource {
+        peri: I2C2,
+        scl: PH4,
+        sda: PB11,
+        tx_dma: DMA1_CH4,
+        rx_dma: DMA1_CH5,
+    },
+    // GIGA R1 WiFi: Wire1, SDA1 and SCL1 by AREF. Same pins on the Portenta H7's I2C2.
+    i2c4: I2c4Resource {
+        peri: I2C4,
+        scl: PH11,
+        sda: PH12,
+    },
+    // GIGA R1 WiFi: INT of the RV-8803 RTC on I2C2, on D5.
+    ext_rtc: ExtRtcResource {
+        int: PA7,
+        int_exti: EXTI7,
+    },
+    // GIGA R1 WiFi: ILI9342C SPI panel, write only. SCK on D13, MOSI (COPI) on D11 and CS on D10,
+    // with DC on D7, RST on D6 and the backlight enable on D24.
+    display: DisplayResource {
+        peri: SPI5,
+        sck: PH6,
+        mosi: PJ10,
+        cs: PK1,
+        dc: PB4,
+        rst: PD13,
+        backlight: PG12,
+    },
+    // GIGA R1 WiFi: the LTDC, which feeds the DSI host behind the display connector. No pins.
+    // The DSI host isn't brought up, so its frames stay in memory.
+    ltdc: LtdcResource {
+        peri: LTDC,
+    },
+    // GIGA R1 WiFi USB-C port. The Portenta H7 routes its USB-C port through a ULPI PHY instead.
+    usb: UsbResource {
+        peri: USB_OTG_FS,
+        dp: PA12,
+        dm: PA11,
     },
     giga_r1_wifi_board_leds: GigaR1WifiBoardLeds {
         red: PI12,
@@ -202,6 +309,17 @@ pub fn init() -> (embassy_stm32::Peripherals, cortex_m::Peripherals) {
                 divr: None,
             });
         }
+        #[cfg(feature = "display-ltdc")]
+        {
+            config.rcc.pll3 = Some(Pll {
+                source: PllSource::HSI,
+                prediv: PllPreDiv::DIV8,
+                mul: PllMul::MUL50,
+                divp: None,
+                divq: None,
+                divr: Some(PllDiv::DIV12), // ((64/8)*50)/12 = 33.3MHz LTDC pixel clock
+            });
+        }
         config.rcc.sys = Sysclk::PLL1_P; // 400 Mhz
         config.rcc.ahb_pre = AHBPrescaler::DIV2; // 200 Mhz
         config.rcc.apb1_pre = APBPrescaler::DIV2; // 100 Mhz
@@ -212,6 +330,7 @@ pub fn init() -> (embassy_stm32::Peripherals, cortex_m::Peripherals) {
 
         let mut mux = embassy_stm32::rcc::mux::ClockMux::default();
         mux.adcsel = embassy_stm32::rcc::mux::Adcsel::PLL2_P;
+        mux.usbsel = embassy_stm32::rcc::mux::Usbsel::HSI48;
         config.rcc.mux = mux;
 
         // RTC
@@ -0,0 +1,264 @@
+/// Escape sequence configuration, modelled on the Hayes `+++` guard-time escape.
+///
+/// The sequence only counts when it is preceded *and* followed by at least `guard_ms` of silence
+/// and its characters arrive no more than `guard_ms` apart, so binary traffic that happens to
+/// contain the escape character is forwarded untouched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EscapeConfig {
+    pub byte: u8,
+    pub count: u8,
+    pub guard_ms: u64,
+}
+
+impl Default for EscapeConfig {
+    fn default() -> Self {
+        Self {
+            byte: b'+',
+            count: 3,
+            guard_ms: 1000,
+        }
+    }
+}
+
+/// What the caller should do with a byte passed to [`EscapeDetector::feed`].
+///
+/// For `Hold` and `Forward`, `flush` copies of the escape byte, released from an earlier hold,
+/// must be forwarded first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feed {
+    /// The byte may be part of the escape sequence and is held back.
+    Hold { flush: u8 },
+    /// Forward this byte.
+    Forward { flush: u8 },
+    /// A complete sequence expired just before this byte arrived, i.e. the caller missed its
+    /// [`EscapeDetector::deadline`]. The byte was not consumed.
+    Escape,
+}
+
+/// Result of [`EscapeDetector::poll`] once the guard time has elapsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Poll {
+    /// Nothing to do yet.
+    Idle,
+    /// An incomplete sequence timed out; forward `n` copies of the escape byte.
+    Flush(u8),
+    /// The full sequence was seen with silence on both sides.
+    Escape,
+}
+
+#[derive(Debug)]
+pub struct EscapeDetector {
+    config: EscapeConfig,
+    held: u8,
+    last_ms: Option<u64>,
+}
+
+impl EscapeDetector {
+    pub const fn new(config: EscapeConfig) -> Self {
+        Self {
+            config,
+            held: 0,
+            last_ms: None,
+        }
+    }
+
+    pub fn config(&self) -> &EscapeConfig {
+        &self.config
+    }
+
+    /// Number of escape bytes currently held back.
+    pub fn held(&self) -> u8 {
+        self.held
+    }
+
+    /// Discard any partial match, e.g. when the bridge is (re-)entered.
+    pub fn reset(&mut self, now_ms: u64) {
+        self.held = 0;
+        self.last_ms = Some(now_ms);
+    }
+
+    fn silent_since(&self, now_ms: u64) -> bool {
+        match self.last_ms {
+            Some(last) => now_ms.saturating_sub(last) >= self.config.guard_ms,
+            None => true,
+        }
+    }
+
+    pub fn feed(&mut self, byte: u8, now_ms: u64) -> Feed {
+        // A held sequence may already have expired; the caller should have polled, but don't
+        // rely on it.
+        let flush = match self.poll(now_ms) {
+            Poll::Flush(n) => n,
+            Poll::Escape => {
+                self.last_ms = Some(now_ms);
+                return Feed::Escape;
+            }
+            Poll::Idle => 0,
+        };
+
+        let is_candidate = byte == self.config.byte
+            && self.held < self.config.count
+            && if self.held == 0 {
+                self.silent_since(now_ms)
+            } else {
+                !self.silent_since(now_ms)
+            };
+
+        if is_candidate {
+            self.held += 1;
+            self.last_ms = Some(now_ms);
+            return Feed::Hold { flush };
+        }
+
+        let flush = flush + self.held;
+        self.held = 0;
+        self.last_ms = Some(now_ms);
+        Feed::Forward { flush }
+    }
+
+    /// Check the guard timers. Call this at [`Self::deadline`].
+    pub fn poll(&mut self, now_ms: u64) -> Poll {
+        if self.held == 0 || !self.silent_since(now_ms) {
+            return Poll::Idle;
+        }
+
+        let held = self.held;
+        self.held = 0;
+        if held == self.config.count {
+            Poll::Escape
+        } else {
+            Poll::Flush(held)
+        }
+    }
+
+    /// Instant (in ms) at which [`Self::poll`] may produce a result, if anything is held.
+    pub fn deadline(&self) -> Option<u64> {
+        match (self.held, self.last_ms) {
+            (0, _) | (_, None) => None,
+            (_, Some(last)) => Some(last + self.config.guard_ms),
+        }
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> EscapeDetector {
+        EscapeDetector::new(EscapeConfig::default())
+    }
+
+    #[test]
+    fn detects_guarded_sequence() {
+        let mut d = detector();
+        d.reset(0);
+
+        assert_eq!(d.feed(b'+', 1000), Feed::Hold { flush: 0 });
+        assert_eq!(d.feed(b'+', 1100), Feed::Hold { flush: 0 });
+        assert_eq!(d.feed(b'+', 1200), Feed::Hold { flush: 0 });
+        assert_eq!(d.deadline(), Some(2200));
+        assert_eq!(d.poll(2199), Poll::Idle);
+        assert_eq!(d.poll(2200), Poll::Escape);
+        assert_eq!(d.held(), 0);
+    }
+
+    #[test]
+    fn requires_leading_silence() {
+        let mut d = detector();
+        d.reset(0);
+
+        assert_eq!(d.feed(b'A', 1000), Feed::Forward { flush: 0 });
+        assert_eq!(d.feed(b'+', 1500), Feed::Forward { flush: 0 });
+        assert_eq!(d.feed(b'+', 1600), Feed::Forward { flush: 0 });
+        assert_eq!(d.feed(b'+', 1700), Feed::Forward { flush: 0 });
+        assert_eq!(d.poll(5000), Poll::Idle);
+    }
+
+    #[test]
+    fn trailing_data_releases_held_bytes() {
+        let mut d = detector();
+        d.reset(0);
+
+        assert_eq!(d.feed(b'+', 2000), Feed::Hold { flush: 0 });
+        assert_eq!(d.feed(b'+', 2010), Feed::Hold { flush: 0 });
+        assert_eq!(d.feed(b'+', 2020), Feed::Hold { flush: 0 });
+        assert_eq!(d.feed(b'\r', 2030), Feed::Forward { flush: 3 });
+        assert_eq!(d.poll(9000), Poll::Idle);
+    }
+
+    #[test]
+    fn fourth_escape_byte_breaks_sequence() {
+        let mut d = detector();
+        d.reset(0);
+
+        for t in [2000, 2010, 2020] {
+            assert_eq!(d.feed(b'+', t), Feed::Hold { flush: 0 });
+        }
+        assert_eq!(d.feed(b'+', 2030), Feed::Forward { flush: 3 });
+        assert_eq!(d.deadline(), None);
+    }
+
+    #[test]
+    fn partial_sequence_times_out() {
+        let mut d = detector();
+        d.reset(0);
+
+        assert_eq!(d.feed(b'+', 2000), Feed::Hold { flush: 0 });
+        assert_eq!(d.feed(b'+', 2100), Feed::Hold { flush: 0 });
+        assert_eq!(d.poll(3100), Poll::Flush(2));
+
+        // A fresh sequence may start right after the flush, since the line was silent.
+        for t in [3100, 3200, 3300] {
+            assert_eq!(d.feed(b'+', t), Feed::Hold { flush: 0 });
+        }
+        assert_eq!(d.poll(4300), Poll::Escape);
+    }
+
+    #[test]
+    fn late_byte_without_poll_flushes_expired_hold() {
+        let mut d = detector();
+        d.reset(0);
+
+        assert_eq!(d.feed(b'+', 2000), Feed::Hold { flush: 0 });
+        // Caller never polled; the expired byte is released ahead of the new one.
+        assert_eq!(d.feed(b'x', 3500), Feed::Forward { flush: 1 });
+    }
+
+    #[test]
+    fn slow_escape_characters_do_not_match() {
+        let mut d = detector();
+        d.reset(0);
+
+        assert_eq!(d.feed(b'+', 2000), Feed::Hold { flush: 0 });
+        // Too slow: the first one is flushed, the second begins a new candidate sequence.
+        assert_eq!(d.feed(b'+', 3000), Feed::Hold { flush: 1 });
+        assert_eq!(d.held(), 1);
+    }
+
+    #[test]
+    fn missed_deadline_still_reports_escape() {
+        let mut d = detector();
+        d.reset(0);
+
+        for t in [2000, 2010, 2020] {
+            assert_eq!(d.feed(b'+', t), Feed::Hold { flush: 0 });
+        }
+        assert_eq!(d.feed(b'A', 3100), Feed::Escape);
+        assert_eq!(d.held(), 0);
+    }
+
+    #[test]
+    fn custom_sequence() {
+        let mut d = EscapeDetector::new(EscapeConfig {
+            byte: 0x1d,
+            count: 2,
+            guard_ms: 100,
+        });
+
+        assert_eq!(d.feed(0x1d, 0), Feed::Hold { flush: 0 });
+        assert_eq!(d.feed(0x1d, 50), Feed::Hold { flush: 0 });
+        assert_eq!(d.poll(150), Poll::Escape);
+    }
+}
@@ -0,0 +1,174 @@
+use core::future::pending;
+use defmt::{info, trace, warn};
+use embassy_futures::select::{select3, Either3};
+use embassy_time::{Instant, Timer};
+use embedded_io_async::{Read, Write};
+
+pub mod escape;
+pub mod stats;
+
+use escape::{EscapeConfig, EscapeDetector, Feed, Poll};
+use stats::{BridgeStats, DirectionStats};
+
+pub const BRIDGE_CHUNK_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BridgeConfig {
+    /// Sequence typed on the host port to enter and leave bridge mode.
+    pub escape: EscapeConfig,
+    /// Hex-dump host -> module traffic at `trace` level.
+    pub log_host_to_module: bool,
+    /// Hex-dump module -> host traffic at `trace` level.
+    pub log_module_to_host: bool,
+}
+
+pub(crate) fn now_ms() -> u64 {
+    Instant::now().as_millis()
+}
+
+pub(crate) async fn wait_until(deadline: Option<u64>) {
+    match deadline {
+        Some(ms) => Timer::at(Instant::from_millis(ms)).await,
+        None => pending().await,
+    }
+}
+
+async fn forward<W: Write>(tx: &mut W, data: &[u8], stats: &mut DirectionStats, log: Option<&str>) {
+    if data.is_empty() {
+        return;
+    }
+
+    if let Some(tag) = log {
+        trace!("{=str} {=[u8]:02x}", tag, data);
+    }
+    if let Err(e) = tx.write_all(data).await {
+        warn!("bridge: write failed: {}", defmt::Debug2Format(&e));
+        return;
+    }
+    stats.record(data);
+}
+
+async fn forward_escape_bytes<W: Write>(
+    tx: &mut W,
+    config: &BridgeConfig,
+    count: u8,
+    stats: &mut DirectionStats,
+) {
+    let bytes = [config.escape.byte; u8::MAX as usize];
+    let log = config.log_host_to_module.then_some("H>M");
+    forward(tx, &bytes[..count as usize], stats, log).await;
+}
+
+/// Forward bytes between the host port and the module until the escape sequence is typed on
+/// the host port. The escape bytes themselves are never forwarded; `+` characters that turn out
+/// not to be part of it are released as soon as that is known.
+pub async fn run<HR, HW, MR, MW>(
+    host_rx: &mut HR,
+    host_tx: &mut HW,
+    module_rx: &mut MR,
+    module_tx: &mut MW,
+    config: &BridgeConfig,
+) -> BridgeStats
+where
+    HR: Read,
+    HW: Write,
+    MR: Read,
+    MW: Write,
+{
+    info!("bridge: started, type the escape sequence on the host port to leave");
+
+    let mut stats = BridgeStats::new(now_ms());
+    let mut escape = EscapeDetector::new(config.escape);
+    escape.reset(now_ms());
+
+    let mut host_buf = [0u8; BRIDGE_CHUNK_SIZE];
+    let mut module_buf = [0u8; BRIDGE_CHUNK_SIZE];
+    let h2m_log = config.log_host_to_module.then_some("H>M");
+    let m2h_log = config.log_module_to_host.then_some("M>H");
+
+    'bridge: loop {
+        match select3(
+            host_rx.read(&mut host_buf),
+            module_rx.read(&mut module_buf),
+            wait_until(escape.deadline()),
+        )
+        .await
+        {
+            Either3::First(Ok(n)) => {
+                let data = &host_buf[..n];
+                let now = now_ms();
+                let mut run_start = 0;
+
+                for (i, &b) in data.iter().enumerate() {
+                    let (flush, hold) = match escape.feed(b, now) {
+                        Feed::Forward { flush } => (flush, false),
+                        Feed::Hold { flush } => (flush, true),
+                        Feed::Escape => {
+                            let pending = &data[run_start..i];
+                            forward(module_tx, pending, &mut stats.host_to_module, h2m_log).await;
+                            break 'bridge;
+                        }
+                    };
+                    if flush == 0 && !hold {
+                        continue;
+                    }
+
+                    let pending = &data[run_start..i];
+                    forward(module_tx, pending, &mut stats.host_to_module, h2m_log).await;
+                    forward_escape_bytes(module_tx, config, flush, &mut stats.host_to_module).await;
+                    run_start = if hold { i + 1 } else { i };
+                }
+
+                let pending = &data[run_start..];
+                forward(module_tx, pending, &mut stats.host_to_module, h2m_log).await;
+            }
+            Either3::First(Err(e)) => {
+                warn!("bridge: host read error: {}", defmt::Debug2Format(&e));
+                stats.host_to_module.record_error();
+            }
+            Either3::Second(Ok(n)) => {
+                let data = &module_buf[..n];
+                forward(host_tx, data, &mut stats.module_to_host, m2h_log).await;
+            }
+            Either3::Second(Err(e)) => {
+                warn!("bridge: module read error: {}", defmt::Debug2Format(&e));
+                stats.module_to_host.record_error();
+            }
+            Either3::Third(()) => match escape.poll(now_ms()) {
+                Poll::Idle => {}
+                Poll::Flush(n) => {
+                    forward_escape_bytes(module_tx, config, n, &mut stats.host_to_module).await
+                }
+                Poll::Escape => break 'bridge,
+            },
+        }
+    }
+
+    let _ = module_tx.flush().await;
+    let _ = host_tx.flush().await;
+    stats.ended_ms = now_ms();
+
+    stats
+}
+
+pub fn log_stats(stats: &BridgeStats) {
+    let elapsed = stats.elapsed_ms();
+    let h2m = &stats.host_to_module;
+    let m2h = &stats.module_to_host;
+
+    info!("bridge: closed after {} ms", elapsed);
+    info!(
+        "bridge: host->module {} bytes, {} lines, {} B/s, {} errors",
+        h2m.bytes,
+        h2m.lines,
+        h2m.bytes_per_sec(elapsed),
+        h2m.errors
+    );
+    info!(
+        "bridge: module->host {} bytes, {} lines, {} B/s, {} errors",
+        m2h.bytes,
+        m2h.lines,
+        m2h.bytes_per_sec(elapsed),
+        m2h.errors
+    );
+}
@@ -0,0 +1,111 @@
+/// Traffic counters for one direction of the bridge.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DirectionStats {
+    /// Bytes forwarded.
+    pub bytes: u64,
+    /// Completed lines, counted on `\n`.
+    pub lines: u32,
+    /// Number of reads that returned data.
+    pub chunks: u32,
+    /// Largest single read, a rough indicator of how close the RX buffer came to overrunning.
+    pub max_chunk: usize,
+    /// Read errors (overrun, framing, noise, parity) on the receiving UART.
+    pub errors: u32,
+}
+
+impl DirectionStats {
+    pub fn record(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        self.bytes += data.len() as u64;
+        self.lines += data.iter().filter(|&&b| b == b'\n').count() as u32;
+        self.chunks += 1;
+        self.max_chunk = self.max_chunk.max(data.len());
+    }
+
+    pub fn record_error(&mut self) {
+        self.errors = self.errors.saturating_add(1);
+    }
+
+    /// Average throughput in bytes per second over `elapsed_ms`.
+    pub fn bytes_per_sec(&self, elapsed_ms: u64) -> u32 {
+        if elapsed_ms == 0 {
+            return 0;
+        }
+
+        (self.bytes * 1000 / elapsed_ms) as u32
+    }
+}
+
+/// Counters for a whole bridge session.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BridgeStats {
+    pub host_to_module: DirectionStats,
+    pub module_to_host: DirectionStats,
+    pub started_ms: u64,
+    pub ended_ms: u64,
+}
+
+impl BridgeStats {
+    pub fn new(now_ms: u64) -> Self {
+        Self {
+            started_ms: now_ms,
+            ended_ms: now_ms,
+            ..Default::default()
+        }
+    }
+
+    pub fn elapsed_ms(&self) -> u64 {
+        self.ended_ms.saturating_sub(self.started_ms)
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.host_to_module.bytes + self.module_to_host.bytes
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_bytes_and_lines() {
+        let mut stats = DirectionStats::default();
+        stats.record(b"AT\r\n");
+        stats.record(b"");
+        stats.record(b"OK\r\nready\r\n+IPD");
+        stats.record_error();
+
+        assert_eq!(stats.bytes, 19);
+        assert_eq!(stats.lines, 3);
+        assert_eq!(stats.chunks, 2);
+        assert_eq!(stats.max_chunk, 15);
+        assert_eq!(stats.errors, 1);
+    }
+
+    #[test]
+    fn throughput() {
+        let mut stats = DirectionStats::default();
+        stats.record(&[0u8; 64]);
+        stats.record(&[0u8; 64]);
+
+        assert_eq!(stats.bytes_per_sec(0), 0);
+        assert_eq!(stats.bytes_per_sec(500), 256);
+        assert_eq!(stats.bytes_per_sec(2000), 64);
+    }
+
+    #[test]
+    fn session_totals() {
+        let mut stats = BridgeStats::new(1_000);
+        stats.host_to_module.record(b"AT+GMR\r\n");
+        stats.module_to_host.record(b"OK\r\n");
+        stats.ended_ms = 4_000;
+
+        assert_eq!(stats.total_bytes(), 12);
+        assert_eq!(stats.elapsed_ms(), 3_000);
+    }
+}
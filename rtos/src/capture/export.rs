@@ -0,0 +1,353 @@
+//! Binary export format for captured traffic.
+//!
+//! ```text
+//! stream  := "UCAP" version:u8 dropped:varint record* END
+//! record  := tag:u8 delta_us:varint len:varint [errors:u8] data[len]
+//! tag     := bit 0: direction (0 = rx, 1 = tx), bit 1: errors byte follows
+//! END     := 0xff
+//! ```
+//!
+//! Integers are unsigned LEB128 varints. `delta_us` is the time since the previous record, or
+//! since boot for the first one, so a typical record costs 3-4 bytes on top of its data.
+
+use super::ring::{Direction, RecordHeader};
+use crate::uart::ErrorFlags;
+
+pub const MAGIC: [u8; 4] = *b"UCAP";
+pub const VERSION: u8 = 1;
+pub const END: u8 = 0xff;
+
+const TAG_TX: u8 = 1 << 0;
+const TAG_ERRORS: u8 = 1 << 1;
+
+/// Longest encoding of a `u64` varint.
+const MAX_VARINT_LEN: usize = 10;
+/// Longest stream or record header produced by [`Encoder`].
+pub const MAX_HEADER_LEN: usize = 1 + 2 * MAX_VARINT_LEN + 1;
+
+fn put_varint(out: &mut [u8], mut value: u64) -> usize {
+    let mut n = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out[n] = byte;
+            return n + 1;
+        }
+        out[n] = byte | 0x80;
+        n += 1;
+    }
+}
+
+/// Produces the stream piecewise, so a large capture can be written out without copying it.
+#[derive(Debug, Default)]
+pub struct Encoder {
+    prev_us: u64,
+    buf: [u8; MAX_HEADER_LEN],
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stream header, `dropped` being the number of records lost before the export.
+    pub fn start(&mut self, dropped: u32) -> &[u8] {
+        self.prev_us = 0;
+        self.buf[..4].copy_from_slice(&MAGIC);
+        self.buf[4] = VERSION;
+        let n = put_varint(&mut self.buf[5..], dropped as u64);
+        &self.buf[..5 + n]
+    }
+
+    /// Bytes to write in front of the record's data.
+    pub fn record(&mut self, header: &RecordHeader) -> &[u8] {
+        let mut tag = match header.direction {
+            Direction::Rx => 0,
+            Direction::Tx => TAG_TX,
+        };
+        if !header.errors.is_empty() {
+            tag |= TAG_ERRORS;
+        }
+
+        let delta = header.timestamp_us.saturating_sub(self.prev_us);
+        self.prev_us = self.prev_us.max(header.timestamp_us);
+
+        self.buf[0] = tag;
+        let mut n = 1;
+        n += put_varint(&mut self.buf[n..], delta);
+        n += put_varint(&mut self.buf[n..], header.len as u64);
+        if tag & TAG_ERRORS != 0 {
+            self.buf[n] = header.errors.bits();
+            n += 1;
+        }
+
+        &self.buf[..n]
+    }
+
+    pub fn end(&self) -> &'static [u8] {
+        &[END]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownTag(u8),
+    /// The stream ended before the end marker.
+    Truncated,
+    VarintOverflow,
+}
+
+/// A decoded record, borrowing its data from the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    pub direction: Direction,
+    pub timestamp_us: u64,
+    pub errors: ErrorFlags,
+    pub data: &'a [u8],
+}
+
+/// Reads an exported stream back, e.g. in a host tool.
+#[derive(Debug)]
+pub struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+    prev_us: u64,
+    dropped: u32,
+    done: bool,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(input: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut decoder = Self {
+            input,
+            pos: 0,
+            prev_us: 0,
+            dropped: 0,
+            done: false,
+        };
+
+        if decoder.read_bytes(4)? != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        match decoder.byte()? {
+            VERSION => {}
+            v => return Err(DecodeError::UnsupportedVersion(v)),
+        }
+        decoder.dropped = decoder.varint()?.min(u32::MAX as u64) as u32;
+
+        Ok(decoder)
+    }
+
+    /// Records lost on the device before the export.
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Bytes consumed so far, including the end marker once reached.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn read_bytes(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        let input = self.input;
+        let bytes = input
+            .get(self.pos..self.pos + n)
+            .ok_or(DecodeError::Truncated)?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(DecodeError::VarintOverflow)
+    }
+
+    fn record(&mut self) -> Result<Option<Record<'a>>, DecodeError> {
+        let tag = self.byte()?;
+        if tag == END {
+            return Ok(None);
+        }
+        if tag & !(TAG_TX | TAG_ERRORS) != 0 {
+            return Err(DecodeError::UnknownTag(tag));
+        }
+
+        let delta = self.varint()?;
+        let len = self.varint()? as usize;
+        let errors = if tag & TAG_ERRORS != 0 {
+            ErrorFlags::from_bits(self.byte()?)
+        } else {
+            ErrorFlags::NONE
+        };
+        let data = self.read_bytes(len)?;
+        self.prev_us = self.prev_us.saturating_add(delta);
+
+        Ok(Some(Record {
+            direction: if tag & TAG_TX != 0 {
+                Direction::Tx
+            } else {
+                Direction::Rx
+            },
+            timestamp_us: self.prev_us,
+            errors,
+            data,
+        }))
+    }
+}
+
+impl<'a> Iterator for Decoder<'a> {
+    type Item = Result<Record<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.record();
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::ring::CaptureRing;
+    use alloc::vec::Vec;
+
+    fn export<B: AsRef<[u8]> + AsMut<[u8]>>(ring: &CaptureRing<B>) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        let mut out = Vec::new();
+
+        out.extend_from_slice(encoder.start(ring.dropped()));
+        for record in ring.iter() {
+            out.extend_from_slice(encoder.record(&record.header));
+            out.extend(record.data_iter());
+        }
+        out.extend_from_slice(encoder.end());
+
+        out
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut ring = CaptureRing::new([0u8; 256]);
+        ring.push(Direction::Tx, ErrorFlags::NONE, 1_000, b"AT+GMR\r\n");
+        ring.push(
+            Direction::Rx,
+            ErrorFlags::NONE,
+            1_500,
+            b"AT version:2.2.0\r\n",
+        );
+        ring.push(
+            Direction::Rx,
+            ErrorFlags::FRAMING | ErrorFlags::NOISE,
+            90_000_000,
+            &[0x00],
+        );
+        ring.push(Direction::Tx, ErrorFlags::NONE, 90_000_000, b"");
+
+        let stream = export(&ring);
+        let decoder = Decoder::new(&stream).unwrap();
+        assert_eq!(decoder.dropped(), 0);
+
+        let decoded: Vec<_> = decoder
+            .map(Result::unwrap)
+            .map(|r| (r.direction, r.timestamp_us, r.errors, r.data.to_vec()))
+            .collect();
+        let expected: Vec<_> = ring
+            .iter()
+            .map(|r| {
+                let h = r.header;
+                (
+                    h.direction,
+                    h.timestamp_us,
+                    h.errors,
+                    r.data_iter().copied().collect(),
+                )
+            })
+            .collect();
+        assert_eq!(decoded.len(), 4);
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn round_trip_after_wrap() {
+        let mut ring = CaptureRing::new([0u8; 40]);
+        for i in 0..10u8 {
+            ring.push(Direction::Rx, ErrorFlags::NONE, i as u64 * 100, &[i; 5]);
+        }
+
+        let stream = export(&ring);
+        let mut decoder = Decoder::new(&stream).unwrap();
+        assert_eq!(decoder.dropped(), ring.dropped());
+
+        let first = decoder.next().unwrap().unwrap();
+        assert_eq!(first.timestamp_us, 800);
+        assert_eq!(first.data, [8; 5]);
+        let second = decoder.next().unwrap().unwrap();
+        assert_eq!(second.timestamp_us, 900);
+        assert!(decoder.next().is_none());
+        assert_eq!(decoder.position(), stream.len());
+    }
+
+    #[test]
+    fn compact_encoding() {
+        let mut encoder = Encoder::new();
+        encoder.start(0);
+        let header = RecordHeader {
+            direction: Direction::Tx,
+            errors: ErrorFlags::NONE,
+            timestamp_us: 100,
+            len: 4,
+        };
+        assert_eq!(encoder.record(&header), [TAG_TX, 100, 4]);
+
+        let header = RecordHeader {
+            timestamp_us: 300,
+            errors: ErrorFlags::OVERRUN,
+            ..header
+        };
+        assert_eq!(
+            encoder.record(&header),
+            [TAG_TX | TAG_ERRORS, 0xc8, 0x01, 4, 1]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!(
+            Decoder::new(b"PCAP\x01\x00").unwrap_err(),
+            DecodeError::BadMagic
+        );
+        assert_eq!(
+            Decoder::new(b"UCAP\x02\x00").unwrap_err(),
+            DecodeError::UnsupportedVersion(2)
+        );
+
+        let mut decoder = Decoder::new(b"UCAP\x01\x00\x00\x05\x04AB").unwrap();
+        assert_eq!(decoder.next(), Some(Err(DecodeError::Truncated)));
+        assert_eq!(decoder.next(), None);
+
+        let mut decoder = Decoder::new(b"UCAP\x01\x00\x10").unwrap();
+        assert_eq!(decoder.next(), Some(Err(DecodeError::UnknownTag(0x10))));
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{uart::ErrorFlags, utils::interrupt_free};
+use alloc::{boxed::Box, vec};
+use core::cell::{Cell, RefCell};
+use defmt::info;
+use embassy_embedded_hal::SetConfig;
+use embassy_time::Instant;
+use embedded_io_async::{ErrorType, Read, Write};
+
+pub mod export;
+pub mod ring;
+
+use export::Encoder;
+use ring::CaptureRing;
+pub use ring::Direction;
+
+/// Taken from the SDRAM heap at boot: half of the 8 MiB, leaving room for the rest of the heap and
+/// the LTDC framebuffers.
+pub const CAPTURE_SIZE: usize = 4 * 1024 * 1024;
+
+struct Capture {
+    ring: CaptureRing<Box<[u8]>>,
+    running: bool,
+}
+
+/// `None` until [`init`], and while an export is in progress.
+static CAPTURE: critical_section::Mutex<RefCell<Option<Capture>>> =
+    critical_section::Mutex::new(RefCell::new(None));
+
+/// Sees every byte run [`record`] does, see [`subscribe`].
+static SUBSCRIBER: critical_section::Mutex<Cell<Option<fn(Direction, &[u8])>>> =
+    critical_section::Mutex::new(Cell::new(None));
+
+/// USART1 traffic since boot, counted whether or not capture is running.
+static TRAFFIC: critical_section::Mutex<Cell<Traffic>> =
+    critical_section::Mutex::new(Cell::new(Traffic {
+        rx_bytes: 0,
+        tx_bytes: 0,
+        errors: 0,
+    }));
+
+#[derive(Debug, Default, Clone, Copy, defmt::Format)]
+pub struct Traffic {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Reads that failed.
+    pub errors: u32,
+}
+
+#[derive(Debug, Clone, Copy, defmt::Format)]
+pub struct Status {
+    pub running: bool,
+    pub records: usize,
+    pub used: usize,
+    pub capacity: usize,
+    pub dropped: u32,
+}
+
+/// Allocate the capture buffer. Capture starts stopped.
+pub fn init(size: usize) {
+    let storage = vec![0u8; size].into_boxed_slice();
+    interrupt_free(|cs| {
+        CAPTURE.borrow(cs).replace(Some(Capture {
+            ring: CaptureRing::new(storage),
+            running: false,
+        }));
+    });
+    info!("capture: {} KiB buffer allocated", size / 1024);
+}
+
+fn with_capture<R>(f: impl FnOnce(&mut Capture) -> R) -> Option<R> {
+    interrupt_free(|cs| CAPTURE.borrow_ref_mut(cs).as_mut().map(f))
+}
+
+pub fn start() -> bool {
+    with_capture(|c| c.running = true).is_some()
+}
+
+pub fn stop() -> bool {
+    with_capture(|c| c.running = false).is_some()
+}
+
+pub fn clear() -> bool {
+    with_capture(|c| c.ring.clear()).is_some()
+}
+
+pub fn status() -> Option<Status> {
+    with_capture(|c| Status {
+        running: c.running,
+        records: c.ring.records(),
+        used: c.ring.used(),
+        capacity: c.ring.capacity(),
+        dropped: c.ring.dropped(),
+    })
+}
+
+pub fn traffic() -> Traffic {
+    interrupt_free(|cs| TRAFFIC.borrow(cs).get())
+}
+
+/// Record a byte run that was just received or handed over for transmission.
+pub fn record(direction: Direction, errors: ErrorFlags, data: &[u8]) {
+    let now = Instant::now().as_micros();
+    interrupt_free(|cs| {
+        let traffic = TRAFFIC.borrow(cs);
+        let mut counts = traffic.get();
+        match direction {
+            Direction::Rx => counts.rx_bytes += data.len() as u64,
+            Direction::Tx => counts.tx_bytes += data.len() as u64,
+        }
+        if errors != ErrorFlags::NONE {
+            counts.errors = counts.errors.saturating_add(1);
+        }
+        traffic.set(counts);
+    });
+    with_capture(|c| {
+        if c.running {
+            c.ring.push(direction, errors, now, data);
+        }
+    });
+    if let Some(subscriber) = interrupt_free(|cs| SUBSCRIBER.borrow(cs).get()) {
+        subscriber(direction, data);
+    }
+}
+
+/// Pass every byte run recorded from now on to `f` as well, whether or not capture is running,
+/// in place of any earlier subscriber. It's called from the task reading or writing USART1, so it
+/// mustn't wait.
+pub fn subscribe(f: fn(Direction, &[u8])) {
+    interrupt_free(|cs| SUBSCRIBER.borrow(cs).set(Some(f)));
+}
+
+/// Write the capture to `w` in the [`export`] format. Recording is paused for the duration and
+/// the capture is kept afterwards. Returns the number of records written.
+pub async fn export_to<W: Write>(w: &mut W) -> Result<usize, W::Error> {
+    let Some(capture) = interrupt_free(|cs| CAPTURE.borrow_ref_mut(cs).take()) else {
+        return Ok(0);
+    };
+
+    let result = write_records(&capture.ring, w).await;
+    interrupt_free(|cs| CAPTURE.borrow(cs).replace(Some(capture)));
+
+    result
+}
+
+async fn write_records<W: Write>(
+    ring: &CaptureRing<Box<[u8]>>,
+    w: &mut W,
+) -> Result<usize, W::Error> {
+    let mut encoder = Encoder::new();
+    let mut count = 0;
+
+    w.write_all(encoder.start(ring.dropped())).await?;
+    for record in ring.iter() {
+        w.write_all(encoder.record(&record.header)).await?;
+        w.write_all(record.data.0).await?;
+        w.write_all(record.data.1).await?;
+        count += 1;
+    }
+    w.write_all(encoder.end()).await?;
+    w.flush().await?;
+
+    Ok(count)
+}
+
+/// Wraps one half of a UART, recording everything that passes through it.
+pub struct Tap<T> {
+    inner: T,
+}
+
+impl<T> Tap<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+}
+
+impl<T: ErrorType> ErrorType for Tap<T> {
+    type Error = T::Error;
+}
+
+impl<T> Read for Tap<T>
+where
+    T: Read,
+    T::Error: Into<ErrorFlags> + Copy,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let result = self.inner.read(buf).await;
+        match result {
+            Ok(n) => record(Direction::Rx, ErrorFlags::NONE, &buf[..n]),
+            Err(e) => record(Direction::Rx, e.into(), &[]),
+        }
+
+        result
+    }
+}
+
+impl<T: Write> Write for Tap<T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let n = self.inner.write(buf).await?;
+        record(Direction::Tx, ErrorFlags::NONE, &buf[..n]);
+
+        Ok(n)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush().await
+    }
+}
+
+impl<T: SetConfig> SetConfig for Tap<T> {
+    type Config = T::Config;
+    type ConfigError = T::ConfigError;
+
+    fn set_config(&mut self, config: &Self::Config) -> Result<(), Self::ConfigError> {
+        self.inner.set_config(config)
+    }
+}
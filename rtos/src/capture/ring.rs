@@ -0,0 +1,336 @@
+use crate::uart::ErrorFlags;
+
+/// Which way a captured byte run travelled, seen from the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Rx,
+    Tx,
+}
+
+/// Metadata stored in front of every record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordHeader {
+    pub direction: Direction,
+    pub errors: ErrorFlags,
+    /// Time the last byte of the run was handed over, in microseconds since boot.
+    pub timestamp_us: u64,
+    pub len: u16,
+}
+
+impl RecordHeader {
+    /// `[direction][errors][len: u16 LE][timestamp_us: u64 LE]`
+    pub const SIZE: usize = 12;
+
+    fn encode(&self) -> [u8; Self::SIZE] {
+        let mut out = [0u8; Self::SIZE];
+        out[0] = match self.direction {
+            Direction::Rx => 0,
+            Direction::Tx => 1,
+        };
+        out[1] = self.errors.bits();
+        out[2..4].copy_from_slice(&self.len.to_le_bytes());
+        out[4..12].copy_from_slice(&self.timestamp_us.to_le_bytes());
+        out
+    }
+
+    fn decode(raw: &[u8; Self::SIZE]) -> Self {
+        let mut len = [0u8; 2];
+        len.copy_from_slice(&raw[2..4]);
+        let mut timestamp = [0u8; 8];
+        timestamp.copy_from_slice(&raw[4..12]);
+
+        Self {
+            direction: if raw[0] == 0 {
+                Direction::Rx
+            } else {
+                Direction::Tx
+            },
+            errors: ErrorFlags::from_bits(raw[1]),
+            timestamp_us: u64::from_le_bytes(timestamp),
+            len: u16::from_le_bytes(len),
+        }
+    }
+}
+
+/// A record borrowed from the ring. The data may wrap around the end of the storage, in which
+/// case it is split in two slices.
+#[derive(Debug, Clone, Copy)]
+pub struct RecordRef<'a> {
+    pub header: RecordHeader,
+    pub data: (&'a [u8], &'a [u8]),
+}
+
+impl RecordRef<'_> {
+    pub fn len(&self) -> usize {
+        self.data.0.len() + self.data.1.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn data_iter(&self) -> impl Iterator<Item = &u8> + '_ {
+        self.data.0.iter().chain(self.data.1.iter())
+    }
+}
+
+/// Ring buffer of variable-length records. When full, the oldest records are evicted to make
+/// room, so the buffer always holds the most recent traffic.
+#[derive(Debug)]
+pub struct CaptureRing<B> {
+    storage: B,
+    /// Offset of the oldest record.
+    head: usize,
+    /// Bytes in use.
+    used: usize,
+    records: usize,
+    /// Records evicted, or rejected as too large, since the last clear.
+    dropped: u32,
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> CaptureRing<B> {
+    pub fn new(storage: B) -> Self {
+        Self {
+            storage,
+            head: 0,
+            used: 0,
+            records: 0,
+            dropped: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.storage.as_ref().len()
+    }
+
+    /// Bytes in use, headers included.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.used = 0;
+        self.records = 0;
+        self.dropped = 0;
+    }
+
+    /// Append a byte run. Runs longer than `u16::MAX` are stored as several records with the
+    /// same timestamp.
+    pub fn push(
+        &mut self,
+        direction: Direction,
+        errors: ErrorFlags,
+        timestamp_us: u64,
+        data: &[u8],
+    ) {
+        let max_data = (self.capacity().saturating_sub(RecordHeader::SIZE)).min(u16::MAX as usize);
+        if max_data == 0 {
+            self.dropped = self.dropped.saturating_add(1);
+            return;
+        }
+        if data.is_empty() {
+            self.push_one(direction, errors, timestamp_us, data);
+            return;
+        }
+
+        for chunk in data.chunks(max_data) {
+            self.push_one(direction, errors, timestamp_us, chunk);
+        }
+    }
+
+    fn push_one(
+        &mut self,
+        direction: Direction,
+        errors: ErrorFlags,
+        timestamp_us: u64,
+        data: &[u8],
+    ) {
+        let needed = RecordHeader::SIZE + data.len();
+        while self.capacity() - self.used < needed {
+            self.evict();
+        }
+
+        let header = RecordHeader {
+            direction,
+            errors,
+            timestamp_us,
+            len: data.len() as u16,
+        };
+        let tail = (self.head + self.used) % self.capacity();
+        self.write_at(tail, &header.encode());
+        self.write_at((tail + RecordHeader::SIZE) % self.capacity(), data);
+        self.used += needed;
+        self.records += 1;
+    }
+
+    fn evict(&mut self) {
+        let header = self.header_at(self.head);
+        let size = RecordHeader::SIZE + header.len as usize;
+        self.head = (self.head + size) % self.capacity();
+        self.used -= size;
+        self.records -= 1;
+        self.dropped = self.dropped.saturating_add(1);
+    }
+
+    fn write_at(&mut self, offset: usize, data: &[u8]) {
+        let storage = self.storage.as_mut();
+        let first = data.len().min(storage.len() - offset);
+        storage[offset..offset + first].copy_from_slice(&data[..first]);
+        storage[..data.len() - first].copy_from_slice(&data[first..]);
+    }
+
+    fn slices_at(&self, offset: usize, len: usize) -> (&[u8], &[u8]) {
+        let storage = self.storage.as_ref();
+        let first = len.min(storage.len() - offset);
+        (&storage[offset..offset + first], &storage[..len - first])
+    }
+
+    fn header_at(&self, offset: usize) -> RecordHeader {
+        let (a, b) = self.slices_at(offset, RecordHeader::SIZE);
+        let mut raw = [0u8; RecordHeader::SIZE];
+        raw[..a.len()].copy_from_slice(a);
+        raw[a.len()..].copy_from_slice(b);
+        RecordHeader::decode(&raw)
+    }
+
+    /// Records from oldest to newest.
+    pub fn iter(&self) -> Iter<'_, B> {
+        Iter {
+            ring: self,
+            offset: self.head,
+            remaining: self.records,
+        }
+    }
+}
+
+pub struct Iter<'a, B> {
+    ring: &'a CaptureRing<B>,
+    offset: usize,
+    remaining: usize,
+}
+
+impl<'a, B: AsRef<[u8]> + AsMut<[u8]>> Iterator for Iter<'a, B> {
+    type Item = RecordRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let ring = self.ring;
+        let header = ring.header_at(self.offset);
+        let data_offset = (self.offset + RecordHeader::SIZE) % ring.capacity();
+        let data = ring.slices_at(data_offset, header.len as usize);
+        self.offset = (data_offset + header.len as usize) % ring.capacity();
+        self.remaining -= 1;
+
+        Some(RecordRef { header, data })
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn contents<B: AsRef<[u8]> + AsMut<[u8]>>(
+        ring: &CaptureRing<B>,
+    ) -> Vec<(Direction, u64, Vec<u8>)> {
+        ring.iter()
+            .map(|r| {
+                let data = r.data_iter().copied().collect();
+                (r.header.direction, r.header.timestamp_us, data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stores_records_in_order() {
+        let mut ring = CaptureRing::new([0u8; 64]);
+
+        ring.push(Direction::Tx, ErrorFlags::NONE, 10, b"AT\r\n");
+        ring.push(Direction::Rx, ErrorFlags::FRAMING, 20, b"OK\r\n");
+
+        assert_eq!(ring.records(), 2);
+        assert_eq!(ring.used(), 2 * (RecordHeader::SIZE + 4));
+        assert_eq!(
+            contents(&ring),
+            [
+                (Direction::Tx, 10, b"AT\r\n".to_vec()),
+                (Direction::Rx, 20, b"OK\r\n".to_vec())
+            ]
+        );
+        assert_eq!(
+            ring.iter().nth(1).unwrap().header.errors,
+            ErrorFlags::FRAMING
+        );
+    }
+
+    #[test]
+    fn evicts_oldest_and_wraps() {
+        // Room for two 8-byte records (20 bytes each) but not three.
+        let mut ring = CaptureRing::new([0u8; 50]);
+
+        ring.push(Direction::Rx, ErrorFlags::NONE, 1, b"11111111");
+        ring.push(Direction::Rx, ErrorFlags::NONE, 2, b"22222222");
+        // The header of this record wraps around the end of the storage.
+        ring.push(Direction::Tx, ErrorFlags::NONE, 3, b"33333333");
+
+        assert_eq!(ring.dropped(), 1);
+        assert_eq!(
+            contents(&ring),
+            [
+                (Direction::Rx, 2, b"22222222".to_vec()),
+                (Direction::Tx, 3, b"33333333".to_vec())
+            ]
+        );
+
+        ring.push(Direction::Rx, ErrorFlags::NONE, 4, b"4");
+        assert_eq!(ring.dropped(), 2);
+        assert_eq!(
+            contents(&ring),
+            [
+                (Direction::Tx, 3, b"33333333".to_vec()),
+                (Direction::Rx, 4, b"4".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_runs_larger_than_the_ring() {
+        let mut ring = CaptureRing::new([0u8; 32]);
+
+        ring.push(Direction::Rx, ErrorFlags::NONE, 7, &[0xaa; 30]);
+
+        // Each piece evicts the previous one, so only the last 10 bytes are left.
+        assert_eq!(contents(&ring), [(Direction::Rx, 7, [0xaa; 10].to_vec())]);
+        assert_eq!(ring.dropped(), 1);
+    }
+
+    #[test]
+    fn clear_resets_everything() {
+        let mut ring = CaptureRing::new([0u8; 32]);
+
+        ring.push(Direction::Rx, ErrorFlags::NONE, 1, b"abc");
+        ring.clear();
+
+        assert!(ring.is_empty());
+        assert_eq!(ring.used(), 0);
+        assert_eq!(ring.iter().count(), 0);
+    }
+}
@@ -0,0 +1,98 @@
+//! Rate of change of one clock's offset from another.
+
+const US_PER_S: f64 = 1_000_000.0;
+
+/// Least-squares fit of offset against uptime.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Drift {
+    /// First sample; the others are taken relative to it to keep the sums small.
+    origin: Option<(u64, i64)>,
+    last_us: u64,
+    samples: u32,
+    sum_t: f64,
+    sum_o: f64,
+    sum_tt: f64,
+    sum_to: f64,
+}
+
+impl Drift {
+    pub const fn new() -> Self {
+        Self {
+            origin: None,
+            last_us: 0,
+            samples: 0,
+            sum_t: 0.0,
+            sum_o: 0.0,
+            sum_tt: 0.0,
+            sum_to: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, uptime_us: u64, offset_us: i64) {
+        let (t0, o0) = *self.origin.get_or_insert((uptime_us, offset_us));
+        // Seconds and microseconds.
+        let t = uptime_us.saturating_sub(t0) as f64 / US_PER_S;
+        let o = (offset_us - o0) as f64;
+        self.samples += 1;
+        self.sum_t += t;
+        self.sum_o += o;
+        self.sum_tt += t * t;
+        self.sum_to += t * o;
+        self.last_us = uptime_us;
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Uptime between the first and last sample.
+    pub fn span_us(&self) -> u64 {
+        self.origin.map_or(0, |(t0, _)| self.last_us - t0)
+    }
+
+    /// How fast the offset grows, in ppb. `None` until the samples span some time.
+    pub fn ppb(&self) -> Option<i32> {
+        let n = self.samples as f64;
+        let denominator = n * self.sum_tt - self.sum_t * self.sum_t;
+        if self.samples < 2 || denominator <= 0.0 {
+            return None;
+        }
+        // Microseconds per second are ppm.
+        let slope = (n * self.sum_to - self.sum_t * self.sum_o) / denominator;
+        Some((slope * 1_000.0) as i32)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_a_line() {
+        let mut drift = Drift::new();
+        assert_eq!(drift.ppb(), None);
+        drift.add(5_000_000, -1_000);
+        assert_eq!(drift.ppb(), None);
+        // 25 ppm, with +-40 us of alternating noise.
+        for s in 1..=600u64 {
+            let noise = if s % 2 == 0 { 40 } else { -40 };
+            drift.add(5_000_000 + s * 1_000_000, -1_000 + (s * 25) as i64 + noise);
+        }
+        assert_eq!(drift.samples(), 601);
+        assert_eq!(drift.span_us(), 600_000_000);
+        let ppb = drift.ppb().unwrap();
+        assert!((ppb - 25_000).abs() < 50, "{ppb}");
+
+        drift.reset();
+        assert_eq!(
+            (drift.samples(), drift.span_us(), drift.ppb()),
+            (0, 0, None)
+        );
+    }
+}
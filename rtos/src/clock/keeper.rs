@@ -0,0 +1,347 @@
+//! Which time source to trust, and what to correct.
+//!
+//! Every source is measured by its offset from the on-chip RTC, which runs whatever else is
+//! there. The wall clock is the on-chip RTC plus the offset of the best source; a steady change
+//! in that offset is the on-chip RTC's drift, which its smooth calibration can cancel.
+
+use super::drift::Drift;
+
+/// Best first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    /// A receiver's time, disciplined by its pulse per second.
+    Gps,
+    /// The RV-8803, temperature compensated to a few ppm.
+    External,
+    /// The on-chip RTC on its own, once set from one of the others.
+    Internal,
+}
+
+impl Source {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Source::Gps => "GPS",
+            Source::External => "RV-8803",
+            Source::Internal => "on-chip RTC",
+        }
+    }
+
+    /// How long an observation stands for the source.
+    fn max_age_us(&self) -> u64 {
+        match self {
+            Source::Gps => 60_000_000,
+            Source::External => 30_000_000,
+            Source::Internal => u64::MAX,
+        }
+    }
+}
+
+/// An offset this large is set right rather than waited out.
+pub const STEP_US: i64 = 500_000;
+/// A change of offset this sudden is a clock being set, not drift.
+pub const JUMP_US: i64 = 50_000;
+/// Uptime a drift estimate has to span before it's acted on.
+pub const CALIBRATION_SPAN_US: u64 = 3_600_000_000;
+pub const CALIBRATION_SAMPLES: u32 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Set the on-chip RTC to the best source.
+    StepInternal,
+    /// Set the RV-8803 to the GPS.
+    StepExternal,
+    /// The on-chip RTC runs this many ppb fast even with its calibration so far; correct it.
+    Calibrate(i32),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Track {
+    /// Uptime and offset from the on-chip RTC.
+    latest: Option<(u64, i64)>,
+    drift: Drift,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Keeper {
+    /// GPS and RV-8803.
+    tracks: [Track; 2],
+    /// The on-chip RTC has been set from a reference, this boot or before.
+    internal_set: bool,
+}
+
+impl Keeper {
+    pub const fn new() -> Self {
+        const TRACK: Track = Track {
+            latest: None,
+            drift: Drift::new(),
+        };
+        Self {
+            tracks: [TRACK; 2],
+            internal_set: false,
+        }
+    }
+
+    fn track(&self, source: Source) -> Option<&Track> {
+        self.tracks.get(source as usize)
+    }
+
+    /// `source` read `offset_us` ahead of the on-chip RTC at `uptime_us`.
+    pub fn observe(&mut self, source: Source, uptime_us: u64, offset_us: i64) {
+        if let Some(track) = self.tracks.get_mut(source as usize) {
+            if let Some((_, latest)) = track.latest {
+                if (offset_us - latest).abs() > JUMP_US {
+                    track.drift.reset();
+                }
+            }
+            track.latest = Some((uptime_us, offset_us));
+            track.drift.add(uptime_us, offset_us);
+        }
+    }
+
+    /// `source` can't be read, or can't be trusted.
+    pub fn lost(&mut self, source: Source) {
+        if let Some(track) = self.tracks.get_mut(source as usize) {
+            *track = Track::default();
+        }
+    }
+
+    pub fn set_internal(&mut self, set: bool) {
+        self.internal_set = set;
+    }
+
+    fn latest(&self, source: Source, uptime_us: u64) -> Option<i64> {
+        let (at, offset) = self.track(source)?.latest?;
+        (uptime_us.saturating_sub(at) <= source.max_age_us()).then_some(offset)
+    }
+
+    /// The best source with a recent observation, and its offset from the on-chip RTC.
+    pub fn best(&self, uptime_us: u64) -> Option<(Source, i64)> {
+        [Source::Gps, Source::External]
+            .into_iter()
+            .find_map(|source| Some((source, self.latest(source, uptime_us)?)))
+            .or(self.internal_set.then_some((Source::Internal, 0)))
+    }
+
+    /// Drift in ppb, positive when fast: of the on-chip RTC against the best source, and of the
+    /// RV-8803 against the GPS.
+    pub fn drift_ppb(&self, source: Source, uptime_us: u64) -> Option<i32> {
+        let slope = |source| self.track(source)?.drift.ppb();
+        match source {
+            Source::Gps => None,
+            Source::External => Some(slope(Source::External)? - slope(Source::Gps)?),
+            Source::Internal => match self.best(uptime_us)? {
+                (Source::Internal, _) => None,
+                (best, _) => slope(best).map(|ppb| -ppb),
+            },
+        }
+    }
+
+    /// What needs correcting after the latest observations. Assumes it gets done.
+    pub fn check(&mut self, uptime_us: u64) -> Option<Action> {
+        let (best, offset) = self.best(uptime_us)?;
+        if best == Source::Internal {
+            return None;
+        }
+        if offset.abs() > STEP_US || !self.internal_set {
+            // Every offset moves with it.
+            self.tracks = Default::default();
+            self.internal_set = true;
+            return Some(Action::StepInternal);
+        }
+        if let (Source::Gps, Some(external)) = (best, self.latest(Source::External, uptime_us)) {
+            if (external - offset).abs() > STEP_US {
+                self.lost(Source::External);
+                return Some(Action::StepExternal);
+            }
+        }
+        let drift = self.tracks[best as usize].drift;
+        if drift.span_us() < CALIBRATION_SPAN_US || drift.samples() < CALIBRATION_SAMPLES {
+            return None;
+        }
+        let ppb = -drift.ppb()?;
+        // Every rate changes with it.
+        for track in &mut self.tracks {
+            track.drift.reset();
+        }
+        Some(Action::Calibrate(ppb))
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtc::registers::Calibration;
+
+    /// A clock `drift_ppb` fast, read to `resolution_us`, `offset_us` off at uptime 0.
+    struct Sim {
+        drift_ppb: i64,
+        offset_us: i64,
+        resolution_us: i64,
+    }
+
+    impl Sim {
+        /// Microseconds of UTC, with UTC counted from uptime 0.
+        fn read(&self, uptime_us: u64) -> i64 {
+            let t = uptime_us as i64;
+            let exact = t + self.offset_us + t * self.drift_ppb / 1_000_000_000;
+            exact - exact.rem_euclid(self.resolution_us)
+        }
+
+        /// Set to `utc_us` at `uptime_us`.
+        fn set(&mut self, uptime_us: u64, utc_us: i64) {
+            self.offset_us += utc_us - self.read(uptime_us);
+        }
+    }
+
+    /// The on-chip RTC 31 ppm fast and 20 s behind, the RV-8803 2 ppm slow and 1.3 s ahead.
+    /// GPS for three and a half hours, then only the RV-8803.
+    #[test]
+    fn keeps_time_from_simulated_clocks() {
+        const LSE_DRIFT_PPB: i64 = 31_000;
+        let mut internal = Sim {
+            drift_ppb: LSE_DRIFT_PPB,
+            offset_us: -20_000_000,
+            resolution_us: 3_906,
+        };
+        let mut external = Sim {
+            drift_ppb: -2_000,
+            offset_us: 1_300_000,
+            resolution_us: 10_000,
+        };
+        let mut calibration_ppb = 0i32;
+        let mut keeper = Keeper::new();
+        let mut actions = std::vec::Vec::new();
+
+        assert_eq!(keeper.best(0), None);
+        for s in 0..4 * 3_600u64 {
+            let uptime_us = s * 1_000_000;
+            let gps = s < 12_600;
+            if gps {
+                keeper.observe(
+                    Source::Gps,
+                    uptime_us,
+                    uptime_us as i64 - internal.read(uptime_us),
+                );
+            }
+            if s % 10 == 0 {
+                let offset = external.read(uptime_us) - internal.read(uptime_us);
+                keeper.observe(Source::External, uptime_us, offset);
+            }
+            let best = keeper.best(uptime_us).map(|(source, _)| source);
+            while let Some(action) = keeper.check(uptime_us) {
+                let reference = match best {
+                    Some(Source::Gps) => uptime_us as i64,
+                    _ => external.read(uptime_us),
+                };
+                match action {
+                    Action::StepInternal => internal.set(uptime_us, reference),
+                    Action::StepExternal => external.set(uptime_us, reference),
+                    Action::Calibrate(ppb) => {
+                        calibration_ppb += ppb;
+                        let applied = Calibration::correcting(calibration_ppb).ppb() as i64;
+                        // Keep the clock where it is at the change of rate.
+                        let now = internal.read(uptime_us);
+                        internal.drift_ppb = LSE_DRIFT_PPB - applied;
+                        internal.set(uptime_us, now);
+                    }
+                }
+                actions.push((s, action));
+            }
+
+            // Wall clock within a few ms of UTC once stepped, tens of ms on the RV-8803.
+            if s > 0 {
+                let (source, offset) = keeper.best(uptime_us).unwrap();
+                let error = internal.read(uptime_us) + offset - uptime_us as i64;
+                let tolerance = if gps { 4_000 } else { 50_000 };
+                assert!(
+                    error.abs() <= tolerance,
+                    "{s} s: {error} us from {source:?}"
+                );
+                assert_eq!(
+                    source,
+                    if gps || s < 12_600 + 60 {
+                        Source::Gps
+                    } else {
+                        Source::External
+                    }
+                );
+            }
+        }
+
+        assert_eq!(actions[0], (0, Action::StepInternal));
+        // Once there's an offset from the stepped on-chip RTC to compare it with.
+        assert_eq!(actions[1], (10, Action::StepExternal));
+        let Action::Calibrate(first) = actions[2].1 else {
+            panic!("{actions:?}");
+        };
+        assert_eq!(actions[2].0, 3_601);
+        assert!((first - 31_000).abs() < 200, "{first}");
+        // The rest is within a step of the calibration.
+        assert!(calibration_ppb.abs_diff(31_000) < 954, "{actions:?}");
+        let applied = Calibration::correcting(calibration_ppb).ppb();
+        assert!(applied.abs_diff(31_000) < 477 + 1, "{applied}");
+        // The RV-8803's drift measured against the GPS, until it went.
+        let mut keeper = Keeper::new();
+        for s in 0..600u64 {
+            let uptime_us = s * 1_000_000;
+            keeper.observe(Source::Gps, uptime_us, 0);
+            keeper.observe(
+                Source::External,
+                uptime_us,
+                (s * 1_000_000) as i64 * -2 / 1_000_000,
+            );
+        }
+        let external = keeper.drift_ppb(Source::External, 600_000_000).unwrap();
+        assert!((external + 2_000).abs() < 10, "{external}");
+    }
+
+    #[test]
+    fn falls_back_by_priority() {
+        let mut keeper = Keeper::new();
+        keeper.set_internal(true);
+        assert_eq!(keeper.best(0), Some((Source::Internal, 0)));
+        assert_eq!(keeper.check(0), None);
+        keeper.observe(Source::External, 0, 300);
+        assert_eq!(keeper.best(0), Some((Source::External, 300)));
+        keeper.observe(Source::Gps, 1_000_000, 200);
+        assert_eq!(keeper.best(1_000_000), Some((Source::Gps, 200)));
+        // The RV-8803 is polled less often, so lasts less long without one.
+        assert_eq!(keeper.best(61_000_000), Some((Source::Gps, 200)));
+        assert_eq!(keeper.best(61_000_001), Some((Source::Internal, 0)));
+        keeper.observe(Source::External, 61_000_001, 250);
+        keeper.lost(Source::Gps);
+        assert_eq!(keeper.best(61_000_001), Some((Source::External, 250)));
+
+        let mut keeper = Keeper::new();
+        keeper.observe(Source::External, 0, 100);
+        // Set once, the offset is small enough to keep.
+        assert_eq!(keeper.check(0), Some(Action::StepInternal));
+        keeper.observe(Source::External, 0, 100);
+        assert_eq!(keeper.check(0), None);
+        keeper.observe(Source::External, 1, -600_000);
+        assert_eq!(keeper.check(1), Some(Action::StepInternal));
+    }
+
+    #[test]
+    fn restarts_drift_when_a_clock_is_set() {
+        let mut keeper = Keeper::new();
+        keeper.set_internal(true);
+        for s in 0..100u64 {
+            keeper.observe(Source::Gps, s * 1_000_000, 20 * s as i64);
+        }
+        assert_eq!(
+            keeper.drift_ppb(Source::Internal, 99_000_000),
+            Some(-20_000)
+        );
+        // The on-chip RTC set 300 ms back.
+        keeper.observe(Source::Gps, 100_000_000, 300_000);
+        assert_eq!(keeper.drift_ppb(Source::Internal, 100_000_000), None);
+        keeper.observe(Source::Gps, 110_000_000, 300_200);
+        assert_eq!(
+            keeper.drift_ppb(Source::Internal, 110_000_000),
+            Some(-20_000)
+        );
+    }
+}
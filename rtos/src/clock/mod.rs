@@ -0,0 +1,171 @@
+use crate::{
+    gps,
+    rtc::{self, backup, internal},
+};
+use chrono::{Duration, NaiveDateTime, Timelike};
+use core::cell::Cell;
+use defmt::{info, unwrap, warn};
+use embassy_executor::Spawner;
+use embassy_time::{Instant, Timer};
+
+pub mod drift;
+pub mod keeper;
+
+pub use keeper::{Action, Keeper, Source};
+
+const POLL_MS: u64 = 1_000;
+/// The RV-8803 is read over I2C, so less often.
+const EXTERNAL_POLLS: u32 = 10;
+/// The GPS mapping is observed while pulses keep placing it.
+const GPS_FRESH_US: u64 = 2_000_000;
+
+static KEEPER: critical_section::Mutex<Cell<Keeper>> =
+    critical_section::Mutex::new(Cell::new(Keeper::new()));
+
+fn keeper() -> Keeper {
+    critical_section::with(|cs| KEEPER.borrow(cs).get())
+}
+
+fn update<R>(f: impl FnOnce(&mut Keeper) -> R) -> R {
+    critical_section::with(|cs| {
+        let cell = KEEPER.borrow(cs);
+        let mut keeper = cell.get();
+        let result = f(&mut keeper);
+        cell.set(keeper);
+        result
+    })
+}
+
+/// Uptime, which only goes forward, with the wall-clock time at that uptime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Time {
+    pub uptime_us: u64,
+    /// UTC, once the on-chip RTC has been set from a reference.
+    pub utc: Option<NaiveDateTime>,
+    /// Where `utc` comes from. It steps when this changes.
+    pub source: Option<Source>,
+}
+
+pub fn now() -> Time {
+    let uptime_us = Instant::now().as_micros();
+    let best = keeper().best(uptime_us);
+    let utc = best
+        .zip(internal::now().ok())
+        .map(|((_, offset), rtc)| rtc + Duration::microseconds(offset));
+    Time {
+        uptime_us,
+        utc,
+        source: best.map(|(source, _)| source),
+    }
+}
+
+/// Drift in ppb, positive when fast: of the on-chip RTC against the best source, and of the
+/// RV-8803 against the GPS.
+pub fn drift_ppb(source: Source) -> Option<i32> {
+    keeper().drift_ppb(source, Instant::now().as_micros())
+}
+
+pub fn spawn(spawner: &Spawner) {
+    unwrap!(spawner.spawn(clock_task()));
+}
+
+fn offset_us(source: NaiveDateTime, rtc: NaiveDateTime) -> i64 {
+    (source - rtc).num_microseconds().unwrap_or(i64::MAX)
+}
+
+#[embassy_executor::task]
+async fn clock_task() {
+    update(|keeper| keeper.set_internal(internal::is_set()));
+    let mut polls = 0u32;
+    loop {
+        Timer::after_millis(POLL_MS).await;
+        observe_gps();
+        if polls % EXTERNAL_POLLS == 0 {
+            observe_external().await;
+        }
+        polls = polls.wrapping_add(1);
+
+        // Before any step moves the clocks.
+        let time = now();
+        while let Some(action) = update(|keeper| keeper.check(time.uptime_us)) {
+            act(action, &time).await;
+        }
+    }
+}
+
+fn observe_gps() {
+    let uptime_us = Instant::now().as_micros();
+    let placed = gps::anchor().is_some_and(|anchor| {
+        anchor.pps && uptime_us.saturating_sub(anchor.uptime_us) <= GPS_FRESH_US
+    });
+    if !placed {
+        return;
+    }
+    if let (Some(gps), Ok(rtc)) = (gps::utc_now(), internal::now()) {
+        update(|keeper| keeper.observe(Source::Gps, uptime_us, offset_us(gps, rtc)));
+    }
+}
+
+async fn observe_external() {
+    match rtc::now().await {
+        // Read right after, so late by the transfer; the RV-8803 only has hundredths anyway.
+        Ok(external) => {
+            let uptime_us = Instant::now().as_micros();
+            if let Ok(rtc) = internal::now() {
+                update(|keeper| {
+                    keeper.observe(Source::External, uptime_us, offset_us(external, rtc))
+                });
+            }
+        }
+        Err(e) => {
+            update(|keeper| keeper.lost(Source::External));
+            let gps = matches!(now().source, Some(Source::Gps));
+            if let (rtc::Error::TimeLost, true) = (e, gps) {
+                act(Action::StepExternal, &now()).await;
+            }
+        }
+    }
+}
+
+async fn act(action: Action, time: &Time) {
+    match action {
+        Action::StepInternal | Action::StepExternal => {
+            // Set at the start of the reference's next second.
+            let Some(utc) = time.utc else {
+                return;
+            };
+            let Some(second) = utc.with_nanosecond(0).map(|s| s + Duration::seconds(1)) else {
+                return;
+            };
+            let wait_us = (second - utc).num_microseconds().unwrap_or(0).max(0) as u64;
+            Timer::at(Instant::from_micros(time.uptime_us + wait_us)).await;
+            let result = match action {
+                Action::StepInternal => internal::set(&second).map_err(|e| e.as_str()),
+                _ => rtc::set(&second).await.map_err(|e| e.as_str()),
+            };
+            let clock = match action {
+                Action::StepInternal => Source::Internal,
+                _ => Source::External,
+            };
+            match result {
+                Ok(()) => info!(
+                    "clock: {} set to {}",
+                    clock.as_str(),
+                    defmt::Debug2Format(&second)
+                ),
+                Err(e) => warn!("clock: {} set failed: {}", clock.as_str(), e),
+            }
+        }
+        Action::Calibrate(ppb) => {
+            let drift_ppb = internal::backup()
+                .get(backup::CALIBRATION_PPB)
+                .saturating_add(ppb);
+            let calibration = internal::calibrate(drift_ppb);
+            info!(
+                "clock: on-chip RTC {} ppb fast, calibrated by {} ppb",
+                drift_ppb,
+                calibration.ppb()
+            );
+        }
+    }
+}
@@ -0,0 +1,822 @@
+use crate::{
+    gps::GPS_BAUDRATE, i2c::scan::Probe, modbus::pdu::Table, module::sequence::Sequence,
+    selftest::report::Peer, uart::SerialSettings,
+};
+use chrono::NaiveDateTime;
+
+/// Commands accepted on the host port console, one per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Capture(CaptureCommand),
+    /// Hand USART1 over to the loopback self-test.
+    SelfTest(Peer),
+    Module(ModuleCommand),
+    /// Needs USART1 to talk to the module.
+    Wifi(WifiCommand),
+    /// Runs on USART1, which then carries Modbus RTU instead of the module's traffic.
+    Modbus(ModbusCommand),
+    /// Runs on USART1 in LIN mode.
+    Lin(LinCommand),
+    /// Runs on USART1 at 250 kbaud.
+    Dmx(DmxCommand),
+    /// Runs on USART1 wired as a 1-Wire bus.
+    OneWire(OneWireCommand),
+    /// Runs on USART1 in single-wire half-duplex mode.
+    Dynamixel(DynamixelCommand),
+    /// Reads a receiver on USART1.
+    Gps(GpsCommand),
+    Rtc(RtcCommand),
+    Clock,
+    I2c(I2cCommand),
+    Display(DisplayCommand),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleCommand {
+    Status,
+    /// Run a power or reset sequence. Needs USART1 to watch for the ready banner.
+    Run(Sequence),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WifiCommand {
+    Status,
+    Scan,
+    /// Neither may contain spaces. An empty password joins an open network.
+    Join {
+        ssid: heapless::String<32>,
+        password: heapless::String<64>,
+    },
+    Leave,
+}
+
+/// Values written by one `modbus write`.
+pub const MODBUS_MAX_VALUES: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModbusCommand {
+    Read {
+        unit: u8,
+        table: Table,
+        address: u16,
+        count: u16,
+    },
+    /// Coils or holding registers; a single value uses the single write function.
+    Write {
+        unit: u8,
+        table: Table,
+        address: u16,
+        values: heapless::Vec<u16, MODBUS_MAX_VALUES>,
+    },
+    /// Act as slave `unit` with a register map in RAM.
+    Serve { unit: u8 },
+}
+
+/// Run the demo frame table of `lin` as one node, until a key is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinCommand {
+    Master,
+    Slave,
+}
+
+/// DMX512 on USART1, until a key is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmxCommand {
+    /// Every slot at `level`.
+    Send {
+        level: u8,
+    },
+    Receive,
+}
+
+/// 1-Wire bus on USART1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OneWireCommand {
+    /// List the ROM codes on the bus.
+    Search,
+    /// Read every DS18B20 once a second, until a key is pressed.
+    Temperature,
+}
+
+/// Bytes written by one `dxl write`.
+pub const DYNAMIXEL_MAX_DATA: usize = 16;
+
+/// Dynamixel protocol 2.0 servos on USART1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DynamixelCommand {
+    /// Ping one device, or every device on the bus.
+    Ping {
+        id: Option<u8>,
+    },
+    Read {
+        id: u8,
+        address: u16,
+        len: u8,
+    },
+    Write {
+        id: u8,
+        address: u16,
+        data: heapless::Vec<u8, DYNAMIXEL_MAX_DATA>,
+    },
+    /// Enable the torque and move to `position`.
+    Goto {
+        id: u8,
+        position: i32,
+    },
+}
+
+/// GPS receiver on USART1, until a key is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpsCommand {
+    pub baudrate: u32,
+    /// Echo the NMEA sentences too.
+    pub raw: bool,
+}
+
+/// RV-8803 on I2C2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtcCommand {
+    Show,
+    /// UTC, to the second.
+    Set(NaiveDateTime),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum I2cCommand {
+    /// I2C1, I2C2 or I2C4.
+    Scan {
+        bus: u8,
+        probe: Probe,
+    },
+    Trace(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayCommand {
+    Dashboard,
+    /// USART1 traffic, `back` lines up into the scrollback, 0 to follow it.
+    Terminal {
+        back: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureCommand {
+    Start,
+    Stop,
+    Clear,
+    Status,
+    /// Write the capture to the host port in the binary export format.
+    Export,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    UnknownCommand,
+    MissingArgument,
+    UnknownArgument,
+    ArgumentTooLong,
+    InvalidNumber,
+    InvalidTime,
+    InvalidBaudrate,
+}
+
+impl ParseError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParseError::Empty => "empty line",
+            ParseError::UnknownCommand => "unknown command, try `help`",
+            ParseError::MissingArgument => "missing argument",
+            ParseError::UnknownArgument => "unknown argument",
+            ParseError::ArgumentTooLong => "argument too long",
+            ParseError::InvalidNumber => "invalid number",
+            ParseError::InvalidTime => "invalid time, use YYYY-MM-DDTHH:MM:SS",
+            ParseError::InvalidBaudrate => "baud rate out of range",
+        }
+    }
+}
+
+pub const HELP: &str = "\
+help                  this text\r\n\
+capture start|stop    record USART1 traffic\r\n\
+capture clear         discard the capture\r\n\
+capture status        show buffer usage\r\n\
+capture export        dump the capture in binary form\r\n\
+module status         show the module state\r\n\
+module on|off         power the module up or down\r\n\
+module reset|boot     hard reset into the firmware or the ROM bootloader\r\n\
+wifi scan             list access points in range\r\n\
+wifi join SSID [PASS] join an access point\r\n\
+wifi leave            leave the access point\r\n\
+wifi status           show the connection and DHCP state\r\n\
+modbus read UNIT coils|discrete|holding|input ADDR [COUNT]\r\n\
+                      read from a Modbus RTU slave on USART1\r\n\
+modbus write UNIT coils|holding ADDR VALUE...\r\n\
+                      write one or more coils or registers\r\n\
+modbus serve UNIT     act as a slave until a key is pressed\r\n\
+lin master|slave      run the LIN demo schedule as master or answer it as slave\r\n\
+dmx send [LEVEL]      transmit a DMX512 universe with every slot at LEVEL\r\n\
+dmx receive           show the DMX512 universe received\r\n\
+onewire search        list the 1-Wire devices on USART1\r\n\
+onewire temp          read every DS18B20 on the 1-Wire bus once a second\r\n\
+dxl ping [ID]         ping a Dynamixel servo on USART1, or all of them\r\n\
+dxl read ID ADDR [LEN]\r\n\
+                      read from a servo's control table\r\n\
+dxl write ID ADDR BYTE...\r\n\
+                      write to a servo's control table\r\n\
+dxl goto ID POSITION  enable the torque and move to POSITION\r\n\
+gps [raw] [BAUD]      show the fix of a GPS receiver on USART1, with its NMEA if raw\r\n\
+rtc                   show the time of the RV-8803, in UTC\r\n\
+rtc set TIME          set it, as YYYY-MM-DDTHH:MM:SS in UTC\r\n\
+clock                 show the time, where it comes from and the clocks' drift\r\n\
+i2c scan [1|2|4] [quick|read]\r\n\
+                      list what answers on I2C2 (D20/D21), or another bus\r\n\
+i2c trace on|off      log the I2C drivers' transactions over RTT\r\n\
+display dashboard     show the status dashboard on the display\r\n\
+display terminal [LINES]\r\n\
+                      show USART1 traffic, LINES up into the scrollback\r\n\
+selftest [external]   PRBS loopback test of USART1, internal unless an echo peer is wired\r\n";
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, ParseError> {
+        let mut words = line.split_ascii_whitespace();
+        let command = words.next().ok_or(ParseError::Empty)?;
+
+        let parsed = match command {
+            "help" | "?" => Command::Help,
+            "capture" => {
+                let action = match words.next().ok_or(ParseError::MissingArgument)? {
+                    "start" => CaptureCommand::Start,
+                    "stop" => CaptureCommand::Stop,
+                    "clear" => CaptureCommand::Clear,
+                    "status" => CaptureCommand::Status,
+                    "export" => CaptureCommand::Export,
+                    _ => return Err(ParseError::UnknownArgument),
+                };
+                Command::Capture(action)
+            }
+            "module" => {
+                let action = match words.next().ok_or(ParseError::MissingArgument)? {
+                    "status" => ModuleCommand::Status,
+                    "on" => ModuleCommand::Run(Sequence::PowerOn),
+                    "off" => ModuleCommand::Run(Sequence::PowerDown),
+                    "reset" => ModuleCommand::Run(Sequence::HardReset),
+                    "boot" => ModuleCommand::Run(Sequence::Bootloader),
+                    _ => return Err(ParseError::UnknownArgument),
+                };
+                Command::Module(action)
+            }
+            "wifi" => Command::Wifi(match words.next().ok_or(ParseError::MissingArgument)? {
+                "status" => WifiCommand::Status,
+                "scan" => WifiCommand::Scan,
+                "leave" => WifiCommand::Leave,
+                "join" => {
+                    let ssid = words.next().ok_or(ParseError::MissingArgument)?;
+                    let password = words.next().unwrap_or("");
+                    WifiCommand::Join {
+                        ssid: ssid.try_into().map_err(|_| ParseError::ArgumentTooLong)?,
+                        password: password
+                            .try_into()
+                            .map_err(|_| ParseError::ArgumentTooLong)?,
+                    }
+                }
+                _ => return Err(ParseError::UnknownArgument),
+            }),
+            "modbus" => Command::Modbus(match words.next().ok_or(ParseError::MissingArgument)? {
+                "read" => ModbusCommand::Read {
+                    unit: number(words.next())?,
+                    table: table(words.next())?,
+                    address: number(words.next())?,
+                    count: match words.next() {
+                        Some(word) => number(Some(word))?,
+                        None => 1,
+                    },
+                },
+                "write" => {
+                    let (unit, table) = (number(words.next())?, table(words.next())?);
+                    if !matches!(table, Table::Coils | Table::HoldingRegisters) {
+                        return Err(ParseError::UnknownArgument);
+                    }
+                    let address = number(words.next())?;
+                    let mut values = heapless::Vec::new();
+                    for word in words.by_ref() {
+                        values
+                            .push(number(Some(word))?)
+                            .map_err(|_| ParseError::ArgumentTooLong)?;
+                    }
+                    if values.is_empty() {
+                        return Err(ParseError::MissingArgument);
+                    }
+                    ModbusCommand::Write {
+                        unit,
+                        table,
+                        address,
+                        values,
+                    }
+                }
+                "serve" => ModbusCommand::Serve {
+                    unit: number(words.next())?,
+                },
+                _ => return Err(ParseError::UnknownArgument),
+            }),
+            "lin" => Command::Lin(match words.next().ok_or(ParseError::MissingArgument)? {
+                "master" => LinCommand::Master,
+                "slave" => LinCommand::Slave,
+                _ => return Err(ParseError::UnknownArgument),
+            }),
+            "dmx" => Command::Dmx(match words.next().ok_or(ParseError::MissingArgument)? {
+                "send" => DmxCommand::Send {
+                    level: match words.next() {
+                        Some(word) => number(Some(word))?,
+                        None => 0,
+                    },
+                },
+                "receive" => DmxCommand::Receive,
+                _ => return Err(ParseError::UnknownArgument),
+            }),
+            "onewire" => {
+                Command::OneWire(match words.next().ok_or(ParseError::MissingArgument)? {
+                    "search" => OneWireCommand::Search,
+                    "temp" => OneWireCommand::Temperature,
+                    _ => return Err(ParseError::UnknownArgument),
+                })
+            }
+            "dxl" => Command::Dynamixel(match words.next().ok_or(ParseError::MissingArgument)? {
+                "ping" => DynamixelCommand::Ping {
+                    id: match words.next() {
+                        Some(word) => Some(number(Some(word))?),
+                        None => None,
+                    },
+                },
+                "read" => DynamixelCommand::Read {
+                    id: number(words.next())?,
+                    address: number(words.next())?,
+                    len: match words.next() {
+                        Some(word) => number(Some(word))?,
+                        None => 1,
+                    },
+                },
+                "write" => {
+                    let (id, address) = (number(words.next())?, number(words.next())?);
+                    let mut data = heapless::Vec::new();
+                    for word in words.by_ref() {
+                        data.push(number(Some(word))?)
+                            .map_err(|_| ParseError::ArgumentTooLong)?;
+                    }
+                    if data.is_empty() {
+                        return Err(ParseError::MissingArgument);
+                    }
+                    DynamixelCommand::Write { id, address, data }
+                }
+                "goto" => DynamixelCommand::Goto {
+                    id: number(words.next())?,
+                    position: number(words.next())?,
+                },
+                _ => return Err(ParseError::UnknownArgument),
+            }),
+            "gps" => {
+                let mut word = words.next();
+                let raw = word == Some("raw");
+                if raw {
+                    word = words.next();
+                }
+                Command::Gps(GpsCommand {
+                    baudrate: match word {
+                        Some(word) => {
+                            let baudrate = number(Some(word))?;
+                            if !(SerialSettings::MIN_BAUDRATE..=SerialSettings::MAX_BAUDRATE)
+                                .contains(&baudrate)
+                            {
+                                return Err(ParseError::InvalidBaudrate);
+                            }
+                            baudrate
+                        }
+                        None => GPS_BAUDRATE,
+                    },
+                    raw,
+                })
+            }
+            "rtc" => Command::Rtc(match words.next() {
+                None => RtcCommand::Show,
+                Some("set") => {
+                    let time = words.next().ok_or(ParseError::MissingArgument)?;
+                    RtcCommand::Set(
+                        NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S")
+                            .map_err(|_| ParseError::InvalidTime)?,
+                    )
+                }
+                Some(_) => return Err(ParseError::UnknownArgument),
+            }),
+            "clock" => Command::Clock,
+            "i2c" => Command::I2c(match words.next().ok_or(ParseError::MissingArgument)? {
+                "scan" => {
+                    let mut word = words.next();
+                    let bus = match word {
+                        Some(n @ ("1" | "2" | "4")) => {
+                            word = words.next();
+                            n.as_bytes()[0] - b'0'
+                        }
+                        _ => 2,
+                    };
+                    let probe = match word {
+                        None => Probe::Auto,
+                        Some("quick") => Probe::Quick,
+                        Some("read") => Probe::Read,
+                        Some(_) => return Err(ParseError::UnknownArgument),
+                    };
+                    I2cCommand::Scan { bus, probe }
+                }
+                "trace" => {
+                    I2cCommand::Trace(match words.next().ok_or(ParseError::MissingArgument)? {
+                        "on" => true,
+                        "off" => false,
+                        _ => return Err(ParseError::UnknownArgument),
+                    })
+                }
+                _ => return Err(ParseError::UnknownArgument),
+            }),
+            "display" => {
+                Command::Display(match words.next().ok_or(ParseError::MissingArgument)? {
+                    "dashboard" => DisplayCommand::Dashboard,
+                    "terminal" => DisplayCommand::Terminal {
+                        back: match words.next() {
+                            Some(word) => number(Some(word))?,
+                            None => 0,
+                        },
+                    },
+                    _ => return Err(ParseError::UnknownArgument),
+                })
+            }
+            "selftest" => match words.next() {
+                None | Some("internal") => Command::SelfTest(Peer::Internal),
+                Some("external") => Command::SelfTest(Peer::External),
+                Some(_) => return Err(ParseError::UnknownArgument),
+            },
+            _ => return Err(ParseError::UnknownCommand),
+        };
+
+        match words.next() {
+            Some(_) => Err(ParseError::UnknownArgument),
+            None => Ok(parsed),
+        }
+    }
+}
+
+/// A decimal or `0x` hexadecimal number.
+fn number<T: TryFrom<u32>>(word: Option<&str>) -> Result<T, ParseError> {
+    let word = word.ok_or(ParseError::MissingArgument)?;
+    let value = match word.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => word.parse(),
+    };
+    value
+        .ok()
+        .and_then(|v| T::try_from(v).ok())
+        .ok_or(ParseError::InvalidNumber)
+}
+
+fn table(word: Option<&str>) -> Result<Table, ParseError> {
+    Ok(match word.ok_or(ParseError::MissingArgument)? {
+        "coils" => Table::Coils,
+        "discrete" => Table::DiscreteInputs,
+        "holding" => Table::HoldingRegisters,
+        "input" => Table::InputRegisters,
+        _ => return Err(ParseError::UnknownArgument),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineError {
+    TooLong,
+    NotUtf8,
+}
+
+/// Collects typed characters into a line, handling backspace.
+#[derive(Debug)]
+pub struct LineBuffer<const N: usize> {
+    buf: heapless::Vec<u8, N>,
+    overflow: bool,
+}
+
+impl<const N: usize> Default for LineBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: heapless::Vec::new(),
+            overflow: false,
+        }
+    }
+
+    /// Add a byte. Returns the finished line on CR or LF.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&str, LineError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                if self.buf.is_empty() && !self.overflow {
+                    // Second half of CRLF, or a blank line.
+                    return None;
+                }
+                if core::mem::replace(&mut self.overflow, false) {
+                    return Some(Err(LineError::TooLong));
+                }
+                Some(core::str::from_utf8(&self.buf).map_err(|_| LineError::NotUtf8))
+            }
+            // Backspace, delete
+            0x08 | 0x7f => {
+                self.buf.pop();
+                None
+            }
+            _ => {
+                if self.buf.push(byte).is_err() {
+                    self.overflow = true;
+                }
+                None
+            }
+        }
+    }
+
+    /// Call after handling the line returned by [`Self::push`].
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(Command::parse("help"), Ok(Command::Help));
+        assert_eq!(
+            Command::parse("  capture   start "),
+            Ok(Command::Capture(CaptureCommand::Start))
+        );
+        assert_eq!(
+            Command::parse("capture export"),
+            Ok(Command::Capture(CaptureCommand::Export))
+        );
+        assert_eq!(Command::parse(""), Err(ParseError::Empty));
+        assert_eq!(Command::parse("capture"), Err(ParseError::MissingArgument));
+        assert_eq!(
+            Command::parse("capture pause"),
+            Err(ParseError::UnknownArgument)
+        );
+        assert_eq!(
+            Command::parse("capture stop now"),
+            Err(ParseError::UnknownArgument)
+        );
+        assert_eq!(
+            Command::parse("selftest"),
+            Ok(Command::SelfTest(Peer::Internal))
+        );
+        assert_eq!(
+            Command::parse("selftest external"),
+            Ok(Command::SelfTest(Peer::External))
+        );
+        assert_eq!(
+            Command::parse("module boot"),
+            Ok(Command::Module(ModuleCommand::Run(Sequence::Bootloader)))
+        );
+        assert_eq!(
+            Command::parse("wifi join home s3cret"),
+            Ok(Command::Wifi(WifiCommand::Join {
+                ssid: "home".try_into().unwrap(),
+                password: "s3cret".try_into().unwrap(),
+            }))
+        );
+        assert_eq!(
+            Command::parse("wifi join 0123456789abcdef0123456789abcdefX"),
+            Err(ParseError::ArgumentTooLong)
+        );
+        assert_eq!(Command::parse("reboot"), Err(ParseError::UnknownCommand));
+    }
+
+    #[test]
+    fn parses_rtc_commands() {
+        assert_eq!(Command::parse("rtc"), Ok(Command::Rtc(RtcCommand::Show)));
+        let time = chrono::NaiveDate::from_ymd_opt(2026, 5, 19)
+            .unwrap()
+            .and_hms_opt(10, 15, 32)
+            .unwrap();
+        assert_eq!(
+            Command::parse("rtc set 2026-05-19T10:15:32"),
+            Ok(Command::Rtc(RtcCommand::Set(time)))
+        );
+        assert_eq!(Command::parse("rtc set"), Err(ParseError::MissingArgument));
+        assert_eq!(
+            Command::parse("rtc set 2026-02-30T10:15:32"),
+            Err(ParseError::InvalidTime)
+        );
+        assert_eq!(
+            Command::parse("rtc set 10:15:32"),
+            Err(ParseError::InvalidTime)
+        );
+        assert_eq!(Command::parse("rtc get"), Err(ParseError::UnknownArgument));
+        assert_eq!(Command::parse("clock"), Ok(Command::Clock));
+    }
+
+    #[test]
+    fn parses_i2c_commands() {
+        let scan = |bus, probe| Ok(Command::I2c(I2cCommand::Scan { bus, probe }));
+        assert_eq!(Command::parse("i2c scan"), scan(2, Probe::Auto));
+        assert_eq!(Command::parse("i2c scan 4"), scan(4, Probe::Auto));
+        assert_eq!(Command::parse("i2c scan 1 read"), scan(1, Probe::Read));
+        assert_eq!(Command::parse("i2c scan quick"), scan(2, Probe::Quick));
+        assert_eq!(
+            Command::parse("i2c scan 3"),
+            Err(ParseError::UnknownArgument)
+        );
+        assert_eq!(
+            Command::parse("i2c trace on"),
+            Ok(Command::I2c(I2cCommand::Trace(true)))
+        );
+        assert_eq!(
+            Command::parse("i2c trace"),
+            Err(ParseError::MissingArgument)
+        );
+        assert_eq!(Command::parse("i2c"), Err(ParseError::MissingArgument));
+    }
+
+    #[test]
+    fn parses_display_commands() {
+        let display = |command| Ok(Command::Display(command));
+        assert_eq!(
+            Command::parse("display dashboard"),
+            display(DisplayCommand::Dashboard)
+        );
+        assert_eq!(
+            Command::parse("display terminal"),
+            display(DisplayCommand::Terminal { back: 0 })
+        );
+        assert_eq!(
+            Command::parse("display terminal 40"),
+            display(DisplayCommand::Terminal { back: 40 })
+        );
+        assert_eq!(
+            Command::parse("display terminal up"),
+            Err(ParseError::InvalidNumber)
+        );
+        assert_eq!(Command::parse("display"), Err(ParseError::MissingArgument));
+    }
+
+    #[test]
+    fn parses_modbus_commands() {
+        assert_eq!(
+            Command::parse("modbus read 17 holding 0x6b 3"),
+            Ok(Command::Modbus(ModbusCommand::Read {
+                unit: 17,
+                table: Table::HoldingRegisters,
+                address: 0x6b,
+                count: 3,
+            }))
+        );
+        assert_eq!(
+            Command::parse("modbus read 1 coils 5"),
+            Ok(Command::Modbus(ModbusCommand::Read {
+                unit: 1,
+                table: Table::Coils,
+                address: 5,
+                count: 1,
+            }))
+        );
+        assert_eq!(
+            Command::parse("modbus write 1 holding 2 10 0xffff"),
+            Ok(Command::Modbus(ModbusCommand::Write {
+                unit: 1,
+                table: Table::HoldingRegisters,
+                address: 2,
+                values: heapless::Vec::from_slice(&[10, 0xffff]).unwrap(),
+            }))
+        );
+        assert_eq!(
+            Command::parse("modbus serve 7"),
+            Ok(Command::Modbus(ModbusCommand::Serve { unit: 7 }))
+        );
+        assert_eq!(
+            Command::parse("lin slave"),
+            Ok(Command::Lin(LinCommand::Slave))
+        );
+        assert_eq!(Command::parse("lin"), Err(ParseError::MissingArgument));
+        assert_eq!(
+            Command::parse("dmx send 0xff"),
+            Ok(Command::Dmx(DmxCommand::Send { level: 255 }))
+        );
+        assert_eq!(
+            Command::parse("dmx send"),
+            Ok(Command::Dmx(DmxCommand::Send { level: 0 }))
+        );
+        assert_eq!(
+            Command::parse("dmx send 256"),
+            Err(ParseError::InvalidNumber)
+        );
+        assert_eq!(
+            Command::parse("onewire temp"),
+            Ok(Command::OneWire(OneWireCommand::Temperature))
+        );
+        assert_eq!(
+            Command::parse("onewire scan"),
+            Err(ParseError::UnknownArgument)
+        );
+        assert_eq!(
+            Command::parse("dxl ping"),
+            Ok(Command::Dynamixel(DynamixelCommand::Ping { id: None }))
+        );
+        assert_eq!(
+            Command::parse("dxl read 1 132 4"),
+            Ok(Command::Dynamixel(DynamixelCommand::Read {
+                id: 1,
+                address: 132,
+                len: 4,
+            }))
+        );
+        assert_eq!(
+            Command::parse("dxl write 1 64 1"),
+            Ok(Command::Dynamixel(DynamixelCommand::Write {
+                id: 1,
+                address: 64,
+                data: heapless::Vec::from_slice(&[1]).unwrap(),
+            }))
+        );
+        assert_eq!(
+            Command::parse("dxl goto 2 2048"),
+            Ok(Command::Dynamixel(DynamixelCommand::Goto {
+                id: 2,
+                position: 2048,
+            }))
+        );
+        assert_eq!(
+            Command::parse("dxl write 1 64 256"),
+            Err(ParseError::InvalidNumber)
+        );
+        assert_eq!(
+            Command::parse("gps"),
+            Ok(Command::Gps(GpsCommand {
+                baudrate: 9600,
+                raw: false,
+            }))
+        );
+        assert_eq!(
+            Command::parse("gps raw 38400"),
+            Ok(Command::Gps(GpsCommand {
+                baudrate: 38400,
+                raw: true,
+            }))
+        );
+        assert_eq!(Command::parse("gps fast"), Err(ParseError::InvalidNumber));
+        // The USART can't divide down to these.
+        assert_eq!(Command::parse("gps 0"), Err(ParseError::InvalidBaudrate));
+        assert_eq!(
+            Command::parse("gps raw 20000000"),
+            Err(ParseError::InvalidBaudrate)
+        );
+        assert!(Command::parse("gps 300").is_ok());
+
+        assert_eq!(
+            Command::parse("modbus read 256 coils 0"),
+            Err(ParseError::InvalidNumber)
+        );
+        assert_eq!(
+            Command::parse("modbus write 1 input 0 1"),
+            Err(ParseError::UnknownArgument)
+        );
+        assert_eq!(
+            Command::parse("modbus write 1 coils 0"),
+            Err(ParseError::MissingArgument)
+        );
+        assert_eq!(
+            Command::parse("modbus read 1 holding x"),
+            Err(ParseError::InvalidNumber)
+        );
+    }
+
+    #[test]
+    fn line_buffer() {
+        let mut line = LineBuffer::<8>::new();
+
+        for &b in b"helq\x08p" {
+            assert_eq!(line.push(b), None);
+        }
+        assert_eq!(line.push(b'\r'), Some(Ok("help")));
+        line.clear();
+        assert_eq!(line.push(b'\n'), None);
+
+        for &b in b"too long line" {
+            assert_eq!(line.push(b), None);
+        }
+        assert_eq!(line.push(b'\n'), Some(Err(LineError::TooLong)));
+        line.clear();
+        assert_eq!(line.push(b'\n'), None);
+
+        line.push(0xff);
+        assert_eq!(line.push(b'\n'), Some(Err(LineError::NotUtf8)));
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{
+    bridge::{
+        escape::{EscapeConfig, EscapeDetector, Feed, Poll},
+        now_ms, wait_until,
+    },
+    capture, clock, i2c, module,
+    module::Sequence,
+    rtc,
+    selftest::Peer,
+};
+use alloc::{format, string::String};
+use core::fmt::Write as _;
+use defmt::{info, warn};
+use embassy_futures::select::{select, Either};
+use embedded_io_async::{Read, Write};
+
+pub mod command;
+
+use command::{
+    CaptureCommand, Command, DmxCommand, DynamixelCommand, GpsCommand, I2cCommand, LinCommand,
+    LineBuffer, LineError, ModbusCommand, ModuleCommand, OneWireCommand, RtcCommand, WifiCommand,
+};
+
+const LINE_LEN: usize = 80;
+
+/// Why [`run`] returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exit {
+    /// The bridge escape sequence was typed.
+    Escape,
+    Handoff(Handoff),
+}
+
+/// A command that needs USART1, which the console doesn't own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Handoff {
+    SelfTest(Peer),
+    Module(Sequence),
+    Wifi(WifiCommand),
+    Modbus(ModbusCommand),
+    Lin(LinCommand),
+    Dmx(DmxCommand),
+    OneWire(OneWireCommand),
+    Dynamixel(DynamixelCommand),
+    Gps(GpsCommand),
+}
+
+impl Handoff {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Handoff::SelfTest(_) => "self-test",
+            Handoff::Module(_) => "module reset",
+            Handoff::Wifi(_) => "Wi-Fi",
+            Handoff::Modbus(_) => "Modbus",
+            Handoff::Lin(_) => "LIN",
+            Handoff::Dmx(_) => "DMX512",
+            Handoff::OneWire(_) => "1-Wire",
+            Handoff::Dynamixel(_) => "Dynamixel",
+            Handoff::Gps(_) => "GPS",
+        }
+    }
+}
+
+/// Commands the console runs itself come back as the error.
+impl TryFrom<Command> for Handoff {
+    type Error = Command;
+
+    fn try_from(command: Command) -> Result<Self, Command> {
+        Ok(match command {
+            Command::SelfTest(peer) => Handoff::SelfTest(peer),
+            Command::Module(ModuleCommand::Run(sequence)) => Handoff::Module(sequence),
+            Command::Wifi(command) => Handoff::Wifi(command),
+            Command::Modbus(command) => Handoff::Modbus(command),
+            Command::Lin(command) => Handoff::Lin(command),
+            Command::Dmx(command) => Handoff::Dmx(command),
+            Command::OneWire(command) => Handoff::OneWire(command),
+            Command::Dynamixel(command) => Handoff::Dynamixel(command),
+            Command::Gps(command) => Handoff::Gps(command),
+            command => return Err(command),
+        })
+    }
+}
+
+/// Line-based command console on the host port, active whenever the port isn't bridged.
+pub async fn run<R: Read, W: Write>(
+    host_rx: &mut R,
+    host_tx: &mut W,
+    escape: &EscapeConfig,
+) -> Exit {
+    let mut detector = EscapeDetector::new(*escape);
+    let mut line = LineBuffer::<LINE_LEN>::new();
+    let mut buf = [0u8; 32];
+
+    loop {
+        match select(host_rx.read(&mut buf), wait_until(detector.deadline())).await {
+            Either::First(Ok(n)) => {
+                for &b in &buf[..n] {
+                    let (flush, byte) = match detector.feed(b, now_ms()) {
+                        Feed::Escape => return Exit::Escape,
+                        Feed::Hold { flush } => (flush, None),
+                        Feed::Forward { flush } => (flush, Some(b)),
+                    };
+                    for _ in 0..flush {
+                        if let Some(exit) = push(&mut line, host_tx, escape.byte).await {
+                            return exit;
+                        }
+                    }
+                    if let Some(b) = byte {
+                        if let Some(exit) = push(&mut line, host_tx, b).await {
+                            return exit;
+                        }
+                    }
+                }
+            }
+            Either::First(Err(e)) => {
+                warn!("console: host read error: {}", defmt::Debug2Format(&e));
+            }
+            Either::Second(()) => match detector.poll(now_ms()) {
+                Poll::Idle => {}
+                Poll::Flush(n) => {
+                    for _ in 0..n {
+                        if let Some(exit) = push(&mut line, host_tx, escape.byte).await {
+                            return exit;
+                        }
+                    }
+                }
+                Poll::Escape => return Exit::Escape,
+            },
+        }
+    }
+}
+
+async fn push<W: Write>(
+    line: &mut LineBuffer<LINE_LEN>,
+    host_tx: &mut W,
+    byte: u8,
+) -> Option<Exit> {
+    let result = match line.push(byte) {
+        None => return None,
+        Some(Ok(text)) => match Command::parse(text).map(Handoff::try_from) {
+            Ok(Ok(handoff)) => {
+                line.clear();
+                return Some(Exit::Handoff(handoff));
+            }
+            Ok(Err(command)) => execute(command, host_tx).await,
+            Err(e) => reply(host_tx, &format!("ERROR: {}\r\n", e.as_str())).await,
+        },
+        Some(Err(LineError::TooLong)) => reply(host_tx, "ERROR: line too long\r\n").await,
+        Some(Err(LineError::NotUtf8)) => reply(host_tx, "ERROR: not UTF-8\r\n").await,
+    };
+    line.clear();
+
+    if let Err(e) = result {
+        warn!("console: host write error: {}", defmt::Debug2Format(&e));
+    }
+
+    None
+}
+
+async fn reply<W: Write>(host_tx: &mut W, text: &str) -> Result<(), W::Error> {
+    host_tx.write_all(text.as_bytes()).await?;
+    host_tx.flush().await
+}
+
+async fn execute<W: Write>(command: Command, host_tx: &mut W) -> Result<(), W::Error> {
+    info!("console: {}", defmt::Debug2Format(&command));
+
+    let ok = match command {
+        Command::Help => return reply(host_tx, command::HELP).await,
+        Command::Capture(CaptureCommand::Start) => capture::start(),
+        Command::Capture(CaptureCommand::Stop) => capture::stop(),
+        Command::Capture(CaptureCommand::Clear) => capture::clear(),
+        Command::Capture(CaptureCommand::Status) => match capture::status() {
+            Some(s) => {
+                let text = format!(
+                    "{} records, {}/{} bytes, {} dropped, {}\r\n",
+                    s.records,
+                    s.used,
+                    s.capacity,
+                    s.dropped,
+                    if s.running { "running" } else { "stopped" }
+                );
+                return reply(host_tx, &text).await;
+            }
+            None => false,
+        },
+        Command::Module(ModuleCommand::Status) => {
+            let text = format!("module: {}\r\n", module::state());
+            return reply(host_tx, &text).await;
+        }
+        Command::Clock => {
+            let time = clock::now();
+            let mut text = match (time.utc, time.source) {
+                (Some(utc), Some(source)) => format!(
+                    "{} UTC from the {}",
+                    utc.format("%Y-%m-%d %H:%M:%S%.3f"),
+                    source.as_str()
+                ),
+                _ => String::from("no time"),
+            };
+            let _ = write!(
+                text,
+                ", up {}.{:03} s",
+                time.uptime_us / 1_000_000,
+                time.uptime_us / 1_000 % 1_000
+            );
+            for source in [clock::Source::Internal, clock::Source::External] {
+                if let Some(ppb) = clock::drift_ppb(source) {
+                    let _ = write!(text, ", {} {} ppb fast", source.as_str(), ppb);
+                }
+            }
+            text.push_str("\r\n");
+            return reply(host_tx, &text).await;
+        }
+        Command::Rtc(RtcCommand::Show) => {
+            let text = match rtc::now().await {
+                Ok(time) => format!("{} UTC\r\n", time.format("%Y-%m-%d %H:%M:%S%.2f")),
+                Err(e) => format!("ERROR: {}\r\n", e.as_str()),
+            };
+            return reply(host_tx, &text).await;
+        }
+        Command::Rtc(RtcCommand::Set(time)) => {
+            return match rtc::set(&time).await {
+                Ok(()) => reply(host_tx, "OK\r\n").await,
+                Err(e) => reply(host_tx, &format!("ERROR: {}\r\n", e.as_str())).await,
+            };
+        }
+        Command::I2c(I2cCommand::Scan { bus, probe }) => {
+            let text = match i2c::survey(bus, probe).await {
+                Ok(devices) => {
+                    let mut text = format!(
+                        "I2C{}, {} probe: {} found\r\n",
+                        bus,
+                        probe.as_str(),
+                        devices.len()
+                    );
+                    for (address, known) in devices {
+                        let name = known.map_or("unknown", |known| known.name);
+                        let _ = write!(text, "{address:#04x} {name}\r\n");
+                    }
+                    text
+                }
+                Err(e) => format!("ERROR: {}\r\n", i2c::trace::describe(e)),
+            };
+            return reply(host_tx, &text).await;
+        }
+        Command::I2c(I2cCommand::Trace(on)) => {
+            i2c::set_tracing(on);
+            true
+        }
+        #[cfg(feature = "display-spi")]
+        Command::Display(command) => {
+            use crate::display::{self, Screen, Update};
+            use command::DisplayCommand;
+            match command {
+                DisplayCommand::Dashboard => display::send(Update::Show(Screen::Dashboard)),
+                DisplayCommand::Terminal { back } => {
+                    display::send(Update::Show(Screen::Terminal));
+                    display::send(Update::ScrollBack(back));
+                }
+            }
+            true
+        }
+        #[cfg(not(feature = "display-spi"))]
+        Command::Display(_) => return reply(host_tx, "ERROR: no display\r\n").await,
+        // Handed back to the caller by `push`, see `Handoff::try_from`.
+        Command::SelfTest(_)
+        | Command::Module(ModuleCommand::Run(_))
+        | Command::Wifi(_)
+        | Command::Modbus(_)
+        | Command::Lin(_)
+        | Command::Dmx(_)
+        | Command::OneWire(_)
+        | Command::Dynamixel(_)
+        | Command::Gps(_) => return Ok(()),
+        Command::Capture(CaptureCommand::Export) => {
+            // The stream is self-delimiting, see `capture::export`.
+            let records = capture::export_to(host_tx).await?;
+            info!("console: exported {} records", records);
+            return Ok(());
+        }
+    };
+
+    reply(
+        host_tx,
+        if ok {
+            "OK\r\n"
+        } else {
+            "ERROR: no capture buffer\r\n"
+        },
+    )
+    .await
+}
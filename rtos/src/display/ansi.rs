@@ -0,0 +1,343 @@
+//! Escape sequences in a byte stream, as a terminal reads them.
+//!
+//! What serial consoles commonly send is understood: SGR colours and attributes, cursor movement
+//! and erasing. Any other CSI sequence, OSC strings and stray escapes are read to their end and
+//! dropped, so they never show as garbage.
+
+/// Parameters kept of a CSI sequence; more are dropped.
+pub const MAX_PARAMS: usize = 8;
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+/// Cancel and substitute, which abort a sequence.
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1a;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Anything that isn't a control character or part of a sequence, bytes over 0x7f included.
+    Print(u8),
+    LineFeed,
+    CarriageReturn,
+    Backspace,
+    Tab,
+    /// Select graphic rendition, see [`Style::apply`].
+    Sgr(heapless::Vec<u16, MAX_PARAMS>),
+    CursorUp(u16),
+    CursorDown(u16),
+    CursorForward(u16),
+    CursorBack(u16),
+    /// From 0, unlike the sequence.
+    CursorPosition {
+        row: u16,
+        col: u16,
+    },
+    /// 0 from the cursor on, 1 up to the cursor, 2 all of the screen, 3 the scrollback too.
+    EraseInDisplay(u16),
+    /// 0 from the cursor on, 1 up to the cursor, 2 all of the line.
+    EraseInLine(u16),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    Csi,
+    /// A CSI sequence that is read but dropped: private, or with too many parameters.
+    CsiIgnore,
+    Osc,
+    OscEscape,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Parser {
+    state: State,
+    params: heapless::Vec<u16, MAX_PARAMS>,
+    /// The parameter being read, if any digits or a separator have been.
+    param: Option<u16>,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            params: heapless::Vec::new(),
+            param: None,
+        }
+    }
+
+    pub fn feed(&mut self, byte: u8) -> Option<Action> {
+        match (self.state, byte) {
+            (_, CAN | SUB) => {
+                self.state = State::Ground;
+                None
+            }
+            (State::Osc, BEL) => {
+                self.state = State::Ground;
+                None
+            }
+            (State::Osc, ESC) => {
+                self.state = State::OscEscape;
+                None
+            }
+            (State::Osc, _) => None,
+            // ESC \ ends the string, anything else starts over.
+            (State::OscEscape, b'\\') => {
+                self.state = State::Ground;
+                None
+            }
+            (State::OscEscape, _) => {
+                self.state = State::Escape;
+                self.feed(byte)
+            }
+            (_, ESC) => {
+                self.state = State::Escape;
+                None
+            }
+            // Other control characters act even within a sequence.
+            (_, 0x00..=0x1f) => control(byte),
+            (State::Ground, _) => Some(Action::Print(byte)),
+            (State::Escape, b'[') => {
+                self.state = State::Csi;
+                self.params.clear();
+                self.param = None;
+                None
+            }
+            (State::Escape, b']') => {
+                self.state = State::Osc;
+                None
+            }
+            // Intermediate bytes, as of a charset selection, come before the final one.
+            (State::Escape, 0x20..=0x2f) => None,
+            (State::Escape, _) => {
+                self.state = State::Ground;
+                None
+            }
+            (State::Csi, b'0'..=b'9') => {
+                let digit = u16::from(byte - b'0');
+                let param = self.param.unwrap_or(0);
+                self.param = Some(param.saturating_mul(10).saturating_add(digit));
+                None
+            }
+            (State::Csi, b';') => {
+                let param = self.param.take().unwrap_or(0);
+                if self.params.push(param).is_err() {
+                    self.state = State::CsiIgnore;
+                }
+                None
+            }
+            (State::Csi, b'<'..=b'?') => {
+                self.state = State::CsiIgnore;
+                None
+            }
+            (State::Csi | State::CsiIgnore, 0x40..=0x7e) => {
+                let ignore = self.state == State::CsiIgnore;
+                self.state = State::Ground;
+                if ignore {
+                    return None;
+                }
+                if let Some(param) = self.param.take() {
+                    if self.params.push(param).is_err() {
+                        return None;
+                    }
+                }
+                self.dispatch(byte)
+            }
+            // Intermediate bytes, and whatever else.
+            (State::Csi | State::CsiIgnore, _) => None,
+        }
+    }
+
+    fn dispatch(&mut self, byte: u8) -> Option<Action> {
+        let params = core::mem::take(&mut self.params);
+        let arg = |i: usize| params.get(i).copied().unwrap_or(0);
+        // Moves of 0 are moves of 1.
+        let count = arg(0).max(1);
+        Some(match byte {
+            b'm' => Action::Sgr(params),
+            b'A' => Action::CursorUp(count),
+            b'B' => Action::CursorDown(count),
+            b'C' => Action::CursorForward(count),
+            b'D' => Action::CursorBack(count),
+            b'H' | b'f' => Action::CursorPosition {
+                row: arg(0).saturating_sub(1),
+                col: arg(1).saturating_sub(1),
+            },
+            b'J' => Action::EraseInDisplay(arg(0)),
+            b'K' => Action::EraseInLine(arg(0)),
+            _ => return None,
+        })
+    }
+}
+
+fn control(byte: u8) -> Option<Action> {
+    match byte {
+        b'\n' | 0x0b | 0x0c => Some(Action::LineFeed),
+        b'\r' => Some(Action::CarriageReturn),
+        0x08 => Some(Action::Backspace),
+        b'\t' => Some(Action::Tab),
+        _ => None,
+    }
+}
+
+/// One of the 16 colours, 0 to 7 normal and 8 to 15 bright, or the terminal's own.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    #[default]
+    Default,
+    Indexed(u8),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    /// Shown as the bright colour.
+    pub bold: bool,
+    pub inverse: bool,
+}
+
+impl Style {
+    /// Apply SGR `params`; none resets, as 0 does. 256-colour and RGB colours are skipped, but
+    /// for the first 16 of the 256.
+    pub fn apply(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            *self = Style::default();
+        }
+        let mut params = params.iter().copied();
+        while let Some(param) = params.next() {
+            match param {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                7 => self.inverse = true,
+                27 => self.inverse = false,
+                30..=37 => self.fg = Color::Indexed((param - 30) as u8),
+                39 => self.fg = Color::Default,
+                40..=47 => self.bg = Color::Indexed((param - 40) as u8),
+                49 => self.bg = Color::Default,
+                90..=97 => self.fg = Color::Indexed((param - 90 + 8) as u8),
+                100..=107 => self.bg = Color::Indexed((param - 100 + 8) as u8),
+                38 | 48 => {
+                    let color = match params.next() {
+                        Some(5) => params.next().filter(|&n| n < 16).map(|n| n as u8),
+                        Some(2) => {
+                            params.nth(2);
+                            None
+                        }
+                        _ => None,
+                    };
+                    if let Some(n) = color {
+                        if param == 38 {
+                            self.fg = Color::Indexed(n);
+                        } else {
+                            self.bg = Color::Indexed(n);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn parse(bytes: &[u8]) -> Vec<Action> {
+        let mut parser = Parser::new();
+        bytes.iter().filter_map(|&b| parser.feed(b)).collect()
+    }
+
+    fn sgr(params: &[u16]) -> Action {
+        Action::Sgr(heapless::Vec::from_slice(params).unwrap())
+    }
+
+    #[test]
+    fn passes_text_and_controls() {
+        assert_eq!(
+            parse(b"ok\r\n\x08\t\x07\xe9"),
+            [
+                Action::Print(b'o'),
+                Action::Print(b'k'),
+                Action::CarriageReturn,
+                Action::LineFeed,
+                Action::Backspace,
+                Action::Tab,
+                Action::Print(0xe9)
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_csi_sequences() {
+        assert_eq!(
+            parse(b"\x1b[1;31mA\x1b[m\x1b[0K\x1b[2J\x1b[5;10H\x1b[H\x1b[3A\x1b[C"),
+            [
+                sgr(&[1, 31]),
+                Action::Print(b'A'),
+                sgr(&[]),
+                Action::EraseInLine(0),
+                Action::EraseInDisplay(2),
+                Action::CursorPosition { row: 4, col: 9 },
+                Action::CursorPosition { row: 0, col: 0 },
+                Action::CursorUp(3),
+                Action::CursorForward(1),
+            ]
+        );
+        // An empty parameter is 0.
+        assert_eq!(
+            parse(b"\x1b[;5H"),
+            [Action::CursorPosition { row: 0, col: 4 }]
+        );
+    }
+
+    #[test]
+    fn drops_what_it_doesnt_know() {
+        // Cursor hiding, a window title ended both ways, a charset selection, an unknown final
+        // byte, too many parameters and a cancelled sequence.
+        let noise = b"\x1b[?25l\x1b]0;title\x07\x1b]2;t\x1b\\\x1b(B\x1b[5n\x1b[1;2;3;4;5;6;7;8;9m\x1b[3\x18";
+        let mut bytes = noise.to_vec();
+        bytes.extend_from_slice(b"x");
+        assert_eq!(parse(&bytes), [Action::Print(b'x')]);
+        // A control character in the middle of a sequence still acts.
+        assert_eq!(parse(b"\x1b[3\r1m"), [Action::CarriageReturn, sgr(&[31])]);
+    }
+
+    #[test]
+    fn applies_sgr() {
+        let mut style = Style::default();
+        style.apply(&[1, 33, 44]);
+        assert_eq!(
+            style,
+            Style {
+                fg: Color::Indexed(3),
+                bg: Color::Indexed(4),
+                bold: true,
+                inverse: false
+            }
+        );
+        style.apply(&[22, 7, 39, 101]);
+        assert_eq!(
+            style,
+            Style {
+                fg: Color::Default,
+                bg: Color::Indexed(9),
+                bold: false,
+                inverse: true
+            }
+        );
+        style.apply(&[38, 5, 12, 48, 2, 1, 2, 3, 94]);
+        assert_eq!(
+            (style.fg, style.bg),
+            (Color::Indexed(12), Color::Indexed(9))
+        );
+        style.apply(&[]);
+        assert_eq!(style, Style::default());
+    }
+}
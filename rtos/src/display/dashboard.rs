@@ -0,0 +1,358 @@
+//! The status dashboard: a title bar, then a row per value, label on the left and value on the
+//! right.
+//!
+//! The frame and labels are drawn once. Values are redrawn in place over a solid background,
+//! padded to the width of their cell, so a shorter value leaves nothing of a longer one behind
+//! and the panel is never cleared, which over SPI would show as flicker.
+
+use core::fmt::Write;
+use embedded_graphics::{
+    mono_font::{MonoFont, MonoTextStyle, MonoTextStyleBuilder},
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{Circle, PrimitiveStyleBuilder, Rectangle},
+    text::{Baseline, Text},
+};
+
+pub const TITLE: &str = "GIGA R1 WiFi";
+/// Characters of the label column, the longest label and a space.
+pub const LABEL_CHARS: u32 = 12;
+/// Characters of a value, past which it is cut.
+pub const VALUE_CHARS: usize = 16;
+/// Left of the labels and title.
+pub const MARGIN: u32 = 8;
+
+pub const BACKGROUND: Rgb565 = Rgb565::BLACK;
+pub const TEXT: Rgb565 = Rgb565::WHITE;
+pub const LABEL: Rgb565 = Rgb565::CSS_LIGHT_GRAY;
+pub const TITLE_BAR: Rgb565 = Rgb565::CSS_DARK_SLATE_BLUE;
+/// Red, green and blue, as the LEDs.
+pub const LEDS: [Rgb565; 3] = [Rgb565::RED, Rgb565::GREEN, Rgb565::BLUE];
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub uptime_s: u64,
+    pub version: &'static str,
+    /// What USART1 is doing.
+    pub mode: &'static str,
+    pub uart_rx: u64,
+    pub uart_tx: u64,
+    pub uart_errors: u32,
+    /// Red, green and blue.
+    pub leds: [bool; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Row {
+    Uptime,
+    Firmware,
+    Mode,
+    UartRx,
+    UartTx,
+    UartErrors,
+    Leds,
+}
+
+impl Row {
+    /// Top to bottom.
+    pub const ALL: [Row; 7] = [
+        Row::Uptime,
+        Row::Firmware,
+        Row::Mode,
+        Row::UartRx,
+        Row::UartTx,
+        Row::UartErrors,
+        Row::Leds,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Row::Uptime => "Uptime",
+            Row::Firmware => "Firmware",
+            Row::Mode => "Mode",
+            Row::UartRx => "UART RX",
+            Row::UartTx => "UART TX",
+            Row::UartErrors => "UART errors",
+            Row::Leds => "LEDs",
+        }
+    }
+
+    /// The value shown, cut to [`VALUE_CHARS`]. Empty for the LEDs, which are drawn.
+    pub fn value(&self, status: &Status) -> heapless::String<VALUE_CHARS> {
+        let mut value = heapless::String::new();
+        match self {
+            Row::Uptime => {
+                let s = status.uptime_s;
+                let (days, h, m, s) = (s / 86_400, s / 3600 % 24, s / 60 % 60, s % 60);
+                let _ = if days > 0 {
+                    write!(value, "{days}d {h:02}:{m:02}:{s:02}")
+                } else {
+                    write!(value, "{h:02}:{m:02}:{s:02}")
+                };
+            }
+            Row::Firmware => push_cut(&mut value, status.version),
+            Row::Mode => push_cut(&mut value, status.mode),
+            Row::UartRx => {
+                let _ = write!(value, "{} B", status.uart_rx);
+            }
+            Row::UartTx => {
+                let _ = write!(value, "{} B", status.uart_tx);
+            }
+            Row::UartErrors => {
+                let _ = write!(value, "{}", status.uart_errors);
+            }
+            Row::Leds => {}
+        }
+        value
+    }
+
+    fn index(&self) -> u32 {
+        Row::ALL.iter().position(|row| row == self).unwrap_or(0) as u32
+    }
+}
+
+fn push_cut(value: &mut heapless::String<VALUE_CHARS>, s: &str) {
+    for c in s.chars() {
+        if value.push(c).is_err() {
+            break;
+        }
+    }
+}
+
+/// Where everything goes on a panel of `size`, in rows a font and a half high.
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    size: Size,
+    font: &'static MonoFont<'static>,
+}
+
+impl Layout {
+    pub fn new(size: Size, font: &'static MonoFont<'static>) -> Self {
+        Self { size, font }
+    }
+
+    fn row_height(&self) -> u32 {
+        self.font.character_size.height * 3 / 2
+    }
+
+    /// Top left of the text in a row starting at `y`.
+    fn text_at(&self, x: u32, y: u32) -> Point {
+        let padding = (self.row_height() - self.font.character_size.height) / 2;
+        Point::new(x as i32, (y + padding) as i32)
+    }
+
+    pub fn title(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), Size::new(self.size.width, self.row_height()))
+    }
+
+    /// The whole width of `row`, or `None` if it's off the bottom of the panel.
+    pub fn row(&self, row: Row) -> Option<Rectangle> {
+        let height = self.row_height();
+        let y = height * (row.index() + 1);
+        (y + height <= self.size.height)
+            .then(|| Rectangle::new(Point::new(0, y as i32), Size::new(self.size.width, height)))
+    }
+
+    /// Where the value of `row` is drawn: [`VALUE_CHARS`] wide after the label column, cut at
+    /// the right of the panel, and a character high.
+    pub fn value(&self, row: Row) -> Option<Rectangle> {
+        let area = self.row(row)?;
+        let char_width = self.font.character_size.width;
+        let x = MARGIN + LABEL_CHARS * char_width;
+        let width = (VALUE_CHARS as u32 * char_width).min(self.size.width.saturating_sub(x));
+        Some(Rectangle::new(
+            self.text_at(x, area.top_left.y as u32),
+            Size::new(width, self.font.character_size.height),
+        ))
+    }
+
+    /// LED `i` of the three, a character high, a character apart.
+    pub fn led(&self, i: usize) -> Option<Circle> {
+        let area = self.value(Row::Leds)?;
+        let diameter = self.font.character_size.height;
+        let step = diameter + self.font.character_size.width;
+        let circle = Circle::new(
+            area.top_left + Point::new((step * i as u32) as i32, 0),
+            diameter,
+        );
+        (area.contains(circle.bounding_box().bottom_right()?)).then_some(circle)
+    }
+
+    fn style(&self, color: Rgb565) -> MonoTextStyle<'static, Rgb565> {
+        MonoTextStyleBuilder::new()
+            .font(self.font)
+            .text_color(color)
+            .background_color(BACKGROUND)
+            .build()
+    }
+}
+
+/// Clear the panel and draw the title and labels.
+pub fn draw_frame<D: DrawTarget<Color = Rgb565>>(
+    target: &mut D,
+    layout: &Layout,
+) -> Result<(), D::Error> {
+    target.clear(BACKGROUND)?;
+    let title = layout.title();
+    target.fill_solid(&title, TITLE_BAR)?;
+    let style = MonoTextStyleBuilder::new()
+        .font(layout.font)
+        .text_color(TEXT)
+        .build();
+    let at = layout.text_at(MARGIN, 0);
+    Text::with_baseline(TITLE, at, style, Baseline::Top).draw(target)?;
+
+    for row in Row::ALL {
+        let Some(area) = layout.row(row) else {
+            break;
+        };
+        let at = layout.text_at(MARGIN, area.top_left.y as u32);
+        Text::with_baseline(row.label(), at, layout.style(LABEL), Baseline::Top).draw(target)?;
+    }
+    Ok(())
+}
+
+/// Draw every value over the last.
+pub fn draw_values<D: DrawTarget<Color = Rgb565>>(
+    target: &mut D,
+    layout: &Layout,
+    status: &Status,
+) -> Result<(), D::Error> {
+    for row in Row::ALL {
+        let Some(area) = layout.value(row) else {
+            break;
+        };
+        if row == Row::Leds {
+            draw_leds(target, layout, status.leds)?;
+            continue;
+        }
+        let mut value = row.value(status);
+        while value.push(' ').is_ok() {}
+        Text::with_baseline(&value, area.top_left, layout.style(TEXT), Baseline::Top)
+            .draw(&mut target.clipped(&area))?;
+    }
+    Ok(())
+}
+
+/// Filled when on, outlined when off.
+fn draw_leds<D: DrawTarget<Color = Rgb565>>(
+    target: &mut D,
+    layout: &Layout,
+    leds: [bool; 3],
+) -> Result<(), D::Error> {
+    for (i, (on, color)) in leds.into_iter().zip(LEDS).enumerate() {
+        let Some(circle) = layout.led(i) else {
+            break;
+        };
+        let style = PrimitiveStyleBuilder::new()
+            .stroke_color(color)
+            .stroke_width(1)
+            .fill_color(if on { color } else { BACKGROUND })
+            .build();
+        circle.into_styled(style).draw(target)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::mock_display::MockDisplay;
+    use embedded_graphics::mono_font::ascii::{FONT_10X20, FONT_6X10};
+
+    const PANEL: Size = Size::new(320, 240);
+
+    fn status() -> Status {
+        Status {
+            uptime_s: 93_784,
+            version: "v0.2.0-48-g1234abc-dirty",
+            mode: "AT client",
+            uart_rx: 1234,
+            uart_tx: 56,
+            uart_errors: 2,
+            leds: [true, false, true],
+        }
+    }
+
+    #[test]
+    fn formats_values() {
+        let mut status = status();
+        assert_eq!(Row::Uptime.value(&status), "1d 02:03:04");
+        assert_eq!(Row::Firmware.value(&status), "v0.2.0-48-g1234a");
+        assert_eq!(Row::UartRx.value(&status), "1234 B");
+        assert_eq!(Row::UartErrors.value(&status), "2");
+        assert_eq!(Row::Leds.value(&status), "");
+        status.uptime_s = 59;
+        assert_eq!(Row::Uptime.value(&status), "00:00:59");
+    }
+
+    #[test]
+    fn rows_fit_the_panel() {
+        let layout = Layout::new(PANEL, &FONT_10X20);
+        let screen = Rectangle::new(Point::zero(), PANEL);
+        let mut above = layout.title();
+        for row in Row::ALL {
+            let area = layout.row(row).unwrap();
+            assert_eq!(area.top_left.y, above.bottom_right().unwrap().y + 1);
+            assert!(screen.contains(area.bottom_right().unwrap()));
+            let value = layout.value(row).unwrap();
+            assert!(area.contains(value.top_left) && area.contains(value.bottom_right().unwrap()));
+            above = area;
+        }
+        for i in 0..3 {
+            assert!(layout.led(i).is_some());
+        }
+
+        // The rows that don't fit are left out.
+        let layout = Layout::new(Size::new(320, 100), &FONT_10X20);
+        assert!(layout.row(Row::Firmware).is_some());
+        assert!(layout.row(Row::Mode).is_none());
+        assert!(layout.value(Row::Leds).is_none());
+    }
+
+    /// `MockDisplay` is 64 pixels square, so each test looks through a window at one value.
+    fn window(layout: &Layout, row: Row, status: &Status) -> MockDisplay<Rgb565> {
+        let mut display = MockDisplay::new();
+        display.set_allow_out_of_bounds_drawing(true);
+        display.set_allow_overdraw(true);
+        let offset = layout.value(row).unwrap().top_left;
+        draw_values(&mut display.translated(-offset), layout, status).unwrap();
+        display
+    }
+
+    #[test]
+    fn values_fill_their_cell() {
+        let layout = Layout::new(PANEL, &FONT_6X10);
+        let mut status = status();
+        let long = window(&layout, Row::Mode, &status);
+        status.mode = "";
+        let empty = window(&layout, Row::Mode, &status);
+
+        // Text over the background, and only background once it's gone. The window is narrower
+        // than the cell.
+        let cell = Rectangle::new(Point::zero(), Size::new(64, 10));
+        assert!(cell.points().all(|p| long.get_pixel(p).is_some()));
+        assert!(cell.points().any(|p| long.get_pixel(p) == Some(TEXT)));
+        assert!(cell
+            .points()
+            .all(|p| empty.get_pixel(p) == Some(BACKGROUND)));
+    }
+
+    #[test]
+    fn draws_the_led_states() {
+        let layout = Layout::new(PANEL, &FONT_6X10);
+        let display = window(&layout, Row::Leds, &status());
+        let offset = layout.value(Row::Leds).unwrap().top_left;
+        let centre = |i| layout.led(i).unwrap().center() - offset;
+        let left = |i| {
+            let circle = layout.led(i).unwrap();
+            Point::new(circle.top_left.x, circle.center().y) - offset
+        };
+        assert_eq!(display.get_pixel(centre(0)), Some(Rgb565::RED));
+        assert_eq!(display.get_pixel(centre(1)), Some(BACKGROUND));
+        assert_eq!(display.get_pixel(left(1)), Some(Rgb565::GREEN));
+        assert_eq!(display.get_pixel(centre(2)), Some(Rgb565::BLUE));
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{board::DisplayResource, capture, capture::ring::Direction, consts};
+use defmt::{info, unwrap, warn};
+use display_interface_spi::SPIInterface;
+use embassy_executor::Spawner;
+use embassy_futures::select::{select3, Either3};
+use embassy_stm32::{
+    dma::NoDma,
+    gpio::{Level, Output, Speed},
+    peripherals,
+    spi::{self, Spi},
+    time::Hertz,
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{Delay, Duration, Instant, Timer};
+use embedded_graphics::{mono_font::MonoFont, pixelcolor::Rgb565, prelude::*};
+use mipidsi::{
+    models::ILI9342CRgb565,
+    options::{ColorInversion, ColorOrder},
+    Builder,
+};
+
+pub mod ansi;
+pub mod dashboard;
+pub mod terminal;
+
+use dashboard::{Layout, Status};
+use terminal::Terminal;
+
+/// SPI5 runs from the 100 MHz PCLK2, halved until it's at most this: 25 MHz.
+const SPI_FREQUENCY: Hertz = Hertz(25_000_000);
+const REFRESH: Duration = Duration::from_secs(1);
+const FONT: &MonoFont<'static> = &profont::PROFONT_14_POINT;
+/// Small, for as much of a line as will fit.
+const TERMINAL_FONT: &MonoFont<'static> = &profont::PROFONT_9_POINT;
+/// Lines kept above the terminal screen, on the heap.
+const SCROLLBACK: usize = 500;
+/// The terminal is drawn at most this often, however fast bytes come.
+const FRAME: Duration = Duration::from_millis(100);
+/// Bytes of UART traffic per message to the display task.
+const CHUNK: usize = 32;
+
+type Panel = mipidsi::Display<
+    SPIInterface<Spi<'static, peripherals::SPI5, NoDma, NoDma>, Output<'static>, Output<'static>>,
+    ILI9342CRgb565,
+    Output<'static>,
+>;
+
+static UPDATES: Channel<CriticalSectionRawMutex, Update, 4> = Channel::new();
+static TRAFFIC: Channel<CriticalSectionRawMutex, (Direction, heapless::Vec<u8, CHUNK>), 32> =
+    Channel::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Screen {
+    Dashboard,
+    /// USART1 traffic.
+    Terminal,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Update {
+    /// What USART1 is doing now.
+    Mode(&'static str),
+    Backlight(bool),
+    /// Draw the whole panel again.
+    Redraw,
+    Show(Screen),
+    /// Show the terminal this many lines up into its scrollback, 0 to follow the traffic.
+    ScrollBack(usize),
+}
+
+/// Hand `update` to the display task. Dropped if it's behind.
+pub fn send(update: Update) {
+    let _ = UPDATES.try_send(update);
+}
+
+/// Hand UART traffic to the terminal, as a [`capture::subscribe`]r. What doesn't fit while the
+/// display task is behind is dropped; the capture has it all.
+fn feed(direction: Direction, bytes: &[u8]) {
+    for chunk in bytes.chunks(CHUNK) {
+        let chunk = heapless::Vec::from_slice(chunk).unwrap_or_default();
+        if TRAFFIC.try_send((direction, chunk)).is_err() {
+            break;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayConfig {
+    /// Most ILI9342C modules, M5Stack's among them, need both.
+    pub inverted: bool,
+    pub bgr: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            inverted: true,
+            bgr: true,
+        }
+    }
+}
+
+/// Bring up the panel and show the dashboard, or log why not.
+pub fn spawn(spawner: &Spawner, r: DisplayResource, config: DisplayConfig) {
+    let mut spi_config = spi::Config::default();
+    spi_config.frequency = SPI_FREQUENCY;
+    let spi = Spi::new_txonly(r.peri, r.sck, r.mosi, NoDma, NoDma, spi_config);
+    let cs = Output::new(r.cs, Level::High, Speed::VeryHigh);
+    let dc = Output::new(r.dc, Level::Low, Speed::VeryHigh);
+    let rst = Output::new(r.rst, Level::High, Speed::Low);
+    let backlight = Output::new(r.backlight, Level::Low, Speed::Low);
+
+    let panel = Builder::ili9342c_rgb565(SPIInterface::new(spi, dc, cs))
+        .with_invert_colors(if config.inverted {
+            ColorInversion::Inverted
+        } else {
+            ColorInversion::Normal
+        })
+        .with_color_order(if config.bgr {
+            ColorOrder::Bgr
+        } else {
+            ColorOrder::Rgb
+        })
+        .init(&mut Delay, Some(rst));
+    match panel {
+        Ok(panel) => {
+            info!("display: ILI9342C up");
+            unwrap!(spawner.spawn(display_task(panel, backlight)));
+            capture::subscribe(feed);
+        }
+        Err(e) => warn!("display: init failed: {}", defmt::Debug2Format(&e)),
+    }
+}
+
+#[embassy_executor::task]
+async fn display_task(mut panel: Panel, mut backlight: Output<'static>) {
+    let size = panel.bounding_box().size;
+    let layout = Layout::new(size, FONT);
+    let mut status = Status {
+        version: consts::GIT_DESCRIBE,
+        ..Default::default()
+    };
+    let (cols, rows) = terminal::grid(size, TERMINAL_FONT);
+    let mut terminal = Terminal::new(cols, rows, SCROLLBACK);
+    let mut screen = Screen::Dashboard;
+    let mut back = 0;
+    let mut redraw = true;
+    let mut next = Instant::now();
+    backlight.set_high();
+
+    loop {
+        if Instant::now() >= next {
+            let drawn = match screen {
+                Screen::Dashboard => draw_dashboard(&mut panel, &layout, &mut status, redraw),
+                Screen::Terminal if redraw || terminal.changed() => {
+                    draw_terminal(&mut panel, &mut terminal, back, redraw)
+                }
+                Screen::Terminal => Ok(()),
+            };
+            if let Err(e) = drawn {
+                warn!("display: {}", defmt::Debug2Format(&e));
+            }
+            redraw = false;
+            next = Instant::now()
+                + match screen {
+                    Screen::Dashboard => REFRESH,
+                    Screen::Terminal => FRAME,
+                };
+        }
+
+        match select3(UPDATES.receive(), TRAFFIC.receive(), Timer::at(next)).await {
+            Either3::First(update) => {
+                match update {
+                    Update::Mode(mode) => status.mode = mode,
+                    Update::Backlight(on) => backlight.set_level(Level::from(on)),
+                    Update::Redraw => redraw = true,
+                    Update::Show(to) => {
+                        screen = to;
+                        back = 0;
+                        redraw = true;
+                    }
+                    Update::ScrollBack(lines) => {
+                        back = lines.min(terminal.scrollback_len());
+                        redraw |= screen == Screen::Terminal;
+                    }
+                }
+                next = Instant::now();
+            }
+            Either3::Second((direction, bytes)) => terminal.write(direction, &bytes),
+            Either3::Third(()) => {}
+        }
+    }
+}
+
+fn draw_dashboard<D: DrawTarget<Color = Rgb565>>(
+    panel: &mut D,
+    layout: &Layout,
+    status: &mut Status,
+    frame: bool,
+) -> Result<(), D::Error> {
+    if frame {
+        dashboard::draw_frame(panel, layout)?;
+    }
+    let traffic = capture::traffic();
+    status.uptime_s = Instant::now().as_secs();
+    status.uart_rx = traffic.rx_bytes;
+    status.uart_tx = traffic.tx_bytes;
+    status.uart_errors = traffic.errors;
+    status.leds = crate::led_states();
+    dashboard::draw_values(panel, layout, status)
+}
+
+/// The terminal draws over its own rows only, so the strip below them is cleared with the rest.
+fn draw_terminal<D: DrawTarget<Color = Rgb565>>(
+    panel: &mut D,
+    terminal: &mut Terminal,
+    back: usize,
+    clear: bool,
+) -> Result<(), D::Error> {
+    if clear {
+        panel.clear(terminal::BACKGROUND)?;
+    }
+    terminal.draw(panel, TERMINAL_FONT, back)
+}
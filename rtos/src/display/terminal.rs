@@ -0,0 +1,533 @@
+//! A scrolling terminal of what goes over the UART: the screen, a grid of characters, and the
+//! scrollback above it, the lines that scrolled off the top.
+//!
+//! Each direction has its own escape parser and style, so a sequence cut by the other direction's
+//! bytes still reads right, and each line holds one direction, shown in its own colour unless an
+//! escape sets one: TX yellow, RX green. A line feed also returns the cursor, as serial devices
+//! rarely send one without the other. Lines wrap at the right edge. Control characters the
+//! terminal has no use for are dropped, and other bytes that aren't printable ASCII show as `.`.
+
+use super::ansi::{Action, Color, Parser, Style};
+use crate::capture::ring::Direction;
+use alloc::{collections::VecDeque, string::String, vec::Vec};
+use embedded_graphics::{
+    mono_font::{MonoFont, MonoTextStyleBuilder},
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::Rectangle,
+    text::{Baseline, Text},
+};
+
+pub const TAB: usize = 8;
+pub const BACKGROUND: Rgb565 = Rgb565::BLACK;
+pub const TX: Rgb565 = Rgb565::YELLOW;
+pub const RX: Rgb565 = Rgb565::GREEN;
+
+/// The 16 colours, near enough xterm's.
+pub const PALETTE: [Rgb565; 16] = [
+    Rgb565::BLACK,
+    Rgb565::new(25, 0, 0),
+    Rgb565::new(0, 50, 0),
+    Rgb565::new(25, 50, 0),
+    Rgb565::new(0, 0, 29),
+    Rgb565::new(25, 0, 25),
+    Rgb565::new(0, 50, 25),
+    Rgb565::new(29, 58, 29),
+    Rgb565::new(15, 31, 15),
+    Rgb565::RED,
+    Rgb565::GREEN,
+    Rgb565::YELLOW,
+    Rgb565::new(11, 23, 31),
+    Rgb565::MAGENTA,
+    Rgb565::CYAN,
+    Rgb565::WHITE,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub byte: u8,
+    pub style: Style,
+}
+
+impl Cell {
+    const BLANK: Cell = Cell {
+        byte: b' ',
+        style: Style {
+            fg: Color::Default,
+            bg: Color::Default,
+            bold: false,
+            inverse: false,
+        },
+    };
+
+    /// What's drawn for the byte.
+    pub fn char(&self) -> char {
+        match self.byte {
+            0x20..=0x7e => self.byte as char,
+            _ => '.',
+        }
+    }
+
+    /// Foreground and background, on a line of `direction`.
+    pub fn colors(&self, direction: Direction) -> (Rgb565, Rgb565) {
+        let Style {
+            fg,
+            bg,
+            bold,
+            inverse,
+        } = self.style;
+        let fg = match fg {
+            Color::Indexed(n) if bold && n < 8 => PALETTE[usize::from(n) + 8],
+            Color::Indexed(n) => PALETTE[usize::from(n) % 16],
+            Color::Default => match direction {
+                Direction::Tx => TX,
+                Direction::Rx => RX,
+            },
+        };
+        let bg = match bg {
+            Color::Indexed(n) => PALETTE[usize::from(n) % 16],
+            Color::Default => BACKGROUND,
+        };
+        if inverse {
+            (bg, fg)
+        } else {
+            (fg, bg)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Line {
+    /// Up to the last written; the rest is blank.
+    cells: Vec<Cell>,
+    direction: Direction,
+}
+
+impl Line {
+    fn new(direction: Direction) -> Self {
+        Self {
+            cells: Vec::new(),
+            direction,
+        }
+    }
+}
+
+/// Columns and rows of `font` that fit in `size`.
+pub fn grid(size: Size, font: &MonoFont) -> (usize, usize) {
+    let width = font.character_size.width + font.character_spacing;
+    let height = font.character_size.height;
+    (
+        (size.width / width.max(1)) as usize,
+        (size.height / height.max(1)) as usize,
+    )
+}
+
+pub struct Terminal {
+    cols: usize,
+    rows: usize,
+    /// Lines kept above the screen.
+    scrollback: usize,
+    /// The scrollback, then the screen: the last `rows`, fewer until that many are written.
+    lines: VecDeque<Line>,
+    /// The cursor, as an index into `lines`, and a column.
+    row: usize,
+    col: usize,
+    /// At the right edge, with the last column written: the next character wraps first.
+    pending_wrap: bool,
+    /// By direction, RX then TX.
+    parsers: [Parser; 2],
+    styles: [Style; 2],
+    changed: bool,
+}
+
+impl Terminal {
+    pub fn new(cols: usize, rows: usize, scrollback: usize) -> Self {
+        let mut lines = VecDeque::new();
+        lines.push_back(Line::new(Direction::Rx));
+        Self {
+            cols: cols.max(1),
+            rows: rows.max(1),
+            scrollback,
+            lines,
+            row: 0,
+            col: 0,
+            pending_wrap: false,
+            parsers: [Parser::new(), Parser::new()],
+            styles: [Style::default(); 2],
+            changed: true,
+        }
+    }
+
+    /// Lines above the screen, as far as [`draw`](Self::draw) goes back.
+    pub fn scrollback_len(&self) -> usize {
+        self.lines.len() - self.lines.len().min(self.rows)
+    }
+
+    /// Whether anything changed since it was last drawn.
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+
+    pub fn write(&mut self, direction: Direction, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        self.changed = true;
+        let line = &mut self.lines[self.row];
+        if line.direction != direction {
+            if line.cells.is_empty() {
+                line.direction = direction;
+            } else {
+                self.line_feed();
+                self.lines[self.row].direction = direction;
+            }
+        }
+        let index = direction as usize;
+        for &byte in bytes {
+            if let Some(action) = self.parsers[index].feed(byte) {
+                self.act(index, action);
+            }
+        }
+    }
+
+    fn act(&mut self, index: usize, action: Action) {
+        if !matches!(action, Action::Print(_)) {
+            self.pending_wrap = false;
+        }
+        match action {
+            Action::Print(byte) => self.print(byte, self.styles[index]),
+            Action::LineFeed => self.line_feed(),
+            Action::CarriageReturn => self.col = 0,
+            Action::Backspace => self.col = self.col.saturating_sub(1),
+            Action::Tab => self.col = ((self.col / TAB + 1) * TAB).min(self.cols - 1),
+            Action::Sgr(params) => self.styles[index].apply(&params),
+            Action::CursorUp(n) => self.row = self.row.saturating_sub(n.into()).max(self.top()),
+            Action::CursorDown(n) => {
+                let row = self.row - self.top() + usize::from(n);
+                self.goto(row, self.col);
+            }
+            Action::CursorForward(n) => self.col = (self.col + usize::from(n)).min(self.cols - 1),
+            Action::CursorBack(n) => self.col = self.col.saturating_sub(n.into()),
+            Action::CursorPosition { row, col } => self.goto(row.into(), col.into()),
+            Action::EraseInDisplay(mode) => self.erase_in_display(mode),
+            Action::EraseInLine(mode) => self.erase_in_line(mode),
+        }
+    }
+
+    /// The first line on the screen.
+    fn top(&self) -> usize {
+        self.scrollback_len()
+    }
+
+    fn print(&mut self, byte: u8, style: Style) {
+        if self.pending_wrap {
+            self.line_feed();
+            self.pending_wrap = false;
+        }
+        let cells = &mut self.lines[self.row].cells;
+        if cells.len() <= self.col {
+            cells.resize(self.col + 1, Cell::BLANK);
+        }
+        cells[self.col] = Cell { byte, style };
+        if self.col + 1 < self.cols {
+            self.col += 1;
+        } else {
+            self.pending_wrap = true;
+        }
+    }
+
+    /// Down a line and back to the start, scrolling at the bottom.
+    fn line_feed(&mut self) {
+        let direction = self.lines[self.row].direction;
+        if self.row + 1 == self.lines.len() {
+            self.lines.push_back(Line::new(direction));
+            while self.lines.len() > self.rows + self.scrollback {
+                self.lines.pop_front();
+            }
+        }
+        self.row = (self.row + 1).min(self.lines.len() - 1);
+        self.col = 0;
+    }
+
+    /// To `row` of the screen, writing blank lines down to it if need be.
+    fn goto(&mut self, row: usize, col: usize) {
+        let row = row.min(self.rows - 1);
+        let direction = self.lines[self.row].direction;
+        while self.lines.len() < self.rows && self.lines.len() <= row {
+            self.lines.push_back(Line::new(direction));
+        }
+        self.row = (self.top() + row).min(self.lines.len() - 1);
+        self.col = col.min(self.cols - 1);
+    }
+
+    fn erase_in_line(&mut self, mode: u16) {
+        let col = self.col;
+        let cells = &mut self.lines[self.row].cells;
+        match mode {
+            0 => cells.truncate(col),
+            1 => {
+                for cell in cells.iter_mut().take(col + 1) {
+                    *cell = Cell::BLANK;
+                }
+            }
+            2 => cells.clear(),
+            _ => {}
+        }
+    }
+
+    fn erase_in_display(&mut self, mode: u16) {
+        let top = self.top();
+        match mode {
+            0 => {
+                self.erase_in_line(0);
+                for line in self.lines.range_mut(self.row + 1..) {
+                    line.cells.clear();
+                }
+            }
+            1 => {
+                self.erase_in_line(1);
+                for line in self.lines.range_mut(top..self.row) {
+                    line.cells.clear();
+                }
+            }
+            2 => {
+                for line in self.lines.range_mut(top..) {
+                    line.cells.clear();
+                }
+            }
+            3 => {
+                self.lines.drain(..top);
+                self.row -= top;
+            }
+            _ => {}
+        }
+    }
+
+    /// Draw the screen, or `back` lines up into the scrollback, from the top left of `target` in
+    /// `font`. Every cell and what's right of the last is drawn over, so nothing is cleared first.
+    pub fn draw<D: DrawTarget<Color = Rgb565>>(
+        &mut self,
+        target: &mut D,
+        font: &MonoFont,
+        back: usize,
+    ) -> Result<(), D::Error> {
+        self.changed = false;
+        let width = target.bounding_box().size.width;
+        let char_width = font.character_size.width + font.character_spacing;
+        let height = font.character_size.height;
+        let end = self.lines.len() - back.min(self.scrollback_len());
+        let start = end - end.min(self.rows);
+        let mut text = String::new();
+
+        for screen_row in 0..self.rows {
+            let y = (screen_row as u32 * height) as i32;
+            let Some(line) = self.lines.get(start + screen_row) else {
+                let rest = Rectangle::new(Point::new(0, y), Size::new(width, height));
+                target.fill_solid(&rest, BACKGROUND)?;
+                continue;
+            };
+            let cells = &line.cells[..line.cells.len().min(self.cols)];
+            let mut col = 0;
+            // In runs of one colour.
+            for run in cells.chunk_by(|a, b| a.colors(line.direction) == b.colors(line.direction)) {
+                let (fg, bg) = run[0].colors(line.direction);
+                text.clear();
+                text.extend(run.iter().map(Cell::char));
+                let style = MonoTextStyleBuilder::new()
+                    .font(font)
+                    .text_color(fg)
+                    .background_color(bg)
+                    .build();
+                let at = Point::new((col as u32 * char_width) as i32, y);
+                Text::with_baseline(&text, at, style, Baseline::Top).draw(target)?;
+                col += run.len();
+            }
+            let x = col as u32 * char_width;
+            let rest = Rectangle::new(
+                Point::new(x as i32, y),
+                Size::new(width.saturating_sub(x), height),
+            );
+            target.fill_solid(&rest, BACKGROUND)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::mock_display::MockDisplay;
+    use embedded_graphics::mono_font::ascii::FONT_4X6;
+    use std::vec;
+
+    /// The text of every line and its direction, the scrollback first.
+    fn lines(term: &Terminal) -> Vec<(Direction, String)> {
+        term.lines
+            .iter()
+            .map(|line| (line.direction, line.cells.iter().map(Cell::char).collect()))
+            .collect()
+    }
+
+    fn rx(text: &str) -> (Direction, String) {
+        (Direction::Rx, text.into())
+    }
+
+    fn tx(text: &str) -> (Direction, String) {
+        (Direction::Tx, text.into())
+    }
+
+    #[test]
+    fn wraps_and_scrolls() {
+        let mut term = Terminal::new(4, 2, 2);
+        term.write(Direction::Rx, b"abcdefgh\r\n");
+        // A line exactly as wide as the screen doesn't leave a blank one after.
+        assert_eq!(lines(&term), [rx("abcd"), rx("efgh"), rx("")]);
+        assert_eq!(term.scrollback_len(), 1);
+
+        term.write(Direction::Rx, b"1\n2\n3\n");
+        assert_eq!(lines(&term), [rx("1"), rx("2"), rx("3"), rx("")]);
+        assert_eq!(term.scrollback_len(), 2);
+
+        // Tabs stop at the edge, bytes that aren't printable show as dots and a bell shows as
+        // nothing.
+        term.write(Direction::Rx, b"\t\xff\x07\x7f");
+        assert_eq!(lines(&term)[2..], [rx("   ."), rx(".")]);
+    }
+
+    #[test]
+    fn lines_keep_one_direction() {
+        let mut term = Terminal::new(16, 4, 4);
+        term.write(Direction::Tx, b"AT");
+        term.write(Direction::Tx, b"+GMR\r\n");
+        term.write(Direction::Rx, b"AT version");
+        term.write(Direction::Tx, b"AT\r\n");
+        term.write(Direction::Rx, b"\r\nOK\r\n");
+        assert_eq!(
+            lines(&term),
+            [
+                tx("AT+GMR"),
+                rx("AT version"),
+                tx("AT"),
+                rx(""),
+                rx("OK"),
+                rx("")
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_styles_by_direction() {
+        let mut term = Terminal::new(16, 4, 4);
+        // The RX escape is cut by TX bytes, and TX's colour doesn't leak into RX.
+        term.write(Direction::Rx, b"\x1b[3");
+        term.write(Direction::Tx, b"\x1b[1;34mT");
+        term.write(Direction::Rx, b"1mR\x1b[0mr");
+        let tx_cell = term.lines[0].cells[0];
+        assert_eq!(tx_cell.colors(Direction::Tx), (PALETTE[12], BACKGROUND));
+        let line = &term.lines[1];
+        assert_eq!(
+            line.cells[0].colors(line.direction),
+            (PALETTE[1], BACKGROUND)
+        );
+        assert_eq!(line.cells[1].colors(line.direction), (RX, BACKGROUND));
+
+        let inverse = Cell {
+            byte: b'x',
+            style: Style {
+                inverse: true,
+                ..Style::default()
+            },
+        };
+        assert_eq!(inverse.colors(Direction::Tx), (BACKGROUND, TX));
+    }
+
+    #[test]
+    fn moves_the_cursor_and_erases() {
+        let mut term = Terminal::new(8, 3, 2);
+        term.write(Direction::Rx, b"\x1b[2;3Hx\x1b[Hab\x1b[5Bc\x1b[9Ad");
+        assert_eq!(lines(&term), [rx("ab d"), rx("  x"), rx("  c")]);
+
+        // The cursor stays on the last column when it fills.
+        term.write(Direction::Rx, b"\x1b[3;1H12345678\x1b[4D\x1b[K");
+        assert_eq!(lines(&term)[2], rx("123"));
+        term.write(Direction::Rx, b"\x1b[2D\x1b[1K");
+        assert_eq!(lines(&term)[2], rx("  3"));
+
+        term.write(Direction::Rx, b"\r\nmore\r\n\x1b[1;2H\x1b[J");
+        assert_eq!(
+            lines(&term),
+            [rx("ab d"), rx("  x"), rx(" "), rx(""), rx("")]
+        );
+        term.write(Direction::Rx, b"\x1b[3J");
+        assert_eq!(term.scrollback_len(), 0);
+        term.write(Direction::Rx, b"y");
+        assert_eq!(lines(&term), [rx(" y"), rx(""), rx("")]);
+    }
+
+    fn draw(term: &mut Terminal, back: usize) -> MockDisplay<Rgb565> {
+        let mut display = MockDisplay::new();
+        term.draw(&mut display, &FONT_4X6, back).unwrap();
+        display
+    }
+
+    /// The colours in the cell at `col` and `row`, in no order.
+    fn cell_colors(display: &MockDisplay<Rgb565>, col: u32, row: u32) -> Vec<Rgb565> {
+        let area = Rectangle::new(Point::new(col as i32 * 4, row as i32 * 6), Size::new(4, 6));
+        let mut colors = vec![];
+        for p in area.points() {
+            let color = display.get_pixel(p).unwrap();
+            if !colors.contains(&color) {
+                colors.push(color);
+            }
+        }
+        colors.sort_by_key(|color| color.into_storage());
+        colors
+    }
+
+    fn sorted<const N: usize>(mut colors: [Rgb565; N]) -> [Rgb565; N] {
+        colors.sort_by_key(|color| color.into_storage());
+        colors
+    }
+
+    #[test]
+    fn draws_the_screen() {
+        let (cols, rows) = grid(Size::new(64, 64), &FONT_4X6);
+        assert_eq!((cols, rows), (16, 10));
+        let mut term = Terminal::new(cols, rows, 4);
+        term.write(Direction::Tx, b"AT\r\n");
+        term.write(Direction::Rx, b"\x1b[41mE\x1b[m \xff");
+        assert!(term.changed());
+        let display = draw(&mut term, 0);
+        assert!(!term.changed());
+
+        // Every pixel of the screen, no more than once.
+        assert_eq!(
+            display.affected_area(),
+            Rectangle::new(Point::zero(), Size::new(64, 60))
+        );
+        assert_eq!(cell_colors(&display, 0, 0), sorted([BACKGROUND, TX]));
+        assert_eq!(cell_colors(&display, 0, 1), sorted([PALETTE[1], RX]));
+        assert_eq!(cell_colors(&display, 1, 1), sorted([BACKGROUND]));
+        assert_eq!(cell_colors(&display, 2, 1), sorted([BACKGROUND, RX]));
+        assert_eq!(cell_colors(&display, 5, 1), sorted([BACKGROUND]));
+        assert_eq!(cell_colors(&display, 0, 9), sorted([BACKGROUND]));
+    }
+
+    #[test]
+    fn draws_the_scrollback() {
+        let mut term = Terminal::new(16, 2, 4);
+        term.write(Direction::Tx, b"a\r\n");
+        term.write(Direction::Rx, b"b\r\nc");
+        let now = draw(&mut term, 0);
+        assert_eq!(cell_colors(&now, 0, 0), sorted([BACKGROUND, RX]));
+        assert_eq!(cell_colors(&now, 1, 1), sorted([BACKGROUND]));
+
+        // Back only as far as there is.
+        let back = draw(&mut term, 5);
+        assert_eq!(back, draw(&mut term, 1));
+        assert_eq!(cell_colors(&back, 0, 0), sorted([BACKGROUND, TX]));
+        assert_eq!(cell_colors(&back, 0, 1), sorted([BACKGROUND, RX]));
+    }
+}
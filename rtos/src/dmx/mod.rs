@@ -0,0 +1,203 @@
+use crate::{
+    bridge::now_ms,
+    capture::Tap,
+    console::command::DmxCommand,
+    uart::{self, De, DriverEnable, SerialSettings},
+};
+use alloc::{format, string::String};
+use core::fmt::Write as _;
+use defmt::{debug, info, warn};
+use embassy_embedded_hal::SetConfig;
+use embassy_futures::select::{select, Either};
+use embassy_stm32::{
+    pac,
+    peripherals::USART1,
+    usart::{self as usart_hal, BufferedUartRx, BufferedUartTx},
+};
+use embedded_io_async::{Read, Write};
+
+pub mod receiver;
+pub mod timing;
+
+pub use receiver::{Event, Packet, Receiver, Stats};
+pub use timing::{BreakTiming, DMX_SETTINGS, NULL_START_CODE, RDM_START_CODE, SLOTS};
+
+/// Break and MAB of `dmx send`.
+pub const BREAK_TIMING: BreakTiming = BreakTiming {
+    break_us: 176,
+    mab_us: timing::MIN_MAB_US,
+};
+/// Slots shown by `dmx receive`.
+const SHOWN_SLOTS: usize = 16;
+const REPORT_MS: u64 = 1_000;
+
+/// Run a console `dmx` command on USART1 until a key is pressed on `host_rx`. With `de`, the
+/// RS-485 transceiver drives the line while sending and listens while receiving. USART1 is put
+/// back to `restore` afterwards.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_command<HR: Read, HW: Write>(
+    rx: &mut Tap<BufferedUartRx<'static, USART1>>,
+    tx: &mut Tap<BufferedUartTx<'static, USART1>>,
+    mut de: Option<&mut De<'static>>,
+    host_rx: &mut HR,
+    host_tx: &mut HW,
+    command: DmxCommand,
+    restore: &SerialSettings,
+) -> Result<(), HW::Error> {
+    info!("dmx: {}", defmt::Debug2Format(&command));
+    if let Err(e) = rx.set_config(&(&DMX_SETTINGS).into()) {
+        warn!("dmx: UART config failed: {}", defmt::Debug2Format(&e));
+    }
+    if let Some(de) = &de {
+        de.retime(&DMX_SETTINGS);
+    }
+
+    let text = match command {
+        DmxCommand::Send { level } => {
+            format!("sending {SLOTS} slots at {level}, any key stops\r\n")
+        }
+        DmxCommand::Receive => String::from("receiving, any key stops\r\n"),
+    };
+    host_tx.write_all(text.as_bytes()).await?;
+    host_tx.flush().await?;
+
+    let mut key = [0u8];
+    let result = match command {
+        DmxCommand::Send { level } => {
+            de.set_asserted(true);
+            let slots = [level; SLOTS];
+            let node = transmit(&mut *rx, &mut *tx, de.as_deref(), &BREAK_TIMING, &slots);
+            let result = select(node, host_rx.read(&mut key)).await;
+            de.set_asserted(false);
+            match result {
+                Either::First(e) => Err(e),
+                Either::Second(_) => Ok(()),
+            }
+        }
+        DmxCommand::Receive => {
+            de.set_asserted(false);
+            let node = receive(&mut *rx, host_tx);
+            match select(node, host_rx.read(&mut key)).await {
+                Either::First(Err(e)) => return Err(e),
+                Either::First(Ok(())) | Either::Second(_) => Ok(()),
+            }
+        }
+    };
+
+    if let Err(e) = rx.set_config(&restore.into()) {
+        warn!(
+            "dmx: failed to restore UART config: {}",
+            defmt::Debug2Format(&e)
+        );
+    }
+    if let Some(de) = &de {
+        de.retime(restore);
+    }
+
+    let text = match result {
+        Ok(()) => String::from("OK\r\n"),
+        Err(e) => {
+            warn!("dmx: {}", defmt::Debug2Format(&e));
+            String::from("ERROR: serial error\r\n")
+        }
+    };
+    host_tx.write_all(text.as_bytes()).await?;
+    host_tx.flush().await
+}
+
+/// Send packets back to back: a zero character at the break rate for the break and MAB, then
+/// the start code and slots at 250 kbaud. Returns only if the UART fails.
+async fn transmit(
+    rx: &mut Tap<BufferedUartRx<'static, USART1>>,
+    tx: &mut Tap<BufferedUartTx<'static, USART1>>,
+    de: Option<&De<'static>>,
+    timing: &BreakTiming,
+    slots: &[u8; SLOTS],
+) -> usart_hal::Error {
+    let break_settings = timing.break_settings();
+    let break_config = (&break_settings).into();
+    let slot_config = (&DMX_SETTINGS).into();
+    let retime = |settings: &SerialSettings| {
+        if let Some(de) = de {
+            de.retime(settings);
+        }
+    };
+    loop {
+        // Both rates are well within the USART's range.
+        let _ = rx.set_config(&break_config);
+        retime(&break_settings);
+        if let Err(e) = send(tx, &[0]).await {
+            return e;
+        }
+        let _ = rx.set_config(&slot_config);
+        retime(&DMX_SETTINGS);
+        if let Err(e) = send(tx, &[NULL_START_CODE]).await {
+            return e;
+        }
+        if let Err(e) = send(tx, slots).await {
+            return e;
+        }
+    }
+}
+
+/// Write `data` and wait until it is on the wire, so the rate can change after it.
+async fn send(
+    tx: &mut Tap<BufferedUartTx<'static, USART1>>,
+    data: &[u8],
+) -> Result<(), usart_hal::Error> {
+    tx.write_all(data).await?;
+    tx.flush().await?;
+    if !uart::wait_tx_complete(pac::USART1).await {
+        warn!("dmx: transmission never completed");
+    }
+    Ok(())
+}
+
+/// Follow incoming packets and print the universe once a second.
+async fn receive<HW: Write>(
+    rx: &mut Tap<BufferedUartRx<'static, USART1>>,
+    host_tx: &mut HW,
+) -> Result<(), HW::Error> {
+    let mut receiver = Receiver::new();
+    let mut byte = [0u8];
+    let mut next_report = now_ms() + REPORT_MS;
+    let mut reported = Stats::default();
+
+    loop {
+        let event = match rx.read(&mut byte).await {
+            // The line low for longer than a character.
+            Err(usart_hal::Error::Framing) => Event::Break,
+            Ok(1) => Event::Byte(byte[0]),
+            Ok(_) | Err(_) => continue,
+        };
+        if let Some(packet) = receiver.feed(event) {
+            // Alternate start codes, RDM requests among them, would be answered from here.
+            if packet.start_code != NULL_START_CODE {
+                debug!(
+                    "dmx: start code {=u8:#x}, {} slots, rdm {}",
+                    packet.start_code,
+                    packet.slots.len(),
+                    packet.is_rdm()
+                );
+            }
+        }
+
+        if now_ms() < next_report {
+            continue;
+        }
+        next_report = now_ms() + REPORT_MS;
+        let stats = receiver.stats();
+        let mut text = format!(
+            "{} packets/s, {} alternate, {} slots:",
+            stats.packets - reported.packets,
+            stats.alternate - reported.alternate,
+            receiver.universe().len()
+        );
+        for slot in receiver.universe().iter().take(SHOWN_SLOTS) {
+            let _ = write!(text, " {slot}");
+        }
+        text.push_str("\r\n");
+        reported = stats;
+        host_tx.write_all(text.as_bytes()).await?;
+    }
+}
@@ -0,0 +1,206 @@
+use super::timing::{NULL_START_CODE, RDM_START_CODE, SLOTS};
+
+/// What the receiver saw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A break: a framing error on a zero character, the line low for longer than one.
+    Break,
+    Byte(u8),
+}
+
+/// A complete packet: its start code and slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet<'a> {
+    pub start_code: u8,
+    pub slots: &'a [u8],
+}
+
+impl Packet<'_> {
+    pub fn is_rdm(&self) -> bool {
+        self.start_code == RDM_START_CODE
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Dimmer packets, start code 0.
+    pub packets: u32,
+    /// Packets with another start code: RDM, text, system information, manufacturer specific.
+    pub alternate: u32,
+    /// Characters after the 512th slot, dropped until the next break.
+    pub overlong: u32,
+    /// Characters that arrived without a break before them.
+    pub unframed: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting for a break.
+    Idle,
+    StartCode,
+    Slots,
+    /// 512 slots received, waiting for the break.
+    Full,
+}
+
+/// DMX512 receiver: packets between breaks. The latest dimmer packet is kept as the universe;
+/// packets with alternate start codes, RDM among them, are only handed to the caller.
+#[derive(Debug, Clone)]
+pub struct Receiver {
+    state: State,
+    start_code: u8,
+    packet: [u8; SLOTS],
+    len: usize,
+    universe: [u8; SLOTS],
+    universe_len: usize,
+    stats: Stats,
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Receiver {
+    pub fn new() -> Self {
+        Self {
+            state: State::Idle,
+            start_code: NULL_START_CODE,
+            packet: [0; SLOTS],
+            len: 0,
+            universe: [0; SLOTS],
+            universe_len: 0,
+            stats: Stats::default(),
+        }
+    }
+
+    /// Returns the packet a break, or the 512th slot, completes.
+    pub fn feed(&mut self, event: Event) -> Option<Packet<'_>> {
+        match (event, self.state) {
+            (Event::Break, State::Slots) => {
+                self.state = State::StartCode;
+                Some(self.complete())
+            }
+            (Event::Break, _) => {
+                self.state = State::StartCode;
+                None
+            }
+            (Event::Byte(_), State::Idle) => {
+                self.stats.unframed += 1;
+                None
+            }
+            (Event::Byte(_), State::Full) => {
+                self.stats.overlong += 1;
+                None
+            }
+            (Event::Byte(b), State::StartCode) => {
+                self.start_code = b;
+                self.len = 0;
+                self.state = State::Slots;
+                None
+            }
+            (Event::Byte(b), State::Slots) => {
+                self.packet[self.len] = b;
+                self.len += 1;
+                if self.len < SLOTS {
+                    return None;
+                }
+                self.state = State::Full;
+                Some(self.complete())
+            }
+        }
+    }
+
+    fn complete(&mut self) -> Packet<'_> {
+        let slots = &self.packet[..self.len];
+        if self.start_code == NULL_START_CODE {
+            self.stats.packets += 1;
+            self.universe[..slots.len()].copy_from_slice(slots);
+            self.universe_len = slots.len();
+        } else {
+            self.stats.alternate += 1;
+        }
+        Packet {
+            start_code: self.start_code,
+            slots,
+        }
+    }
+
+    /// Slots of the latest dimmer packet; fewer than 512 if the transmitter sends fewer.
+    pub fn universe(&self) -> &[u8] {
+        &self.universe[..self.universe_len]
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn feed(receiver: &mut Receiver, start_code: u8, slots: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut packets = Vec::new();
+        let events = [Event::Break, Event::Byte(start_code)]
+            .into_iter()
+            .chain(slots.iter().map(|&b| Event::Byte(b)));
+        for event in events {
+            if let Some(packet) = receiver.feed(event) {
+                packets.push((packet.start_code, packet.slots.to_vec()));
+            }
+        }
+        packets
+    }
+
+    #[test]
+    fn packets_between_breaks() {
+        let mut receiver = Receiver::new();
+
+        assert!(feed(&mut receiver, NULL_START_CODE, &[1, 2, 3]).is_empty());
+        // The next break completes it.
+        assert_eq!(
+            feed(&mut receiver, RDM_START_CODE, &[0x01, 0x18]),
+            [(NULL_START_CODE, alloc::vec![1, 2, 3])]
+        );
+        assert_eq!(receiver.universe(), [1, 2, 3]);
+        let packets = feed(&mut receiver, NULL_START_CODE, &[9; SLOTS]);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0], (RDM_START_CODE, alloc::vec![0x01, 0x18]));
+        // A full universe completes without waiting for the break.
+        assert_eq!(packets[1].1.len(), SLOTS);
+        assert_eq!(receiver.universe(), [9; SLOTS]);
+        // The RDM packet didn't touch the universe.
+        assert_eq!(
+            receiver.stats(),
+            Stats {
+                packets: 2,
+                alternate: 1,
+                overlong: 0,
+                unframed: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn ignores_stray_data() {
+        let mut receiver = Receiver::new();
+        assert_eq!(receiver.feed(Event::Byte(0)), None);
+        assert_eq!(receiver.feed(Event::Byte(7)), None);
+        assert_eq!(receiver.stats().unframed, 2);
+        assert!(receiver.universe().is_empty());
+
+        // After a full packet, until the next break.
+        feed(&mut receiver, NULL_START_CODE, &[1; SLOTS]);
+        assert_eq!(receiver.feed(Event::Byte(7)), None);
+        assert_eq!(receiver.stats().overlong, 1);
+        // Two breaks in a row are an empty packet's worth of nothing.
+        assert_eq!(receiver.feed(Event::Break), None);
+        assert_eq!(receiver.feed(Event::Break), None);
+        assert_eq!(receiver.universe(), [1; SLOTS]);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::uart::{SerialSettings, StopBits};
+
+pub const BAUDRATE: u32 = 250_000;
+/// Slots in a universe, after the start code.
+pub const SLOTS: usize = 512;
+/// Start code of dimmer data.
+pub const NULL_START_CODE: u8 = 0x00;
+/// Start code of Remote Device Management (ANSI E1.20) packets.
+pub const RDM_START_CODE: u8 = 0xcc;
+
+/// Line settings of the slots: 250 kbaud 8N2.
+pub const DMX_SETTINGS: SerialSettings =
+    SerialSettings::new(BAUDRATE).with_stop_bits(StopBits::Two);
+
+/// Shortest break and mark after break a transmitter may send (ANSI E1.11).
+pub const MIN_BREAK_US: u32 = 92;
+pub const MIN_MAB_US: u32 = 12;
+
+/// Start code and slots on the wire.
+const START_BITS: u32 = 1;
+const DATA_BITS: u32 = 8;
+
+/// Break and mark after break (MAB) of a transmitted packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakTiming {
+    pub break_us: u32,
+    pub mab_us: u32,
+}
+
+impl Default for BreakTiming {
+    /// The break most consoles send, and the shortest MAB.
+    fn default() -> Self {
+        Self {
+            break_us: 176,
+            mab_us: MIN_MAB_US,
+        }
+    }
+}
+
+impl BreakTiming {
+    /// Raised to the minimum the standard allows.
+    pub fn clamped(self) -> Self {
+        Self {
+            break_us: self.break_us.max(MIN_BREAK_US),
+            mab_us: self.mab_us.max(MIN_MAB_US),
+        }
+    }
+
+    /// Line settings that make a zero character the break, and its stop bits the MAB. The
+    /// start bit and eight zero bits last at least `break_us`; the stop bits at least `mab_us`
+    /// if two are enough, and reconfiguring back to [`DMX_SETTINGS`] adds to them.
+    pub fn break_settings(&self) -> SerialSettings {
+        let timing = self.clamped();
+        let low_bits = START_BITS + DATA_BITS;
+        let baudrate = (low_bits * 1_000_000 / timing.break_us).max(SerialSettings::MIN_BAUDRATE);
+        let stop_bits = if 1_000_000 / baudrate >= timing.mab_us {
+            StopBits::One
+        } else {
+            StopBits::Two
+        };
+        SerialSettings::new(baudrate).with_stop_bits(stop_bits)
+    }
+}
+
+/// Time on the wire of a packet with `slots` slots, from the start of the break.
+pub fn packet_time_us(timing: &BreakTiming, slots: usize) -> u32 {
+    let timing = timing.clamped();
+    timing.break_us + timing.mab_us + DMX_SETTINGS.chars_time_us(slots as u32 + 1)
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn break_from_a_zero_character() {
+        let settings = BreakTiming::default().break_settings();
+        assert_eq!(settings.baudrate, 51_136);
+        assert_eq!(settings.stop_bits, StopBits::One);
+
+        // A short break needs a faster character, whose single stop bit is too short a MAB.
+        let settings = BreakTiming {
+            break_us: 92,
+            mab_us: 16,
+        }
+        .break_settings();
+        assert_eq!(settings.baudrate, 97_826);
+        assert_eq!(settings.stop_bits, StopBits::Two);
+
+        // Below the minimum.
+        let settings = BreakTiming {
+            break_us: 20,
+            mab_us: 1,
+        }
+        .break_settings();
+        assert_eq!(
+            settings,
+            BreakTiming {
+                break_us: 92,
+                mab_us: 12,
+            }
+            .break_settings()
+        );
+    }
+
+    #[test]
+    fn refresh_rate() {
+        // 513 characters of 44 us each, plus 176 + 12 us: about 44 packets a second.
+        assert_eq!(packet_time_us(&BreakTiming::default(), SLOTS), 22_760);
+        assert_eq!(packet_time_us(&BreakTiming::default(), 24), 1_288);
+    }
+}
@@ -0,0 +1,131 @@
+//! Control table of the X series (XL430, XC430, XM430, XH430 and relatives) on protocol 2.0
+//! firmware, as typed registers.
+
+use core::marker::PhantomData;
+
+/// A register's value, little-endian on the wire.
+pub trait Value: Sized {
+    const SIZE: usize;
+    fn encode(&self, out: &mut [u8]);
+    /// `None` for a value outside the register's range.
+    fn decode(data: &[u8]) -> Option<Self>;
+}
+
+macro_rules! int_value {
+    ($($t:ty),*) => {$(
+        impl Value for $t {
+            const SIZE: usize = core::mem::size_of::<$t>();
+            fn encode(&self, out: &mut [u8]) {
+                out.copy_from_slice(&self.to_le_bytes());
+            }
+            fn decode(data: &[u8]) -> Option<Self> {
+                Some(Self::from_le_bytes(data.try_into().ok()?))
+            }
+        }
+    )*};
+}
+
+int_value!(u8, u16, i16, u32, i32);
+
+impl Value for bool {
+    const SIZE: usize = 1;
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = *self as u8;
+    }
+    fn decode(data: &[u8]) -> Option<Self> {
+        match data {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperatingMode {
+    Current = 0,
+    Velocity = 1,
+    Position = 3,
+    /// Multi-turn.
+    ExtendedPosition = 4,
+    CurrentBasedPosition = 5,
+    Pwm = 16,
+}
+
+impl Value for OperatingMode {
+    const SIZE: usize = 1;
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = *self as u8;
+    }
+    fn decode(data: &[u8]) -> Option<Self> {
+        match data {
+            [0] => Some(OperatingMode::Current),
+            [1] => Some(OperatingMode::Velocity),
+            [3] => Some(OperatingMode::Position),
+            [4] => Some(OperatingMode::ExtendedPosition),
+            [5] => Some(OperatingMode::CurrentBasedPosition),
+            [16] => Some(OperatingMode::Pwm),
+            _ => None,
+        }
+    }
+}
+
+/// Address of a register holding a `T`.
+#[derive(Debug)]
+pub struct Register<T> {
+    pub address: u16,
+    value: PhantomData<T>,
+}
+
+impl<T> Clone for Register<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Register<T> {}
+
+impl<T: Value> Register<T> {
+    pub const fn new(address: u16) -> Self {
+        Self {
+            address,
+            value: PhantomData,
+        }
+    }
+
+    pub const fn size(&self) -> u16 {
+        T::SIZE as u16
+    }
+}
+
+// EEPROM area, writable with torque off.
+pub const MODEL_NUMBER: Register<u16> = Register::new(0);
+pub const FIRMWARE_VERSION: Register<u8> = Register::new(6);
+pub const ID: Register<u8> = Register::new(7);
+/// 0 to 7: 9600, 57600 (default), 115200, 1M, 2M, 3M, 4M, 4.5M baud.
+pub const BAUD_RATE: Register<u8> = Register::new(8);
+/// In units of 2 us.
+pub const RETURN_DELAY_TIME: Register<u8> = Register::new(9);
+pub const OPERATING_MODE: Register<OperatingMode> = Register::new(11);
+
+// RAM area.
+pub const TORQUE_ENABLE: Register<bool> = Register::new(64);
+pub const LED: Register<bool> = Register::new(65);
+/// 0: answer ping only, 1: and reads, 2: everything (default).
+pub const STATUS_RETURN_LEVEL: Register<u8> = Register::new(68);
+pub const HARDWARE_ERROR_STATUS: Register<u8> = Register::new(70);
+pub const GOAL_CURRENT: Register<i16> = Register::new(102);
+/// In units of 0.229 rpm.
+pub const GOAL_VELOCITY: Register<i32> = Register::new(104);
+pub const PROFILE_ACCELERATION: Register<u32> = Register::new(108);
+pub const PROFILE_VELOCITY: Register<u32> = Register::new(112);
+/// 4096 per turn, 2048 in the middle.
+pub const GOAL_POSITION: Register<i32> = Register::new(116);
+pub const MOVING: Register<bool> = Register::new(122);
+pub const PRESENT_CURRENT: Register<i16> = Register::new(126);
+pub const PRESENT_VELOCITY: Register<i32> = Register::new(128);
+pub const PRESENT_POSITION: Register<i32> = Register::new(132);
+/// In units of 0.1 V.
+pub const PRESENT_INPUT_VOLTAGE: Register<u16> = Register::new(144);
+/// In °C.
+pub const PRESENT_TEMPERATURE: Register<u8> = Register::new(146);
@@ -0,0 +1,523 @@
+use super::{
+    control::{Register, Value},
+    instruction::{BulkRead, Request},
+    packet::{Decoder, PacketError, Status, StatusError, BROADCAST_ID},
+};
+use alloc::vec::Vec;
+use embassy_futures::select::{select, Either};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+/// How long a status may take to arrive, from the end of the instruction or the last status.
+/// The return delay is 500 us by default.
+pub const RESPONSE_TIMEOUT_MS: u32 = 50;
+/// Devices answer a broadcast ping in ID order, about 3 ms apart per ID.
+pub const SCAN_TIMEOUT_MS: u32 = 800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Packet(PacketError),
+    /// The device refused the instruction.
+    Status(StatusError),
+    /// No answer. Devices with a status return level below 2 don't answer writes.
+    Timeout,
+    /// Read or write error on the UART.
+    Serial,
+    /// A status from another device, or with the wrong amount of data.
+    UnexpectedResponse,
+    /// A register value the typed register can't hold.
+    InvalidValue,
+}
+
+impl Error {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::Packet(e) => e.as_str(),
+            Error::Status(e) => e.as_str(),
+            Error::Timeout => "no response",
+            Error::Serial => "serial error",
+            Error::UnexpectedResponse => "unexpected response",
+            Error::InvalidValue => "invalid value",
+        }
+    }
+}
+
+impl From<PacketError> for Error {
+    fn from(e: PacketError) -> Self {
+        Error::Packet(e)
+    }
+}
+
+/// Answer to a ping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PingInfo {
+    pub id: u8,
+    pub model: u16,
+    pub firmware: u8,
+}
+
+impl PingInfo {
+    fn from_status(status: &Status) -> Option<Self> {
+        match status.params[..] {
+            [model_l, model_h, firmware] => Some(Self {
+                id: status.id,
+                model: u16::from_le_bytes([model_l, model_h]),
+                firmware,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Dynamixel protocol 2.0 controller: one instruction at a time, each waiting for its status
+/// packets. On a single-wire half-duplex line our own packets come back first; the decoder
+/// passes them by as they aren't status packets.
+pub struct Controller<R, W, D> {
+    rx: R,
+    tx: W,
+    delay: D,
+    decoder: Decoder,
+    packet: Vec<u8>,
+}
+
+impl<R: Read, W: Write, D: DelayNs> Controller<R, W, D> {
+    pub fn new(rx: R, tx: W, delay: D) -> Self {
+        Self {
+            rx,
+            tx,
+            delay,
+            decoder: Decoder::new(),
+            packet: Vec::new(),
+        }
+    }
+
+    async fn send(&mut self, request: &Request<'_>) -> Result<(), Error> {
+        self.packet.clear();
+        request.encode(&mut self.packet);
+        self.decoder.clear();
+        self.tx
+            .write_all(&self.packet)
+            .await
+            .map_err(|_| Error::Serial)?;
+        self.tx.flush().await.map_err(|_| Error::Serial)
+    }
+
+    /// The next status packet.
+    async fn receive(&mut self, timeout_ms: u32) -> Result<Status, Error> {
+        let Self {
+            rx, delay, decoder, ..
+        } = self;
+        let receive = async {
+            let mut byte = [0u8];
+            loop {
+                if rx.read(&mut byte).await.map_err(|_| Error::Serial)? == 0 {
+                    continue;
+                }
+                match decoder.push(byte[0]) {
+                    Some(Ok(packet)) => {
+                        if let Some(status) = Status::from_packet(packet) {
+                            return Ok(status);
+                        }
+                    }
+                    Some(Err(e)) => return Err(e.into()),
+                    None => {}
+                }
+            }
+        };
+        match select(receive, delay.delay_ms(timeout_ms)).await {
+            Either::First(result) => result,
+            Either::Second(()) => Err(Error::Timeout),
+        }
+    }
+
+    /// The parameters of `id`'s status, which must have `len` bytes of them.
+    async fn expect(&mut self, id: u8, len: usize) -> Result<Vec<u8>, Error> {
+        let status = self.receive(RESPONSE_TIMEOUT_MS).await?;
+        if status.id != id {
+            return Err(Error::UnexpectedResponse);
+        }
+        if let Some(e) = status.error() {
+            return Err(Error::Status(e));
+        }
+        if status.params.len() != len {
+            return Err(Error::UnexpectedResponse);
+        }
+        Ok(status.params)
+    }
+
+    pub async fn ping(&mut self, id: u8) -> Result<PingInfo, Error> {
+        self.send(&Request::Ping { id }).await?;
+        let status = self.receive(RESPONSE_TIMEOUT_MS).await?;
+        match PingInfo::from_status(&status) {
+            Some(info) if info.id == id => Ok(info),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Every device on the bus, from a broadcast ping.
+    pub async fn scan(&mut self) -> Result<Vec<PingInfo>, Error> {
+        self.send(&Request::Ping { id: BROADCAST_ID }).await?;
+        let mut found = Vec::new();
+        loop {
+            match self.receive(SCAN_TIMEOUT_MS).await {
+                Ok(status) => {
+                    found.push(PingInfo::from_status(&status).ok_or(Error::UnexpectedResponse)?)
+                }
+                Err(Error::Timeout) => return Ok(found),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub async fn read(&mut self, id: u8, address: u16, buf: &mut [u8]) -> Result<(), Error> {
+        let len = buf.len() as u16;
+        self.send(&Request::Read { id, address, len }).await?;
+        buf.copy_from_slice(&self.expect(id, buf.len()).await?);
+        Ok(())
+    }
+
+    /// Write `data` from `address` on; to [`BROADCAST_ID`], without an answer.
+    pub async fn write(&mut self, id: u8, address: u16, data: &[u8]) -> Result<(), Error> {
+        self.send(&Request::Write { id, address, data }).await?;
+        if id == BROADCAST_ID {
+            return Ok(());
+        }
+        self.expect(id, 0).await.map(drop)
+    }
+
+    pub async fn reboot(&mut self, id: u8) -> Result<(), Error> {
+        self.send(&Request::Reboot { id }).await?;
+        self.expect(id, 0).await.map(drop)
+    }
+
+    /// `len` bytes from `address` of each of `ids`, in one instruction.
+    pub async fn sync_read(
+        &mut self,
+        address: u16,
+        len: u16,
+        ids: &[u8],
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        self.send(&Request::SyncRead { address, len, ids }).await?;
+        let mut data = Vec::with_capacity(ids.len());
+        for &id in ids {
+            data.push(self.expect(id, len as usize).await?);
+        }
+        Ok(data)
+    }
+
+    /// Data of the same length for several devices, in one instruction. Not answered.
+    pub async fn sync_write(&mut self, address: u16, writes: &[(u8, &[u8])]) -> Result<(), Error> {
+        self.send(&Request::SyncWrite { address, writes }).await
+    }
+
+    /// Different registers of several devices, in one instruction.
+    pub async fn bulk_read(&mut self, reads: &[BulkRead]) -> Result<Vec<Vec<u8>>, Error> {
+        self.send(&Request::BulkRead { reads }).await?;
+        let mut data = Vec::with_capacity(reads.len());
+        for read in reads {
+            data.push(self.expect(read.id, read.len as usize).await?);
+        }
+        Ok(data)
+    }
+
+    pub async fn read_register<T: Value>(
+        &mut self,
+        id: u8,
+        register: Register<T>,
+    ) -> Result<T, Error> {
+        let mut buf = [0; 4];
+        let buf = &mut buf[..T::SIZE];
+        self.read(id, register.address, buf).await?;
+        T::decode(buf).ok_or(Error::InvalidValue)
+    }
+
+    pub async fn write_register<T: Value>(
+        &mut self,
+        id: u8,
+        register: Register<T>,
+        value: T,
+    ) -> Result<(), Error> {
+        let mut buf = [0; 4];
+        let buf = &mut buf[..T::SIZE];
+        value.encode(buf);
+        self.write(id, register.address, buf).await
+    }
+
+    /// One register of several devices, e.g. the goal positions of a limb.
+    pub async fn sync_write_register<T: Value>(
+        &mut self,
+        register: Register<T>,
+        values: &[(u8, T)],
+    ) -> Result<(), Error> {
+        let data: Vec<[u8; 4]> = values
+            .iter()
+            .map(|(_, value)| {
+                let mut buf = [0; 4];
+                value.encode(&mut buf[..T::SIZE]);
+                buf
+            })
+            .collect();
+        let writes: Vec<(u8, &[u8])> = values
+            .iter()
+            .zip(&data)
+            .map(|((id, _), buf)| (*id, &buf[..T::SIZE]))
+            .collect();
+        self.sync_write(register.address, &writes).await
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamixel::{
+        control::{self, OperatingMode},
+        instruction::{BULK_READ, PING, READ, STATUS, SYNC_READ, SYNC_WRITE, WRITE},
+        packet::{encode, Packet},
+    };
+    use alloc::{collections::VecDeque, rc::Rc};
+    use core::{cell::RefCell, convert::Infallible, future::pending};
+    use embassy_futures::block_on;
+    use embedded_io_async::ErrorType;
+
+    struct Servo {
+        id: u8,
+        table: [u8; 256],
+    }
+
+    impl Servo {
+        fn new(id: u8) -> Self {
+            let mut table = [0; 256];
+            // An XL430-W250, firmware 46.
+            table[..2].copy_from_slice(&1060u16.to_le_bytes());
+            table[6] = 46;
+            table[7] = id;
+            table[11] = OperatingMode::Position as u8;
+            table[132..136].copy_from_slice(&2048i32.to_le_bytes());
+            Self { id, table }
+        }
+    }
+
+    /// A single-wire half-duplex line: everything sent comes back, followed by the answers of
+    /// the servos on it.
+    struct Bus {
+        servos: Vec<Servo>,
+        decoder: Decoder,
+        output: VecDeque<u8>,
+    }
+
+    impl Bus {
+        fn put(&mut self, byte: u8) {
+            self.output.push_back(byte);
+            if let Some(Ok(packet)) = self.decoder.push(byte) {
+                if packet.instruction != STATUS {
+                    self.answer(&packet);
+                }
+            }
+        }
+
+        fn status(&mut self, id: u8, error: u8, data: &[u8]) {
+            let mut params = alloc::vec![error];
+            params.extend_from_slice(data);
+            let mut out = Vec::new();
+            encode(id, STATUS, &params, &mut out);
+            self.output.extend(out);
+        }
+
+        fn read(&mut self, id: u8, address: usize, len: usize) {
+            let Some(servo) = self.servos.iter().find(|s| s.id == id) else {
+                return;
+            };
+            match servo.table.get(address..address + len) {
+                Some(data) => {
+                    let data = data.to_vec();
+                    self.status(id, 0, &data);
+                }
+                // Data range error.
+                None => self.status(id, 0x04, &[]),
+            }
+        }
+
+        fn write(&mut self, id: u8, address: usize, data: &[u8]) {
+            for servo in &mut self.servos {
+                if servo.id == id || id == BROADCAST_ID {
+                    servo.table[address..address + data.len()].copy_from_slice(data);
+                }
+            }
+        }
+
+        fn answer(&mut self, packet: &Packet) {
+            let p = &packet.params;
+            let word = |i: usize| u16::from_le_bytes([p[i], p[i + 1]]) as usize;
+            match packet.instruction {
+                PING => {
+                    let answers: Vec<(u8, [u8; 3])> = self
+                        .servos
+                        .iter()
+                        .filter(|s| s.id == packet.id || packet.id == BROADCAST_ID)
+                        .map(|s| (s.id, [s.table[0], s.table[1], s.table[6]]))
+                        .collect();
+                    for (id, data) in answers {
+                        self.status(id, 0, &data);
+                    }
+                }
+                READ => self.read(packet.id, word(0), word(2)),
+                WRITE => {
+                    self.write(packet.id, word(0), &p[2..]);
+                    if self.servos.iter().any(|s| s.id == packet.id) {
+                        self.status(packet.id, 0, &[]);
+                    }
+                }
+                SYNC_READ => {
+                    for &id in &p[4..] {
+                        self.read(id, word(0), word(2));
+                    }
+                }
+                SYNC_WRITE => {
+                    let (address, len) = (word(0), word(2));
+                    for chunk in p[4..].chunks(1 + len) {
+                        self.write(chunk[0], address, &chunk[1..]);
+                    }
+                }
+                BULK_READ => {
+                    for read in p.chunks(5) {
+                        self.read(
+                            read[0],
+                            u16::from_le_bytes([read[1], read[2]]) as usize,
+                            u16::from_le_bytes([read[3], read[4]]) as usize,
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    struct Port(Rc<RefCell<Bus>>);
+
+    impl ErrorType for Port {
+        type Error = Infallible;
+    }
+
+    impl Read for Port {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            let next = self.0.borrow_mut().output.pop_front();
+            match next {
+                Some(b) => {
+                    buf[0] = b;
+                    Ok(1)
+                }
+                None => pending().await,
+            }
+        }
+    }
+
+    impl Write for Port {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            for &b in buf {
+                self.0.borrow_mut().put(b);
+            }
+            Ok(buf.len())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    fn setup(ids: &[u8]) -> (Rc<RefCell<Bus>>, Controller<Port, Port, NoDelay>) {
+        let bus = Rc::new(RefCell::new(Bus {
+            servos: ids.iter().map(|&id| Servo::new(id)).collect(),
+            decoder: Decoder::new(),
+            output: VecDeque::new(),
+        }));
+        let controller = Controller::new(Port(bus.clone()), Port(bus.clone()), NoDelay);
+        (bus, controller)
+    }
+
+    #[test]
+    fn ping_and_scan() {
+        let (_bus, mut controller) = setup(&[1, 5]);
+        let info = PingInfo {
+            id: 5,
+            model: 1060,
+            firmware: 46,
+        };
+        assert_eq!(block_on(controller.ping(5)), Ok(info));
+        assert_eq!(block_on(controller.ping(2)), Err(Error::Timeout));
+        let found = block_on(controller.scan()).unwrap();
+        assert_eq!(found.iter().map(|i| i.id).collect::<Vec<_>>(), [1, 5]);
+    }
+
+    #[test]
+    fn registers() {
+        let (bus, mut controller) = setup(&[1, 2]);
+
+        assert_eq!(
+            block_on(controller.read_register(1, control::PRESENT_POSITION)),
+            Ok(2048)
+        );
+        assert_eq!(
+            block_on(controller.read_register(1, control::OPERATING_MODE)),
+            Ok(OperatingMode::Position)
+        );
+        block_on(controller.write_register(2, control::GOAL_POSITION, -1000)).unwrap();
+        block_on(controller.write_register(2, control::TORQUE_ENABLE, true)).unwrap();
+        assert_eq!(
+            block_on(controller.read_register(2, control::GOAL_POSITION)),
+            Ok(-1000)
+        );
+        assert_eq!(bus.borrow().servos[1].table[64], 1);
+        // Broadcast writes aren't answered.
+        block_on(controller.write_register(BROADCAST_ID, control::LED, true)).unwrap();
+        assert!(bus.borrow().servos.iter().all(|s| s.table[65] == 1));
+
+        bus.borrow_mut().servos[0].table[64] = 7;
+        assert_eq!(
+            block_on(controller.read_register(1, control::TORQUE_ENABLE)),
+            Err(Error::InvalidValue)
+        );
+        let mut buf = [0; 8];
+        assert_eq!(
+            block_on(controller.read(1, 250, &mut buf)),
+            Err(Error::Status(StatusError::DataRange))
+        );
+    }
+
+    #[test]
+    fn sync_and_bulk() {
+        let (bus, mut controller) = setup(&[1, 2, 3]);
+
+        block_on(
+            controller.sync_write_register(control::GOAL_POSITION, &[(1, 100), (3, 300), (2, 200)]),
+        )
+        .unwrap();
+        let positions = block_on(controller.sync_read(116, 4, &[3, 1])).unwrap();
+        assert_eq!(positions, [300i32.to_le_bytes(), 100i32.to_le_bytes()]);
+
+        bus.borrow_mut().servos[1].table[146] = 41;
+        let reads = [
+            BulkRead {
+                id: 1,
+                address: 132,
+                len: 4,
+            },
+            BulkRead {
+                id: 2,
+                address: 146,
+                len: 1,
+            },
+        ];
+        let data = block_on(controller.bulk_read(&reads)).unwrap();
+        assert_eq!(data, [alloc::vec![0x00, 0x08, 0x00, 0x00], alloc::vec![41]]);
+
+        // A device missing from a sync read.
+        assert_eq!(
+            block_on(controller.sync_read(116, 4, &[1, 9])),
+            Err(Error::Timeout)
+        );
+    }
+}
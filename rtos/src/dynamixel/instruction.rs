@@ -0,0 +1,202 @@
+use super::packet::{encode, BROADCAST_ID};
+use alloc::vec::Vec;
+
+pub const PING: u8 = 0x01;
+pub const READ: u8 = 0x02;
+pub const WRITE: u8 = 0x03;
+pub const REBOOT: u8 = 0x08;
+/// Instruction field of every answer.
+pub const STATUS: u8 = 0x55;
+pub const SYNC_READ: u8 = 0x82;
+pub const SYNC_WRITE: u8 = 0x83;
+pub const BULK_READ: u8 = 0x92;
+
+/// One device's part of a bulk read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BulkRead {
+    pub id: u8,
+    pub address: u16,
+    pub len: u16,
+}
+
+/// An instruction packet. Sync and bulk instructions go to [`BROADCAST_ID`]; the devices named
+/// answer a read one after another, in the order given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request<'a> {
+    Ping {
+        id: u8,
+    },
+    Read {
+        id: u8,
+        address: u16,
+        len: u16,
+    },
+    Write {
+        id: u8,
+        address: u16,
+        data: &'a [u8],
+    },
+    Reboot {
+        id: u8,
+    },
+    /// The same `len` bytes from each of `ids`.
+    SyncRead {
+        address: u16,
+        len: u16,
+        ids: &'a [u8],
+    },
+    /// Data of the same length for each device.
+    SyncWrite {
+        address: u16,
+        writes: &'a [(u8, &'a [u8])],
+    },
+    BulkRead {
+        reads: &'a [BulkRead],
+    },
+}
+
+impl Request<'_> {
+    pub fn id(&self) -> u8 {
+        match *self {
+            Request::Ping { id }
+            | Request::Read { id, .. }
+            | Request::Write { id, .. }
+            | Request::Reboot { id } => id,
+            Request::SyncRead { .. } | Request::SyncWrite { .. } | Request::BulkRead { .. } => {
+                BROADCAST_ID
+            }
+        }
+    }
+
+    pub fn instruction(&self) -> u8 {
+        match self {
+            Request::Ping { .. } => PING,
+            Request::Read { .. } => READ,
+            Request::Write { .. } => WRITE,
+            Request::Reboot { .. } => REBOOT,
+            Request::SyncRead { .. } => SYNC_READ,
+            Request::SyncWrite { .. } => SYNC_WRITE,
+            Request::BulkRead { .. } => BULK_READ,
+        }
+    }
+
+    /// Append the packet.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let mut params = Vec::new();
+        match *self {
+            Request::Ping { .. } | Request::Reboot { .. } => {}
+            Request::Read { address, len, .. } => {
+                params.extend_from_slice(&address.to_le_bytes());
+                params.extend_from_slice(&len.to_le_bytes());
+            }
+            Request::Write { address, data, .. } => {
+                params.extend_from_slice(&address.to_le_bytes());
+                params.extend_from_slice(data);
+            }
+            Request::SyncRead { address, len, ids } => {
+                params.extend_from_slice(&address.to_le_bytes());
+                params.extend_from_slice(&len.to_le_bytes());
+                params.extend_from_slice(ids);
+            }
+            Request::SyncWrite { address, writes } => {
+                let len = writes.first().map_or(0, |(_, data)| data.len()) as u16;
+                params.extend_from_slice(&address.to_le_bytes());
+                params.extend_from_slice(&len.to_le_bytes());
+                for (id, data) in writes {
+                    params.push(*id);
+                    params.extend_from_slice(data);
+                }
+            }
+            Request::BulkRead { reads } => {
+                for read in reads {
+                    params.push(read.id);
+                    params.extend_from_slice(&read.address.to_le_bytes());
+                    params.extend_from_slice(&read.len.to_le_bytes());
+                }
+            }
+        }
+        encode(self.id(), self.instruction(), &params, out);
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(request: Request) -> Vec<u8> {
+        let mut out = Vec::new();
+        request.encode(&mut out);
+        out
+    }
+
+    #[test]
+    fn manual_examples() {
+        // The instruction packet examples of the protocol 2.0 manual.
+        assert_eq!(
+            encoded(Request::Ping { id: 1 }),
+            [0xff, 0xff, 0xfd, 0x00, 0x01, 0x03, 0x00, 0x01, 0x19, 0x4e]
+        );
+        assert_eq!(
+            encoded(Request::Read {
+                id: 1,
+                address: 132,
+                len: 4
+            }),
+            [0xff, 0xff, 0xfd, 0x00, 0x01, 0x07, 0x00, 0x02, 0x84, 0x00, 0x04, 0x00, 0x1d, 0x15]
+        );
+        assert_eq!(
+            encoded(Request::Write {
+                id: 1,
+                address: 116,
+                data: &512u32.to_le_bytes()
+            }),
+            [
+                0xff, 0xff, 0xfd, 0x00, 0x01, 0x09, 0x00, 0x03, 0x74, 0x00, 0x00, 0x02, 0x00, 0x00,
+                0xca, 0x89
+            ]
+        );
+        assert_eq!(
+            encoded(Request::SyncRead {
+                address: 132,
+                len: 4,
+                ids: &[1, 2]
+            }),
+            [
+                0xff, 0xff, 0xfd, 0x00, 0xfe, 0x09, 0x00, 0x82, 0x84, 0x00, 0x04, 0x00, 0x01, 0x02,
+                0xce, 0xfa
+            ]
+        );
+        assert_eq!(
+            encoded(Request::SyncWrite {
+                address: 116,
+                writes: &[(1, &150u32.to_le_bytes()), (2, &170u32.to_le_bytes())]
+            }),
+            [
+                0xff, 0xff, 0xfd, 0x00, 0xfe, 0x11, 0x00, 0x83, 0x74, 0x00, 0x04, 0x00, 0x01, 0x96,
+                0x00, 0x00, 0x00, 0x02, 0xaa, 0x00, 0x00, 0x00, 0x82, 0x87
+            ]
+        );
+        assert_eq!(
+            encoded(Request::BulkRead {
+                reads: &[
+                    BulkRead {
+                        id: 1,
+                        address: 132,
+                        len: 4
+                    },
+                    BulkRead {
+                        id: 2,
+                        address: 146,
+                        len: 1
+                    },
+                ]
+            }),
+            [
+                0xff, 0xff, 0xfd, 0x00, 0xfe, 0x0d, 0x00, 0x92, 0x01, 0x84, 0x00, 0x04, 0x00, 0x02,
+                0x92, 0x00, 0x01, 0x00, 0xfa, 0x7c
+            ]
+        );
+    }
+}
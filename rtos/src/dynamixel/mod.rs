@@ -0,0 +1,122 @@
+use crate::{
+    capture::Tap,
+    console::command::DynamixelCommand,
+    uart::{self, SerialSettings},
+};
+use alloc::{format, string::String, vec};
+use core::fmt::Write as _;
+use defmt::{info, warn};
+use embassy_embedded_hal::SetConfig;
+use embassy_stm32::{
+    pac,
+    peripherals::USART1,
+    usart::{BufferedUartRx, BufferedUartTx},
+};
+use embassy_time::Delay;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+pub mod control;
+pub mod controller;
+pub mod instruction;
+pub mod packet;
+
+pub use control::{OperatingMode, Register};
+pub use controller::{Controller, Error, PingInfo};
+pub use instruction::{BulkRead, Request};
+pub use packet::BROADCAST_ID;
+
+/// The factory setting of the X series.
+pub const DYNAMIXEL_SETTINGS: SerialSettings = SerialSettings::new(57_600);
+/// USART1's TX pin, `D1`, which carries the data line.
+const TX_PIN: usize = 9;
+
+/// Run a console `dxl` command on USART1 in single-wire half-duplex mode at
+/// [`DYNAMIXEL_SETTINGS`]. USART1 is put back to `restore` afterwards.
+pub async fn run_command<HW: Write>(
+    rx: &mut Tap<BufferedUartRx<'static, USART1>>,
+    tx: &mut Tap<BufferedUartTx<'static, USART1>>,
+    host_tx: &mut HW,
+    command: DynamixelCommand,
+    restore: &SerialSettings,
+) -> Result<(), HW::Error> {
+    info!("dynamixel: {}", defmt::Debug2Format(&command));
+    if let Err(e) = rx.set_config(&(&DYNAMIXEL_SETTINGS).into()) {
+        warn!("dynamixel: UART config failed: {}", defmt::Debug2Format(&e));
+    }
+    uart::set_half_duplex(pac::USART1, pac::GPIOA, TX_PIN, true);
+
+    let mut controller = Controller::new(&mut *rx, &mut *tx, Delay);
+    let result = execute(&mut controller, &command).await;
+
+    uart::set_half_duplex(pac::USART1, pac::GPIOA, TX_PIN, false);
+    if let Err(e) = rx.set_config(&restore.into()) {
+        warn!(
+            "dynamixel: failed to restore UART config: {}",
+            defmt::Debug2Format(&e)
+        );
+    }
+
+    let text = match result {
+        Ok(mut text) => {
+            text.push_str("OK\r\n");
+            text
+        }
+        Err(e) => format!("ERROR: {}\r\n", e.as_str()),
+    };
+    host_tx.write_all(text.as_bytes()).await?;
+    host_tx.flush().await
+}
+
+async fn execute<R: Read, W: Write, D: DelayNs>(
+    controller: &mut Controller<R, W, D>,
+    command: &DynamixelCommand,
+) -> Result<String, Error> {
+    let mut text = String::new();
+    match *command {
+        DynamixelCommand::Ping { id: Some(id) } => {
+            let info = controller.ping(id).await?;
+            print_ping(&mut text, &info);
+        }
+        DynamixelCommand::Ping { id: None } => {
+            for info in controller.scan().await? {
+                print_ping(&mut text, &info);
+            }
+        }
+        DynamixelCommand::Read { id, address, len } => {
+            let mut data = vec![0; len as usize];
+            controller.read(id, address, &mut data).await?;
+            let _ = write!(text, "{address}:");
+            for b in data {
+                let _ = write!(text, " {b:02x}");
+            }
+            text.push_str("\r\n");
+        }
+        DynamixelCommand::Write {
+            id,
+            address,
+            ref data,
+        } => controller.write(id, address, data).await?,
+        DynamixelCommand::Goto { id, position } => {
+            controller
+                .write_register(id, control::TORQUE_ENABLE, true)
+                .await?;
+            controller
+                .write_register(id, control::GOAL_POSITION, position)
+                .await?;
+            let present = controller
+                .read_register(id, control::PRESENT_POSITION)
+                .await?;
+            let _ = write!(text, "present position {present}\r\n");
+        }
+    }
+    Ok(text)
+}
+
+fn print_ping(text: &mut String, info: &PingInfo) {
+    let _ = write!(
+        text,
+        "ID {}: model {}, firmware {}\r\n",
+        info.id, info.model, info.firmware
+    );
+}
@@ -0,0 +1,293 @@
+//! Dynamixel protocol 2.0 packets: header, ID, length, instruction, parameters and CRC-16, with
+//! byte stuffing keeping the header out of the payload.
+
+use super::instruction::STATUS;
+use alloc::vec::Vec;
+
+pub const HEADER: [u8; 4] = [0xff, 0xff, 0xfd, 0x00];
+/// Every device acts on it; only ping and the sync and bulk reads are answered.
+pub const BROADCAST_ID: u8 = 0xfe;
+/// Longest length field accepted: instruction, parameters and CRC, stuffed.
+pub const MAX_LENGTH: usize = 1024;
+
+/// Header, ID and length field.
+const PREFIX_LEN: usize = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketError {
+    Crc,
+    /// A length field too short for instruction and CRC, or longer than [`MAX_LENGTH`].
+    Length,
+}
+
+impl PacketError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PacketError::Crc => "CRC mismatch",
+            PacketError::Length => "invalid length",
+        }
+    }
+}
+
+/// CRC-16 with polynomial 0x8005, not reflected, initial value 0, over everything from the
+/// header on. Sent low byte first.
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &b| {
+        (0..8).fold(crc ^ (b as u16) << 8, |crc, _| match crc & 0x8000 {
+            0 => crc << 1,
+            _ => (crc << 1) ^ 0x8005,
+        })
+    })
+}
+
+/// Append `payload`, with an 0xfd after each 0xff 0xff 0xfd so it can't be taken for a header.
+fn stuff(payload: impl IntoIterator<Item = u8>, out: &mut Vec<u8>) {
+    let start = out.len();
+    for b in payload {
+        out.push(b);
+        if out[start..].ends_with(&HEADER[..3]) {
+            out.push(0xfd);
+        }
+    }
+}
+
+/// Undo [`stuff`].
+fn unstuff(payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len());
+    for (n, &b) in payload.iter().enumerate() {
+        if b == 0xfd && payload[..n].ends_with(&HEADER[..3]) {
+            continue;
+        }
+        out.push(b);
+    }
+    out
+}
+
+/// Append the packet with `instruction` and `params` for device `id`.
+pub fn encode(id: u8, instruction: u8, params: &[u8], out: &mut Vec<u8>) {
+    let start = out.len();
+    out.extend_from_slice(&HEADER);
+    out.push(id);
+    out.extend_from_slice(&[0, 0]);
+    let body = out.len();
+    stuff(
+        core::iter::once(instruction).chain(params.iter().copied()),
+        out,
+    );
+    let length = (out.len() - body + 2) as u16;
+    out[body - 2..body].copy_from_slice(&length.to_le_bytes());
+    let crc = crc16(&out[start..]);
+    out.extend_from_slice(&crc.to_le_bytes());
+}
+
+/// A packet with the stuffing removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub id: u8,
+    pub instruction: u8,
+    pub params: Vec<u8>,
+}
+
+/// Error field of a status packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusError {
+    ResultFail,
+    Instruction,
+    Crc,
+    DataRange,
+    DataLength,
+    DataLimit,
+    Access,
+    Other(u8),
+}
+
+impl StatusError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatusError::ResultFail => "result fail",
+            StatusError::Instruction => "instruction error",
+            StatusError::Crc => "CRC error",
+            StatusError::DataRange => "data range error",
+            StatusError::DataLength => "data length error",
+            StatusError::DataLimit => "data limit error",
+            StatusError::Access => "access error",
+            StatusError::Other(_) => "unknown error",
+        }
+    }
+}
+
+/// A device's answer to an instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub id: u8,
+    /// Error number, and the alert bit.
+    pub error: u8,
+    pub params: Vec<u8>,
+}
+
+impl Status {
+    /// `None` for an instruction packet, e.g. the echo of our own on a half-duplex line.
+    pub fn from_packet(packet: Packet) -> Option<Self> {
+        if packet.instruction != STATUS || packet.params.is_empty() {
+            return None;
+        }
+        let mut params = packet.params;
+        let error = params.remove(0);
+        Some(Self {
+            id: packet.id,
+            error,
+            params,
+        })
+    }
+
+    /// What went wrong with the instruction.
+    pub fn error(&self) -> Option<StatusError> {
+        match self.error & 0x7f {
+            0 => None,
+            1 => Some(StatusError::ResultFail),
+            2 => Some(StatusError::Instruction),
+            3 => Some(StatusError::Crc),
+            4 => Some(StatusError::DataRange),
+            5 => Some(StatusError::DataLength),
+            6 => Some(StatusError::DataLimit),
+            7 => Some(StatusError::Access),
+            e => Some(StatusError::Other(e)),
+        }
+    }
+
+    /// The device has a hardware error; see its Hardware Error Status register.
+    pub fn alert(&self) -> bool {
+        self.error & 0x80 != 0
+    }
+}
+
+/// Finds packets in a stream of bytes, skipping whatever comes before a header.
+#[derive(Debug, Clone, Default)]
+pub struct Decoder {
+    buf: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Returns the packet `byte` completes.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Packet, PacketError>> {
+        self.buf.push(byte);
+        while !HEADER.starts_with(&self.buf[..self.buf.len().min(HEADER.len())]) {
+            self.buf.remove(0);
+        }
+        if self.buf.len() < PREFIX_LEN {
+            return None;
+        }
+
+        let length = u16::from_le_bytes([self.buf[5], self.buf[6]]) as usize;
+        if !(3..=MAX_LENGTH).contains(&length) {
+            self.buf.clear();
+            return Some(Err(PacketError::Length));
+        }
+        if self.buf.len() < PREFIX_LEN + length {
+            return None;
+        }
+
+        let (body, crc) = self.buf.split_at(self.buf.len() - 2);
+        let result = if crc16(body).to_le_bytes() == crc {
+            let payload = unstuff(&body[PREFIX_LEN..]);
+            Ok(Packet {
+                id: body[4],
+                instruction: payload[0],
+                params: payload[1..].to_vec(),
+            })
+        } else {
+            Err(PacketError::Crc)
+        };
+        self.buf.clear();
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(decoder: &mut Decoder, bytes: &[u8]) -> Vec<Result<Packet, PacketError>> {
+        bytes.iter().filter_map(|&b| decoder.push(b)).collect()
+    }
+
+    #[test]
+    fn stuffing() {
+        // A write of ff ff fd fd: the header in the data gets an extra fd.
+        let mut out = Vec::new();
+        encode(1, 0x03, &[0x10, 0x00, 0xff, 0xff, 0xfd, 0xfd], &mut out);
+        assert_eq!(
+            out[..15],
+            [
+                0xff, 0xff, 0xfd, 0x00, 0x01, 0x0a, 0x00, 0x03, 0x10, 0x00, 0xff, 0xff, 0xfd, 0xfd,
+                0xfd
+            ]
+        );
+
+        let packets = decode_all(&mut Decoder::new(), &out);
+        assert_eq!(
+            packets,
+            [Ok(Packet {
+                id: 1,
+                instruction: 0x03,
+                params: alloc::vec![0x10, 0x00, 0xff, 0xff, 0xfd, 0xfd],
+            })]
+        );
+    }
+
+    #[test]
+    fn decodes_status() {
+        // The ping answer of the manual, after line noise and a truncated header.
+        let status = [
+            0xff, 0xff, 0xfd, 0x00, 0x01, 0x07, 0x00, 0x55, 0x00, 0x06, 0x04, 0x26, 0x65, 0x5d,
+        ];
+        let mut bytes = alloc::vec![0x00, 0xff, 0xff, 0xfd];
+        bytes.extend_from_slice(&status);
+        let mut decoder = Decoder::new();
+        let packets = decode_all(&mut decoder, &bytes);
+        assert_eq!(packets.len(), 1);
+        let status = Status::from_packet(packets[0].clone().unwrap()).unwrap();
+        assert_eq!(status.id, 1);
+        assert_eq!(status.error(), None);
+        assert_eq!(status.params, [0x06, 0x04, 0x26]);
+
+        // Our own instruction echoed back isn't a status.
+        let mut ping = Vec::new();
+        encode(1, 0x01, &[], &mut ping);
+        let echo = decode_all(&mut decoder, &ping).remove(0).unwrap();
+        assert_eq!(Status::from_packet(echo), None);
+
+        let mut out = Vec::new();
+        encode(3, STATUS, &[0x84], &mut out);
+        let status = Status::from_packet(decode_all(&mut decoder, &out).remove(0).unwrap());
+        let status = status.unwrap();
+        assert_eq!(status.error(), Some(StatusError::DataRange));
+        assert!(status.alert());
+    }
+
+    #[test]
+    fn rejects_damaged_packets() {
+        let mut out = Vec::new();
+        encode(1, 0x01, &[], &mut out);
+        out[7] ^= 0x02;
+        let mut decoder = Decoder::new();
+        assert_eq!(decode_all(&mut decoder, &out), [Err(PacketError::Crc)]);
+        assert_eq!(
+            decode_all(&mut decoder, &[0xff, 0xff, 0xfd, 0x00, 0x01, 0x02, 0x00]),
+            [Err(PacketError::Length)]
+        );
+        // Back in sync for the next one.
+        out[7] ^= 0x02;
+        assert!(decode_all(&mut decoder, &out)[0].is_ok());
+    }
+}
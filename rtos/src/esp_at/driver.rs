@@ -0,0 +1,908 @@
+use super::{
+    info::{self, AccessPoint, DhcpStatus, IpConfig, JoinError, Joined},
+    parse::{Event, Parser},
+};
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{cell::Cell, fmt::Write as _};
+use embassy_futures::select::{select, Either};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+/// Connections the module supports at once (`AT+CIPMUX=1`).
+pub const MAX_LINKS: usize = 5;
+/// Largest payload of one `AT+CIPSEND`. Longer writes are split.
+pub const MAX_SEND: usize = 2048;
+
+type CommandLine = heapless::String<192>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No final response in time.
+    Timeout,
+    /// `ERROR`: unsupported command, bad arguments, or the operation was refused.
+    Rejected,
+    /// `FAIL`
+    Failed,
+    Join(JoinError),
+    /// The module is still working on an earlier command.
+    Busy,
+    SendFailed,
+    /// The link was closed by the peer or the module.
+    Closed,
+    /// All of the module's links are in use.
+    NoFreeLink,
+    /// Received data was dropped because the socket's buffer was full.
+    Overflow,
+    /// The command doesn't fit the command buffer.
+    TooLong,
+    /// Read or write error on the UART.
+    Serial,
+}
+
+impl Error {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::Timeout => "timeout",
+            Error::Rejected => "rejected",
+            Error::Failed => "failed",
+            Error::Join(e) => e.as_str(),
+            Error::Busy => "module busy",
+            Error::SendFailed => "send failed",
+            Error::Closed => "connection closed",
+            Error::NoFreeLink => "no free link",
+            Error::Overflow => "receive buffer overflow",
+            Error::TooLong => "command too long",
+            Error::Serial => "serial error",
+        }
+    }
+}
+
+impl embedded_io_async::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Timeout => ErrorKind::TimedOut,
+            Error::Closed => ErrorKind::NotConnected,
+            Error::NoFreeLink => ErrorKind::OutOfMemory,
+            Error::TooLong => ErrorKind::InvalidInput,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// Only ever raised by formatting into a full [`CommandLine`].
+impl From<core::fmt::Error> for Error {
+    fn from(_: core::fmt::Error) -> Self {
+        Error::TooLong
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    pub command_timeout_ms: u32,
+    pub join_timeout_ms: u32,
+    pub scan_timeout_ms: u32,
+    pub connect_timeout_ms: u32,
+    /// How long a socket read listens for data before giving other callers a turn.
+    pub poll_ms: u32,
+    /// Received bytes buffered per socket before data is dropped.
+    pub socket_buffer: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            command_timeout_ms: 2_000,
+            join_timeout_ms: 20_000,
+            scan_timeout_ms: 10_000,
+            connect_timeout_ms: 10_000,
+            poll_ms: 20,
+            socket_buffer: 2048,
+        }
+    }
+}
+
+/// Station state reported by the module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WifiStatus {
+    /// `None` when not joined to an access point.
+    pub joined: Option<Joined>,
+    pub dhcp: DhcpStatus,
+}
+
+#[derive(Debug, Default)]
+struct Link {
+    open: bool,
+    rx: VecDeque<u8>,
+    overflowed: bool,
+}
+
+struct Inner<R, W, D> {
+    rx: R,
+    tx: W,
+    delay: D,
+    parser: Parser,
+    /// Bytes read from the UART but not yet parsed.
+    input: [u8; 64],
+    start: usize,
+    end: usize,
+    links: [Link; MAX_LINKS],
+    buffer_limit: usize,
+}
+
+impl<R: Read, W: Write, D: DelayNs> Inner<R, W, D> {
+    /// Feed events to `on_event` until it returns a value. Events are also applied to the link
+    /// state, so notifications for other sockets aren't lost.
+    async fn wait<T>(
+        &mut self,
+        timeout_ms: u32,
+        mut on_event: impl FnMut(&Event<'_>) -> Option<T>,
+    ) -> Result<T, Error> {
+        let Inner {
+            rx,
+            delay,
+            parser,
+            input,
+            start,
+            end,
+            links,
+            buffer_limit,
+            ..
+        } = self;
+
+        let pump = async {
+            loop {
+                if start == end {
+                    *start = 0;
+                    *end = match rx.read(input).await {
+                        Ok(0) | Err(_) => return Err(Error::Serial),
+                        Ok(n) => n,
+                    };
+                }
+                let (used, event) = parser.feed(&input[*start..*end]);
+                *start += used;
+                if let Some(event) = event {
+                    apply(&event, links, *buffer_limit);
+                    if let Some(value) = on_event(&event) {
+                        return Ok(value);
+                    }
+                }
+            }
+        };
+
+        match select(pump, delay.delay_ms(timeout_ms)).await {
+            Either::First(result) => result,
+            Either::Second(()) => Err(Error::Timeout),
+        }
+    }
+
+    async fn write_line(&mut self, line: &str) -> Result<(), Error> {
+        self.tx
+            .write_all(line.as_bytes())
+            .await
+            .map_err(|_| Error::Serial)?;
+        self.tx
+            .write_all(b"\r\n")
+            .await
+            .map_err(|_| Error::Serial)?;
+        self.tx.flush().await.map_err(|_| Error::Serial)
+    }
+
+    /// Send `line` and wait for its final response. `+...` lines in the reply go to `on_info`.
+    async fn command(
+        &mut self,
+        line: &str,
+        timeout_ms: u32,
+        mut on_info: impl FnMut(&str),
+    ) -> Result<(), Error> {
+        self.write_line(line).await?;
+
+        let mut join_error = None;
+        self.wait(timeout_ms, |event| match *event {
+            Event::Ok => Some(Ok(())),
+            Event::Error | Event::Fail => Some(Err(match join_error {
+                Some(e) => Error::Join(e),
+                None if *event == Event::Fail => Error::Failed,
+                None => Error::Rejected,
+            })),
+            Event::Busy => Some(Err(Error::Busy)),
+            Event::Info(text) => {
+                if let Some(e) = info::parse_join_error(text) {
+                    join_error = Some(e);
+                }
+                on_info(text);
+                None
+            }
+            _ => None,
+        })
+        .await?
+    }
+
+    async fn send(&mut self, link: u8, data: &[u8], timeout_ms: u32) -> Result<(), Error> {
+        let mut line = CommandLine::new();
+        write!(line, "AT+CIPSEND={},{}", link, data.len())?;
+        self.write_line(&line).await?;
+
+        // `OK` comes first, then the prompt.
+        self.wait(timeout_ms, |event| match event {
+            Event::Prompt => Some(Ok(())),
+            Event::Error => Some(Err(Error::Rejected)),
+            Event::Busy => Some(Err(Error::Busy)),
+            _ => None,
+        })
+        .await??;
+
+        self.tx.write_all(data).await.map_err(|_| Error::Serial)?;
+        self.tx.flush().await.map_err(|_| Error::Serial)?;
+
+        self.wait(timeout_ms, |event| match event {
+            Event::SendOk => Some(Ok(())),
+            Event::SendFail | Event::Error => Some(Err(Error::SendFailed)),
+            _ => None,
+        })
+        .await?
+    }
+
+    async fn close(&mut self, link: u8, timeout_ms: u32) -> Result<(), Error> {
+        let result = match self.links[link as usize].open {
+            true => {
+                let mut line = CommandLine::new();
+                write!(line, "AT+CIPCLOSE={}", link)?;
+                match self.command(&line, timeout_ms, |_| {}).await {
+                    // Closed by the peer in the meantime.
+                    Err(Error::Rejected) => Ok(()),
+                    result => result,
+                }
+            }
+            false => Ok(()),
+        };
+        self.links[link as usize] = Link::default();
+
+        result
+    }
+}
+
+/// Keep link state up to date with what the module reports.
+fn apply(event: &Event<'_>, links: &mut [Link], buffer_limit: usize) {
+    match *event {
+        Event::Data { link, data } => {
+            if let Some(link) = links.get_mut(link as usize) {
+                let room = buffer_limit.saturating_sub(link.rx.len());
+                link.rx.extend(&data[..data.len().min(room)]);
+                link.overflowed |= data.len() > room;
+            }
+        }
+        Event::Connected(link) => {
+            if let Some(link) = links.get_mut(link as usize) {
+                link.open = true;
+            }
+        }
+        Event::Closed(link) => {
+            if let Some(link) = links.get_mut(link as usize) {
+                link.open = false;
+            }
+        }
+        // The module restarted and forgot its connections.
+        Event::Ready => links.iter_mut().for_each(|link| link.open = false),
+        _ => {}
+    }
+}
+
+/// Driver for a module running Espressif's ESP-AT firmware, or a compatible AT dialect, as a
+/// Wi-Fi station with TCP and UDP client sockets.
+///
+/// Operations take turns on the UART. A socket read that is waiting for data gives way every
+/// [`Config::poll_ms`], so other sockets can be used from concurrent tasks.
+pub struct EspAt<R, W, D> {
+    inner: Mutex<NoopRawMutex, Inner<R, W, D>>,
+    config: Config,
+    /// Links handed out as [`Socket`]s, one bit each.
+    claimed: Cell<u8>,
+}
+
+impl<R: Read, W: Write, D: DelayNs> EspAt<R, W, D> {
+    pub fn new(rx: R, tx: W, delay: D, config: Config) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                rx,
+                tx,
+                delay,
+                parser: Parser::new(),
+                input: [0; 64],
+                start: 0,
+                end: 0,
+                links: Default::default(),
+                buffer_limit: config.socket_buffer,
+            }),
+            config,
+            claimed: Cell::new(0),
+        }
+    }
+
+    /// Check the module answers and set it up the way the driver expects: no echo, station
+    /// mode, multiple connections.
+    pub async fn init(&self) -> Result<(), Error> {
+        let t = self.config.command_timeout_ms;
+        let mut inner = self.inner.lock().await;
+
+        // The first attempt may be garbled by a half-sent line from before.
+        let mut alive = Err(Error::Timeout);
+        for _ in 0..3 {
+            alive = inner.command("AT", t, |_| {}).await;
+            if alive.is_ok() {
+                break;
+            }
+        }
+        alive?;
+
+        for line in ["ATE0", "AT+CWMODE=1", "AT+CIPMUX=1"] {
+            inner.command(line, t, |_| {}).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Access points in range, strongest first as the module lists them.
+    pub async fn scan(&self) -> Result<Vec<AccessPoint>, Error> {
+        let mut found = Vec::new();
+        let mut inner = self.inner.lock().await;
+        inner
+            .command("AT+CWLAP", self.config.scan_timeout_ms, |text| {
+                found.extend(info::parse_access_point(text));
+            })
+            .await?;
+
+        Ok(found)
+    }
+
+    /// Join an access point. An empty `password` joins an open network.
+    pub async fn join(&self, ssid: &str, password: &str) -> Result<(), Error> {
+        let mut line = CommandLine::new();
+        line.push_str("AT+CWJAP=").map_err(|_| Error::TooLong)?;
+        info::quote(&mut line, ssid)?;
+        line.push(',').map_err(|_| Error::TooLong)?;
+        info::quote(&mut line, password)?;
+
+        let mut inner = self.inner.lock().await;
+        inner
+            .command(&line, self.config.join_timeout_ms, |_| {})
+            .await
+    }
+
+    pub async fn leave(&self) -> Result<(), Error> {
+        let mut inner = self.inner.lock().await;
+        inner
+            .command("AT+CWQAP", self.config.command_timeout_ms, |_| {})
+            .await
+    }
+
+    pub async fn dhcp_status(&self) -> Result<DhcpStatus, Error> {
+        let t = self.config.command_timeout_ms;
+        let mut status = DhcpStatus::default();
+        let mut inner = self.inner.lock().await;
+
+        inner
+            .command("AT+CWDHCP?", t, |text| {
+                if let Some(enabled) = info::parse_dhcp(text) {
+                    status.enabled = enabled;
+                }
+            })
+            .await?;
+
+        let mut ip = IpConfig::default();
+        inner
+            .command("AT+CIPSTA?", t, |text| {
+                ip.update(text);
+            })
+            .await?;
+        status.ip = ip;
+
+        Ok(status)
+    }
+
+    pub async fn status(&self) -> Result<WifiStatus, Error> {
+        let mut joined = None;
+        {
+            let mut inner = self.inner.lock().await;
+            inner
+                .command("AT+CWJAP?", self.config.command_timeout_ms, |text| {
+                    if let Some(ap) = info::parse_joined(text) {
+                        joined = Some(ap);
+                    }
+                })
+                .await?;
+        }
+
+        Ok(WifiStatus {
+            joined,
+            dhcp: self.dhcp_status().await?,
+        })
+    }
+
+    /// Open a TCP connection to `host`, a name or dotted address.
+    pub async fn connect_tcp(&self, host: &str, port: u16) -> Result<Socket<'_, R, W, D>, Error> {
+        self.open("TCP", host, port, None).await
+    }
+
+    /// Open a UDP socket sending to `host:port` and receiving on `local_port`. Each write is
+    /// sent as one datagram; reads return the received data as a byte stream.
+    pub async fn connect_udp(
+        &self,
+        host: &str,
+        port: u16,
+        local_port: u16,
+    ) -> Result<Socket<'_, R, W, D>, Error> {
+        self.open("UDP", host, port, Some(local_port)).await
+    }
+
+    async fn open(
+        &self,
+        kind: &str,
+        host: &str,
+        port: u16,
+        local_port: Option<u16>,
+    ) -> Result<Socket<'_, R, W, D>, Error> {
+        // Released again by the socket's `Drop` if opening fails.
+        let socket = Socket {
+            driver: self,
+            link: self.claim()?,
+        };
+        let link = socket.link;
+
+        let mut line = CommandLine::new();
+        write!(line, "AT+CIPSTART={},\"{}\",", link, kind)?;
+        info::quote(&mut line, host)?;
+        write!(line, ",{}", port)?;
+        if let Some(local_port) = local_port {
+            // Mode 0: the peer address stays fixed.
+            write!(line, ",{},0", local_port)?;
+        }
+
+        let mut inner = self.inner.lock().await;
+        // Left open by a socket that was dropped without being closed.
+        inner.close(link, self.config.command_timeout_ms).await?;
+        inner
+            .command(&line, self.config.connect_timeout_ms, |_| {})
+            .await?;
+        inner.links[link as usize].open = true;
+        drop(inner);
+
+        Ok(socket)
+    }
+
+    fn claim(&self) -> Result<u8, Error> {
+        let claimed = self.claimed.get();
+        let link = (0..MAX_LINKS as u8)
+            .find(|link| claimed & (1 << link) == 0)
+            .ok_or(Error::NoFreeLink)?;
+        self.claimed.set(claimed | (1 << link));
+
+        Ok(link)
+    }
+}
+
+/// One of the module's links, readable and writable as a byte stream.
+///
+/// Close it with [`Socket::close`]. A socket that is just dropped stays open on the module until
+/// its link is reused.
+pub struct Socket<'a, R, W, D> {
+    driver: &'a EspAt<R, W, D>,
+    link: u8,
+}
+
+impl<R: Read, W: Write, D: DelayNs> Socket<'_, R, W, D> {
+    /// The module's link ID.
+    pub fn link(&self) -> u8 {
+        self.link
+    }
+
+    pub async fn close(self) -> Result<(), Error> {
+        let mut inner = self.driver.inner.lock().await;
+        inner
+            .close(self.link, self.driver.config.command_timeout_ms)
+            .await
+    }
+}
+
+impl<R, W, D> Drop for Socket<'_, R, W, D> {
+    fn drop(&mut self) {
+        self.driver
+            .claimed
+            .set(self.driver.claimed.get() & !(1 << self.link));
+    }
+}
+
+impl<R, W, D> ErrorType for Socket<'_, R, W, D> {
+    type Error = Error;
+}
+
+impl<R: Read, W: Write, D: DelayNs> Read for Socket<'_, R, W, D> {
+    /// Returns 0 once the connection is closed and all received data has been read.
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let id = self.link;
+        loop {
+            let mut inner = self.driver.inner.lock().await;
+            let link = &mut inner.links[id as usize];
+            if core::mem::take(&mut link.overflowed) {
+                return Err(Error::Overflow);
+            }
+            if !link.rx.is_empty() {
+                let n = buf.len().min(link.rx.len());
+                for (dst, src) in buf.iter_mut().zip(link.rx.drain(..n)) {
+                    *dst = src;
+                }
+                return Ok(n);
+            }
+            if !link.open {
+                return Ok(0);
+            }
+
+            let result = inner
+                .wait(self.driver.config.poll_ms, |event| match *event {
+                    Event::Data { link, .. } | Event::Closed(link) if link == id => Some(()),
+                    _ => None,
+                })
+                .await;
+            match result {
+                Ok(()) | Err(Error::Timeout) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<R: Read, W: Write, D: DelayNs> Write for Socket<'_, R, W, D> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let data = &buf[..buf.len().min(MAX_SEND)];
+        let mut inner = self.driver.inner.lock().await;
+        if !inner.links[self.link as usize].open {
+            return Err(Error::Closed);
+        }
+        inner
+            .send(self.link, data, self.driver.config.command_timeout_ms)
+            .await?;
+
+        Ok(data.len())
+    }
+
+    /// Writes are handed to the module before they return, so there is nothing to flush.
+    async fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{rc::Rc, string::String};
+    use core::{cell::RefCell, convert::Infallible, future::pending, net::Ipv4Addr};
+    use embassy_futures::block_on;
+
+    /// Simulated ESP-AT module. Answers commands the way the firmware does and echoes socket
+    /// data back to the sender.
+    struct Sim {
+        echo: bool,
+        /// Ignore everything, like a module that is off.
+        mute: bool,
+        joined: bool,
+        open: [bool; MAX_LINKS],
+        line: Vec<u8>,
+        /// Link, length and data so far of a payload after `AT+CIPSEND`.
+        payload: Option<(u8, usize, Vec<u8>)>,
+        output: VecDeque<u8>,
+        commands: Vec<String>,
+    }
+
+    impl Sim {
+        fn new() -> Rc<RefCell<Self>> {
+            Rc::new(RefCell::new(Self {
+                echo: true,
+                mute: false,
+                joined: false,
+                open: [false; MAX_LINKS],
+                line: Vec::new(),
+                payload: None,
+                output: VecDeque::new(),
+                commands: Vec::new(),
+            }))
+        }
+
+        fn say(&mut self, text: &str) {
+            self.output.extend(text.as_bytes());
+        }
+
+        fn receive(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                if let Some((link, len, data)) = &mut self.payload {
+                    data.push(b);
+                    if data.len() == *len {
+                        let (link, data) = (*link, core::mem::take(data));
+                        self.payload = None;
+                        self.say(&alloc::format!(
+                            "\r\nRecv {} bytes\r\n\r\nSEND OK\r\n",
+                            data.len()
+                        ));
+                        self.say(&alloc::format!("\r\n+IPD,{},{}:", link, data.len()));
+                        self.output.extend(data);
+                    }
+                } else if b == b'\n' {
+                    let line = String::from_utf8(core::mem::take(&mut self.line)).unwrap();
+                    self.command(line.trim_end());
+                } else {
+                    self.line.push(b);
+                }
+            }
+        }
+
+        fn command(&mut self, line: &str) {
+            self.commands.push(line.into());
+            if self.mute {
+                return;
+            }
+            if self.echo {
+                self.say(&alloc::format!("{}\r\r\n", line));
+            }
+
+            const OK: &str = "\r\nOK\r\n";
+            const ERROR: &str = "\r\nERROR\r\n";
+            let link_arg = |rest: &str| -> usize { rest[..1].parse().unwrap() };
+
+            match line {
+                "AT" | "AT+CWMODE=1" | "AT+CIPMUX=1" => self.say(OK),
+                "ATE0" => {
+                    self.echo = false;
+                    self.say(OK);
+                }
+                "AT+CWLAP" => {
+                    self.say("+CWLAP:(3,\"home\",-42,\"01:02:03:04:05:06\",6,-1,-1,4,4,7,0)\r\n");
+                    self.say("+CWLAP:(0,\"guest\",-80,\"01:02:03:04:05:07\",11,-1,-1,0,0,7,0)\r\n");
+                    self.say(OK);
+                }
+                r#"AT+CWJAP="home","s\,cret""# => {
+                    self.joined = true;
+                    self.say("WIFI CONNECTED\r\nWIFI GOT IP\r\n");
+                    self.say(OK);
+                }
+                "AT+CWJAP?" if self.joined => {
+                    self.say("+CWJAP:\"home\",\"01:02:03:04:05:06\",6,-42,0,1,3,0,1\r\n");
+                    self.say(OK);
+                }
+                "AT+CWJAP?" => {
+                    self.say("No AP\r\n");
+                    self.say(OK);
+                }
+                l if l.starts_with("AT+CWJAP=") => {
+                    self.say("+CWJAP:2\r\n");
+                    self.say(ERROR);
+                }
+                "AT+CWDHCP?" => {
+                    self.say("+CWDHCP:3\r\n");
+                    self.say(OK);
+                }
+                "AT+CIPSTA?" => {
+                    self.say("+CIPSTA:ip:\"192.168.4.2\"\r\n");
+                    self.say("+CIPSTA:gateway:\"192.168.4.1\"\r\n");
+                    self.say("+CIPSTA:netmask:\"255.255.255.0\"\r\n");
+                    self.say(OK);
+                }
+                l if l.starts_with("AT+CIPSTART=") => {
+                    let rest = &l["AT+CIPSTART=".len()..];
+                    let link = link_arg(rest);
+                    if rest.contains("refused") {
+                        self.say(&alloc::format!("{},CONNECT FAIL\r\n", link));
+                        self.say(ERROR);
+                    } else {
+                        self.open[link] = true;
+                        self.say(&alloc::format!("{},CONNECT\r\n", link));
+                        self.say(OK);
+                    }
+                }
+                l if l.starts_with("AT+CIPSEND=") => {
+                    let (link, len) = l["AT+CIPSEND=".len()..].split_once(',').unwrap();
+                    let link: usize = link.parse().unwrap();
+                    if self.open[link] {
+                        self.payload = Some((link as u8, len.parse().unwrap(), Vec::new()));
+                        self.say(OK);
+                        self.say("> ");
+                    } else {
+                        self.say(ERROR);
+                    }
+                }
+                l if l.starts_with("AT+CIPCLOSE=") => {
+                    let link = link_arg(&l["AT+CIPCLOSE=".len()..]);
+                    if core::mem::take(&mut self.open[link]) {
+                        self.say(&alloc::format!("{},CLOSED\r\n", link));
+                        self.say(OK);
+                    } else {
+                        self.say(ERROR);
+                    }
+                }
+                _ => self.say(ERROR),
+            }
+        }
+    }
+
+    struct Port(Rc<RefCell<Sim>>);
+
+    impl ErrorType for Port {
+        type Error = Infallible;
+    }
+
+    impl Read for Port {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            let n = {
+                let mut sim = self.0.borrow_mut();
+                let n = buf.len().min(sim.output.len());
+                for (dst, src) in buf.iter_mut().zip(sim.output.drain(..n)) {
+                    *dst = src;
+                }
+                n
+            };
+            match n {
+                0 => pending().await,
+                n => Ok(n),
+            }
+        }
+    }
+
+    impl Write for Port {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            self.0.borrow_mut().receive(buf);
+            Ok(buf.len())
+        }
+    }
+
+    /// Times out as soon as the module has nothing more to say.
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    type SimDriver = EspAt<Port, Port, NoDelay>;
+
+    fn driver(sim: &Rc<RefCell<Sim>>, config: Config) -> SimDriver {
+        EspAt::new(Port(sim.clone()), Port(sim.clone()), NoDelay, config)
+    }
+
+    #[test]
+    fn joins_and_reports_status() {
+        let sim = Sim::new();
+        let wifi = driver(&sim, Config::default());
+
+        block_on(async {
+            wifi.init().await.unwrap();
+
+            let aps = wifi.scan().await.unwrap();
+            assert_eq!(aps.len(), 2);
+            assert_eq!((aps[0].ssid.as_str(), aps[0].rssi), ("home", -42));
+            assert_eq!(aps[1].security(), "open");
+
+            assert_eq!(wifi.status().await.unwrap().joined, None);
+            assert_eq!(
+                wifi.join("home", "wrong").await,
+                Err(Error::Join(JoinError::WrongPassword))
+            );
+            wifi.join("home", "s,cret").await.unwrap();
+
+            let status = wifi.status().await.unwrap();
+            assert_eq!(status.joined.unwrap().channel, 6);
+            assert!(status.dhcp.enabled);
+            assert_eq!(status.dhcp.ip.address, Ipv4Addr::new(192, 168, 4, 2));
+        });
+    }
+
+    #[test]
+    fn tcp_socket_round_trip() {
+        let sim = Sim::new();
+        let wifi = driver(&sim, Config::default());
+
+        block_on(async {
+            wifi.init().await.unwrap();
+
+            let mut socket = wifi.connect_tcp("example.com", 80).await.unwrap();
+            assert_eq!(socket.link(), 0);
+            // Looks like responses, but is payload.
+            let message = b"GET /\r\n\r\nOK\r\n+IPD,1,2:\r\n";
+            socket.write_all(message).await.unwrap();
+            let mut buf = [0u8; 64];
+            socket.read_exact(&mut buf[..message.len()]).await.unwrap();
+            assert_eq!(&buf[..message.len()], message);
+            socket.close().await.unwrap();
+
+            // The link is free again.
+            let socket = wifi.connect_tcp("example.com", 80).await.unwrap();
+            assert_eq!(socket.link(), 0);
+        });
+
+        let sim = sim.borrow();
+        assert!(sim
+            .commands
+            .contains(&r#"AT+CIPSTART=0,"TCP","example.com",80"#.into()));
+        assert!(sim.commands.contains(&"AT+CIPCLOSE=0".into()));
+    }
+
+    #[test]
+    fn sockets_share_the_module() {
+        let sim = Sim::new();
+        let wifi = driver(&sim, Config::default());
+
+        block_on(async {
+            wifi.init().await.unwrap();
+
+            let mut tcp = wifi.connect_tcp("10.0.0.1", 23).await.unwrap();
+            let mut udp = wifi.connect_udp("10.0.0.2", 5000, 5001).await.unwrap();
+            assert_eq!((tcp.link(), udp.link()), (0, 1));
+
+            // Data for both links arrives while the UDP socket is reading; the TCP peer then
+            // hangs up.
+            sim.borrow_mut()
+                .say("\r\n+IPD,0,3:tcp\r\n+IPD,1,3:udp0,CLOSED\r\n");
+            let mut buf = [0u8; 8];
+            assert_eq!(udp.read(&mut buf).await, Ok(3));
+            assert_eq!(&buf[..3], b"udp");
+            assert_eq!(tcp.read(&mut buf).await, Ok(3));
+            assert_eq!(&buf[..3], b"tcp");
+            assert_eq!(tcp.read(&mut buf).await, Ok(0));
+            assert_eq!(tcp.write(b"x").await, Err(Error::Closed));
+
+            udp.write_all(b"ping").await.unwrap();
+            assert_eq!(udp.read(&mut buf).await, Ok(4));
+        });
+
+        assert!(sim
+            .borrow()
+            .commands
+            .contains(&r#"AT+CIPSTART=1,"UDP","10.0.0.2",5000,5001,0"#.into()));
+    }
+
+    #[test]
+    fn errors() {
+        let sim = Sim::new();
+        let wifi = driver(
+            &sim,
+            Config {
+                socket_buffer: 8,
+                ..Default::default()
+            },
+        );
+
+        block_on(async {
+            wifi.init().await.unwrap();
+
+            assert_eq!(
+                wifi.connect_tcp("refused.example", 80).await.err(),
+                Some(Error::Rejected)
+            );
+
+            let mut sockets = Vec::new();
+            for _ in 0..MAX_LINKS {
+                sockets.push(wifi.connect_tcp("example.com", 80).await.unwrap());
+            }
+            assert_eq!(
+                wifi.connect_tcp("example.com", 80).await.err(),
+                Some(Error::NoFreeLink)
+            );
+
+            sim.borrow_mut().say("+IPD,4,12:0123456789ab");
+            let mut buf = [0u8; 16];
+            let socket = &mut sockets[4];
+            assert_eq!(socket.read(&mut buf).await, Err(Error::Overflow));
+            assert_eq!(socket.read(&mut buf).await, Ok(8));
+            assert_eq!(&buf[..8], b"01234567");
+
+            sim.borrow_mut().mute = true;
+            assert_eq!(wifi.leave().await, Err(Error::Timeout));
+        });
+    }
+}
@@ -0,0 +1,305 @@
+use core::{
+    fmt::{self, Write},
+    net::Ipv4Addr,
+};
+
+pub type Ssid = heapless::String<32>;
+
+/// One `+CWLAP` scan result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessPoint {
+    pub ssid: Ssid,
+    pub rssi: i8,
+    pub bssid: [u8; 6],
+    pub channel: u8,
+    /// Encryption method as reported by the module, see [`AccessPoint::security`].
+    pub ecn: u8,
+}
+
+impl AccessPoint {
+    pub fn security(&self) -> &'static str {
+        match self.ecn {
+            0 => "open",
+            1 => "WEP",
+            2 => "WPA",
+            3 => "WPA2",
+            4 => "WPA/WPA2",
+            5 => "WPA2-EAP",
+            6 => "WPA3",
+            7 => "WPA2/WPA3",
+            _ => "?",
+        }
+    }
+}
+
+/// The access point the station is joined to, from `+CWJAP?`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Joined {
+    pub ssid: Ssid,
+    pub bssid: [u8; 6],
+    pub channel: u8,
+    pub rssi: i8,
+}
+
+/// Reason code of a failed `+CWJAP`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    Timeout,
+    WrongPassword,
+    NoAccessPoint,
+    Failed,
+    Other(u8),
+}
+
+impl JoinError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JoinError::Timeout => "join timed out",
+            JoinError::WrongPassword => "wrong password",
+            JoinError::NoAccessPoint => "access point not found",
+            JoinError::Failed => "join failed",
+            JoinError::Other(_) => "join failed (unknown reason)",
+        }
+    }
+}
+
+impl From<u8> for JoinError {
+    fn from(code: u8) -> Self {
+        match code {
+            1 => JoinError::Timeout,
+            2 => JoinError::WrongPassword,
+            3 => JoinError::NoAccessPoint,
+            4 => JoinError::Failed,
+            code => JoinError::Other(code),
+        }
+    }
+}
+
+/// Station address, from the `+CIPSTA:` lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpConfig {
+    pub address: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+}
+
+impl Default for IpConfig {
+    fn default() -> Self {
+        Self {
+            address: Ipv4Addr::UNSPECIFIED,
+            gateway: Ipv4Addr::UNSPECIFIED,
+            netmask: Ipv4Addr::UNSPECIFIED,
+        }
+    }
+}
+
+impl IpConfig {
+    /// Apply one `+CIPSTA:<field>:"<addr>"` line. Returns false for anything else.
+    pub fn update(&mut self, info: &str) -> bool {
+        let Some((field, value)) = info
+            .strip_prefix("+CIPSTA:")
+            .and_then(|rest| rest.split_once(':'))
+        else {
+            return false;
+        };
+        let Some(addr) = unquote::<16>(value).and_then(|s| s.parse().ok()) else {
+            return false;
+        };
+
+        match field {
+            "ip" => self.address = addr,
+            "gateway" => self.gateway = addr,
+            "netmask" => self.netmask = addr,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn has_address(&self) -> bool {
+        !self.address.is_unspecified()
+    }
+}
+
+/// DHCP client state and the address it obtained.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DhcpStatus {
+    /// The station's DHCP client is enabled.
+    pub enabled: bool,
+    pub ip: IpConfig,
+}
+
+/// `+CWLAP:(<ecn>,"<ssid>",<rssi>,"<bssid>",<channel>,...)`
+pub fn parse_access_point(info: &str) -> Option<AccessPoint> {
+    let body = info.strip_prefix("+CWLAP:(")?.strip_suffix(')')?;
+    let mut fields = Fields(body);
+
+    Some(AccessPoint {
+        ecn: fields.next()?.parse().ok()?,
+        ssid: unquote(fields.next()?)?,
+        rssi: fields.next()?.parse().ok()?,
+        bssid: parse_mac(&unquote::<17>(fields.next()?)?)?,
+        channel: fields.next()?.parse().ok()?,
+    })
+}
+
+/// `+CWJAP:"<ssid>","<bssid>",<channel>,<rssi>,...`, the reply to `AT+CWJAP?`.
+pub fn parse_joined(info: &str) -> Option<Joined> {
+    let mut fields = Fields(info.strip_prefix("+CWJAP:")?);
+
+    Some(Joined {
+        ssid: unquote(fields.next()?)?,
+        bssid: parse_mac(&unquote::<17>(fields.next()?)?)?,
+        channel: fields.next()?.parse().ok()?,
+        rssi: fields.next()?.parse().ok()?,
+    })
+}
+
+/// `+CWJAP:<code>`, printed before `FAIL` when joining fails.
+pub fn parse_join_error(info: &str) -> Option<JoinError> {
+    let code: u8 = info.strip_prefix("+CWJAP:")?.parse().ok()?;
+    Some(code.into())
+}
+
+/// `+CWDHCP:<state>`; bit 0 is the station's DHCP client.
+pub fn parse_dhcp(info: &str) -> Option<bool> {
+    let state: u8 = info.strip_prefix("+CWDHCP:")?.parse().ok()?;
+    Some(state & 1 != 0)
+}
+
+/// Append `s` as a quoted AT command argument, escaping `"`, `,` and `\`.
+pub fn quote<W: Write>(out: &mut W, s: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in s.chars() {
+        if matches!(c, '"' | ',' | '\\') {
+            out.write_char('\\')?;
+        }
+        out.write_char(c)?;
+    }
+    out.write_char('"')
+}
+
+/// Inverse of [`quote`]. `None` if `s` isn't quoted or doesn't fit.
+pub fn unquote<const N: usize>(s: &str) -> Option<heapless::String<N>> {
+    let inner = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = heapless::String::new();
+    let mut escaped = false;
+    for c in inner.chars() {
+        if !escaped && c == '\\' {
+            escaped = true;
+            continue;
+        }
+        escaped = false;
+        out.push(c).ok()?;
+    }
+
+    Some(out)
+}
+
+fn parse_mac(s: &str) -> Option<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    let mut parts = s.split(':');
+    for b in &mut mac {
+        *b = u8::from_str_radix(parts.next()?, 16).ok()?;
+    }
+
+    parts.next().is_none().then_some(mac)
+}
+
+/// Comma-separated fields, where commas inside quoted strings don't count.
+struct Fields<'a>(&'a str);
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.0.is_empty() {
+            return None;
+        }
+
+        let (mut quoted, mut escaped) = (false, false);
+        let end = self
+            .0
+            .char_indices()
+            .find(|&(_, c)| {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' if quoted => escaped = true,
+                    '"' => quoted = !quoted,
+                    ',' if !quoted => return true,
+                    _ => {}
+                }
+                false
+            })
+            .map(|(i, _)| i);
+
+        let field;
+        (field, self.0) = match end {
+            Some(i) => (&self.0[..i], &self.0[i + 1..]),
+            None => (self.0, ""),
+        };
+        Some(field)
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_points() {
+        let ap = parse_access_point(
+            r#"+CWLAP:(3,"Cafe \"Wi\,Fi\"",-67,"a4:2b:b0:01:02:ff",11,-1,-1,4,4,7,0)"#,
+        )
+        .unwrap();
+        assert_eq!(ap.ssid, "Cafe \"Wi,Fi\"");
+        assert_eq!(ap.rssi, -67);
+        assert_eq!(ap.bssid, [0xa4, 0x2b, 0xb0, 0x01, 0x02, 0xff]);
+        assert_eq!(ap.channel, 11);
+        assert_eq!(ap.security(), "WPA2");
+
+        assert_eq!(parse_access_point(r#"+CWLAP:(0,"x",-50)"#), None);
+        assert_eq!(parse_access_point("+CIPSTA:ip:\"1.2.3.4\""), None);
+    }
+
+    #[test]
+    fn join_state() {
+        let joined = parse_joined(r#"+CWJAP:"home","01:02:03:04:05:06",6,-42,0,1,3,0,1"#).unwrap();
+        assert_eq!(joined.ssid, "home");
+        assert_eq!(joined.channel, 6);
+        assert_eq!(joined.rssi, -42);
+
+        assert_eq!(parse_join_error("+CWJAP:2"), Some(JoinError::WrongPassword));
+        assert_eq!(parse_join_error("+CWJAP:9"), Some(JoinError::Other(9)));
+        assert_eq!(parse_joined("+CWJAP:2"), None);
+        assert_eq!(parse_dhcp("+CWDHCP:3"), Some(true));
+        assert_eq!(parse_dhcp("+CWDHCP:2"), Some(false));
+    }
+
+    #[test]
+    fn ip_config() {
+        let mut ip = IpConfig::default();
+        assert!(!ip.has_address());
+        assert!(ip.update(r#"+CIPSTA:ip:"192.168.4.2""#));
+        assert!(ip.update(r#"+CIPSTA:gateway:"192.168.4.1""#));
+        assert!(ip.update(r#"+CIPSTA:netmask:"255.255.255.0""#));
+        assert!(!ip.update(r#"+CIPSTA:ip6ll:"fe80::1""#));
+        assert!(!ip.update("+CWDHCP:3"));
+
+        assert!(ip.has_address());
+        assert_eq!(ip.address, Ipv4Addr::new(192, 168, 4, 2));
+        assert_eq!(ip.gateway, Ipv4Addr::new(192, 168, 4, 1));
+        assert_eq!(ip.netmask, Ipv4Addr::new(255, 255, 255, 0));
+    }
+
+    #[test]
+    fn quoting_round_trips() {
+        let mut out = heapless::String::<64>::new();
+        quote(&mut out, r#"a"b,c\d"#).unwrap();
+        assert_eq!(out, r#""a\"b\,c\\d""#);
+        assert_eq!(unquote::<16>(&out).unwrap(), r#"a"b,c\d"#);
+        assert_eq!(unquote::<4>(&out), None);
+        assert_eq!(unquote::<16>("bare"), None);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{capture::Tap, console::command::WifiCommand};
+use alloc::{format, string::String};
+use core::fmt::Write as _;
+use defmt::{info, warn};
+use embassy_stm32::{
+    peripherals::USART1,
+    usart::{BufferedUartRx, BufferedUartTx},
+};
+use embassy_time::Delay;
+use embedded_io_async::Write;
+
+pub mod driver;
+pub mod info;
+pub mod parse;
+
+pub use driver::{Config, Error, EspAt, Socket, WifiStatus};
+
+/// The driver on USART1, borrowing the UART for as long as it lives.
+pub type Wifi<'a> = EspAt<
+    &'a mut Tap<BufferedUartRx<'static, USART1>>,
+    &'a mut Tap<BufferedUartTx<'static, USART1>>,
+    Delay,
+>;
+
+pub fn new<'a>(
+    rx: &'a mut Tap<BufferedUartRx<'static, USART1>>,
+    tx: &'a mut Tap<BufferedUartTx<'static, USART1>>,
+) -> Wifi<'a> {
+    EspAt::new(rx, tx, Delay, Config::default())
+}
+
+/// Run a console `wifi` command and write the outcome to `out`.
+pub async fn run_command<W: Write>(
+    wifi: &Wifi<'_>,
+    command: &WifiCommand,
+    out: &mut W,
+) -> Result<(), W::Error> {
+    // Not the whole command: it may hold a password.
+    let name = match command {
+        WifiCommand::Status => "status",
+        WifiCommand::Scan => "scan",
+        WifiCommand::Join { .. } => "join",
+        WifiCommand::Leave => "leave",
+    };
+    info!("wifi: {=str}", name);
+
+    let text = match execute(wifi, command).await {
+        Ok(text) => text + "OK\r\n",
+        Err(e) => {
+            warn!("wifi: {=str}", e.as_str());
+            format!("ERROR: {}\r\n", e.as_str())
+        }
+    };
+    out.write_all(text.as_bytes()).await?;
+    out.flush().await
+}
+
+async fn execute(wifi: &Wifi<'_>, command: &WifiCommand) -> Result<String, Error> {
+    wifi.init().await?;
+
+    let mut text = String::new();
+    match command {
+        WifiCommand::Scan => {
+            for ap in wifi.scan().await? {
+                let _ = write!(
+                    text,
+                    "{:>4} dBm  ch {:>2}  {:<9}  {}\r\n",
+                    ap.rssi,
+                    ap.channel,
+                    ap.security(),
+                    ap.ssid
+                );
+            }
+        }
+        WifiCommand::Join { ssid, password } => wifi.join(ssid, password).await?,
+        WifiCommand::Leave => wifi.leave().await?,
+        WifiCommand::Status => {
+            let status = wifi.status().await?;
+            match status.joined {
+                Some(ap) => {
+                    let b = ap.bssid;
+                    let _ = write!(
+                        text,
+                        "joined {} ({:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}), ch {}, {} dBm\r\n",
+                        ap.ssid, b[0], b[1], b[2], b[3], b[4], b[5], ap.channel, ap.rssi
+                    );
+                }
+                None => text.push_str("not joined\r\n"),
+            }
+            let (dhcp, ip) = (status.dhcp, status.dhcp.ip);
+            let _ = write!(
+                text,
+                "dhcp {}, ip {} netmask {} gateway {}\r\n",
+                if dhcp.enabled { "on" } else { "off" },
+                ip.address,
+                ip.netmask,
+                ip.gateway
+            );
+        }
+    }
+
+    Ok(text)
+}
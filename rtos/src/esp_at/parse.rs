@@ -0,0 +1,299 @@
+/// Longest response line kept. Longer lines are truncated.
+pub const MAX_LINE: usize = 256;
+
+/// Something the module said.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    Ok,
+    Error,
+    /// Command failed, e.g. `AT+CWJAP` with a wrong password.
+    Fail,
+    /// Still busy with the previous command (`busy p...` / `busy s...`).
+    Busy,
+    /// Ready for the payload of `AT+CIPSEND`.
+    Prompt,
+    SendOk,
+    SendFail,
+    /// Printed after a reset.
+    Ready,
+    WifiConnected,
+    WifiGotIp,
+    WifiDisconnected,
+    /// `<link>,CONNECT`
+    Connected(u8),
+    /// `<link>,CLOSED`, also sent for `<link>,CONNECT FAIL`.
+    Closed(u8),
+    /// Some payload bytes of a `+IPD,<link>,<len>:` notification. A payload may be split over
+    /// several events.
+    Data {
+        link: u8,
+        data: &'a [u8],
+    },
+    /// A `+...` information line, such as `+CWLAP:(...)`.
+    Info(&'a str),
+    /// Anything else: command echo, `Recv n bytes`, text that isn't UTF-8.
+    Other(&'a [u8]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Line,
+    /// The last event borrowed the line buffer; clear it before going on.
+    LineDone,
+    /// Skipping the rest of a line that didn't fit.
+    Overflow,
+    Payload {
+        link: u8,
+        remaining: usize,
+    },
+}
+
+/// Splits the module's output into [`Event`]s, one at a time.
+///
+/// Responses are line based, except for the `>` send prompt, which isn't followed by a line
+/// ending, and `+IPD` payloads, which are binary and counted by length.
+#[derive(Debug)]
+pub struct Parser {
+    line: heapless::Vec<u8, MAX_LINE>,
+    state: State,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self {
+            line: heapless::Vec::new(),
+            state: State::Line,
+        }
+    }
+
+    /// Consume bytes from `input` until an event is complete. Returns the number of bytes used,
+    /// which is all of `input` if no event was found.
+    pub fn feed<'a>(&'a mut self, input: &'a [u8]) -> (usize, Option<Event<'a>>) {
+        match self.state {
+            State::LineDone => {
+                self.line.clear();
+                self.state = State::Line;
+            }
+            State::Payload { link, remaining } => {
+                let n = remaining.min(input.len());
+                self.state = match remaining - n {
+                    0 => State::Line,
+                    remaining => State::Payload { link, remaining },
+                };
+                let event = (n > 0).then(|| Event::Data {
+                    link,
+                    data: &input[..n],
+                });
+                return (n, event);
+            }
+            State::Line | State::Overflow => {}
+        }
+
+        for (i, &b) in input.iter().enumerate() {
+            if b == b'\n' {
+                if self.line.last() == Some(&b'\r') && self.state == State::Line {
+                    self.line.pop();
+                }
+                // Blank, or the space some firmware prints after the send prompt.
+                if self.line.iter().all(u8::is_ascii_whitespace) {
+                    self.line.clear();
+                    self.state = State::Line;
+                    continue;
+                }
+                self.state = State::LineDone;
+                return (i + 1, Some(classify(&self.line)));
+            }
+            if self.state == State::Overflow {
+                continue;
+            }
+            if self.line.push(b).is_err() {
+                self.state = State::Overflow;
+                continue;
+            }
+
+            if self.line == b">" {
+                self.line.clear();
+                return (i + 1, Some(Event::Prompt));
+            }
+            if b == b':' {
+                if let Some((link, len)) = ipd_header(&self.line) {
+                    self.line.clear();
+                    if len > 0 {
+                        self.state = State::Payload {
+                            link,
+                            remaining: len,
+                        };
+                    }
+                    return (i + 1, None);
+                }
+            }
+        }
+
+        (input.len(), None)
+    }
+}
+
+/// `+IPD,<link>,<len>:` with the colon included.
+fn ipd_header(line: &[u8]) -> Option<(u8, usize)> {
+    let fields = core::str::from_utf8(line.strip_prefix(b"+IPD,")?.strip_suffix(b":")?).ok()?;
+    let (link, len) = fields.split_once(',')?;
+    // Newer firmware may append the remote address and port.
+    let len = len.split(',').next()?;
+
+    Some((link.parse().ok()?, len.parse().ok()?))
+}
+
+fn classify(line: &[u8]) -> Event<'_> {
+    let Ok(text) = core::str::from_utf8(line) else {
+        return Event::Other(line);
+    };
+
+    match text.trim_end() {
+        "OK" => Event::Ok,
+        "ERROR" => Event::Error,
+        "FAIL" => Event::Fail,
+        "SEND OK" => Event::SendOk,
+        "SEND FAIL" => Event::SendFail,
+        "ready" => Event::Ready,
+        "WIFI CONNECTED" => Event::WifiConnected,
+        "WIFI GOT IP" => Event::WifiGotIp,
+        "WIFI DISCONNECT" => Event::WifiDisconnected,
+        t if t.starts_with("busy ") => Event::Busy,
+        t if t.starts_with('+') => Event::Info(t),
+        t => match link_status(t) {
+            Some(event) => event,
+            None => Event::Other(line),
+        },
+    }
+}
+
+fn link_status(text: &str) -> Option<Event<'static>> {
+    let (link, status) = text.split_once(',')?;
+    let link = link.parse().ok()?;
+    match status {
+        "CONNECT" => Some(Event::Connected(link)),
+        "CLOSED" | "CONNECT FAIL" => Some(Event::Closed(link)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{string::String, vec::Vec};
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Owned {
+        Data(u8, Vec<u8>),
+        Info(String),
+        Other(Vec<u8>),
+        Plain(String),
+    }
+
+    /// Feed `input` in chunks of `chunk` bytes and collect the events.
+    fn events(input: &[u8], chunk: usize) -> Vec<Owned> {
+        let mut parser = Parser::new();
+        let mut out = Vec::new();
+        for mut part in input.chunks(chunk) {
+            while !part.is_empty() {
+                let (used, event) = parser.feed(part);
+                out.extend(event.map(|e| match e {
+                    Event::Data { link, data } => Owned::Data(link, data.to_vec()),
+                    Event::Info(text) => Owned::Info(text.into()),
+                    Event::Other(bytes) => Owned::Other(bytes.to_vec()),
+                    e => Owned::Plain(alloc::format!("{:?}", e)),
+                }));
+                part = &part[used..];
+            }
+        }
+        // Adjacent payload pieces are one notification.
+        out.dedup_by(|b, a| match (a, b) {
+            (Owned::Data(la, da), Owned::Data(lb, db)) if la == lb => {
+                da.extend_from_slice(db);
+                true
+            }
+            _ => false,
+        });
+        out
+    }
+
+    fn plain(s: &str) -> Owned {
+        Owned::Plain(s.into())
+    }
+
+    #[test]
+    fn command_responses() {
+        let input = b"AT+CWLAP\r\r\n+CWLAP:(3,\"home\",-60)\r\n\r\nOK\r\nbusy p...\r\nERROR\r\n";
+        for chunk in [1, 5, input.len()] {
+            assert_eq!(
+                events(input, chunk),
+                [
+                    Owned::Other(b"AT+CWLAP\r".to_vec()),
+                    Owned::Info("+CWLAP:(3,\"home\",-60)".into()),
+                    plain("Ok"),
+                    plain("Busy"),
+                    plain("Error"),
+                ],
+                "chunk {}",
+                chunk
+            );
+        }
+    }
+
+    #[test]
+    fn unsolicited_notifications() {
+        let input = b"WIFI CONNECTED\r\nWIFI GOT IP\r\n0,CONNECT\r\n1,CONNECT FAIL\r\n0,CLOSED\r\n";
+        assert_eq!(
+            events(input, 3),
+            [
+                plain("WifiConnected"),
+                plain("WifiGotIp"),
+                plain("Connected(0)"),
+                plain("Closed(1)"),
+                plain("Closed(0)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn send_prompt_and_binary_payload() {
+        // The payload contains line endings and a fake `OK` that must not be parsed.
+        let input = b"\r\nOK\r\n> \r\nRecv 4 bytes\r\n\r\nSEND OK\r\n\r\n+IPD,2,8:ab\r\nOK\r\n\r\n+IPD,0,3,\"10.0.0.1\",80:xyz";
+        for chunk in [1, 7, input.len()] {
+            assert_eq!(
+                events(input, chunk),
+                [
+                    plain("Ok"),
+                    plain("Prompt"),
+                    Owned::Other(b"Recv 4 bytes".to_vec()),
+                    plain("SendOk"),
+                    Owned::Data(2, b"ab\r\nOK\r\n".to_vec()),
+                    Owned::Data(0, b"xyz".to_vec()),
+                ],
+                "chunk {}",
+                chunk
+            );
+        }
+    }
+
+    #[test]
+    fn long_lines_are_truncated() {
+        let mut input = Vec::new();
+        input.extend_from_slice(b"+X:");
+        input.resize(MAX_LINE + 50, b'a');
+        input.extend_from_slice(b"\r\nOK\r\n");
+
+        let events = events(&input, 16);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], Owned::Info(t) if t.len() == MAX_LINE));
+        assert_eq!(events[1], plain("Ok"));
+    }
+}
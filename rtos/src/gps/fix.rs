@@ -0,0 +1,236 @@
+//! The receiver's latest solution, from NMEA sentences, NAV-PVT messages or both.
+
+use super::{
+    nmea::{Dimension, Position, Quality, Sentence},
+    ubx::{FixType, NavPvt},
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fix {
+    /// UTC of the epoch.
+    pub time: Option<NaiveDateTime>,
+    /// A usable position; `time` may be good without one.
+    pub valid: bool,
+    pub dimension: Option<Dimension>,
+    pub position: Option<Position>,
+    /// Above mean sea level, in mm.
+    pub altitude: Option<i32>,
+    /// Over ground, in mm/s.
+    pub speed: Option<u32>,
+    /// True course over ground, in 1e-5 degrees.
+    pub course: Option<i32>,
+    /// Satellites used in the solution.
+    pub satellites: u8,
+    /// Satellites in view, across every constellation.
+    pub in_view: u8,
+    /// Dilutions of precision, in hundredths.
+    pub hdop: Option<u16>,
+    pub pdop: Option<u16>,
+    date: Option<NaiveDate>,
+}
+
+impl Fix {
+    /// Merge a sentence in. An epoch starts with RMC, as u-blox receivers send them.
+    pub fn update(&mut self, sentence: &Sentence) {
+        match sentence {
+            Sentence::Rmc(rmc) => {
+                self.in_view = 0;
+                self.valid = rmc.valid;
+                self.date = rmc.date.or(self.date);
+                self.set_time(rmc.time);
+                self.position = rmc.position;
+                self.speed = rmc.speed;
+                self.course = rmc.course;
+            }
+            Sentence::Gga(gga) => {
+                self.set_time(gga.time);
+                self.valid = gga.quality != Quality::Invalid;
+                self.position = gga.position;
+                self.altitude = gga.altitude;
+                self.satellites = gga.satellites;
+                self.hdop = gga.hdop;
+            }
+            Sentence::Gsa(gsa) => {
+                self.dimension = Some(gsa.dimension);
+                self.pdop = gsa.pdop;
+                self.hdop = gsa.hdop.or(self.hdop);
+            }
+            // Each constellation sends its own group.
+            Sentence::Gsv(gsv) => {
+                if gsv.message == 1 {
+                    self.in_view = self.in_view.saturating_add(gsv.in_view);
+                }
+            }
+            Sentence::Vtg(vtg) => {
+                self.speed = vtg.speed.or(self.speed);
+                self.course = vtg.course.or(self.course);
+            }
+        }
+    }
+
+    /// Take over a NAV-PVT solution, which has everything but the satellites in view.
+    pub fn update_pvt(&mut self, pvt: &NavPvt) {
+        self.time = pvt.time;
+        self.date = pvt.time.map(|time| time.date());
+        self.valid = pvt.fix_ok && !matches!(pvt.fix_type, FixType::None | FixType::TimeOnly);
+        self.dimension = Some(match pvt.fix_type {
+            FixType::Fix2d => Dimension::Fix2d,
+            FixType::Fix3d | FixType::GnssDeadReckoning => Dimension::Fix3d,
+            _ => Dimension::None,
+        });
+        self.position = Some(Position {
+            latitude: pvt.latitude,
+            longitude: pvt.longitude,
+        })
+        .filter(|_| self.valid);
+        self.altitude = Some(pvt.height_msl).filter(|_| self.valid);
+        self.speed = Some(pvt.speed.unsigned_abs());
+        self.course = Some(pvt.heading);
+        self.satellites = pvt.satellites;
+        self.pdop = Some(pvt.pdop);
+    }
+
+    /// NMEA time is only a time of day; the date comes from the last RMC.
+    fn set_time(&mut self, time: Option<chrono::NaiveTime>) {
+        self.time = self.date.zip(time).map(|(date, time)| date.and_time(time));
+    }
+}
+
+/// Degrees with seven decimals.
+fn degrees(f: &mut fmt::Formatter<'_>, value: i32) -> fmt::Result {
+    let sign = if value < 0 { "-" } else { "" };
+    let value = value.unsigned_abs();
+    write!(f, "{sign}{}.{:07}", value / 10_000_000, value % 10_000_000)
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        degrees(f, self.latitude)?;
+        f.write_str(",")?;
+        degrees(f, self.longitude)
+    }
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.time {
+            Some(time) => write!(f, "{} UTC", time.format("%Y-%m-%d %H:%M:%S%.3f"))?,
+            None => f.write_str("no time")?,
+        }
+        match (self.valid, self.position) {
+            (true, Some(position)) => write!(f, " {position}")?,
+            _ => f.write_str(" no fix")?,
+        }
+        if let (true, Some(altitude)) = (self.valid, self.altitude) {
+            let sign = if altitude < 0 { "-" } else { "" };
+            let altitude = altitude.unsigned_abs();
+            write!(f, " {sign}{}.{} m", altitude / 1000, altitude % 1000 / 100)?;
+        }
+        write!(f, ", {}/{} satellites", self.satellites, self.in_view)
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gps::stream::{Decoder, Frame};
+    use alloc::string::ToString;
+    use chrono::{NaiveDate, NaiveTime};
+
+    /// One epoch of a u-blox M10 at its default NMEA output, NMEA 4.11.
+    const LOG: &str = "\
+$GNRMC,101532.00,A,5130.43712,N,00007.65024,W,0.027,,190526,,,A,V*08\r\n\
+$GNVTG,,T,,M,0.027,N,0.050,K,A*3D\r\n\
+$GNGGA,101532.00,5130.43712,N,00007.65024,W,1,09,0.98,23.4,M,45.9,M,,*67\r\n\
+$GNGSA,A,3,10,12,24,25,32,,,,,,,,1.71,0.98,1.40,1*00\r\n\
+$GNGSA,A,3,65,72,88,,,,,,,,,,1.71,0.98,1.40,2*07\r\n\
+$GPGSV,2,1,07,10,71,273,33,12,30,300,29,24,22,063,37,25,52,117,41,1*6C\r\n\
+$GPGSV,2,2,07,32,55,195,35,15,09,035,,18,04,326,,1*58\r\n\
+$GLGSV,1,1,03,65,39,068,30,72,45,289,27,88,26,232,24,1*4C\r\n\
+$GNGLL,5130.43712,N,00007.65024,W,101532.00,A,A*67\r\n";
+
+    /// NAV-PVT of the same epoch.
+    const NAV_PVT: [u8; 100] = [
+        0xb5, 0x62, 0x01, 0x07, 0x5c, 0x00, 0xf0, 0x87, 0x80, 0x0c, 0xea, 0x07, 0x05, 0x13, 0x0a,
+        0x0f, 0x20, 0x37, 0x19, 0x00, 0x00, 0x00, 0x20, 0xd1, 0xff, 0xff, 0x03, 0x01, 0xea, 0x09,
+        0x60, 0x8b, 0xec, 0xff, 0x55, 0x63, 0xb3, 0x1e, 0xb4, 0x0e, 0x01, 0x00, 0x68, 0x5b, 0x00,
+        0x00, 0xdc, 0x05, 0x00, 0x00, 0xfc, 0x08, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00, 0xf8, 0xff,
+        0xff, 0xff, 0x03, 0x00, 0x00, 0x00, 0x0e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08,
+        0xcf, 0x00, 0x00, 0x80, 0x4f, 0x12, 0x00, 0xab, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1c, 0x8d,
+    ];
+
+    fn epoch() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 5, 19)
+            .unwrap()
+            .and_time(NaiveTime::from_hms_opt(10, 15, 32).unwrap())
+    }
+
+    #[test]
+    fn fix_from_nmea_log() {
+        let mut decoder = Decoder::new();
+        let mut fix = Fix::default();
+        let mut unsupported = 0;
+        for frame in LOG.bytes().filter_map(|b| decoder.push(b)) {
+            match frame {
+                Frame::Nmea(_, Ok(sentence)) => fix.update(&sentence),
+                Frame::Nmea(_, Err(crate::gps::nmea::Error::Unsupported)) => unsupported += 1,
+                other => panic!("{other:?}"),
+            }
+        }
+        assert_eq!(unsupported, 1); // GLL
+
+        assert_eq!(fix.time, Some(epoch()));
+        assert!(fix.valid);
+        assert_eq!(fix.dimension, Some(Dimension::Fix3d));
+        assert_eq!(
+            fix.position,
+            Some(Position {
+                latitude: 515_072_853,
+                longitude: -1_275_040,
+            })
+        );
+        assert_eq!(fix.altitude, Some(23_400));
+        assert_eq!(fix.speed, Some(13));
+        assert_eq!(fix.course, None);
+        assert_eq!((fix.satellites, fix.in_view), (9, 10));
+        assert_eq!((fix.hdop, fix.pdop), (Some(98), Some(171)));
+        assert_eq!(
+            fix.to_string(),
+            "2026-05-19 10:15:32.000 UTC 51.5072853,-0.1275040 23.4 m, 9/10 satellites"
+        );
+    }
+
+    #[test]
+    fn fix_from_nav_pvt() {
+        let mut decoder = Decoder::new();
+        let Some(Frame::Ubx(Ok(message))) = NAV_PVT.iter().find_map(|&b| decoder.push(b)) else {
+            panic!("no message");
+        };
+        let pvt = NavPvt::from_message(&message).unwrap();
+        assert!(pvt.time_resolved);
+        assert_eq!(pvt.time_accuracy, 25);
+        assert_eq!(pvt.height, 69_300);
+
+        let mut fix = Fix::default();
+        fix.update_pvt(&pvt);
+        // The receiver rounds to the second and gives the rest as negative nanoseconds.
+        assert_eq!(fix.time, Some(epoch() - chrono::Duration::microseconds(12)));
+        assert!(fix.valid);
+        assert_eq!(
+            fix.position,
+            Some(Position {
+                latitude: 515_072_853,
+                longitude: -1_275_040,
+            })
+        );
+        assert_eq!(
+            (fix.speed, fix.satellites, fix.pdop),
+            (Some(14), 9, Some(171))
+        );
+    }
+}
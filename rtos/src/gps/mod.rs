@@ -0,0 +1,211 @@
+use crate::{
+    bridge::{now_ms, wait_until},
+    capture::Tap,
+    console::command::GpsCommand,
+    rtc,
+    uart::SerialSettings,
+};
+use alloc::{string::String, vec::Vec};
+use chrono::{NaiveDateTime, Timelike};
+use core::{cell::Cell, fmt::Write as _};
+use defmt::{info, warn};
+use embassy_embedded_hal::SetConfig;
+use embassy_futures::select::{select4, Either4};
+use embassy_stm32::{
+    exti::ExtiInput,
+    peripherals::USART1,
+    usart::{BufferedUartRx, BufferedUartTx},
+};
+use embassy_time::Instant;
+use embedded_io_async::{Read, Write};
+
+pub mod fix;
+pub mod nmea;
+pub mod pps;
+pub mod stream;
+pub mod ubx;
+
+pub use fix::Fix;
+pub use nmea::{Position, Sentence};
+pub use pps::Discipline;
+pub use ubx::NavPvt;
+
+/// The factory setting of u-blox and most other receivers.
+pub const GPS_BAUDRATE: u32 = 9_600;
+const REPORT_MS: u64 = 1_000;
+
+/// Outlives a `gps` session, so the mapping keeps serving [`utc_now`] afterwards.
+static DISCIPLINE: critical_section::Mutex<Cell<Discipline>> =
+    critical_section::Mutex::new(Cell::new(Discipline::new()));
+
+/// UTC now, once a receiver has been heard from.
+pub fn utc_now() -> Option<NaiveDateTime> {
+    discipline().utc(Instant::now().as_micros())
+}
+
+/// The latest anchor of the mapping, to tell how fresh it is.
+pub fn anchor() -> Option<pps::Anchor> {
+    discipline().anchor()
+}
+
+fn discipline() -> Discipline {
+    critical_section::with(|cs| DISCIPLINE.borrow(cs).get())
+}
+
+fn update<R>(f: impl FnOnce(&mut Discipline) -> R) -> R {
+    critical_section::with(|cs| {
+        let cell = DISCIPLINE.borrow(cs);
+        let mut discipline = cell.get();
+        let result = f(&mut discipline);
+        cell.set(discipline);
+        result
+    })
+}
+
+/// Run the console `gps` command on a receiver on USART1 until a key is pressed on `host_rx`,
+/// printing the fix once a second. Pulses on `pps` discipline the uptime to UTC mapping, and the
+/// first one placed sets the on-chip RTC. USART1 is put back to `restore` afterwards.
+pub async fn run_command<HR: Read, HW: Write>(
+    rx: &mut Tap<BufferedUartRx<'static, USART1>>,
+    tx: &mut Tap<BufferedUartTx<'static, USART1>>,
+    host_rx: &mut HR,
+    host_tx: &mut HW,
+    pps: &mut ExtiInput<'static>,
+    command: GpsCommand,
+    restore: &SerialSettings,
+) -> Result<(), HW::Error> {
+    info!("gps: {}", defmt::Debug2Format(&command));
+    if let Err(e) = rx.set_config(&(&SerialSettings::new(command.baudrate)).into()) {
+        warn!("gps: UART config failed: {}", defmt::Debug2Format(&e));
+    }
+    host_tx
+        .write_all(b"reading the GPS receiver, any key stops\r\n")
+        .await?;
+    host_tx.flush().await?;
+
+    // u-blox receivers add NAV-PVT to their output; others ignore it.
+    let mut request = Vec::new();
+    ubx::encode_set_rate(ubx::CLASS_NAV, ubx::NAV_PVT, 1, &mut request);
+    if let Err(e) = tx.write_all(&request).await {
+        warn!("gps: UART write failed: {}", defmt::Debug2Format(&e));
+    }
+
+    let result = monitor(rx, host_rx, host_tx, pps, command.raw).await;
+
+    if let Err(e) = rx.set_config(&restore.into()) {
+        warn!(
+            "gps: failed to restore UART config: {}",
+            defmt::Debug2Format(&e)
+        );
+    }
+    result?;
+    host_tx.write_all(b"OK\r\n").await?;
+    host_tx.flush().await
+}
+
+async fn monitor<HR: Read, HW: Write>(
+    rx: &mut Tap<BufferedUartRx<'static, USART1>>,
+    host_rx: &mut HR,
+    host_tx: &mut HW,
+    pps: &mut ExtiInput<'static>,
+    raw: bool,
+) -> Result<(), HW::Error> {
+    let mut decoder = stream::Decoder::new();
+    let mut fix = Fix::default();
+    let mut rtc_set = false;
+    let mut text = String::new();
+    let mut buf = [0u8; 64];
+    let mut key = [0u8];
+    let mut report = now_ms() + REPORT_MS;
+    loop {
+        text.clear();
+        match select4(
+            rx.read(&mut buf),
+            pps.wait_for_rising_edge(),
+            wait_until(Some(report)),
+            host_rx.read(&mut key),
+        )
+        .await
+        {
+            Either4::First(Ok(n)) => {
+                let uptime_us = Instant::now().as_micros();
+                for frame in buf[..n].iter().filter_map(|&b| decoder.push(b)) {
+                    handle(&mut fix, &frame, uptime_us);
+                    if let (true, stream::Frame::Nmea(line, _)) = (raw, &frame) {
+                        let _ = write!(text, "{line}\r\n");
+                    }
+                }
+            }
+            Either4::First(Err(e)) => warn!("gps: UART read error: {}", defmt::Debug2Format(&e)),
+            // Late by the interrupt latency and whatever else the executor is running.
+            Either4::Second(()) => {
+                let uptime_us = Instant::now().as_micros();
+                let second = update(|discipline| {
+                    let second = discipline.second_at(uptime_us);
+                    discipline.pulse(uptime_us);
+                    second
+                });
+                // From then on `clock` keeps it, as its drift is only seen if it's left to run.
+                if let Some(second) = second.filter(|_| !rtc_set) {
+                    match rtc::internal::set(&second) {
+                        Ok(()) => {
+                            info!("gps: RTC set to {}", defmt::Debug2Format(&second));
+                            rtc_set = true;
+                        }
+                        Err(e) => warn!("gps: RTC set failed: {}", e.as_str()),
+                    }
+                }
+            }
+            Either4::Third(()) => {
+                report += REPORT_MS;
+                let discipline = discipline();
+                let _ = write!(text, "{fix}");
+                match discipline.anchor() {
+                    Some(anchor) if anchor.pps => {
+                        let ppb = discipline.drift_ppb();
+                        let sign = if ppb < 0 { "-" } else { "+" };
+                        let ppb = ppb.unsigned_abs();
+                        let _ = write!(text, ", PPS, {sign}{}.{:03} ppm", ppb / 1000, ppb % 1000);
+                    }
+                    Some(_) => text.push_str(", no PPS"),
+                    None => {}
+                }
+                text.push_str("\r\n");
+            }
+            Either4::Fourth(_) => return Ok(()),
+        }
+        host_tx.write_all(text.as_bytes()).await?;
+    }
+}
+
+/// Merge a frame into the fix, and anchor the mapping at the start of each epoch.
+fn handle(fix: &mut Fix, frame: &stream::Frame, uptime_us: u64) {
+    let epoch = match frame {
+        stream::Frame::Nmea(_, Ok(sentence)) => {
+            fix.update(sentence);
+            matches!(sentence, Sentence::Rmc(rmc) if rmc.valid)
+        }
+        stream::Frame::Nmea(line, Err(nmea::Error::Unsupported)) => {
+            defmt::trace!("gps: skipped {}", line.as_str());
+            false
+        }
+        stream::Frame::Nmea(line, Err(e)) => {
+            warn!("gps: {} in {}", e.as_str(), line.as_str());
+            false
+        }
+        stream::Frame::Ubx(Ok(message)) => match NavPvt::from_message(message) {
+            Some(pvt) => {
+                fix.update_pvt(&pvt);
+                pvt.time_resolved
+            }
+            None => false,
+        },
+        stream::Frame::Ubx(Err(e)) => {
+            warn!("gps: {}", e.as_str());
+            false
+        }
+    };
+    if let (true, Some(time)) = (epoch, fix.time) {
+        update(|discipline| discipline.solution(uptime_us, time));
+    }
+}
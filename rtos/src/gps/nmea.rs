@@ -0,0 +1,520 @@
+//! NMEA 0183 sentences of a GNSS receiver: GGA, RMC, GSA, GSV and VTG, from any talker (`GP`,
+//! `GN`, `GL`, `GA`, `GB`, `BD`).
+//!
+//! Values are kept in fixed point, in the units of the UBX protocol where there is one, so both
+//! protocols feed the same [`Fix`](super::fix::Fix).
+
+use chrono::{NaiveDate, NaiveTime};
+
+/// Longest sentence, `$` to `\n`.
+pub const MAX_SENTENCE: usize = 82;
+/// Satellites in one GSV sentence.
+pub const GSV_SATELLITES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Missing or wrong `*hh` checksum.
+    Checksum,
+    /// A field that doesn't parse.
+    Format,
+    /// A sentence type we don't decode.
+    Unsupported,
+}
+
+impl Error {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::Checksum => "checksum",
+            Error::Format => "format",
+            Error::Unsupported => "unsupported",
+        }
+    }
+}
+
+/// GGA position fix indicator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    Invalid,
+    Gps,
+    Differential,
+    Pps,
+    RtkFixed,
+    RtkFloat,
+    DeadReckoning,
+    Manual,
+    Simulator,
+}
+
+/// Latitude and longitude in 1e-7 degrees, north and east positive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub latitude: i32,
+    pub longitude: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gga {
+    pub time: Option<NaiveTime>,
+    pub position: Option<Position>,
+    pub quality: Quality,
+    pub satellites: u8,
+    /// In hundredths.
+    pub hdop: Option<u16>,
+    /// Above mean sea level, in mm.
+    pub altitude: Option<i32>,
+    /// Geoid height above the ellipsoid, in mm.
+    pub separation: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rmc {
+    pub time: Option<NaiveTime>,
+    pub date: Option<NaiveDate>,
+    /// `A`ctive rather than `V`oid.
+    pub valid: bool,
+    pub position: Option<Position>,
+    /// Over ground, in mm/s.
+    pub speed: Option<u32>,
+    /// True course over ground, in 1e-5 degrees.
+    pub course: Option<i32>,
+}
+
+/// GSA fix type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    None,
+    Fix2d,
+    Fix3d,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gsa {
+    /// Set by the receiver rather than forced by the host.
+    pub automatic: bool,
+    pub dimension: Dimension,
+    /// Satellites used in the solution.
+    pub satellites: heapless::Vec<u8, 12>,
+    /// Dilutions of precision, in hundredths.
+    pub pdop: Option<u16>,
+    pub hdop: Option<u16>,
+    pub vdop: Option<u16>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Satellite {
+    pub id: u8,
+    /// In degrees.
+    pub elevation: Option<u8>,
+    pub azimuth: Option<u16>,
+    /// Carrier to noise ratio in dBHz, `None` while not tracked.
+    pub snr: Option<u8>,
+}
+
+/// One sentence of a GSV group, which together list the satellites in view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gsv {
+    pub messages: u8,
+    pub message: u8,
+    pub in_view: u8,
+    pub satellites: heapless::Vec<Satellite, GSV_SATELLITES>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vtg {
+    /// True course over ground, in 1e-5 degrees.
+    pub course: Option<i32>,
+    /// Over ground, in mm/s.
+    pub speed: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sentence {
+    Gga(Gga),
+    Rmc(Rmc),
+    Gsa(Gsa),
+    Gsv(Gsv),
+    Vtg(Vtg),
+}
+
+/// XOR of the bytes between `$` and `*`.
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, b| sum ^ b)
+}
+
+impl Sentence {
+    /// Parse one sentence, with or without its line ending.
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let body = line.strip_prefix('$').ok_or(Error::Format)?;
+        let (body, sum) = body.split_once('*').ok_or(Error::Checksum)?;
+        let sum = u8::from_str_radix(sum, 16).map_err(|_| Error::Checksum)?;
+        if checksum(body.as_bytes()) != sum {
+            return Err(Error::Checksum);
+        }
+
+        let mut fields = body.split(',');
+        let address = fields.next().ok_or(Error::Format)?;
+        if address.len() != 5 || !address.is_ascii() {
+            return Err(Error::Format);
+        }
+        let mut fields = Fields(fields);
+        let sentence = match &address[2..] {
+            "GGA" => Sentence::Gga(Gga {
+                time: fields.time()?,
+                position: fields.position()?,
+                quality: match fields.next() {
+                    "" | "0" => Quality::Invalid,
+                    "1" => Quality::Gps,
+                    "2" => Quality::Differential,
+                    "3" => Quality::Pps,
+                    "4" => Quality::RtkFixed,
+                    "5" => Quality::RtkFloat,
+                    "6" => Quality::DeadReckoning,
+                    "7" => Quality::Manual,
+                    "8" => Quality::Simulator,
+                    _ => return Err(Error::Format),
+                },
+                satellites: fields.number()?.unwrap_or(0),
+                hdop: fields.decimal(2)?,
+                altitude: fields.meters()?,
+                separation: fields.meters()?,
+            }),
+            "RMC" => {
+                let time = fields.time()?;
+                let valid = fields.next() == "A";
+                let position = fields.position()?;
+                let speed = fields.knots()?;
+                let course = fields.decimal(5)?;
+                let date = fields.date()?;
+                Sentence::Rmc(Rmc {
+                    time,
+                    date,
+                    valid,
+                    position,
+                    speed,
+                    course,
+                })
+            }
+            "GSA" => {
+                let automatic = fields.next() == "A";
+                let dimension = match fields.next() {
+                    "" | "1" => Dimension::None,
+                    "2" => Dimension::Fix2d,
+                    "3" => Dimension::Fix3d,
+                    _ => return Err(Error::Format),
+                };
+                let mut satellites = heapless::Vec::new();
+                for _ in 0..12 {
+                    if let Some(id) = fields.number()? {
+                        // Twelve fields, twelve slots.
+                        let _ = satellites.push(id);
+                    }
+                }
+                Sentence::Gsa(Gsa {
+                    automatic,
+                    dimension,
+                    satellites,
+                    pdop: fields.decimal(2)?,
+                    hdop: fields.decimal(2)?,
+                    vdop: fields.decimal(2)?,
+                })
+            }
+            "GSV" => {
+                let messages = fields.number()?.ok_or(Error::Format)?;
+                let message = fields.number()?.ok_or(Error::Format)?;
+                let in_view = fields.number()?.unwrap_or(0);
+                let mut satellites = heapless::Vec::new();
+                // Four satellites at most, then an optional signal ID from NMEA 4.10.
+                while fields.remaining() >= 4 && !satellites.is_full() {
+                    let id = fields.number()?;
+                    let satellite = Satellite {
+                        id: id.unwrap_or(0),
+                        elevation: fields.number()?,
+                        azimuth: fields.number()?,
+                        snr: fields.number()?,
+                    };
+                    if id.is_some() {
+                        let _ = satellites.push(satellite);
+                    }
+                }
+                Sentence::Gsv(Gsv {
+                    messages,
+                    message,
+                    in_view,
+                    satellites,
+                })
+            }
+            "VTG" => {
+                let course = fields.decimal(5)?;
+                fields.next(); // T
+                fields.next(); // Magnetic course.
+                fields.next(); // M
+                Sentence::Vtg(Vtg {
+                    course,
+                    speed: fields.knots()?,
+                })
+            }
+            _ => return Err(Error::Unsupported),
+        };
+        Ok(sentence)
+    }
+}
+
+struct Fields<'a>(core::str::Split<'a, char>);
+
+impl<'a> Fields<'a> {
+    /// The next field, empty past the end as receivers drop trailing empty fields.
+    fn next(&mut self) -> &'a str {
+        self.0.next().unwrap_or("")
+    }
+
+    fn remaining(&self) -> usize {
+        self.0.clone().count()
+    }
+
+    fn number<T: core::str::FromStr>(&mut self) -> Result<Option<T>, Error> {
+        match self.next() {
+            "" => Ok(None),
+            field => field.parse().map(Some).map_err(|_| Error::Format),
+        }
+    }
+
+    fn decimal<T: TryFrom<i64>>(&mut self, places: u32) -> Result<Option<T>, Error> {
+        match self.next() {
+            "" => Ok(None),
+            field => decimal(field, places)
+                .and_then(|v| T::try_from(v).ok())
+                .map(Some)
+                .ok_or(Error::Format),
+        }
+    }
+
+    /// A value in meters, followed by its `M` unit field, in mm.
+    fn meters(&mut self) -> Result<Option<i32>, Error> {
+        let value = self.decimal(3)?;
+        self.next();
+        Ok(value)
+    }
+
+    /// A speed in knots, in mm/s.
+    fn knots(&mut self) -> Result<Option<u32>, Error> {
+        Ok(self
+            .decimal::<u32>(3)?
+            .map(|knots| (knots as u64 * 1852 / 3600) as u32))
+    }
+
+    /// `hhmmss.ss`.
+    fn time(&mut self) -> Result<Option<NaiveTime>, Error> {
+        let field = self.next();
+        if field.is_empty() {
+            return Ok(None);
+        }
+        let (hms, fraction) = field.split_once('.').unwrap_or((field, ""));
+        if hms.len() != 6 || !field.is_ascii() {
+            return Err(Error::Format);
+        }
+        let part = |range: core::ops::Range<usize>| hms[range].parse::<u32>().ok();
+        let milli = match fraction {
+            "" => Some(0),
+            fraction => decimal(&["0.", fraction].concat(), 3).map(|v| v as u32),
+        };
+        match (part(0..2), part(2..4), part(4..6), milli) {
+            (Some(h), Some(m), Some(s), Some(milli)) => {
+                NaiveTime::from_hms_milli_opt(h, m, s, milli)
+                    .map(Some)
+                    .ok_or(Error::Format)
+            }
+            _ => Err(Error::Format),
+        }
+    }
+
+    /// `ddmmyy`, in this century.
+    fn date(&mut self) -> Result<Option<NaiveDate>, Error> {
+        let field = self.next();
+        if field.is_empty() {
+            return Ok(None);
+        }
+        if field.len() != 6 || !field.is_ascii() {
+            return Err(Error::Format);
+        }
+        let part = |range: core::ops::Range<usize>| field[range].parse::<u32>().ok();
+        match (part(0..2), part(2..4), part(4..6)) {
+            (Some(d), Some(m), Some(y)) => NaiveDate::from_ymd_opt(2000 + y as i32, m, d)
+                .map(Some)
+                .ok_or(Error::Format),
+            _ => Err(Error::Format),
+        }
+    }
+
+    /// Latitude `ddmm.mmmm`, `N`/`S`, longitude `dddmm.mmmm`, `E`/`W`.
+    fn position(&mut self) -> Result<Option<Position>, Error> {
+        let latitude = angle(self.next(), self.next(), 'N', 'S')?;
+        let longitude = angle(self.next(), self.next(), 'E', 'W')?;
+        match (latitude, longitude) {
+            (Some(latitude), Some(longitude)) => Ok(Some(Position {
+                latitude,
+                longitude,
+            })),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Degrees and decimal minutes, and the hemisphere, in 1e-7 degrees.
+fn angle(
+    value: &str,
+    hemisphere: &str,
+    positive: char,
+    negative: char,
+) -> Result<Option<i32>, Error> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let minutes = decimal(value, 7).ok_or(Error::Format)?;
+    if minutes < 0 {
+        return Err(Error::Format);
+    }
+    let degrees = minutes / 1_000_000_000;
+    let minutes = minutes % 1_000_000_000;
+    let angle = (degrees * 10_000_000 + minutes / 60) as i32;
+    match hemisphere.chars().next() {
+        Some(c) if c == positive => Ok(Some(angle)),
+        Some(c) if c == negative => Ok(Some(-angle)),
+        _ => Err(Error::Format),
+    }
+}
+
+/// A decimal number scaled by `10^places`; further digits are dropped.
+fn decimal(field: &str, places: u32) -> Option<i64> {
+    let (negative, field) = match field.strip_prefix('-') {
+        Some(field) => (true, field),
+        None => (false, field),
+    };
+    let (integer, fraction) = field.split_once('.').unwrap_or((field, ""));
+    if integer.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    let mut value: i64 = 0;
+    for c in integer.chars() {
+        value = value.checked_mul(10)?.checked_add(c.to_digit(10)? as i64)?;
+    }
+    let mut digits = fraction.chars();
+    for _ in 0..places {
+        let digit = match digits.next() {
+            Some(c) => c.to_digit(10)?,
+            None => 0,
+        };
+        value = value.checked_mul(10)?.checked_add(digit as i64)?;
+    }
+    if !digits.all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimals() {
+        assert_eq!(decimal("1.01", 2), Some(101));
+        assert_eq!(decimal("499.6", 3), Some(499_600));
+        assert_eq!(decimal("-12.3456", 2), Some(-1234));
+        assert_eq!(decimal(".5", 1), Some(5));
+        assert_eq!(decimal("1.2.3", 1), None);
+        assert_eq!(decimal("x", 1), None);
+        assert_eq!(decimal("", 1), None);
+    }
+
+    #[test]
+    fn parses_sentences() {
+        // The examples of the u-blox protocol specification.
+        assert_eq!(
+            Sentence::parse(
+                "$GPGGA,092725.00,4717.11399,N,00833.91590,E,1,08,1.01,499.6,M,48.0,M,,*5B\r\n"
+            ),
+            Ok(Sentence::Gga(Gga {
+                time: NaiveTime::from_hms_opt(9, 27, 25),
+                position: Some(Position {
+                    latitude: 472_852_331,
+                    longitude: 85_652_650,
+                }),
+                quality: Quality::Gps,
+                satellites: 8,
+                hdop: Some(101),
+                altitude: Some(499_600),
+                separation: Some(48_000),
+            }))
+        );
+        assert_eq!(
+            Sentence::parse(
+                "$GPRMC,083559.00,A,4717.11437,N,00833.91522,E,0.004,77.52,091202,,,A*57"
+            ),
+            Ok(Sentence::Rmc(Rmc {
+                time: NaiveTime::from_hms_opt(8, 35, 59),
+                date: NaiveDate::from_ymd_opt(2002, 12, 9),
+                valid: true,
+                position: Some(Position {
+                    latitude: 472_852_395,
+                    longitude: 85_652_536,
+                }),
+                speed: Some(2),
+                course: Some(7_752_000),
+            }))
+        );
+        let Ok(Sentence::Gsa(gsa)) =
+            Sentence::parse("$GPGSA,A,3,23,29,07,08,09,18,26,28,,,,,1.94,1.18,1.54*0D")
+        else {
+            panic!("GSA");
+        };
+        assert_eq!(gsa.dimension, Dimension::Fix3d);
+        assert_eq!(gsa.satellites, [23, 29, 7, 8, 9, 18, 26, 28]);
+        assert_eq!(
+            (gsa.pdop, gsa.hdop, gsa.vdop),
+            (Some(194), Some(118), Some(154))
+        );
+        let Ok(Sentence::Gsv(gsv)) =
+            Sentence::parse("$GPGSV,3,1,10,23,38,230,44,29,71,156,47,07,29,116,41,08,09,081,36*7F")
+        else {
+            panic!("GSV");
+        };
+        assert_eq!((gsv.messages, gsv.message, gsv.in_view), (3, 1, 10));
+        assert_eq!(
+            gsv.satellites[3],
+            Satellite {
+                id: 8,
+                elevation: Some(9),
+                azimuth: Some(81),
+                snr: Some(36),
+            }
+        );
+        assert_eq!(
+            Sentence::parse("$GPVTG,77.52,T,,M,0.004,N,0.008,K,A*06"),
+            Ok(Sentence::Vtg(Vtg {
+                course: Some(7_752_000),
+                speed: Some(2),
+            }))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_sentences() {
+        assert_eq!(
+            Sentence::parse("$GPVTG,77.52,T,,M,0.004,N,0.008,K,A*07"),
+            Err(Error::Checksum)
+        );
+        assert_eq!(
+            Sentence::parse("$GPVTG,77.52,T,,M,0.004,N,0.008,K,A"),
+            Err(Error::Checksum)
+        );
+        assert_eq!(Sentence::parse("$GPTXT,01*00"), Err(Error::Checksum));
+        assert_eq!(Sentence::parse("$GPZDA*48"), Err(Error::Unsupported));
+        assert_eq!(
+            Sentence::parse("$GPGGA,0927,,,,,0,00,,,,,,,*6A"),
+            Err(Error::Format)
+        );
+    }
+}
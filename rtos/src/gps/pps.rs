@@ -0,0 +1,204 @@
+//! Uptime to UTC mapping, disciplined by the receiver's pulse per second.
+//!
+//! The pulse marks the start of a UTC second, and the receiver names that second in the
+//! sentences or NAV-PVT that follow it, a few hundred ms later. Pairing the two gives an anchor
+//! good to the interrupt latency; the spacing of successive anchors gives the drift of our clock.
+
+use chrono::{Duration, NaiveDateTime, Timelike};
+
+/// Time from a pulse to the solution naming its second, at most.
+pub const MAX_LATENCY_US: u64 = 950_000;
+/// Uptime runs off the HSI, trimmed to within about 1%.
+pub const MAX_DRIFT_PPB: i64 = 10_000_000;
+/// An interval this far from the drift so far is a glitch on the pulse line, not drift.
+pub const MAX_STEP_PPB: i64 = 100_000;
+/// How far a predicted pulse or a solution's epoch may be from a whole second.
+pub const SECOND_TOLERANCE_US: i64 = 1_000;
+
+const US_PER_S: i64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Anchor {
+    pub uptime_us: u64,
+    pub utc: NaiveDateTime,
+    /// Taken at a pulse, rather than at the arrival of a solution.
+    pub pps: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Discipline {
+    edge: Option<u64>,
+    anchor: Option<Anchor>,
+    /// Our clock's rate error, positive when it runs fast, in parts per billion.
+    drift_ppb: i64,
+    /// Pulse pairs the drift is averaged over, up to a limit.
+    intervals: u32,
+}
+
+impl Discipline {
+    pub const fn new() -> Self {
+        Self {
+            edge: None,
+            anchor: None,
+            drift_ppb: 0,
+            intervals: 0,
+        }
+    }
+
+    /// A pulse's rising edge, at `uptime_us`.
+    pub fn pulse(&mut self, uptime_us: u64) {
+        self.edge = Some(uptime_us);
+    }
+
+    /// A solution for `utc` received at `uptime_us`. Returns the anchor taken, if any.
+    pub fn solution(&mut self, uptime_us: u64, utc: NaiveDateTime) -> Option<Anchor> {
+        let edge = self
+            .edge
+            .filter(|&edge| edge <= uptime_us && uptime_us - edge <= MAX_LATENCY_US);
+        let anchor = match (edge, whole_second(utc)) {
+            // Only the solution for the pulse's own second, once.
+            (Some(edge), Some(utc)) => {
+                self.edge = None;
+                if let Some(previous) = self.anchor.filter(|anchor| anchor.pps) {
+                    if !self.measure(&previous, edge, utc) {
+                        return None;
+                    }
+                }
+                Anchor {
+                    uptime_us: edge,
+                    utc,
+                    pps: true,
+                }
+            }
+            // Without pulses, the arrival time will do, late by the receiver's latency.
+            (None, _) if !self.anchor.is_some_and(|anchor| anchor.pps) => Anchor {
+                uptime_us,
+                utc,
+                pps: false,
+            },
+            _ => return None,
+        };
+        self.anchor = Some(anchor);
+        Some(anchor)
+    }
+
+    /// Fold in the interval since `previous`; false if it's inconsistent with the drift so far.
+    fn measure(&mut self, previous: &Anchor, edge: u64, utc: NaiveDateTime) -> bool {
+        let expected = match (utc - previous.utc).num_microseconds() {
+            Some(expected) if expected > 0 => expected,
+            _ => return false,
+        };
+        let measured = edge.saturating_sub(previous.uptime_us) as i64;
+        let drift_ppb = ((measured - expected) as i128 * 1_000_000_000 / expected as i128) as i64;
+        if drift_ppb.abs() > MAX_DRIFT_PPB
+            || (self.intervals > 0 && (drift_ppb - self.drift_ppb).abs() > MAX_STEP_PPB)
+        {
+            return false;
+        }
+        // A running mean at first, then an exponential average over the last 16.
+        self.intervals = (self.intervals + 1).min(16);
+        self.drift_ppb += (drift_ppb - self.drift_ppb) / self.intervals as i64;
+        true
+    }
+
+    pub fn anchor(&self) -> Option<Anchor> {
+        self.anchor
+    }
+
+    pub fn drift_ppb(&self) -> i64 {
+        self.drift_ppb
+    }
+
+    /// UTC at `uptime_us`, which may be before the anchor.
+    pub fn utc(&self, uptime_us: u64) -> Option<NaiveDateTime> {
+        let anchor = self.anchor?;
+        let elapsed = uptime_us as i64 - anchor.uptime_us as i64;
+        let correction = (elapsed as i128 * self.drift_ppb as i128 / 1_000_000_000) as i64;
+        Some(anchor.utc + Duration::microseconds(elapsed - correction))
+    }
+
+    /// The UTC second starting at `uptime_us`, a pulse, if the mapping puts it at one.
+    pub fn second_at(&self, uptime_us: u64) -> Option<NaiveDateTime> {
+        if !self.anchor?.pps {
+            return None;
+        }
+        whole_second(self.utc(uptime_us)?)
+    }
+}
+
+/// `utc` rounded to the second, if it's that close to one.
+fn whole_second(utc: NaiveDateTime) -> Option<NaiveDateTime> {
+    let second = utc.with_nanosecond(0)?;
+    let offset = (utc - second).num_microseconds()?;
+    if offset <= SECOND_TOLERANCE_US {
+        Some(second)
+    } else if US_PER_S - offset <= SECOND_TOLERANCE_US {
+        Some(second + Duration::seconds(1))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn utc(s: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 5, 19)
+            .unwrap()
+            .and_hms_opt(10, 15, s)
+            .unwrap()
+    }
+
+    #[test]
+    fn coarse_without_pulses() {
+        let mut discipline = Discipline::new();
+        assert_eq!(discipline.utc(0), None);
+        let anchor = discipline.solution(5_300_000, utc(32)).unwrap();
+        assert!(!anchor.pps);
+        assert_eq!(discipline.utc(6_300_000), Some(utc(33)));
+        assert_eq!(discipline.second_at(6_300_000), None);
+    }
+
+    #[test]
+    fn disciplined_by_pulses() {
+        let mut discipline = Discipline::new();
+        // Our clock runs 50 ppm fast: a second lasts 1_000_050 us of uptime.
+        let mut edge = 10_000_000;
+        for s in 30..40 {
+            discipline.pulse(edge);
+            // The solution for the second arrives 200 ms later, twice.
+            assert!(discipline
+                .solution(edge + 200_000, utc(s))
+                .is_some_and(|a| a.pps));
+            assert_eq!(discipline.solution(edge + 250_000, utc(s)), None);
+            edge += 1_000_050;
+        }
+        assert_eq!(discipline.drift_ppb(), 50_000);
+        // A second after the last anchor the next pulse lands on a whole second.
+        assert_eq!(discipline.second_at(edge), Some(utc(40)));
+        assert_eq!(discipline.second_at(edge - 1_050), None);
+        assert_eq!(
+            discipline.utc(edge + 500_025),
+            Some(utc(40) + Duration::milliseconds(500))
+        );
+
+        // A pulse without its solution, and a late solution, don't move the anchor.
+        discipline.pulse(edge);
+        assert_eq!(discipline.solution(edge + 960_000, utc(40)), None);
+        assert_eq!(discipline.anchor().unwrap().utc, utc(39));
+        // Nor does a glitch on the pulse line.
+        edge += 1_000_050;
+        discipline.pulse(edge + 400_000);
+        assert_eq!(discipline.solution(edge + 500_000, utc(41)), None);
+        assert_eq!(discipline.anchor().unwrap().utc, utc(39));
+        // The missed pulses don't look like drift.
+        edge += 1_000_050;
+        discipline.pulse(edge);
+        assert!(discipline.solution(edge + 200_000, utc(42)).is_some());
+        assert_eq!(discipline.drift_ppb(), 50_000);
+    }
+}
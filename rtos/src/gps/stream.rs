@@ -0,0 +1,175 @@
+//! Splits a receiver's output, NMEA sentences and UBX messages interleaved, into frames.
+
+use super::{
+    nmea::{self, Sentence, MAX_SENTENCE},
+    ubx::{self, Message, MAX_PAYLOAD, SYNC},
+};
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UbxError {
+    Checksum,
+    /// Longer than [`MAX_PAYLOAD`].
+    Length,
+}
+
+impl UbxError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UbxError::Checksum => "UBX checksum",
+            UbxError::Length => "UBX length",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    /// With the line it came from, for echoing to the host.
+    Nmea(
+        heapless::String<MAX_SENTENCE>,
+        Result<Sentence, nmea::Error>,
+    ),
+    Ubx(Result<Message, UbxError>),
+}
+
+enum State {
+    Idle,
+    Sentence,
+    Sync,
+    /// Class, ID and length.
+    Header,
+    Payload(usize),
+}
+
+pub struct Decoder {
+    state: State,
+    line: heapless::String<MAX_SENTENCE>,
+    buf: Vec<u8>,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            state: State::Idle,
+            line: heapless::String::new(),
+            buf: Vec::new(),
+        }
+    }
+
+    /// Feed one byte; bytes outside a frame are dropped.
+    pub fn push(&mut self, byte: u8) -> Option<Frame> {
+        match self.state {
+            State::Idle => self.start(byte),
+            State::Sentence => match byte {
+                b'\n' => {
+                    self.state = State::Idle;
+                    let line = core::mem::take(&mut self.line);
+                    let sentence = Sentence::parse(&line);
+                    return Some(Frame::Nmea(line, sentence));
+                }
+                b'\r' => {}
+                // A receiver restarting, or UBX in the middle of a broken sentence.
+                b'$' | 0xb5 => self.start(byte),
+                _ => {
+                    if !byte.is_ascii() || self.line.push(byte as char).is_err() {
+                        self.state = State::Idle;
+                    }
+                }
+            },
+            State::Sync => {
+                if byte == SYNC[1] {
+                    self.buf.clear();
+                    self.state = State::Header;
+                } else {
+                    self.start(byte);
+                }
+            }
+            State::Header => {
+                self.buf.push(byte);
+                if self.buf.len() == 4 {
+                    let len = u16::from_le_bytes([self.buf[2], self.buf[3]]) as usize;
+                    if len > MAX_PAYLOAD {
+                        self.state = State::Idle;
+                        return Some(Frame::Ubx(Err(UbxError::Length)));
+                    }
+                    self.state = State::Payload(len);
+                }
+            }
+            State::Payload(len) => {
+                self.buf.push(byte);
+                // Header, payload and checksum.
+                if self.buf.len() == 4 + len + 2 {
+                    self.state = State::Idle;
+                    let (data, sum) = self.buf.split_at(4 + len);
+                    if ubx::checksum(data) != sum {
+                        return Some(Frame::Ubx(Err(UbxError::Checksum)));
+                    }
+                    return Some(Frame::Ubx(Ok(Message {
+                        class: data[0],
+                        id: data[1],
+                        payload: data[4..].to_vec(),
+                    })));
+                }
+            }
+        }
+        None
+    }
+
+    fn start(&mut self, byte: u8) {
+        self.state = match byte {
+            b'$' => {
+                self.line.clear();
+                // Can't fail on an empty line.
+                let _ = self.line.push('$');
+                State::Sentence
+            }
+            b if b == SYNC[0] => State::Sync,
+            _ => State::Idle,
+        };
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_mixed_stream() {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"GSV,1,1*00\r\n$GPVTG,77.52,T,,M,0.004,N,0.008,K,A*06\r\n");
+        ubx::encode(ubx::CLASS_ACK, ubx::ACK_ACK, &[0x06, 0x01], &mut data);
+        data.extend_from_slice(b"$GPVTG,77.52,T,,M,0.004,N,0.008,K,A*07\r\n$GPVTG,77.");
+        ubx::encode(ubx::CLASS_ACK, ubx::ACK_NAK, &[0x06, 0x01], &mut data);
+        let last = data.len() - 1;
+        data[last] ^= 1;
+
+        let mut decoder = Decoder::new();
+        let frames: Vec<Frame> = data.iter().filter_map(|&b| decoder.push(b)).collect();
+        assert_eq!(frames.len(), 4);
+        assert!(
+            matches!(&frames[0], Frame::Nmea(line, Ok(Sentence::Vtg(_))) if line.starts_with("$GPVTG"))
+        );
+        assert_eq!(
+            frames[1],
+            Frame::Ubx(Ok(Message {
+                class: ubx::CLASS_ACK,
+                id: ubx::ACK_ACK,
+                payload: [0x06, 0x01].to_vec(),
+            }))
+        );
+        assert!(matches!(
+            frames[2],
+            Frame::Nmea(_, Err(nmea::Error::Checksum))
+        ));
+        // The broken sentence is dropped for the UBX message.
+        assert_eq!(frames[3], Frame::Ubx(Err(UbxError::Checksum)));
+    }
+}
@@ -0,0 +1,176 @@
+//! u-blox UBX binary protocol: `B5 62`, class, ID, little-endian length, payload and an 8-bit
+//! Fletcher checksum over everything after the sync characters.
+
+use alloc::vec::Vec;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+pub const SYNC: [u8; 2] = [0xb5, 0x62];
+/// Largest payload accepted; NAV-SAT with 64 satellites fits.
+pub const MAX_PAYLOAD: usize = 1024;
+
+pub const CLASS_NAV: u8 = 0x01;
+pub const CLASS_ACK: u8 = 0x05;
+pub const CLASS_CFG: u8 = 0x06;
+pub const NAV_PVT: u8 = 0x07;
+pub const ACK_NAK: u8 = 0x00;
+pub const ACK_ACK: u8 = 0x01;
+pub const CFG_MSG: u8 = 0x01;
+
+/// `CK_A` and `CK_B`.
+pub fn checksum(data: &[u8]) -> [u8; 2] {
+    data.iter().fold([0u8, 0u8], |[a, b], &byte| {
+        let a = a.wrapping_add(byte);
+        [a, b.wrapping_add(a)]
+    })
+}
+
+/// Append a message.
+pub fn encode(class: u8, id: u8, payload: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(&SYNC);
+    let start = out.len();
+    out.extend_from_slice(&[class, id]);
+    out.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    out.extend_from_slice(payload);
+    let sum = checksum(&out[start..]);
+    out.extend_from_slice(&sum);
+}
+
+/// Append the CFG-MSG that makes the receiver send `class`/`id` every `rate` navigation
+/// solutions on its current port, 0 to stop it.
+pub fn encode_set_rate(class: u8, id: u8, rate: u8, out: &mut Vec<u8>) {
+    encode(CLASS_CFG, CFG_MSG, &[class, id, rate], out);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub class: u8,
+    pub id: u8,
+    pub payload: Vec<u8>,
+}
+
+/// NAV-PVT fix type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixType {
+    None,
+    DeadReckoning,
+    Fix2d,
+    Fix3d,
+    GnssDeadReckoning,
+    TimeOnly,
+}
+
+/// Navigation position, velocity and time solution, NAV-PVT. Units as on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NavPvt {
+    /// GPS time of week of the epoch, in ms.
+    pub itow: u32,
+    /// UTC, when both date and time are valid.
+    pub time: Option<NaiveDateTime>,
+    /// UTC is fully resolved, not just a guess from the almanac.
+    pub time_resolved: bool,
+    /// Time accuracy estimate, in ns.
+    pub time_accuracy: u32,
+    pub fix_type: FixType,
+    /// Within the DOP and accuracy masks.
+    pub fix_ok: bool,
+    pub satellites: u8,
+    /// In 1e-7 degrees.
+    pub longitude: i32,
+    pub latitude: i32,
+    /// Above the ellipsoid, in mm.
+    pub height: i32,
+    /// Above mean sea level, in mm.
+    pub height_msl: i32,
+    /// Horizontal and vertical accuracy estimates, in mm.
+    pub horizontal_accuracy: u32,
+    pub vertical_accuracy: u32,
+    /// Ground speed, in mm/s.
+    pub speed: i32,
+    /// Heading of motion, in 1e-5 degrees.
+    pub heading: i32,
+    /// Position DOP, in hundredths.
+    pub pdop: u16,
+}
+
+impl NavPvt {
+    pub const LEN: usize = 92;
+
+    pub fn from_message(message: &Message) -> Option<Self> {
+        if (message.class, message.id) != (CLASS_NAV, NAV_PVT) {
+            return None;
+        }
+        Self::decode(&message.payload)
+    }
+
+    pub fn decode(p: &[u8]) -> Option<Self> {
+        if p.len() < Self::LEN {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([p[i], p[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes([p[i], p[i + 1], p[i + 2], p[i + 3]]);
+        let i32_at = |i: usize| u32_at(i) as i32;
+
+        let valid = p[11];
+        // Valid date and valid time.
+        let time = if valid & 0x03 == 0x03 {
+            // The nanoseconds may be negative, the rest of the time rounded up.
+            NaiveDate::from_ymd_opt(u16_at(4) as i32, p[6] as u32, p[7] as u32)
+                .zip(NaiveTime::from_hms_opt(
+                    p[8] as u32,
+                    p[9] as u32,
+                    p[10] as u32,
+                ))
+                .map(|(date, time)| {
+                    date.and_time(time) + chrono::Duration::nanoseconds(i32_at(16) as i64)
+                })
+        } else {
+            None
+        };
+        Some(NavPvt {
+            itow: u32_at(0),
+            time,
+            time_resolved: valid & 0x04 != 0,
+            time_accuracy: u32_at(12),
+            fix_type: match p[20] {
+                1 => FixType::DeadReckoning,
+                2 => FixType::Fix2d,
+                3 => FixType::Fix3d,
+                4 => FixType::GnssDeadReckoning,
+                5 => FixType::TimeOnly,
+                _ => FixType::None,
+            },
+            fix_ok: p[21] & 0x01 != 0,
+            satellites: p[23],
+            longitude: i32_at(24),
+            latitude: i32_at(28),
+            height: i32_at(32),
+            height_msl: i32_at(36),
+            horizontal_accuracy: u32_at(40),
+            vertical_accuracy: u32_at(44),
+            speed: i32_at(60),
+            heading: i32_at(64),
+            pdop: u16_at(76),
+        })
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_messages() {
+        let mut out = Vec::new();
+        encode_set_rate(CLASS_NAV, NAV_PVT, 1, &mut out);
+        assert_eq!(
+            out,
+            [0xb5, 0x62, 0x06, 0x01, 0x03, 0x00, 0x01, 0x07, 0x01, 0x13, 0x51]
+        );
+        out.clear();
+        // Polling a message is sending it with no payload.
+        encode(CLASS_NAV, NAV_PVT, &[], &mut out);
+        assert_eq!(out, [0xb5, 0x62, 0x01, 0x07, 0x00, 0x00, 0x08, 0x19]);
+    }
+}
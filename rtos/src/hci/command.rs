@@ -0,0 +1,383 @@
+//! Typed HCI commands: the standard ones needed to bring a controller up and advertise or scan,
+//! and the Broadcom/Cypress vendor commands for the CYW4343W's patch RAM and baud rate.
+
+use super::packet::opcode;
+use alloc::vec::Vec;
+
+const LINK_CONTROL: u8 = 0x01;
+const CONTROLLER: u8 = 0x03;
+const INFORMATIONAL: u8 = 0x04;
+const LE: u8 = 0x08;
+const VENDOR: u8 = 0x3f;
+
+pub trait Command {
+    const OPCODE: u16;
+    /// Return parameters after the status byte.
+    type Return;
+
+    fn params(&self, out: &mut Vec<u8>);
+    /// `None` if the return parameters are too short. Commands answered with a Command Status
+    /// are given an empty slice.
+    fn parse_return(params: &[u8]) -> Option<Self::Return>;
+}
+
+/// Commands without parameters or return values.
+macro_rules! simple_command {
+    ($(#[$doc:meta])* $name:ident, $ogf:expr, $ocf:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy)]
+        pub struct $name;
+
+        impl Command for $name {
+            const OPCODE: u16 = opcode($ogf, $ocf);
+            type Return = ();
+
+            fn params(&self, _out: &mut Vec<u8>) {}
+
+            fn parse_return(_params: &[u8]) -> Option<()> {
+                Some(())
+            }
+        }
+    };
+}
+
+simple_command!(Reset, CONTROLLER, 0x0003);
+simple_command!(
+    /// Put the controller into patch download mode. It answers, then stops listening for about
+    /// 50 ms.
+    DownloadMinidriver,
+    VENDOR,
+    0x002e
+);
+
+/// A command built at runtime, e.g. a record of a firmware patch.
+#[derive(Debug, Clone, Copy)]
+pub struct Raw<'a> {
+    pub opcode: u16,
+    pub params: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Disconnect {
+    pub handle: u16,
+    pub reason: u8,
+}
+
+impl Command for Disconnect {
+    const OPCODE: u16 = opcode(LINK_CONTROL, 0x0006);
+    type Return = ();
+
+    fn params(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.handle.to_le_bytes());
+        out.push(self.reason);
+    }
+
+    fn parse_return(_params: &[u8]) -> Option<()> {
+        Some(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SetEventMask(pub u64);
+
+impl Command for SetEventMask {
+    const OPCODE: u16 = opcode(CONTROLLER, 0x0001);
+    type Return = ();
+
+    fn params(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.0.to_le_bytes());
+    }
+
+    fn parse_return(_params: &[u8]) -> Option<()> {
+        Some(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalVersion {
+    pub hci_version: u8,
+    pub hci_revision: u16,
+    pub lmp_version: u8,
+    /// Company identifier, 15 for Broadcom.
+    pub manufacturer: u16,
+    pub lmp_subversion: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReadLocalVersion;
+
+impl Command for ReadLocalVersion {
+    const OPCODE: u16 = opcode(INFORMATIONAL, 0x0001);
+    type Return = LocalVersion;
+
+    fn params(&self, _out: &mut Vec<u8>) {}
+
+    fn parse_return(params: &[u8]) -> Option<LocalVersion> {
+        let &[hci_version, r0, r1, lmp_version, m0, m1, s0, s1, ..] = params else {
+            return None;
+        };
+        Some(LocalVersion {
+            hci_version,
+            hci_revision: u16::from_le_bytes([r0, r1]),
+            lmp_version,
+            manufacturer: u16::from_le_bytes([m0, m1]),
+            lmp_subversion: u16::from_le_bytes([s0, s1]),
+        })
+    }
+}
+
+/// The public device address, least significant byte first as on the wire.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadBdAddr;
+
+impl Command for ReadBdAddr {
+    const OPCODE: u16 = opcode(INFORMATIONAL, 0x0009);
+    type Return = [u8; 6];
+
+    fn params(&self, _out: &mut Vec<u8>) {}
+
+    fn parse_return(params: &[u8]) -> Option<[u8; 6]> {
+        params.get(..6)?.try_into().ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LeSetEventMask(pub u64);
+
+impl Command for LeSetEventMask {
+    const OPCODE: u16 = opcode(LE, 0x0001);
+    type Return = ();
+
+    fn params(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.0.to_le_bytes());
+    }
+
+    fn parse_return(_params: &[u8]) -> Option<()> {
+        Some(())
+    }
+}
+
+/// Largest LE ACL payload the controller takes and how many it can buffer.
+#[derive(Debug, Clone, Copy)]
+pub struct LeReadBufferSize;
+
+impl Command for LeReadBufferSize {
+    const OPCODE: u16 = opcode(LE, 0x0002);
+    type Return = (u16, u8);
+
+    fn params(&self, _out: &mut Vec<u8>) {}
+
+    fn parse_return(params: &[u8]) -> Option<(u16, u8)> {
+        let &[l0, l1, count, ..] = params else {
+            return None;
+        };
+        Some((u16::from_le_bytes([l0, l1]), count))
+    }
+}
+
+/// Undirected advertising on all three channels from the public address.
+#[derive(Debug, Clone, Copy)]
+pub struct LeSetAdvertisingParameters {
+    /// Advertising interval range in units of 0.625 ms.
+    pub interval_min: u16,
+    pub interval_max: u16,
+    /// 0 connectable, 2 scannable, 3 non-connectable.
+    pub kind: u8,
+}
+
+impl Command for LeSetAdvertisingParameters {
+    const OPCODE: u16 = opcode(LE, 0x0006);
+    type Return = ();
+
+    fn params(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.interval_min.to_le_bytes());
+        out.extend_from_slice(&self.interval_max.to_le_bytes());
+        // Type, own address public, peer address unused, all channels, no filter.
+        out.extend_from_slice(&[self.kind, 0, 0, 0, 0, 0, 0, 0, 0, 0x07, 0]);
+    }
+
+    fn parse_return(_params: &[u8]) -> Option<()> {
+        Some(())
+    }
+}
+
+/// Advertising data, up to 31 bytes of AD structures. Longer data is truncated.
+#[derive(Debug, Clone, Copy)]
+pub struct LeSetAdvertisingData<'a>(pub &'a [u8]);
+
+impl Command for LeSetAdvertisingData<'_> {
+    const OPCODE: u16 = opcode(LE, 0x0008);
+    type Return = ();
+
+    fn params(&self, out: &mut Vec<u8>) {
+        let data = &self.0[..self.0.len().min(31)];
+        out.push(data.len() as u8);
+        out.extend_from_slice(data);
+        out.resize(out.len() + 31 - data.len(), 0);
+    }
+
+    fn parse_return(_params: &[u8]) -> Option<()> {
+        Some(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LeSetAdvertiseEnable(pub bool);
+
+impl Command for LeSetAdvertiseEnable {
+    const OPCODE: u16 = opcode(LE, 0x000a);
+    type Return = ();
+
+    fn params(&self, out: &mut Vec<u8>) {
+        out.push(self.0 as u8);
+    }
+
+    fn parse_return(_params: &[u8]) -> Option<()> {
+        Some(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LeSetScanParameters {
+    /// Send scan requests for scan responses.
+    pub active: bool,
+    /// In units of 0.625 ms.
+    pub interval: u16,
+    pub window: u16,
+}
+
+impl Command for LeSetScanParameters {
+    const OPCODE: u16 = opcode(LE, 0x000b);
+    type Return = ();
+
+    fn params(&self, out: &mut Vec<u8>) {
+        out.push(self.active as u8);
+        out.extend_from_slice(&self.interval.to_le_bytes());
+        out.extend_from_slice(&self.window.to_le_bytes());
+        // Own address public, accept all advertisers.
+        out.extend_from_slice(&[0, 0]);
+    }
+
+    fn parse_return(_params: &[u8]) -> Option<()> {
+        Some(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LeSetScanEnable {
+    pub enable: bool,
+    pub filter_duplicates: bool,
+}
+
+impl Command for LeSetScanEnable {
+    const OPCODE: u16 = opcode(LE, 0x000c);
+    type Return = ();
+
+    fn params(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[self.enable as u8, self.filter_duplicates as u8]);
+    }
+
+    fn parse_return(_params: &[u8]) -> Option<()> {
+        Some(())
+    }
+}
+
+/// Switch the controller's UART once it has answered. The host follows with its own UART.
+#[derive(Debug, Clone, Copy)]
+pub struct UpdateUartBaudRate(pub u32);
+
+impl Command for UpdateUartBaudRate {
+    const OPCODE: u16 = opcode(VENDOR, 0x0018);
+    type Return = ();
+
+    fn params(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(&self.0.to_le_bytes());
+    }
+
+    fn parse_return(_params: &[u8]) -> Option<()> {
+        Some(())
+    }
+}
+
+/// Split a `.hcd` patch file into its commands: opcode, one length byte, parameters.
+pub fn patch_records(patch: &[u8]) -> impl Iterator<Item = Option<Raw<'_>>> + '_ {
+    let mut rest = patch;
+    core::iter::from_fn(move || {
+        let &[o0, o1, len, ..] = rest else {
+            // Trailing bytes too short for a header make the patch malformed.
+            return (!core::mem::take(&mut rest).is_empty()).then_some(None);
+        };
+        let Some(params) = rest.get(3..3 + len as usize) else {
+            rest = &[];
+            return Some(None);
+        };
+        rest = &rest[3 + len as usize..];
+        Some(Some(Raw {
+            opcode: u16::from_le_bytes([o0, o1]),
+            params,
+        }))
+    })
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params<C: Command>(command: &C) -> Vec<u8> {
+        let mut out = Vec::new();
+        command.params(&mut out);
+        out
+    }
+
+    #[test]
+    fn opcodes_and_parameters() {
+        assert_eq!(Reset::OPCODE, 0x0c03);
+        assert_eq!(ReadBdAddr::OPCODE, 0x1009);
+        assert_eq!(LeSetScanEnable::OPCODE, 0x200c);
+        assert_eq!(DownloadMinidriver::OPCODE, 0xfc2e);
+        assert_eq!(
+            params(&UpdateUartBaudRate(3_000_000)),
+            [0, 0, 0xc0, 0xc6, 0x2d, 0x00]
+        );
+
+        let data = params(&LeSetAdvertisingData(&[0x02, 0x01, 0x06]));
+        assert_eq!(data.len(), 32);
+        assert_eq!(&data[..4], &[3, 0x02, 0x01, 0x06]);
+
+        assert_eq!(
+            ReadLocalVersion::parse_return(&[0x09, 0x00, 0x01, 0x09, 0x0f, 0x00, 0x0d, 0x41]),
+            Some(LocalVersion {
+                hci_version: 9,
+                hci_revision: 0x0100,
+                lmp_version: 9,
+                manufacturer: 15,
+                lmp_subversion: 0x410d,
+            })
+        );
+        assert_eq!(ReadBdAddr::parse_return(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn splits_patch_files() {
+        // Two Write RAM records and Launch RAM, as they start and end a .hcd file.
+        let patch = [
+            0x4c, 0xfc, 0x05, 0x00, 0x00, 0x20, 0x00, 0xaa, //
+            0x4c, 0xfc, 0x04, 0x04, 0x00, 0x20, 0x00, //
+            0x4e, 0xfc, 0x04, 0xff, 0xff, 0xff, 0xff,
+        ];
+        let records: Vec<_> = patch_records(&patch).map(Option::unwrap).collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].opcode, 0xfc4c);
+        assert_eq!(records[0].params, &[0x00, 0x00, 0x20, 0x00, 0xaa]);
+        assert_eq!(records[2].opcode, 0xfc4e);
+
+        let truncated: Vec<_> = patch_records(&patch[..10]).collect();
+        assert!(truncated[0].is_some());
+        assert!(truncated[1].is_none());
+        assert_eq!(truncated.len(), 2);
+        assert_eq!(patch_records(&[]).count(), 0);
+    }
+}
@@ -0,0 +1,460 @@
+use super::{
+    command::{self, patch_records, Command, LocalVersion, Raw},
+    event::{Event, Status},
+    packet::{DecodeError, Decoder, Packet},
+};
+use alloc::{collections::VecDeque, vec::Vec};
+use embassy_futures::select::{select, Either};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+/// Packets kept while waiting for a command to complete. Older ones are dropped.
+const MAX_PENDING: usize = 32;
+/// Largest payload accepted from the controller; enough for extended advertising reports and
+/// the default LE ACL size.
+const MAX_PAYLOAD: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No Command Complete or Command Status in time.
+    Timeout,
+    /// The controller answered with an error status.
+    Status(Status),
+    /// Return parameters too short for the command, or a malformed patch.
+    Malformed,
+    /// The UART couldn't be switched to the new baud rate.
+    Baudrate,
+    /// Read or write error on the UART.
+    Serial,
+}
+
+impl Error {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::Timeout => "timeout",
+            Error::Status(status) => status.as_str(),
+            Error::Malformed => "malformed",
+            Error::Baudrate => "baud rate change failed",
+            Error::Serial => "serial error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    pub command_timeout_ms: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            command_timeout_ms: 2_000,
+        }
+    }
+}
+
+/// A packet received from the controller, H4 indicator included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Received(Vec<u8>);
+
+impl Received {
+    pub fn packet(&self) -> Packet<'_> {
+        // Only built from bytes the decoder accepted.
+        Packet::parse(&self.0).unwrap()
+    }
+
+    /// The typed event, if this is an event the parser understands.
+    pub fn event(&self) -> Option<Event<'_>> {
+        match self.packet() {
+            Packet::Event { code, params } => Event::parse(code, params),
+            _ => None,
+        }
+    }
+}
+
+/// Reads whole packets off the UART.
+struct Reader<R> {
+    rx: R,
+    decoder: Decoder,
+    input: [u8; 64],
+    start: usize,
+    end: usize,
+    /// Bytes skipped because they didn't form a packet we could take.
+    discarded: usize,
+}
+
+impl<R: Read> Reader<R> {
+    /// Cancel safe: nothing is lost when the future is dropped between packets or mid-packet.
+    async fn next(&mut self) -> Result<Received, Error> {
+        loop {
+            while self.start < self.end {
+                let byte = self.input[self.start];
+                self.start += 1;
+                match self.decoder.push(byte) {
+                    Some(Ok(packet)) => {
+                        let mut bytes = Vec::new();
+                        packet.encode(&mut bytes);
+                        return Ok(Received(bytes));
+                    }
+                    Some(Err(DecodeError::UnknownIndicator(_))) => self.discarded += 1,
+                    Some(Err(DecodeError::TooLong { len, .. })) => self.discarded += len,
+                    None => {}
+                }
+            }
+
+            self.end = self
+                .rx
+                .read(&mut self.input)
+                .await
+                .map_err(|_| Error::Serial)?;
+            self.start = 0;
+            if self.end == 0 {
+                return Err(Error::Serial);
+            }
+        }
+    }
+}
+
+/// HCI over an H4 UART. One command is in flight at a time; events and data that arrive while
+/// waiting for its completion are queued for [`Hci::receive`].
+pub struct Hci<R, W, D> {
+    reader: Reader<R>,
+    tx: W,
+    delay: D,
+    config: Config,
+    pending: VecDeque<Received>,
+    dropped: usize,
+}
+
+impl<R: Read, W: Write, D: DelayNs> Hci<R, W, D> {
+    pub fn new(rx: R, tx: W, delay: D, config: Config) -> Self {
+        Self {
+            reader: Reader {
+                rx,
+                decoder: Decoder::new(MAX_PAYLOAD),
+                input: [0; 64],
+                start: 0,
+                end: 0,
+                discarded: 0,
+            },
+            tx,
+            delay,
+            config,
+            pending: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Bytes the decoder skipped and packets dropped from a full queue, since start.
+    pub fn losses(&self) -> (usize, usize) {
+        (self.reader.discarded, self.dropped)
+    }
+
+    pub async fn command<C: Command>(&mut self, command: &C) -> Result<C::Return, Error> {
+        let mut params = Vec::new();
+        command.params(&mut params);
+        let ret = self.raw(C::OPCODE, &params).await?;
+        C::parse_return(&ret).ok_or(Error::Malformed)
+    }
+
+    /// Send any command and return its return parameters after the status byte.
+    pub async fn raw(&mut self, opcode: u16, params: &[u8]) -> Result<Vec<u8>, Error> {
+        self.send(&Packet::Command { opcode, params }).await?;
+
+        let Self {
+            reader,
+            delay,
+            config,
+            pending,
+            dropped,
+            ..
+        } = self;
+        let wait = async {
+            loop {
+                let received = reader.next().await?;
+                match received.event() {
+                    Some(Event::CommandComplete {
+                        opcode: answered,
+                        params,
+                        ..
+                    }) if answered == opcode => {
+                        // A few vendor commands return nothing at all.
+                        let (status, ret) = params.split_first().unwrap_or((&0, &[]));
+                        return match Status(*status) {
+                            s if s.is_success() => Ok(ret.to_vec()),
+                            s => Err(Error::Status(s)),
+                        };
+                    }
+                    Some(Event::CommandStatus {
+                        status,
+                        opcode: answered,
+                        ..
+                    }) if answered == opcode => {
+                        return match status.is_success() {
+                            true => Ok(Vec::new()),
+                            false => Err(Error::Status(status)),
+                        };
+                    }
+                    _ => {}
+                }
+
+                if pending.len() == MAX_PENDING {
+                    pending.pop_front();
+                    *dropped += 1;
+                }
+                pending.push_back(received);
+            }
+        };
+
+        match select(wait, delay.delay_ms(config.command_timeout_ms)).await {
+            Either::First(result) => result,
+            Either::Second(()) => Err(Error::Timeout),
+        }
+    }
+
+    /// Send an ACL data packet. The caller keeps to the controller's buffer size and count.
+    pub async fn send_acl(&mut self, handle: u16, flags: u8, data: &[u8]) -> Result<(), Error> {
+        self.send(&Packet::Acl {
+            handle,
+            flags,
+            data,
+        })
+        .await
+    }
+
+    /// Whether packets queued while waiting for a command are waiting for [`Hci::receive`].
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// The next event or data packet that isn't the answer to a command.
+    pub async fn receive(&mut self) -> Result<Received, Error> {
+        match self.pending.pop_front() {
+            Some(received) => Ok(received),
+            None => self.reader.next().await,
+        }
+    }
+
+    async fn send(&mut self, packet: &Packet<'_>) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        packet.encode(&mut bytes);
+        self.tx.write_all(&bytes).await.map_err(|_| Error::Serial)?;
+        self.tx.flush().await.map_err(|_| Error::Serial)
+    }
+
+    /// Reset the controller, load `patch` (a Broadcom `.hcd` file) if given, and read its
+    /// version.
+    pub async fn init(&mut self, patch: Option<&[u8]>) -> Result<LocalVersion, Error> {
+        self.command(&command::Reset).await?;
+
+        if let Some(patch) = patch {
+            self.command(&command::DownloadMinidriver).await?;
+            self.delay.delay_ms(50).await;
+            for record in patch_records(patch) {
+                let Raw { opcode, params } = record.ok_or(Error::Malformed)?;
+                self.raw(opcode, params).await?;
+            }
+            // The patch ends with Launch RAM; the controller restarts into it.
+            self.delay.delay_ms(250).await;
+            self.command(&command::Reset).await?;
+        }
+
+        self.command(&command::ReadLocalVersion).await
+    }
+
+    /// Move the link to `baudrate`: tell the controller, then switch our side with `reconfigure`,
+    /// which returns false if the UART can't do the rate.
+    pub async fn set_baudrate(
+        &mut self,
+        baudrate: u32,
+        reconfigure: impl FnOnce(&mut R, &mut W, u32) -> bool,
+    ) -> Result<(), Error> {
+        self.command(&command::UpdateUartBaudRate(baudrate)).await?;
+        if !reconfigure(&mut self.reader.rx, &mut self.tx, baudrate) {
+            return Err(Error::Baudrate);
+        }
+        self.delay.delay_ms(10).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{rc::Rc, vec};
+    use core::{cell::RefCell, convert::Infallible, future::pending};
+    use embassy_futures::block_on;
+    use embedded_io_async::ErrorType;
+
+    /// A controller that answers every command with Command Complete, after sending whatever
+    /// `unsolicited` holds.
+    struct Controller {
+        decoder: Decoder,
+        output: VecDeque<u8>,
+        commands: Vec<(u16, Vec<u8>)>,
+        unsolicited: Vec<u8>,
+        /// Opcode to answer with an error status instead.
+        refuse: Option<u16>,
+        /// Stay silent, like a controller that is powered off.
+        silent: bool,
+    }
+
+    impl Controller {
+        fn new() -> Rc<RefCell<Self>> {
+            Rc::new(RefCell::new(Self {
+                decoder: Decoder::new(255),
+                output: VecDeque::new(),
+                commands: Vec::new(),
+                unsolicited: Vec::new(),
+                refuse: None,
+                silent: false,
+            }))
+        }
+
+        fn receive(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                let Some(Ok(Packet::Command { opcode, params })) = self.decoder.push(b) else {
+                    continue;
+                };
+                self.commands.push((opcode, params.to_vec()));
+                if self.silent {
+                    continue;
+                }
+                self.output.extend(core::mem::take(&mut self.unsolicited));
+
+                let [lo, hi] = opcode.to_le_bytes();
+                let status = if self.refuse == Some(opcode) { 0x12 } else { 0 };
+                let ret: &[u8] = match opcode {
+                    0x1001 => &[0x09, 0x00, 0x01, 0x09, 0x0f, 0x00, 0x0d, 0x41],
+                    0x1009 => &[0x66, 0x55, 0x44, 0x33, 0x22, 0x11],
+                    _ => &[],
+                };
+                let mut event = Vec::from([0x01, lo, hi, status]);
+                event.extend_from_slice(ret);
+                let mut bytes = Vec::new();
+                Packet::Event {
+                    code: 0x0e,
+                    params: &event,
+                }
+                .encode(&mut bytes);
+                self.output.extend(bytes);
+            }
+        }
+    }
+
+    struct Port(Rc<RefCell<Controller>>);
+
+    impl ErrorType for Port {
+        type Error = Infallible;
+    }
+
+    impl Read for Port {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            let n = {
+                let mut controller = self.0.borrow_mut();
+                // Short reads, so packets straddle them.
+                let n = buf.len().min(controller.output.len()).min(5);
+                for (dst, src) in buf.iter_mut().zip(controller.output.drain(..n)) {
+                    *dst = src;
+                }
+                n
+            };
+            match n {
+                0 => pending().await,
+                n => Ok(n),
+            }
+        }
+    }
+
+    impl Write for Port {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            self.0.borrow_mut().receive(buf);
+            Ok(buf.len())
+        }
+    }
+
+    /// Times out as soon as the controller has nothing more to say.
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    fn hci(controller: &Rc<RefCell<Controller>>) -> Hci<Port, Port, NoDelay> {
+        Hci::new(
+            Port(controller.clone()),
+            Port(controller.clone()),
+            NoDelay,
+            Config::default(),
+        )
+    }
+
+    #[test]
+    fn commands_and_queued_events() {
+        let controller = Controller::new();
+        let mut hci = hci(&controller);
+
+        // An advertising report arrives before the answer to Read BD_ADDR.
+        let report = [0x04, 0x3e, 0x03, 0x02, 0x00, 0x00];
+        controller.borrow_mut().unsolicited = report.to_vec();
+        let address = block_on(hci.command(&command::ReadBdAddr));
+        assert_eq!(address, Ok([0x66, 0x55, 0x44, 0x33, 0x22, 0x11]));
+        assert!(hci.has_pending());
+
+        let received = block_on(hci.receive()).unwrap();
+        assert_eq!(
+            received.event(),
+            Some(Event::LeMeta {
+                subevent: 0x02,
+                params: &[0x00, 0x00],
+            })
+        );
+
+        controller.borrow_mut().refuse = Some(0x200c);
+        let scan = command::LeSetScanEnable {
+            enable: true,
+            filter_duplicates: false,
+        };
+        assert_eq!(
+            block_on(hci.command(&scan)),
+            Err(Error::Status(Status(0x12)))
+        );
+        assert_eq!(
+            controller.borrow().commands.last(),
+            Some(&(0x200c, vec![1, 0]))
+        );
+
+        controller.borrow_mut().silent = true;
+        assert_eq!(block_on(hci.command(&command::Reset)), Err(Error::Timeout));
+    }
+
+    #[test]
+    fn init_with_patch_and_baudrate() {
+        let controller = Controller::new();
+        let mut hci = hci(&controller);
+
+        let patch = [
+            0x4c, 0xfc, 0x05, 0x00, 0x00, 0x20, 0x00, 0xaa, //
+            0x4e, 0xfc, 0x04, 0xff, 0xff, 0xff, 0xff,
+        ];
+        let version = block_on(hci.init(Some(&patch))).unwrap();
+        assert_eq!(version.manufacturer, 15);
+        let opcodes: Vec<u16> = controller.borrow().commands.iter().map(|c| c.0).collect();
+        assert_eq!(opcodes, [0x0c03, 0xfc2e, 0xfc4c, 0xfc4e, 0x0c03, 0x1001]);
+
+        let mut switched = None;
+        let result = block_on(hci.set_baudrate(3_000_000, |_, _, baud| {
+            switched = Some(baud);
+            true
+        }));
+        assert_eq!(result, Ok(()));
+        assert_eq!(switched, Some(3_000_000));
+
+        let result = block_on(hci.set_baudrate(4_000_000, |_, _, _| false));
+        assert_eq!(result, Err(Error::Baudrate));
+
+        assert_eq!(block_on(hci.init(Some(&patch[..5]))), Err(Error::Malformed));
+    }
+}
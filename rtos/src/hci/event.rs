@@ -0,0 +1,167 @@
+//! Typed view of the HCI events the transport handles itself or that are commonly logged.
+
+/// Event codes.
+pub mod code {
+    pub const DISCONNECTION_COMPLETE: u8 = 0x05;
+    pub const COMMAND_COMPLETE: u8 = 0x0e;
+    pub const COMMAND_STATUS: u8 = 0x0f;
+    pub const HARDWARE_ERROR: u8 = 0x10;
+    pub const NUMBER_OF_COMPLETED_PACKETS: u8 = 0x13;
+    pub const LE_META: u8 = 0x3e;
+    pub const VENDOR: u8 = 0xff;
+}
+
+/// Controller error code, zero on success (Core specification, volume 1 part F).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status(pub u8);
+
+impl Status {
+    pub fn is_success(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self.0 {
+            0x00 => "success",
+            0x01 => "unknown command",
+            0x02 => "unknown connection identifier",
+            0x03 => "hardware failure",
+            0x07 => "memory capacity exceeded",
+            0x08 => "connection timeout",
+            0x0c => "command disallowed",
+            0x11 => "unsupported feature or parameter value",
+            0x12 => "invalid command parameters",
+            0x13 => "remote user terminated connection",
+            0x16 => "connection terminated by local host",
+            0x3e => "connection failed to be established",
+            _ => "controller error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    CommandComplete {
+        /// Commands the controller accepts now.
+        credits: u8,
+        opcode: u16,
+        /// Return parameters, usually starting with a status byte.
+        params: &'a [u8],
+    },
+    CommandStatus {
+        status: Status,
+        credits: u8,
+        opcode: u16,
+    },
+    DisconnectionComplete {
+        status: Status,
+        handle: u16,
+        reason: Status,
+    },
+    HardwareError(u8),
+    /// Pairs of connection handle and completed packet count, four bytes each.
+    NumberOfCompletedPackets(&'a [u8]),
+    LeMeta {
+        subevent: u8,
+        params: &'a [u8],
+    },
+    Other {
+        code: u8,
+        params: &'a [u8],
+    },
+}
+
+impl<'a> Event<'a> {
+    /// `None` if the parameters are too short for the event code.
+    pub fn parse(code: u8, params: &'a [u8]) -> Option<Self> {
+        let u16_at = |i: usize| Some(u16::from_le_bytes([*params.get(i)?, *params.get(i + 1)?]));
+        Some(match code {
+            code::COMMAND_COMPLETE => Event::CommandComplete {
+                credits: *params.first()?,
+                opcode: u16_at(1)?,
+                params: &params[3..],
+            },
+            code::COMMAND_STATUS => Event::CommandStatus {
+                status: Status(*params.first()?),
+                credits: *params.get(1)?,
+                opcode: u16_at(2)?,
+            },
+            code::DISCONNECTION_COMPLETE => Event::DisconnectionComplete {
+                status: Status(*params.first()?),
+                handle: u16_at(1)? & 0x0fff,
+                reason: Status(*params.get(3)?),
+            },
+            code::HARDWARE_ERROR => Event::HardwareError(*params.first()?),
+            code::NUMBER_OF_COMPLETED_PACKETS => {
+                let (&count, pairs) = params.split_first()?;
+                Event::NumberOfCompletedPackets(pairs.get(..count as usize * 4)?)
+            }
+            code::LE_META => {
+                let (&subevent, params) = params.split_first()?;
+                Event::LeMeta { subevent, params }
+            }
+            code => Event::Other { code, params },
+        })
+    }
+
+    /// The command this event answers, if it is a Command Complete or Command Status.
+    pub fn opcode(&self) -> Option<u16> {
+        match *self {
+            Event::CommandComplete { opcode, .. } | Event::CommandStatus { opcode, .. } => {
+                Some(opcode)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_events() {
+        assert_eq!(
+            Event::parse(
+                0x0e,
+                &[0x01, 0x09, 0x10, 0x00, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11]
+            ),
+            Some(Event::CommandComplete {
+                credits: 1,
+                opcode: 0x1009,
+                params: &[0x00, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11],
+            })
+        );
+        assert_eq!(
+            Event::parse(0x0f, &[0x0c, 0x01, 0x0d, 0x20]),
+            Some(Event::CommandStatus {
+                status: Status(0x0c),
+                credits: 1,
+                opcode: 0x200d,
+            })
+        );
+        assert_eq!(
+            Event::parse(0x05, &[0x00, 0x40, 0x00, 0x13]),
+            Some(Event::DisconnectionComplete {
+                status: Status(0),
+                handle: 0x040,
+                reason: Status(0x13),
+            })
+        );
+        assert_eq!(
+            Event::parse(0x13, &[0x01, 0x40, 0x00, 0x02, 0x00]),
+            Some(Event::NumberOfCompletedPackets(&[0x40, 0x00, 0x02, 0x00]))
+        );
+        assert_eq!(
+            Event::parse(0x3e, &[0x01, 0x00]).and_then(|e| e.opcode()),
+            None
+        );
+
+        // Truncated parameters.
+        assert_eq!(Event::parse(0x0e, &[0x01, 0x03]), None);
+        assert_eq!(Event::parse(0x13, &[0x02, 0x40, 0x00, 0x02, 0x00]), None);
+        assert_eq!(Status(0x0c).as_str(), "command disallowed");
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{board::BluetoothResource, uart::SerialSettings};
+use defmt::{debug, info, warn};
+use embassy_embedded_hal::SetConfig;
+use embassy_executor::Spawner;
+use embassy_stm32::{
+    bind_interrupts,
+    gpio::{Level, Output, Speed},
+    peripherals,
+    usart::{self, BufferedUart, BufferedUartRx, BufferedUartTx},
+};
+use embassy_time::{Delay, Timer};
+use static_cell::StaticCell;
+
+pub mod command;
+pub mod driver;
+pub mod event;
+pub mod packet;
+
+pub use driver::{Config, Error, Hci, Received};
+pub use event::{Event, Status};
+
+use packet::Packet;
+
+bind_interrupts!(struct HciIrqs {
+    UART7 => usart::BufferedInterruptHandler<peripherals::UART7>;
+});
+
+const UART_BUF_SIZE: usize = 1024;
+/// How long BT_REG_ON is held low to reset the controller, and how long it then takes to boot.
+const POWER_OFF_MS: u64 = 20;
+const BOOT_MS: u64 = 100;
+/// Pause before power cycling the controller again after it failed.
+const RETRY_SECS: u64 = 5;
+/// The default event mask plus LE Meta, without which no LE event gets through.
+const EVENT_MASK: u64 = 0x2000_1fff_ffff_ffff;
+
+/// The transport on UART7, borrowing the UART for as long as it lives.
+pub type Transport<'a> = Hci<
+    &'a mut BufferedUartRx<'static, peripherals::UART7>,
+    &'a mut BufferedUartTx<'static, peripherals::UART7>,
+    Delay,
+>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HciConfig {
+    /// The controller's rate after power-up.
+    pub serial: SerialSettings,
+    /// Rate to move to once the controller is up. `None` stays at `serial`.
+    pub baudrate: Option<u32>,
+    /// Broadcom `.hcd` firmware patch, e.g. from `include_bytes!`. The controller runs its ROM
+    /// firmware without one.
+    pub patch: Option<&'static [u8]>,
+}
+
+impl Default for HciConfig {
+    fn default() -> Self {
+        Self {
+            serial: SerialSettings::new(115200),
+            baudrate: Some(921_600),
+            patch: None,
+        }
+    }
+}
+
+/// Power up the Bluetooth controller in the Murata module and run HCI on UART7 with hardware
+/// flow control. The task brings the controller up, then logs the events it sends; it power
+/// cycles the controller when it stops answering.
+pub fn spawn(spawner: &Spawner, r: BluetoothResource, config: HciConfig) {
+    static TX_BUF: StaticCell<[u8; UART_BUF_SIZE]> = StaticCell::new();
+    static RX_BUF: StaticCell<[u8; UART_BUF_SIZE]> = StaticCell::new();
+    let uart = defmt::unwrap!(BufferedUart::new_with_rtscts(
+        r.peri,
+        HciIrqs,
+        r.rx,
+        r.tx,
+        r.rts,
+        r.cts,
+        &mut TX_BUF.init([0; UART_BUF_SIZE])[..],
+        &mut RX_BUF.init([0; UART_BUF_SIZE])[..],
+        (&config.serial).into(),
+    ));
+    let power = Output::new(r.power, Level::Low, Speed::Low);
+
+    defmt::unwrap!(spawner.spawn(hci_task(uart, power, config)));
+}
+
+#[embassy_executor::task]
+async fn hci_task(
+    uart: BufferedUart<'static, peripherals::UART7>,
+    mut power: Output<'static>,
+    config: HciConfig,
+) -> ! {
+    let (mut tx, mut rx) = uart.split();
+
+    loop {
+        power.set_low();
+        Timer::after_millis(POWER_OFF_MS).await;
+        // The controller comes back at its default rate.
+        if let Err(e) = rx.set_config(&(&config.serial).into()) {
+            warn!("hci: UART config failed: {}", defmt::Debug2Format(&e));
+        }
+        power.set_high();
+        Timer::after_millis(BOOT_MS).await;
+
+        let mut hci = Hci::new(&mut rx, &mut tx, Delay, Config::default());
+        match bring_up(&mut hci, &config).await {
+            Ok(()) => log_events(&mut hci).await,
+            Err(e) => warn!("hci: init failed: {=str}", e.as_str()),
+        }
+        Timer::after_secs(RETRY_SECS).await;
+    }
+}
+
+async fn bring_up(hci: &mut Transport<'_>, config: &HciConfig) -> Result<(), Error> {
+    let version = hci.init(config.patch).await?;
+    info!(
+        "hci: controller up, HCI version {}, manufacturer {}, LMP subversion {:04x}",
+        version.hci_version, version.manufacturer, version.lmp_subversion
+    );
+
+    if let Some(baudrate) = config.baudrate {
+        let settings = SerialSettings {
+            baudrate,
+            ..config.serial
+        };
+        hci.set_baudrate(baudrate, |rx, _, _| {
+            rx.set_config(&(&settings).into()).is_ok()
+        })
+        .await?;
+        info!("hci: {} baud", baudrate);
+    }
+
+    let address = hci.command(&command::ReadBdAddr).await?;
+    info!(
+        "hci: address {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+        address[5], address[4], address[3], address[2], address[1], address[0]
+    );
+    hci.command(&command::SetEventMask(EVENT_MASK)).await
+}
+
+/// Log what the controller sends until it reports a hardware error or the UART fails.
+async fn log_events(hci: &mut Transport<'_>) {
+    loop {
+        let received = match hci.receive().await {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("hci: {=str}", e.as_str());
+                return;
+            }
+        };
+        match (received.event(), received.packet()) {
+            (Some(Event::HardwareError(code)), _) => {
+                warn!("hci: hardware error {:02x}", code);
+                return;
+            }
+            (Some(Event::DisconnectionComplete { handle, reason, .. }), _) => {
+                info!("hci: {:03x} disconnected: {=str}", handle, reason.as_str())
+            }
+            (Some(Event::LeMeta { subevent, params }), _) => {
+                debug!("hci: LE event {:02x}, {} bytes", subevent, params.len())
+            }
+            (Some(event), _) => debug!("hci: {}", defmt::Debug2Format(&event)),
+            (None, Packet::Acl { handle, data, .. }) => {
+                debug!("hci: ACL {:03x}, {} bytes", handle, data.len())
+            }
+            (None, packet) => debug!("hci: {}", defmt::Debug2Format(&packet)),
+        }
+    }
+}
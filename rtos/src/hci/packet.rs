@@ -0,0 +1,319 @@
+//! HCI packets in UART (H4) framing: a one byte packet indicator, then the packet as defined in
+//! the Bluetooth Core specification, volume 4 part E, section 5.4.
+
+use alloc::vec::Vec;
+
+/// H4 packet indicators.
+pub mod indicator {
+    pub const COMMAND: u8 = 0x01;
+    pub const ACL: u8 = 0x02;
+    pub const EVENT: u8 = 0x04;
+    pub const ISO: u8 = 0x05;
+}
+
+/// Build an opcode from its group and command fields.
+pub const fn opcode(ogf: u8, ocf: u16) -> u16 {
+    (ogf as u16) << 10 | (ocf & 0x03ff)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packet<'a> {
+    Command {
+        opcode: u16,
+        params: &'a [u8],
+    },
+    Acl {
+        /// Connection handle, 12 bits.
+        handle: u16,
+        /// Packet boundary and broadcast flags, the top 4 bits of the first header field.
+        flags: u8,
+        data: &'a [u8],
+    },
+    Event {
+        code: u8,
+        params: &'a [u8],
+    },
+    Iso {
+        handle: u16,
+        /// Packet boundary and timestamp flags, the top 4 bits of the first header field.
+        flags: u8,
+        data: &'a [u8],
+    },
+}
+
+impl<'a> Packet<'a> {
+    /// Parse one complete H4 packet, indicator included. Trailing bytes are an error.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        let (&kind, rest) = bytes.split_first()?;
+        let header = header_len(kind)?;
+        let (head, body) = (rest.get(..header)?, &rest[header..]);
+        if body.len() != payload_len(kind, head) {
+            return None;
+        }
+
+        let handle = || u16::from_le_bytes([head[0], head[1]]);
+        Some(match kind {
+            indicator::COMMAND => Packet::Command {
+                opcode: handle(),
+                params: body,
+            },
+            indicator::ACL => Packet::Acl {
+                handle: handle() & 0x0fff,
+                flags: (handle() >> 12) as u8,
+                data: body,
+            },
+            indicator::EVENT => Packet::Event {
+                code: head[0],
+                params: body,
+            },
+            _ => Packet::Iso {
+                handle: handle() & 0x0fff,
+                flags: (handle() >> 12) as u8,
+                data: body,
+            },
+        })
+    }
+
+    /// Append the packet in H4 framing. Payloads longer than their length field allows are
+    /// truncated.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let with_flags = |handle: u16, flags: u8| (handle & 0x0fff) | (flags as u16) << 12;
+        let body = match *self {
+            Packet::Command { opcode, params } => {
+                let params = &params[..params.len().min(u8::MAX as usize)];
+                out.push(indicator::COMMAND);
+                out.extend_from_slice(&opcode.to_le_bytes());
+                out.push(params.len() as u8);
+                params
+            }
+            Packet::Acl {
+                handle,
+                flags,
+                data,
+            } => {
+                let data = &data[..data.len().min(u16::MAX as usize)];
+                out.push(indicator::ACL);
+                out.extend_from_slice(&with_flags(handle, flags).to_le_bytes());
+                out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+                data
+            }
+            Packet::Event { code, params } => {
+                let params = &params[..params.len().min(u8::MAX as usize)];
+                out.extend_from_slice(&[indicator::EVENT, code, params.len() as u8]);
+                params
+            }
+            Packet::Iso {
+                handle,
+                flags,
+                data,
+            } => {
+                let data = &data[..data.len().min(0x3fff)];
+                out.push(indicator::ISO);
+                out.extend_from_slice(&with_flags(handle, flags).to_le_bytes());
+                out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+                data
+            }
+        };
+        out.extend_from_slice(body);
+    }
+}
+
+/// Header length after the indicator, or `None` for indicators we don't handle (SCO included).
+fn header_len(indicator: u8) -> Option<usize> {
+    match indicator {
+        indicator::COMMAND => Some(3),
+        indicator::ACL | indicator::ISO => Some(4),
+        indicator::EVENT => Some(2),
+        _ => None,
+    }
+}
+
+fn payload_len(indicator: u8, header: &[u8]) -> usize {
+    match indicator {
+        indicator::COMMAND => header[2] as usize,
+        indicator::EVENT => header[1] as usize,
+        indicator::ACL => u16::from_le_bytes([header[2], header[3]]) as usize,
+        // The top two bits of an ISO length are reserved.
+        _ => u16::from_le_bytes([header[2], header[3]]) as usize & 0x3fff,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Not a packet indicator. H4 can't resynchronise by itself, so the byte is skipped and the
+    /// next one tried.
+    UnknownIndicator(u8),
+    /// The payload is longer than the decoder's limit; it is skipped.
+    TooLong { indicator: u8, len: usize },
+}
+
+/// Reassembles packets from the byte stream.
+#[derive(Debug)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    max_payload: usize,
+    /// Payload bytes of an oversized packet still to skip.
+    skip: usize,
+    /// The last packet returned borrows `buf`; clear it on the next byte.
+    done: bool,
+}
+
+impl Decoder {
+    pub fn new(max_payload: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            max_payload,
+            skip: 0,
+            done: false,
+        }
+    }
+
+    /// Nothing buffered: the stream is between packets.
+    pub fn is_idle(&self) -> bool {
+        self.skip == 0 && (self.done || self.buf.is_empty())
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<Result<Packet<'_>, DecodeError>> {
+        if core::mem::take(&mut self.done) {
+            self.buf.clear();
+        }
+        if self.skip > 0 {
+            self.skip -= 1;
+            return None;
+        }
+
+        let kind = *self.buf.first().unwrap_or(&byte);
+        let Some(header) = header_len(kind) else {
+            return Some(Err(DecodeError::UnknownIndicator(byte)));
+        };
+        self.buf.push(byte);
+        if self.buf.len() < 1 + header {
+            return None;
+        }
+
+        let len = payload_len(kind, &self.buf[1..1 + header]);
+        if self.buf.len() == 1 + header && len > self.max_payload {
+            self.buf.clear();
+            self.skip = len;
+            return Some(Err(DecodeError::TooLong {
+                indicator: kind,
+                len,
+            }));
+        }
+        if self.buf.len() < 1 + header + len {
+            return None;
+        }
+
+        self.done = true;
+        Packet::parse(&self.buf).map(Ok)
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// Controller to host traffic of a CYW4343W, read off its HCI UART during start-up and a
+    /// scan.
+    const CAPTURE: &[u8] = &[
+        // Command Complete for HCI_Reset.
+        0x04, 0x0e, 0x04, 0x01, 0x03, 0x0c, 0x00,
+        // Command Complete for Read_Local_Version_Information.
+        0x04, 0x0e, 0x0c, 0x01, 0x01, 0x10, 0x00, 0x09, 0x00, 0x01, 0x09, 0x0f, 0x00, 0x0d, 0x41,
+        // LE Advertising Report with one 3 byte AD structure.
+        0x04, 0x3e, 0x0f, 0x02, 0x01, 0x00, 0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x03, 0x02,
+        0x01, 0x06, 0xc4, // ACL data on handle 0x040, an LE signalling response.
+        0x02, 0x40, 0x20, 0x0a, 0x00, 0x06, 0x00, 0x05, 0x00, 0x13, 0x01, 0x02, 0x00, 0x00, 0x00,
+    ];
+
+    fn decode_all(bytes: &[u8], max_payload: usize) -> Vec<Result<Vec<u8>, DecodeError>> {
+        let mut decoder = Decoder::new(max_payload);
+        let mut packets = Vec::new();
+        for &b in bytes {
+            if let Some(result) = decoder.push(b) {
+                packets.push(result.map(|p| {
+                    let mut out = Vec::new();
+                    p.encode(&mut out);
+                    out
+                }));
+            }
+        }
+        assert!(decoder.is_idle());
+        packets
+    }
+
+    #[test]
+    fn decodes_a_capture() {
+        let packets = decode_all(CAPTURE, 64);
+        assert_eq!(packets.len(), 4);
+
+        // Re-encoding gives back the captured bytes.
+        let joined: Vec<u8> = packets.iter().flat_map(|p| p.clone().unwrap()).collect();
+        assert_eq!(joined, CAPTURE);
+
+        let acl = packets[3].as_ref().unwrap();
+        assert_eq!(
+            Packet::parse(acl),
+            Some(Packet::Acl {
+                handle: 0x040,
+                flags: 0x2,
+                data: &[0x06, 0x00, 0x05, 0x00, 0x13, 0x01, 0x02, 0x00, 0x00, 0x00],
+            })
+        );
+        assert!(matches!(
+            Packet::parse(&CAPTURE[..7]),
+            Some(Packet::Event { code: 0x0e, .. })
+        ));
+    }
+
+    #[test]
+    fn encodes_commands_and_iso() {
+        let mut out = Vec::new();
+        Packet::Command {
+            opcode: opcode(0x03, 0x0003),
+            params: &[],
+        }
+        .encode(&mut out);
+        assert_eq!(out, [0x01, 0x03, 0x0c, 0x00]);
+
+        out.clear();
+        let iso = Packet::Iso {
+            handle: 0x061,
+            flags: 0b0110,
+            data: &[1, 2, 3],
+        };
+        iso.encode(&mut out);
+        assert_eq!(out, [0x05, 0x61, 0x60, 0x03, 0x00, 1, 2, 3]);
+        assert_eq!(Packet::parse(&out), Some(iso));
+
+        // Length mismatches don't parse.
+        assert_eq!(Packet::parse(&[0x01, 0x03, 0x0c, 0x01]), None);
+        assert_eq!(Packet::parse(&[0x04, 0x0e, 0x00, 0x00]), None);
+    }
+
+    #[test]
+    fn skips_garbage_and_oversized_packets() {
+        let mut stream = vec![0x00, 0xff];
+        // 20 byte event payload, over the limit.
+        stream.extend_from_slice(&[0x04, 0xff, 20]);
+        stream.extend_from_slice(&[0x04; 20]);
+        stream.extend_from_slice(&CAPTURE[..7]);
+
+        let packets = decode_all(&stream, 16);
+        assert_eq!(
+            packets,
+            [
+                Err(DecodeError::UnknownIndicator(0x00)),
+                Err(DecodeError::UnknownIndicator(0xff)),
+                Err(DecodeError::TooLong {
+                    indicator: indicator::EVENT,
+                    len: 20
+                }),
+                Ok(CAPTURE[..7].to_vec()),
+            ]
+        );
+    }
+}
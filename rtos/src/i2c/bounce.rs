@@ -0,0 +1,141 @@
+//! An I2C bus whose transfers go through a buffer of its own.
+//!
+//! DMA1 and DMA2 can't reach DTCM, where the stack and `.bss` are, so a peripheral they serve
+//! can't be handed a driver's buffers. [`Bounce`] copies each transaction's writes into its buffer,
+//! placed where the DMA reaches, lets the bus transfer from there, and copies the reads back out.
+
+use alloc::vec::Vec;
+use embedded_hal_async::i2c::{self, ErrorKind, ErrorType, I2c, Operation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error<E> {
+    Bus(E),
+    /// The transaction's operations don't fit in the buffer together.
+    TooLong,
+}
+
+impl<E> Error<E> {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::Bus(_) => "bus error",
+            Error::TooLong => "transaction too long",
+        }
+    }
+}
+
+impl<E: i2c::Error> i2c::Error for Error<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Bus(e) => e.kind(),
+            Error::TooLong => ErrorKind::Other,
+        }
+    }
+}
+
+pub struct Bounce<B> {
+    bus: B,
+    buf: &'static mut [u8],
+}
+
+impl<B> Bounce<B> {
+    pub fn new(bus: B, buf: &'static mut [u8]) -> Self {
+        Self { bus, buf }
+    }
+}
+
+fn len(operation: &Operation<'_>) -> usize {
+    match operation {
+        Operation::Read(buf) => buf.len(),
+        Operation::Write(bytes) => bytes.len(),
+    }
+}
+
+impl<B: ErrorType> ErrorType for Bounce<B> {
+    type Error = Error<B::Error>;
+}
+
+impl<B: I2c> I2c for Bounce<B> {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        if operations.iter().map(len).sum::<usize>() > self.buf.len() {
+            return Err(Error::TooLong);
+        }
+        let mut rest = &mut self.buf[..];
+        let mut bounced = Vec::with_capacity(operations.len());
+        for operation in operations.iter() {
+            let (chunk, tail) = core::mem::take(&mut rest).split_at_mut(len(operation));
+            rest = tail;
+            bounced.push(match operation {
+                Operation::Read(_) => Operation::Read(chunk),
+                Operation::Write(bytes) => {
+                    chunk.copy_from_slice(bytes);
+                    Operation::Write(chunk)
+                }
+            });
+        }
+        let result = self.bus.transaction(address, &mut bounced).await;
+        drop(bounced);
+        result.map_err(Error::Bus)?;
+
+        let mut offset = 0;
+        for operation in operations.iter_mut() {
+            let n = len(operation);
+            if let Operation::Read(buf) = operation {
+                buf.copy_from_slice(&self.buf[offset..offset + n]);
+            }
+            offset += n;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::sim::Targets;
+    use embassy_futures::block_on;
+    use std::{boxed::Box, vec};
+
+    fn bounce(targets: Targets, size: usize) -> Bounce<Targets> {
+        Bounce::new(targets, Box::leak(vec![0; size].into_boxed_slice()))
+    }
+
+    #[test]
+    fn copies_reads_back() {
+        let targets = Targets::new(&[(0x32, &[(0x10, 0x59), (0x11, 0x5a)])]);
+        let mut bus = bounce(targets, 8);
+        let mut buf = [0; 2];
+        block_on(bus.write_read(0x32, &[0x10], &mut buf)).unwrap();
+        assert_eq!(buf, [0x59, 0x5a]);
+        assert_eq!(bus.buf[..3], [0x10, 0x59, 0x5a]);
+    }
+
+    #[test]
+    fn rejects_what_does_not_fit() {
+        let targets = Targets::new(&[(0x32, &[])]);
+        let mut bus = bounce(targets, 4);
+        let mut buf = [0; 4];
+        assert_eq!(
+            block_on(bus.write_read(0x32, &[0x10], &mut buf)),
+            Err(Error::TooLong)
+        );
+        assert!(bus.bus.reads.is_empty());
+        block_on(bus.read(0x32, &mut buf)).unwrap();
+    }
+
+    #[test]
+    fn passes_errors_on() {
+        let mut targets = Targets::new(&[(0x32, &[])]);
+        targets.fail_at = Some(0x32);
+        let mut bus = bounce(targets, 4);
+        assert_eq!(
+            block_on(bus.write(0x32, &[0x10])),
+            Err(Error::Bus(ErrorKind::Bus))
+        );
+    }
+}
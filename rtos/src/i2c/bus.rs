@@ -0,0 +1,259 @@
+//! One I2C peripheral shared by several drivers.
+//!
+//! Each driver gets its own [`Device`], an `embedded_hal_async` I2C bus of its own, and they take
+//! turns: a transaction holds the bus from its START to its STOP. A device gives up on a
+//! transaction after its own timeout, which frees the bus for the others. After a timeout, or an
+//! error with SDA held low, the bus is recovered before anyone else gets it.
+
+use super::recovery::{self, Lines};
+use embassy_futures::select::{select, Either};
+use embassy_sync::{blocking_mutex::raw::RawMutex, mutex::Mutex};
+use embedded_hal_async::{
+    delay::DelayNs,
+    i2c::{self, Error as _, ErrorKind, ErrorType, I2c, Operation},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error<E> {
+    Bus(E),
+    /// The device's timeout ran out.
+    Timeout,
+    /// SDA is held low, and clocking it didn't free it.
+    Stuck,
+}
+
+impl<E> Error<E> {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::Bus(_) => "bus error",
+            Error::Timeout => "timeout",
+            Error::Stuck => "SDA stuck low",
+        }
+    }
+}
+
+impl<E: i2c::Error> i2c::Error for Error<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Bus(e) => e.kind(),
+            Error::Timeout | Error::Stuck => ErrorKind::Other,
+        }
+    }
+}
+
+/// Counts since the bus was brought up.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub transactions: u32,
+    pub errors: u32,
+    pub timeouts: u32,
+    pub recoveries: u32,
+    /// Recoveries that left SDA low.
+    pub stuck: u32,
+}
+
+struct Inner<B, L> {
+    bus: B,
+    lines: L,
+    stats: Stats,
+}
+
+pub struct Shared<M: RawMutex, B, L> {
+    inner: Mutex<M, Inner<B, L>>,
+    half_period_us: u32,
+}
+
+impl<M: RawMutex, B: I2c, L: Lines> Shared<M, B, L> {
+    /// `bus` running at `frequency_hz`, which recovery clocks at too.
+    pub fn new(bus: B, lines: L, frequency_hz: u32) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                bus,
+                lines,
+                stats: Stats::default(),
+            }),
+            half_period_us: (500_000 / frequency_hz.max(1)).max(1),
+        }
+    }
+
+    /// A handle for one driver, giving up on a transaction after `timeout_ms`.
+    pub fn device<D: DelayNs>(&self, delay: D, timeout_ms: u32) -> Device<'_, M, B, L, D> {
+        Device {
+            shared: self,
+            delay,
+            timeout_ms,
+        }
+    }
+
+    pub async fn stats(&self) -> Stats {
+        self.inner.lock().await.stats
+    }
+
+    /// Clock the bus free, whether or not anything went wrong.
+    pub async fn recover<D: DelayNs>(&self, delay: &mut D) -> Result<(), Error<B::Error>> {
+        let mut inner = self.inner.lock().await;
+        self.recover_locked(&mut inner, delay).await
+    }
+
+    async fn recover_locked<D: DelayNs>(
+        &self,
+        inner: &mut Inner<B, L>,
+        delay: &mut D,
+    ) -> Result<(), Error<B::Error>> {
+        inner.stats.recoveries = inner.stats.recoveries.wrapping_add(1);
+        match recovery::recover(&mut inner.lines, delay, self.half_period_us).await {
+            Some(_) => Ok(()),
+            None => {
+                inner.stats.stuck = inner.stats.stuck.wrapping_add(1);
+                Err(Error::Stuck)
+            }
+        }
+    }
+}
+
+pub struct Device<'a, M: RawMutex, B, L, D> {
+    shared: &'a Shared<M, B, L>,
+    delay: D,
+    timeout_ms: u32,
+}
+
+impl<M: RawMutex, B: I2c, L: Lines, D: DelayNs> ErrorType for Device<'_, M, B, L, D> {
+    type Error = Error<B::Error>;
+}
+
+impl<M: RawMutex, B: I2c, L: Lines, D: DelayNs> I2c for Device<'_, M, B, L, D> {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        // The timeout starts once the bus is ours; whoever has it is bounded by their own.
+        let mut inner = self.shared.inner.lock().await;
+        inner.stats.transactions = inner.stats.transactions.wrapping_add(1);
+        let result = match select(
+            inner.bus.transaction(address, operations),
+            self.delay.delay_ms(self.timeout_ms),
+        )
+        .await
+        {
+            Either::First(result) => result.map_err(Error::Bus),
+            Either::Second(()) => Err(Error::Timeout),
+        };
+        let recover = match &result {
+            Ok(()) => false,
+            Err(Error::Timeout) => {
+                inner.stats.timeouts = inner.stats.timeouts.wrapping_add(1);
+                true
+            }
+            Err(e) => {
+                inner.stats.errors = inner.stats.errors.wrapping_add(1);
+                // A missing target only doesn't acknowledge. Losing arbitration with no other
+                // controller on the bus, or any other error, means a target may be holding SDA.
+                !matches!(e.kind(), ErrorKind::NoAcknowledge(_)) && !inner.lines.sda()
+            }
+        };
+        if recover {
+            self.shared
+                .recover_locked(&mut inner, &mut self.delay)
+                .await?;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::sim::{setup, Event, Ticks, TARGET};
+    use embassy_futures::{block_on, join::join};
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embedded_hal_async::i2c::NoAcknowledgeSource;
+    use std::vec;
+
+    #[test]
+    fn devices_take_turns() {
+        let (wire, peripheral, lines) = setup();
+        let shared = Shared::<NoopRawMutex, _, _>::new(peripheral, lines, 100_000);
+        let mut a = shared.device(Ticks, 10);
+        let mut b = shared.device(Ticks, 10);
+
+        let mut buf = [0u8; 2];
+        let (wrote, read) = block_on(join(
+            a.write(TARGET, &[1, 2, 3]),
+            b.write_read(TARGET, &[0], &mut buf),
+        ));
+        assert_eq!((wrote, read), (Ok(()), Ok(())));
+        assert_eq!(buf, [TARGET; 2]);
+        // Neither transaction started while the other was on the bus.
+        assert_eq!(
+            wire.borrow().log,
+            vec![Event::Start, Event::Stop, Event::Start, Event::Stop]
+        );
+        assert_eq!(block_on(shared.stats()).transactions, 2);
+    }
+
+    #[test]
+    fn a_timeout_frees_the_bus_for_the_others() {
+        let (wire, peripheral, lines) = setup();
+        let shared = Shared::<NoopRawMutex, _, _>::new(peripheral, lines, 100_000);
+        let mut hung = shared.device(Ticks, 10);
+        let mut other = shared.device(Ticks, 10);
+
+        // The target holds SDA for its next five clocks when the transaction is abandoned.
+        wire.borrow_mut().hang = Some(5);
+        let (first, second) = block_on(join(hung.write(TARGET, &[1]), other.write(TARGET, &[2])));
+        assert_eq!((first, second), (Err(Error::Timeout), Ok(())));
+        let wire = wire.borrow();
+        assert_eq!((wire.pulses, wire.stops, wire.restored), (5, 1, 1));
+        let stats = block_on(shared.stats());
+        assert_eq!(
+            (stats.transactions, stats.timeouts, stats.recoveries),
+            (2, 1, 1)
+        );
+    }
+
+    #[test]
+    fn recovers_from_lost_arbitration() {
+        let (wire, peripheral, lines) = setup();
+        let shared = Shared::<NoopRawMutex, _, _>::new(peripheral, lines, 100_000);
+        let mut device = shared.device(Ticks, 10);
+
+        // A target still in the middle of a read from before a reset.
+        wire.borrow_mut().held = 7;
+        assert_eq!(
+            block_on(device.write(TARGET, &[1])),
+            Err(Error::Bus(ErrorKind::ArbitrationLoss))
+        );
+        assert_eq!(block_on(device.write(TARGET, &[1])), Ok(()));
+        assert_eq!(wire.borrow().pulses, 7);
+
+        // Missing targets don't need it.
+        assert_eq!(
+            block_on(device.write(TARGET + 1, &[1])),
+            Err(Error::Bus(ErrorKind::NoAcknowledge(
+                NoAcknowledgeSource::Address
+            )))
+        );
+        let stats = block_on(shared.stats());
+        assert_eq!((stats.errors, stats.recoveries), (2, 1));
+    }
+
+    #[test]
+    fn reports_a_stuck_bus() {
+        let (wire, peripheral, lines) = setup();
+        let shared = Shared::<NoopRawMutex, _, _>::new(peripheral, lines, 100_000);
+        let mut device = shared.device(Ticks, 10);
+
+        wire.borrow_mut().held = u8::MAX;
+        assert_eq!(block_on(device.write(TARGET, &[1])), Err(Error::Stuck));
+        assert_eq!(block_on(shared.recover(&mut Ticks)), Err(Error::Stuck));
+        let stats = block_on(shared.stats());
+        assert_eq!((stats.recoveries, stats.stuck), (2, 2));
+
+        wire.borrow_mut().held = 0;
+        assert_eq!(block_on(shared.recover(&mut Ticks)), Ok(()));
+        assert_eq!(block_on(device.write(TARGET, &[1])), Ok(()));
+    }
+}
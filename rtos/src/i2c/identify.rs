@@ -0,0 +1,133 @@
+//! Naming the targets a scan finds.
+//!
+//! Most parts have a few addresses to choose from, and many share them, so the address only
+//! narrows it down. Where a part has an ID register it is read to tell it apart; parts without
+//! one are named from their address alone, after every part with an ID has been ruled out.
+
+use embedded_hal_async::i2c::I2c;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Known {
+    pub name: &'static str,
+    pub addresses: &'static [u8],
+    /// Register that reads back `.1`.
+    pub id: Option<(u8, u8)>,
+}
+
+const fn known(name: &'static str, addresses: &'static [u8], id: Option<(u8, u8)>) -> Known {
+    Known {
+        name,
+        addresses,
+        id,
+    }
+}
+
+const EEPROM: &[u8] = &[0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57];
+const MUX: &[u8] = &[0x70, 0x71, 0x72, 0x73, 0x74, 0x75, 0x76, 0x77];
+
+pub const KNOWN: &[Known] = &[
+    // No ID register; its address is fixed.
+    known("RV-8803 RTC", &[0x32], None),
+    known("PF1550 PMIC", &[0x08], None),
+    known("LIS3DH accelerometer", &[0x18, 0x19], Some((0x0f, 0x33))),
+    known("VL53L0X time of flight", &[0x29], Some((0xc0, 0xee))),
+    known("APDS-9960 gesture", &[0x39], Some((0x92, 0xab))),
+    known("SSD1306 OLED", &[0x3c, 0x3d], None),
+    known("PCA9685 PWM", &[0x40], None),
+    known("SHT3x humidity", &[0x44, 0x45], None),
+    known("ADS1115 ADC", &[0x48, 0x49, 0x4a, 0x4b], None),
+    known("24Cxx EEPROM", EEPROM, None),
+    known("LPS22HB pressure", &[0x5c, 0x5d], Some((0x0f, 0xb1))),
+    known("HTS221 humidity", &[0x5f], Some((0x0f, 0xbc))),
+    known("ATECC608 secure element", &[0x60], None),
+    known("MPU-6050 IMU", &[0x68, 0x69], Some((0x75, 0x68))),
+    known("MPU-9250 IMU", &[0x68, 0x69], Some((0x75, 0x71))),
+    known("BMI270 IMU", &[0x68, 0x69], Some((0x00, 0x24))),
+    known("DS3231 RTC", &[0x68], None),
+    known("LSM6DSOX IMU", &[0x6a, 0x6b], Some((0x0f, 0x6c))),
+    known("LSM6DS3 IMU", &[0x6a, 0x6b], Some((0x0f, 0x69))),
+    known("BMP280 pressure", &[0x76, 0x77], Some((0xd0, 0x58))),
+    known("BME280 environment", &[0x76, 0x77], Some((0xd0, 0x60))),
+    known("BME680 environment", &[0x76, 0x77], Some((0xd0, 0x61))),
+    known("TCA9548A mux", MUX, None),
+];
+
+/// Parts that may sit at `address`.
+pub fn candidates(address: u8) -> impl Iterator<Item = &'static Known> {
+    KNOWN
+        .iter()
+        .filter(move |known| known.addresses.contains(&address))
+}
+
+/// The part at `address`, which has acknowledged. Reading an ID register writes the register's
+/// address first, harmless to the parts here but for a TCA9548A at 0x76 or 0x77, whose channels it
+/// switches.
+pub async fn identify<I: I2c>(bus: &mut I, address: u8) -> Option<&'static Known> {
+    for known in candidates(address) {
+        let Some((register, value)) = known.id else {
+            continue;
+        };
+        let mut id = [0];
+        if bus.write_read(address, &[register], &mut id).await.is_ok() && id[0] == value {
+            return Some(known);
+        }
+    }
+    candidates(address).find(|known| known.id.is_none())
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::sim::Targets;
+    use embassy_futures::block_on;
+
+    fn name(bus: &mut Targets, address: u8) -> Option<&'static str> {
+        block_on(identify(bus, address)).map(|known| known.name)
+    }
+
+    #[test]
+    fn tells_parts_apart_by_id() {
+        let mut bus = Targets::new(&[
+            (0x68, &[(0x75, 0x71)]),
+            (0x69, &[(0x00, 0x24)]),
+            (0x76, &[(0xd0, 0x60)]),
+            (0x77, &[(0xd0, 0x61)]),
+        ]);
+        assert_eq!(name(&mut bus, 0x68), Some("MPU-9250 IMU"));
+        assert_eq!(name(&mut bus, 0x69), Some("BMI270 IMU"));
+        assert_eq!(name(&mut bus, 0x76), Some("BME280 environment"));
+        assert_eq!(name(&mut bus, 0x77), Some("BME680 environment"));
+    }
+
+    #[test]
+    fn falls_back_to_the_address() {
+        let mut bus = Targets::new(&[
+            (0x32, &[]),
+            (0x68, &[(0x75, 0x00)]),
+            (0x76, &[]),
+            (0x23, &[]),
+        ]);
+        assert_eq!(name(&mut bus, 0x32), Some("RV-8803 RTC"));
+        // An unknown ID at 0x68 leaves the RTC that shares it.
+        assert_eq!(name(&mut bus, 0x68), Some("DS3231 RTC"));
+        assert_eq!(name(&mut bus, 0x76), Some("TCA9548A mux"));
+        assert_eq!(name(&mut bus, 0x23), None);
+    }
+
+    #[test]
+    fn every_address_is_a_7_bit_one() {
+        for known in KNOWN {
+            assert!(!known.addresses.is_empty(), "{}", known.name);
+            assert!(
+                known
+                    .addresses
+                    .iter()
+                    .all(|a| crate::i2c::scan::ADDRESSES.contains(a)),
+                "{}",
+                known.name
+            );
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use crate::board::{I2c1Resource, I2c2Resource};
+use alloc::vec::Vec;
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use defmt::info;
+use embassy_embedded_hal::shared_bus::blocking::i2c::I2cDevice;
+use embassy_stm32::{
+    bind_interrupts,
+    dma::NoDma,
+    i2c::{self, I2c},
+    pac::{
+        self,
+        gpio::vals::{Idr, Moder},
+    },
+    peripherals,
+    time::Hertz,
+};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{Delay, Instant};
+use embedded_hal_async::i2c::{Error as _, ErrorKind};
+use static_cell::StaticCell;
+#[cfg(not(feature = "i2c-target"))]
+use {
+    crate::board::I2c4Resource, core::cell::RefCell, embassy_embedded_hal::adapter::BlockingAsync,
+    embassy_sync::blocking_mutex,
+};
+
+pub mod bounce;
+pub mod bus;
+pub mod identify;
+pub mod recovery;
+pub mod register_map;
+pub mod scan;
+#[cfg(test)]
+mod sim;
+#[cfg(feature = "i2c-target")]
+pub mod target;
+pub mod trace;
+
+pub use bus::{Error, Stats};
+pub use identify::Known;
+pub use scan::Probe;
+
+bind_interrupts!(struct Irqs {
+    I2C1_EV => i2c::EventInterruptHandler<peripherals::I2C1>;
+    I2C1_ER => i2c::ErrorInterruptHandler<peripherals::I2C1>;
+    I2C2_EV => i2c::EventInterruptHandler<peripherals::I2C2>;
+    I2C2_ER => i2c::ErrorInterruptHandler<peripherals::I2C2>;
+});
+
+// With the `i2c-target` feature `target` has the I2C4 interrupts.
+#[cfg(not(feature = "i2c-target"))]
+bind_interrupts!(struct I2c4Irqs {
+    I2C4_EV => i2c::EventInterruptHandler<peripherals::I2C4>;
+    I2C4_ER => i2c::ErrorInterruptHandler<peripherals::I2C4>;
+});
+
+/// Standard mode, which every target supports.
+pub const FREQUENCY: Hertz = Hertz(100_000);
+
+/// Bounced: DMA1 can't reach DTCM, where drivers' buffers are.
+pub type I2c1 =
+    bounce::Bounce<I2c<'static, peripherals::I2C1, peripherals::DMA1_CH2, peripherals::DMA1_CH3>>;
+pub type I2c2 =
+    bounce::Bounce<I2c<'static, peripherals::I2C2, peripherals::DMA1_CH4, peripherals::DMA1_CH5>>;
+/// Blocking only: I2C4 is in the D3 domain, where only the BDMA serves it, and the BDMA can only
+/// reach SRAM4.
+pub type I2c4 = I2c<'static, peripherals::I2C4, NoDma, NoDma>;
+
+pub type Shared<B> = bus::Shared<CriticalSectionRawMutex, B, Lines>;
+/// One driver's handle on a shared bus.
+pub type Device<B> = bus::Device<'static, CriticalSectionRawMutex, B, Lines, Delay>;
+pub type BlockingDevice = I2cDevice<'static, CriticalSectionRawMutex, I2c4>;
+/// A driver's bus, logged over RTT while tracing is on.
+pub type Traced<I> = trace::Traced<I, fn(&trace::Record), fn() -> u64>;
+
+/// Each probe only has to be acknowledged.
+const SCAN_TIMEOUT_MS: u32 = 10;
+/// The most one transaction on I2C1 or I2C2 moves, all its operations together.
+const BOUNCE_SIZE: usize = 256;
+
+static BUSES: critical_section::Mutex<Cell<Option<Buses>>> =
+    critical_section::Mutex::new(Cell::new(None));
+static TRACING: AtomicBool = AtomicBool::new(false);
+
+/// The GIGA R1 WiFi's three I2C buses.
+#[derive(Clone, Copy)]
+pub struct Buses {
+    /// D9 (SDA2) and D8 (SCL2).
+    pub i2c1: &'static Shared<I2c1>,
+    /// D20 (SDA) and D21 (SCL).
+    pub i2c2: &'static Shared<I2c2>,
+    /// SDA1 and SCL1, by AREF. A target instead with the `i2c-target` feature, see [`target`].
+    #[cfg(not(feature = "i2c-target"))]
+    i2c4: &'static blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<I2c4>>,
+}
+
+impl Buses {
+    #[cfg(not(feature = "i2c-target"))]
+    pub fn i2c4(&self) -> BlockingDevice {
+        I2cDevice::new(self.i2c4)
+    }
+}
+
+pub fn init(
+    i2c1: I2c1Resource,
+    i2c2: I2c2Resource,
+    #[cfg(not(feature = "i2c-target"))] i2c4: I2c4Resource,
+) -> Buses {
+    static I2C1: StaticCell<Shared<I2c1>> = StaticCell::new();
+    static I2C2: StaticCell<Shared<I2c2>> = StaticCell::new();
+    // Not StaticCells: AXI SRAM isn't initialized at boot, which would leave their flags random.
+    #[link_section = ".axisram"]
+    static mut BOUNCE1: [u8; BOUNCE_SIZE] = [0; BOUNCE_SIZE];
+    #[link_section = ".axisram"]
+    static mut BOUNCE2: [u8; BOUNCE_SIZE] = [0; BOUNCE_SIZE];
+
+    // Taken first, so a second `init` panics before the buffers are borrowed again.
+    let cell1 = I2C1.uninit();
+    let cell2 = I2C2.uninit();
+    // SAFETY: only borrowed here, and the cells above make sure this runs once.
+    let (bounce1, bounce2) = unsafe {
+        (
+            &mut *core::ptr::addr_of_mut!(BOUNCE1),
+            &mut *core::ptr::addr_of_mut!(BOUNCE2),
+        )
+    };
+
+    let bus = I2c::new(
+        i2c1.peri,
+        i2c1.scl,
+        i2c1.sda,
+        Irqs,
+        i2c1.tx_dma,
+        i2c1.rx_dma,
+        FREQUENCY,
+        Default::default(),
+    );
+    let lines = Lines::new(pac::I2C1, (pac::GPIOB, 8), (pac::GPIOB, 9));
+    let i2c1: &'static Shared<I2c1> = cell1.write(Shared::new(
+        bounce::Bounce::new(bus, bounce1),
+        lines,
+        FREQUENCY.0,
+    ));
+
+    let bus = I2c::new(
+        i2c2.peri,
+        i2c2.scl,
+        i2c2.sda,
+        Irqs,
+        i2c2.tx_dma,
+        i2c2.rx_dma,
+        FREQUENCY,
+        Default::default(),
+    );
+    let lines = Lines::new(pac::I2C2, (pac::GPIOH, 4), (pac::GPIOB, 11));
+    let i2c2: &'static Shared<I2c2> = cell2.write(Shared::new(
+        bounce::Bounce::new(bus, bounce2),
+        lines,
+        FREQUENCY.0,
+    ));
+
+    #[cfg(not(feature = "i2c-target"))]
+    let buses = Buses {
+        i2c1,
+        i2c2,
+        i2c4: init_i2c4(i2c4),
+    };
+    #[cfg(feature = "i2c-target")]
+    let buses = Buses { i2c1, i2c2 };
+    critical_section::with(|cs| BUSES.borrow(cs).set(Some(buses)));
+    buses
+}
+
+#[cfg(not(feature = "i2c-target"))]
+fn init_i2c4(
+    i2c4: I2c4Resource,
+) -> &'static blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<I2c4>> {
+    static I2C4: StaticCell<blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<I2c4>>> =
+        StaticCell::new();
+    let bus = I2c::new(
+        i2c4.peri,
+        i2c4.scl,
+        i2c4.sda,
+        I2c4Irqs,
+        NoDma,
+        NoDma,
+        FREQUENCY,
+        Default::default(),
+    );
+    I2C4.init(blocking_mutex::Mutex::new(RefCell::new(bus)))
+}
+
+/// Scan I2C1, I2C2 or I2C4 and name what answered.
+pub async fn survey(n: u8, probe: Probe) -> Result<Vec<(u8, Option<&'static Known>)>, ErrorKind> {
+    let Some(buses) = critical_section::with(|cs| BUSES.borrow(cs).get()) else {
+        return Err(ErrorKind::Other);
+    };
+    let result = match n {
+        1 => scan::survey(&mut buses.i2c1.device(Delay, SCAN_TIMEOUT_MS), probe)
+            .await
+            .map_err(|e| e.kind()),
+        2 => scan::survey(&mut buses.i2c2.device(Delay, SCAN_TIMEOUT_MS), probe)
+            .await
+            .map_err(|e| e.kind()),
+        #[cfg(not(feature = "i2c-target"))]
+        4 => scan::survey(&mut BlockingAsync::new(buses.i2c4()), probe)
+            .await
+            .map_err(|e| e.kind()),
+        _ => Err(ErrorKind::Other),
+    };
+    if let Ok(devices) = &result {
+        info!("i2c: {} devices on I2C{}", devices.len(), n);
+    }
+    result
+}
+
+/// Log every transaction of the drivers on [`traced`] buses, or stop.
+pub fn set_tracing(on: bool) {
+    TRACING.store(on, Ordering::Relaxed);
+}
+
+pub fn traced<I: embedded_hal_async::i2c::I2c>(bus: I) -> Traced<I> {
+    trace::Traced::new(bus, log as fn(&trace::Record), uptime_us as fn() -> u64)
+}
+
+fn log(record: &trace::Record) {
+    if TRACING.load(Ordering::Relaxed) {
+        info!("i2c: {}", defmt::Display2Format(record));
+    }
+}
+
+fn uptime_us() -> u64 {
+    Instant::now().as_micros()
+}
+
+/// SCL and SDA of one peripheral, as `(port, pin)` matching its resource in `board`.
+pub struct Lines {
+    regs: pac::i2c::I2c,
+    scl: (pac::gpio::Gpio, usize),
+    sda: (pac::gpio::Gpio, usize),
+}
+
+impl Lines {
+    fn new(
+        regs: pac::i2c::I2c,
+        scl: (pac::gpio::Gpio, usize),
+        sda: (pac::gpio::Gpio, usize),
+    ) -> Self {
+        Self { regs, scl, sda }
+    }
+
+    fn set((port, pin): (pac::gpio::Gpio, usize), high: bool) {
+        port.bsrr().write(|w| {
+            if high {
+                w.set_bs(pin, true)
+            } else {
+                w.set_br(pin, true)
+            }
+        });
+    }
+
+    fn set_mode(&self, mode: Moder) {
+        critical_section::with(|_| {
+            for (port, pin) in [self.scl, self.sda] {
+                port.moder().modify(|w| w.set_moder(pin, mode));
+            }
+        });
+    }
+}
+
+impl recovery::Lines for Lines {
+    fn take(&mut self) {
+        // Already open-drain, from the alternate function setup.
+        Self::set(self.scl, true);
+        Self::set(self.sda, true);
+        self.set_mode(Moder::OUTPUT);
+    }
+
+    fn set_scl(&mut self, high: bool) {
+        Self::set(self.scl, high);
+    }
+
+    fn set_sda(&mut self, high: bool) {
+        Self::set(self.sda, high);
+    }
+
+    fn sda(&mut self) -> bool {
+        let (port, pin) = self.sda;
+        port.idr().read().idr(pin) == Idr::HIGH
+    }
+
+    fn restore(&mut self) {
+        self.set_mode(Moder::ALTERNATE);
+        // Clearing PE resets the peripheral's state machine and flags, keeping its timing. Reading
+        // it back covers the three APB clocks it must stay low.
+        self.regs.cr1().modify(|w| w.set_pe(false));
+        while self.regs.cr1().read().pe() {}
+        self.regs.cr1().modify(|w| w.set_pe(true));
+    }
+}
@@ -0,0 +1,96 @@
+//! Freeing a bus that a target holds.
+//!
+//! A target that was reset, or lost clocks, in the middle of a read keeps driving SDA low while
+//! it waits for the rest of its byte. Clocking SCL until it lets go, at most nine times for eight
+//! data bits and the acknowledge, then sending a STOP, brings it back to idle.
+
+use embedded_hal_async::delay::DelayNs;
+
+/// Enough for any target to finish its byte.
+pub const PULSES: u8 = 9;
+
+/// Direct control of a bus's lines, both open-drain.
+pub trait Lines {
+    /// Take SCL and SDA from the peripheral as outputs, released high.
+    fn take(&mut self);
+    fn set_scl(&mut self, high: bool);
+    fn set_sda(&mut self, high: bool);
+    /// SDA as seen on the pin, whoever drives it.
+    fn sda(&mut self) -> bool;
+    /// Give the lines back to the peripheral and reset it.
+    fn restore(&mut self);
+}
+
+/// Clock SCL until SDA is released and send a STOP, spending `half_period_us` on each level.
+/// Returns the pulses it took, or `None` if SDA is still held low, by a target that doesn't
+/// recover this way or by a short. The lines are given back to the peripheral either way.
+pub async fn recover<L: Lines, D: DelayNs>(
+    lines: &mut L,
+    delay: &mut D,
+    half_period_us: u32,
+) -> Option<u8> {
+    lines.take();
+    let mut pulses = 0;
+    while !lines.sda() && pulses < PULSES {
+        lines.set_scl(false);
+        delay.delay_us(half_period_us).await;
+        lines.set_scl(true);
+        delay.delay_us(half_period_us).await;
+        pulses += 1;
+    }
+    let released = lines.sda();
+    if released {
+        // SDA rises while SCL is high.
+        lines.set_scl(false);
+        lines.set_sda(false);
+        delay.delay_us(half_period_us).await;
+        lines.set_scl(true);
+        delay.delay_us(half_period_us).await;
+        lines.set_sda(true);
+        delay.delay_us(half_period_us).await;
+    }
+    lines.restore();
+    released.then_some(pulses)
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::sim::{setup, Ticks};
+    use embassy_futures::block_on;
+
+    #[test]
+    fn clocks_until_released() {
+        let (wire, _, mut lines) = setup();
+        wire.borrow_mut().held = 3;
+        assert_eq!(block_on(recover(&mut lines, &mut Ticks, 5)), Some(3));
+        {
+            let wire = wire.borrow();
+            assert_eq!((wire.pulses, wire.stops, wire.restored), (3, 1, 1));
+            assert!(!wire.taken);
+        }
+
+        // An idle bus only gets the STOP.
+        let (wire, _, mut lines) = setup();
+        assert_eq!(block_on(recover(&mut lines, &mut Ticks, 5)), Some(0));
+        assert_eq!((wire.borrow().pulses, wire.borrow().stops), (0, 1));
+    }
+
+    #[test]
+    fn gives_up_after_nine_clocks() {
+        let (wire, _, mut lines) = setup();
+        wire.borrow_mut().held = u8::MAX;
+        assert_eq!(block_on(recover(&mut lines, &mut Ticks, 5)), None);
+        let (pulses, stops, restored) = {
+            let wire = wire.borrow();
+            (wire.pulses, wire.stops, wire.restored)
+        };
+        assert_eq!((pulses, stops, restored), (PULSES, 0, 1));
+
+        let (wire, _, mut lines) = setup();
+        wire.borrow_mut().held = PULSES;
+        assert_eq!(block_on(recover(&mut lines, &mut Ticks, 5)), Some(PULSES));
+    }
+}
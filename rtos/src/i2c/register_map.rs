@@ -0,0 +1,319 @@
+//! Registers served to a controller, as an I2C target.
+//!
+//! The controller first writes the address of a register. Bytes it writes after that go to the
+//! register and the ones following it; after a repeated START, or in a later transaction, it
+//! reads from there instead. The address moves on after every byte, either way, and stops past
+//! the last register, where writes are dropped and reads return 0xff.
+
+/// Changes to report per transaction; further ones are written but not reported.
+pub const CHANGES: usize = 16;
+/// Read past the end of the map.
+pub const FILL: u8 = 0xff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Set by the application only.
+    ReadOnly,
+    ReadWrite,
+}
+
+/// A register the controller wrote a new value to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    pub register: u8,
+    pub old: u8,
+    pub new: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Our address, to be written to or read from.
+    AddressMatch {
+        read: bool,
+    },
+    Changed(Change),
+    /// The end of a transaction, with the STOP or a bus error.
+    Stop,
+}
+
+/// The registers, how the controller may access them, and what to call when it changes them.
+#[derive(Clone, Copy)]
+pub struct RegisterMap<const N: usize> {
+    access: [Access; N],
+    values: [u8; N],
+    on_change: [Option<fn(Change)>; N],
+    on_event: Option<fn(Event)>,
+}
+
+impl<const N: usize> Default for RegisterMap<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> RegisterMap<N> {
+    /// Every register read-only and zero.
+    pub const fn new() -> Self {
+        Self {
+            access: [Access::ReadOnly; N],
+            values: [0; N],
+            on_change: [None; N],
+            on_event: None,
+        }
+    }
+
+    pub const fn register(mut self, register: u8, access: Access, value: u8) -> Self {
+        self.access[register as usize] = access;
+        self.values[register as usize] = value;
+        self
+    }
+
+    /// Call `f` after a transaction that changed `register`.
+    pub const fn on_change(mut self, register: u8, f: fn(Change)) -> Self {
+        self.on_change[register as usize] = Some(f);
+        self
+    }
+
+    /// Call `f` with every event, after any `on_change` for it.
+    pub const fn on_event(mut self, f: fn(Event)) -> Self {
+        self.on_event = Some(f);
+        self
+    }
+
+    pub fn get(&self, register: u8) -> Option<u8> {
+        self.values.get(register as usize).copied()
+    }
+
+    /// Set registers from `first` on, whatever their access, as the application does.
+    pub fn set(&mut self, first: u8, bytes: &[u8]) {
+        for (value, byte) in self.values.iter_mut().skip(first as usize).zip(bytes) {
+            *value = *byte;
+        }
+    }
+
+    /// Call the callbacks for `event`.
+    pub fn dispatch(&self, event: Event) {
+        if let Event::Changed(change) = event {
+            if let Some(f) = self
+                .on_change
+                .get(change.register as usize)
+                .copied()
+                .flatten()
+            {
+                f(change);
+            }
+        }
+        if let Some(f) = self.on_event {
+            f(event);
+        }
+    }
+}
+
+/// The map and where the controller is in it, driven from the peripheral's interrupts.
+pub struct Target<const N: usize> {
+    pub map: RegisterMap<N>,
+    pointer: usize,
+    /// The next byte written is a register address.
+    addressing: bool,
+    changes: heapless::Vec<Change, CHANGES>,
+}
+
+impl<const N: usize> Target<N> {
+    pub const fn new(map: RegisterMap<N>) -> Self {
+        Self {
+            map,
+            pointer: 0,
+            addressing: false,
+            changes: heapless::Vec::new(),
+        }
+    }
+
+    pub fn address_match(&mut self, read: bool) -> Event {
+        self.addressing = !read;
+        Event::AddressMatch { read }
+    }
+
+    pub fn receive(&mut self, byte: u8) {
+        if self.addressing {
+            self.addressing = false;
+            self.pointer = byte as usize;
+            return;
+        }
+        let register = self.pointer;
+        self.pointer = self.pointer.saturating_add(1);
+        if register >= N || self.map.access[register] != Access::ReadWrite {
+            return;
+        }
+        let old = core::mem::replace(&mut self.map.values[register], byte);
+        let register = register as u8;
+        match self.changes.iter_mut().find(|c| c.register == register) {
+            Some(change) => change.new = byte,
+            None if old != byte => {
+                let _ = self.changes.push(Change {
+                    register,
+                    old,
+                    new: byte,
+                });
+            }
+            None => {}
+        }
+    }
+
+    /// The next byte to send. The peripheral asks for it before the controller acknowledges the
+    /// one before, see [`Self::nack`].
+    pub fn transmit(&mut self) -> u8 {
+        let byte = self.map.values.get(self.pointer).copied();
+        self.pointer = self.pointer.saturating_add(1);
+        byte.unwrap_or(FILL)
+    }
+
+    /// The controller didn't acknowledge, so the byte loaded last wasn't sent.
+    pub fn nack(&mut self) {
+        self.pointer = self.pointer.saturating_sub(1);
+    }
+
+    /// The changes the transaction made, each register's once, leaving out writes of the same
+    /// value and any that were undone.
+    pub fn stop(&mut self) -> heapless::Vec<Change, CHANGES> {
+        self.addressing = false;
+        let mut changes = core::mem::take(&mut self.changes);
+        changes.retain(|change| change.old != change.new);
+        changes
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use std::vec::Vec;
+
+    const MAP: RegisterMap<8> = RegisterMap::new()
+        .register(0, Access::ReadOnly, 0x47)
+        .register(1, Access::ReadOnly, 0x02)
+        .register(4, Access::ReadWrite, 0x10)
+        .register(5, Access::ReadWrite, 0x20)
+        .register(7, Access::ReadWrite, 0x40);
+
+    fn write(target: &mut Target<8>, bytes: &[u8]) -> Vec<Change> {
+        target.address_match(false);
+        for &byte in bytes {
+            target.receive(byte);
+        }
+        target.stop().into_iter().collect()
+    }
+
+    fn read(target: &mut Target<8>, len: usize) -> Vec<u8> {
+        target.address_match(true);
+        // The byte after the last is loaded, then not acknowledged.
+        let mut bytes = (0..=len).map(|_| target.transmit()).collect::<Vec<_>>();
+        bytes.pop();
+        target.nack();
+        target.stop();
+        bytes
+    }
+
+    #[test]
+    fn writes_auto_increment_and_skip_read_only_registers() {
+        let mut target = Target::new(MAP);
+        let changes = write(&mut target, &[3, 0xaa, 0xbb, 0x20, 0xcc, 0xdd]);
+        assert_eq!(
+            changes,
+            [
+                Change {
+                    register: 4,
+                    old: 0x10,
+                    new: 0xbb
+                },
+                Change {
+                    register: 7,
+                    old: 0x40,
+                    new: 0xdd
+                }
+            ]
+        );
+        // 3 and 6 are read-only, 5 kept its value.
+        assert_eq!(
+            (0..8)
+                .map(|r| target.map.get(r).unwrap())
+                .collect::<Vec<_>>(),
+            [0x47, 0x02, 0, 0, 0xbb, 0x20, 0, 0xdd]
+        );
+
+        // Past the end nothing is written.
+        assert_eq!(write(&mut target, &[7, 0x41, 0x42, 0x43]).len(), 1);
+        assert_eq!(target.map.get(7), Some(0x41));
+        assert_eq!(target.map.get(8), None);
+    }
+
+    #[test]
+    fn reads_from_the_register_written() {
+        let mut target = Target::new(MAP);
+        // A write of the register address alone, then a repeated START.
+        assert!(write(&mut target, &[0]).is_empty());
+        assert_eq!(read(&mut target, 2), [0x47, 0x02]);
+        // The next read carries on from there.
+        assert_eq!(read(&mut target, 3), [0, 0, 0x10]);
+        assert_eq!(read(&mut target, 4), [0x20, 0, 0x40, FILL]);
+
+        target.map.set(0, &[0x48, 0x03]);
+        write(&mut target, &[0]);
+        assert_eq!(read(&mut target, 1), [0x48]);
+    }
+
+    #[test]
+    fn reports_each_register_once() {
+        let mut target = Target::new(MAP);
+        target.address_match(false);
+        for byte in [4, 0x11, 0x20] {
+            target.receive(byte);
+        }
+        // Writes the same registers again without a STOP.
+        target.address_match(false);
+        for byte in [4, 0x12, 0x21] {
+            target.receive(byte);
+        }
+        target.address_match(false);
+        for byte in [5, 0x20] {
+            target.receive(byte);
+        }
+        assert_eq!(
+            target.stop().as_slice(),
+            [Change {
+                register: 4,
+                old: 0x10,
+                new: 0x12
+            }]
+        );
+    }
+
+    static CHANGED: AtomicU32 = AtomicU32::new(0);
+    static EVENTS: AtomicU32 = AtomicU32::new(0);
+
+    #[test]
+    fn dispatches_callbacks() {
+        let map = MAP
+            .on_change(4, |change| {
+                CHANGED.store(u32::from(change.new), Ordering::Relaxed);
+            })
+            .on_event(|_| {
+                EVENTS.fetch_add(1, Ordering::Relaxed);
+            });
+        let mut target = Target::new(map);
+        let mut events = Vec::new();
+        events.push(target.address_match(false));
+        for byte in [4, 0x99, 0x21] {
+            target.receive(byte);
+        }
+        events.extend(target.stop().into_iter().map(Event::Changed));
+        events.push(Event::Stop);
+        for &event in &events {
+            target.map.dispatch(event);
+        }
+        assert_eq!(events.len(), 4);
+        assert_eq!(CHANGED.load(Ordering::Relaxed), 0x99);
+        assert_eq!(EVENTS.load(Ordering::Relaxed), 4);
+    }
+}
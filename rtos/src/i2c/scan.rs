@@ -0,0 +1,169 @@
+//! Finding what answers on a bus.
+//!
+//! Each 7-bit address gets a transfer that no target should act on, and the ones that
+//! acknowledge their address are present. What the transfer is depends on [`Probe`]: a write of
+//! no data is harmless to most targets, but some EEPROMs take it as the start of a write, and some
+//! targets don't acknowledge it at all.
+
+use super::identify::{self, Known};
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+use embedded_hal_async::i2c::{Error as _, ErrorKind, I2c};
+
+/// 0x00 to 0x07 and 0x78 to 0x7f are reserved, for general call, CBUS, high speed mode and
+/// 10-bit addressing.
+pub const ADDRESSES: RangeInclusive<u8> = 0x08..=0x77;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    /// A write of no data.
+    Quick,
+    /// A read of one byte.
+    Read,
+    /// Reads where EEPROMs and their write protection sit, 0x30 to 0x37 and 0x50 to 0x5f, and
+    /// quick writes elsewhere, as `i2cdetect` does.
+    #[default]
+    Auto,
+}
+
+impl Probe {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Probe::Quick => "quick",
+            Probe::Read => "read",
+            Probe::Auto => "auto",
+        }
+    }
+
+    fn reads(&self, address: u8) -> bool {
+        match self {
+            Probe::Quick => false,
+            Probe::Read => true,
+            Probe::Auto => matches!(address, 0x30..=0x37 | 0x50..=0x5f),
+        }
+    }
+}
+
+/// Addresses that acknowledged.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Found(u128);
+
+impl Found {
+    pub fn insert(&mut self, address: u8) {
+        self.0 |= 1 << (address & 0x7f);
+    }
+
+    pub fn contains(&self, address: u8) -> bool {
+        address < 0x80 && self.0 & (1 << address) != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..0x80).filter(|&address| self.contains(address))
+    }
+}
+
+/// Probe each of `addresses`. A missing target doesn't acknowledge; anything else going wrong
+/// stops the scan, as it would on every address after.
+pub async fn scan<I: I2c>(
+    bus: &mut I,
+    probe: Probe,
+    addresses: RangeInclusive<u8>,
+) -> Result<Found, I::Error> {
+    let mut found = Found::default();
+    for address in addresses {
+        let result = if probe.reads(address) {
+            bus.read(address, &mut [0]).await
+        } else {
+            bus.write(address, &[]).await
+        };
+        match result {
+            Ok(()) => found.insert(address),
+            Err(e) if matches!(e.kind(), ErrorKind::NoAcknowledge(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(found)
+}
+
+/// Scan the whole bus and name what answered.
+pub async fn survey<I: I2c>(
+    bus: &mut I,
+    probe: Probe,
+) -> Result<Vec<(u8, Option<&'static Known>)>, I::Error> {
+    let found = scan(bus, probe, ADDRESSES).await?;
+    let mut devices = Vec::with_capacity(found.len());
+    for address in found.iter() {
+        devices.push((address, identify::identify(bus, address).await));
+    }
+    Ok(devices)
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::sim::Targets;
+    use embassy_futures::block_on;
+    use std::vec;
+
+    #[test]
+    fn finds_what_acknowledges() {
+        let mut bus = Targets::new(&[(0x32, &[]), (0x50, &[]), (0x76, &[(0xd0, 0x60)])]);
+        let found = block_on(scan(&mut bus, Probe::Auto, ADDRESSES)).unwrap();
+        assert_eq!(found.iter().collect::<Vec<_>>(), [0x32, 0x50, 0x76]);
+        assert_eq!(found.len(), 3);
+        // The EEPROM and 0x32 were read, the rest written to.
+        assert_eq!(
+            bus.reads,
+            [0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37]
+                .into_iter()
+                .chain(0x50..=0x5f)
+                .collect::<Vec<_>>()
+        );
+
+        let found = block_on(scan(&mut bus, Probe::Quick, 0x30..=0x40)).unwrap();
+        assert_eq!(found.iter().collect::<Vec<_>>(), [0x32]);
+        assert!(!found.contains(0x50) && !found.contains(0xff));
+    }
+
+    #[test]
+    fn stops_on_a_bus_error() {
+        let mut bus = Targets::new(&[(0x32, &[])]);
+        bus.fail_at = Some(0x20);
+        assert_eq!(
+            block_on(scan(&mut bus, Probe::Read, ADDRESSES)),
+            Err(ErrorKind::Bus)
+        );
+    }
+
+    #[test]
+    fn names_what_it_finds() {
+        let mut bus = Targets::new(&[
+            (0x32, &[]),
+            (0x68, &[(0x75, 0x68)]),
+            (0x76, &[(0xd0, 0x58)]),
+        ]);
+        let devices = block_on(survey(&mut bus, Probe::Auto)).unwrap();
+        let names = devices
+            .iter()
+            .map(|(address, known)| (*address, known.map(|k| k.name)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                (0x32, Some("RV-8803 RTC")),
+                (0x68, Some("MPU-6050 IMU")),
+                (0x76, Some("BMP280 pressure"))
+            ]
+        );
+    }
+}
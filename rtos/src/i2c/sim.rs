@@ -0,0 +1,214 @@
+//! An I2C bus for the tests: one target, which can hang a transaction or hold SDA low, seen both
+//! through the peripheral and through its lines.
+
+use super::recovery;
+use alloc::{rc::Rc, vec::Vec};
+use core::{cell::RefCell, future::pending};
+use embassy_futures::yield_now;
+use embedded_hal_async::{
+    delay::DelayNs,
+    i2c::{ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation},
+};
+
+pub const TARGET: u8 = 0x32;
+/// Polls a transfer takes, so another device can try to cut in.
+const TRANSFER_POLLS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Start,
+    Stop,
+}
+
+#[derive(Debug, Default)]
+pub struct Wire {
+    /// Clocks the target holds SDA low for, for good with `u8::MAX`.
+    pub held: u8,
+    /// The next transaction never ends, and leaves the target holding SDA for this many clocks.
+    pub hang: Option<u8>,
+    pub log: Vec<Event>,
+    pub taken: bool,
+    pub restored: u32,
+    /// Clocks sent while SDA was held.
+    pub pulses: u8,
+    pub stops: u32,
+    scl: bool,
+    sda: bool,
+}
+
+pub struct Peripheral(Rc<RefCell<Wire>>);
+
+pub struct Lines(Rc<RefCell<Wire>>);
+
+/// Counts milliseconds as polls, so transfers finish well inside a timeout.
+pub struct Ticks;
+
+impl DelayNs for Ticks {
+    async fn delay_ns(&mut self, ns: u32) {
+        for _ in 0..ns / 1_000_000 {
+            yield_now().await;
+        }
+    }
+}
+
+pub fn setup() -> (Rc<RefCell<Wire>>, Peripheral, Lines) {
+    let wire = Rc::new(RefCell::new(Wire {
+        scl: true,
+        sda: true,
+        ..Wire::default()
+    }));
+    (wire.clone(), Peripheral(wire.clone()), Lines(wire))
+}
+
+impl ErrorType for Peripheral {
+    type Error = ErrorKind;
+}
+
+impl I2c for Peripheral {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), ErrorKind> {
+        assert!(!self.0.borrow().taken);
+        self.0.borrow_mut().log.push(Event::Start);
+        let hang = self.0.borrow_mut().hang.take();
+        if let Some(held) = hang {
+            self.0.borrow_mut().held = held;
+            pending::<()>().await;
+        }
+        if self.0.borrow().held > 0 {
+            // Our START or first high bit isn't seen on SDA.
+            return Err(ErrorKind::ArbitrationLoss);
+        }
+        for _ in 0..TRANSFER_POLLS {
+            yield_now().await;
+        }
+        self.0.borrow_mut().log.push(Event::Stop);
+        if address != TARGET {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+        for operation in operations {
+            if let Operation::Read(buf) = operation {
+                buf.fill(address);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl recovery::Lines for Lines {
+    fn take(&mut self) {
+        let mut wire = self.0.borrow_mut();
+        wire.taken = true;
+        wire.scl = true;
+        wire.sda = true;
+    }
+
+    fn set_scl(&mut self, high: bool) {
+        let mut wire = self.0.borrow_mut();
+        assert!(wire.taken);
+        if high && !wire.scl && wire.held > 0 {
+            wire.pulses += 1;
+            if wire.held != u8::MAX {
+                wire.held -= 1;
+            }
+        }
+        wire.scl = high;
+    }
+
+    fn set_sda(&mut self, high: bool) {
+        let mut wire = self.0.borrow_mut();
+        assert!(wire.taken);
+        if high && !wire.sda && wire.scl && wire.held == 0 {
+            wire.stops += 1;
+        }
+        wire.sda = high;
+    }
+
+    fn sda(&mut self) -> bool {
+        let wire = self.0.borrow();
+        wire.sda && wire.held == 0
+    }
+
+    fn restore(&mut self) {
+        let mut wire = self.0.borrow_mut();
+        wire.taken = false;
+        wire.restored += 1;
+    }
+}
+
+/// Targets at their addresses, each with registers that read back after their address is
+/// written, for scans and identification.
+struct Target {
+    address: u8,
+    registers: Vec<(u8, u8)>,
+    pointer: u8,
+}
+
+pub struct Targets {
+    targets: Vec<Target>,
+    /// Addresses read from.
+    pub reads: Vec<u8>,
+    /// A transaction to this address fails with a bus error.
+    pub fail_at: Option<u8>,
+}
+
+impl Targets {
+    pub fn new(targets: &[(u8, &[(u8, u8)])]) -> Self {
+        Self {
+            targets: targets
+                .iter()
+                .map(|(address, registers)| Target {
+                    address: *address,
+                    registers: registers.to_vec(),
+                    pointer: 0,
+                })
+                .collect(),
+            reads: Vec::new(),
+            fail_at: None,
+        }
+    }
+}
+
+impl ErrorType for Targets {
+    type Error = ErrorKind;
+}
+
+impl I2c for Targets {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), ErrorKind> {
+        if self.fail_at == Some(address) {
+            return Err(ErrorKind::Bus);
+        }
+        if operations.iter().any(|op| matches!(op, Operation::Read(_))) {
+            self.reads.push(address);
+        }
+        let Some(target) = self.targets.iter_mut().find(|t| t.address == address) else {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        };
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    if let Some(&register) = bytes.first() {
+                        target.pointer = register;
+                    }
+                }
+                Operation::Read(buf) => {
+                    for byte in buf.iter_mut() {
+                        *byte = target
+                            .registers
+                            .iter()
+                            .find(|(register, _)| *register == target.pointer)
+                            .map_or(0, |(_, value)| *value);
+                        target.pointer = target.pointer.wrapping_add(1);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
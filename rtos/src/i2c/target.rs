@@ -0,0 +1,172 @@
+//! I2C4, on SDA1 and SCL1, as a target serving a [`RegisterMap`] to a host MCU, with the
+//! `i2c-target` feature. embassy only drives I2C as a controller, so this goes through the
+//! registers: the interrupts run the map's [`Target`] a byte at a time, holding SCL low until each
+//! byte is ready, and a task calls the map's callbacks after.
+
+use super::register_map::{Event, RegisterMap, Target};
+use crate::board::I2c4Resource;
+use core::cell::RefCell;
+use defmt::{info, unwrap};
+use embassy_executor::Spawner;
+use embassy_stm32::{
+    interrupt::{self, InterruptExt},
+    pac::{
+        self,
+        gpio::vals::{Moder, Ot, Pupdr},
+        i2c::vals::{Dir, Oamode},
+    },
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+
+pub const MAP_SIZE: usize = 64;
+pub type Map = RegisterMap<MAP_SIZE>;
+
+/// SCL1 and SDA1.
+const PINS: [usize; 2] = [11, 12];
+const AF_I2C4: u8 = 4;
+
+static TARGET: critical_section::Mutex<RefCell<Option<Target<MAP_SIZE>>>> =
+    critical_section::Mutex::new(RefCell::new(None));
+static EVENTS: Channel<CriticalSectionRawMutex, Event, 16> = Channel::new();
+
+/// Answer at the 7-bit `address` with `map`.
+pub fn spawn(spawner: &Spawner, _r: I2c4Resource, address: u8, map: Map) {
+    critical_section::with(|cs| TARGET.borrow(cs).replace(Some(Target::new(map))));
+
+    let regs = pac::I2C4;
+    critical_section::with(|_| {
+        pac::RCC.apb4enr().modify(|w| w.set_i2c4en(true));
+        for pin in PINS {
+            let port = pac::GPIOH;
+            port.afr(pin / 8).modify(|w| w.set_afr(pin % 8, AF_I2C4));
+            port.otyper().modify(|w| w.set_ot(pin, Ot::OPENDRAIN));
+            port.pupdr().modify(|w| w.set_pupdr(pin, Pupdr::PULLUP));
+            port.moder().modify(|w| w.set_moder(pin, Moder::ALTERNATE));
+        }
+    });
+
+    regs.cr1().modify(|w| w.set_pe(false));
+    // As a target only the data setup and hold times count: 800 and 320 ns from the 100 MHz
+    // PCLK4, enough for standard and fast mode.
+    regs.timingr().write(|w| {
+        w.set_presc(15);
+        w.set_scldel(4);
+        w.set_sdadel(2);
+    });
+    regs.oar1().write(|w| {
+        w.set_oa1(u16::from(address) << 1);
+        w.set_oa1mode(Oamode::BIT7);
+        w.set_oa1en(true);
+    });
+    regs.cr1().modify(|w| {
+        // NOSTRETCH stays clear: SCL is held low from the address match until it is cleared,
+        // and on each byte until it is read or written.
+        w.set_addrie(true);
+        w.set_rxie(true);
+        w.set_txie(true);
+        w.set_stopie(true);
+        w.set_nackie(true);
+        w.set_errie(true);
+        w.set_pe(true);
+    });
+    for irq in [interrupt::I2C4_EV, interrupt::I2C4_ER] {
+        irq.unpend();
+        // SAFETY: the handlers below only touch I2C4 and `TARGET`.
+        unsafe { irq.enable() };
+    }
+    info!("i2c: target at {:#04x} on I2C4", address);
+
+    unwrap!(spawner.spawn(target_task(map)));
+}
+
+/// Set registers from `first` on, as the application, whatever their access.
+pub fn set(first: u8, bytes: &[u8]) {
+    critical_section::with(|cs| {
+        if let Some(target) = TARGET.borrow_ref_mut(cs).as_mut() {
+            target.map.set(first, bytes);
+        }
+    });
+}
+
+pub fn get(register: u8) -> Option<u8> {
+    critical_section::with(|cs| {
+        TARGET
+            .borrow_ref(cs)
+            .as_ref()
+            .and_then(|target| target.map.get(register))
+    })
+}
+
+/// The callbacks run here rather than in the interrupt, from a copy of the map taken at start; its
+/// values are stale, but the callbacks don't change.
+#[embassy_executor::task]
+async fn target_task(map: Map) {
+    loop {
+        map.dispatch(EVENTS.receive().await);
+    }
+}
+
+/// A slow task loses events, never bytes.
+fn send(event: Event) {
+    let _ = EVENTS.try_send(event);
+}
+
+fn end(target: &mut Target<MAP_SIZE>) {
+    for change in target.stop() {
+        send(Event::Changed(change));
+    }
+    send(Event::Stop);
+}
+
+#[interrupt]
+fn I2C4_EV() {
+    let regs = pac::I2C4;
+    let isr = regs.isr().read();
+    critical_section::with(|cs| {
+        let mut target = TARGET.borrow_ref_mut(cs);
+        let Some(target) = target.as_mut() else {
+            return;
+        };
+        // In the order they happen, as several can be pending.
+        if isr.rxne() {
+            target.receive(regs.rxdr().read().rxdata());
+        }
+        if isr.nackf() {
+            target.nack();
+            regs.icr().write(|w| w.set_nackcf(true));
+        }
+        if isr.stopf() {
+            end(target);
+            regs.icr().write(|w| w.set_stopcf(true));
+        }
+        if isr.addr() {
+            let read = isr.dir() == Dir::READ;
+            if read {
+                // Drop a byte loaded for the last read and not sent, so the first comes from the
+                // register now addressed.
+                regs.isr().write(|w| w.set_txe(true));
+            }
+            send(target.address_match(read));
+            regs.icr().write(|w| w.set_addrcf(true));
+        } else if isr.txis() {
+            regs.txdr().write(|w| w.set_txdata(target.transmit()));
+        }
+    });
+}
+
+#[interrupt]
+fn I2C4_ER() {
+    let regs = pac::I2C4;
+    let isr = regs.isr().read();
+    regs.icr().write(|w| {
+        w.set_berrcf(isr.berr());
+        w.set_arlocf(isr.arlo());
+        w.set_ovrcf(isr.ovr());
+    });
+    // A misplaced START or STOP ends the transaction, and the peripheral releases the bus.
+    critical_section::with(|cs| {
+        if let Some(target) = TARGET.borrow_ref_mut(cs).as_mut() {
+            end(target);
+        }
+    });
+}
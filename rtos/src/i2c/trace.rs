@@ -0,0 +1,224 @@
+//! A record of every transaction a driver makes.
+//!
+//! [`Traced`] wraps any `embedded_hal_async` I2C bus and hands each transaction, once done, to a
+//! sink: the address, what was written and read, how long it took and how it ended, down to
+//! which byte wasn't acknowledged.
+
+use core::fmt;
+use embedded_hal_async::i2c::{
+    Error as _, ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation,
+};
+
+/// Bytes kept of each operation.
+pub const DATA: usize = 8;
+/// Operations kept of each transaction.
+pub const OPS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Op {
+    pub read: bool,
+    pub len: usize,
+    /// The first bytes. Empty for reads that failed.
+    pub data: heapless::Vec<u8, DATA>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    Error(ErrorKind),
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Ok => "OK",
+            Outcome::Error(kind) => describe(*kind),
+        }
+    }
+}
+
+pub fn describe(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address) => "NACK on the address",
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data) => "NACK on data",
+        ErrorKind::NoAcknowledge(_) => "NACK",
+        ErrorKind::ArbitrationLoss => "arbitration loss",
+        ErrorKind::Bus => "bus error",
+        ErrorKind::Overrun => "overrun",
+        _ => "error",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub address: u8,
+    /// The first [`OPS`] of `op_count`.
+    pub ops: heapless::Vec<Op, OPS>,
+    pub op_count: usize,
+    pub duration_us: u64,
+    pub outcome: Outcome,
+}
+
+impl Record {
+    fn new(address: u8, operations: &[Operation<'_>], outcome: Outcome, duration_us: u64) -> Self {
+        let ops = operations
+            .iter()
+            .take(OPS)
+            .map(|operation| {
+                let (read, bytes): (bool, &[u8]) = match operation {
+                    Operation::Read(buf) => (true, buf),
+                    Operation::Write(bytes) => (false, bytes),
+                };
+                let kept = if read && outcome != Outcome::Ok {
+                    &[][..]
+                } else {
+                    &bytes[..bytes.len().min(DATA)]
+                };
+                Op {
+                    read,
+                    len: bytes.len(),
+                    data: heapless::Vec::from_slice(kept).unwrap_or_default(),
+                }
+            })
+            .collect();
+        Self {
+            address,
+            ops,
+            op_count: operations.len(),
+            duration_us,
+            outcome,
+        }
+    }
+}
+
+/// `0x32 W 10, R 00 59 23 19 05 05 26: OK, 230 us`. Bytes not kept are counted in brackets, as
+/// are reads that failed.
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#04x}", self.address)?;
+        for (i, op) in self.ops.iter().enumerate() {
+            f.write_str(if i == 0 { " " } else { ", " })?;
+            f.write_str(if op.read { "R" } else { "W" })?;
+            for byte in &op.data {
+                write!(f, " {byte:02x}")?;
+            }
+            if op.data.len() < op.len || op.len == 0 {
+                write!(f, " ({})", op.len)?;
+            }
+        }
+        if self.op_count > self.ops.len() {
+            write!(f, ", {} more", self.op_count - self.ops.len())?;
+        }
+        write!(f, ": {}, {} us", self.outcome.as_str(), self.duration_us)
+    }
+}
+
+/// `bus`, handing each transaction to `sink`, timed by `now_us`.
+pub struct Traced<I, S, C> {
+    bus: I,
+    sink: S,
+    now_us: C,
+}
+
+impl<I, S, C> Traced<I, S, C> {
+    pub fn new(bus: I, sink: S, now_us: C) -> Self {
+        Self { bus, sink, now_us }
+    }
+}
+
+impl<I: I2c, S, C> ErrorType for Traced<I, S, C> {
+    type Error = I::Error;
+}
+
+impl<I: I2c, S: FnMut(&Record), C: FnMut() -> u64> I2c for Traced<I, S, C> {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let start = (self.now_us)();
+        let result = self.bus.transaction(address, operations).await;
+        let duration_us = (self.now_us)().saturating_sub(start);
+        let outcome = match &result {
+            Ok(()) => Outcome::Ok,
+            Err(e) => Outcome::Error(e.kind()),
+        };
+        (self.sink)(&Record::new(address, operations, outcome, duration_us));
+        result
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::sim::Targets;
+    use core::cell::{Cell, RefCell};
+    use embassy_futures::block_on;
+    use std::{string::ToString, vec, vec::Vec};
+
+    #[test]
+    fn formats_records() {
+        let lines = RefCell::new(Vec::new());
+        let clock = Cell::new(1_000u64);
+        let mut bus = Targets::new(&[(0x32, &[(0x10, 0x59)]), (0x50, &[])]);
+        let mut traced = Traced::new(
+            &mut bus,
+            |record: &Record| lines.borrow_mut().push(record.to_string()),
+            || {
+                clock.set(clock.get() + 115);
+                clock.get()
+            },
+        );
+
+        let mut buf = [0; 3];
+        block_on(traced.write_read(0x32, &[0x10], &mut buf)).unwrap();
+        block_on(traced.write(0x50, &[0; 12])).unwrap();
+        let _ = block_on(traced.write(0x33, &[]));
+        let _ = block_on(traced.read(0x33, &mut buf));
+
+        assert_eq!(
+            *lines.borrow(),
+            vec![
+                "0x32 W 10, R 59 00 00: OK, 115 us",
+                "0x50 W 00 00 00 00 00 00 00 00 (12): OK, 115 us",
+                "0x33 W (0): NACK on the address, 115 us",
+                "0x33 R (3): NACK on the address, 115 us",
+            ]
+        );
+    }
+
+    #[test]
+    fn records_failures() {
+        let records = RefCell::new(Vec::new());
+        let mut bus = Targets::new(&[(0x32, &[])]);
+        bus.fail_at = Some(0x32);
+        let mut traced = Traced::new(
+            &mut bus,
+            |r: &Record| records.borrow_mut().push(r.clone()),
+            || 0,
+        );
+        let mut buf = [0; 2];
+        let mut ops = [
+            Operation::Write(&[1]),
+            Operation::Read(&mut buf),
+            Operation::Write(&[2]),
+            Operation::Write(&[3]),
+            Operation::Write(&[4]),
+        ];
+        assert_eq!(
+            block_on(traced.transaction(0x32, &mut ops)),
+            Err(ErrorKind::Bus)
+        );
+
+        let record = &records.borrow()[0];
+        assert_eq!(record.outcome, Outcome::Error(ErrorKind::Bus));
+        assert_eq!((record.ops.len(), record.op_count), (OPS, 5));
+        assert!(record.ops[1].read && record.ops[1].data.is_empty());
+        assert_eq!(
+            record.to_string(),
+            "0x32 W 01, R (2), W 02, W 03, 1 more: bus error, 0 us"
+        );
+    }
+}
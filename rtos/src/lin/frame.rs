@@ -0,0 +1,291 @@
+use alloc::vec::Vec;
+
+/// The byte after the break.
+pub const SYNC: u8 = 0x55;
+/// Identifiers are 6 bits.
+pub const MAX_ID: u8 = 0x3f;
+pub const MAX_DATA: usize = 8;
+/// Master request and slave response diagnostic frames, always with the classic checksum.
+pub const MASTER_REQUEST: u8 = 0x3c;
+pub const SLAVE_RESPONSE: u8 = 0x3d;
+
+/// Break (13 bits), delimiter, sync and PID fields, nominal.
+const HEADER_BITS: u32 = 34;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The PID parity bits don't match the identifier.
+    Parity,
+    Checksum,
+    /// No response at all.
+    NoResponse,
+    /// Part of a response, cut off by the end of the frame slot.
+    Incomplete,
+    /// What we sent didn't come back from the bus: another node drove it, or there is no
+    /// transceiver.
+    Bit,
+    /// Something other than the sync byte followed the break.
+    Sync,
+}
+
+impl FrameError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FrameError::Parity => "PID parity error",
+            FrameError::Checksum => "checksum error",
+            FrameError::NoResponse => "no response",
+            FrameError::Incomplete => "incomplete response",
+            FrameError::Bit => "bit error",
+            FrameError::Sync => "sync error",
+        }
+    }
+}
+
+/// Protected identifier: `id` with its two parity bits on top.
+pub fn protected_id(id: u8) -> u8 {
+    let id = id & MAX_ID;
+    let bit = |n: u8| (id >> n) & 1;
+    let p0 = bit(0) ^ bit(1) ^ bit(2) ^ bit(4);
+    let p1 = !(bit(1) ^ bit(3) ^ bit(4) ^ bit(5)) & 1;
+    id | p0 << 6 | p1 << 7
+}
+
+/// The identifier in a protected identifier, if its parity is right.
+pub fn parse_pid(pid: u8) -> Result<u8, FrameError> {
+    let id = pid & MAX_ID;
+    if protected_id(id) == pid {
+        Ok(id)
+    } else {
+        Err(FrameError::Parity)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumModel {
+    /// LIN 1.x: the data bytes only.
+    Classic,
+    /// LIN 2.x: the protected identifier and the data bytes.
+    Enhanced,
+}
+
+/// Inverted eight-bit sum with carry.
+pub fn checksum(model: ChecksumModel, pid: u8, data: &[u8]) -> u8 {
+    let first = match model {
+        ChecksumModel::Classic => 0,
+        ChecksumModel::Enhanced => pid as u16,
+    };
+    let sum = data.iter().fold(first, |sum, &b| {
+        let sum = sum + b as u16;
+        if sum > 0xff {
+            sum - 0xff
+        } else {
+            sum
+        }
+    });
+    !(sum as u8)
+}
+
+/// Longest a frame with `len` data bytes may take: 140% of its nominal time.
+pub fn max_frame_time_us(len: usize, baudrate: u32) -> u32 {
+    let bits = HEADER_BITS + 10 * (len as u32 + 1);
+    (bits as u64 * 14 * 1_000_000).div_ceil(10 * baudrate as u64) as u32
+}
+
+/// Who sends a frame's response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Publisher {
+    Master,
+    Slave,
+}
+
+/// One entry of the frame-definition table, shared by master and slaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameDef {
+    pub id: u8,
+    pub len: u8,
+    pub checksum: ChecksumModel,
+    pub publisher: Publisher,
+}
+
+impl FrameDef {
+    /// A LIN 2.x frame, with the classic checksum for the diagnostic identifiers.
+    pub const fn new(id: u8, len: u8, publisher: Publisher) -> Self {
+        let checksum = if id == MASTER_REQUEST || id == SLAVE_RESPONSE {
+            ChecksumModel::Classic
+        } else {
+            ChecksumModel::Enhanced
+        };
+        Self {
+            id,
+            len,
+            checksum,
+            publisher,
+        }
+    }
+
+    pub fn pid(&self) -> u8 {
+        protected_id(self.id)
+    }
+
+    /// The response: data and checksum.
+    pub fn encode(&self, data: &[u8], out: &mut impl Extend<u8>) {
+        let data = &data[..self.len as usize];
+        out.extend(data.iter().copied());
+        out.extend([checksum(self.checksum, self.pid(), data)]);
+    }
+
+    /// Check a response of data and checksum, returning the data.
+    pub fn decode<'a>(&self, response: &'a [u8]) -> Result<&'a [u8], FrameError> {
+        let len = self.len as usize;
+        if response.len() <= len {
+            return Err(match response.len() {
+                0 => FrameError::NoResponse,
+                _ => FrameError::Incomplete,
+            });
+        }
+        let data = &response[..len];
+        if checksum(self.checksum, self.pid(), data) != response[len] {
+            return Err(FrameError::Checksum);
+        }
+        Ok(data)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub def: FrameDef,
+    pub data: [u8; MAX_DATA],
+    /// Set when a response was received, cleared by [`Frames::take_updated`].
+    pub updated: bool,
+}
+
+/// The frame-definition table with the latest data of every frame: what this node publishes,
+/// and what it last received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frames {
+    frames: Vec<Frame>,
+}
+
+impl Frames {
+    pub fn new(defs: &[FrameDef]) -> Self {
+        let frames = defs
+            .iter()
+            .map(|&def| Frame {
+                def,
+                data: [0; MAX_DATA],
+                updated: false,
+            })
+            .collect();
+        Self { frames }
+    }
+
+    pub fn get(&self, id: u8) -> Option<&Frame> {
+        self.frames.iter().find(|f| f.def.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: u8) -> Option<&mut Frame> {
+        self.frames.iter_mut().find(|f| f.def.id == id)
+    }
+
+    /// Set the data this node publishes in frame `id`. Returns false for an unknown frame.
+    pub fn set(&mut self, id: u8, data: &[u8]) -> bool {
+        match self.get_mut(id) {
+            Some(frame) => {
+                let len = data.len().min(MAX_DATA);
+                frame.data[..len].copy_from_slice(&data[..len]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Store a received response.
+    pub fn receive(&mut self, id: u8, data: &[u8]) {
+        if let Some(frame) = self.get_mut(id) {
+            frame.data[..data.len()].copy_from_slice(data);
+            frame.updated = true;
+        }
+    }
+
+    /// Frames received since the last call.
+    pub fn take_updated(&mut self) -> impl Iterator<Item = &Frame> {
+        self.frames
+            .iter_mut()
+            .filter_map(|f| core::mem::take(&mut f.updated).then_some(&*f))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Frame> {
+        self.frames.iter()
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn protected_identifiers() {
+        let pids: Vec<u8> = [0x00, 0x01, 0x10, 0x20, 0x3c, 0x3d, 0x3f]
+            .iter()
+            .map(|&id| protected_id(id))
+            .collect();
+        assert_eq!(pids, [0x80, 0xc1, 0x50, 0x20, 0x3c, 0x7d, 0xbf]);
+        for id in 0..=MAX_ID {
+            assert_eq!(parse_pid(protected_id(id)), Ok(id));
+            assert_eq!(parse_pid(protected_id(id) ^ 0x40), Err(FrameError::Parity));
+        }
+    }
+
+    #[test]
+    fn checksums() {
+        // The worked example in the LIN 2.x specification.
+        assert_eq!(
+            checksum(ChecksumModel::Enhanced, 0x4a, &[0x55, 0x93, 0xe5]),
+            0xe6
+        );
+        assert_eq!(
+            checksum(ChecksumModel::Classic, 0x4a, &[0x55, 0x93, 0xe5]),
+            0x31
+        );
+        assert_eq!(checksum(ChecksumModel::Classic, 0, &[0xff, 0xff]), 0x00);
+
+        let def = FrameDef::new(0x0a, 3, Publisher::Slave);
+        assert_eq!(def.pid(), 0xca);
+        let mut response = Vec::new();
+        def.encode(&[0x55, 0x93, 0xe5, 0x00], &mut response);
+        assert_eq!(response, [0x55, 0x93, 0xe5, 0x66]);
+        assert_eq!(def.decode(&response), Ok(&response[..3]));
+        assert_eq!(def.decode(&response[..2]), Err(FrameError::Incomplete));
+        assert_eq!(def.decode(&[]), Err(FrameError::NoResponse));
+        response[1] ^= 1;
+        assert_eq!(def.decode(&response), Err(FrameError::Checksum));
+
+        // Diagnostic frames stay classic.
+        assert_eq!(
+            FrameDef::new(MASTER_REQUEST, 8, Publisher::Master).checksum,
+            ChecksumModel::Classic
+        );
+    }
+
+    #[test]
+    fn frame_table() {
+        let mut frames = Frames::new(&[
+            FrameDef::new(0x10, 2, Publisher::Master),
+            FrameDef::new(0x20, 4, Publisher::Slave),
+        ]);
+        assert!(frames.set(0x10, &[1, 2]));
+        assert!(!frames.set(0x11, &[1]));
+        frames.receive(0x20, &[9, 8, 7, 6]);
+        let updated: Vec<_> = frames.take_updated().map(|f| f.def.id).collect();
+        assert_eq!(updated, vec![0x20]);
+        assert_eq!(frames.take_updated().count(), 0);
+        assert_eq!(frames.get(0x20).unwrap().data[..4], [9, 8, 7, 6]);
+
+        // 19200 baud: 34 + 30 bits nominal, times 1.4.
+        assert_eq!(max_frame_time_us(2, 19200), 4667);
+        assert_eq!(max_frame_time_us(8, 19200), 9042);
+    }
+}
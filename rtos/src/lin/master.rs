@@ -0,0 +1,249 @@
+use super::frame::{max_frame_time_us, FrameError, Frames, Publisher, SYNC};
+use alloc::vec::Vec;
+use embassy_futures::select::select;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+/// Transmit side of a UART that can send a LIN break field.
+pub trait SendBreak {
+    /// Queue a break, sent before the next character.
+    fn send_break(&mut self);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Frame(FrameError),
+    /// The schedule names a frame missing from the frame table.
+    UnknownFrame(u8),
+    /// Write error on the UART.
+    Serial,
+}
+
+impl Error {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::Frame(e) => e.as_str(),
+            Error::UnknownFrame(_) => "unknown frame",
+            Error::Serial => "serial error",
+        }
+    }
+}
+
+impl From<FrameError> for Error {
+    fn from(e: FrameError) -> Self {
+        Error::Frame(e)
+    }
+}
+
+/// LIN master: sends headers, and the responses the master publishes, and reads the whole frame
+/// back from the bus. The transceiver echoes everything, so what we sent is checked too.
+pub struct Master<R, W, D> {
+    rx: R,
+    tx: W,
+    delay: D,
+    baudrate: u32,
+    bus: Vec<u8>,
+}
+
+impl<R: Read, W: Write + SendBreak, D: DelayNs> Master<R, W, D> {
+    pub fn new(rx: R, tx: W, delay: D, baudrate: u32) -> Self {
+        Self {
+            rx,
+            tx,
+            delay,
+            baudrate,
+            bus: Vec::new(),
+        }
+    }
+
+    /// Run frame `id`. A response from a slave is stored in `frames`.
+    pub async fn transfer(&mut self, frames: &mut Frames, id: u8) -> Result<(), Error> {
+        let def = frames.get(id).ok_or(Error::UnknownFrame(id))?.def;
+        let pid = def.pid();
+        let mut sent = Vec::new();
+        if def.publisher == Publisher::Master {
+            def.encode(&frames.get(id).unwrap().data, &mut sent);
+        }
+
+        self.tx.send_break();
+        let header = [SYNC, pid];
+        self.tx
+            .write_all(&header)
+            .await
+            .map_err(|_| Error::Serial)?;
+        self.tx.write_all(&sent).await.map_err(|_| Error::Serial)?;
+        self.tx.flush().await.map_err(|_| Error::Serial)?;
+
+        let wanted = def.len as usize + 1;
+        let window = max_frame_time_us(def.len as usize, self.baudrate);
+        let Self { rx, delay, bus, .. } = self;
+        bus.clear();
+        let collect = async {
+            let mut byte = [0u8];
+            loop {
+                // The break comes back as a zero character with a framing error; skip errors.
+                if let Ok(1) = rx.read(&mut byte).await {
+                    bus.push(byte[0]);
+                }
+                if response(bus, &header).is_some_and(|r| r.len() >= wanted) {
+                    break;
+                }
+            }
+        };
+        // When the frame time is up, what arrived is all there is.
+        select(collect, delay.delay_us(window)).await;
+
+        let response = response(bus, &header).ok_or(FrameError::Bit)?;
+        let response = &response[..response.len().min(wanted)];
+        match def.publisher {
+            Publisher::Master if response == sent.as_slice() => Ok(()),
+            Publisher::Master => Err(FrameError::Bit.into()),
+            Publisher::Slave => {
+                let data = def.decode(response)?;
+                frames.receive(id, data);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// What followed the echo of `header` on the bus.
+fn response<'a>(bus: &'a [u8], header: &[u8]) -> Option<&'a [u8]> {
+    bus.windows(header.len())
+        .position(|w| w == header)
+        .map(|i| &bus[i + header.len()..])
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lin::{
+        frame::FrameDef,
+        slave::{Action, Event, Responder},
+    };
+    use alloc::{collections::VecDeque, rc::Rc};
+    use core::{cell::RefCell, convert::Infallible, future::pending};
+    use embassy_futures::block_on;
+    use embedded_io_async::ErrorType;
+
+    /// A bus with a transceiver echoing everything and one slave on it.
+    struct Bus {
+        slave: Responder,
+        frames: Frames,
+        output: VecDeque<u8>,
+        /// Flip a bit in the next character the slave sends.
+        corrupt: bool,
+    }
+
+    impl Bus {
+        fn put(&mut self, event: Event, echo: u8) {
+            self.output.push_back(echo);
+            if let Action::Respond(mut response) = self.slave.feed(&mut self.frames, event) {
+                if core::mem::take(&mut self.corrupt) {
+                    response[0] ^= 0x01;
+                }
+                for b in response {
+                    self.put(Event::Byte(b), b);
+                }
+            }
+        }
+    }
+
+    struct Port(Rc<RefCell<Bus>>);
+
+    impl ErrorType for Port {
+        type Error = Infallible;
+    }
+
+    impl Read for Port {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            let next = self.0.borrow_mut().output.pop_front();
+            match next {
+                Some(b) => {
+                    buf[0] = b;
+                    Ok(1)
+                }
+                None => pending().await,
+            }
+        }
+    }
+
+    impl Write for Port {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            for &b in buf {
+                self.0.borrow_mut().put(Event::Byte(b), b);
+            }
+            Ok(buf.len())
+        }
+    }
+
+    impl SendBreak for Port {
+        fn send_break(&mut self) {
+            self.0.borrow_mut().put(Event::Break, 0);
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    const DEFS: [FrameDef; 3] = [
+        FrameDef::new(0x10, 2, Publisher::Master),
+        FrameDef::new(0x20, 4, Publisher::Slave),
+        FrameDef::new(0x21, 1, Publisher::Slave),
+    ];
+
+    fn setup() -> (Rc<RefCell<Bus>>, Master<Port, Port, NoDelay>, Frames) {
+        // The slave doesn't know frame 0x21.
+        let mut slave = Frames::new(&DEFS[..2]);
+        slave.set(0x20, &[1, 2, 3, 4]);
+        let bus = Rc::new(RefCell::new(Bus {
+            slave: Responder::new(),
+            frames: slave,
+            output: VecDeque::new(),
+            corrupt: false,
+        }));
+        let master = Master::new(Port(bus.clone()), Port(bus.clone()), NoDelay, 19200);
+        (bus, master, Frames::new(&DEFS))
+    }
+
+    #[test]
+    fn runs_frames() {
+        let (bus, mut master, mut frames) = setup();
+
+        frames.set(0x10, &[0xab, 0xcd]);
+        assert_eq!(block_on(master.transfer(&mut frames, 0x10)), Ok(()));
+        assert_eq!(
+            bus.borrow().frames.get(0x10).unwrap().data[..2],
+            [0xab, 0xcd]
+        );
+
+        assert_eq!(block_on(master.transfer(&mut frames, 0x20)), Ok(()));
+        assert_eq!(frames.get(0x20).unwrap().data[..4], [1, 2, 3, 4]);
+        assert!(frames.get(0x20).unwrap().updated);
+    }
+
+    #[test]
+    fn reports_failures() {
+        let (bus, mut master, mut frames) = setup();
+
+        assert_eq!(
+            block_on(master.transfer(&mut frames, 0x21)),
+            Err(Error::Frame(FrameError::NoResponse))
+        );
+        bus.borrow_mut().corrupt = true;
+        assert_eq!(
+            block_on(master.transfer(&mut frames, 0x20)),
+            Err(Error::Frame(FrameError::Checksum))
+        );
+        assert_eq!(
+            block_on(master.transfer(&mut frames, 0x30)),
+            Err(Error::UnknownFrame(0x30))
+        );
+        assert!(bus.borrow().output.is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    bridge::{now_ms, wait_until},
+    capture::Tap,
+    console::command::LinCommand,
+    uart::{self, SerialSettings},
+};
+use alloc::{format, string::String};
+use core::fmt::Write as _;
+use defmt::{info, warn};
+use embassy_embedded_hal::SetConfig;
+use embassy_futures::select::{select, Either};
+use embassy_stm32::{
+    pac,
+    peripherals::USART1,
+    usart::{self as usart_hal, BufferedUartRx, BufferedUartTx},
+};
+use embassy_time::Delay;
+use embedded_io_async::{ErrorType, Read, Write};
+
+pub mod frame;
+pub mod master;
+pub mod schedule;
+pub mod slave;
+
+pub use frame::{ChecksumModel, FrameDef, FrameError, Frames, Publisher};
+pub use master::{Master, SendBreak};
+pub use schedule::{Schedule, Slot};
+pub use slave::{Action, Event, Responder};
+
+pub const LIN_SETTINGS: SerialSettings = SerialSettings::new(19200);
+
+/// Frames of the console's `lin` commands: the master publishes a counter in 0x10, the slave
+/// answers 0x20 with the number of 0x10 frames it received and the last counter value.
+pub const FRAMES: [FrameDef; 2] = [
+    FrameDef::new(0x10, 2, Publisher::Master),
+    FrameDef::new(0x20, 4, Publisher::Slave),
+];
+pub const SCHEDULE: [Slot; 2] = [
+    Slot {
+        id: 0x10,
+        slot_ms: 20,
+    },
+    Slot {
+        id: 0x20,
+        slot_ms: 30,
+    },
+];
+
+/// USART1's transmit half with break generation.
+struct BreakTx<'a>(&'a mut Tap<BufferedUartTx<'static, USART1>>);
+
+impl ErrorType for BreakTx<'_> {
+    type Error = usart_hal::Error;
+}
+
+impl Write for BreakTx<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.0.write(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush().await
+    }
+}
+
+impl SendBreak for BreakTx<'_> {
+    fn send_break(&mut self) {
+        uart::send_break(pac::USART1);
+    }
+}
+
+/// Run a console `lin` command on USART1 in LIN mode at [`LIN_SETTINGS`] until a key is pressed
+/// on `host_rx`, reporting received frames and errors to `host_tx`. USART1 is put back to
+/// `restore` afterwards.
+pub async fn run_command<HR: Read, HW: Write>(
+    rx: &mut Tap<BufferedUartRx<'static, USART1>>,
+    tx: &mut Tap<BufferedUartTx<'static, USART1>>,
+    host_rx: &mut HR,
+    host_tx: &mut HW,
+    command: LinCommand,
+    restore: &SerialSettings,
+) -> Result<(), HW::Error> {
+    info!("lin: {}", defmt::Debug2Format(&command));
+    if let Err(e) = rx.set_config(&(&LIN_SETTINGS).into()) {
+        warn!("lin: UART config failed: {}", defmt::Debug2Format(&e));
+    }
+    uart::set_lin_mode(pac::USART1, true);
+
+    let text = format!(
+        "LIN {} at {} baud, any key stops\r\n",
+        match command {
+            LinCommand::Master => "master",
+            LinCommand::Slave => "slave",
+        },
+        LIN_SETTINGS.baudrate
+    );
+    host_tx.write_all(text.as_bytes()).await?;
+    host_tx.flush().await?;
+
+    let mut frames = Frames::new(&FRAMES);
+    let mut key = [0u8];
+    let result = match command {
+        LinCommand::Master => {
+            let node = run_master(&mut *rx, &mut *tx, &mut frames, host_tx);
+            select(node, host_rx.read(&mut key)).await
+        }
+        LinCommand::Slave => {
+            let node = run_slave(&mut *rx, &mut *tx, &mut frames, host_tx);
+            select(node, host_rx.read(&mut key)).await
+        }
+    };
+
+    uart::set_lin_mode(pac::USART1, false);
+    if let Err(e) = rx.set_config(&restore.into()) {
+        warn!(
+            "lin: failed to restore UART config: {}",
+            defmt::Debug2Format(&e)
+        );
+    }
+
+    match result {
+        Either::First(Err(e)) => return Err(e),
+        Either::First(Ok(())) | Either::Second(_) => {}
+    }
+    host_tx.write_all(b"OK\r\n").await?;
+    host_tx.flush().await
+}
+
+async fn run_master<HW: Write>(
+    rx: &mut Tap<BufferedUartRx<'static, USART1>>,
+    tx: &mut Tap<BufferedUartTx<'static, USART1>>,
+    frames: &mut Frames,
+    host_tx: &mut HW,
+) -> Result<(), HW::Error> {
+    let mut schedule = match Schedule::new(&SCHEDULE, frames, LIN_SETTINGS.baudrate) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            let text = format!("ERROR: {}\r\n", e.as_str());
+            return host_tx.write_all(text.as_bytes()).await;
+        }
+    };
+    let mut master = Master::new(rx, BreakTx(tx), Delay, LIN_SETTINGS.baudrate);
+    let mut counter = 0u16;
+
+    loop {
+        let (due, id) = schedule.next(now_ms());
+        wait_until(Some(due)).await;
+        if id == FRAMES[0].id {
+            counter = counter.wrapping_add(1);
+            frames.set(id, &counter.to_le_bytes());
+        }
+        if let Err(e) = master.transfer(frames, id).await {
+            let text = format!("0x{id:02x}: {}\r\n", e.as_str());
+            host_tx.write_all(text.as_bytes()).await?;
+        }
+        report(frames, host_tx).await?;
+    }
+}
+
+async fn run_slave<HW: Write>(
+    rx: &mut Tap<BufferedUartRx<'static, USART1>>,
+    tx: &mut Tap<BufferedUartTx<'static, USART1>>,
+    frames: &mut Frames,
+    host_tx: &mut HW,
+) -> Result<(), HW::Error> {
+    let mut responder = Responder::new();
+    let mut received = 0u16;
+    let mut byte = [0u8];
+
+    loop {
+        let event = match rx.read(&mut byte).await {
+            // The break arrives as a zero character, usually with a framing error.
+            Err(usart_hal::Error::Framing) => {
+                uart::take_break(pac::USART1);
+                Event::Break
+            }
+            Ok(1) if byte[0] == 0 && uart::take_break(pac::USART1) => Event::Break,
+            Ok(1) => Event::Byte(byte[0]),
+            Ok(_) | Err(_) => continue,
+        };
+
+        match responder.feed(frames, event) {
+            Action::None => {}
+            Action::Respond(response) => {
+                if let Err(e) = tx.write_all(&response).await {
+                    warn!("lin: write failed: {}", defmt::Debug2Format(&e));
+                }
+                let _ = tx.flush().await;
+            }
+            Action::Received(id) => {
+                received = received.wrapping_add(1);
+                let counter = frames.get(id).map_or([0; 2], |f| [f.data[0], f.data[1]]);
+                let status = received.to_le_bytes();
+                frames.set(
+                    FRAMES[1].id,
+                    &[status[0], status[1], counter[0], counter[1]],
+                );
+                report(frames, host_tx).await?;
+            }
+            Action::Error(e) => {
+                let text = format!("{}\r\n", e.as_str());
+                host_tx.write_all(text.as_bytes()).await?;
+            }
+        }
+    }
+}
+
+/// Print the frames received since the last report.
+async fn report<HW: Write>(frames: &mut Frames, host_tx: &mut HW) -> Result<(), HW::Error> {
+    let mut text = String::new();
+    for frame in frames.take_updated() {
+        let _ = write!(text, "0x{:02x}:", frame.def.id);
+        for b in &frame.data[..frame.def.len as usize] {
+            let _ = write!(text, " {b:02x}");
+        }
+        text.push_str("\r\n");
+    }
+    if !text.is_empty() {
+        host_tx.write_all(text.as_bytes()).await?;
+    }
+    Ok(())
+}
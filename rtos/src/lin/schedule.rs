@@ -0,0 +1,148 @@
+use super::frame::{max_frame_time_us, Frames};
+
+/// One entry of a schedule table: frame `id`, then nothing else for the rest of the slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub id: u8,
+    pub slot_ms: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleError {
+    Empty,
+    /// A slot for a frame missing from the frame table.
+    UnknownFrame(u8),
+    /// The slot is shorter than the longest the frame may take.
+    SlotTooShort(u8),
+}
+
+impl ScheduleError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleError::Empty => "empty schedule",
+            ScheduleError::UnknownFrame(_) => "unknown frame in schedule",
+            ScheduleError::SlotTooShort(_) => "schedule slot too short",
+        }
+    }
+}
+
+/// Runs a schedule table round and round. Slots start a fixed time apart; when the master falls
+/// behind, the late slot starts at once and the ones after it are timed from there, instead of
+/// bunching up to catch up.
+#[derive(Debug, Clone)]
+pub struct Schedule<'a> {
+    slots: &'a [Slot],
+    next: usize,
+    due_ms: Option<u64>,
+}
+
+impl<'a> Schedule<'a> {
+    /// Check `slots` against the frame table at `baudrate`.
+    pub fn new(slots: &'a [Slot], frames: &Frames, baudrate: u32) -> Result<Self, ScheduleError> {
+        if slots.is_empty() {
+            return Err(ScheduleError::Empty);
+        }
+        for slot in slots {
+            let frame = frames
+                .get(slot.id)
+                .ok_or(ScheduleError::UnknownFrame(slot.id))?;
+            let needed = max_frame_time_us(frame.def.len as usize, baudrate);
+            if slot.slot_ms * 1000 < needed {
+                return Err(ScheduleError::SlotTooShort(slot.id));
+            }
+        }
+        Ok(Self {
+            slots,
+            next: 0,
+            due_ms: None,
+        })
+    }
+
+    /// The next frame and when to send its header; the first one is due at once.
+    pub fn next(&mut self, now_ms: u64) -> (u64, u8) {
+        let due = match self.due_ms {
+            Some(due) if due >= now_ms => due,
+            _ => now_ms,
+        };
+        let slot = self.slots[self.next];
+        self.next = (self.next + 1) % self.slots.len();
+        self.due_ms = Some(due + slot.slot_ms as u64);
+        (due, slot.id)
+    }
+
+    /// Start again from the first slot.
+    pub fn restart(&mut self) {
+        self.next = 0;
+        self.due_ms = None;
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lin::frame::{FrameDef, Publisher};
+
+    fn frames() -> Frames {
+        Frames::new(&[
+            FrameDef::new(0x10, 2, Publisher::Master),
+            FrameDef::new(0x20, 8, Publisher::Slave),
+        ])
+    }
+
+    #[test]
+    fn slot_timing() {
+        let slots = [
+            Slot {
+                id: 0x10,
+                slot_ms: 10,
+            },
+            Slot {
+                id: 0x20,
+                slot_ms: 20,
+            },
+        ];
+        let frames = frames();
+        let mut schedule = Schedule::new(&slots, &frames, 19200).unwrap();
+
+        assert_eq!(schedule.next(100), (100, 0x10));
+        // On time: the slot length, however early the master asks.
+        assert_eq!(schedule.next(101), (110, 0x20));
+        assert_eq!(schedule.next(115), (130, 0x10));
+        assert_eq!(schedule.next(130), (140, 0x20));
+        // Late: the slot starts now and the next one is timed from it.
+        assert_eq!(schedule.next(175), (175, 0x10));
+        assert_eq!(schedule.next(176), (185, 0x20));
+
+        schedule.restart();
+        assert_eq!(schedule.next(300), (300, 0x10));
+    }
+
+    #[test]
+    fn checks_table() {
+        let frames = frames();
+        assert_eq!(
+            Schedule::new(&[], &frames, 19200).err(),
+            Some(ScheduleError::Empty)
+        );
+        let unknown = [Slot {
+            id: 0x30,
+            slot_ms: 10,
+        }];
+        assert_eq!(
+            Schedule::new(&unknown, &frames, 19200).err(),
+            Some(ScheduleError::UnknownFrame(0x30))
+        );
+        // Eight bytes need 9.1 ms at 19200 baud, 18.1 ms at 9600.
+        let short = [Slot {
+            id: 0x20,
+            slot_ms: 10,
+        }];
+        assert!(Schedule::new(&short, &frames, 19200).is_ok());
+        assert_eq!(
+            Schedule::new(&short, &frames, 9600).err(),
+            Some(ScheduleError::SlotTooShort(0x20))
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use super::frame::{parse_pid, FrameDef, FrameError, Frames, Publisher, MAX_DATA, SYNC};
+use heapless::Vec;
+
+/// What the receiver saw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A break field, from the USART's break detection or a framing error on a zero byte.
+    Break,
+    Byte(u8),
+}
+
+/// What the slave should do next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    None,
+    /// Send this response now: data and checksum.
+    Respond(Vec<u8, { MAX_DATA + 1 }>),
+    /// A complete response for frame `id` was received and stored.
+    Received(u8),
+    /// A header or response was bad; the rest of the frame is ignored.
+    Error(FrameError),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    /// Waiting for a break; anything else is ignored, including the echo of our responses.
+    Idle,
+    Sync,
+    Pid,
+    Response(FrameDef),
+}
+
+/// LIN slave: follows headers on the bus and answers the frames this node publishes, by the
+/// frame table.
+#[derive(Debug, Clone)]
+pub struct Responder {
+    state: State,
+    response: Vec<u8, { MAX_DATA + 1 }>,
+}
+
+impl Default for Responder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Responder {
+    pub fn new() -> Self {
+        Self {
+            state: State::Idle,
+            response: Vec::new(),
+        }
+    }
+
+    pub fn feed(&mut self, frames: &mut Frames, event: Event) -> Action {
+        let byte = match event {
+            Event::Break => {
+                // A response cut short by the next header was never completed.
+                let cut = matches!(self.state, State::Response(_)) && !self.response.is_empty();
+                self.state = State::Sync;
+                return if cut {
+                    Action::Error(FrameError::Incomplete)
+                } else {
+                    Action::None
+                };
+            }
+            Event::Byte(b) => b,
+        };
+
+        match self.state {
+            State::Idle => Action::None,
+            // The break itself may also arrive as a zero byte.
+            State::Sync if byte == 0 => Action::None,
+            State::Sync if byte == SYNC => {
+                self.state = State::Pid;
+                Action::None
+            }
+            State::Sync => {
+                self.state = State::Idle;
+                Action::Error(FrameError::Sync)
+            }
+            State::Pid => {
+                self.state = State::Idle;
+                let id = match parse_pid(byte) {
+                    Ok(id) => id,
+                    Err(e) => return Action::Error(e),
+                };
+                let Some(frame) = frames.get(id) else {
+                    // Not ours.
+                    return Action::None;
+                };
+                let def = frame.def;
+                self.response.clear();
+                match def.publisher {
+                    Publisher::Slave => {
+                        let mut response = Vec::new();
+                        def.encode(&frame.data, &mut response);
+                        Action::Respond(response)
+                    }
+                    Publisher::Master => {
+                        self.state = State::Response(def);
+                        Action::None
+                    }
+                }
+            }
+            State::Response(def) => {
+                // The response is at most nine bytes, the length is checked below.
+                let _ = self.response.push(byte);
+                if self.response.len() <= def.len as usize {
+                    return Action::None;
+                }
+                self.state = State::Idle;
+                match def.decode(&self.response) {
+                    Ok(data) => {
+                        frames.receive(def.id, data);
+                        Action::Received(def.id)
+                    }
+                    Err(e) => Action::Error(e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lin::frame::{protected_id, FrameDef};
+    use alloc::vec;
+
+    fn frames() -> Frames {
+        let mut frames = Frames::new(&[
+            FrameDef::new(0x10, 2, Publisher::Master),
+            FrameDef::new(0x20, 4, Publisher::Slave),
+        ]);
+        frames.set(0x20, &[1, 2, 3, 4]);
+        frames
+    }
+
+    fn feed(responder: &mut Responder, frames: &mut Frames, bytes: &[u8]) -> vec::Vec<Action> {
+        let mut actions = vec![responder.feed(frames, Event::Break)];
+        for &b in bytes {
+            actions.push(responder.feed(frames, Event::Byte(b)));
+        }
+        actions.retain(|a| *a != Action::None);
+        actions
+    }
+
+    #[test]
+    fn responds_and_receives() {
+        let (mut responder, mut frames) = (Responder::new(), frames());
+
+        let pid = protected_id(0x20);
+        let actions = feed(&mut responder, &mut frames, &[0x00, SYNC, pid]);
+        let mut expected = vec::Vec::new();
+        frames
+            .get(0x20)
+            .unwrap()
+            .def
+            .encode(&[1, 2, 3, 4], &mut expected);
+        assert_eq!(
+            actions,
+            [Action::Respond(Vec::from_slice(&expected).unwrap())]
+        );
+        // Our own response echoed back is ignored.
+        for &b in &expected {
+            assert_eq!(responder.feed(&mut frames, Event::Byte(b)), Action::None);
+        }
+
+        let def = frames.get(0x10).unwrap().def;
+        let mut request = vec![SYNC, def.pid()];
+        def.encode(&[0xab, 0xcd], &mut request);
+        assert_eq!(
+            feed(&mut responder, &mut frames, &request),
+            [Action::Received(0x10)]
+        );
+        assert_eq!(frames.get(0x10).unwrap().data[..2], [0xab, 0xcd]);
+
+        // Someone else's frame.
+        assert!(feed(
+            &mut responder,
+            &mut frames,
+            &[SYNC, protected_id(0x30), 7, 7]
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn header_and_response_errors() {
+        let (mut responder, mut frames) = (Responder::new(), frames());
+
+        assert_eq!(
+            feed(&mut responder, &mut frames, &[0x54]),
+            [Action::Error(FrameError::Sync)]
+        );
+        assert_eq!(
+            feed(
+                &mut responder,
+                &mut frames,
+                &[SYNC, protected_id(0x10) ^ 0x80]
+            ),
+            [Action::Error(FrameError::Parity)]
+        );
+
+        let def = frames.get(0x10).unwrap().def;
+        let mut request = vec![SYNC, def.pid()];
+        def.encode(&[0xab, 0xcd], &mut request);
+        request[3] ^= 0x01;
+        assert_eq!(
+            feed(&mut responder, &mut frames, &request),
+            [Action::Error(FrameError::Checksum)]
+        );
+
+        // Cut short by the next break.
+        feed(&mut responder, &mut frames, &[SYNC, def.pid(), 0xab]);
+        assert_eq!(
+            responder.feed(&mut frames, Event::Break),
+            Action::Error(FrameError::Incomplete)
+        );
+    }
+}
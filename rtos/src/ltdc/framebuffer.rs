@@ -0,0 +1,193 @@
+//! A framebuffer in memory, as the LTDC scans it, that `embedded-graphics` draws on.
+//!
+//! Lines follow each other with no padding, top line first, each pixel in the buffer's
+//! [`PixelFormat`]. Drawing is in `Rgb565` and converted on the way in; filled rectangles are
+//! converted once and copied along each line.
+
+use super::pixel::PixelFormat;
+use core::convert::Infallible;
+use embedded_graphics::{image::GetPixel, pixelcolor::Rgb565, prelude::*, primitives::Rectangle};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramebufferError {
+    /// The buffer is shorter than a frame.
+    TooSmall,
+}
+
+impl FramebufferError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FramebufferError::TooSmall => "buffer smaller than a frame",
+        }
+    }
+}
+
+/// Bytes in a frame of `size` in `format`.
+pub const fn frame_len(size: Size, format: PixelFormat) -> usize {
+    size.width as usize * size.height as usize * format.bytes()
+}
+
+pub struct Framebuffer<'a> {
+    buf: &'a mut [u8],
+    size: Size,
+    format: PixelFormat,
+}
+
+impl<'a> Framebuffer<'a> {
+    pub fn new(
+        buf: &'a mut [u8],
+        size: Size,
+        format: PixelFormat,
+    ) -> Result<Self, FramebufferError> {
+        if buf.len() < frame_len(size, format) {
+            return Err(FramebufferError::TooSmall);
+        }
+        Ok(Self { buf, size, format })
+    }
+
+    /// Where pixel `point` starts in the buffer, if it's on it.
+    fn offset(&self, point: Point) -> Option<usize> {
+        let (x, y) = (u32::try_from(point.x).ok()?, u32::try_from(point.y).ok()?);
+        (x < self.size.width && y < self.size.height)
+            .then(|| (y as usize * self.size.width as usize + x as usize) * self.format.bytes())
+    }
+}
+
+impl OriginDimensions for Framebuffer<'_> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for Framebuffer<'_> {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let Some(offset) = self.offset(point) {
+                self.format.encode(color, &mut self.buf[offset..]);
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area.intersection(&self.bounding_box());
+        let Some(bottom_right) = area.bottom_right() else {
+            return Ok(());
+        };
+        let bytes = self.format.bytes();
+        let mut pixel = [0; 4];
+        self.format.encode(color, &mut pixel);
+        let line = area.size.width as usize * bytes;
+        for y in area.top_left.y..=bottom_right.y {
+            let Some(start) = self.offset(Point::new(area.top_left.x, y)) else {
+                continue;
+            };
+            for out in self.buf[start..start + line].chunks_exact_mut(bytes) {
+                out.copy_from_slice(&pixel[..bytes]);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl GetPixel for Framebuffer<'_> {
+    type Color = Rgb565;
+
+    fn pixel(&self, point: Point) -> Option<Rgb565> {
+        self.offset(point)
+            .map(|offset| self.format.decode(&self.buf[offset..]))
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::{
+        mono_font::{ascii::FONT_6X10, MonoTextStyle},
+        primitives::{PrimitiveStyle, Rectangle},
+        text::{Baseline, Text},
+    };
+    use std::vec;
+
+    const SIZE: Size = Size::new(16, 8);
+
+    #[test]
+    fn needs_a_whole_frame() {
+        let mut buf = vec![0; frame_len(SIZE, PixelFormat::Rgb888) - 1];
+        assert!(matches!(
+            Framebuffer::new(&mut buf, SIZE, PixelFormat::Rgb888),
+            Err(FramebufferError::TooSmall)
+        ));
+        assert!(Framebuffer::new(&mut buf, SIZE, PixelFormat::Rgb565).is_ok());
+    }
+
+    #[test]
+    fn writes_pixels_where_the_ltdc_reads_them() {
+        let mut buf = vec![0; frame_len(SIZE, PixelFormat::Argb8888)];
+        let mut fb = Framebuffer::new(&mut buf, SIZE, PixelFormat::Argb8888).unwrap();
+        Pixel(Point::new(1, 0), Rgb565::RED).draw(&mut fb).unwrap();
+        Pixel(Point::new(15, 7), Rgb565::BLUE)
+            .draw(&mut fb)
+            .unwrap();
+        // Off the edges, and dropped.
+        Pixel(Point::new(16, 0), Rgb565::WHITE)
+            .draw(&mut fb)
+            .unwrap();
+        Pixel(Point::new(-1, 0), Rgb565::WHITE)
+            .draw(&mut fb)
+            .unwrap();
+        assert_eq!(buf[4..8], [0x00, 0x00, 0xff, 0xff]);
+        assert_eq!(buf[buf.len() - 4..], [0xff, 0x00, 0x00, 0xff]);
+        assert_eq!(buf.iter().filter(|&&b| b != 0).count(), 4);
+    }
+
+    #[test]
+    fn fills_clipped_rectangles() {
+        for format in [
+            PixelFormat::Argb8888,
+            PixelFormat::Rgb888,
+            PixelFormat::Rgb565,
+        ] {
+            let mut buf = vec![0; frame_len(SIZE, format)];
+            let mut fb = Framebuffer::new(&mut buf, SIZE, format).unwrap();
+            fb.clear(Rgb565::BLUE).unwrap();
+            Rectangle::new(Point::new(12, -2), Size::new(10, 4))
+                .into_styled(PrimitiveStyle::with_fill(Rgb565::GREEN))
+                .draw(&mut fb)
+                .unwrap();
+            for point in fb.bounding_box().points() {
+                let expected = if point.x >= 12 && point.y < 2 {
+                    Rgb565::GREEN
+                } else {
+                    Rgb565::BLUE
+                };
+                assert_eq!(fb.pixel(point), Some(expected), "{format:?} {point:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn draws_text() {
+        let mut buf = vec![0; frame_len(SIZE, PixelFormat::Rgb565)];
+        let mut fb = Framebuffer::new(&mut buf, SIZE, PixelFormat::Rgb565).unwrap();
+        let style = MonoTextStyle::new(&FONT_6X10, Rgb565::WHITE);
+        Text::with_baseline("Hi", Point::zero(), style, Baseline::Top)
+            .draw(&mut fb)
+            .unwrap();
+        let lit = |x: core::ops::Range<i32>| {
+            x.flat_map(|x| (0..8).map(move |y| Point::new(x, y)))
+                .any(|p| fb.pixel(p) == Some(Rgb565::WHITE))
+        };
+        assert!(lit(0..6) && lit(6..12));
+        assert!(!lit(12..16));
+        assert_eq!(fb.pixel(Point::new(16, 0)), None);
+    }
+}
@@ -0,0 +1,295 @@
+//! The LTDC, scanning out one of two framebuffers in SDRAM.
+//!
+//! On the GIGA the LTDC's only output is the DSI host, and neither the DSI host nor the Display
+//! Shield's panel controller is brought up here. So this produces frames in memory and swaps them
+//! at vertical blanking, but nothing reaches the panel yet.
+
+use crate::board::LtdcResource;
+use core::fmt::Write as _;
+use defmt::{info, unwrap, warn};
+use embassy_executor::Spawner;
+use embassy_stm32::{
+    interrupt::{self, InterruptExt},
+    pac::{
+        self,
+        ltdc::vals::{Bf1, Bf2, Depol, Hspol, Imr, Pcpol, Pf, Vbr, Vspol},
+    },
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyleBuilder},
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::Rectangle,
+    text::{Baseline, Text},
+};
+
+pub mod framebuffer;
+pub mod pixel;
+pub mod timing;
+
+use framebuffer::{frame_len, Framebuffer};
+use pixel::PixelFormat;
+use timing::{line_length, Timings};
+
+/// The GIGA Display Shield's panel.
+pub const SIZE: Size = Size::new(800, 480);
+pub const FORMAT: PixelFormat = PixelFormat::Rgb565;
+/// Bytes in each of the two framebuffers, which `mem` carves out of the SDRAM.
+pub const FRAME_LEN: usize = frame_len(SIZE, FORMAT);
+/// Framebuffers start on an AXI burst.
+pub const ALIGN: usize = 64;
+
+/// PLL3 R, set in `board::init`.
+const PIXEL_CLOCK: u32 = 33_333_333;
+/// Layer 1; layer 2 stays off.
+const LAYER: usize = 0;
+const REFRESH: Duration = Duration::from_secs(1);
+
+/// Set by the interrupt when registers written with a vertical blanking reload are in use.
+static RELOADED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+#[derive(Debug, Clone, Copy)]
+pub struct LayerConfig {
+    /// Top left of the framebuffer in the active area. What falls off the panel isn't shown.
+    pub x: u16,
+    pub y: u16,
+    /// 255 for opaque; less blends with the background colour.
+    pub alpha: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LtdcConfig {
+    pub timings: Timings,
+    /// Sync and data enable polarities, low unless set.
+    pub hsync_high: bool,
+    pub vsync_high: bool,
+    pub de_high: bool,
+    /// Data out on the falling edge of the pixel clock rather than the rising.
+    pub pclk_falling: bool,
+    /// Shown where no layer is.
+    pub background: Rgb565,
+    pub layer: LayerConfig,
+}
+
+impl Default for LtdcConfig {
+    /// 800x480 at 60 Hz from the 33.3 MHz pixel clock.
+    fn default() -> Self {
+        Self {
+            timings: Timings {
+                width: SIZE.width as u16,
+                height: SIZE.height as u16,
+                hsync: 20,
+                hbp: 26,
+                hfp: 210,
+                vsync: 10,
+                vbp: 13,
+                vfp: 22,
+            },
+            hsync_high: false,
+            vsync_high: false,
+            de_high: false,
+            pclk_falling: false,
+            background: Rgb565::BLACK,
+            layer: LayerConfig {
+                x: 0,
+                y: 0,
+                alpha: 255,
+            },
+        }
+    }
+}
+
+/// Two framebuffers, one scanned out while the other is drawn.
+pub struct Ltdc {
+    buffers: [&'static mut [u8]; 2],
+    /// The one being scanned out.
+    front: usize,
+}
+
+impl Ltdc {
+    /// The frame to draw next. It holds the frame before last, so draw all of it.
+    pub fn back(&mut self) -> Framebuffer<'_> {
+        let buf = &mut *self.buffers[1 - self.front];
+        unwrap!(Framebuffer::new(buf, SIZE, FORMAT).map_err(|e| e.as_str()))
+    }
+
+    /// Show the back buffer from the next vertical blanking on, when it becomes the front.
+    pub async fn swap(&mut self) {
+        let back = 1 - self.front;
+        let layer = pac::LTDC.layer(LAYER);
+        RELOADED.reset();
+        layer
+            .cfbar()
+            .write(|w| w.set_cfbadd(self.buffers[back].as_ptr() as u32));
+        pac::LTDC.srcr().write(|w| w.set_vbr(Vbr::RELOAD));
+        RELOADED.wait().await;
+        self.front = back;
+    }
+}
+
+/// Start the LTDC on the framebuffers `mem` reserved and draw a test card in them, or log why not.
+/// Without the DSI host the card stays in memory.
+pub fn spawn(spawner: &Spawner, _r: LtdcResource, config: LtdcConfig) {
+    let Some(mut buffers) = crate::mem::take_framebuffers() else {
+        warn!("ltdc: no framebuffers");
+        return;
+    };
+    let timings = config.timings;
+    let Some(window) = timings.window(
+        config.layer.x,
+        config.layer.y,
+        SIZE.width as u16,
+        SIZE.height as u16,
+    ) else {
+        warn!("ltdc: layer off the panel");
+        return;
+    };
+    for buf in buffers.iter_mut() {
+        buf.fill(0);
+    }
+
+    let regs = pac::LTDC;
+    pac::RCC.apb3enr().modify(|w| w.set_ltdcen(true));
+
+    let counts = timings.counts();
+    regs.sscr().write(|w| {
+        w.set_hsw(counts.sync.0);
+        w.set_vsh(counts.sync.1);
+    });
+    regs.bpcr().write(|w| {
+        w.set_ahbp(counts.back_porch.0);
+        w.set_avbp(counts.back_porch.1);
+    });
+    regs.awcr().write(|w| {
+        w.set_aaw(counts.active.0);
+        w.set_aah(counts.active.1);
+    });
+    regs.twcr().write(|w| {
+        w.set_totalw(counts.total.0);
+        w.set_totalh(counts.total.1);
+    });
+    regs.gcr().modify(|w| {
+        w.set_hspol(if config.hsync_high {
+            Hspol::ACTIVEHIGH
+        } else {
+            Hspol::ACTIVELOW
+        });
+        w.set_vspol(if config.vsync_high {
+            Vspol::ACTIVEHIGH
+        } else {
+            Vspol::ACTIVELOW
+        });
+        w.set_depol(if config.de_high {
+            Depol::ACTIVEHIGH
+        } else {
+            Depol::ACTIVELOW
+        });
+        w.set_pcpol(if config.pclk_falling {
+            Pcpol::FALLINGEDGE
+        } else {
+            Pcpol::RISINGEDGE
+        });
+    });
+    let [r, g, b] = pixel::to_rgb888(config.background);
+    regs.bccr().write(|w| {
+        w.set_bcred(r);
+        w.set_bcgreen(g);
+        w.set_bcblue(b);
+    });
+
+    // The window is cut to the panel, but lines are still a framebuffer's width apart.
+    let layer = regs.layer(LAYER);
+    let (pitch, _) = line_length(SIZE.width as u16, FORMAT);
+    let (_, length) = line_length(window.h.1 - window.h.0 + 1, FORMAT);
+    layer.whpcr().write(|w| {
+        w.set_whstpos(window.h.0);
+        w.set_whsppos(window.h.1);
+    });
+    layer.wvpcr().write(|w| {
+        w.set_wvstpos(window.v.0);
+        w.set_wvsppos(window.v.1);
+    });
+    layer.pfcr().write(|w| {
+        w.set_pf(match FORMAT {
+            PixelFormat::Argb8888 => Pf::ARGB8888,
+            PixelFormat::Rgb888 => Pf::RGB888,
+            PixelFormat::Rgb565 => Pf::RGB565,
+        })
+    });
+    layer.cacr().write(|w| w.set_consta(config.layer.alpha));
+    layer.bfcr().write(|w| {
+        w.set_bf1(Bf1::CONSTANT);
+        w.set_bf2(Bf2::CONSTANT);
+    });
+    layer
+        .cfbar()
+        .write(|w| w.set_cfbadd(buffers[0].as_ptr() as u32));
+    layer.cfblr().write(|w| {
+        w.set_cfbp(pitch);
+        w.set_cfbll(length);
+    });
+    layer
+        .cfblnr()
+        .write(|w| w.set_cfblnbr(window.v.1 - window.v.0 + 1));
+    layer.cr().modify(|w| w.set_len(true));
+
+    regs.ier().write(|w| w.set_rrie(true));
+    regs.srcr().write(|w| w.set_imr(Imr::RELOAD));
+    regs.gcr().modify(|w| w.set_ltdcen(true));
+    interrupt::LTDC.unpend();
+    // SAFETY: the handler below only touches the LTDC's status and `RELOADED`.
+    unsafe { interrupt::LTDC.enable() };
+    info!(
+        "ltdc: {}x{} at {} Hz, in memory only: no DSI host",
+        timings.width,
+        timings.height,
+        timings.refresh_hz(PIXEL_CLOCK)
+    );
+
+    unwrap!(spawner.spawn(ltdc_task(Ltdc { buffers, front: 0 })));
+}
+
+/// Colour bars and the uptime, a frame a second, drawn in the back buffer and swapped in.
+#[embassy_executor::task]
+async fn ltdc_task(mut ltdc: Ltdc) {
+    const BARS: [Rgb565; 8] = [
+        Rgb565::WHITE,
+        Rgb565::YELLOW,
+        Rgb565::CYAN,
+        Rgb565::GREEN,
+        Rgb565::MAGENTA,
+        Rgb565::RED,
+        Rgb565::BLUE,
+        Rgb565::BLACK,
+    ];
+    let style = MonoTextStyleBuilder::new()
+        .font(&FONT_10X20)
+        .text_color(Rgb565::WHITE)
+        .background_color(Rgb565::BLACK)
+        .build();
+
+    loop {
+        let mut fb = ltdc.back();
+        let bar = SIZE.width / BARS.len() as u32;
+        for (i, color) in BARS.into_iter().enumerate() {
+            let at = Point::new((bar * i as u32) as i32, 0);
+            let _ = fb.fill_solid(&Rectangle::new(at, Size::new(bar, SIZE.height)), color);
+        }
+        let mut text = heapless::String::<32>::new();
+        let _ = write!(text, " up {} s ", Instant::now().as_secs());
+        let _ = Text::with_baseline(&text, Point::new(20, 20), style, Baseline::Top).draw(&mut fb);
+        ltdc.swap().await;
+        Timer::after(REFRESH).await;
+    }
+}
+
+#[interrupt]
+fn LTDC() {
+    let regs = pac::LTDC;
+    if regs.isr().read().rrif() {
+        regs.icr().write(|w| w.set_crrif(true));
+        RELOADED.signal(());
+    }
+}
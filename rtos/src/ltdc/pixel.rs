@@ -0,0 +1,106 @@
+//! The pixel formats a layer's framebuffer can hold, and conversion from the `Rgb565` everything
+//! is drawn in.
+//!
+//! Widening a channel repeats its top bits in the new low ones, so full scale stays full scale:
+//! 5-bit 31 becomes 255, not 248. Narrowing drops the low bits, so a pixel written and read back
+//! comes back the same.
+
+use embedded_graphics::{
+    pixelcolor::{raw::RawU16, Rgb565},
+    prelude::*,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Argb8888,
+    Rgb888,
+    Rgb565,
+}
+
+impl PixelFormat {
+    pub const fn bytes(self) -> usize {
+        match self {
+            PixelFormat::Argb8888 => 4,
+            PixelFormat::Rgb888 => 3,
+            PixelFormat::Rgb565 => 2,
+        }
+    }
+
+    /// Write `color` to the first [`bytes`](Self::bytes) of `out`, little-endian as the LTDC
+    /// reads them. ARGB8888 is opaque.
+    pub fn encode(self, color: Rgb565, out: &mut [u8]) {
+        match self {
+            PixelFormat::Argb8888 | PixelFormat::Rgb888 => {
+                let [r, g, b] = to_rgb888(color);
+                out[..3].copy_from_slice(&[b, g, r]);
+                if self == PixelFormat::Argb8888 {
+                    out[3] = 0xff;
+                }
+            }
+            PixelFormat::Rgb565 => out[..2].copy_from_slice(&color.into_storage().to_le_bytes()),
+        }
+    }
+
+    /// The color in the first [`bytes`](Self::bytes) of `bytes`, whatever its alpha.
+    pub fn decode(self, bytes: &[u8]) -> Rgb565 {
+        match self {
+            PixelFormat::Argb8888 | PixelFormat::Rgb888 => {
+                Rgb565::new(bytes[2] >> 3, bytes[1] >> 2, bytes[0] >> 3)
+            }
+            PixelFormat::Rgb565 => {
+                Rgb565::from(RawU16::new(u16::from_le_bytes([bytes[0], bytes[1]])))
+            }
+        }
+    }
+}
+
+/// Red, green and blue, 8 bits each.
+pub fn to_rgb888(color: Rgb565) -> [u8; 3] {
+    let (r, g, b) = (color.r(), color.g(), color.b());
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [PixelFormat; 3] = [
+        PixelFormat::Argb8888,
+        PixelFormat::Rgb888,
+        PixelFormat::Rgb565,
+    ];
+
+    #[test]
+    fn widens_to_full_scale() {
+        assert_eq!(to_rgb888(Rgb565::WHITE), [0xff, 0xff, 0xff]);
+        assert_eq!(to_rgb888(Rgb565::BLACK), [0, 0, 0]);
+        assert_eq!(to_rgb888(Rgb565::new(16, 32, 1)), [0x84, 0x82, 0x08]);
+    }
+
+    #[test]
+    fn encodes_little_endian() {
+        let color = Rgb565::new(31, 0, 1);
+        let mut out = [0; 4];
+        PixelFormat::Argb8888.encode(color, &mut out);
+        assert_eq!(out, [0x08, 0x00, 0xff, 0xff]);
+        let mut out = [0xaa; 4];
+        PixelFormat::Rgb888.encode(color, &mut out);
+        assert_eq!(out, [0x08, 0x00, 0xff, 0xaa]);
+        PixelFormat::Rgb565.encode(color, &mut out);
+        assert_eq!(out, [0x01, 0xf8, 0xff, 0xaa]);
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut out = [0; 4];
+        for format in FORMATS {
+            for raw in (0..=u16::MAX).step_by(7).chain([u16::MAX]) {
+                let color = Rgb565::from(RawU16::new(raw));
+                format.encode(color, &mut out);
+                assert_eq!(format.decode(&out), color, "{format:?} {raw:#06x}");
+            }
+        }
+    }
+}
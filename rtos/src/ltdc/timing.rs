@@ -0,0 +1,152 @@
+//! Panel timings and the LTDC register values that follow from them.
+//!
+//! The LTDC counts from the start of sync, and most of its registers hold an accumulated position
+//! less one: sync width, sync plus back porch, then the active area and the whole line or frame
+//! on top. Layer windows are in the same counts, so a layer at the top left of the active area
+//! starts one after the back porch.
+
+use super::pixel::PixelFormat;
+
+/// Horizontal counts in pixel clocks, vertical in lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    pub width: u16,
+    pub height: u16,
+    pub hsync: u16,
+    pub hbp: u16,
+    pub hfp: u16,
+    pub vsync: u16,
+    pub vbp: u16,
+    pub vfp: u16,
+}
+
+/// Horizontal and vertical, as [`Timings`] gives them to the registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Counts {
+    /// SSCR.
+    pub sync: (u16, u16),
+    /// BPCR.
+    pub back_porch: (u16, u16),
+    /// AWCR.
+    pub active: (u16, u16),
+    /// TWCR.
+    pub total: (u16, u16),
+}
+
+/// A layer's window, WHPCR and WVPCR, first and last, inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub h: (u16, u16),
+    pub v: (u16, u16),
+}
+
+impl Timings {
+    pub const fn counts(&self) -> Counts {
+        let h_bp = self.hsync + self.hbp;
+        let v_bp = self.vsync + self.vbp;
+        Counts {
+            sync: (self.hsync - 1, self.vsync - 1),
+            back_porch: (h_bp - 1, v_bp - 1),
+            active: (h_bp + self.width - 1, v_bp + self.height - 1),
+            total: (
+                h_bp + self.width + self.hfp - 1,
+                v_bp + self.height + self.vfp - 1,
+            ),
+        }
+    }
+
+    /// Frames a second at `pixel_clock` Hz.
+    pub fn refresh_hz(&self, pixel_clock: u32) -> u32 {
+        let (h, v) = self.counts().total;
+        pixel_clock / ((u32::from(h) + 1) * (u32::from(v) + 1))
+    }
+
+    /// The window of a `width` by `height` layer at `x`, `y` of the active area, cut to it.
+    /// `None` if nothing of it is left.
+    pub fn window(&self, x: u16, y: u16, width: u16, height: u16) -> Option<Window> {
+        let width = width.min(self.width.checked_sub(x)?);
+        let height = height.min(self.height.checked_sub(y)?);
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let h = self.hsync + self.hbp + x;
+        let v = self.vsync + self.vbp + y;
+        Some(Window {
+            h: (h, h + width - 1),
+            v: (v, v + height - 1),
+        })
+    }
+}
+
+/// CFBLR for lines `width` pixels long in `format`: the pitch from one line to the next, and the
+/// line length in bytes plus 7, as the H7's LTDC wants it.
+pub const fn line_length(width: u16, format: PixelFormat) -> (u16, u16) {
+    let bytes = width * format.bytes() as u16;
+    (bytes, bytes + 7)
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PANEL: Timings = Timings {
+        width: 800,
+        height: 480,
+        hsync: 20,
+        hbp: 26,
+        hfp: 210,
+        vsync: 10,
+        vbp: 13,
+        vfp: 22,
+    };
+
+    #[test]
+    fn accumulates() {
+        assert_eq!(
+            PANEL.counts(),
+            Counts {
+                sync: (19, 9),
+                back_porch: (45, 22),
+                active: (845, 502),
+                total: (1055, 524),
+            }
+        );
+        assert_eq!(PANEL.refresh_hz(33_333_333), 60);
+    }
+
+    #[test]
+    fn places_windows() {
+        // The whole screen spans the active area.
+        let full = PANEL.window(0, 0, 800, 480).unwrap();
+        let counts = PANEL.counts();
+        assert_eq!(full.h, (counts.back_porch.0 + 1, counts.active.0));
+        assert_eq!(full.v, (counts.back_porch.1 + 1, counts.active.1));
+
+        assert_eq!(
+            PANEL.window(100, 50, 200, 100),
+            Some(Window {
+                h: (146, 345),
+                v: (73, 172)
+            })
+        );
+        // Cut at the edge.
+        assert_eq!(
+            PANEL.window(700, 400, 200, 100).unwrap(),
+            Window {
+                h: (746, 845),
+                v: (423, 502)
+            }
+        );
+        assert_eq!(PANEL.window(800, 0, 1, 1), None);
+        assert_eq!(PANEL.window(0, 0, 0, 1), None);
+    }
+
+    #[test]
+    fn line_lengths() {
+        assert_eq!(line_length(800, PixelFormat::Rgb565), (1600, 1607));
+        assert_eq!(line_length(800, PixelFormat::Argb8888), (3200, 3207));
+        assert_eq!(line_length(10, PixelFormat::Rgb888), (30, 37));
+    }
+}
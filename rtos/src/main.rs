@@ -6,6 +6,8 @@ use crate::{
         config_portenta_giga_r1_wifi_leds, AssignedResources, FMCResources, GigaR1WifiBoardLeds,
         LedState, USART1Resource,
     },
+    bridge::BridgeConfig,
+    module::Sequence,
     utils::interrupt_free,
 };
 use alloc::{
@@ -15,11 +17,12 @@ use alloc::{
 use core::cell::RefCell;
 #[allow(unused_imports)]
 use embassy_executor::{Executor, Spawner};
-use embassy_stm32::usart::{BufferedUart, BufferedUartRx};
+use embassy_futures::select::{select4, Either4};
+use embassy_stm32::usart::{BufferedUart, BufferedUartRx, BufferedUartTx};
 #[allow(unused_imports)]
 use embassy_stm32::{
     bind_interrupts,
-    gpio::Output,
+    gpio::{Level, Output, Speed},
     peripherals,
     usart::{self, Config, Uart},
     wdg,
@@ -36,8 +39,36 @@ extern crate alloc;
 
 #[macro_use]
 mod board;
+mod bridge;
+mod capture;
+mod clock;
+mod console;
+mod consts;
+#[cfg(feature = "display-spi")]
+mod display;
+mod dmx;
+mod dynamixel;
+mod esp_at;
+mod gps;
+#[cfg(feature = "bluetooth")]
+mod hci;
+mod i2c;
+mod lin;
+#[cfg(feature = "display-ltdc")]
+mod ltdc;
 #[cfg(feature = "use_alloc")]
 mod mem;
+mod modbus;
+mod module;
+mod onewire;
+#[cfg(feature = "ppp")]
+mod ppp;
+mod rtc;
+mod selftest;
+#[cfg(feature = "sniffer")]
+mod sniffer;
+mod uart;
+mod usb;
 
 pub static LED_RED: critical_section::Mutex<RefCell<Option<Output<'_>>>> =
     critical_section::Mutex::new(RefCell::new(None));
@@ -73,6 +104,33 @@ set_led!(
     set_red_led => (LED_RED),
 );
 
+/// Whether each LED is on, red, green and blue.
+#[cfg(feature = "display-spi")]
+pub fn led_states() -> [bool; 3] {
+    interrupt_free(|cs| {
+        [&LED_RED, &LED_GREEN, &LED_BLUE].map(|led| {
+            led.borrow_ref(cs)
+                .as_ref()
+                .is_some_and(|pin| pin.is_set_low())
+        })
+    })
+}
+
+/// Bit 0 red, 1 green, 2 blue.
+#[cfg(feature = "i2c-target")]
+fn set_leds(bits: u8) {
+    let state = |bit: u8| {
+        if bits & bit != 0 {
+            LedState::On
+        } else {
+            LedState::Off
+        }
+    };
+    set_red_led(state(0b001));
+    set_green_led(state(0b010));
+    set_blue_led(state(0b100));
+}
+
 // NOTE: Only needed for testing with DMA
 // bind_interrupts!(struct USART1Irqs {
 //     USART1 => usart::InterruptHandler<peripherals::USART1>;
@@ -83,7 +141,26 @@ bind_interrupts!(struct USART1Irqs {
     USART1 => usart::BufferedInterruptHandler<peripherals::USART1>;
 });
 
+bind_interrupts!(struct USART6Irqs {
+    USART6 => usart::BufferedInterruptHandler<peripherals::USART6>;
+});
+
 pub const USART_BAUD: u32 = 115200;
+pub const USART_SETTINGS: uart::SerialSettings = uart::SerialSettings::new(USART_BAUD);
+/// Driver enable timing for an RS-485 transceiver on D0/D1, used by Modbus.
+pub const RS485_CONFIG: uart::Rs485Config = uart::Rs485Config::new();
+/// Our address as an I2C target on SDA1 and SCL1, with the `i2c-target` feature.
+#[cfg(feature = "i2c-target")]
+pub const I2C_TARGET_ADDRESS: u8 = 0x42;
+/// What a host MCU reads and writes there: an ID at 0x00, the LEDs at 0x10, bit 0 red, 1 green
+/// and 2 blue, and a scratch register at 0x11.
+#[cfg(feature = "i2c-target")]
+pub const I2C_TARGET_MAP: i2c::target::Map = i2c::target::Map::new()
+    .register(0x00, i2c::register_map::Access::ReadOnly, 0x47)
+    .register(0x10, i2c::register_map::Access::ReadWrite, 0)
+    .register(0x11, i2c::register_map::Access::ReadWrite, 0)
+    .on_change(0x10, |change| set_leds(change.new));
+pub const HOST_USART_BAUD: u32 = 115200;
 pub const USART_READ_BUF_SIZE: usize = 32;
 pub static MESSAGE: critical_section::Mutex<RefCell<Option<String>>> =
     critical_section::Mutex::new(RefCell::new(None));
@@ -97,6 +174,7 @@ async fn main(spawner: Spawner) {
     let r = split_resources!(p);
     // FMC
     mem::init_sdram(r.fmc, &mut core_peri);
+    capture::init(capture::CAPTURE_SIZE);
 
     // Configure LEDs
     #[cfg(feature = "board_giga_r1_wifi")]
@@ -113,22 +191,249 @@ async fn main(spawner: Spawner) {
     });
 
     unwrap!(spawner.spawn(heatbeat_task()));
+    unwrap!(spawner.spawn(usb::usb_task(r.usb)));
+    #[cfg(feature = "sniffer")]
+    sniffer::spawn(&spawner, r.sniffer, sniffer::SnifferConfig::default());
+    #[cfg(feature = "bluetooth")]
+    hci::spawn(&spawner, r.bluetooth, hci::HciConfig::default());
+    #[cfg(feature = "ppp")]
+    let _stack = ppp::spawn(&spawner, r.ppp, ppp::PppConfig::default()).await;
+    #[cfg(not(feature = "i2c-target"))]
+    let buses = i2c::init(r.i2c1, r.i2c2, r.i2c4);
+    #[cfg(feature = "i2c-target")]
+    let buses = {
+        i2c::target::spawn(&spawner, r.i2c4, I2C_TARGET_ADDRESS, I2C_TARGET_MAP);
+        i2c::init(r.i2c1, r.i2c2)
+    };
+    rtc::spawn(&spawner, buses.i2c2, r.ext_rtc);
+    #[cfg(feature = "display-spi")]
+    display::spawn(&spawner, r.display, display::DisplayConfig::default());
+    #[cfg(feature = "display-ltdc")]
+    ltdc::spawn(&spawner, r.ltdc, ltdc::LtdcConfig::default());
     // unwrap!(spawner.spawn(usart_task(r.usart1)));
 
+    let mut module = module::new(
+        r.usart1.rtc_power_key,
+        r.usart1.wifi_reset,
+        r.usart1.wifi_boot,
+        Default::default(),
+    );
+
     let (tx_pin, rx_pin, uart) = (r.usart1.tx, r.usart1.rx, r.usart1.peri);
 
     static TX_BUF: StaticCell<[u8; 16]> = StaticCell::new();
     let tx_buf = &mut TX_BUF.init([0; 16])[..];
-    static RX_BUF: StaticCell<[u8; 16]> = StaticCell::new();
-    let rx_buf = &mut RX_BUF.init([0; 16])[..];
-    let mut config = embassy_stm32::usart::Config::default();
-    config.baudrate = USART_BAUD;
+    // Over 1 ms of slack at the self-test's 2 Mbaud, so a slow reader isn't counted as line errors.
+    static RX_BUF: StaticCell<[u8; 256]> = StaticCell::new();
+    let rx_buf = &mut RX_BUF.init([0; 256])[..];
+    let config = (&USART_SETTINGS).into();
     let uart = BufferedUart::new(uart, USART1Irqs, rx_pin, tx_pin, tx_buf, rx_buf, config)
         .expect("Create UART");
-    let (mut tx, rx) = uart.split();
+    let (tx, rx) = uart.split();
+    let (mut tx, mut rx) = (capture::Tap::new(tx), capture::Tap::new(rx));
+
+    // USART1's own DE pin, PA12, is the USB D+ line here, so the transceiver is switched by GPIO
+    // rather than by `uart::De::hardware`.
+    #[cfg(feature = "rs485")]
+    let mut de = Some(uart::De::Gpio(uart::GpioDe::new(
+        embassy_stm32::gpio::Output::new(
+            r.rs485.de,
+            embassy_stm32::gpio::Level::Low,
+            embassy_stm32::gpio::Speed::VeryHigh,
+        ),
+        RS485_CONFIG.polarity,
+        embassy_stm32::pac::USART1,
+    )));
+    #[cfg(not(feature = "rs485"))]
+    let mut de: Option<uart::De<'static>> = None;
+
+    // GPS pulse per second.
+    let mut pps = embassy_stm32::exti::ExtiInput::new(
+        r.gps.pps,
+        r.gps.pps_exti,
+        embassy_stm32::gpio::Pull::Down,
+    );
+    rtc::internal::init(embassy_stm32::rtc::Rtc::new(
+        r.rtc.peri,
+        embassy_stm32::rtc::RtcConfig::default(),
+    ));
+    clock::spawn(&spawner);
+
+    let (tx_pin, rx_pin, uart) = (r.host_uart.tx, r.host_uart.rx, r.host_uart.peri);
+
+    static HOST_TX_BUF: StaticCell<[u8; 256]> = StaticCell::new();
+    let host_tx_buf = &mut HOST_TX_BUF.init([0; 256])[..];
+    static HOST_RX_BUF: StaticCell<[u8; 256]> = StaticCell::new();
+    let host_rx_buf = &mut HOST_RX_BUF.init([0; 256])[..];
+    let mut config = embassy_stm32::usart::Config::default();
+    config.baudrate = HOST_USART_BAUD;
+    let host_uart = BufferedUart::new(
+        uart,
+        USART6Irqs,
+        rx_pin,
+        tx_pin,
+        host_tx_buf,
+        host_rx_buf,
+        config,
+    )
+    .expect("Create host UART");
+    let (mut host_tx, mut host_rx) = host_uart.split();
+
+    module.run(Sequence::PowerOn, &mut rx).await;
+
+    let bridge_config = BridgeConfig::default();
+    let mut mode = Mode::AtClient;
+    loop {
+        #[cfg(feature = "display-spi")]
+        display::send(display::Update::Mode(mode.as_str()));
+        mode = match mode {
+            // Normal AT-client operation, with the console on the host port, until the escape
+            // sequence is typed there or the USB serial port is opened.
+            Mode::AtClient => match select4(
+                at_client_writer(&mut tx),
+                buffered_uart_reader(&mut rx),
+                console::run(&mut host_rx, &mut host_tx, &bridge_config.escape),
+                usb::wait_for_session(),
+            )
+            .await
+            {
+                Either4::Third(console::Exit::Escape) => Mode::HostBridge,
+                Either4::Third(console::Exit::Handoff(handoff)) => Mode::Handoff(handoff),
+                Either4::Fourth(lines) => Mode::UsbBridge(lines),
+                Either4::First(()) | Either4::Second(()) => Mode::AtClient,
+            },
+            Mode::HostBridge => {
+                let stats =
+                    bridge::run(&mut host_rx, &mut host_tx, &mut rx, &mut tx, &bridge_config).await;
+                bridge::log_stats(&stats);
+                Mode::AtClient
+            }
+            Mode::UsbBridge(lines) => {
+                let stats =
+                    usb::run_bridge(&mut rx, &mut tx, &mut module, lines, &USART_SETTINGS).await;
+                bridge::log_stats(&stats);
+                Mode::AtClient
+            }
+            Mode::Handoff(handoff) => {
+                let name = handoff.as_str();
+                let result = hand_off(
+                    handoff,
+                    &mut rx,
+                    &mut tx,
+                    &mut host_rx,
+                    &mut host_tx,
+                    &mut module,
+                    de.as_mut(),
+                    &mut pps,
+                )
+                .await;
+                if let Err(e) = result {
+                    warn!("{}: host write error: {}", name, defmt::Debug2Format(&e));
+                }
+                Mode::AtClient
+            }
+        };
+    }
+}
+
+/// Run a command the console handed over along with USART1, replying on `host_tx`.
+#[allow(clippy::too_many_arguments)]
+async fn hand_off<HR: Read, HW: Write>(
+    handoff: console::Handoff,
+    rx: &mut capture::Tap<BufferedUartRx<'static, peripherals::USART1>>,
+    tx: &mut capture::Tap<BufferedUartTx<'static, peripherals::USART1>>,
+    host_rx: &mut HR,
+    host_tx: &mut HW,
+    module: &mut module::Module<'static>,
+    de: Option<&mut uart::De<'static>>,
+    pps: &mut embassy_stm32::exti::ExtiInput<'static>,
+) -> Result<(), HW::Error> {
+    match handoff {
+        console::Handoff::SelfTest(peer) => {
+            // Keep the module off the line; internal loopback still drives the TX pin.
+            if peer == selftest::Peer::Internal {
+                module.hold_in_reset();
+            }
+            selftest::run(
+                rx,
+                tx,
+                host_tx,
+                &selftest::SelfTestConfig::new(peer),
+                &mut |on| uart::set_internal_loopback(embassy_stm32::pac::USART1, on),
+                &USART_SETTINGS,
+            )
+            .await;
+            if peer == selftest::Peer::Internal {
+                module.run(Sequence::HardReset, rx).await;
+            }
+            Ok(())
+        }
+        console::Handoff::Module(sequence) => {
+            let state = module.run(sequence, rx).await;
+            let reply = alloc::format!("module: {}\r\n", state);
+            host_tx.write_all(reply.as_bytes()).await
+        }
+        console::Handoff::Wifi(command) => {
+            let wifi = esp_at::new(rx, tx);
+            esp_at::run_command(&wifi, &command, host_tx).await
+        }
+        console::Handoff::Modbus(command) => {
+            modbus::run_command(
+                rx,
+                tx,
+                de,
+                &RS485_CONFIG,
+                host_rx,
+                host_tx,
+                &command,
+                &USART_SETTINGS,
+            )
+            .await
+        }
+        console::Handoff::Lin(command) => {
+            lin::run_command(rx, tx, host_rx, host_tx, command, &USART_SETTINGS).await
+        }
+        console::Handoff::Dmx(command) => {
+            dmx::run_command(rx, tx, de, host_rx, host_tx, command, &USART_SETTINGS).await
+        }
+        console::Handoff::OneWire(command) => {
+            onewire::run_command(rx, tx, host_rx, host_tx, command, &USART_SETTINGS).await
+        }
+        console::Handoff::Dynamixel(command) => {
+            dynamixel::run_command(rx, tx, host_tx, command, &USART_SETTINGS).await
+        }
+        console::Handoff::Gps(command) => {
+            gps::run_command(rx, tx, host_rx, host_tx, pps, command, &USART_SETTINGS).await
+        }
+    }
+}
 
-    unwrap!(spawner.spawn(buffered_uart_reader(rx)));
+/// Who currently owns USART1.
+enum Mode {
+    AtClient,
+    /// Transparent bridge to the host port, see [`bridge::run`].
+    HostBridge,
+    /// Transparent bridge to the USB CDC-ACM port, see [`usb::run_bridge`].
+    UsbBridge(usb::control::ControlLines),
+    /// A console command that needs USART1, see [`hand_off`].
+    Handoff(console::Handoff),
+}
 
+impl Mode {
+    #[cfg(feature = "display-spi")]
+    fn as_str(&self) -> &'static str {
+        match self {
+            Mode::AtClient => "AT client",
+            Mode::HostBridge => "host bridge",
+            Mode::UsbBridge(_) => "USB bridge",
+            Mode::Handoff(handoff) => handoff.as_str(),
+        }
+    }
+}
+
+async fn at_client_writer(
+    tx: &mut capture::Tap<BufferedUartTx<'static, embassy_stm32::peripherals::USART1>>,
+) {
     info!("Writing...");
     loop {
         let data = b"ATB\r\n";
@@ -138,9 +443,9 @@ async fn main(spawner: Spawner) {
     }
 }
 
-#[allow(dead_code)]
-#[embassy_executor::task]
-async fn buffered_uart_reader(mut rx: BufferedUartRx<'static, embassy_stm32::peripherals::USART1>) {
+async fn buffered_uart_reader(
+    rx: &mut capture::Tap<BufferedUartRx<'static, embassy_stm32::peripherals::USART1>>,
+) {
     info!("Reading...");
 
     const LEADER: char = '+';
@@ -4,11 +4,20 @@ use cortex_m_alloc::Heap;
 use defmt::info;
 use embassy_stm32::fmc::Fmc;
 
+pub mod region;
+
+use region::{Carver, Region};
+
 // Heap allocator
 #[global_allocator]
 pub static ALLOCATOR: Heap = Heap::empty();
 
-pub const HEAP_SIZE: usize = 32 * 1024 * 1024;
+/// The AS4C4M16SA: 4M words of 16 bits. The heap gets what isn't carved out for something else.
+pub const SDRAM_SIZE: usize = 8 * 1024 * 1024;
+
+#[cfg(feature = "display-ltdc")]
+static FRAMEBUFFERS: critical_section::Mutex<core::cell::RefCell<Option<[&'static mut [u8]; 2]>>> =
+    critical_section::Mutex::new(core::cell::RefCell::new(None));
 
 pub fn init_sdram(r: FMCResources, core_peri: &mut cortex_m::Peripherals) {
     // taken from stm32h7xx-hal
@@ -22,7 +31,7 @@ pub fn init_sdram(r: FMCResources, core_peri: &mut cortex_m::Peripherals) {
     {
         let mpu = &core_peri.MPU;
         let scb = &mut core_peri.SCB;
-        let size = HEAP_SIZE;
+        let size = SDRAM_SIZE;
 
         // Refer to ARM®v7-M Architecture Reference Manual ARM DDI 0403
         // Version E.b Section B3.5
@@ -150,11 +159,37 @@ pub fn init_sdram(r: FMCResources, core_peri: &mut cortex_m::Peripherals) {
     // NOTE: for testing purposes only.
     // mem::check_sdram(ram_ptr, sdram_size);
 
+    let mut carver = Carver::new(Region::new(ram_ptr as usize, SDRAM_SIZE));
+    #[cfg(feature = "display-ltdc")]
+    reserve_framebuffers(&mut carver);
+    let heap = carver.rest();
+    info!("heap: {} bytes", heap.len);
+
     unsafe {
-        ALLOCATOR.init(ram_ptr as usize, HEAP_SIZE);
+        ALLOCATOR.init(heap.start, heap.len);
     }
 }
 
+/// Two LTDC framebuffers from the top of the SDRAM, kept for [`take_framebuffers`].
+#[cfg(feature = "display-ltdc")]
+fn reserve_framebuffers(carver: &mut Carver) {
+    let mut take = || match carver.take(crate::ltdc::FRAME_LEN, crate::ltdc::ALIGN) {
+        // SAFETY: carved regions don't overlap each other or the heap, and are handed out once.
+        Ok(region) => unsafe {
+            core::slice::from_raw_parts_mut(region.start as *mut u8, region.len)
+        },
+        Err(e) => crate::panic!("framebuffers: {}", e.as_str()),
+    };
+    let buffers = [take(), take()];
+    critical_section::with(|cs| FRAMEBUFFERS.borrow(cs).replace(Some(buffers)));
+}
+
+/// The framebuffers carved out by [`init_sdram`], to whoever asks first.
+#[cfg(feature = "display-ltdc")]
+pub fn take_framebuffers() -> Option<[&'static mut [u8]; 2]> {
+    critical_section::with(|cs| FRAMEBUFFERS.borrow_ref_mut(cs).take())
+}
+
 #[allow(dead_code)]
 pub fn check_sdram(ram_ptr: *mut u32, sdram_size: usize) {
     let ram_slice = unsafe {
@@ -0,0 +1,130 @@
+//! Carving fixed regions out of the SDRAM before the heap gets the rest.
+//!
+//! Regions are taken from the top down, each aligned, so what's left for the heap is one block at
+//! the bottom, starting where the SDRAM does.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Region {
+    pub const fn new(start: usize, len: usize) -> Self {
+        Self { start, len }
+    }
+
+    /// One past the last byte.
+    pub const fn end(&self) -> usize {
+        self.start + self.len
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CarveError {
+    /// Not a power of two.
+    BadAlignment,
+    TooBig,
+}
+
+impl CarveError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CarveError::BadAlignment => "alignment not a power of two",
+            CarveError::TooBig => "not enough SDRAM left",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Carver {
+    free: Region,
+}
+
+impl Carver {
+    pub const fn new(sdram: Region) -> Self {
+        Self { free: sdram }
+    }
+
+    /// `len` bytes from the top of what's free, starting on a multiple of `align`. The gap left
+    /// above it by the alignment is lost.
+    pub fn take(&mut self, len: usize, align: usize) -> Result<Region, CarveError> {
+        if !align.is_power_of_two() {
+            return Err(CarveError::BadAlignment);
+        }
+        let start = self
+            .free
+            .end()
+            .checked_sub(len)
+            .map(|start| start & !(align - 1))
+            .filter(|&start| start >= self.free.start)
+            .ok_or(CarveError::TooBig)?;
+        self.free.len = start - self.free.start;
+        Ok(Region::new(start, len))
+    }
+
+    /// What's still free, for the heap.
+    pub fn rest(&self) -> Region {
+        self.free
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SDRAM: Region = Region::new(0xd000_0000, 8 * 1024 * 1024);
+
+    fn contains(outer: &Region, inner: &Region) -> bool {
+        inner.start >= outer.start && inner.end() <= outer.end()
+    }
+
+    fn overlaps(a: &Region, b: &Region) -> bool {
+        a.start < b.end() && b.start < a.end()
+    }
+
+    #[test]
+    fn carves_from_the_top() {
+        let mut carver = Carver::new(SDRAM);
+        let a = carver.take(800 * 480 * 2, 64).unwrap();
+        let b = carver.take(800 * 480 * 2, 64).unwrap();
+        assert_eq!(a.end(), SDRAM.end());
+        assert_eq!(a.start, 0xd074_4800);
+        assert_eq!(b.start % 64, 0);
+        assert!(b.end() <= a.start);
+
+        let heap = carver.rest();
+        assert_eq!(heap.start, SDRAM.start);
+        assert_eq!(heap.end(), b.start);
+        for region in [a, b, heap] {
+            assert!(contains(&SDRAM, &region));
+        }
+        assert!(!overlaps(&a, &b) && !overlaps(&b, &heap) && !overlaps(&heap, &a));
+    }
+
+    #[test]
+    fn aligns_down() {
+        let mut carver = Carver::new(Region::new(0x1000, 0x1000));
+        assert_eq!(carver.take(0x10, 0x100), Ok(Region::new(0x1f00, 0x10)));
+        // The 0xf0 bytes over it are gone.
+        assert_eq!(carver.rest(), Region::new(0x1000, 0xf00));
+        assert_eq!(carver.take(1, 3), Err(CarveError::BadAlignment));
+        assert_eq!(carver.take(1, 0), Err(CarveError::BadAlignment));
+    }
+
+    #[test]
+    fn refuses_what_doesnt_fit() {
+        let mut carver = Carver::new(Region::new(0x1000, 0x1000));
+        assert_eq!(carver.take(0x1001, 1), Err(CarveError::TooBig));
+        // Fits, but not once aligned.
+        assert_eq!(carver.take(0x10, 0x2000), Err(CarveError::TooBig));
+        assert_eq!(carver.rest(), Region::new(0x1000, 0x1000));
+
+        assert_eq!(carver.take(0x1000, 0x1000), Ok(Region::new(0x1000, 0x1000)));
+        assert_eq!(carver.rest().len, 0);
+        assert_eq!(carver.take(1, 1), Err(CarveError::TooBig));
+        assert_eq!(carver.take(0, 1), Ok(Region::new(0x1000, 0)));
+    }
+}
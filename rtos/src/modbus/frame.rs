@@ -0,0 +1,143 @@
+//! Modbus RTU framing: unit address, PDU and CRC-16, with frames separated by line silence
+//! (Modbus over Serial Line V1.02, section 2.5.1).
+
+use crate::uart::SerialSettings;
+use alloc::vec::Vec;
+
+/// Unit address every slave acts on without answering.
+pub const BROADCAST: u8 = 0;
+/// Largest RTU frame: address, 253 byte PDU and CRC.
+pub const MAX_ADU: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// Fewer bytes than address, function code and CRC.
+    TooShort,
+    TooLong,
+    Crc,
+    /// Characters more than 1.5 character times apart inside the frame.
+    Gap,
+}
+
+impl FrameError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FrameError::TooShort => "frame too short",
+            FrameError::TooLong => "frame too long",
+            FrameError::Crc => "CRC mismatch",
+            FrameError::Gap => "gap inside frame",
+        }
+    }
+}
+
+/// CRC-16/MODBUS: reflected polynomial 0xA001, initial value 0xFFFF. Sent low byte first.
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xffff, |crc, &b| {
+        (0..8).fold(crc ^ b as u16, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xa001,
+            _ => crc >> 1,
+        })
+    })
+}
+
+/// Append the frame for `pdu` sent to or from `unit`.
+pub fn encode(unit: u8, pdu: &[u8], out: &mut Vec<u8>) {
+    let start = out.len();
+    out.push(unit);
+    out.extend_from_slice(pdu);
+    let crc = crc16(&out[start..]);
+    out.extend_from_slice(&crc.to_le_bytes());
+}
+
+/// Check a received frame and split it into unit address and PDU.
+pub fn decode(adu: &[u8]) -> Result<(u8, &[u8]), FrameError> {
+    if adu.len() < 4 {
+        return Err(FrameError::TooShort);
+    }
+    if adu.len() > MAX_ADU {
+        return Err(FrameError::TooLong);
+    }
+    let (body, crc) = adu.split_at(adu.len() - 2);
+    if crc16(body).to_le_bytes() != crc {
+        return Err(FrameError::Crc);
+    }
+
+    Ok((body[0], &body[1..]))
+}
+
+/// Inter-character (t1.5) and inter-frame (t3.5) silence for a line. Above 19200 baud the
+/// specification fixes them at 750 and 1750 us, as the computed values get too short to time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    pub t1_5_us: u32,
+    pub t3_5_us: u32,
+}
+
+impl Timing {
+    pub fn new(settings: &SerialSettings) -> Self {
+        if settings.baudrate > 19200 {
+            return Self {
+                t1_5_us: 750,
+                t3_5_us: 1750,
+            };
+        }
+
+        Self {
+            t1_5_us: settings.chars_time_us(3).div_ceil(2),
+            t3_5_us: settings.chars_time_us(7).div_ceil(2),
+        }
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uart::Parity;
+
+    #[test]
+    fn crc_and_frames() {
+        // Read holding registers 0x006b..0x006d of unit 0x11, from the specification's examples.
+        let pdu = [0x03, 0x00, 0x6b, 0x00, 0x03];
+        assert_eq!(crc16(&[0x11, 0x03, 0x00, 0x6b, 0x00, 0x03]), 0x8776);
+
+        let mut adu = Vec::new();
+        encode(0x11, &pdu, &mut adu);
+        assert_eq!(adu, [0x11, 0x03, 0x00, 0x6b, 0x00, 0x03, 0x76, 0x87]);
+        assert_eq!(decode(&adu), Ok((0x11, &pdu[..])));
+
+        adu[3] ^= 1;
+        assert_eq!(decode(&adu), Err(FrameError::Crc));
+        assert_eq!(decode(&adu[..3]), Err(FrameError::TooShort));
+        assert_eq!(decode(&[0; MAX_ADU + 1]), Err(FrameError::TooLong));
+    }
+
+    #[test]
+    fn timing_follows_line_settings() {
+        // 8E1: 11 bits per character.
+        let settings = SerialSettings::new(9600).with_parity(Parity::Even);
+        assert_eq!(
+            Timing::new(&settings),
+            Timing {
+                t1_5_us: 1719,
+                t3_5_us: 4011,
+            }
+        );
+        // 8N1 at 19200: 10 bits per character.
+        assert_eq!(
+            Timing::new(&SerialSettings::new(19200)),
+            Timing {
+                t1_5_us: 782,
+                t3_5_us: 1823,
+            }
+        );
+        assert_eq!(
+            Timing::new(&SerialSettings::new(115200)),
+            Timing {
+                t1_5_us: 750,
+                t3_5_us: 1750,
+            }
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use super::frame::{FrameError, Timing, MAX_ADU};
+use alloc::vec::Vec;
+use embassy_futures::select::{select, Either};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkError {
+    /// Nothing received in time.
+    Timeout,
+    Frame(FrameError),
+    /// Read or write error on the UART.
+    Serial,
+}
+
+impl LinkError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkError::Timeout => "timeout",
+            LinkError::Frame(e) => e.as_str(),
+            LinkError::Serial => "serial error",
+        }
+    }
+}
+
+/// Frames on a UART, delimited by silence.
+///
+/// Silence is timed from when the reader starts waiting, so bytes that sit in the UART's buffer
+/// while the task is busy don't count as a gap.
+pub struct Link<R, W, D> {
+    rx: R,
+    tx: W,
+    delay: D,
+    timing: Timing,
+}
+
+impl<R: Read, W: Write, D: DelayNs> Link<R, W, D> {
+    pub fn new(rx: R, tx: W, delay: D, timing: Timing) -> Self {
+        Self {
+            rx,
+            tx,
+            delay,
+            timing,
+        }
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// Wait for `ms` milliseconds, e.g. the turnaround after a broadcast.
+    pub async fn pause_ms(&mut self, ms: u32) {
+        self.delay.delay_ms(ms).await
+    }
+
+    /// Receive one frame into `out`: characters up to t3.5 of silence. A frame with more than
+    /// t1.5 between two characters is read to its end and then rejected. `timeout_ms` bounds the
+    /// wait for the first character.
+    pub async fn read_frame(
+        &mut self,
+        out: &mut Vec<u8>,
+        timeout_ms: Option<u32>,
+    ) -> Result<(), LinkError> {
+        out.clear();
+        let mut byte = [0u8];
+        match timeout_ms {
+            Some(ms) => match select(self.rx.read(&mut byte), self.delay.delay_ms(ms)).await {
+                Either::First(result) => read_one(result)?,
+                Either::Second(()) => return Err(LinkError::Timeout),
+            },
+            None => read_one(self.rx.read(&mut byte).await)?,
+        }
+        out.push(byte[0]);
+
+        let Timing { t1_5_us, t3_5_us } = self.timing;
+        let mut gap = false;
+        loop {
+            let next = match select(self.rx.read(&mut byte), self.delay.delay_us(t1_5_us)).await {
+                Either::First(result) => Some(result),
+                Either::Second(()) => {
+                    let rest = t3_5_us - t1_5_us;
+                    match select(self.rx.read(&mut byte), self.delay.delay_us(rest)).await {
+                        Either::First(result) => {
+                            gap = true;
+                            Some(result)
+                        }
+                        Either::Second(()) => None,
+                    }
+                }
+            };
+            match next {
+                Some(result) => {
+                    read_one(result)?;
+                    // Keep reading an oversized frame to its end, but not its bytes.
+                    if out.len() <= MAX_ADU {
+                        out.push(byte[0]);
+                    }
+                }
+                None => break,
+            }
+        }
+
+        if gap {
+            Err(LinkError::Frame(FrameError::Gap))
+        } else if out.len() > MAX_ADU {
+            Err(LinkError::Frame(FrameError::TooLong))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn write_frame(&mut self, adu: &[u8]) -> Result<(), LinkError> {
+        self.tx
+            .write_all(adu)
+            .await
+            .map_err(|_| LinkError::Serial)?;
+        self.tx.flush().await.map_err(|_| LinkError::Serial)
+    }
+}
+
+fn read_one<E>(result: Result<usize, E>) -> Result<(), LinkError> {
+    match result {
+        Ok(1) => Ok(()),
+        _ => Err(LinkError::Serial),
+    }
+}
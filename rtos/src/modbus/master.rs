@@ -0,0 +1,350 @@
+use super::{
+    frame::{self, FrameError, BROADCAST},
+    link::{Link, LinkError},
+    pdu::{Exception, Request, Response},
+};
+use alloc::vec::Vec;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Link(LinkError),
+    /// The slave answered with an exception.
+    Exception(Exception),
+    /// A reply from another unit, or one that doesn't fit the request.
+    UnexpectedResponse,
+    /// Quantities out of range, or a read sent to the broadcast address.
+    InvalidRequest,
+}
+
+impl Error {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::Link(e) => e.as_str(),
+            Error::Exception(e) => e.as_str(),
+            Error::UnexpectedResponse => "unexpected response",
+            Error::InvalidRequest => "invalid request",
+        }
+    }
+}
+
+impl From<LinkError> for Error {
+    fn from(e: LinkError) -> Self {
+        Error::Link(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MasterConfig {
+    /// How long a slave may take to start its reply.
+    pub response_timeout_ms: u32,
+    /// Pause after a broadcast, for the slaves to carry it out.
+    pub turnaround_ms: u32,
+}
+
+impl Default for MasterConfig {
+    fn default() -> Self {
+        Self {
+            response_timeout_ms: 1_000,
+            turnaround_ms: 100,
+        }
+    }
+}
+
+/// Modbus RTU client: one request at a time, each waiting for its reply.
+pub struct Master<R, W, D> {
+    link: Link<R, W, D>,
+    config: MasterConfig,
+    adu: Vec<u8>,
+}
+
+impl<R: Read, W: Write, D: DelayNs> Master<R, W, D> {
+    pub fn new(link: Link<R, W, D>, config: MasterConfig) -> Self {
+        Self {
+            link,
+            config,
+            adu: Vec::new(),
+        }
+    }
+
+    /// Send `request` to `unit` and wait for the reply. A broadcast gets none and returns
+    /// `None` after the turnaround delay.
+    pub async fn request(
+        &mut self,
+        unit: u8,
+        request: &Request,
+    ) -> Result<Option<Response>, Error> {
+        request.validate().map_err(|_| Error::InvalidRequest)?;
+        if unit == BROADCAST && !request.is_write() {
+            return Err(Error::InvalidRequest);
+        }
+
+        let mut pdu = Vec::new();
+        request.encode(&mut pdu);
+        self.adu.clear();
+        frame::encode(unit, &pdu, &mut self.adu);
+        self.link.write_frame(&self.adu).await?;
+
+        if unit == BROADCAST {
+            self.link.pause_ms(self.config.turnaround_ms).await;
+            return Ok(None);
+        }
+
+        let timeout = Some(self.config.response_timeout_ms);
+        self.link.read_frame(&mut self.adu, timeout).await?;
+        let (from, pdu) = frame::decode(&self.adu).map_err(LinkError::Frame)?;
+        if from != unit {
+            return Err(Error::UnexpectedResponse);
+        }
+        match Response::parse(request, pdu) {
+            Some(Ok(response)) => Ok(Some(response)),
+            Some(Err(exception)) => Err(Error::Exception(exception)),
+            None => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    pub async fn read_bits(&mut self, unit: u8, request: &Request) -> Result<Vec<bool>, Error> {
+        match self.request(unit, request).await? {
+            Some(Response::Bits(bits)) => Ok(bits),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    pub async fn read_registers(&mut self, unit: u8, request: &Request) -> Result<Vec<u16>, Error> {
+        match self.request(unit, request).await? {
+            Some(Response::Registers(registers)) => Ok(registers),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// A write, checking the slave's echo against what was sent.
+    pub async fn write(&mut self, unit: u8, request: &Request) -> Result<(), Error> {
+        let response = self.request(unit, request).await?;
+        let expected = match *request {
+            Request::WriteSingleCoil { address, value } => Response::WriteSingle {
+                address,
+                value: if value { 0xff00 } else { 0 },
+            },
+            Request::WriteSingleRegister { address, value } => {
+                Response::WriteSingle { address, value }
+            }
+            Request::WriteMultipleCoils {
+                address,
+                ref values,
+            } => Response::WriteMultiple {
+                address,
+                count: values.len() as u16,
+            },
+            Request::WriteMultipleRegisters {
+                address,
+                ref values,
+            } => Response::WriteMultiple {
+                address,
+                count: values.len() as u16,
+            },
+            _ => return Err(Error::InvalidRequest),
+        };
+        match response {
+            None => Ok(()),
+            Some(response) if response == expected => Ok(()),
+            Some(_) => Err(Error::UnexpectedResponse),
+        }
+    }
+}
+
+/// Frames that arrived damaged; worth a retry.
+pub fn is_transient(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::Link(LinkError::Timeout)
+            | Error::Link(LinkError::Frame(FrameError::Crc | FrameError::Gap))
+    )
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modbus::{
+        frame::Timing,
+        slave::{respond, Memory},
+    };
+    use alloc::{collections::VecDeque, rc::Rc, vec};
+    use core::{cell::RefCell, convert::Infallible, future::pending};
+    use embassy_futures::block_on;
+    use embedded_io_async::ErrorType;
+
+    /// A bus with one slave. `None` in `output` is silence: the pending read loses to the delay.
+    struct Bus {
+        slave: Memory,
+        unit: u8,
+        output: VecDeque<Option<u8>>,
+        /// Damage the next reply.
+        corrupt: bool,
+    }
+
+    struct Port(Rc<RefCell<Bus>>);
+
+    impl ErrorType for Port {
+        type Error = Infallible;
+    }
+
+    impl Read for Port {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            let next = self.0.borrow_mut().output.pop_front();
+            match next {
+                Some(Some(b)) => {
+                    buf[0] = b;
+                    Ok(1)
+                }
+                _ => pending().await,
+            }
+        }
+    }
+
+    impl Write for Port {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            let mut bus = self.0.borrow_mut();
+            let bus = &mut *bus;
+            let mut reply = Vec::new();
+            if respond(&mut bus.slave, bus.unit, buf, &mut reply) {
+                if core::mem::take(&mut bus.corrupt) {
+                    reply[1] ^= 0x01;
+                }
+                bus.output.extend(reply.into_iter().map(Some));
+                bus.output.extend([None, None]);
+            }
+            Ok(buf.len())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    fn master(bus: &Rc<RefCell<Bus>>) -> Master<Port, Port, NoDelay> {
+        let timing = Timing {
+            t1_5_us: 750,
+            t3_5_us: 1750,
+        };
+        let link = Link::new(Port(bus.clone()), Port(bus.clone()), NoDelay, timing);
+        Master::new(link, MasterConfig::default())
+    }
+
+    fn bus() -> Rc<RefCell<Bus>> {
+        Rc::new(RefCell::new(Bus {
+            slave: Memory::new(32, 16),
+            unit: 7,
+            output: VecDeque::new(),
+            corrupt: false,
+        }))
+    }
+
+    #[test]
+    fn reads_and_writes() {
+        let bus = bus();
+        let mut master = master(&bus);
+
+        let write = Request::WriteMultipleRegisters {
+            address: 2,
+            values: vec![10, 20, 30],
+        };
+        assert_eq!(block_on(master.write(7, &write)), Ok(()));
+        let coil = Request::WriteSingleCoil {
+            address: 9,
+            value: true,
+        };
+        assert_eq!(block_on(master.write(7, &coil)), Ok(()));
+        assert!(bus.borrow().slave.coils[9]);
+
+        let read = Request::ReadHoldingRegisters {
+            address: 1,
+            count: 4,
+        };
+        assert_eq!(
+            block_on(master.read_registers(7, &read)),
+            Ok(vec![0, 10, 20, 30])
+        );
+        let read = Request::ReadCoils {
+            address: 8,
+            count: 3,
+        };
+        assert_eq!(
+            block_on(master.read_bits(7, &read)),
+            Ok(vec![false, true, false])
+        );
+        let swap = Request::ReadWriteMultipleRegisters {
+            read_address: 0,
+            read_count: 3,
+            write_address: 0,
+            values: vec![1, 2],
+        };
+        assert_eq!(
+            block_on(master.read_registers(7, &swap)),
+            Ok(vec![1, 2, 10])
+        );
+
+        // Broadcast writes get no reply.
+        let broadcast = Request::WriteSingleRegister {
+            address: 15,
+            value: 0xbeef,
+        };
+        assert_eq!(block_on(master.request(0, &broadcast)), Ok(None));
+        assert_eq!(bus.borrow().slave.registers[15], 0xbeef);
+        assert!(bus.borrow().output.is_empty());
+    }
+
+    #[test]
+    fn failures() {
+        let bus = bus();
+        let mut master = master(&bus);
+
+        let read = Request::ReadInputRegisters {
+            address: 15,
+            count: 2,
+        };
+        assert_eq!(
+            block_on(master.read_registers(7, &read)),
+            Err(Error::Exception(Exception::IllegalDataAddress))
+        );
+
+        // Nobody at unit 8.
+        let read = Request::ReadInputRegisters {
+            address: 0,
+            count: 2,
+        };
+        let timeout = block_on(master.read_registers(8, &read));
+        assert_eq!(timeout, Err(Error::Link(LinkError::Timeout)));
+        assert!(is_transient(&timeout.unwrap_err()));
+
+        bus.borrow_mut().corrupt = true;
+        assert_eq!(
+            block_on(master.read_registers(7, &read)),
+            Err(Error::Link(LinkError::Frame(FrameError::Crc)))
+        );
+
+        // A character late: the reply is cut by a gap over t1.5.
+        bus.borrow_mut().output.extend([Some(7), None, Some(4)]);
+        assert_eq!(
+            block_on(master.link.read_frame(&mut Vec::new(), None)),
+            Err(LinkError::Frame(FrameError::Gap))
+        );
+
+        assert_eq!(
+            block_on(master.request(0, &read)),
+            Err(Error::InvalidRequest)
+        );
+        let empty = Request::WriteMultipleCoils {
+            address: 0,
+            values: Vec::new(),
+        };
+        assert_eq!(
+            block_on(master.request(7, &empty)),
+            Err(Error::InvalidRequest)
+        );
+    }
+}
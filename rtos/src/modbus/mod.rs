@@ -0,0 +1,195 @@
+use crate::{
+    capture::Tap,
+    console::command::ModbusCommand,
+    uart::{De, EchoFilter, Parity, Rs485Config, Rs485Rx, Rs485Tx, SerialSettings},
+};
+use alloc::{format, string::String};
+use core::{cell::RefCell, fmt::Write as _};
+use defmt::{info, warn};
+use embassy_embedded_hal::SetConfig;
+use embassy_futures::select::{select, Either};
+use embassy_stm32::{
+    peripherals::USART1,
+    usart::{BufferedUartRx, BufferedUartTx},
+};
+use embassy_time::Delay;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+pub mod frame;
+pub mod link;
+pub mod master;
+pub mod pdu;
+pub mod slave;
+
+pub use frame::Timing;
+pub use link::{Link, LinkError};
+pub use master::{Error, Master, MasterConfig};
+pub use pdu::{Exception, Request, Response, Table};
+pub use slave::{Memory, RegisterMap};
+
+/// The line settings the Modbus specification asks every device to support by default.
+pub const MODBUS_SETTINGS: SerialSettings = SerialSettings::new(19200).with_parity(Parity::Even);
+/// Attempts per console request when replies time out or arrive damaged.
+const ATTEMPTS: usize = 3;
+/// Size of the register map served by `modbus serve`.
+const SERVE_COILS: usize = 64;
+const SERVE_REGISTERS: usize = 64;
+
+type Usart1Link<'a> = Link<
+    Rs485Rx<'a, &'a mut Tap<BufferedUartRx<'static, USART1>>>,
+    Rs485Tx<'a, &'a mut Tap<BufferedUartTx<'static, USART1>>, Option<&'a mut De<'static>>, Delay>,
+    Delay,
+>;
+
+/// Run a console `modbus` command on USART1 at [`MODBUS_SETTINGS`] and write the outcome to
+/// `host_tx`. `modbus serve` runs until a key is pressed on `host_rx`. USART1 is put back to
+/// `restore` afterwards. With `de`, the bus is RS-485, switched as `rs485` says.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_command<HR: Read, HW: Write>(
+    rx: &mut Tap<BufferedUartRx<'static, USART1>>,
+    tx: &mut Tap<BufferedUartTx<'static, USART1>>,
+    mut de: Option<&mut De<'static>>,
+    rs485: &Rs485Config,
+    host_rx: &mut HR,
+    host_tx: &mut HW,
+    command: &ModbusCommand,
+    restore: &SerialSettings,
+) -> Result<(), HW::Error> {
+    info!("modbus: {}", defmt::Debug2Format(command));
+    if let Err(e) = rx.set_config(&(&MODBUS_SETTINGS).into()) {
+        warn!("modbus: UART config failed: {}", defmt::Debug2Format(&e));
+    }
+    if let Some(de) = &de {
+        de.retime(&MODBUS_SETTINGS);
+    }
+
+    let filter = RefCell::new(EchoFilter::new());
+    let echo = (de.is_some() && rs485.suppress_echo).then_some(&filter);
+    let mut link = Link::new(
+        Rs485Rx::new(&mut *rx, echo),
+        Rs485Tx::new(&mut *tx, de.as_deref_mut(), Delay, *rs485, echo),
+        Delay,
+        Timing::new(&MODBUS_SETTINGS),
+    );
+    let result = match *command {
+        ModbusCommand::Serve { unit } => {
+            let text = format!("serving unit {unit} at 19200 8E1, any key stops\r\n");
+            host_tx.write_all(text.as_bytes()).await?;
+            host_tx.flush().await?;
+            serve(&mut link, unit, host_rx).await
+        }
+        ModbusCommand::Read { .. } | ModbusCommand::Write { .. } => query(link, command).await,
+    };
+    let collisions = filter.borrow().collisions();
+    if collisions > 0 {
+        warn!("modbus: {} RS-485 collisions", collisions);
+    }
+
+    if let Err(e) = rx.set_config(&restore.into()) {
+        warn!(
+            "modbus: failed to restore UART config: {}",
+            defmt::Debug2Format(&e)
+        );
+    }
+    if let Some(de) = &de {
+        de.retime(restore);
+    }
+
+    let text = match result {
+        Ok(text) => text + "OK\r\n",
+        Err(e) => {
+            warn!("modbus: {=str}", e.as_str());
+            format!("ERROR: {}\r\n", e.as_str())
+        }
+    };
+    host_tx.write_all(text.as_bytes()).await?;
+    host_tx.flush().await
+}
+
+async fn serve<HR: Read>(
+    link: &mut Usart1Link<'_>,
+    unit: u8,
+    host_rx: &mut HR,
+) -> Result<String, Error> {
+    let mut map = Memory::new(SERVE_COILS, SERVE_REGISTERS);
+    let mut key = [0u8];
+    match select(slave::serve(link, unit, &mut map), host_rx.read(&mut key)).await {
+        Either::First(e) => Err(Error::Link(e)),
+        Either::Second(_) => Ok(String::new()),
+    }
+}
+
+async fn query(link: Usart1Link<'_>, command: &ModbusCommand) -> Result<String, Error> {
+    let mut master = Master::new(link, MasterConfig::default());
+    let mut text = String::new();
+
+    match *command {
+        ModbusCommand::Read {
+            unit,
+            table,
+            address,
+            count,
+        } => {
+            let request = table.read(address, count);
+            let addresses = address as u32..;
+            match retry(&mut master, unit, &request).await? {
+                Some(Response::Bits(bits)) => {
+                    for (address, bit) in addresses.zip(bits) {
+                        let _ = write!(text, "{address:5}: {}\r\n", bit as u8);
+                    }
+                }
+                Some(Response::Registers(registers)) => {
+                    for (address, value) in addresses.zip(registers) {
+                        let _ = write!(text, "{address:5}: {value:5} (0x{value:04x})\r\n");
+                    }
+                }
+                _ => return Err(Error::UnexpectedResponse),
+            }
+        }
+        ModbusCommand::Write {
+            unit,
+            table,
+            address,
+            ref values,
+        } => {
+            let request = match (table, values.as_slice()) {
+                (Table::Coils, &[value]) => Request::WriteSingleCoil {
+                    address,
+                    value: value != 0,
+                },
+                (Table::Coils, values) => Request::WriteMultipleCoils {
+                    address,
+                    values: values.iter().map(|&v| v != 0).collect(),
+                },
+                (_, &[value]) => Request::WriteSingleRegister { address, value },
+                (_, values) => Request::WriteMultipleRegisters {
+                    address,
+                    values: values.into(),
+                },
+            };
+            master.write(unit, &request).await?;
+        }
+        // Handled by `run_command`.
+        ModbusCommand::Serve { .. } => {}
+    }
+
+    Ok(text)
+}
+
+async fn retry<R: Read, W: Write, D: DelayNs>(
+    master: &mut Master<R, W, D>,
+    unit: u8,
+    request: &Request,
+) -> Result<Option<Response>, Error> {
+    let mut attempt = 1;
+    loop {
+        match master.request(unit, request).await {
+            Err(e) if master::is_transient(&e) && attempt < ATTEMPTS => {
+                warn!("modbus: {=str}, retrying", e.as_str());
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,614 @@
+//! Modbus requests and responses for function codes 1-6, 15, 16 and 23 (Modbus Application
+//! Protocol V1.1b3, section 6).
+
+use alloc::vec::Vec;
+
+pub mod function {
+    pub const READ_COILS: u8 = 0x01;
+    pub const READ_DISCRETE_INPUTS: u8 = 0x02;
+    pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+    pub const READ_INPUT_REGISTERS: u8 = 0x04;
+    pub const WRITE_SINGLE_COIL: u8 = 0x05;
+    pub const WRITE_SINGLE_REGISTER: u8 = 0x06;
+    pub const WRITE_MULTIPLE_COILS: u8 = 0x0f;
+    pub const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+    pub const READ_WRITE_MULTIPLE_REGISTERS: u8 = 0x17;
+    /// Set on the function code of an exception response.
+    pub const EXCEPTION: u8 = 0x80;
+}
+
+/// Quantity limits, chosen so requests and responses fit a 253 byte PDU.
+const MAX_READ_BITS: u16 = 2000;
+const MAX_WRITE_BITS: u16 = 1968;
+const MAX_READ_REGISTERS: u16 = 125;
+const MAX_WRITE_REGISTERS: u16 = 123;
+const MAX_READ_WRITE_REGISTERS: u16 = 121;
+
+const COIL_ON: u16 = 0xff00;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    IllegalFunction,
+    IllegalDataAddress,
+    IllegalDataValue,
+    ServerDeviceFailure,
+    Acknowledge,
+    ServerDeviceBusy,
+    Other(u8),
+}
+
+impl Exception {
+    pub fn code(&self) -> u8 {
+        match *self {
+            Exception::IllegalFunction => 1,
+            Exception::IllegalDataAddress => 2,
+            Exception::IllegalDataValue => 3,
+            Exception::ServerDeviceFailure => 4,
+            Exception::Acknowledge => 5,
+            Exception::ServerDeviceBusy => 6,
+            Exception::Other(code) => code,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            1 => Exception::IllegalFunction,
+            2 => Exception::IllegalDataAddress,
+            3 => Exception::IllegalDataValue,
+            4 => Exception::ServerDeviceFailure,
+            5 => Exception::Acknowledge,
+            6 => Exception::ServerDeviceBusy,
+            code => Exception::Other(code),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Exception::IllegalFunction => "illegal function",
+            Exception::IllegalDataAddress => "illegal data address",
+            Exception::IllegalDataValue => "illegal data value",
+            Exception::ServerDeviceFailure => "server device failure",
+            Exception::Acknowledge => "acknowledge",
+            Exception::ServerDeviceBusy => "server device busy",
+            Exception::Other(_) => "exception",
+        }
+    }
+}
+
+/// The four data tables of the Modbus data model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+    Coils,
+    DiscreteInputs,
+    HoldingRegisters,
+    InputRegisters,
+}
+
+impl Table {
+    pub fn read(self, address: u16, count: u16) -> Request {
+        match self {
+            Table::Coils => Request::ReadCoils { address, count },
+            Table::DiscreteInputs => Request::ReadDiscreteInputs { address, count },
+            Table::HoldingRegisters => Request::ReadHoldingRegisters { address, count },
+            Table::InputRegisters => Request::ReadInputRegisters { address, count },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    ReadCoils {
+        address: u16,
+        count: u16,
+    },
+    ReadDiscreteInputs {
+        address: u16,
+        count: u16,
+    },
+    ReadHoldingRegisters {
+        address: u16,
+        count: u16,
+    },
+    ReadInputRegisters {
+        address: u16,
+        count: u16,
+    },
+    WriteSingleCoil {
+        address: u16,
+        value: bool,
+    },
+    WriteSingleRegister {
+        address: u16,
+        value: u16,
+    },
+    WriteMultipleCoils {
+        address: u16,
+        values: Vec<bool>,
+    },
+    WriteMultipleRegisters {
+        address: u16,
+        values: Vec<u16>,
+    },
+    /// The write is done before the read.
+    ReadWriteMultipleRegisters {
+        read_address: u16,
+        read_count: u16,
+        write_address: u16,
+        values: Vec<u16>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// Coils or discrete inputs, as many as requested.
+    Bits(Vec<bool>),
+    /// Holding or input registers.
+    Registers(Vec<u16>),
+    /// Echo of a single coil or register write; coils as 0xFF00 or 0.
+    WriteSingle {
+        address: u16,
+        value: u16,
+    },
+    WriteMultiple {
+        address: u16,
+        count: u16,
+    },
+}
+
+impl Request {
+    pub fn function(&self) -> u8 {
+        match self {
+            Request::ReadCoils { .. } => function::READ_COILS,
+            Request::ReadDiscreteInputs { .. } => function::READ_DISCRETE_INPUTS,
+            Request::ReadHoldingRegisters { .. } => function::READ_HOLDING_REGISTERS,
+            Request::ReadInputRegisters { .. } => function::READ_INPUT_REGISTERS,
+            Request::WriteSingleCoil { .. } => function::WRITE_SINGLE_COIL,
+            Request::WriteSingleRegister { .. } => function::WRITE_SINGLE_REGISTER,
+            Request::WriteMultipleCoils { .. } => function::WRITE_MULTIPLE_COILS,
+            Request::WriteMultipleRegisters { .. } => function::WRITE_MULTIPLE_REGISTERS,
+            Request::ReadWriteMultipleRegisters { .. } => function::READ_WRITE_MULTIPLE_REGISTERS,
+        }
+    }
+
+    /// Only writes may be broadcast.
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Request::WriteSingleCoil { .. }
+                | Request::WriteSingleRegister { .. }
+                | Request::WriteMultipleCoils { .. }
+                | Request::WriteMultipleRegisters { .. }
+        )
+    }
+
+    /// Check quantities against the protocol limits and the 16 bit address space.
+    pub fn validate(&self) -> Result<(), Exception> {
+        let range = |address: u16, count: usize, max: u16| {
+            if count == 0 || count > max as usize {
+                Err(Exception::IllegalDataValue)
+            } else if address as usize + count > 0x1_0000 {
+                Err(Exception::IllegalDataAddress)
+            } else {
+                Ok(())
+            }
+        };
+
+        match *self {
+            Request::ReadCoils { address, count }
+            | Request::ReadDiscreteInputs { address, count } => {
+                range(address, count as usize, MAX_READ_BITS)
+            }
+            Request::ReadHoldingRegisters { address, count }
+            | Request::ReadInputRegisters { address, count } => {
+                range(address, count as usize, MAX_READ_REGISTERS)
+            }
+            Request::WriteSingleCoil { .. } | Request::WriteSingleRegister { .. } => Ok(()),
+            Request::WriteMultipleCoils {
+                address,
+                ref values,
+            } => range(address, values.len(), MAX_WRITE_BITS),
+            Request::WriteMultipleRegisters {
+                address,
+                ref values,
+            } => range(address, values.len(), MAX_WRITE_REGISTERS),
+            Request::ReadWriteMultipleRegisters {
+                read_address,
+                read_count,
+                write_address,
+                ref values,
+            } => range(read_address, read_count as usize, MAX_READ_REGISTERS).and(range(
+                write_address,
+                values.len(),
+                MAX_READ_WRITE_REGISTERS,
+            )),
+        }
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.function());
+        match *self {
+            Request::ReadCoils { address, count }
+            | Request::ReadDiscreteInputs { address, count }
+            | Request::ReadHoldingRegisters { address, count }
+            | Request::ReadInputRegisters { address, count } => push_words(out, &[address, count]),
+            Request::WriteSingleCoil { address, value } => {
+                push_words(out, &[address, if value { COIL_ON } else { 0 }])
+            }
+            Request::WriteSingleRegister { address, value } => push_words(out, &[address, value]),
+            Request::WriteMultipleCoils {
+                address,
+                ref values,
+            } => {
+                push_words(out, &[address, values.len() as u16]);
+                out.push(values.len().div_ceil(8) as u8);
+                pack_bits(values, out);
+            }
+            Request::WriteMultipleRegisters {
+                address,
+                ref values,
+            } => {
+                push_words(out, &[address, values.len() as u16]);
+                out.push(2 * values.len() as u8);
+                push_words(out, values);
+            }
+            Request::ReadWriteMultipleRegisters {
+                read_address,
+                read_count,
+                write_address,
+                ref values,
+            } => {
+                push_words(
+                    out,
+                    &[read_address, read_count, write_address, values.len() as u16],
+                );
+                out.push(2 * values.len() as u8);
+                push_words(out, values);
+            }
+        }
+    }
+
+    /// Decode a request PDU, as a slave. The error is the exception to answer with.
+    pub fn parse(pdu: &[u8]) -> Result<Self, Exception> {
+        let (&function, data) = pdu.split_first().ok_or(Exception::IllegalFunction)?;
+        let word = |i: usize| {
+            data.get(2 * i..2 * i + 2)
+                .map(|w| u16::from_be_bytes([w[0], w[1]]))
+                .ok_or(Exception::IllegalDataValue)
+        };
+        // Byte count, then the values, at `offset`; the count has to match the quantity.
+        let payload = |offset: usize, expected: usize| {
+            let (&len, rest) = data
+                .get(offset..)
+                .and_then(|d| d.split_first())
+                .ok_or(Exception::IllegalDataValue)?;
+            match rest.len() == len as usize && len as usize == expected {
+                true => Ok(rest),
+                false => Err(Exception::IllegalDataValue),
+            }
+        };
+
+        let request = match function {
+            function::READ_COILS
+            | function::READ_DISCRETE_INPUTS
+            | function::READ_HOLDING_REGISTERS
+            | function::READ_INPUT_REGISTERS => {
+                let (address, count) = (word(0)?, word(1)?);
+                match function {
+                    function::READ_COILS => Request::ReadCoils { address, count },
+                    function::READ_DISCRETE_INPUTS => {
+                        Request::ReadDiscreteInputs { address, count }
+                    }
+                    function::READ_HOLDING_REGISTERS => {
+                        Request::ReadHoldingRegisters { address, count }
+                    }
+                    _ => Request::ReadInputRegisters { address, count },
+                }
+            }
+            function::WRITE_SINGLE_COIL => Request::WriteSingleCoil {
+                address: word(0)?,
+                value: match word(1)? {
+                    COIL_ON => true,
+                    0 => false,
+                    _ => return Err(Exception::IllegalDataValue),
+                },
+            },
+            function::WRITE_SINGLE_REGISTER => Request::WriteSingleRegister {
+                address: word(0)?,
+                value: word(1)?,
+            },
+            function::WRITE_MULTIPLE_COILS => {
+                let (address, count) = (word(0)?, word(1)?);
+                let bytes = payload(4, (count as usize).div_ceil(8))?;
+                Request::WriteMultipleCoils {
+                    address,
+                    values: unpack_bits(bytes, count as usize),
+                }
+            }
+            function::WRITE_MULTIPLE_REGISTERS => {
+                let (address, count) = (word(0)?, word(1)?);
+                let bytes = payload(4, 2 * count as usize)?;
+                Request::WriteMultipleRegisters {
+                    address,
+                    values: words(bytes),
+                }
+            }
+            function::READ_WRITE_MULTIPLE_REGISTERS => {
+                let bytes = payload(8, 2 * word(3)? as usize)?;
+                Request::ReadWriteMultipleRegisters {
+                    read_address: word(0)?,
+                    read_count: word(1)?,
+                    write_address: word(2)?,
+                    values: words(bytes),
+                }
+            }
+            _ => return Err(Exception::IllegalFunction),
+        };
+
+        request.validate()?;
+        Ok(request)
+    }
+}
+
+impl Response {
+    pub fn encode(&self, function: u8, out: &mut Vec<u8>) {
+        out.push(function);
+        match self {
+            Response::Bits(bits) => {
+                out.push(bits.len().div_ceil(8) as u8);
+                pack_bits(bits, out);
+            }
+            Response::Registers(registers) => {
+                out.push(2 * registers.len() as u8);
+                push_words(out, registers);
+            }
+            Response::WriteSingle { address, value } => push_words(out, &[*address, *value]),
+            Response::WriteMultiple { address, count } => push_words(out, &[*address, *count]),
+        }
+    }
+
+    /// Decode the response to `request`, as a master. `None` if it doesn't fit the request;
+    /// exception responses are `Some(Err(_))`.
+    pub fn parse(request: &Request, pdu: &[u8]) -> Option<Result<Self, Exception>> {
+        let (&function, data) = pdu.split_first()?;
+        if function == request.function() | function::EXCEPTION {
+            return Some(Err(Exception::from_code(*data.first()?)));
+        }
+        if function != request.function() {
+            return None;
+        }
+
+        // Byte count and exactly that many bytes.
+        let counted = |expected: usize| {
+            let (&len, rest) = data.split_first()?;
+            (len as usize == expected && rest.len() == expected).then_some(rest)
+        };
+        let response = match *request {
+            Request::ReadCoils { count, .. } | Request::ReadDiscreteInputs { count, .. } => {
+                let bytes = counted((count as usize).div_ceil(8))?;
+                Response::Bits(unpack_bits(bytes, count as usize))
+            }
+            Request::ReadHoldingRegisters { count, .. }
+            | Request::ReadInputRegisters { count, .. }
+            | Request::ReadWriteMultipleRegisters {
+                read_count: count, ..
+            } => Response::Registers(words(counted(2 * count as usize)?)),
+            _ => {
+                let &[a0, a1, v0, v1] = data else {
+                    return None;
+                };
+                let (address, value) = (u16::from_be_bytes([a0, a1]), u16::from_be_bytes([v0, v1]));
+                match request.function() {
+                    function::WRITE_SINGLE_COIL | function::WRITE_SINGLE_REGISTER => {
+                        Response::WriteSingle { address, value }
+                    }
+                    _ => Response::WriteMultiple {
+                        address,
+                        count: value,
+                    },
+                }
+            }
+        };
+
+        Some(Ok(response))
+    }
+}
+
+/// Append an exception response.
+pub fn encode_exception(function: u8, exception: Exception, out: &mut Vec<u8>) {
+    out.extend_from_slice(&[function | function::EXCEPTION, exception.code()]);
+}
+
+fn push_words(out: &mut Vec<u8>, words: &[u16]) {
+    for w in words {
+        out.extend_from_slice(&w.to_be_bytes());
+    }
+}
+
+fn words(bytes: &[u8]) -> Vec<u16> {
+    bytes
+        .chunks_exact(2)
+        .map(|w| u16::from_be_bytes([w[0], w[1]]))
+        .collect()
+}
+
+/// First bit in the least significant bit of the first byte, unused high bits zero.
+fn pack_bits(bits: &[bool], out: &mut Vec<u8>) {
+    for chunk in bits.chunks(8) {
+        out.push(
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (i, &bit)| byte | (bit as u8) << i),
+        );
+    }
+}
+
+fn unpack_bits(bytes: &[u8], count: usize) -> Vec<bool> {
+    (0..count)
+        .map(|i| bytes[i / 8] & 1 << (i % 8) != 0)
+        .collect()
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn encoded(request: &Request) -> Vec<u8> {
+        let mut out = Vec::new();
+        request.encode(&mut out);
+        out
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let requests = [
+            Request::ReadCoils {
+                address: 0x13,
+                count: 0x13,
+            },
+            Request::ReadInputRegisters {
+                address: 0x08,
+                count: 1,
+            },
+            Request::WriteSingleCoil {
+                address: 0xac,
+                value: true,
+            },
+            Request::WriteSingleRegister {
+                address: 1,
+                value: 3,
+            },
+            Request::WriteMultipleCoils {
+                address: 0x13,
+                values: vec![
+                    true, false, true, true, false, false, true, true, true, false,
+                ],
+            },
+            Request::WriteMultipleRegisters {
+                address: 1,
+                values: vec![0x000a, 0x0102],
+            },
+            Request::ReadWriteMultipleRegisters {
+                read_address: 3,
+                read_count: 6,
+                write_address: 0x0e,
+                values: vec![0x00ff, 0x00ff, 0x00ff],
+            },
+        ];
+        for request in requests {
+            assert_eq!(Request::parse(&encoded(&request)), Ok(request));
+        }
+
+        // Examples from the application protocol specification.
+        let coils = Request::WriteMultipleCoils {
+            address: 0x13,
+            values: vec![
+                true, false, true, true, false, false, true, true, true, false,
+            ],
+        };
+        assert_eq!(
+            encoded(&coils),
+            [0x0f, 0x00, 0x13, 0x00, 0x0a, 0x02, 0xcd, 0x01]
+        );
+        assert_eq!(
+            encoded(&Request::WriteSingleCoil {
+                address: 0xac,
+                value: true
+            }),
+            [0x05, 0x00, 0xac, 0xff, 0x00]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_requests() {
+        assert_eq!(
+            Request::parse(&[0x2b, 0x0e]),
+            Err(Exception::IllegalFunction)
+        );
+        assert_eq!(Request::parse(&[]), Err(Exception::IllegalFunction));
+        // Zero and too many registers.
+        assert_eq!(
+            Request::parse(&[0x03, 0x00, 0x00, 0x00, 0x00]),
+            Err(Exception::IllegalDataValue)
+        );
+        assert_eq!(
+            Request::parse(&[0x03, 0x00, 0x00, 0x00, 0x7e]),
+            Err(Exception::IllegalDataValue)
+        );
+        // Past the end of the address space.
+        assert_eq!(
+            Request::parse(&[0x03, 0xff, 0xff, 0x00, 0x02]),
+            Err(Exception::IllegalDataAddress)
+        );
+        // Coil values other than 0xFF00 and 0.
+        assert_eq!(
+            Request::parse(&[0x05, 0x00, 0x01, 0x12, 0x34]),
+            Err(Exception::IllegalDataValue)
+        );
+        // Byte count not matching the quantity.
+        assert_eq!(
+            Request::parse(&[0x10, 0x00, 0x01, 0x00, 0x02, 0x02, 0x00, 0x0a]),
+            Err(Exception::IllegalDataValue)
+        );
+        assert_eq!(
+            Request::parse(&[0x03, 0x00]),
+            Err(Exception::IllegalDataValue)
+        );
+    }
+
+    #[test]
+    fn responses() {
+        let read = Request::ReadCoils {
+            address: 0x13,
+            count: 0x13,
+        };
+        let bits = Response::parse(&read, &[0x01, 0x03, 0xcd, 0x6b, 0x05])
+            .unwrap()
+            .unwrap();
+        let Response::Bits(ref values) = bits else {
+            panic!("{bits:?}");
+        };
+        assert_eq!(values.len(), 19);
+        assert_eq!(
+            &values[..8],
+            &[true, false, true, true, false, false, true, true]
+        );
+        let mut out = Vec::new();
+        bits.encode(0x01, &mut out);
+        assert_eq!(out, [0x01, 0x03, 0xcd, 0x6b, 0x05]);
+
+        let registers = Request::ReadHoldingRegisters {
+            address: 0x6b,
+            count: 3,
+        };
+        assert_eq!(
+            Response::parse(
+                &registers,
+                &[0x03, 0x06, 0x02, 0x2b, 0x00, 0x00, 0x00, 0x64]
+            ),
+            Some(Ok(Response::Registers(vec![0x022b, 0, 0x64])))
+        );
+        assert_eq!(
+            Response::parse(&registers, &[0x83, 0x02]),
+            Some(Err(Exception::IllegalDataAddress))
+        );
+        // Wrong byte count, wrong function.
+        assert_eq!(Response::parse(&registers, &[0x03, 0x04, 0, 0, 0, 0]), None);
+        assert_eq!(Response::parse(&registers, &[0x04, 0x02, 0, 0]), None);
+
+        let write = Request::WriteMultipleRegisters {
+            address: 1,
+            values: vec![0x000a, 0x0102],
+        };
+        assert_eq!(
+            Response::parse(&write, &[0x10, 0x00, 0x01, 0x00, 0x02]),
+            Some(Ok(Response::WriteMultiple {
+                address: 1,
+                count: 2
+            }))
+        );
+    }
+}
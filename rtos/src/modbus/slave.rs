@@ -0,0 +1,327 @@
+use super::{
+    frame::{self, BROADCAST},
+    link::{Link, LinkError},
+    pdu::{encode_exception, Exception, Request, Response},
+};
+use alloc::{vec, vec::Vec};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+/// The data a slave exposes. Addresses are zero-based; each table may be backed by anything.
+/// Tables that aren't implemented answer with an illegal function exception.
+pub trait RegisterMap {
+    fn read_coils(&mut self, address: u16, out: &mut [bool]) -> Result<(), Exception> {
+        let _ = (address, out);
+        Err(Exception::IllegalFunction)
+    }
+
+    fn read_discrete_inputs(&mut self, address: u16, out: &mut [bool]) -> Result<(), Exception> {
+        let _ = (address, out);
+        Err(Exception::IllegalFunction)
+    }
+
+    fn read_holding_registers(&mut self, address: u16, out: &mut [u16]) -> Result<(), Exception> {
+        let _ = (address, out);
+        Err(Exception::IllegalFunction)
+    }
+
+    fn read_input_registers(&mut self, address: u16, out: &mut [u16]) -> Result<(), Exception> {
+        let _ = (address, out);
+        Err(Exception::IllegalFunction)
+    }
+
+    fn write_coils(&mut self, address: u16, values: &[bool]) -> Result<(), Exception> {
+        let _ = (address, values);
+        Err(Exception::IllegalFunction)
+    }
+
+    fn write_registers(&mut self, address: u16, values: &[u16]) -> Result<(), Exception> {
+        let _ = (address, values);
+        Err(Exception::IllegalFunction)
+    }
+}
+
+/// Coils and holding registers in RAM. Discrete inputs and input registers read the same
+/// values, read-only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Memory {
+    pub coils: Vec<bool>,
+    pub registers: Vec<u16>,
+}
+
+impl Memory {
+    pub fn new(coils: usize, registers: usize) -> Self {
+        Self {
+            coils: vec![false; coils],
+            registers: vec![0; registers],
+        }
+    }
+}
+
+fn span<T>(table: &mut [T], address: u16, len: usize) -> Result<&mut [T], Exception> {
+    let start = address as usize;
+    table
+        .get_mut(start..start + len)
+        .ok_or(Exception::IllegalDataAddress)
+}
+
+impl RegisterMap for Memory {
+    fn read_coils(&mut self, address: u16, out: &mut [bool]) -> Result<(), Exception> {
+        out.copy_from_slice(span(&mut self.coils, address, out.len())?);
+        Ok(())
+    }
+
+    fn read_discrete_inputs(&mut self, address: u16, out: &mut [bool]) -> Result<(), Exception> {
+        self.read_coils(address, out)
+    }
+
+    fn read_holding_registers(&mut self, address: u16, out: &mut [u16]) -> Result<(), Exception> {
+        out.copy_from_slice(span(&mut self.registers, address, out.len())?);
+        Ok(())
+    }
+
+    fn read_input_registers(&mut self, address: u16, out: &mut [u16]) -> Result<(), Exception> {
+        self.read_holding_registers(address, out)
+    }
+
+    fn write_coils(&mut self, address: u16, values: &[bool]) -> Result<(), Exception> {
+        span(&mut self.coils, address, values.len())?.copy_from_slice(values);
+        Ok(())
+    }
+
+    fn write_registers(&mut self, address: u16, values: &[u16]) -> Result<(), Exception> {
+        span(&mut self.registers, address, values.len())?.copy_from_slice(values);
+        Ok(())
+    }
+}
+
+/// Carry out a request on `map`.
+pub fn execute<M: RegisterMap + ?Sized>(
+    map: &mut M,
+    request: &Request,
+) -> Result<Response, Exception> {
+    Ok(match *request {
+        Request::ReadCoils { address, count } => {
+            let mut bits = vec![false; count as usize];
+            map.read_coils(address, &mut bits)?;
+            Response::Bits(bits)
+        }
+        Request::ReadDiscreteInputs { address, count } => {
+            let mut bits = vec![false; count as usize];
+            map.read_discrete_inputs(address, &mut bits)?;
+            Response::Bits(bits)
+        }
+        Request::ReadHoldingRegisters { address, count } => {
+            let mut registers = vec![0; count as usize];
+            map.read_holding_registers(address, &mut registers)?;
+            Response::Registers(registers)
+        }
+        Request::ReadInputRegisters { address, count } => {
+            let mut registers = vec![0; count as usize];
+            map.read_input_registers(address, &mut registers)?;
+            Response::Registers(registers)
+        }
+        Request::WriteSingleCoil { address, value } => {
+            map.write_coils(address, &[value])?;
+            Response::WriteSingle {
+                address,
+                value: if value { 0xff00 } else { 0 },
+            }
+        }
+        Request::WriteSingleRegister { address, value } => {
+            map.write_registers(address, &[value])?;
+            Response::WriteSingle { address, value }
+        }
+        Request::WriteMultipleCoils {
+            address,
+            ref values,
+        } => {
+            map.write_coils(address, values)?;
+            Response::WriteMultiple {
+                address,
+                count: values.len() as u16,
+            }
+        }
+        Request::WriteMultipleRegisters {
+            address,
+            ref values,
+        } => {
+            map.write_registers(address, values)?;
+            Response::WriteMultiple {
+                address,
+                count: values.len() as u16,
+            }
+        }
+        Request::ReadWriteMultipleRegisters {
+            read_address,
+            read_count,
+            write_address,
+            ref values,
+        } => {
+            map.write_registers(write_address, values)?;
+            let mut registers = vec![0; read_count as usize];
+            map.read_holding_registers(read_address, &mut registers)?;
+            Response::Registers(registers)
+        }
+    })
+}
+
+/// Handle one received frame as slave `unit`. Returns false if there is nothing to send back:
+/// a bad frame, another unit's request, or a broadcast.
+pub fn respond<M: RegisterMap + ?Sized>(
+    map: &mut M,
+    unit: u8,
+    adu: &[u8],
+    out: &mut Vec<u8>,
+) -> bool {
+    out.clear();
+    let Ok((address, pdu)) = frame::decode(adu) else {
+        return false;
+    };
+    if address != unit && address != BROADCAST {
+        return false;
+    }
+
+    let result = Request::parse(pdu).and_then(|request| {
+        // Reads make no sense without an answer.
+        if address == BROADCAST && !request.is_write() {
+            return Err(Exception::IllegalFunction);
+        }
+        execute(map, &request).map(|response| (request.function(), response))
+    });
+    if address == BROADCAST {
+        return false;
+    }
+
+    let mut reply = Vec::new();
+    match result {
+        Ok((function, response)) => response.encode(function, &mut reply),
+        Err(exception) => encode_exception(pdu[0], exception, &mut reply),
+    }
+    frame::encode(unit, &reply, out);
+    true
+}
+
+/// Serve requests for `unit` until the UART fails.
+pub async fn serve<R: Read, W: Write, D: DelayNs, M: RegisterMap + ?Sized>(
+    link: &mut Link<R, W, D>,
+    unit: u8,
+    map: &mut M,
+) -> LinkError {
+    let (mut request, mut reply) = (Vec::new(), Vec::new());
+    loop {
+        match link.read_frame(&mut request, None).await {
+            Ok(()) => {}
+            // Ignored like a frame for another unit; the master times out and retries.
+            Err(LinkError::Frame(_)) => continue,
+            Err(e) => return e,
+        }
+        if respond(map, unit, &request, &mut reply) {
+            if let Err(e) = link.write_frame(&reply).await {
+                return e;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(unit: u8, pdu: &[u8]) -> Vec<u8> {
+        let mut adu = Vec::new();
+        frame::encode(unit, pdu, &mut adu);
+        adu
+    }
+
+    fn reply(map: &mut Memory, adu: &[u8]) -> Option<Vec<u8>> {
+        let mut out = Vec::new();
+        respond(map, 0x11, adu, &mut out).then(|| {
+            let (unit, pdu) = frame::decode(&out).unwrap();
+            assert_eq!(unit, 0x11);
+            pdu.to_vec()
+        })
+    }
+
+    #[test]
+    fn answers_requests() {
+        let mut map = Memory::new(16, 8);
+        map.registers[..3].copy_from_slice(&[0x022b, 0, 0x64]);
+
+        assert_eq!(
+            reply(&mut map, &request(0x11, &[0x03, 0x00, 0x00, 0x00, 0x03])),
+            Some(vec![0x03, 0x06, 0x02, 0x2b, 0x00, 0x00, 0x00, 0x64])
+        );
+        assert_eq!(
+            reply(&mut map, &request(0x11, &[0x05, 0x00, 0x02, 0xff, 0x00])),
+            Some(vec![0x05, 0x00, 0x02, 0xff, 0x00])
+        );
+        assert_eq!(
+            reply(&mut map, &request(0x11, &[0x02, 0x00, 0x00, 0x00, 0x03])),
+            Some(vec![0x02, 0x01, 0b100])
+        );
+        // Read/write: written first, then read back.
+        assert_eq!(
+            reply(
+                &mut map,
+                &request(
+                    0x11,
+                    &[0x17, 0x00, 0x04, 0x00, 0x01, 0x00, 0x04, 0x00, 0x01, 0x02, 0x12, 0x34]
+                )
+            ),
+            Some(vec![0x17, 0x02, 0x12, 0x34])
+        );
+    }
+
+    #[test]
+    fn exceptions_and_silence() {
+        let mut map = Memory::new(16, 8);
+
+        // Outside the map, and a function the slave doesn't know.
+        assert_eq!(
+            reply(&mut map, &request(0x11, &[0x03, 0x00, 0x07, 0x00, 0x02])),
+            Some(vec![0x83, 0x02])
+        );
+        assert_eq!(
+            reply(&mut map, &request(0x11, &[0x08, 0x00, 0x00, 0x12, 0x34])),
+            Some(vec![0x88, 0x01])
+        );
+
+        // Another unit, a bad CRC, a broadcast: all silent. The broadcast still writes.
+        assert_eq!(
+            reply(&mut map, &request(0x12, &[0x06, 0x00, 0x01, 0x00, 0x07])),
+            None
+        );
+        let mut corrupt = request(0x11, &[0x06, 0x00, 0x01, 0x00, 0x07]);
+        corrupt[4] ^= 0x10;
+        assert_eq!(reply(&mut map, &corrupt), None);
+        assert_eq!(
+            reply(&mut map, &request(0x00, &[0x06, 0x00, 0x01, 0x00, 0x07])),
+            None
+        );
+        assert_eq!(map.registers[1], 7);
+
+        /// Only holding registers.
+        struct Holding(u16);
+        impl RegisterMap for Holding {
+            fn read_holding_registers(
+                &mut self,
+                _address: u16,
+                out: &mut [u16],
+            ) -> Result<(), Exception> {
+                out.fill(self.0);
+                Ok(())
+            }
+        }
+        let mut out = Vec::new();
+        assert!(respond(
+            &mut Holding(5),
+            0x11,
+            &request(0x11, &[0x01, 0x00, 0x00, 0x00, 0x01]),
+            &mut out
+        ));
+        assert_eq!(frame::decode(&out).unwrap().1, &[0x81, 0x01]);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::utils::interrupt_free;
+use core::cell::Cell;
+use defmt::info;
+use embassy_stm32::{
+    gpio::{Level, Output, Speed},
+    peripherals,
+};
+use embassy_time::Delay;
+
+pub mod sequence;
+
+use sequence::{ModulePins, Sequencer, Timings};
+pub use sequence::{ModuleState, Sequence};
+
+/// The module's power key (PG10), reset (PH15) and boot-select (PJ12) lines. Power is
+/// active-high, reset and boot-select are active-low.
+pub struct GpioPins<'d> {
+    power: Output<'d>,
+    reset: Output<'d>,
+    boot: Output<'d>,
+}
+
+impl ModulePins for GpioPins<'_> {
+    fn set_power(&mut self, on: bool) {
+        self.power
+            .set_level(if on { Level::High } else { Level::Low });
+    }
+
+    fn set_reset(&mut self, asserted: bool) {
+        self.reset
+            .set_level(if asserted { Level::Low } else { Level::High });
+    }
+
+    fn set_boot(&mut self, asserted: bool) {
+        self.boot
+            .set_level(if asserted { Level::Low } else { Level::High });
+    }
+}
+
+pub type Module<'d> = Sequencer<GpioPins<'d>, Delay, fn(ModuleState, ModuleState)>;
+
+static STATE: critical_section::Mutex<Cell<ModuleState>> =
+    critical_section::Mutex::new(Cell::new(ModuleState::Off));
+
+fn report(old: ModuleState, new: ModuleState) {
+    info!(
+        "module: {} -> {}",
+        defmt::Display2Format(&old),
+        defmt::Display2Format(&new)
+    );
+    interrupt_free(|cs| STATE.borrow(cs).set(new));
+}
+
+/// Last state reported by the [`Module`].
+pub fn state() -> ModuleState {
+    interrupt_free(|cs| STATE.borrow(cs).get())
+}
+
+/// Take over the module's control lines, holding it unpowered and in reset until a
+/// [`Sequence::PowerOn`] is run.
+pub fn new(
+    power: peripherals::PG10,
+    reset: peripherals::PH15,
+    boot: peripherals::PJ12,
+    timings: Timings,
+) -> Module<'static> {
+    let pins = GpioPins {
+        power: Output::new(power, Level::Low, Speed::Low),
+        reset: Output::new(reset, Level::Low, Speed::Low),
+        boot: Output::new(boot, Level::High, Speed::Low),
+    };
+
+    Sequencer::new(pins, Delay, timings, report as fn(ModuleState, ModuleState))
+}
@@ -0,0 +1,458 @@
+use embassy_futures::select::{select, Either};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::Read;
+
+/// Power, reset and boot-select lines of the module, in logical terms. Polarity is up to the
+/// implementation.
+pub trait ModulePins {
+    fn set_power(&mut self, on: bool);
+    fn set_reset(&mut self, asserted: bool);
+    fn set_boot(&mut self, asserted: bool);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleState {
+    Off,
+    /// Pins are being sequenced.
+    Starting,
+    /// Booted and printed its ready banner.
+    Ready,
+    /// Held in the ROM bootloader for flashing.
+    Bootloader,
+    /// Held in reset by request, e.g. during a self-test.
+    Reset,
+    /// Lines are driven from outside, e.g. by the USB bridge's DTR/RTS.
+    External,
+    /// The last sequence didn't complete.
+    Fault(Fault),
+}
+
+impl core::fmt::Display for ModuleState {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            ModuleState::Off => "off",
+            ModuleState::Starting => "starting",
+            ModuleState::Ready => "ready",
+            ModuleState::Bootloader => "bootloader",
+            ModuleState::Reset => "held in reset",
+            ModuleState::External => "external control",
+            ModuleState::Fault(Fault::NoBanner) => "fault: no ready banner",
+            ModuleState::Fault(Fault::Serial) => "fault: serial error",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// No ready banner within [`Timings::ready_timeout_ms`].
+    NoBanner,
+    /// The UART failed while waiting for the banner.
+    Serial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sequence {
+    PowerOn,
+    HardReset,
+    Bootloader,
+    PowerDown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// Supply ramp before reset is released.
+    pub power_settle_ms: u32,
+    pub reset_pulse_ms: u32,
+    /// How long boot-select is held after reset is released, so the ROM samples it.
+    pub boot_hold_ms: u32,
+    pub ready_timeout_ms: u32,
+    /// Discharge time after removing power.
+    pub power_off_ms: u32,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Self {
+            power_settle_ms: 50,
+            reset_pulse_ms: 20,
+            boot_hold_ms: 50,
+            ready_timeout_ms: 3_000,
+            power_off_ms: 100,
+        }
+    }
+}
+
+/// ESP-AT prints this once it accepts commands.
+pub const READY_BANNER: &[u8] = b"\r\nready\r\n";
+
+/// Finds `pattern` in a byte stream fed in arbitrary chunks.
+#[derive(Debug, Clone)]
+pub struct BannerMatcher<'a> {
+    pattern: &'a [u8],
+    matched: usize,
+}
+
+impl<'a> BannerMatcher<'a> {
+    pub fn new(pattern: &'a [u8]) -> Self {
+        Self {
+            pattern,
+            matched: 0,
+        }
+    }
+
+    /// Returns true once the whole pattern has been seen.
+    pub fn feed(&mut self, data: &[u8]) -> bool {
+        for &b in data {
+            if self.matched == self.pattern.len() {
+                break;
+            }
+            // Fall back to the longest prefix of the pattern that is a suffix of what matched,
+            // so overlapping candidates (e.g. "\r\n\r\nready") are not missed.
+            while self.matched > 0 && self.pattern[self.matched] != b {
+                self.matched = self.longest_border(self.matched);
+            }
+            if self.pattern[self.matched] == b {
+                self.matched += 1;
+            }
+        }
+
+        self.matched == self.pattern.len()
+    }
+
+    fn longest_border(&self, len: usize) -> usize {
+        let matched = &self.pattern[..len];
+        (1..len)
+            .rev()
+            .find(|&k| matched[len - k..] == self.pattern[..k])
+            .unwrap_or(0)
+    }
+
+    pub fn reset(&mut self) {
+        self.matched = 0;
+    }
+}
+
+/// Runs power and reset sequences on a set of [`ModulePins`], reporting every state change to
+/// `on_state`.
+pub struct Sequencer<P, D, F> {
+    pins: P,
+    delay: D,
+    timings: Timings,
+    state: ModuleState,
+    on_state: F,
+}
+
+impl<P, D, F> Sequencer<P, D, F>
+where
+    P: ModulePins,
+    D: DelayNs,
+    F: FnMut(ModuleState, ModuleState),
+{
+    /// The module is assumed to be off; call [`Self::run`] with [`Sequence::PowerOn`] to start it.
+    pub fn new(pins: P, delay: D, timings: Timings, on_state: F) -> Self {
+        Self {
+            pins,
+            delay,
+            timings,
+            state: ModuleState::Off,
+            on_state,
+        }
+    }
+
+    pub fn state(&self) -> ModuleState {
+        self.state
+    }
+
+    pub fn timings(&self) -> &Timings {
+        &self.timings
+    }
+
+    pub fn pins(&mut self) -> &mut P {
+        &mut self.pins
+    }
+
+    fn enter(&mut self, state: ModuleState) {
+        if state != self.state {
+            let old = core::mem::replace(&mut self.state, state);
+            (self.on_state)(old, state);
+        }
+    }
+
+    /// Hold the module in reset until the next sequence.
+    pub fn hold_in_reset(&mut self) {
+        self.pins.set_boot(false);
+        self.pins.set_reset(true);
+        self.enter(ModuleState::Reset);
+    }
+
+    /// Drive reset and boot-select directly, e.g. from a USB-serial adapter's DTR/RTS. Power is
+    /// left on.
+    pub fn set_lines(&mut self, reset: bool, boot: bool) {
+        self.pins.set_power(true);
+        self.pins.set_reset(reset);
+        self.pins.set_boot(boot);
+        self.enter(ModuleState::External);
+    }
+
+    /// Run `sequence`. Sequences that boot the module wait for [`READY_BANNER`] on `rx`; other
+    /// data received meanwhile is discarded.
+    pub async fn run<R: Read>(&mut self, sequence: Sequence, rx: &mut R) -> ModuleState {
+        let t = self.timings;
+        self.enter(ModuleState::Starting);
+
+        let state = match sequence {
+            Sequence::PowerOn => {
+                self.pins.set_boot(false);
+                self.pins.set_reset(true);
+                self.pins.set_power(true);
+                self.delay.delay_ms(t.power_settle_ms).await;
+                self.pins.set_reset(false);
+                self.wait_ready(rx).await
+            }
+            Sequence::HardReset => {
+                self.pins.set_boot(false);
+                self.pins.set_reset(true);
+                self.pins.set_power(true);
+                self.delay.delay_ms(t.reset_pulse_ms).await;
+                self.pins.set_reset(false);
+                self.wait_ready(rx).await
+            }
+            Sequence::Bootloader => {
+                self.pins.set_boot(true);
+                self.pins.set_reset(true);
+                self.pins.set_power(true);
+                self.delay.delay_ms(t.reset_pulse_ms).await;
+                self.pins.set_reset(false);
+                self.delay.delay_ms(t.boot_hold_ms).await;
+                self.pins.set_boot(false);
+                ModuleState::Bootloader
+            }
+            Sequence::PowerDown => {
+                self.pins.set_boot(false);
+                self.pins.set_reset(true);
+                self.pins.set_power(false);
+                self.delay.delay_ms(t.power_off_ms).await;
+                ModuleState::Off
+            }
+        };
+
+        self.enter(state);
+        state
+    }
+
+    async fn wait_ready<R: Read>(&mut self, rx: &mut R) -> ModuleState {
+        let mut matcher = BannerMatcher::new(READY_BANNER);
+        let mut buf = [0u8; 32];
+        let timeout = self.delay.delay_ms(self.timings.ready_timeout_ms);
+        let mut timeout = core::pin::pin!(timeout);
+
+        loop {
+            match select(rx.read(&mut buf), timeout.as_mut()).await {
+                Either::First(Ok(n)) => {
+                    if matcher.feed(&buf[..n]) {
+                        return ModuleState::Ready;
+                    }
+                }
+                Either::First(Err(_)) => return ModuleState::Fault(Fault::Serial),
+                Either::Second(()) => return ModuleState::Fault(Fault::NoBanner),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{boxed::Box, rc::Rc, vec::Vec};
+    use core::{cell::RefCell, convert::Infallible, future::pending};
+    use embassy_futures::block_on;
+    use embedded_io_async::ErrorType;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Event {
+        Power(bool),
+        Reset(bool),
+        Boot(bool),
+    }
+
+    /// Virtual clock shared by the fakes, in milliseconds.
+    #[derive(Default)]
+    struct World {
+        now: u32,
+        events: Vec<(u32, Event)>,
+    }
+
+    struct FakePins(Rc<RefCell<World>>);
+
+    impl FakePins {
+        fn log(&mut self, event: Event) {
+            let mut world = self.0.borrow_mut();
+            let now = world.now;
+            world.events.push((now, event));
+        }
+    }
+
+    impl ModulePins for FakePins {
+        fn set_power(&mut self, on: bool) {
+            self.log(Event::Power(on));
+        }
+        fn set_reset(&mut self, asserted: bool) {
+            self.log(Event::Reset(asserted));
+        }
+        fn set_boot(&mut self, asserted: bool) {
+            self.log(Event::Boot(asserted));
+        }
+    }
+
+    struct FakeDelay(Rc<RefCell<World>>);
+
+    impl DelayNs for FakeDelay {
+        async fn delay_ns(&mut self, ns: u32) {
+            self.0.borrow_mut().now += ns / 1_000_000;
+        }
+    }
+
+    /// Serial peer that replays `(at_ms, data)` chunks. Once they run out it never answers.
+    struct ScriptedPeer {
+        world: Rc<RefCell<World>>,
+        script: Vec<(u32, &'static [u8])>,
+    }
+
+    impl ErrorType for ScriptedPeer {
+        type Error = Infallible;
+    }
+
+    impl Read for ScriptedPeer {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            if self.script.is_empty() {
+                return pending().await;
+            }
+            let (at, data) = self.script.remove(0);
+            let mut world = self.world.borrow_mut();
+            world.now = world.now.max(at);
+            buf[..data.len()].copy_from_slice(data);
+            Ok(data.len())
+        }
+    }
+
+    type FakeSequencer = Sequencer<FakePins, FakeDelay, Box<dyn FnMut(ModuleState, ModuleState)>>;
+    type States = Rc<RefCell<Vec<ModuleState>>>;
+
+    fn setup(
+        script: Vec<(u32, &'static [u8])>,
+    ) -> (Rc<RefCell<World>>, FakeSequencer, ScriptedPeer, States) {
+        let world = Rc::new(RefCell::new(World::default()));
+        let states = Rc::new(RefCell::new(Vec::new()));
+        let log = states.clone();
+        let sequencer: FakeSequencer = Sequencer::new(
+            FakePins(world.clone()),
+            FakeDelay(world.clone()),
+            Timings::default(),
+            Box::new(move |_, new| log.borrow_mut().push(new)),
+        );
+        let peer = ScriptedPeer {
+            world: world.clone(),
+            script,
+        };
+
+        (world, sequencer, peer, states)
+    }
+
+    #[test]
+    fn power_on_waits_for_banner() {
+        let (world, mut seq, mut peer, states) = setup(std::vec![
+            (60, &b"\r\nets Jun  8 2016 00:22:57\r\n"[..]),
+            (400, &b"\r\nrea"[..]),
+            (401, &b"dy\r\n"[..]),
+        ]);
+
+        let state = block_on(seq.run(Sequence::PowerOn, &mut peer));
+
+        assert_eq!(state, ModuleState::Ready);
+        assert_eq!(
+            world.borrow().events,
+            [
+                (0, Event::Boot(false)),
+                (0, Event::Reset(true)),
+                (0, Event::Power(true)),
+                (50, Event::Reset(false)),
+            ]
+        );
+        assert_eq!(
+            *states.borrow(),
+            [ModuleState::Starting, ModuleState::Ready]
+        );
+    }
+
+    #[test]
+    fn missing_banner_is_a_fault() {
+        let (world, mut seq, mut peer, _) = setup(std::vec![(30, &b"garbage"[..])]);
+
+        let state = block_on(seq.run(Sequence::HardReset, &mut peer));
+
+        assert_eq!(state, ModuleState::Fault(Fault::NoBanner));
+        assert_eq!(seq.state(), state);
+        // Reset pulse, then the full ready timeout.
+        assert!(world.borrow().now >= 20 + 3_000);
+    }
+
+    #[test]
+    fn bootloader_holds_boot_select_across_reset_release() {
+        let (world, mut seq, mut peer, _) = setup(Vec::new());
+
+        let state = block_on(seq.run(Sequence::Bootloader, &mut peer));
+
+        assert_eq!(state, ModuleState::Bootloader);
+        assert_eq!(
+            world.borrow().events,
+            [
+                (0, Event::Boot(true)),
+                (0, Event::Reset(true)),
+                (0, Event::Power(true)),
+                (20, Event::Reset(false)),
+                (70, Event::Boot(false)),
+            ]
+        );
+    }
+
+    #[test]
+    fn power_down_and_external_control() {
+        let (world, mut seq, mut peer, states) = setup(Vec::new());
+
+        seq.set_lines(true, false);
+        assert_eq!(seq.state(), ModuleState::External);
+        seq.hold_in_reset();
+        let state = block_on(seq.run(Sequence::PowerDown, &mut peer));
+
+        assert_eq!(state, ModuleState::Off);
+        assert_eq!(world.borrow().now, 100);
+        assert_eq!(
+            world.borrow().events.last(),
+            Some(&(0, Event::Power(false)))
+        );
+        assert_eq!(
+            *states.borrow(),
+            [
+                ModuleState::External,
+                ModuleState::Reset,
+                ModuleState::Starting,
+                ModuleState::Off
+            ]
+        );
+    }
+
+    #[test]
+    fn banner_matcher_handles_overlap_and_chunking() {
+        let mut matcher = BannerMatcher::new(READY_BANNER);
+        assert!(!matcher.feed(b"\r\n\r"));
+        assert!(!matcher.feed(b"\nrea"));
+        assert!(matcher.feed(b"dy\r\nmore"));
+
+        let mut matcher = BannerMatcher::new(b"abab");
+        assert!(matcher.feed(b"aababab"));
+        matcher.reset();
+        assert!(!matcher.feed(b"abba"));
+    }
+}
@@ -0,0 +1,327 @@
+use super::rom::Rom;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use embassy_futures::select::{select, Either};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+/// Rate of the reset slot: the start bit and four zero bits of 0xf0 are a 520 us reset pulse,
+/// and a presence pulse pulls some of the one bits after it low.
+pub const RESET_BAUDRATE: u32 = 9600;
+/// Rate of the bit slots: only the start bit of 0xff is low, for 8.7 us, a write-one or read
+/// slot. A device answering zero holds the line low into the data bits. A zero character is
+/// low for 78 us, a write-zero slot.
+pub const DATA_BAUDRATE: u32 = 115_200;
+
+const RESET: u8 = 0xf0;
+const ONE: u8 = 0xff;
+const ZERO: u8 = 0x00;
+/// An exchange is a few characters; their echo takes a millisecond at most.
+const TIMEOUT_MS: u32 = 10;
+
+pub const SEARCH_ROM: u8 = 0xf0;
+pub const READ_ROM: u8 = 0x33;
+pub const MATCH_ROM: u8 = 0x55;
+pub const SKIP_ROM: u8 = 0xcc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rate {
+    /// [`RESET_BAUDRATE`]
+    Reset,
+    /// [`DATA_BAUDRATE`]
+    Data,
+}
+
+/// Receive side of the UART, which sets the rate of both directions.
+pub trait SetRate {
+    fn set_rate(&mut self, rate: Rate);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No device answered the reset.
+    NoPresence,
+    /// A ROM code or scratchpad failed its CRC.
+    Crc,
+    /// The echo didn't come back: the UART isn't wired to the bus.
+    Timeout,
+    /// Read or write error on the UART, e.g. a framing error with the bus held low.
+    Serial,
+}
+
+impl Error {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::NoPresence => "no presence pulse",
+            Error::Crc => "CRC error",
+            Error::Timeout => "no echo",
+            Error::Serial => "serial error",
+        }
+    }
+}
+
+/// Character sending `bit`, and reading one with a one.
+pub fn slot(bit: bool) -> u8 {
+    if bit {
+        ONE
+    } else {
+        ZERO
+    }
+}
+
+/// What the line did in a slot: only a one comes back unchanged.
+pub fn decode_slot(echo: u8) -> bool {
+    echo == ONE
+}
+
+/// Slots of a byte, least significant bit first.
+pub fn encode_byte(byte: u8) -> [u8; 8] {
+    core::array::from_fn(|n| slot(byte & (1 << n) != 0))
+}
+
+pub fn decode_byte(echo: &[u8; 8]) -> u8 {
+    echo.iter()
+        .enumerate()
+        .fold(0, |byte, (n, &e)| byte | (decode_slot(e) as u8) << n)
+}
+
+/// Progress of a ROM search over calls of [`OneWire::search`].
+#[derive(Debug, Clone)]
+pub struct Search {
+    rom: [u8; 8],
+    /// Bit, counted from 1, of the deepest difference where the last pass took the zero branch;
+    /// the next pass takes the one branch there. 0 once no zero branch is left.
+    last_discrepancy: usize,
+    done: bool,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Self {
+            rom: [0; 8],
+            last_discrepancy: 0,
+            done: false,
+        }
+    }
+}
+
+/// 1-Wire master on a UART whose transmitter pulls the bus low through a diode, with the
+/// receiver on the bus: each character sent is a slot, and its echo is what the line did.
+pub struct OneWire<R, W, D> {
+    rx: R,
+    tx: W,
+    delay: D,
+}
+
+impl<R: Read + SetRate, W: Write, D: DelayNs> OneWire<R, W, D> {
+    /// Transactions start with [`reset`](Self::reset), which sets the rate.
+    pub fn new(rx: R, tx: W, delay: D) -> Self {
+        Self { rx, tx, delay }
+    }
+
+    pub fn delay(&mut self) -> &mut D {
+        &mut self.delay
+    }
+
+    /// Send the slots in `buf`, replacing them with their echo.
+    async fn exchange(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.tx.write_all(buf).await.map_err(|_| Error::Serial)?;
+        self.tx.flush().await.map_err(|_| Error::Serial)?;
+
+        let Self { rx, delay, .. } = self;
+        let echo = async {
+            let mut len = 0;
+            while len < buf.len() {
+                len += rx.read(&mut buf[len..]).await.map_err(|_| Error::Serial)?;
+            }
+            Ok(())
+        };
+        match select(echo, delay.delay_ms(TIMEOUT_MS)).await {
+            Either::First(result) => result,
+            Either::Second(()) => Err(Error::Timeout),
+        }
+    }
+
+    /// Reset pulse; fails unless a device answers with a presence pulse.
+    pub async fn reset(&mut self) -> Result<(), Error> {
+        self.rx.set_rate(Rate::Reset);
+        let mut echo = [RESET];
+        let result = self.exchange(&mut echo).await;
+        self.rx.set_rate(Rate::Data);
+        result?;
+        match echo[0] {
+            RESET => Err(Error::NoPresence),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn write_bit(&mut self, bit: bool) -> Result<(), Error> {
+        self.exchange(&mut [slot(bit)]).await
+    }
+
+    pub async fn read_bit(&mut self) -> Result<bool, Error> {
+        let mut echo = [ONE];
+        self.exchange(&mut echo).await?;
+        Ok(decode_slot(echo[0]))
+    }
+
+    pub async fn write_bytes(&mut self, data: &[u8]) -> Result<(), Error> {
+        for &byte in data {
+            self.exchange(&mut encode_byte(byte)).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        for byte in buf {
+            let mut echo = [ONE; 8];
+            self.exchange(&mut echo).await?;
+            *byte = decode_byte(&echo);
+        }
+        Ok(())
+    }
+
+    /// Reset and address the device with `rom`, or every device on the bus for commands that
+    /// don't answer, or with a single device.
+    pub async fn select(&mut self, rom: Option<&Rom>) -> Result<(), Error> {
+        self.reset().await?;
+        match rom {
+            Some(rom) => {
+                self.write_bytes(&[MATCH_ROM]).await?;
+                self.write_bytes(rom.bytes()).await
+            }
+            None => self.write_bytes(&[SKIP_ROM]).await,
+        }
+    }
+
+    /// ROM code of the only device on the bus. With more, their codes are ANDed and fail the
+    /// CRC, mostly.
+    pub async fn read_rom(&mut self) -> Result<Rom, Error> {
+        self.reset().await?;
+        self.write_bytes(&[READ_ROM]).await?;
+        let mut bytes = [0; 8];
+        self.read_bytes(&mut bytes).await?;
+        Rom::new(bytes).ok_or(Error::Crc)
+    }
+
+    /// The next device of a search, `None` once all were found. Each pass walks the binary tree
+    /// of ROM codes: the devices send every bit and its complement, and both reading zero means
+    /// they differ there. The pass takes the zero branch of a new difference, and the one
+    /// branch where the last pass took zero last.
+    pub async fn search(&mut self, search: &mut Search) -> Result<Option<Rom>, Error> {
+        if search.done {
+            return Ok(None);
+        }
+        match self.reset().await {
+            Err(Error::NoPresence) => {
+                search.done = true;
+                return Ok(None);
+            }
+            result => result?,
+        }
+        self.write_bytes(&[SEARCH_ROM]).await?;
+
+        let mut last_zero = 0;
+        for n in 0..64 {
+            let mut echo = [ONE; 2];
+            self.exchange(&mut echo).await?;
+            let bit = match (decode_slot(echo[0]), decode_slot(echo[1])) {
+                // Nobody is left: a device went away mid-search.
+                (true, true) => {
+                    *search = Search::new();
+                    return Err(Error::NoPresence);
+                }
+                (true, false) => true,
+                (false, true) => false,
+                (false, false) => {
+                    let position = n + 1;
+                    let bit = match position.cmp(&search.last_discrepancy) {
+                        Ordering::Less => search.rom[n / 8] & (1 << (n % 8)) != 0,
+                        Ordering::Equal => true,
+                        Ordering::Greater => false,
+                    };
+                    if !bit {
+                        last_zero = position;
+                    }
+                    bit
+                }
+            };
+            if bit {
+                search.rom[n / 8] |= 1 << (n % 8);
+            } else {
+                search.rom[n / 8] &= !(1 << (n % 8));
+            }
+            self.write_bit(bit).await?;
+        }
+
+        search.last_discrepancy = last_zero;
+        search.done = last_zero == 0;
+        Rom::new(search.rom).map(Some).ok_or(Error::Crc)
+    }
+
+    /// ROM codes of every device on the bus.
+    pub async fn search_all(&mut self) -> Result<Vec<Rom>, Error> {
+        let mut search = Search::new();
+        let mut roms = Vec::new();
+        while let Some(rom) = self.search(&mut search).await? {
+            roms.push(rom);
+        }
+        Ok(roms)
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::onewire::sim::{setup, Device};
+    use embassy_futures::block_on;
+
+    #[test]
+    fn slot_encoding() {
+        assert_eq!(
+            encode_byte(0x55),
+            [0xff, 0x00, 0xff, 0x00, 0xff, 0x00, 0xff, 0x00]
+        );
+        // A device pulling a read slot low shows as zeros in the low data bits.
+        assert_eq!(
+            decode_byte(&[0xff, 0xfc, 0xfe, 0xff, 0xff, 0xff, 0xff, 0x00]),
+            0x79
+        );
+    }
+
+    #[test]
+    fn finds_every_device() {
+        // Codes sharing long prefixes, so the search backtracks deep in the tree.
+        let serials = [0x0a1b2c3d, 0x0a1b2c3c, 0x8a1b2c3d, 0x01];
+        let (bus, mut master) = setup(serials.iter().map(|&s| Device::new(s, 0)).collect());
+
+        let mut found = block_on(master.search_all()).unwrap();
+        assert_eq!(found.len(), serials.len());
+        found.sort();
+        let mut roms: Vec<Rom> = bus.borrow().devices.iter().map(|d| d.rom).collect();
+        roms.sort();
+        assert_eq!(found, roms);
+
+        // One device: read its code directly, and address it.
+        let (bus, mut master) = setup(alloc::vec![Device::new(0x1234, 0)]);
+        let rom = bus.borrow().devices[0].rom;
+        assert_eq!(block_on(master.read_rom()), Ok(rom));
+        assert_eq!(block_on(master.search_all()), Ok(alloc::vec![rom]));
+    }
+
+    #[test]
+    fn empty_bus() {
+        let (_bus, mut master) = setup(Vec::new());
+        assert_eq!(block_on(master.reset()), Err(Error::NoPresence));
+        assert_eq!(block_on(master.search_all()), Ok(Vec::new()));
+        assert_eq!(block_on(master.read_rom()), Err(Error::NoPresence));
+    }
+}
@@ -0,0 +1,232 @@
+use super::{
+    bus::{Error, OneWire, SetRate},
+    rom::{crc8, Rom},
+};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+pub const FAMILY: u8 = 0x28;
+pub const CONVERT_T: u8 = 0x44;
+pub const WRITE_SCRATCHPAD: u8 = 0x4e;
+pub const READ_SCRATCHPAD: u8 = 0xbe;
+
+/// Interval of the busy polls while converting.
+const POLL_MS: u32 = 10;
+
+/// Resolution of the conversions, in the configuration register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Bits9,
+    Bits10,
+    Bits11,
+    Bits12,
+}
+
+impl Resolution {
+    fn from_config(config: u8) -> Self {
+        match (config >> 5) & 0x03 {
+            0 => Resolution::Bits9,
+            1 => Resolution::Bits10,
+            2 => Resolution::Bits11,
+            _ => Resolution::Bits12,
+        }
+    }
+
+    fn config(self) -> u8 {
+        (self as u8) << 5 | 0x1f
+    }
+
+    /// Longest conversion time of the data sheet, rounded up.
+    pub fn conversion_time_ms(self) -> u32 {
+        match self {
+            Resolution::Bits9 => 94,
+            Resolution::Bits10 => 188,
+            Resolution::Bits11 => 375,
+            Resolution::Bits12 => 750,
+        }
+    }
+}
+
+/// The nine scratchpad bytes: temperature, alarm thresholds, configuration and CRC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scratchpad([u8; 9]);
+
+impl Scratchpad {
+    pub fn new(bytes: [u8; 9]) -> Result<Self, Error> {
+        match crc8(&bytes) {
+            0 => Ok(Self(bytes)),
+            _ => Err(Error::Crc),
+        }
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        Resolution::from_config(self.0[4])
+    }
+
+    /// Temperature in 1/16 °C, with the bits below the resolution, undefined, cleared.
+    pub fn raw(&self) -> i16 {
+        let undefined = 3 - self.resolution() as u32;
+        i16::from_le_bytes([self.0[0], self.0[1]]) & (-1 << undefined)
+    }
+
+    pub fn millicelsius(&self) -> i32 {
+        self.raw() as i32 * 125 / 2
+    }
+
+    /// High and low alarm thresholds in °C.
+    pub fn alarms(&self) -> (i8, i8) {
+        (self.0[2] as i8, self.0[3] as i8)
+    }
+}
+
+/// Start a conversion on every DS18B20 on the bus at once.
+pub async fn convert_all<R: Read + SetRate, W: Write, D: DelayNs>(
+    bus: &mut OneWire<R, W, D>,
+) -> Result<(), Error> {
+    bus.select(None).await?;
+    bus.write_bytes(&[CONVERT_T]).await
+}
+
+/// Wait for the conversions to end: converting devices answer read slots with zero. Only for
+/// devices with their own supply; parasite-powered ones need the line held high instead.
+pub async fn wait_conversion<R: Read + SetRate, W: Write, D: DelayNs>(
+    bus: &mut OneWire<R, W, D>,
+    resolution: Resolution,
+) -> Result<(), Error> {
+    for _ in 0..resolution.conversion_time_ms() / POLL_MS + 2 {
+        if bus.read_bit().await? {
+            return Ok(());
+        }
+        bus.delay().delay_ms(POLL_MS).await;
+    }
+    Err(Error::Timeout)
+}
+
+pub async fn read_scratchpad<R: Read + SetRate, W: Write, D: DelayNs>(
+    bus: &mut OneWire<R, W, D>,
+    rom: &Rom,
+) -> Result<Scratchpad, Error> {
+    bus.select(Some(rom)).await?;
+    bus.write_bytes(&[READ_SCRATCHPAD]).await?;
+    let mut bytes = [0; 9];
+    bus.read_bytes(&mut bytes).await?;
+    Scratchpad::new(bytes)
+}
+
+/// Set the resolution of the next conversions, keeping the alarm thresholds. Lost at power-off,
+/// as it isn't copied to the EEPROM.
+pub async fn set_resolution<R: Read + SetRate, W: Write, D: DelayNs>(
+    bus: &mut OneWire<R, W, D>,
+    rom: &Rom,
+    resolution: Resolution,
+) -> Result<(), Error> {
+    let (high, low) = read_scratchpad(bus, rom).await?.alarms();
+    bus.select(Some(rom)).await?;
+    bus.write_bytes(&[WRITE_SCRATCHPAD, high as u8, low as u8, resolution.config()])
+        .await
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::onewire::sim::{setup, Device};
+    use alloc::vec::Vec;
+    use embassy_futures::block_on;
+
+    fn scratchpad(raw: i16, config: u8) -> Scratchpad {
+        let mut bytes = [0, 0, 0x4b, 0x46, config, 0xff, 0x0c, 0x10, 0];
+        bytes[..2].copy_from_slice(&raw.to_le_bytes());
+        bytes[8] = crc8(&bytes[..8]);
+        Scratchpad::new(bytes).unwrap()
+    }
+
+    #[test]
+    fn temperatures() {
+        // The examples of the data sheet.
+        assert_eq!(scratchpad(0x07d0, 0x7f).millicelsius(), 125_000);
+        assert_eq!(scratchpad(0x0550, 0x7f).millicelsius(), 85_000);
+        assert_eq!(scratchpad(0x0191, 0x7f).millicelsius(), 25_062);
+        assert_eq!(scratchpad(0x0008, 0x7f).millicelsius(), 500);
+        assert_eq!(scratchpad(-0x0008, 0x7f).millicelsius(), -500);
+        assert_eq!(scratchpad(-0x00a2, 0x7f).millicelsius(), -10_125);
+        assert_eq!(scratchpad(-0x0370, 0x7f).millicelsius(), -55_000);
+
+        // At 9 bits only half degrees are defined.
+        let low = scratchpad(0x0197, 0x1f);
+        assert_eq!(low.resolution(), Resolution::Bits9);
+        assert_eq!(low.millicelsius(), 25_000);
+        assert_eq!(low.alarms(), (75, 70));
+
+        let mut bytes = low.0;
+        bytes[0] ^= 0x01;
+        assert_eq!(Scratchpad::new(bytes), Err(Error::Crc));
+    }
+
+    #[test]
+    fn converts_on_every_sensor() {
+        let raws = [0x0191, -0x00a2, 0x0008];
+        let (bus, mut master) = setup(
+            raws.iter()
+                .enumerate()
+                .map(|(n, &raw)| Device::new(n as u64 + 1, raw))
+                .collect(),
+        );
+
+        let roms = block_on(master.search_all()).unwrap();
+        // Before the first conversion: the power-on value.
+        let first = block_on(read_scratchpad(&mut master, &roms[0])).unwrap();
+        assert_eq!(first.millicelsius(), 85_000);
+
+        block_on(convert_all(&mut master)).unwrap();
+        block_on(wait_conversion(&mut master, Resolution::Bits12)).unwrap();
+        let mut read: Vec<(Rom, i16)> = roms
+            .iter()
+            .map(|rom| {
+                (
+                    *rom,
+                    block_on(read_scratchpad(&mut master, rom)).unwrap().raw(),
+                )
+            })
+            .collect();
+        read.sort();
+        let mut want: Vec<(Rom, i16)> = bus
+            .borrow()
+            .devices
+            .iter()
+            .map(|d| (d.rom, d.raw))
+            .collect();
+        want.sort();
+        assert_eq!(read, want);
+
+        bus.borrow_mut().devices[0].corrupt = true;
+        let rom = bus.borrow().devices[0].rom;
+        assert_eq!(
+            block_on(read_scratchpad(&mut master, &rom)),
+            Err(Error::Crc)
+        );
+    }
+
+    #[test]
+    fn resolution() {
+        let (bus, mut master) = setup(alloc::vec![Device::new(7, 0x0197)]);
+        let rom = bus.borrow().devices[0].rom;
+
+        block_on(set_resolution(&mut master, &rom, Resolution::Bits10)).unwrap();
+        block_on(convert_all(&mut master)).unwrap();
+        block_on(wait_conversion(&mut master, Resolution::Bits10)).unwrap();
+        let scratchpad = block_on(read_scratchpad(&mut master, &rom)).unwrap();
+        assert_eq!(scratchpad.resolution(), Resolution::Bits10);
+        assert_eq!(scratchpad.alarms(), (75, 70));
+        assert_eq!(scratchpad.raw(), 0x0194);
+
+        // A sensor that never finishes.
+        bus.borrow_mut().devices[0].stuck = true;
+        block_on(convert_all(&mut master)).unwrap();
+        assert_eq!(
+            block_on(wait_conversion(&mut master, Resolution::Bits9)),
+            Err(Error::Timeout)
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{
+    bridge::{now_ms, wait_until},
+    capture::Tap,
+    console::command::OneWireCommand,
+    uart::SerialSettings,
+};
+use alloc::{format, string::String};
+use core::fmt::Write as _;
+use defmt::{info, warn};
+use embassy_embedded_hal::SetConfig;
+use embassy_futures::select::{select, Either};
+use embassy_stm32::{
+    peripherals::USART1,
+    usart::{self as usart_hal, BufferedUartRx, BufferedUartTx},
+};
+use embassy_time::Delay;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{ErrorType, Read, Write};
+
+pub mod bus;
+pub mod ds18b20;
+pub mod rom;
+#[cfg(test)]
+mod sim;
+
+pub use bus::{Error, OneWire, Rate, SetRate};
+pub use ds18b20::Resolution;
+pub use rom::Rom;
+
+const RESET_SETTINGS: SerialSettings = SerialSettings::new(bus::RESET_BAUDRATE);
+const DATA_SETTINGS: SerialSettings = SerialSettings::new(bus::DATA_BAUDRATE);
+const REPORT_MS: u64 = 1_000;
+
+/// USART1's receive half, which sets the rate of both halves.
+struct RateRx<'a>(&'a mut Tap<BufferedUartRx<'static, USART1>>);
+
+impl ErrorType for RateRx<'_> {
+    type Error = usart_hal::Error;
+}
+
+impl Read for RateRx<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf).await
+    }
+}
+
+impl SetRate for RateRx<'_> {
+    fn set_rate(&mut self, rate: Rate) {
+        let settings = match rate {
+            Rate::Reset => &RESET_SETTINGS,
+            Rate::Data => &DATA_SETTINGS,
+        };
+        // Both rates are well within the USART's range.
+        let _ = self.0.set_config(&settings.into());
+    }
+}
+
+/// Run a console `onewire` command on the 1-Wire bus on USART1; `onewire temp` runs until a key
+/// is pressed on `host_rx`. USART1 is put back to `restore` afterwards.
+pub async fn run_command<HR: Read, HW: Write>(
+    rx: &mut Tap<BufferedUartRx<'static, USART1>>,
+    tx: &mut Tap<BufferedUartTx<'static, USART1>>,
+    host_rx: &mut HR,
+    host_tx: &mut HW,
+    command: OneWireCommand,
+    restore: &SerialSettings,
+) -> Result<(), HW::Error> {
+    info!("onewire: {}", defmt::Debug2Format(&command));
+    if command == OneWireCommand::Temperature {
+        host_tx
+            .write_all(b"reading DS18B20s once a second, any key stops\r\n")
+            .await?;
+        host_tx.flush().await?;
+    }
+
+    let mut bus = OneWire::new(RateRx(&mut *rx), &mut *tx, Delay);
+    let mut key = [0u8];
+    let result = match command {
+        OneWireCommand::Search => Ok(match bus.search_all().await {
+            Ok(roms) => {
+                let mut text = String::new();
+                for rom in &roms {
+                    let _ = write!(text, "{rom}\r\n");
+                }
+                let _ = write!(text, "{} devices\r\nOK\r\n", roms.len());
+                text
+            }
+            Err(e) => format!("ERROR: {}\r\n", e.as_str()),
+        }),
+        OneWireCommand::Temperature => {
+            match select(monitor(&mut bus, &mut *host_tx), host_rx.read(&mut key)).await {
+                Either::First(Err(e)) => Err(e),
+                Either::First(Ok(())) | Either::Second(_) => Ok(String::from("OK\r\n")),
+            }
+        }
+    };
+
+    if let Err(e) = rx.set_config(&restore.into()) {
+        warn!(
+            "onewire: failed to restore UART config: {}",
+            defmt::Debug2Format(&e)
+        );
+    }
+
+    host_tx.write_all(result?.as_bytes()).await?;
+    host_tx.flush().await
+}
+
+/// Convert on every DS18B20 at once and print their temperatures, once a second.
+async fn monitor<R: Read + SetRate, W: Write, D: DelayNs, HW: Write>(
+    bus: &mut OneWire<R, W, D>,
+    host_tx: &mut HW,
+) -> Result<(), HW::Error> {
+    let mut text = String::new();
+    loop {
+        let start = now_ms();
+        text.clear();
+        if let Err(e) = read_all(bus, &mut text).await {
+            let _ = write!(text, "{}\r\n", e.as_str());
+        }
+        host_tx.write_all(text.as_bytes()).await?;
+        wait_until(Some(start + REPORT_MS)).await;
+    }
+}
+
+/// Search the bus, so sensors can come and go, convert, and read each sensor.
+async fn read_all<R: Read + SetRate, W: Write, D: DelayNs>(
+    bus: &mut OneWire<R, W, D>,
+    text: &mut String,
+) -> Result<(), Error> {
+    let roms = bus.search_all().await?;
+    ds18b20::convert_all(bus).await?;
+    ds18b20::wait_conversion(bus, Resolution::Bits12).await?;
+
+    for rom in roms.iter().filter(|rom| rom.family() == ds18b20::FAMILY) {
+        match ds18b20::read_scratchpad(bus, rom).await {
+            Ok(scratchpad) => {
+                let millicelsius = scratchpad.millicelsius();
+                let sign = if millicelsius < 0 { "-" } else { "" };
+                let millicelsius = millicelsius.unsigned_abs();
+                let _ = write!(
+                    text,
+                    "{rom}: {sign}{}.{:03} C\r\n",
+                    millicelsius / 1000,
+                    millicelsius % 1000
+                );
+            }
+            Err(e) => {
+                let _ = write!(text, "{rom}: {}\r\n", e.as_str());
+            }
+        }
+    }
+    Ok(())
+}
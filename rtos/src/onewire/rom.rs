@@ -0,0 +1,74 @@
+use core::fmt;
+
+/// Dallas/Maxim CRC-8 (x^8 + x^5 + x^4 + 1, reflected) of ROM codes and scratchpads. A block
+/// followed by its CRC sums to zero.
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &b| {
+        (0..8).fold(crc ^ b, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0x8c,
+            _ => crc >> 1,
+        })
+    })
+}
+
+/// 64-bit ROM code of a device, in the order it is sent: family code, 48-bit serial number
+/// least significant byte first, CRC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rom([u8; 8]);
+
+impl Rom {
+    /// `None` unless the CRC matches.
+    pub fn new(bytes: [u8; 8]) -> Option<Self> {
+        (crc8(&bytes) == 0).then_some(Self(bytes))
+    }
+
+    pub fn family(&self) -> u8 {
+        self.0[0]
+    }
+
+    pub fn bytes(&self) -> &[u8; 8] {
+        &self.0
+    }
+
+    /// Bit `n` of the code, as the search sends it: least significant bit of the family first.
+    pub fn bit(&self, n: usize) -> bool {
+        self.0[n / 8] & (1 << (n % 8)) != 0
+    }
+}
+
+impl fmt::Display for Rom {
+    /// Family code and serial number the way the Linux w1 driver names devices, e.g.
+    /// `28-00000a1b2c3d`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02x}-", self.0[0])?;
+        for b in self.0[1..7].iter().rev() {
+            write!(f, "{b:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn crc_of_rom_codes() {
+        // The example of Maxim application note 27.
+        let bytes = [0x02, 0x1c, 0xb8, 0x01, 0x00, 0x00, 0x00, 0xa2];
+        assert_eq!(crc8(&bytes[..7]), 0xa2);
+        let rom = Rom::new(bytes).unwrap();
+        assert_eq!(rom.family(), 0x02);
+        assert!(!rom.bit(0));
+        assert!(rom.bit(1));
+        assert!(rom.bit(10));
+        assert_eq!(rom.to_string(), "02-00000001b81c");
+
+        let mut corrupt = bytes;
+        corrupt[3] ^= 0x10;
+        assert_eq!(Rom::new(corrupt), None);
+    }
+}
@@ -0,0 +1,232 @@
+//! A 1-Wire bus behind the UART, for the tests: DS18B20s answering ROM commands, conversions
+//! and scratchpad reads and writes, and the echo of every slot.
+
+use super::{
+    bus::{OneWire, Rate, SetRate},
+    ds18b20,
+    rom::{crc8, Rom},
+};
+use alloc::{collections::VecDeque, rc::Rc, vec::Vec};
+use core::{cell::RefCell, convert::Infallible, future::pending};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{ErrorType, Read, Write};
+
+/// Read slots a conversion answers with zero.
+const BUSY_SLOTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Until the next reset.
+    Idle,
+    RomCommand,
+    /// Bit `n` of the ROM code, its complement, then the direction the master took.
+    Search {
+        n: usize,
+        slot: u8,
+    },
+    Match(usize),
+    ReadRom(usize),
+    Function,
+    Converting(u32),
+    ReadScratchpad(usize),
+    WriteScratchpad(usize),
+}
+
+pub struct Device {
+    pub rom: Rom,
+    /// What the next conversion measures, in 1/16 °C.
+    pub raw: i16,
+    /// Flip a bit of the scratchpad CRC when reading it.
+    pub corrupt: bool,
+    /// Conversions never end.
+    pub stuck: bool,
+    scratchpad: [u8; 9],
+    state: State,
+    shift: u8,
+    bits: u8,
+}
+
+impl Device {
+    pub fn new(serial: u64, raw: i16) -> Self {
+        let mut rom = [0; 8];
+        rom[0] = ds18b20::FAMILY;
+        rom[1..7].copy_from_slice(&serial.to_le_bytes()[..6]);
+        rom[7] = crc8(&rom[..7]);
+        // 85 °C until the first conversion.
+        let mut scratchpad = [0x50, 0x05, 0x4b, 0x46, 0x7f, 0xff, 0x0c, 0x10, 0];
+        scratchpad[8] = crc8(&scratchpad[..8]);
+        Self {
+            rom: Rom::new(rom).unwrap(),
+            raw,
+            corrupt: false,
+            stuck: false,
+            scratchpad,
+            state: State::Idle,
+            shift: 0,
+            bits: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = State::RomCommand;
+        self.bits = 0;
+    }
+
+    /// Whether the device leaves the line high in this slot.
+    fn output(&self) -> bool {
+        match self.state {
+            State::Search { n, slot: 0 } | State::ReadRom(n) => self.rom.bit(n),
+            State::Search { n, slot: 1 } => !self.rom.bit(n),
+            State::Converting(slots) => slots == 0 && !self.stuck,
+            State::ReadScratchpad(n) => {
+                let bit = self.scratchpad[n / 8] & (1 << (n % 8)) != 0;
+                bit ^ (self.corrupt && n == 64)
+            }
+            _ => true,
+        }
+    }
+
+    /// Shift in a bit, returning the byte it completes.
+    fn byte(&mut self, level: bool) -> Option<u8> {
+        self.shift = self.shift >> 1 | (level as u8) << 7;
+        self.bits += 1;
+        (self.bits == 8).then(|| {
+            self.bits = 0;
+            self.shift
+        })
+    }
+
+    fn sample(&mut self, level: bool) {
+        self.state = match self.state {
+            State::Idle => State::Idle,
+            State::RomCommand => match self.byte(level) {
+                None => State::RomCommand,
+                Some(0xf0) => State::Search { n: 0, slot: 0 },
+                Some(0x55) => State::Match(0),
+                Some(0x33) => State::ReadRom(0),
+                Some(0xcc) => State::Function,
+                Some(_) => State::Idle,
+            },
+            State::Search {
+                n,
+                slot: slot @ (0 | 1),
+            } => State::Search { n, slot: slot + 1 },
+            State::Search { n, .. } | State::Match(n) if level != self.rom.bit(n) => State::Idle,
+            State::Search { n: 63, .. } | State::Match(63) | State::ReadRom(63) => State::Function,
+            State::Search { n, .. } => State::Search { n: n + 1, slot: 0 },
+            State::Match(n) => State::Match(n + 1),
+            State::ReadRom(n) => State::ReadRom(n + 1),
+            State::Function => match self.byte(level) {
+                None => State::Function,
+                Some(ds18b20::CONVERT_T) => {
+                    self.scratchpad[..2].copy_from_slice(&self.raw.to_le_bytes());
+                    self.scratchpad[8] = crc8(&self.scratchpad[..8]);
+                    State::Converting(BUSY_SLOTS)
+                }
+                Some(ds18b20::READ_SCRATCHPAD) => State::ReadScratchpad(0),
+                Some(ds18b20::WRITE_SCRATCHPAD) => State::WriteScratchpad(0),
+                Some(_) => State::Idle,
+            },
+            State::Converting(slots) => State::Converting(slots.saturating_sub(1)),
+            State::ReadScratchpad(71) => State::Idle,
+            State::ReadScratchpad(n) => State::ReadScratchpad(n + 1),
+            State::WriteScratchpad(n) => match self.byte(level) {
+                None => State::WriteScratchpad(n),
+                Some(b) => {
+                    self.scratchpad[2 + n] = b;
+                    self.scratchpad[8] = crc8(&self.scratchpad[..8]);
+                    match n {
+                        2 => State::Idle,
+                        _ => State::WriteScratchpad(n + 1),
+                    }
+                }
+            },
+        }
+    }
+}
+
+pub struct Bus {
+    pub devices: Vec<Device>,
+    rate: Rate,
+    echo: VecDeque<u8>,
+}
+
+impl Bus {
+    fn put(&mut self, c: u8) {
+        let echo = match self.rate {
+            Rate::Reset => {
+                for device in &mut self.devices {
+                    device.reset();
+                }
+                // Presence pulses hold the first one bits low.
+                if self.devices.is_empty() {
+                    c
+                } else {
+                    c & 0xcf
+                }
+            }
+            Rate::Data => {
+                let released = c == 0xff;
+                let level = released && self.devices.iter().all(Device::output);
+                for device in &mut self.devices {
+                    device.sample(level);
+                }
+                match (released, level) {
+                    (true, false) => 0xfc,
+                    _ => c,
+                }
+            }
+        };
+        self.echo.push_back(echo);
+    }
+}
+
+pub struct Port(Rc<RefCell<Bus>>);
+
+impl ErrorType for Port {
+    type Error = Infallible;
+}
+
+impl Read for Port {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        let next = self.0.borrow_mut().echo.pop_front();
+        match next {
+            Some(b) => {
+                buf[0] = b;
+                Ok(1)
+            }
+            None => pending().await,
+        }
+    }
+}
+
+impl Write for Port {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        for &c in buf {
+            self.0.borrow_mut().put(c);
+        }
+        Ok(buf.len())
+    }
+}
+
+impl SetRate for Port {
+    fn set_rate(&mut self, rate: Rate) {
+        self.0.borrow_mut().rate = rate;
+    }
+}
+
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
+
+pub fn setup(devices: Vec<Device>) -> (Rc<RefCell<Bus>>, OneWire<Port, Port, NoDelay>) {
+    let bus = Rc::new(RefCell::new(Bus {
+        devices,
+        rate: Rate::Data,
+        echo: VecDeque::new(),
+    }));
+    let master = OneWire::new(Port(bus.clone()), Port(bus.clone()), NoDelay);
+    (bus, master)
+}
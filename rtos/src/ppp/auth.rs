@@ -0,0 +1,353 @@
+//! Client side of PAP (RFC 1334) and CHAP with MD5 (RFC 1994).
+
+use super::{
+    negotiate::{MAX_CONFIGURE, RESTART_TICKS},
+    packet::{protocol, send, Outbox, Outgoing, Packet},
+};
+
+/// Name and secret to authenticate with. Most carriers accept anything.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub username: heapless::String<32>,
+    pub password: heapless::String<32>,
+}
+
+/// Authentication the peer asked for during LCP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    None,
+    Pap,
+    ChapMd5,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Pending,
+    Success,
+    Failed,
+}
+
+mod code {
+    pub const PAP_REQUEST: u8 = 1;
+    pub const PAP_ACK: u8 = 2;
+    pub const PAP_NAK: u8 = 3;
+    pub const CHAP_CHALLENGE: u8 = 1;
+    pub const CHAP_RESPONSE: u8 = 2;
+    pub const CHAP_SUCCESS: u8 = 3;
+    pub const CHAP_FAILURE: u8 = 4;
+}
+
+#[derive(Debug)]
+pub struct Authenticator {
+    method: Method,
+    state: State,
+    id: u8,
+    ticks: u8,
+    retries: u8,
+}
+
+impl Authenticator {
+    pub fn new(method: Method) -> Self {
+        Self {
+            method,
+            state: match method {
+                Method::None => State::Success,
+                _ => State::Pending,
+            },
+            id: 0,
+            ticks: 0,
+            retries: MAX_CONFIGURE,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// PAP sends the first request; CHAP waits for the peer's challenge.
+    pub fn start(&mut self, credentials: &Credentials, out: &mut Outbox) {
+        if self.method == Method::Pap {
+            self.send_pap(credentials, out);
+        }
+    }
+
+    /// Resend PAP requests, and give up on a peer that stays silent. Call once a second.
+    pub fn tick(&mut self, credentials: &Credentials, out: &mut Outbox) {
+        if self.state != State::Pending {
+            return;
+        }
+        self.ticks += 1;
+        if self.ticks < RESTART_TICKS {
+            return;
+        }
+        if self.retries == 0 {
+            self.state = State::Failed;
+            return;
+        }
+
+        self.retries -= 1;
+        self.ticks = 0;
+        if self.method == Method::Pap {
+            self.send_pap(credentials, out);
+        }
+    }
+
+    pub fn received(
+        &mut self,
+        protocol: u16,
+        packet: &Packet<'_>,
+        credentials: &Credentials,
+        out: &mut Outbox,
+    ) {
+        match (self.method, protocol, packet.code) {
+            (Method::Pap, protocol::PAP, code::PAP_ACK) if packet.id == self.id => {
+                self.state = State::Success
+            }
+            (Method::Pap, protocol::PAP, code::PAP_NAK) if packet.id == self.id => {
+                self.state = State::Failed
+            }
+            // The peer may challenge again at any time, also after success.
+            (Method::ChapMd5, protocol::CHAP, code::CHAP_CHALLENGE) => {
+                let Some((&size, rest)) = packet.data.split_first() else {
+                    return;
+                };
+                let Some(challenge) = rest.get(..size as usize) else {
+                    return;
+                };
+
+                self.id = packet.id;
+                let secret = credentials.password.as_bytes();
+                let digest = md5(&[&[packet.id], secret, challenge]);
+                let username = credentials.username.as_bytes();
+                send(
+                    out,
+                    Outgoing::new(
+                        protocol::CHAP,
+                        code::CHAP_RESPONSE,
+                        packet.id,
+                        &[&[16], &digest, username],
+                    ),
+                );
+            }
+            (Method::ChapMd5, protocol::CHAP, code::CHAP_SUCCESS) if packet.id == self.id => {
+                self.state = State::Success
+            }
+            (Method::ChapMd5, protocol::CHAP, code::CHAP_FAILURE) if packet.id == self.id => {
+                self.state = State::Failed
+            }
+            _ => {}
+        }
+    }
+
+    fn send_pap(&mut self, credentials: &Credentials, out: &mut Outbox) {
+        self.id = self.id.wrapping_add(1);
+        let username = credentials.username.as_bytes();
+        let password = credentials.password.as_bytes();
+        send(
+            out,
+            Outgoing::new(
+                protocol::PAP,
+                code::PAP_REQUEST,
+                self.id,
+                &[
+                    &[username.len() as u8],
+                    username,
+                    &[password.len() as u8],
+                    password,
+                ],
+            ),
+        );
+    }
+}
+
+/// MD5 over the concatenation of `parts`. Only for CHAP; MD5 is not fit for anything that needs
+/// collision resistance.
+pub fn md5(parts: &[&[u8]]) -> [u8; 16] {
+    let mut md5 = Md5 {
+        state: [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476],
+        block: [0; 64],
+        fill: 0,
+        len: 0,
+    };
+    for part in parts {
+        md5.update(part);
+    }
+    md5.finish()
+}
+
+const SHIFTS: [[u32; 4]; 4] = [
+    [7, 12, 17, 22],
+    [5, 9, 14, 20],
+    [4, 11, 16, 23],
+    [6, 10, 15, 21],
+];
+
+/// `floor(abs(sin(i + 1)) * 2^32)`
+const K: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+struct Md5 {
+    state: [u32; 4],
+    block: [u8; 64],
+    fill: usize,
+    len: u64,
+}
+
+impl Md5 {
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.block[self.fill] = b;
+            self.fill += 1;
+            if self.fill == 64 {
+                self.compress();
+                self.fill = 0;
+            }
+        }
+        self.len += data.len() as u64;
+    }
+
+    fn finish(mut self) -> [u8; 16] {
+        let bits = self.len.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.fill != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bits.to_le_bytes());
+
+        let mut digest = [0; 16];
+        for (out, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            out.copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self) {
+        let mut m = [0u32; 16];
+        for (word, bytes) in m.iter_mut().zip(self.block.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(K[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(SHIFTS[i / 16][i % 4]));
+        }
+
+        for (s, v) in self.state.iter_mut().zip([a, b, c, d]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn hex(digest: [u8; 16]) -> std::string::String {
+        digest.iter().map(|b| std::format!("{b:02x}")).collect()
+    }
+
+    fn credentials() -> Credentials {
+        Credentials {
+            username: "user".try_into().unwrap(),
+            password: "secret".try_into().unwrap(),
+        }
+    }
+
+    #[test]
+    fn md5_test_suite() {
+        // RFC 1321 appendix A.5.
+        assert_eq!(hex(md5(&[])), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(md5(&[b"abc"])), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            hex(md5(&[b"message ", b"digest"])),
+            "f96b697d7cb7938d525a2f31aaf161d0"
+        );
+        let digits = b"1234567890".repeat(8);
+        assert_eq!(hex(md5(&[&digits])), "57edf4a22be3c955ac49da2e2107b67a");
+
+        for (i, &k) in K.iter().enumerate() {
+            let expected = ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32;
+            assert_eq!(k, expected, "K[{i}]");
+        }
+    }
+
+    #[test]
+    fn pap() {
+        let mut outbox = Outbox::new();
+        let mut auth = Authenticator::new(Method::Pap);
+        auth.start(&credentials(), &mut outbox);
+
+        let request = outbox.pop_front().unwrap();
+        assert_eq!(request.protocol, protocol::PAP);
+        assert_eq!(&request.packet[..], b"\x01\x01\x00\x10\x04user\x06secret");
+
+        // Retransmitted with a new identifier; the answer to the old one no longer counts.
+        for _ in 0..RESTART_TICKS {
+            auth.tick(&credentials(), &mut outbox);
+        }
+        assert_eq!(outbox.pop_front().unwrap().packet[1], 2);
+        let ack = |id| Packet {
+            code: code::PAP_ACK,
+            id,
+            data: &[],
+        };
+        auth.received(protocol::PAP, &ack(1), &credentials(), &mut outbox);
+        assert_eq!(auth.state(), State::Pending);
+        auth.received(protocol::PAP, &ack(2), &credentials(), &mut outbox);
+        assert_eq!(auth.state(), State::Success);
+    }
+
+    #[test]
+    fn chap_md5() {
+        let mut outbox = Outbox::new();
+        let mut auth = Authenticator::new(Method::ChapMd5);
+        auth.start(&credentials(), &mut outbox);
+        assert!(outbox.is_empty());
+
+        let challenge = [0x11, 0x22, 0x33, 0x44];
+        let mut data = Vec::from([challenge.len() as u8]);
+        data.extend_from_slice(&challenge);
+        data.extend_from_slice(b"isp");
+        let packet = Packet {
+            code: code::CHAP_CHALLENGE,
+            id: 7,
+            data: &data,
+        };
+        auth.received(protocol::CHAP, &packet, &credentials(), &mut outbox);
+
+        let response = outbox.pop_front().unwrap();
+        let Packet { code, id, data } = Packet::parse(&response.packet).unwrap();
+        assert_eq!((code, id, data[0]), (code::CHAP_RESPONSE, 7, 16));
+        assert_eq!(&data[1..17], &md5(&[&[7], b"secret", &challenge]));
+        assert_eq!(&data[17..], b"user");
+
+        let failure = Packet {
+            code: code::CHAP_FAILURE,
+            id: 7,
+            data: b"bad",
+        };
+        auth.received(protocol::CHAP, &failure, &credentials(), &mut outbox);
+        assert_eq!(auth.state(), State::Failed);
+    }
+}
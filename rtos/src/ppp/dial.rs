@@ -0,0 +1,323 @@
+//! Hayes AT dial-up: bring a cellular modem from command mode into a PPP data call.
+
+use core::fmt::Write as _;
+use embassy_futures::select::{select, Either};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+/// `AT`s sent before deciding the modem isn't there.
+const ATTEMPTS: usize = 5;
+const COMMAND_TIMEOUT_MS: u32 = 1_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DialConfig<'a> {
+    /// Access point name of the carrier's packet data network.
+    pub apn: &'a str,
+    pub number: &'a str,
+    /// How long the modem may take to attach and answer `ATD`.
+    pub timeout_ms: u32,
+}
+
+impl Default for DialConfig<'_> {
+    fn default() -> Self {
+        Self {
+            apn: "internet",
+            number: "*99#",
+            timeout_ms: 60_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DialError {
+    /// The modem didn't answer `AT`.
+    NoResponse,
+    /// `ERROR` or `+CME ERROR`, e.g. no SIM or an unknown APN.
+    Rejected,
+    NoCarrier,
+    Busy,
+    NoDialtone,
+    NoAnswer,
+    Timeout,
+    /// The APN or number don't fit the command buffer.
+    TooLong,
+    Serial,
+}
+
+impl DialError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DialError::NoResponse => "no response from modem",
+            DialError::Rejected => "command rejected",
+            DialError::NoCarrier => "no carrier",
+            DialError::Busy => "busy",
+            DialError::NoDialtone => "no dialtone",
+            DialError::NoAnswer => "no answer",
+            DialError::Timeout => "timeout",
+            DialError::TooLong => "command too long",
+            DialError::Serial => "serial error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Final {
+    Ok,
+    Connect,
+}
+
+/// Wake the modem, set up the PDP context and dial. On success the modem is in data mode and
+/// the next bytes on the line are PPP frames.
+pub async fn dial<R: Read, W: Write, D: DelayNs>(
+    rx: &mut R,
+    tx: &mut W,
+    delay: &mut D,
+    config: &DialConfig<'_>,
+) -> Result<(), DialError> {
+    let mut attempts = 0;
+    while command(rx, tx, delay, "AT", COMMAND_TIMEOUT_MS).await != Ok(Final::Ok) {
+        attempts += 1;
+        if attempts == ATTEMPTS {
+            return Err(DialError::NoResponse);
+        }
+    }
+    expect(
+        Final::Ok,
+        command(rx, tx, delay, "ATE0", COMMAND_TIMEOUT_MS).await,
+    )?;
+
+    let mut line = heapless::String::<96>::new();
+    write!(line, "AT+CGDCONT=1,\"IP\",\"{}\"", config.apn).map_err(|_| DialError::TooLong)?;
+    expect(
+        Final::Ok,
+        command(rx, tx, delay, &line, COMMAND_TIMEOUT_MS).await,
+    )?;
+
+    line.clear();
+    write!(line, "ATD{}", config.number).map_err(|_| DialError::TooLong)?;
+    expect(
+        Final::Connect,
+        command(rx, tx, delay, &line, config.timeout_ms).await,
+    )
+}
+
+fn expect(wanted: Final, result: Result<Final, DialError>) -> Result<(), DialError> {
+    match result? {
+        got if got == wanted => Ok(()),
+        _ => Err(DialError::Rejected),
+    }
+}
+
+/// Send one command and read lines up to its final result code.
+async fn command<R: Read, W: Write, D: DelayNs>(
+    rx: &mut R,
+    tx: &mut W,
+    delay: &mut D,
+    line: &str,
+    timeout_ms: u32,
+) -> Result<Final, DialError> {
+    tx.write_all(line.as_bytes())
+        .await
+        .map_err(|_| DialError::Serial)?;
+    tx.write_all(b"\r").await.map_err(|_| DialError::Serial)?;
+    tx.flush().await.map_err(|_| DialError::Serial)?;
+
+    let read = async {
+        let mut line = heapless::Vec::<u8, 64>::new();
+        loop {
+            let mut byte = [0u8];
+            match rx.read(&mut byte).await {
+                Ok(1) => {}
+                _ => return Err(DialError::Serial),
+            }
+            match byte[0] {
+                // Stop right after the result code: after CONNECT, what follows is PPP.
+                b'\r' | b'\n' => {
+                    if let Some(result) = final_result(&line) {
+                        return result;
+                    }
+                    line.clear();
+                }
+                // Long informational lines don't matter, only their start is kept.
+                b => {
+                    let _ = line.push(b);
+                }
+            }
+        }
+    };
+
+    match select(read, delay.delay_ms(timeout_ms)).await {
+        Either::First(result) => result,
+        Either::Second(()) => Err(DialError::Timeout),
+    }
+}
+
+fn final_result(line: &[u8]) -> Option<Result<Final, DialError>> {
+    Some(match line {
+        b"OK" => Ok(Final::Ok),
+        l if l.starts_with(b"CONNECT") => Ok(Final::Connect),
+        l if l == b"ERROR" || l.starts_with(b"+CME ERROR") => Err(DialError::Rejected),
+        b"NO CARRIER" => Err(DialError::NoCarrier),
+        b"BUSY" => Err(DialError::Busy),
+        b"NO DIALTONE" | b"NO DIAL TONE" => Err(DialError::NoDialtone),
+        b"NO ANSWER" => Err(DialError::NoAnswer),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{collections::VecDeque, rc::Rc, string::String, vec::Vec};
+    use core::{cell::RefCell, convert::Infallible, future::pending};
+    use embassy_futures::block_on;
+    use embedded_io_async::ErrorType;
+
+    /// A modem that echoes commands and answers `ATD` with `dial_reply`.
+    struct Modem {
+        /// `AT`s to ignore, like a modem that is still booting.
+        asleep: usize,
+        dial_reply: &'static str,
+        line: Vec<u8>,
+        output: VecDeque<u8>,
+        commands: Vec<String>,
+    }
+
+    impl Modem {
+        fn new(asleep: usize, dial_reply: &'static str) -> Rc<RefCell<Self>> {
+            Rc::new(RefCell::new(Self {
+                asleep,
+                dial_reply,
+                line: Vec::new(),
+                output: VecDeque::new(),
+                commands: Vec::new(),
+            }))
+        }
+
+        fn receive(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                if b != b'\r' {
+                    self.line.push(b);
+                    continue;
+                }
+
+                let line = String::from_utf8(core::mem::take(&mut self.line)).unwrap();
+                self.commands.push(line.clone());
+                if line == "AT" && self.asleep > 0 {
+                    self.asleep -= 1;
+                    continue;
+                }
+                if !self.commands.contains(&"ATE0".into()) || line == "ATE0" {
+                    self.output.extend(line.as_bytes());
+                    self.output.push_back(b'\r');
+                }
+                let reply = match line.as_str() {
+                    "AT" | "ATE0" => "OK",
+                    l if l.starts_with("AT+CGDCONT=1,\"IP\",") => "OK",
+                    l if l.starts_with("ATD") => self.dial_reply,
+                    _ => "ERROR",
+                };
+                self.output.extend(b"\r\n");
+                self.output.extend(reply.as_bytes());
+                self.output.extend(b"\r\n");
+            }
+        }
+    }
+
+    struct Port(Rc<RefCell<Modem>>);
+
+    impl ErrorType for Port {
+        type Error = Infallible;
+    }
+
+    impl Read for Port {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            let n = {
+                let mut modem = self.0.borrow_mut();
+                let n = buf.len().min(modem.output.len());
+                for (dst, src) in buf.iter_mut().zip(modem.output.drain(..n)) {
+                    *dst = src;
+                }
+                n
+            };
+            match n {
+                0 => pending().await,
+                n => Ok(n),
+            }
+        }
+    }
+
+    impl Write for Port {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            self.0.borrow_mut().receive(buf);
+            Ok(buf.len())
+        }
+    }
+
+    /// Times out as soon as the modem has nothing more to say.
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    fn dial_with(modem: &Rc<RefCell<Modem>>, config: &DialConfig<'_>) -> Result<(), DialError> {
+        let (mut rx, mut tx) = (Port(modem.clone()), Port(modem.clone()));
+        block_on(dial(&mut rx, &mut tx, &mut NoDelay, config))
+    }
+
+    #[test]
+    fn dials_a_sleepy_modem() {
+        let modem = Modem::new(2, "CONNECT 150000000");
+        let config = DialConfig {
+            apn: "data.example",
+            ..DialConfig::default()
+        };
+        assert_eq!(dial_with(&modem, &config), Ok(()));
+        assert_eq!(
+            modem.borrow().commands,
+            [
+                "AT",
+                "AT",
+                "AT",
+                "ATE0",
+                r#"AT+CGDCONT=1,"IP","data.example""#,
+                "ATD*99#"
+            ]
+        );
+        // The rest of the CONNECT line is left for the PPP decoder to skip.
+        assert_eq!(modem.borrow().output, [b'\n']);
+    }
+
+    #[test]
+    fn dial_failures() {
+        let modem = Modem::new(0, "NO CARRIER");
+        assert_eq!(
+            dial_with(&modem, &DialConfig::default()),
+            Err(DialError::NoCarrier)
+        );
+
+        let modem = Modem::new(0, "+CME ERROR: 30");
+        assert_eq!(
+            dial_with(&modem, &DialConfig::default()),
+            Err(DialError::Rejected)
+        );
+
+        let modem = Modem::new(usize::MAX, "CONNECT");
+        assert_eq!(
+            dial_with(&modem, &DialConfig::default()),
+            Err(DialError::NoResponse)
+        );
+        assert_eq!(modem.borrow().commands.len(), ATTEMPTS);
+
+        let apn = "a".repeat(100);
+        let config = DialConfig {
+            apn: &apn,
+            ..DialConfig::default()
+        };
+        let modem = Modem::new(0, "CONNECT");
+        assert_eq!(dial_with(&modem, &config), Err(DialError::TooLong));
+    }
+}
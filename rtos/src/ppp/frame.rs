@@ -0,0 +1,237 @@
+//! HDLC-like framing from RFC 1662: flag-delimited frames, byte stuffing and a 16-bit FCS.
+
+use alloc::vec::Vec;
+
+pub const FLAG: u8 = 0x7e;
+const ESCAPE: u8 = 0x7d;
+const ADDRESS: u8 = 0xff;
+const CONTROL: u8 = 0x03;
+/// Residue of the FCS computed over a frame including its own FCS.
+const FCS_GOOD: u16 = 0xf0b8;
+
+/// Escape every control character. Used for LCP, and until the peer agrees to something else.
+pub const DEFAULT_ACCM: u32 = 0xffff_ffff;
+
+/// CRC-16/X.25 over `data`, continuing from `fcs`. Start from `0xffff`.
+pub fn fcs(mut fcs: u16, data: &[u8]) -> u16 {
+    for &b in data {
+        fcs ^= b as u16;
+        for _ in 0..8 {
+            fcs = match fcs & 1 {
+                0 => fcs >> 1,
+                _ => (fcs >> 1) ^ 0x8408,
+            };
+        }
+    }
+    fcs
+}
+
+/// Append one frame to `out`. Control characters set in `accm` are escaped, as are the flag and
+/// escape bytes. Address, control and protocol fields are never compressed.
+pub fn encode(protocol: u16, payload: &[u8], accm: u32, out: &mut Vec<u8>) {
+    let header = [ADDRESS, CONTROL, (protocol >> 8) as u8, protocol as u8];
+    let check = !fcs(fcs(0xffff, &header), payload);
+
+    out.push(FLAG);
+    let mut put = |b: u8| {
+        if b == FLAG || b == ESCAPE || (b < 0x20 && accm & (1 << b) != 0) {
+            out.extend_from_slice(&[ESCAPE, b ^ 0x20]);
+        } else {
+            out.push(b);
+        }
+    };
+
+    header.iter().chain(payload).for_each(|&b| put(b));
+    check.to_le_bytes().into_iter().for_each(put);
+    out.push(FLAG);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Frame check sequence mismatch.
+    Fcs,
+    TooLong,
+    /// Too short to hold a protocol field and FCS, or a malformed protocol field.
+    Malformed,
+}
+
+/// A received frame with address, control and FCS removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub protocol: u16,
+    pub payload: &'a [u8],
+}
+
+/// Reassembles frames from the byte stream.
+#[derive(Debug)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    max_len: usize,
+    escaped: bool,
+    overflow: bool,
+    /// The last frame returned borrows `buf`; clear it on the next byte.
+    done: bool,
+}
+
+impl Decoder {
+    /// `max_len` limits the unescaped frame, protocol field and FCS included.
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(max_len),
+            max_len,
+            escaped: false,
+            overflow: false,
+            done: false,
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<Result<Frame<'_>, DecodeError>> {
+        if core::mem::take(&mut self.done) {
+            self.buf.clear();
+        }
+
+        match byte {
+            FLAG => {
+                self.escaped = false;
+                self.done = true;
+                if core::mem::take(&mut self.overflow) {
+                    return Some(Err(DecodeError::TooLong));
+                }
+                // Back-to-back flags between frames.
+                if self.buf.is_empty() {
+                    return None;
+                }
+                Some(self.finish())
+            }
+            ESCAPE => {
+                self.escaped = true;
+                None
+            }
+            _ if self.overflow => None,
+            b => {
+                let b = match core::mem::take(&mut self.escaped) {
+                    true => b ^ 0x20,
+                    false => b,
+                };
+                if self.buf.len() == self.max_len {
+                    self.overflow = true;
+                } else {
+                    self.buf.push(b);
+                }
+                None
+            }
+        }
+    }
+
+    fn finish(&self) -> Result<Frame<'_>, DecodeError> {
+        if self.buf.len() < 3 {
+            return Err(DecodeError::Malformed);
+        }
+        if fcs(0xffff, &self.buf) != FCS_GOOD {
+            return Err(DecodeError::Fcs);
+        }
+
+        let mut rest = &self.buf[..self.buf.len() - 2];
+        // Address and control may be left out if the peer negotiated that.
+        if let [ADDRESS, CONTROL, tail @ ..] = rest {
+            rest = tail;
+        }
+        // A protocol field with an odd first byte was compressed to one byte.
+        match *rest {
+            [p, ref payload @ ..] if p & 1 == 1 => Ok(Frame {
+                protocol: p as u16,
+                payload,
+            }),
+            [hi, lo, ref payload @ ..] if lo & 1 == 1 => Ok(Frame {
+                protocol: u16::from_be_bytes([hi, lo]),
+                payload,
+            }),
+            _ => Err(DecodeError::Malformed),
+        }
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn decode_all(bytes: &[u8]) -> Vec<Result<(u16, Vec<u8>), DecodeError>> {
+        let mut decoder = Decoder::new(64);
+        bytes
+            .iter()
+            .filter_map(|&b| {
+                decoder
+                    .push(b)
+                    .map(|r| r.map(|f| (f.protocol, f.payload.to_vec())))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn check_value() {
+        assert_eq!(!fcs(0xffff, b"123456789"), 0x906e);
+    }
+
+    #[test]
+    fn escapes_per_accm() {
+        let mut out = Vec::new();
+        encode(0x0021, &[0x01, 0x11, 0x7e, 0x7d, 0x41], 1 << 0x11, &mut out);
+
+        let body = &out[1..out.len() - 1];
+        assert_eq!(out[0], FLAG);
+        assert_eq!(*out.last().unwrap(), FLAG);
+        assert_eq!(
+            &body[..12],
+            &[0xff, 0x03, 0x00, 0x21, 0x01, 0x7d, 0x31, 0x7d, 0x5e, 0x7d, 0x5d, 0x41]
+        );
+        assert!(!body.contains(&FLAG));
+
+        out.clear();
+        encode(0xc021, &[0x01, 0x11], DEFAULT_ACCM, &mut out);
+        assert!(out[1..out.len() - 1]
+            .windows(2)
+            .all(|w| w[1] >= 0x20 || w[0] == ESCAPE));
+    }
+
+    #[test]
+    fn round_trip_and_errors() {
+        let mut stream = vec![FLAG, FLAG];
+        encode(0xc021, &[1, 2, 3, 0x7e], DEFAULT_ACCM, &mut stream);
+        encode(0x0021, &[0x45; 20], 0, &mut stream);
+
+        // A corrupted frame, then one with compressed address, control and protocol fields.
+        let mut bad = Vec::new();
+        encode(0x0021, b"abc", 0, &mut bad);
+        bad[6] ^= 1;
+        stream.extend_from_slice(&bad[1..]);
+        let compressed = [0x21, b'x'];
+        stream.extend_from_slice(&compressed);
+        stream.extend_from_slice(&(!fcs(0xffff, &compressed)).to_le_bytes());
+        stream.push(FLAG);
+
+        assert_eq!(
+            decode_all(&stream),
+            [
+                Ok((0xc021, vec![1, 2, 3, 0x7e])),
+                Ok((0x0021, vec![0x45; 20])),
+                Err(DecodeError::Fcs),
+                Ok((0x0021, vec![b'x'])),
+            ]
+        );
+    }
+
+    #[test]
+    fn oversized_frames_are_dropped() {
+        let mut stream = Vec::new();
+        encode(0x0021, &[0; 100], 0, &mut stream);
+        encode(0x0021, &[1; 10], 0, &mut stream);
+
+        assert_eq!(
+            decode_all(&stream),
+            [Err(DecodeError::TooLong), Ok((0x0021, vec![1; 10]))]
+        );
+    }
+}
@@ -0,0 +1,419 @@
+use super::{
+    auth::{self, Authenticator, Credentials, Method},
+    frame::DEFAULT_ACCM,
+    negotiate::{Negotiator, State},
+    options::{IpcpOptions, LcpOptions},
+    packet::{code, protocol, send, Outbox, Outgoing, Packet},
+};
+use core::net::Ipv4Addr;
+
+/// Link phases (RFC 1661 section 3.2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Dead,
+    Establish,
+    Authenticate,
+    Network,
+    /// IPCP is up; IPv4 datagrams flow.
+    Open,
+    Terminate,
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkConfig {
+    /// Seed for the LCP magic number; should differ between boots.
+    pub magic: u32,
+    pub credentials: Credentials,
+    /// Address to ask for. `0.0.0.0` lets the peer assign one, which is what modems do.
+    pub address: Ipv4Addr,
+    /// Address to assign to a peer that asks for one.
+    pub offer_peer: Option<Ipv4Addr>,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            magic: 0x5050_5031,
+            credentials: Credentials::default(),
+            address: Ipv4Addr::UNSPECIFIED,
+            offer_peer: None,
+        }
+    }
+}
+
+/// What IPCP settled on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ipv4Status {
+    pub address: Ipv4Addr,
+    pub peer: Ipv4Addr,
+    pub dns: heapless::Vec<Ipv4Addr, 2>,
+}
+
+/// PPP control plane: runs LCP, authentication and IPCP in turn, and tells IPv4 datagrams apart
+/// from control traffic. Framing and the serial port are up to the caller.
+#[derive(Debug)]
+pub struct Link {
+    phase: Phase,
+    lcp: Negotiator<LcpOptions>,
+    auth: Authenticator,
+    ipcp: Negotiator<IpcpOptions>,
+    credentials: Credentials,
+    outbox: Outbox,
+    /// Identifier of our last Protocol-Reject.
+    reject_id: u8,
+}
+
+impl Link {
+    pub fn new(config: LinkConfig) -> Self {
+        Self {
+            phase: Phase::Dead,
+            lcp: Negotiator::new(protocol::LCP, LcpOptions::new(config.magic)),
+            auth: Authenticator::new(Method::None),
+            ipcp: Negotiator::new(
+                protocol::IPCP,
+                IpcpOptions::new(config.address, config.offer_peer),
+            ),
+            credentials: config.credentials,
+            outbox: Outbox::new(),
+            reject_id: 0,
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Start negotiating, e.g. once the modem reported `CONNECT`.
+    pub fn open(&mut self) {
+        self.outbox.clear();
+        self.lcp.open(&mut self.outbox);
+        self.phase = Phase::Establish;
+    }
+
+    /// Tell the peer we're done. The link is [`Phase::Dead`] once it agreed or timed out.
+    pub fn close(&mut self) {
+        self.lcp.close(&mut self.outbox);
+        self.phase = match self.lcp.state() {
+            State::Closing => Phase::Terminate,
+            _ => Phase::Dead,
+        };
+    }
+
+    /// Handle a received frame. Returns true if `payload` is an IPv4 datagram for the stack.
+    pub fn received(&mut self, protocol: u16, payload: &[u8]) -> bool {
+        if self.phase == Phase::Dead {
+            return false;
+        }
+
+        let packet = Packet::parse(payload);
+        match (protocol, packet) {
+            (protocol::IPV4, _) => return self.phase == Phase::Open,
+            (protocol::LCP, Some(packet)) => self.lcp_received(&packet),
+            (protocol::PAP | protocol::CHAP, Some(packet)) => {
+                if matches!(
+                    self.phase,
+                    Phase::Authenticate | Phase::Network | Phase::Open
+                ) {
+                    let out = &mut self.outbox;
+                    self.auth
+                        .received(protocol, &packet, &self.credentials, out);
+                }
+            }
+            (protocol::IPCP, Some(packet)) => {
+                if matches!(self.phase, Phase::Network | Phase::Open) {
+                    self.ipcp.received(&packet, &mut self.outbox);
+                }
+            }
+            (protocol::LCP | protocol::PAP | protocol::CHAP | protocol::IPCP, None) => {}
+            // Anything else, e.g. IPv6CP or CCP, isn't spoken here.
+            _ if self.lcp.is_opened() => {
+                self.reject_id = self.reject_id.wrapping_add(1);
+                send(
+                    &mut self.outbox,
+                    Outgoing::new(
+                        protocol::LCP,
+                        code::PROTOCOL_REJECT,
+                        self.reject_id,
+                        &[&protocol.to_be_bytes(), payload],
+                    ),
+                );
+            }
+            _ => {}
+        }
+
+        self.update();
+        false
+    }
+
+    /// Drive retransmissions and timeouts. Call once a second.
+    pub fn tick(&mut self) {
+        self.lcp.tick(&mut self.outbox);
+        if self.phase == Phase::Authenticate {
+            self.auth.tick(&self.credentials, &mut self.outbox);
+        }
+        if matches!(self.phase, Phase::Network | Phase::Open) {
+            self.ipcp.tick(&mut self.outbox);
+        }
+        self.update();
+    }
+
+    /// Next control packet to frame and send.
+    pub fn poll_transmit(&mut self) -> Option<Outgoing> {
+        self.outbox.pop_front()
+    }
+
+    /// Control characters to escape when sending a frame of `protocol`. LCP always uses the
+    /// default so it still gets through if the peer's idea of the ACCM differs from ours.
+    pub fn tx_accm(&self, protocol: u16) -> u32 {
+        match protocol {
+            protocol::LCP => DEFAULT_ACCM,
+            _ if self.lcp.is_opened() => self.lcp.options.peer_accm,
+            _ => DEFAULT_ACCM,
+        }
+    }
+
+    /// Largest IPv4 datagram the peer accepts.
+    pub fn peer_mru(&self) -> u16 {
+        self.lcp.options.peer_mru
+    }
+
+    pub fn ipv4(&self) -> Option<Ipv4Status> {
+        if self.phase != Phase::Open {
+            return None;
+        }
+
+        let options = &self.ipcp.options;
+        Some(Ipv4Status {
+            address: options.address,
+            peer: options.peer_address,
+            dns: options
+                .dns
+                .iter()
+                .flatten()
+                .filter(|dns| !dns.is_unspecified())
+                .copied()
+                .collect(),
+        })
+    }
+
+    fn lcp_received(&mut self, packet: &Packet<'_>) {
+        match packet.code {
+            code::ECHO_REQUEST if self.lcp.is_opened() => {
+                let data = packet.data.get(4..).unwrap_or_default();
+                let magic = self.lcp.options.magic.to_be_bytes();
+                send(
+                    &mut self.outbox,
+                    Outgoing::new(protocol::LCP, code::ECHO_REPLY, packet.id, &[&magic, data]),
+                );
+            }
+            code::ECHO_REQUEST | code::ECHO_REPLY | code::DISCARD_REQUEST => {}
+            _ => self.lcp.received(packet, &mut self.outbox),
+        }
+    }
+
+    /// Move between phases as the protocols come up and go down.
+    fn update(&mut self) {
+        match self.lcp.state() {
+            State::Stopped => {
+                self.phase = Phase::Dead;
+                self.ipcp.reset();
+                return;
+            }
+            _ if matches!(self.phase, Phase::Dead | Phase::Terminate) => return,
+            State::Opened => {}
+            // LCP is renegotiating; everything above it starts over.
+            _ => {
+                if self.phase != Phase::Establish {
+                    self.ipcp.reset();
+                    self.phase = Phase::Establish;
+                }
+                return;
+            }
+        }
+
+        if self.phase == Phase::Establish {
+            self.auth = Authenticator::new(self.lcp.options.auth);
+            self.auth.start(&self.credentials, &mut self.outbox);
+            self.phase = Phase::Authenticate;
+        }
+        if self.phase == Phase::Authenticate {
+            match self.auth.state() {
+                auth::State::Pending => return,
+                auth::State::Failed => return self.close(),
+                auth::State::Success => {
+                    self.ipcp.open(&mut self.outbox);
+                    self.phase = Phase::Network;
+                }
+            }
+        }
+
+        match (self.phase, self.ipcp.state()) {
+            (_, State::Stopped) => self.close(),
+            (Phase::Network, State::Opened) => self.phase = Phase::Open,
+            (Phase::Open, state) if state != State::Opened => self.phase = Phase::Network,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppp::negotiate::{MAX_CONFIGURE, RESTART_TICKS};
+
+    /// Deliver everything either side has to send until both are quiet.
+    fn exchange(a: &mut Link, b: &mut Link) {
+        for _ in 0..32 {
+            let mut quiet = true;
+            while let Some(out) = a.poll_transmit() {
+                b.received(out.protocol, &out.packet);
+                quiet = false;
+            }
+            while let Some(out) = b.poll_transmit() {
+                a.received(out.protocol, &out.packet);
+                quiet = false;
+            }
+            if quiet {
+                return;
+            }
+        }
+        panic!("links never settled");
+    }
+
+    fn pair() -> (Link, Link) {
+        let server = Link::new(LinkConfig {
+            magic: 0x1111_1111,
+            address: Ipv4Addr::new(192, 168, 7, 1),
+            offer_peer: Some(Ipv4Addr::new(192, 168, 7, 2)),
+            ..LinkConfig::default()
+        });
+        let client = Link::new(LinkConfig {
+            magic: 0x2222_2222,
+            ..LinkConfig::default()
+        });
+        (server, client)
+    }
+
+    #[test]
+    fn two_links_come_up() {
+        let (mut server, mut client) = pair();
+        server.open();
+        client.open();
+        exchange(&mut server, &mut client);
+
+        assert_eq!(server.phase(), Phase::Open);
+        assert_eq!(client.phase(), Phase::Open);
+        let status = client.ipv4().unwrap();
+        assert_eq!(status.address, Ipv4Addr::new(192, 168, 7, 2));
+        assert_eq!(status.peer, Ipv4Addr::new(192, 168, 7, 1));
+        assert!(status.dns.is_empty());
+        assert_eq!(client.tx_accm(protocol::IPV4), 0);
+        assert_eq!(client.tx_accm(protocol::LCP), DEFAULT_ACCM);
+
+        assert!(client.received(protocol::IPV4, &[0x45, 0, 0, 20]));
+
+        client.close();
+        assert_eq!(client.phase(), Phase::Terminate);
+        exchange(&mut server, &mut client);
+        assert_eq!(client.phase(), Phase::Dead);
+        assert_eq!(server.phase(), Phase::Dead);
+        assert!(!client.received(protocol::IPV4, &[0x45, 0, 0, 20]));
+    }
+
+    #[test]
+    fn echo_and_unknown_protocols() {
+        let (mut server, mut client) = pair();
+        server.open();
+        client.open();
+        exchange(&mut server, &mut client);
+
+        client.received(
+            protocol::LCP,
+            &[
+                code::ECHO_REQUEST,
+                3,
+                0,
+                10,
+                0x11,
+                0x11,
+                0x11,
+                0x11,
+                b'h',
+                b'i',
+            ],
+        );
+        let reply = client.poll_transmit().unwrap();
+        assert_eq!(
+            &reply.packet[..],
+            &[
+                code::ECHO_REPLY,
+                3,
+                0,
+                10,
+                0x22,
+                0x22,
+                0x22,
+                0x22,
+                b'h',
+                b'i'
+            ]
+        );
+
+        // IPv6CP is rejected as a protocol.
+        client.received(0x8057, &[1, 1, 0, 4]);
+        let reject = client.poll_transmit().unwrap();
+        assert_eq!(reject.packet[0], code::PROTOCOL_REJECT);
+        assert_eq!(&reject.packet[4..], &[0x80, 0x57, 1, 1, 0, 4]);
+        assert_eq!(client.phase(), Phase::Open);
+    }
+
+    #[test]
+    fn gives_up_on_a_silent_peer() {
+        let mut link = Link::new(LinkConfig::default());
+        link.open();
+        let mut sent = 0;
+        for _ in 0..(MAX_CONFIGURE as usize + 2) * RESTART_TICKS as usize {
+            while link.poll_transmit().is_some() {
+                sent += 1;
+            }
+            link.tick();
+        }
+
+        assert_eq!(sent, MAX_CONFIGURE as usize + 1);
+        assert_eq!(link.phase(), Phase::Dead);
+    }
+
+    #[test]
+    fn authenticates_before_ipcp() {
+        let (_, mut client) = pair();
+        client.open();
+        let request = client.poll_transmit().unwrap();
+
+        // The peer requires PAP and acknowledges our options.
+        client.received(
+            protocol::LCP,
+            &[code::CONFIGURE_REQUEST, 1, 0, 8, 3, 4, 0xc0, 0x23],
+        );
+        let ack = client.poll_transmit().unwrap();
+        assert_eq!(ack.packet[0], code::CONFIGURE_ACK);
+        let mut reply = request.packet.clone();
+        reply[0] = code::CONFIGURE_ACK;
+        client.received(protocol::LCP, &reply);
+        assert_eq!(client.phase(), Phase::Authenticate);
+
+        // IPCP has to wait for authentication.
+        client.received(
+            protocol::IPCP,
+            &[code::CONFIGURE_REQUEST, 1, 0, 10, 3, 6, 10, 0, 0, 1],
+        );
+        let pap = client.poll_transmit().unwrap();
+        assert_eq!(pap.protocol, protocol::PAP);
+        assert!(client.poll_transmit().is_none());
+
+        client.received(protocol::PAP, &[2, pap.packet[1], 0, 5, 0]);
+        assert_eq!(client.phase(), Phase::Network);
+        assert_eq!(client.poll_transmit().unwrap().protocol, protocol::IPCP);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::{board::PppResource, uart::SerialSettings};
+use alloc::vec::Vec;
+use defmt::{debug, info, warn};
+use embassy_executor::Spawner;
+use embassy_futures::select::{select3, Either3};
+use embassy_net::{ConfigV4, Ipv4Address, Ipv4Cidr, Stack, StackResources, StaticConfigV4};
+use embassy_net_driver_channel::{
+    self as ch,
+    driver::{HardwareAddress, LinkState},
+};
+use embassy_stm32::{
+    bind_interrupts, peripherals, rng,
+    usart::{self, BufferedUart, BufferedUartRx, BufferedUartTx},
+};
+use embassy_time::{Delay, Duration, Ticker, Timer};
+use embedded_io_async::{Read, Write};
+use static_cell::StaticCell;
+
+pub mod auth;
+pub mod dial;
+pub mod frame;
+pub mod link;
+pub mod negotiate;
+pub mod options;
+pub mod packet;
+
+pub use auth::Credentials;
+pub use dial::{DialConfig, DialError};
+pub use link::{Link, LinkConfig, Phase};
+
+use frame::Decoder;
+use packet::protocol;
+
+bind_interrupts!(struct PppIrqs {
+    UART8 => usart::BufferedInterruptHandler<peripherals::UART8>;
+    HASH_RNG => rng::InterruptHandler<peripherals::RNG>;
+});
+
+pub const MTU: usize = 1500;
+/// Unescaped frame: address, control, protocol, a full datagram and FCS.
+const MAX_FRAME: usize = MTU + 6;
+const UART_BUF_SIZE: usize = 1024;
+/// Pause before dialing again after the link went down.
+const RETRY_SECS: u64 = 5;
+
+pub type Device = ch::Device<'static, MTU>;
+
+#[derive(Debug, Clone)]
+pub struct PppConfig {
+    pub serial: SerialSettings,
+    /// Dial-up sequence for a modem. `None` for a peer that speaks PPP right away, like pppd on
+    /// a host.
+    pub dial: Option<DialConfig<'static>>,
+    pub link: LinkConfig,
+}
+
+impl Default for PppConfig {
+    fn default() -> Self {
+        Self {
+            serial: SerialSettings::new(115200),
+            dial: Some(DialConfig::default()),
+            link: LinkConfig::default(),
+        }
+    }
+}
+
+/// Start PPP on UART8 and the network stack on top of it. The stack gets its IPv4 address from
+/// IPCP each time the link comes up.
+pub async fn spawn(
+    spawner: &Spawner,
+    r: PppResource,
+    mut config: PppConfig,
+) -> &'static Stack<Device> {
+    let mut rng = rng::Rng::new(r.rng, PppIrqs);
+    let mut seed = [0u8; 8];
+    defmt::unwrap!(rng.async_fill_bytes(&mut seed).await);
+    let seed = u64::from_le_bytes(seed);
+    config.link.magic = (seed >> 32) as u32;
+
+    static TX_BUF: StaticCell<[u8; UART_BUF_SIZE]> = StaticCell::new();
+    static RX_BUF: StaticCell<[u8; UART_BUF_SIZE]> = StaticCell::new();
+    let uart = defmt::unwrap!(BufferedUart::new(
+        r.peri,
+        PppIrqs,
+        r.rx,
+        r.tx,
+        &mut TX_BUF.init([0; UART_BUF_SIZE])[..],
+        &mut RX_BUF.init([0; UART_BUF_SIZE])[..],
+        (&config.serial).into(),
+    ));
+
+    static STATE: StaticCell<ch::State<MTU, 4, 4>> = StaticCell::new();
+    let (runner, device) = ch::new(STATE.init(ch::State::new()), HardwareAddress::Ip);
+
+    static RESOURCES: StaticCell<StackResources<4>> = StaticCell::new();
+    static STACK: StaticCell<Stack<Device>> = StaticCell::new();
+    let stack = &*STACK.init(Stack::new(
+        device,
+        embassy_net::Config::default(),
+        RESOURCES.init(StackResources::new()),
+        seed,
+    ));
+
+    defmt::unwrap!(spawner.spawn(net_task(stack)));
+    defmt::unwrap!(spawner.spawn(ppp_task(uart, runner, stack, config)));
+    stack
+}
+
+#[embassy_executor::task]
+async fn net_task(stack: &'static Stack<Device>) -> ! {
+    stack.run().await
+}
+
+#[embassy_executor::task]
+async fn ppp_task(
+    uart: BufferedUart<'static, peripherals::UART8>,
+    runner: ch::Runner<'static, MTU>,
+    stack: &'static Stack<Device>,
+    config: PppConfig,
+) -> ! {
+    let (mut tx, mut rx) = uart.split();
+    let (state, mut rx_chan, mut tx_chan) = runner.split();
+
+    loop {
+        if let Some(dial) = &config.dial {
+            info!("ppp: dialing {=str}", dial.number);
+            if let Err(e) = dial::dial(&mut rx, &mut tx, &mut Delay, dial).await {
+                warn!("ppp: dial failed: {=str}", e.as_str());
+                Timer::after_secs(RETRY_SECS).await;
+                continue;
+            }
+        }
+
+        info!("ppp: negotiating");
+        let mut link = Link::new(config.link.clone());
+        let mut session = Session {
+            link: &mut link,
+            rx: &mut rx,
+            tx: &mut tx,
+            stack,
+            up: false,
+        };
+        session.run(&state, &mut rx_chan, &mut tx_chan).await;
+
+        state.set_link_state(LinkState::Down);
+        stack.set_config_v4(ConfigV4::None);
+        info!("ppp: link down");
+        Timer::after_secs(RETRY_SECS).await;
+    }
+}
+
+struct Session<'a> {
+    link: &'a mut Link,
+    rx: &'a mut BufferedUartRx<'static, peripherals::UART8>,
+    tx: &'a mut BufferedUartTx<'static, peripherals::UART8>,
+    stack: &'static Stack<Device>,
+    up: bool,
+}
+
+impl Session<'_> {
+    /// Negotiate and then carry datagrams until the link goes down.
+    async fn run(
+        &mut self,
+        state: &ch::StateRunner<'static>,
+        rx_chan: &mut ch::RxRunner<'static, MTU>,
+        tx_chan: &mut ch::TxRunner<'static, MTU>,
+    ) {
+        // Anything left over from the modem's CONNECT line ends up as one malformed frame.
+        let mut decoder = Decoder::new(MAX_FRAME);
+        let mut out = Vec::with_capacity(2 * MAX_FRAME + 2);
+        let mut ticker = Ticker::every(Duration::from_secs(1));
+        let mut buf = [0u8; 64];
+
+        self.link.open();
+        loop {
+            while let Some(packet) = self.link.poll_transmit() {
+                out.clear();
+                let accm = self.link.tx_accm(packet.protocol);
+                frame::encode(packet.protocol, &packet.packet, accm, &mut out);
+                self.write(&out).await;
+            }
+            self.update(state);
+            if self.link.phase() == Phase::Dead {
+                return;
+            }
+
+            match select3(self.rx.read(&mut buf), tx_chan.tx_buf(), ticker.next()).await {
+                Either3::First(Ok(n)) => {
+                    for &b in &buf[..n] {
+                        match decoder.push(b) {
+                            Some(Ok(frame)) => {
+                                if self.link.received(frame.protocol, frame.payload) {
+                                    deliver(rx_chan, frame.payload);
+                                }
+                            }
+                            Some(Err(e)) => {
+                                debug!("ppp: dropped frame: {}", defmt::Debug2Format(&e))
+                            }
+                            None => {}
+                        }
+                    }
+                }
+                Either3::First(Err(e)) => warn!("ppp: serial error: {}", e),
+                Either3::Second(datagram) => {
+                    out.clear();
+                    // Datagrams longer than the peer's MRU are dropped.
+                    if self.up && datagram.len() <= self.link.peer_mru() as usize {
+                        let accm = self.link.tx_accm(protocol::IPV4);
+                        frame::encode(protocol::IPV4, datagram, accm, &mut out);
+                    }
+                    tx_chan.tx_done();
+                    self.write(&out).await;
+                }
+                Either3::Third(()) => self.link.tick(),
+            }
+        }
+    }
+
+    async fn write(&mut self, bytes: &[u8]) {
+        if let Err(e) = self.tx.write_all(bytes).await {
+            warn!("ppp: serial error: {}", e);
+        }
+    }
+
+    /// Hand the negotiated address to the stack when IPCP comes up, and take it back when the
+    /// link goes down.
+    fn update(&mut self, state: &ch::StateRunner<'static>) {
+        let ipv4 = self.link.ipv4();
+        if ipv4.is_some() == self.up {
+            return;
+        }
+
+        self.up = ipv4.is_some();
+        let Some(ipv4) = ipv4 else {
+            state.set_link_state(LinkState::Down);
+            self.stack.set_config_v4(ConfigV4::None);
+            return;
+        };
+
+        let address = Ipv4Address::from_bytes(&ipv4.address.octets());
+        let peer = Ipv4Address::from_bytes(&ipv4.peer.octets());
+        info!("ppp: up, address {} peer {}", address, peer);
+        // Everything is on-link: there are no neighbours to resolve on a point-to-point link.
+        self.stack.set_config_v4(ConfigV4::Static(StaticConfigV4 {
+            address: Ipv4Cidr::new(address, 0),
+            gateway: None,
+            dns_servers: ipv4
+                .dns
+                .iter()
+                .map(|dns| Ipv4Address::from_bytes(&dns.octets()))
+                .collect(),
+        }));
+        state.set_link_state(LinkState::Up);
+    }
+}
+
+/// Pass a received datagram to the stack, or drop it if the stack is behind.
+fn deliver(rx_chan: &mut ch::RxRunner<'static, MTU>, datagram: &[u8]) {
+    match rx_chan.try_rx_buf() {
+        Some(buf) if buf.len() >= datagram.len() => {
+            buf[..datagram.len()].copy_from_slice(datagram);
+            rx_chan.rx_done(datagram.len());
+        }
+        _ => debug!("ppp: dropped {} byte datagram", datagram.len()),
+    }
+}
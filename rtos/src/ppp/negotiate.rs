@@ -0,0 +1,277 @@
+use super::packet::{code, push_option, send, Bytes, Options, Outbox, Outgoing, Packet};
+
+/// Ticks between retransmissions. The link is ticked once a second.
+pub const RESTART_TICKS: u8 = 3;
+/// Configure-Requests sent without an answer before giving up.
+pub const MAX_CONFIGURE: u8 = 10;
+pub const MAX_TERMINATE: u8 = 2;
+
+/// Answer to one of the peer's options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Ack,
+    /// A counter-proposal was written to the Nak data.
+    Nak,
+    Reject,
+}
+
+/// The options of one control protocol, e.g. LCP or IPCP.
+pub trait OptionSet {
+    /// Write the options of our Configure-Request.
+    fn request(&self, out: &mut Bytes);
+    /// Called before the options of a Configure-Request from the peer are checked, so values
+    /// from an earlier request can be reset.
+    fn peer_request_start(&mut self) {}
+    /// Check one option of the peer's request. For [`Verdict::Nak`], append the value we would
+    /// accept to `nak` as a whole option.
+    fn check(&mut self, kind: u8, value: &[u8], nak: &mut Bytes) -> Verdict;
+    /// The peer would rather have `value` for one of our options.
+    fn nak(&mut self, kind: u8, value: &[u8]);
+    /// The peer doesn't accept one of our options at all.
+    fn reject(&mut self, kind: u8);
+}
+
+/// States of the option negotiation automaton (RFC 1661 section 4), without the ones only
+/// needed for a passive open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Initial,
+    ReqSent,
+    AckRcvd,
+    AckSent,
+    Opened,
+    Closing,
+    /// Terminated by either side, or given up after too many retries.
+    Stopped,
+}
+
+/// Runs the Configure-Request/Ack/Nak/Reject exchange for one control protocol until both
+/// sides have acknowledged each other's options.
+#[derive(Debug)]
+pub struct Negotiator<S> {
+    pub options: S,
+    protocol: u16,
+    state: State,
+    /// Identifier of our last request.
+    id: u8,
+    ticks: u8,
+    retries: u8,
+}
+
+impl<S: OptionSet> Negotiator<S> {
+    pub fn new(protocol: u16, options: S) -> Self {
+        Self {
+            options,
+            protocol,
+            state: State::Initial,
+            id: 0,
+            ticks: 0,
+            retries: 0,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn is_opened(&self) -> bool {
+        self.state == State::Opened
+    }
+
+    pub fn open(&mut self, out: &mut Outbox) {
+        self.retries = MAX_CONFIGURE;
+        self.send_request(out);
+        self.state = State::ReqSent;
+    }
+
+    pub fn close(&mut self, out: &mut Outbox) {
+        match self.state {
+            State::Initial | State::Stopped | State::Closing => {}
+            _ => {
+                self.retries = MAX_TERMINATE;
+                self.send_terminate(out);
+                self.state = State::Closing;
+            }
+        }
+    }
+
+    /// Back to [`State::Initial`] without telling the peer, e.g. when the layer below went down.
+    pub fn reset(&mut self) {
+        self.state = State::Initial;
+    }
+
+    /// Retransmit if the peer is slow to answer. Call once a second.
+    pub fn tick(&mut self, out: &mut Outbox) {
+        let retransmit = match self.state {
+            State::ReqSent | State::AckRcvd | State::AckSent | State::Closing => {
+                self.ticks += 1;
+                self.ticks >= RESTART_TICKS
+            }
+            _ => false,
+        };
+        if !retransmit {
+            return;
+        }
+        if self.retries == 0 {
+            self.state = State::Stopped;
+            return;
+        }
+
+        self.retries -= 1;
+        match self.state {
+            State::Closing => self.send_terminate(out),
+            State::AckRcvd => {
+                self.send_request(out);
+                self.state = State::ReqSent;
+            }
+            _ => self.send_request(out),
+        }
+    }
+
+    pub fn received(&mut self, packet: &Packet<'_>, out: &mut Outbox) {
+        match packet.code {
+            code::CONFIGURE_REQUEST => self.configure_request(packet, out),
+            code::CONFIGURE_ACK if packet.id == self.id => self.configure_ack(out),
+            code::CONFIGURE_NAK | code::CONFIGURE_REJECT if packet.id == self.id => {
+                self.configure_nak(packet, out)
+            }
+            // Answers to an earlier request.
+            code::CONFIGURE_ACK | code::CONFIGURE_NAK | code::CONFIGURE_REJECT => {}
+            code::TERMINATE_REQUEST => {
+                send(
+                    out,
+                    Outgoing::new(self.protocol, code::TERMINATE_ACK, packet.id, &[]),
+                );
+                if self.state != State::Closing {
+                    self.state = State::Stopped;
+                }
+            }
+            code::TERMINATE_ACK => match self.state {
+                State::Closing => self.state = State::Stopped,
+                State::AckRcvd | State::Opened => {
+                    self.send_request(out);
+                    self.state = State::ReqSent;
+                }
+                _ => {}
+            },
+            // Nothing we send is optional enough to work around a rejection.
+            code::CODE_REJECT | code::PROTOCOL_REJECT => {}
+            _ => {
+                let len = (packet.data.len() as u16 + 4).to_be_bytes();
+                let header = [packet.code, packet.id, len[0], len[1]];
+                self.id = self.id.wrapping_add(1);
+                send(
+                    out,
+                    Outgoing::new(
+                        self.protocol,
+                        code::CODE_REJECT,
+                        self.id,
+                        &[&header, packet.data],
+                    ),
+                );
+            }
+        }
+    }
+
+    fn send_request(&mut self, out: &mut Outbox) {
+        self.id = self.id.wrapping_add(1);
+        self.ticks = 0;
+        let mut options = Bytes::new();
+        self.options.request(&mut options);
+        send(
+            out,
+            Outgoing::new(self.protocol, code::CONFIGURE_REQUEST, self.id, &[&options]),
+        );
+    }
+
+    fn send_terminate(&mut self, out: &mut Outbox) {
+        self.id = self.id.wrapping_add(1);
+        self.ticks = 0;
+        send(
+            out,
+            Outgoing::new(self.protocol, code::TERMINATE_REQUEST, self.id, &[]),
+        );
+    }
+
+    fn configure_request(&mut self, packet: &Packet<'_>, out: &mut Outbox) {
+        match self.state {
+            State::Closing => return,
+            // The peer (re)started negotiation: start over from our side too.
+            State::Initial | State::Stopped | State::Opened => self.open(out),
+            _ => {}
+        }
+
+        self.options.peer_request_start();
+        let (mut naks, mut rejects) = (Bytes::new(), Bytes::new());
+        let mut nak = false;
+        let mut options = Options::new(packet.data);
+        for (kind, value) in &mut options {
+            match self.options.check(kind, value, &mut naks) {
+                Verdict::Ack => {}
+                Verdict::Nak => nak = true,
+                Verdict::Reject => push_option(&mut rejects, kind, value),
+            }
+        }
+        if options.malformed() {
+            return;
+        }
+
+        let (reply, data, good) = if !rejects.is_empty() {
+            (code::CONFIGURE_REJECT, &rejects[..], false)
+        } else if nak {
+            (code::CONFIGURE_NAK, &naks[..], false)
+        } else {
+            (code::CONFIGURE_ACK, packet.data, true)
+        };
+        send(out, Outgoing::new(self.protocol, reply, packet.id, &[data]));
+
+        self.state = match (self.state, good) {
+            (State::ReqSent | State::AckSent, true) => State::AckSent,
+            (State::AckRcvd, true) => State::Opened,
+            (State::AckSent, false) => State::ReqSent,
+            (state, _) => state,
+        };
+    }
+
+    fn configure_ack(&mut self, out: &mut Outbox) {
+        self.state = match self.state {
+            State::ReqSent => {
+                self.retries = MAX_CONFIGURE;
+                State::AckRcvd
+            }
+            State::AckSent => State::Opened,
+            // Crossed or duplicate acknowledgements.
+            State::AckRcvd | State::Opened => {
+                self.send_request(out);
+                State::ReqSent
+            }
+            state => state,
+        };
+    }
+
+    fn configure_nak(&mut self, packet: &Packet<'_>, out: &mut Outbox) {
+        if !matches!(
+            self.state,
+            State::ReqSent | State::AckRcvd | State::AckSent | State::Opened
+        ) {
+            return;
+        }
+        // Counts against the retries too, so a peer that never agrees can't keep us going.
+        if self.retries == 0 {
+            self.state = State::Stopped;
+            return;
+        }
+        self.retries -= 1;
+
+        for (kind, value) in Options::new(packet.data) {
+            match packet.code {
+                code::CONFIGURE_NAK => self.options.nak(kind, value),
+                _ => self.options.reject(kind),
+            }
+        }
+        self.send_request(out);
+        if matches!(self.state, State::AckRcvd | State::Opened) {
+            self.state = State::ReqSent;
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use super::{
+    auth::Method,
+    frame::DEFAULT_ACCM,
+    negotiate::{OptionSet, Verdict},
+    packet::{protocol, push_option, Bytes},
+};
+use core::net::Ipv4Addr;
+
+/// LCP option types (RFC 1661 section 6).
+mod lcp {
+    pub const MRU: u8 = 1;
+    pub const ACCM: u8 = 2;
+    pub const AUTH: u8 = 3;
+    pub const MAGIC: u8 = 5;
+}
+
+/// IPCP option types (RFC 1332, RFC 1877).
+mod ipcp {
+    pub const ADDRESS: u8 = 3;
+    pub const PRIMARY_DNS: u8 = 129;
+    pub const SECONDARY_DNS: u8 = 131;
+}
+
+/// CHAP algorithm number for MD5.
+const CHAP_MD5: u8 = 5;
+pub const DEFAULT_MRU: u16 = 1500;
+
+/// Link options. We ask for no escaping of control characters on receive and a magic number to
+/// spot looped-back links. The peer may set its MRU, ACCM and magic number, and require PAP or
+/// CHAP-MD5; field compression is refused.
+#[derive(Debug, Clone)]
+pub struct LcpOptions {
+    /// Our magic number. Zero once the peer rejected it.
+    pub magic: u32,
+    /// Control characters the peer has to escape for us. `None` once the peer rejected it.
+    pub accm: Option<u32>,
+    /// Control characters we have to escape for the peer.
+    pub peer_accm: u32,
+    pub peer_mru: u16,
+    pub peer_magic: u32,
+    /// How the peer wants us to authenticate.
+    pub auth: Method,
+}
+
+impl LcpOptions {
+    pub fn new(magic: u32) -> Self {
+        Self {
+            magic,
+            accm: Some(0),
+            peer_accm: DEFAULT_ACCM,
+            peer_mru: DEFAULT_MRU,
+            peer_magic: 0,
+            auth: Method::None,
+        }
+    }
+}
+
+impl OptionSet for LcpOptions {
+    fn request(&self, out: &mut Bytes) {
+        if let Some(accm) = self.accm {
+            push_option(out, lcp::ACCM, &accm.to_be_bytes());
+        }
+        if self.magic != 0 {
+            push_option(out, lcp::MAGIC, &self.magic.to_be_bytes());
+        }
+    }
+
+    fn peer_request_start(&mut self) {
+        self.peer_accm = DEFAULT_ACCM;
+        self.peer_mru = DEFAULT_MRU;
+        self.peer_magic = 0;
+        self.auth = Method::None;
+    }
+
+    fn check(&mut self, kind: u8, value: &[u8], nak: &mut Bytes) -> Verdict {
+        match (kind, value) {
+            (lcp::MRU, &[hi, lo]) => self.peer_mru = u16::from_be_bytes([hi, lo]),
+            (lcp::ACCM, &[a, b, c, d]) => self.peer_accm = u32::from_be_bytes([a, b, c, d]),
+            (lcp::AUTH, &[0xc0, 0x23]) => self.auth = Method::Pap,
+            (lcp::AUTH, &[0xc2, 0x23, CHAP_MD5]) => self.auth = Method::ChapMd5,
+            (lcp::AUTH, _) => {
+                let [hi, lo] = protocol::CHAP.to_be_bytes();
+                push_option(nak, lcp::AUTH, &[hi, lo, CHAP_MD5]);
+                return Verdict::Nak;
+            }
+            (lcp::MAGIC, &[a, b, c, d]) => {
+                let magic = u32::from_be_bytes([a, b, c, d]);
+                // Our own number coming back: the link is probably looped.
+                if magic != 0 && magic == self.magic {
+                    self.magic = next_magic(self.magic);
+                    push_option(nak, lcp::MAGIC, &next_magic(magic).to_be_bytes());
+                    return Verdict::Nak;
+                }
+                self.peer_magic = magic;
+            }
+            _ => return Verdict::Reject,
+        }
+
+        Verdict::Ack
+    }
+
+    fn nak(&mut self, kind: u8, value: &[u8]) {
+        match (kind, value) {
+            (lcp::ACCM, &[a, b, c, d]) => self.accm = Some(u32::from_be_bytes([a, b, c, d])),
+            (lcp::MAGIC, _) => self.magic = next_magic(self.magic),
+            _ => {}
+        }
+    }
+
+    fn reject(&mut self, kind: u8) {
+        match kind {
+            lcp::ACCM => self.accm = None,
+            lcp::MAGIC => self.magic = 0,
+            _ => {}
+        }
+    }
+}
+
+/// A different nonzero magic number, when the peer says ours clashes.
+fn next_magic(magic: u32) -> u32 {
+    magic.wrapping_mul(1_664_525).wrapping_add(1_013_904_223) | 1
+}
+
+/// Network options. We ask for an address (`0.0.0.0` lets the peer pick one) and DNS servers.
+/// A peer asking us for an address is given `offer_peer`, if set.
+#[derive(Debug, Clone)]
+pub struct IpcpOptions {
+    pub address: Ipv4Addr,
+    /// Primary and secondary DNS server. `None` once the peer rejected the option.
+    pub dns: [Option<Ipv4Addr>; 2],
+    pub peer_address: Ipv4Addr,
+    pub offer_peer: Option<Ipv4Addr>,
+}
+
+impl IpcpOptions {
+    pub fn new(address: Ipv4Addr, offer_peer: Option<Ipv4Addr>) -> Self {
+        Self {
+            address,
+            dns: [Some(Ipv4Addr::UNSPECIFIED); 2],
+            peer_address: Ipv4Addr::UNSPECIFIED,
+            offer_peer,
+        }
+    }
+}
+
+const DNS_OPTIONS: [u8; 2] = [ipcp::PRIMARY_DNS, ipcp::SECONDARY_DNS];
+
+impl OptionSet for IpcpOptions {
+    fn request(&self, out: &mut Bytes) {
+        push_option(out, ipcp::ADDRESS, &self.address.octets());
+        for (kind, dns) in DNS_OPTIONS.into_iter().zip(self.dns) {
+            if let Some(dns) = dns {
+                push_option(out, kind, &dns.octets());
+            }
+        }
+    }
+
+    fn check(&mut self, kind: u8, value: &[u8], nak: &mut Bytes) -> Verdict {
+        let (ipcp::ADDRESS, &[a, b, c, d]) = (kind, value) else {
+            return Verdict::Reject;
+        };
+        let address = Ipv4Addr::new(a, b, c, d);
+        if address.is_unspecified() {
+            return match self.offer_peer {
+                Some(offer) => {
+                    push_option(nak, kind, &offer.octets());
+                    Verdict::Nak
+                }
+                None => Verdict::Reject,
+            };
+        }
+
+        self.peer_address = address;
+        Verdict::Ack
+    }
+
+    fn nak(&mut self, kind: u8, value: &[u8]) {
+        let &[a, b, c, d] = value else {
+            return;
+        };
+        let address = Ipv4Addr::new(a, b, c, d);
+        match kind {
+            ipcp::ADDRESS => self.address = address,
+            ipcp::PRIMARY_DNS => self.dns[0] = Some(address),
+            ipcp::SECONDARY_DNS => self.dns[1] = Some(address),
+            _ => {}
+        }
+    }
+
+    fn reject(&mut self, kind: u8) {
+        match kind {
+            ipcp::PRIMARY_DNS => self.dns[0] = None,
+            ipcp::SECONDARY_DNS => self.dns[1] = None,
+            // Without an address there is nothing to run; keep asking until negotiation gives up.
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lcp_checks_peer_options() {
+        let mut options = LcpOptions::new(0x1234_5678);
+        let mut nak = Bytes::new();
+
+        assert_eq!(options.check(1, &[0x05, 0xdc], &mut nak), Verdict::Ack);
+        assert_eq!(options.check(2, &[0, 0, 0, 0], &mut nak), Verdict::Ack);
+        assert_eq!(options.check(3, &[0xc2, 0x23, 5], &mut nak), Verdict::Ack);
+        assert_eq!(options.auth, Method::ChapMd5);
+        assert_eq!(options.peer_accm, 0);
+        assert_eq!(options.check(7, &[], &mut nak), Verdict::Reject);
+        assert_eq!(options.check(8, &[], &mut nak), Verdict::Reject);
+        assert!(nak.is_empty());
+
+        // MS-CHAP is countered with CHAP-MD5.
+        assert_eq!(
+            options.check(3, &[0xc2, 0x23, 0x81], &mut nak),
+            Verdict::Nak
+        );
+        assert_eq!(&nak[..], &[3, 5, 0xc2, 0x23, 5]);
+
+        // A looped-back link shows our own magic number.
+        nak.clear();
+        assert_eq!(
+            options.check(5, &[0x12, 0x34, 0x56, 0x78], &mut nak),
+            Verdict::Nak
+        );
+        assert_ne!(options.magic, 0x1234_5678);
+
+        options.peer_request_start();
+        assert_eq!(options.peer_accm, DEFAULT_ACCM);
+        assert_eq!(options.auth, Method::None);
+    }
+
+    #[test]
+    fn lcp_request_follows_peer_answers() {
+        let mut options = LcpOptions::new(0xcafe_f00d);
+        let mut request = Bytes::new();
+        options.request(&mut request);
+        assert_eq!(
+            &request[..],
+            &[2, 6, 0, 0, 0, 0, 5, 6, 0xca, 0xfe, 0xf0, 0x0d]
+        );
+
+        options.nak(2, &[0, 0x0a, 0, 0]);
+        options.reject(5);
+        request.clear();
+        options.request(&mut request);
+        assert_eq!(&request[..], &[2, 6, 0, 0x0a, 0, 0]);
+    }
+
+    #[test]
+    fn ipcp_addresses() {
+        let mut options = IpcpOptions::new(Ipv4Addr::UNSPECIFIED, None);
+        let mut request = Bytes::new();
+        options.request(&mut request);
+        assert_eq!(
+            &request[..],
+            &[3, 6, 0, 0, 0, 0, 129, 6, 0, 0, 0, 0, 131, 6, 0, 0, 0, 0]
+        );
+
+        options.nak(3, &[10, 64, 64, 64]);
+        options.nak(129, &[1, 1, 1, 1]);
+        options.reject(131);
+        assert_eq!(options.address, Ipv4Addr::new(10, 64, 64, 64));
+        assert_eq!(options.dns, [Some(Ipv4Addr::new(1, 1, 1, 1)), None]);
+
+        let mut nak = Bytes::new();
+        assert_eq!(options.check(3, &[10, 0, 0, 1], &mut nak), Verdict::Ack);
+        assert_eq!(options.peer_address, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(options.check(3, &[0; 4], &mut nak), Verdict::Reject);
+        assert_eq!(
+            options.check(2, &[0, 0x2d, 15, 1], &mut nak),
+            Verdict::Reject
+        );
+
+        options.offer_peer = Some(Ipv4Addr::new(10, 0, 0, 9));
+        assert_eq!(options.check(3, &[0; 4], &mut nak), Verdict::Nak);
+        assert_eq!(&nak[..], &[3, 6, 10, 0, 0, 9]);
+    }
+}
@@ -0,0 +1,176 @@
+/// PPP protocol numbers.
+pub mod protocol {
+    pub const IPV4: u16 = 0x0021;
+    pub const IPCP: u16 = 0x8021;
+    pub const LCP: u16 = 0xc021;
+    pub const PAP: u16 = 0xc023;
+    pub const CHAP: u16 = 0xc223;
+}
+
+/// Control protocol packet codes shared by LCP and IPCP (RFC 1661 section 5).
+pub mod code {
+    pub const CONFIGURE_REQUEST: u8 = 1;
+    pub const CONFIGURE_ACK: u8 = 2;
+    pub const CONFIGURE_NAK: u8 = 3;
+    pub const CONFIGURE_REJECT: u8 = 4;
+    pub const TERMINATE_REQUEST: u8 = 5;
+    pub const TERMINATE_ACK: u8 = 6;
+    pub const CODE_REJECT: u8 = 7;
+    pub const PROTOCOL_REJECT: u8 = 8;
+    pub const ECHO_REQUEST: u8 = 9;
+    pub const ECHO_REPLY: u8 = 10;
+    pub const DISCARD_REQUEST: u8 = 11;
+}
+
+/// Longest control packet we build.
+pub const MAX_CONTROL: usize = 256;
+
+pub type Bytes = heapless::Vec<u8, MAX_CONTROL>;
+
+/// A control packet: code, identifier and data. The length field has been checked and any
+/// padding after it dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet<'a> {
+    pub code: u8,
+    pub id: u8,
+    pub data: &'a [u8],
+}
+
+impl<'a> Packet<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        let [code, id, hi, lo, ..] = *bytes else {
+            return None;
+        };
+        let len = u16::from_be_bytes([hi, lo]) as usize;
+        if len < 4 || len > bytes.len() {
+            return None;
+        }
+
+        Some(Self {
+            code,
+            id,
+            data: &bytes[4..len],
+        })
+    }
+}
+
+/// A control packet waiting to be framed and sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outgoing {
+    pub protocol: u16,
+    pub packet: Bytes,
+}
+
+impl Outgoing {
+    /// Build a packet from pieces of data, truncated to [`MAX_CONTROL`].
+    pub fn new(protocol: u16, code: u8, id: u8, data: &[&[u8]]) -> Self {
+        let mut packet = Bytes::new();
+        let _ = packet.extend_from_slice(&[code, id, 0, 0]);
+        for part in data {
+            let room = packet.capacity() - packet.len();
+            let _ = packet.extend_from_slice(&part[..part.len().min(room)]);
+        }
+        let len = (packet.len() as u16).to_be_bytes();
+        packet[2..4].copy_from_slice(&len);
+
+        Self { protocol, packet }
+    }
+}
+
+/// Control packets queued for sending. When full, new packets are dropped; the protocols
+/// retransmit.
+pub type Outbox = heapless::Deque<Outgoing, 8>;
+
+pub fn send(outbox: &mut Outbox, packet: Outgoing) {
+    let _ = outbox.push_back(packet);
+}
+
+/// Append a configuration option.
+pub fn push_option(out: &mut Bytes, kind: u8, value: &[u8]) {
+    let _ = out.extend_from_slice(&[kind, value.len() as u8 + 2]);
+    let _ = out.extend_from_slice(value);
+}
+
+/// Iterates over `(type, value)` of configuration options. Stops at the first malformed option;
+/// [`Options::malformed`] tells whether that happened.
+#[derive(Debug, Clone)]
+pub struct Options<'a> {
+    rest: &'a [u8],
+    malformed: bool,
+}
+
+impl<'a> Options<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            rest: data,
+            malformed: false,
+        }
+    }
+
+    pub fn malformed(&self) -> bool {
+        self.malformed
+    }
+}
+
+impl<'a> Iterator for Options<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let [kind, len, ..] = *self.rest else {
+            self.malformed |= !self.rest.is_empty();
+            self.rest = &[];
+            return None;
+        };
+        let len = len as usize;
+        if len < 2 || len > self.rest.len() {
+            self.malformed = true;
+            self.rest = &[];
+            return None;
+        }
+
+        let value = &self.rest[2..len];
+        self.rest = &self.rest[len..];
+        Some((kind, value))
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn build_and_parse() {
+        let mut options = Bytes::new();
+        push_option(&mut options, 2, &[0; 4]);
+        push_option(&mut options, 7, &[]);
+        let out = Outgoing::new(protocol::LCP, code::CONFIGURE_REQUEST, 9, &[&options]);
+        assert_eq!(&out.packet[..4], &[1, 9, 0, 12]);
+
+        // Padding after the length is ignored.
+        let mut bytes = out.packet.to_vec();
+        bytes.extend_from_slice(&[0xaa, 0xbb]);
+        let packet = Packet::parse(&bytes).unwrap();
+        assert_eq!((packet.code, packet.id), (1, 9));
+
+        let parsed: Vec<_> = Options::new(packet.data).collect();
+        assert_eq!(parsed, [(2, &[0u8; 4][..]), (7, &[][..])]);
+
+        assert_eq!(Packet::parse(&[1, 2, 0, 9, 0]), None);
+        assert_eq!(Packet::parse(&[1, 2, 0]), None);
+    }
+
+    #[test]
+    fn malformed_options() {
+        let mut options = Options::new(&[3, 6, 10, 0, 0, 1, 5, 1]);
+        assert_eq!(options.next(), Some((3, &[10, 0, 0, 1][..])));
+        assert_eq!(options.next(), None);
+        assert!(options.malformed());
+
+        let mut options = Options::new(&[3, 7, 0]);
+        assert_eq!(options.next(), None);
+        assert!(options.malformed());
+    }
+}
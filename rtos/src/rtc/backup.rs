@@ -0,0 +1,215 @@
+//! The 32 RTC backup registers as typed keys. They live in the backup domain, so they survive
+//! resets and, on VBAT, power cycles.
+
+use core::marker::PhantomData;
+
+pub const REGISTERS: usize = 32;
+
+/// Changed whenever the keys below move, so stale contents aren't read as the new layout.
+pub const LAYOUT_VERSION: u32 = 0x4b56_0001;
+
+pub const LAYOUT: Key<u32> = Key::new(0);
+/// Resets since the backup domain was last lost.
+pub const BOOT_COUNT: Key<u32> = Key::new(1);
+/// Drift of the LSE, in ppb, the smooth calibration was set to cancel.
+pub const CALIBRATION_PPB: Key<i32> = Key::new(2);
+/// When the calendar was last set from a reference, in seconds since the Unix epoch; 0 if never.
+pub const LAST_SET: Key<i64> = Key::new(3);
+/// Tamper events seen.
+pub const TAMPER_COUNT: Key<u32> = Key::new(5);
+
+/// Every key above, to check they don't overlap.
+#[cfg(test)]
+const KEYS: [(usize, usize); 5] = [
+    LAYOUT.span(),
+    BOOT_COUNT.span(),
+    CALIBRATION_PPB.span(),
+    LAST_SET.span(),
+    TAMPER_COUNT.span(),
+];
+
+/// A type stored in one or more consecutive registers.
+pub trait Value: Sized {
+    const WORDS: usize;
+    fn load(words: &[u32]) -> Self;
+    fn store(&self, words: &mut [u32]);
+}
+
+impl Value for u32 {
+    const WORDS: usize = 1;
+    fn load(words: &[u32]) -> Self {
+        words[0]
+    }
+    fn store(&self, words: &mut [u32]) {
+        words[0] = *self;
+    }
+}
+
+impl Value for i32 {
+    const WORDS: usize = 1;
+    fn load(words: &[u32]) -> Self {
+        words[0] as i32
+    }
+    fn store(&self, words: &mut [u32]) {
+        words[0] = *self as u32;
+    }
+}
+
+impl Value for bool {
+    const WORDS: usize = 1;
+    fn load(words: &[u32]) -> Self {
+        words[0] != 0
+    }
+    fn store(&self, words: &mut [u32]) {
+        words[0] = *self as u32;
+    }
+}
+
+/// Low word first.
+impl Value for u64 {
+    const WORDS: usize = 2;
+    fn load(words: &[u32]) -> Self {
+        words[0] as u64 | (words[1] as u64) << 32
+    }
+    fn store(&self, words: &mut [u32]) {
+        words[0] = *self as u32;
+        words[1] = (*self >> 32) as u32;
+    }
+}
+
+impl Value for i64 {
+    const WORDS: usize = 2;
+    fn load(words: &[u32]) -> Self {
+        u64::load(words) as i64
+    }
+    fn store(&self, words: &mut [u32]) {
+        (*self as u64).store(words)
+    }
+}
+
+/// Where a `T` lives. Checked at compile time to fit.
+pub struct Key<T> {
+    index: usize,
+    _value: PhantomData<T>,
+}
+
+impl<T> Clone for Key<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Key<T> {}
+
+impl<T: Value> Key<T> {
+    pub const fn new(index: usize) -> Self {
+        assert!(index + T::WORDS <= REGISTERS, "backup key out of range");
+        Self {
+            index,
+            _value: PhantomData,
+        }
+    }
+
+    /// First register and count.
+    pub const fn span(&self) -> (usize, usize) {
+        (self.index, T::WORDS)
+    }
+}
+
+/// Raw register access.
+pub trait Registers {
+    fn read(&self, index: usize) -> u32;
+    fn write(&mut self, index: usize, value: u32);
+}
+
+pub struct Backup<R> {
+    registers: R,
+}
+
+impl<R: Registers> Backup<R> {
+    pub fn new(registers: R) -> Self {
+        Self { registers }
+    }
+
+    pub fn get<T: Value>(&self, key: Key<T>) -> T {
+        let mut words = [0u32; 2];
+        let words = &mut words[..T::WORDS];
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = self.registers.read(key.index + i);
+        }
+        T::load(words)
+    }
+
+    pub fn set<T: Value>(&mut self, key: Key<T>, value: T) {
+        let mut words = [0u32; 2];
+        let words = &mut words[..T::WORDS];
+        value.store(words);
+        for (i, word) in words.iter().enumerate() {
+            self.registers.write(key.index + i, *word);
+        }
+    }
+
+    /// Clear every register unless they hold this layout. Returns whether they were cleared.
+    pub fn init(&mut self) -> bool {
+        if self.get(LAYOUT) == LAYOUT_VERSION {
+            return false;
+        }
+        for index in 0..REGISTERS {
+            self.registers.write(index, 0);
+        }
+        self.set(LAYOUT, LAYOUT_VERSION);
+        true
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Registers for [u32; REGISTERS] {
+        fn read(&self, index: usize) -> u32 {
+            self[index]
+        }
+        fn write(&mut self, index: usize, value: u32) {
+            self[index] = value;
+        }
+    }
+
+    #[test]
+    fn keys_do_not_overlap() {
+        let mut used = [false; REGISTERS];
+        for (index, words) in KEYS {
+            for register in &mut used[index..index + words] {
+                assert!(!*register, "register {index} used twice");
+                *register = true;
+            }
+        }
+    }
+
+    #[test]
+    fn typed_values() {
+        let mut backup = Backup::new([0xdead_beefu32; REGISTERS]);
+        assert!(backup.init());
+        assert_eq!(backup.get(BOOT_COUNT), 0);
+        assert_eq!(backup.get(LAST_SET), 0);
+
+        backup.set(CALIBRATION_PPB, -20_027);
+        backup.set(LAST_SET, 1_779_185_732);
+        backup.set(Key::<bool>::new(31), true);
+        assert_eq!(backup.get(CALIBRATION_PPB), -20_027);
+        assert_eq!(backup.get(LAST_SET), 1_779_185_732);
+        assert!(backup.get(Key::<bool>::new(31)));
+        assert_eq!(backup.registers[2], -20_027i32 as u32);
+        assert_eq!(backup.registers[3..5], [0x6a0c_3844, 0]);
+
+        backup.set(LAST_SET, -1);
+        assert_eq!(backup.registers[3..5], [u32::MAX; 2]);
+        assert_eq!(backup.get(Key::<u64>::new(3)), u64::MAX);
+
+        // Kept across a reset.
+        assert!(!backup.init());
+        assert_eq!(backup.get(CALIBRATION_PPB), -20_027);
+    }
+}
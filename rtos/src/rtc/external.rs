@@ -0,0 +1,433 @@
+//! Micro Crystal RV-8803-C7 real-time clock on I2C.
+//!
+//! Driven directly over the async I2C bus rather than through the `rv8803` crate, whose driver
+//! blocks. The clock keeps UTC; the weekday register counts from Sunday.
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use embedded_hal_async::i2c::{Error as _, ErrorKind, I2c};
+
+pub const ADDRESS: u8 = 0x32;
+
+pub const HUNDREDTHS: u8 = 0x00;
+pub const SECONDS: u8 = 0x01;
+pub const MINUTES_ALARM: u8 = 0x09;
+pub const TIMER_COUNTER_0: u8 = 0x0c;
+pub const EXTENSION: u8 = 0x0e;
+pub const FLAG: u8 = 0x0f;
+pub const CONTROL: u8 = 0x10;
+
+/// Alarm register bit that leaves the field out of the comparison.
+const ALARM_DISABLE: u8 = 0x80;
+/// Extension register: weekday or date alarm, timer enable, timer clock.
+const EXT_WADA: u8 = 0x40;
+const EXT_TE: u8 = 0x10;
+const EXT_TD: u8 = 0x03;
+/// Control register: interrupt enables and the prescaler reset.
+const CTRL_TIE: u8 = 0x10;
+const CTRL_AIE: u8 = 0x08;
+const CTRL_RESET: u8 = 0x01;
+
+/// Latest year the clock can hold.
+pub const MAX_YEAR: i32 = 2099;
+/// Largest countdown.
+pub const MAX_TICKS: u16 = 0x0fff;
+/// Most bytes [`Rv8803::write`] sends at once.
+pub const MAX_WRITE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Bus(ErrorKind),
+    /// The time registers don't hold a valid time.
+    Invalid,
+    /// V2F: the supply dropped too low and the time was lost.
+    TimeLost,
+    /// Outside 2000 to 2099.
+    OutOfRange,
+    /// The clock didn't answer at boot.
+    Absent,
+    /// More than [`MAX_WRITE`] bytes in one write.
+    TooLong,
+}
+
+impl Error {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::Bus(ErrorKind::NoAcknowledge(_)) => "no acknowledge",
+            Error::Bus(ErrorKind::ArbitrationLoss) => "arbitration loss",
+            Error::Bus(_) => "bus error",
+            Error::Invalid => "invalid time",
+            Error::TimeLost => "time lost",
+            Error::OutOfRange => "out of range",
+            Error::Absent => "no RTC",
+            Error::TooLong => "write too long",
+        }
+    }
+}
+
+/// Flag register bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags(pub u8);
+
+impl Flags {
+    pub const UPDATE: u8 = 0x20;
+    pub const TIMER: u8 = 0x10;
+    pub const ALARM: u8 = 0x08;
+    pub const EVENT: u8 = 0x04;
+    /// Supply too low, time lost.
+    pub const V2: u8 = 0x02;
+    /// Supply low, temperature compensation stopped.
+    pub const V1: u8 = 0x01;
+
+    pub fn contains(&self, bits: u8) -> bool {
+        self.0 & bits == bits
+    }
+}
+
+/// Day part of an alarm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmDay {
+    /// Day of the month.
+    Date(u8),
+    /// Any of these weekdays, bit 0 for Sunday.
+    Weekdays(u8),
+}
+
+/// Fires when every field given matches; `None` matches anything.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Alarm {
+    pub minute: Option<u8>,
+    pub hour: Option<u8>,
+    pub day: Option<AlarmDay>,
+}
+
+/// Countdown timer source clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerClock {
+    Hz4096 = 0,
+    Hz64 = 1,
+    Hz1 = 2,
+    /// One tick a minute.
+    PerMinute = 3,
+}
+
+pub fn bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// `None` for a nibble above 9.
+pub fn from_bcd(value: u8) -> Option<u8> {
+    let (tens, units) = (value >> 4, value & 0x0f);
+    if tens > 9 || units > 9 {
+        return None;
+    }
+    Some(tens * 10 + units)
+}
+
+/// Seconds to year, registers 01h to 07h.
+pub fn encode_time(time: &NaiveDateTime) -> Result<[u8; 7], Error> {
+    if !(2000..=MAX_YEAR).contains(&time.year()) {
+        return Err(Error::OutOfRange);
+    }
+    Ok([
+        bcd(time.second().min(59) as u8),
+        bcd(time.minute() as u8),
+        bcd(time.hour() as u8),
+        1 << time.weekday().num_days_from_sunday(),
+        bcd(time.day() as u8),
+        bcd(time.month() as u8),
+        bcd((time.year() - 2000) as u8),
+    ])
+}
+
+/// Hundredths to year, registers 00h to 07h. The weekday isn't checked.
+pub fn decode_time(regs: &[u8; 8]) -> Result<NaiveDateTime, Error> {
+    let field = |i: usize, mask: u8| from_bcd(regs[i] & mask).ok_or(Error::Invalid);
+    let hundredths = field(0, 0xff)? as u32;
+    let time = NaiveTime::from_hms_milli_opt(
+        field(3, 0x3f)? as u32,
+        field(2, 0x7f)? as u32,
+        field(1, 0x7f)? as u32,
+        hundredths * 10,
+    );
+    let date = NaiveDate::from_ymd_opt(
+        2000 + field(7, 0xff)? as i32,
+        field(6, 0x1f)? as u32,
+        field(5, 0x3f)? as u32,
+    );
+    match (date, time) {
+        (Some(date), Some(time)) => Ok(date.and_time(time)),
+        _ => Err(Error::Invalid),
+    }
+}
+
+pub struct Rv8803<I> {
+    i2c: I,
+}
+
+impl<I: I2c> Rv8803<I> {
+    pub fn new(i2c: I) -> Self {
+        Self { i2c }
+    }
+
+    pub async fn read(&mut self, register: u8, buf: &mut [u8]) -> Result<(), Error> {
+        self.i2c
+            .write_read(ADDRESS, &[register], buf)
+            .await
+            .map_err(|e| Error::Bus(e.kind()))
+    }
+
+    pub async fn write(&mut self, register: u8, data: &[u8]) -> Result<(), Error> {
+        if data.len() > MAX_WRITE {
+            return Err(Error::TooLong);
+        }
+        let mut buf = [0u8; MAX_WRITE + 1];
+        buf[0] = register;
+        buf[1..=data.len()].copy_from_slice(data);
+        self.i2c
+            .write(ADDRESS, &buf[..=data.len()])
+            .await
+            .map_err(|e| Error::Bus(e.kind()))
+    }
+
+    async fn read_register(&mut self, register: u8) -> Result<u8, Error> {
+        let mut value = [0u8];
+        self.read(register, &mut value).await?;
+        Ok(value[0])
+    }
+
+    async fn modify(&mut self, register: u8, clear: u8, set: u8) -> Result<(), Error> {
+        let value = self.read_register(register).await?;
+        self.write(register, &[value & !clear | set]).await
+    }
+
+    pub async fn flags(&mut self) -> Result<Flags, Error> {
+        self.read_register(FLAG).await.map(Flags)
+    }
+
+    /// Clear flag bits; the others are left alone.
+    pub async fn clear_flags(&mut self, bits: u8) -> Result<(), Error> {
+        // Writing 1 leaves a flag as it is.
+        self.write(FLAG, &[!bits]).await
+    }
+
+    /// The time, unless it was lost to a supply drop since it was last set.
+    pub async fn datetime(&mut self) -> Result<NaiveDateTime, Error> {
+        if self.flags().await?.contains(Flags::V2) {
+            return Err(Error::TimeLost);
+        }
+        // The clock holds its registers still for the length of a transfer.
+        let mut regs = [0u8; 8];
+        self.read(HUNDREDTHS, &mut regs).await?;
+        decode_time(&regs)
+    }
+
+    /// Set the time, to the second, and clear the time lost flag. The second starts now.
+    pub async fn set_datetime(&mut self, time: &NaiveDateTime) -> Result<(), Error> {
+        let regs = encode_time(time)?;
+        self.modify(CONTROL, 0, CTRL_RESET).await?;
+        self.write(SECONDS, &regs).await?;
+        self.modify(CONTROL, CTRL_RESET, 0).await?;
+        self.clear_flags(Flags::V2 | Flags::V1).await
+    }
+
+    /// Set the alarm and enable its interrupt, or disable both.
+    pub async fn set_alarm(&mut self, alarm: Option<&Alarm>) -> Result<(), Error> {
+        self.modify(CONTROL, CTRL_AIE, 0).await?;
+        let Some(alarm) = alarm else {
+            return self.clear_flags(Flags::ALARM).await;
+        };
+        let field = |value: Option<u8>| value.map_or(ALARM_DISABLE, bcd);
+        let (day, weekday) = match alarm.day {
+            None => (ALARM_DISABLE, false),
+            Some(AlarmDay::Date(date)) => (bcd(date), false),
+            Some(AlarmDay::Weekdays(days)) => (days & 0x7f, true),
+        };
+        let wada = if weekday { 0 } else { EXT_WADA };
+        self.modify(EXTENSION, EXT_WADA, wada).await?;
+        self.write(
+            MINUTES_ALARM,
+            &[field(alarm.minute), field(alarm.hour), day],
+        )
+        .await?;
+        self.clear_flags(Flags::ALARM).await?;
+        self.modify(CONTROL, 0, CTRL_AIE).await
+    }
+
+    /// Start the periodic countdown, interrupting every `ticks` of `clock`, or stop it.
+    pub async fn set_countdown(
+        &mut self,
+        countdown: Option<(u16, TimerClock)>,
+    ) -> Result<(), Error> {
+        self.modify(EXTENSION, EXT_TE, 0).await?;
+        self.modify(CONTROL, CTRL_TIE, 0).await?;
+        let Some((ticks, clock)) = countdown else {
+            return self.clear_flags(Flags::TIMER).await;
+        };
+        let ticks = ticks.clamp(1, MAX_TICKS);
+        self.write(TIMER_COUNTER_0, &[ticks as u8, (ticks >> 8) as u8])
+            .await?;
+        self.modify(EXTENSION, EXT_TD, clock as u8).await?;
+        self.clear_flags(Flags::TIMER).await?;
+        self.modify(CONTROL, 0, CTRL_TIE).await?;
+        self.modify(EXTENSION, 0, EXT_TE).await
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use embedded_hal_async::i2c::{ErrorType, NoAcknowledgeSource, Operation};
+
+    /// An RV-8803 register file behind an auto-incrementing address pointer.
+    struct Device {
+        regs: [u8; 0x20],
+        pointer: usize,
+        present: bool,
+    }
+
+    impl ErrorType for Device {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for Device {
+        async fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            if !self.present || address != ADDRESS {
+                return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+            }
+            for operation in operations {
+                match operation {
+                    Operation::Write(data) => {
+                        let Some((&register, data)) = data.split_first() else {
+                            continue;
+                        };
+                        self.pointer = register as usize;
+                        for &b in data {
+                            let register = self.pointer % self.regs.len();
+                            self.regs[register] = match register as u8 {
+                                // Flags are cleared by writing 0.
+                                FLAG => self.regs[register] & b,
+                                _ => b,
+                            };
+                            self.pointer += 1;
+                        }
+                    }
+                    Operation::Read(buf) => {
+                        for b in buf.iter_mut() {
+                            *b = self.regs[self.pointer % self.regs.len()];
+                            self.pointer += 1;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn device() -> Rv8803<Device> {
+        Rv8803::new(Device {
+            regs: [0; 0x20],
+            pointer: 0,
+            present: true,
+        })
+    }
+
+    fn time(s: &str) -> NaiveDateTime {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!((bcd(59), from_bcd(0x59)), (0x59, Some(59)));
+        assert_eq!(from_bcd(0x5a), None);
+        assert_eq!(
+            encode_time(&time("2026-05-19T10:15:32")),
+            Ok([0x32, 0x15, 0x10, 0x04, 0x19, 0x05, 0x26])
+        );
+        assert_eq!(
+            encode_time(&time("2100-01-01T00:00:00")),
+            Err(Error::OutOfRange)
+        );
+        assert_eq!(
+            decode_time(&[0x25, 0x32, 0x15, 0x10, 0x04, 0x19, 0x05, 0x26]),
+            Ok(time("2026-05-19T10:15:32.250"))
+        );
+        // February 30th, and a seconds register past 59.
+        assert_eq!(
+            decode_time(&[0, 0, 0, 0, 1, 0x30, 0x02, 0x26]),
+            Err(Error::Invalid)
+        );
+        assert_eq!(
+            decode_time(&[0, 0x60, 0, 0, 1, 0x01, 0x01, 0x26]),
+            Err(Error::Invalid)
+        );
+    }
+
+    #[test]
+    fn time_and_voltage_flags() {
+        let mut rtc = device();
+        // Straight after a power-on, V2F is set.
+        rtc.i2c.regs[FLAG as usize] = Flags::V2 | Flags::V1;
+        assert_eq!(block_on(rtc.datetime()), Err(Error::TimeLost));
+
+        block_on(rtc.set_datetime(&time("2026-05-19T10:15:32"))).unwrap();
+        assert_eq!(rtc.i2c.regs[FLAG as usize], 0);
+        assert_eq!(rtc.i2c.regs[CONTROL as usize] & CTRL_RESET, 0);
+        assert_eq!(block_on(rtc.datetime()), Ok(time("2026-05-19T10:15:32")));
+
+        rtc.i2c.regs[FLAG as usize] = Flags::ALARM | Flags::TIMER;
+        block_on(rtc.clear_flags(Flags::ALARM)).unwrap();
+        assert_eq!(block_on(rtc.flags()), Ok(Flags(Flags::TIMER)));
+
+        assert_eq!(block_on(rtc.write(0, &[0; 9])), Err(Error::TooLong));
+
+        rtc.i2c.present = false;
+        assert_eq!(
+            block_on(rtc.datetime()),
+            Err(Error::Bus(ErrorKind::NoAcknowledge(
+                NoAcknowledgeSource::Address
+            )))
+        );
+    }
+
+    #[test]
+    fn alarm_and_countdown() {
+        let mut rtc = device();
+        let alarm = Alarm {
+            minute: Some(30),
+            hour: Some(7),
+            day: Some(AlarmDay::Weekdays(0b0111110)),
+        };
+        block_on(rtc.set_alarm(Some(&alarm))).unwrap();
+        assert_eq!(rtc.i2c.regs[0x09..0x0c], [0x30, 0x07, 0x3e]);
+        assert_eq!(rtc.i2c.regs[EXTENSION as usize] & EXT_WADA, 0);
+        assert_eq!(rtc.i2c.regs[CONTROL as usize], CTRL_AIE);
+
+        let alarm = Alarm {
+            day: Some(AlarmDay::Date(15)),
+            ..Default::default()
+        };
+        block_on(rtc.set_alarm(Some(&alarm))).unwrap();
+        assert_eq!(rtc.i2c.regs[0x09..0x0c], [0x80, 0x80, 0x15]);
+        assert_eq!(rtc.i2c.regs[EXTENSION as usize] & EXT_WADA, EXT_WADA);
+
+        block_on(rtc.set_countdown(Some((300, TimerClock::Hz64)))).unwrap();
+        assert_eq!(rtc.i2c.regs[0x0c..0x0e], [0x2c, 0x01]);
+        assert_eq!(
+            rtc.i2c.regs[EXTENSION as usize],
+            EXT_WADA | EXT_TE | TimerClock::Hz64 as u8
+        );
+        assert_eq!(rtc.i2c.regs[CONTROL as usize], CTRL_AIE | CTRL_TIE);
+
+        block_on(rtc.set_alarm(None)).unwrap();
+        block_on(rtc.set_countdown(None)).unwrap();
+        assert_eq!(rtc.i2c.regs[CONTROL as usize], 0);
+        assert_eq!(rtc.i2c.regs[EXTENSION as usize] & EXT_TE, 0);
+    }
+}
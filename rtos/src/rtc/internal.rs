@@ -0,0 +1,318 @@
+//! The on-chip RTC. The calendar goes through embassy's driver; the alarms, wakeup timer, smooth
+//! calibration, tamper timestamp and backup registers through the registers, as embassy doesn't
+//! cover them. The calendar keeps UTC.
+
+use super::{
+    backup::{self, Backup, Registers},
+    registers::{
+        self as regs, CR_ALRE, CR_ALRIE, CR_TSIE, CR_WUCKSEL, CR_WUTE, CR_WUTIE, ISR_ALRF,
+        ISR_ALRWF, ISR_DEFINED, ISR_INIT, ISR_RECALPF, ISR_TAMP1F, ISR_TSF, ISR_TSOVF, ISR_WUTF,
+        ISR_WUTWF, TAMPCR_TAMP1E, TAMPCR_TAMP1NOERASE, TAMPCR_TAMPTS,
+    },
+};
+use crate::consts;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use core::cell::RefCell;
+use defmt::{info, warn};
+use embassy_stm32::{
+    interrupt::{self, InterruptExt},
+    pac,
+    rtc::Rtc,
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+
+pub use super::registers::{Alarm, AlarmDay, AlarmId, Calibration};
+
+/// EXTI lines of the RTC interrupts.
+const EXTI_ALARM: usize = 17;
+const EXTI_TAMPER: usize = 18;
+const EXTI_WAKEUP: usize = 19;
+
+static RTC: critical_section::Mutex<RefCell<Option<Rtc>>> =
+    critical_section::Mutex::new(RefCell::new(None));
+static ALARMS: [Signal<CriticalSectionRawMutex, ()>; 2] = [Signal::new(), Signal::new()];
+static WAKEUP: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static TAMPER: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// An alarm field or wakeup period the RTC can't hold.
+    OutOfRange,
+    /// Not handed over by [`init`] yet.
+    Absent,
+    /// embassy's driver refused the calendar.
+    Calendar,
+}
+
+impl Error {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Error::OutOfRange => "out of range",
+            Error::Absent => "no RTC",
+            Error::Calendar => "invalid calendar",
+        }
+    }
+}
+
+/// RTC_BKPxR. Writable while the backup domain is, which embassy leaves it.
+pub struct BackupRegisters;
+
+impl Registers for BackupRegisters {
+    fn read(&self, index: usize) -> u32 {
+        pac::RTC.bkpr(index).read().0
+    }
+
+    fn write(&mut self, index: usize, value: u32) {
+        pac::RTC
+            .bkpr(index)
+            .write_value(pac::rtc::regs::Bkpr(value));
+    }
+}
+
+pub fn backup() -> Backup<BackupRegisters> {
+    Backup::new(BackupRegisters)
+}
+
+/// When this image was built, in UTC.
+fn build_time() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(
+        consts::COMPILE_TIME_YEAR,
+        consts::COMPILE_TIME_MONTH,
+        consts::COMPILE_TIME_DAY,
+    )
+    .and_then(|date| {
+        date.and_hms_opt(
+            consts::COMPILE_TIME_HOUR,
+            consts::COMPILE_TIME_MINUTE,
+            consts::COMPILE_TIME_SECOND,
+        )
+    })
+    .unwrap_or_default()
+}
+
+/// Take over `rtc`. Check the backup registers and the calendar, seeding the calendar with the
+/// build time when it's unset or earlier, and enable the RTC interrupts.
+pub fn init(mut rtc: Rtc) {
+    let mut backup = backup();
+    if backup.init() {
+        warn!("rtc: backup registers lost, cleared");
+    }
+    let boots = backup.get(backup::BOOT_COUNT).wrapping_add(1);
+    backup.set(backup::BOOT_COUNT, boots);
+
+    let build = build_time();
+    match rtc.now().map(NaiveDateTime::from) {
+        Ok(now) if now >= build => {
+            info!("rtc: {} UTC, boot {}", defmt::Debug2Format(&now), boots)
+        }
+        _ => {
+            warn!("rtc: calendar invalid, seeding it with the build time");
+            // Not a reference, so `LAST_SET` is left alone.
+            if let Err(e) = rtc.set_datetime(build.into()) {
+                warn!("rtc: {}", defmt::Debug2Format(&e));
+            }
+        }
+    }
+    critical_section::with(|cs| RTC.borrow(cs).replace(Some(rtc)));
+
+    critical_section::with(|_| {
+        for line in [EXTI_ALARM, EXTI_TAMPER, EXTI_WAKEUP] {
+            pac::EXTI.rtsr(0).modify(|w| w.set_line(line, true));
+            pac::EXTI.cpu(0).imr(0).modify(|w| w.set_line(line, true));
+        }
+    });
+    for irq in [
+        interrupt::RTC_ALARM,
+        interrupt::TAMP_STAMP,
+        interrupt::RTC_WKUP,
+    ] {
+        irq.unpend();
+        // SAFETY: the handlers below only touch the RTC flags and signals.
+        unsafe { irq.enable() };
+    }
+}
+
+fn with_rtc<R>(f: impl FnOnce(&mut Rtc) -> Result<R, Error>) -> Result<R, Error> {
+    critical_section::with(|cs| match RTC.borrow(cs).borrow_mut().as_mut() {
+        Some(rtc) => f(rtc),
+        None => Err(Error::Absent),
+    })
+}
+
+/// The calendar, to the tick of the synchronous prescaler.
+pub fn now() -> Result<NaiveDateTime, Error> {
+    with_rtc(|rtc| {
+        // Reading SSR holds the calendar registers until they've been read.
+        let ssr = pac::RTC.ssr().read().0;
+        let prer = pac::RTC.prer().read().0;
+        let time = rtc.now().map_err(|_| Error::Calendar)?;
+        Ok(
+            NaiveDateTime::from(time)
+                + Duration::microseconds(regs::subsecond_us(ssr, prer) as i64),
+        )
+    })
+}
+
+/// Set the calendar from a reference, the second starting now, and note when in the backup
+/// registers.
+pub fn set(time: &NaiveDateTime) -> Result<(), Error> {
+    with_rtc(|rtc| {
+        rtc.set_datetime((*time).into())
+            .map_err(|_| Error::Calendar)
+    })?;
+    backup().set(backup::LAST_SET, time.and_utc().timestamp());
+    Ok(())
+}
+
+/// Whether the calendar has been set from a reference, rather than only seeded.
+pub fn is_set() -> bool {
+    backup().get(backup::LAST_SET) != 0
+}
+
+/// Run `f` with the RTC registers unlocked.
+fn unlocked<R>(f: impl FnOnce(pac::rtc::Rtc) -> R) -> R {
+    critical_section::with(|_| {
+        let r = pac::RTC;
+        r.wpr().write_value(pac::rtc::regs::Wpr(0xca));
+        r.wpr().write_value(pac::rtc::regs::Wpr(0x53));
+        let result = f(r);
+        r.wpr().write_value(pac::rtc::regs::Wpr(0xff));
+        result
+    })
+}
+
+fn clear_flags(flags: u32) {
+    // Writing 1 leaves the other flags be, whatever they read, and the read-only bits ignore it.
+    // Only INIT is written back as it is.
+    critical_section::with(|_| {
+        let init = pac::RTC.isr().read().0 & ISR_INIT;
+        let bits = (ISR_DEFINED & !(flags | ISR_INIT)) | init;
+        pac::RTC.isr().write_value(pac::rtc::regs::Isr(bits));
+    });
+}
+
+/// Disarms an alarm when its future completes or is dropped.
+struct Disarm(usize);
+
+impl Drop for Disarm {
+    fn drop(&mut self) {
+        let i = self.0;
+        unlocked(|r| r.cr().modify(|w| w.0 &= !(CR_ALRE[i] | CR_ALRIE[i])));
+    }
+}
+
+/// Wait for the next time `alarm` matches. Alarms A and B run independently.
+pub async fn alarm(id: AlarmId, alarm: &Alarm) -> Result<(), Error> {
+    let bits = alarm.bits().ok_or(Error::OutOfRange)?;
+    let i = id as usize;
+    ALARMS[i].reset();
+    unlocked(|r| {
+        r.cr().modify(|w| w.0 &= !(CR_ALRE[i] | CR_ALRIE[i]));
+        while r.isr().read().0 & ISR_ALRWF[i] == 0 {}
+        r.alrmr(i).write_value(pac::rtc::regs::Alrmr(bits));
+        // Sub-seconds left out of the comparison.
+        r.alrmssr(i).write_value(pac::rtc::regs::Alrmssr(0));
+        clear_flags(ISR_ALRF[i]);
+        r.cr().modify(|w| w.0 |= CR_ALRE[i] | CR_ALRIE[i]);
+    });
+    let _disarm = Disarm(i);
+    ALARMS[i].wait().await;
+    Ok(())
+}
+
+/// Start the periodic wakeup timer; see [`regs::wakeup`] for the range.
+pub fn start_wakeup(period_ms: u32) -> Result<(), Error> {
+    let (wucksel, reload) = regs::wakeup(period_ms).ok_or(Error::OutOfRange)?;
+    WAKEUP.reset();
+    unlocked(|r| {
+        r.cr().modify(|w| w.0 &= !(CR_WUTE | CR_WUTIE));
+        while r.isr().read().0 & ISR_WUTWF == 0 {}
+        r.wutr().write_value(pac::rtc::regs::Wutr(reload as u32));
+        clear_flags(ISR_WUTF);
+        r.cr()
+            .modify(|w| w.0 = (w.0 & !CR_WUCKSEL) | wucksel as u32 | CR_WUTE | CR_WUTIE);
+    });
+    Ok(())
+}
+
+pub fn stop_wakeup() {
+    unlocked(|r| r.cr().modify(|w| w.0 &= !(CR_WUTE | CR_WUTIE)));
+}
+
+/// Each period of the wakeup timer; periods missed in between are merged.
+pub async fn wait_wakeup() {
+    WAKEUP.wait().await
+}
+
+/// Apply the smooth calibration that cancels an LSE running `drift_ppb` fast, and keep the
+/// drift in the backup registers.
+pub fn calibrate(drift_ppb: i32) -> Calibration {
+    let calibration = Calibration::correcting(drift_ppb);
+    unlocked(|r| {
+        // A new value is taken at the next 32 s cycle.
+        while r.isr().read().0 & ISR_RECALPF != 0 {}
+        r.calr()
+            .write_value(pac::rtc::regs::Calr(calibration.bits()));
+    });
+    backup().set(backup::CALIBRATION_PPB, drift_ppb);
+    calibration
+}
+
+pub fn calibration() -> Calibration {
+    Calibration::from_bits(pac::RTC.calr().read().0)
+}
+
+/// Timestamp the rising edges of tamper 1, RTC_TAMP1 on PC13. The backup registers are kept.
+pub fn enable_tamper_timestamp() {
+    unlocked(|r| {
+        r.tampcr()
+            .modify(|w| w.0 |= TAMPCR_TAMP1E | TAMPCR_TAMPTS | TAMPCR_TAMP1NOERASE);
+        r.cr().modify(|w| w.0 |= CR_TSIE);
+    });
+}
+
+/// The time of the next tamper event, counted in the backup registers.
+pub async fn wait_tamper() -> Option<NaiveDateTime> {
+    TAMPER.wait().await;
+    let mut backup = backup();
+    backup.set(
+        backup::TAMPER_COUNT,
+        backup.get(backup::TAMPER_COUNT).wrapping_add(1),
+    );
+    let r = pac::RTC;
+    regs::timestamp(r.tstr().read().0, r.tsdr().read().0, now().ok()?)
+}
+
+fn clear_exti(line: usize) {
+    pac::EXTI.cpu(0).pr(0).write(|w| w.set_line(line, true));
+}
+
+#[interrupt]
+fn RTC_ALARM() {
+    let isr = pac::RTC.isr().read().0;
+    for i in 0..2 {
+        if isr & ISR_ALRF[i] != 0 {
+            ALARMS[i].signal(());
+        }
+    }
+    clear_flags(isr & (ISR_ALRF[0] | ISR_ALRF[1]));
+    clear_exti(EXTI_ALARM);
+}
+
+#[interrupt]
+fn RTC_WKUP() {
+    clear_flags(ISR_WUTF);
+    clear_exti(EXTI_WAKEUP);
+    WAKEUP.signal(());
+}
+
+#[interrupt]
+fn TAMP_STAMP() {
+    let isr = pac::RTC.isr().read().0;
+    // TSTR and TSDR hold the stamp until the next one.
+    clear_flags(isr & (ISR_TSF | ISR_TSOVF | ISR_TAMP1F));
+    clear_exti(EXTI_TAMPER);
+    if isr & ISR_TSF != 0 {
+        TAMPER.signal(());
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{
+    board::ExtRtcResource,
+    i2c::{self, I2c2},
+};
+use chrono::NaiveDateTime;
+use defmt::{info, unwrap, warn};
+use embassy_executor::Spawner;
+use embassy_stm32::{exti::ExtiInput, gpio::Pull};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, signal::Signal};
+use embassy_time::{Delay, Duration, Timer};
+
+pub mod backup;
+pub mod external;
+pub mod internal;
+pub mod registers;
+
+use external::Rv8803;
+pub use external::{Alarm, AlarmDay, Error, Flags, TimerClock};
+
+type Bus = i2c::Traced<i2c::Device<I2c2>>;
+
+/// Its longest transfer, the time, takes under 1 ms at 100 kHz.
+const I2C_TIMEOUT_MS: u32 = 20;
+/// Wait after failing to clear the interrupt before trying again.
+const RETRY: Duration = Duration::from_millis(100);
+
+/// `None` until the task has found the clock.
+static RTC: Mutex<CriticalSectionRawMutex, Option<Rv8803<Bus>>> = Mutex::new(None);
+static ALARM: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static COUNTDOWN: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Bring up the RV-8803 on I2C2 and serve its interrupt line.
+pub fn spawn(spawner: &Spawner, bus: &'static i2c::Shared<I2c2>, r: ExtRtcResource) {
+    // INT is open-drain, active low.
+    let int = ExtiInput::new(r.int, r.int_exti, Pull::Up);
+    let device = i2c::traced(bus.device(Delay, I2C_TIMEOUT_MS));
+    unwrap!(spawner.spawn(rtc_task(device, int)));
+}
+
+#[embassy_executor::task]
+async fn rtc_task(i2c: Bus, mut int: ExtiInput<'static>) {
+    let mut rtc = Rv8803::new(i2c);
+    match rtc.flags().await {
+        Ok(flags) => {
+            if flags.contains(Flags::V2) {
+                warn!("rtc: time lost to a supply drop, waiting to be set");
+            }
+            if flags.contains(Flags::V1) {
+                warn!("rtc: supply was low, temperature compensation stopped");
+                if let Err(e) = rtc.clear_flags(Flags::V1).await {
+                    warn!("rtc: {}", e.as_str());
+                }
+            }
+        }
+        Err(e) => {
+            warn!("rtc: no RV-8803: {}", e.as_str());
+            return;
+        }
+    }
+    match rtc.datetime().await {
+        Ok(time) => info!("rtc: {} UTC", defmt::Debug2Format(&time)),
+        Err(e) => warn!("rtc: {}", e.as_str()),
+    }
+    *RTC.lock().await = Some(rtc);
+
+    loop {
+        int.wait_for_low().await;
+        // INT stays low until the flags behind it are cleared, so if that failed it would be
+        // back at once; give the bus a rest instead.
+        if !serve_interrupt().await || int.is_low() {
+            Timer::after(RETRY).await;
+        }
+    }
+}
+
+/// Clear what pulled INT low and pass it on. False if it couldn't be cleared.
+async fn serve_interrupt() -> bool {
+    let mut rtc = RTC.lock().await;
+    let Some(rtc) = rtc.as_mut() else {
+        return false;
+    };
+    let flags = match rtc.flags().await {
+        Ok(flags) => flags,
+        Err(e) => {
+            warn!("rtc: {}", e.as_str());
+            return false;
+        }
+    };
+    let cleared = rtc
+        .clear_flags(flags.0 & (Flags::ALARM | Flags::TIMER))
+        .await;
+    if let Err(e) = cleared {
+        warn!("rtc: {}", e.as_str());
+    }
+    if flags.contains(Flags::ALARM) {
+        ALARM.signal(());
+    }
+    if flags.contains(Flags::TIMER) {
+        COUNTDOWN.signal(());
+    }
+    cleared.is_ok()
+}
+
+/// The time, in UTC.
+pub async fn now() -> Result<NaiveDateTime, Error> {
+    match RTC.lock().await.as_mut() {
+        Some(rtc) => rtc.datetime().await,
+        None => Err(Error::Absent),
+    }
+}
+
+/// Set the time, in UTC.
+pub async fn set(time: &NaiveDateTime) -> Result<(), Error> {
+    match RTC.lock().await.as_mut() {
+        Some(rtc) => rtc.set_datetime(time).await,
+        None => Err(Error::Absent),
+    }
+}
+
+/// Arm the alarm, or disarm it with `None`. See [`wait_alarm`].
+pub async fn set_alarm(alarm: Option<&Alarm>) -> Result<(), Error> {
+    ALARM.reset();
+    match RTC.lock().await.as_mut() {
+        Some(rtc) => rtc.set_alarm(alarm).await,
+        None => Err(Error::Absent),
+    }
+}
+
+/// Start the periodic countdown, or stop it with `None`. See [`wait_countdown`].
+pub async fn set_countdown(countdown: Option<(u16, TimerClock)>) -> Result<(), Error> {
+    COUNTDOWN.reset();
+    match RTC.lock().await.as_mut() {
+        Some(rtc) => rtc.set_countdown(countdown).await,
+        None => Err(Error::Absent),
+    }
+}
+
+pub async fn wait_alarm() {
+    ALARM.wait().await
+}
+
+/// Each expiry of the countdown, which reloads itself.
+pub async fn wait_countdown() {
+    COUNTDOWN.wait().await
+}
@@ -0,0 +1,328 @@
+//! Register encodings of the on-chip RTC that embassy doesn't cover: alarms, the wakeup timer,
+//! smooth calibration and the tamper timestamp. RM0399, section 49.
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+/// ISR flags, cleared by writing 0.
+pub const ISR_ALRF: [u32; 2] = [1 << 8, 1 << 9];
+pub const ISR_WUTF: u32 = 1 << 10;
+pub const ISR_TSF: u32 = 1 << 11;
+pub const ISR_TSOVF: u32 = 1 << 12;
+pub const ISR_TAMP1F: u32 = 1 << 13;
+/// ISR status: alarm and wakeup registers writable, calibration pending.
+pub const ISR_ALRWF: [u32; 2] = [1 << 0, 1 << 1];
+pub const ISR_WUTWF: u32 = 1 << 2;
+pub const ISR_RECALPF: u32 = 1 << 16;
+/// ISR control: initialization mode, the only bit that is read-write.
+pub const ISR_INIT: u32 = 1 << 7;
+/// Bits 18 and up are reserved, to be kept 0.
+pub const ISR_DEFINED: u32 = (1 << 18) - 1;
+
+pub const CR_WUCKSEL: u32 = 0x7;
+pub const CR_ALRE: [u32; 2] = [1 << 8, 1 << 9];
+pub const CR_WUTE: u32 = 1 << 10;
+pub const CR_ALRIE: [u32; 2] = [1 << 12, 1 << 13];
+pub const CR_WUTIE: u32 = 1 << 14;
+pub const CR_TSIE: u32 = 1 << 15;
+
+/// TAMPCR: tamper 1 on a rising edge takes a timestamp and keeps the backup registers.
+pub const TAMPCR_TAMP1E: u32 = 1 << 0;
+pub const TAMPCR_TAMPTS: u32 = 1 << 7;
+pub const TAMPCR_TAMP1NOERASE: u32 = 1 << 17;
+
+/// RTCCLK/16 and ck_spre, with and without 2^16 added to the reload value.
+const WUCKSEL_DIV16: u8 = 0b000;
+const WUCKSEL_SPRE: u8 = 0b100;
+const WUCKSEL_SPRE_LONG: u8 = 0b110;
+/// RTCCLK/16 from the LSE.
+const DIV16_HZ: u32 = 32_768 / 16;
+
+/// Leaves the field out of the comparison.
+const ALRM_MSK: [u32; 4] = [1 << 7, 1 << 15, 1 << 23, 1 << 31];
+const ALRM_WDSEL: u32 = 1 << 30;
+
+/// Which of the two alarms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmId {
+    A = 0,
+    B = 1,
+}
+
+/// Day part of an alarm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmDay {
+    /// Day of the month.
+    Date(u8),
+    Weekday(Weekday),
+}
+
+/// Fires when every field given matches; `None` matches anything.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Alarm {
+    pub second: Option<u8>,
+    pub minute: Option<u8>,
+    pub hour: Option<u8>,
+    pub day: Option<AlarmDay>,
+}
+
+impl Alarm {
+    /// ALRMxR, 24-hour format. `None` for a field out of range.
+    pub fn bits(&self) -> Option<u32> {
+        let field = |value: Option<u8>, max: u8, shift: u32, mask: u32| match value {
+            None => Some(mask),
+            Some(v) if v <= max => Some(bcd(v) << shift),
+            Some(_) => None,
+        };
+        let day = match self.day {
+            None => ALRM_MSK[3],
+            Some(AlarmDay::Date(date)) if (1..=31).contains(&date) => bcd(date) << 24,
+            Some(AlarmDay::Date(_)) => return None,
+            Some(AlarmDay::Weekday(day)) => ALRM_WDSEL | day.number_from_monday() << 24,
+        };
+        Some(
+            field(self.second, 59, 0, ALRM_MSK[0])?
+                | field(self.minute, 59, 8, ALRM_MSK[1])?
+                | field(self.hour, 23, 16, ALRM_MSK[2])?
+                | day,
+        )
+    }
+}
+
+fn bcd(value: u8) -> u32 {
+    (((value / 10) << 4) | (value % 10)) as u32
+}
+
+/// Two BCD digits at `shift`, the tens `tens_bits` wide.
+fn from_bcd(reg: u32, shift: u32, tens_bits: u32) -> u32 {
+    let tens = (reg >> (shift + 4)) & ((1 << tens_bits) - 1);
+    tens * 10 + ((reg >> shift) & 0xf)
+}
+
+/// WUCKSEL and WUTR for a wakeup every `period_ms`: to the tick of RTCCLK/16 up to 32 s, to the
+/// second beyond, up to 36 hours. `None` when out of range.
+pub fn wakeup(period_ms: u32) -> Option<(u8, u16)> {
+    if period_ms == 0 {
+        return None;
+    }
+    let ticks = (period_ms as u64 * DIV16_HZ as u64 + 500) / 1000;
+    if ticks <= 1 << 16 {
+        return Some((WUCKSEL_DIV16, (ticks.max(1) - 1) as u16));
+    }
+    let seconds = (period_ms + 500) / 1000;
+    match seconds {
+        1..=0x1_0000 => Some((WUCKSEL_SPRE, (seconds - 1) as u16)),
+        0x1_0001..=0x2_0000 => Some((WUCKSEL_SPRE_LONG, (seconds - 0x1_0001) as u16)),
+        _ => None,
+    }
+}
+
+/// Smooth calibration: every 2^20 RTCCLK cycles (32 s) `minus` pulses are masked and, with
+/// `plus`, 512 are added. One step is 2^-20, about 954 ppb.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Calibration {
+    pub plus: bool,
+    pub minus: u16,
+}
+
+impl Calibration {
+    const CYCLE: i64 = 1 << 20;
+    const CALP_PULSES: i64 = 512;
+    const CALP: u32 = 1 << 15;
+
+    /// The calibration that cancels a clock running `drift_ppb` fast, as near as it can; up to
+    /// about +-487 ppm.
+    pub fn correcting(drift_ppb: i32) -> Self {
+        // Pulses to remove per cycle, rounded.
+        let pulses = (drift_ppb as i64 * Self::CYCLE).div_euclid(1_000_000_000 / 2);
+        let pulses = (pulses + 1).div_euclid(2);
+        let net = pulses.clamp(-Self::CALP_PULSES, Self::CALP_PULSES - 1);
+        if net < 0 {
+            Self {
+                plus: true,
+                minus: (Self::CALP_PULSES + net) as u16,
+            }
+        } else {
+            Self {
+                plus: false,
+                minus: net as u16,
+            }
+        }
+    }
+
+    /// Read back from CALR.
+    pub fn from_bits(bits: u32) -> Self {
+        Self {
+            plus: bits & Self::CALP != 0,
+            minus: (bits & 0x1ff) as u16,
+        }
+    }
+
+    /// CALR, over the 32 s cycle.
+    pub fn bits(&self) -> u32 {
+        (if self.plus { Self::CALP } else { 0 }) | self.minus as u32 & 0x1ff
+    }
+
+    /// The drift it cancels, in ppb.
+    pub fn ppb(&self) -> i32 {
+        let net = self.minus as i64 - if self.plus { Self::CALP_PULSES } else { 0 };
+        (net * 1_000_000_000 / Self::CYCLE) as i32
+    }
+}
+
+/// Microseconds into the second from SSR, which counts down from PREDIV_S in PRER.
+pub fn subsecond_us(ssr: u32, prer: u32) -> u32 {
+    let prediv_s = prer & 0x7fff;
+    // Above PREDIV_S only for a moment after a shift.
+    let ss = (ssr & 0xffff).min(prediv_s);
+    ((prediv_s - ss) as u64 * 1_000_000 / (prediv_s as u64 + 1)) as u32
+}
+
+/// The time in TSTR and TSDR, which leave the year out: the latest one not after `now`.
+pub fn timestamp(tstr: u32, tsdr: u32, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let time = NaiveTime::from_hms_opt(
+        from_bcd(tstr, 16, 2),
+        from_bcd(tstr, 8, 3),
+        from_bcd(tstr, 0, 3),
+    )?;
+    let (month, day) = (from_bcd(tsdr, 8, 1), from_bcd(tsdr, 0, 2));
+    // A 29 February stamp may be years back.
+    (0..8)
+        .filter_map(|years| NaiveDate::from_ymd_opt(now.year() - years, month, day))
+        .map(|date| date.and_time(time))
+        .find(|stamp| *stamp <= now)
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32, s: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, mo, d)
+            .unwrap()
+            .and_hms_opt(h, mi, s)
+            .unwrap()
+    }
+
+    #[test]
+    fn alarm_registers() {
+        assert_eq!(Alarm::default().bits(), Some(0x8080_8080));
+        let daily = Alarm {
+            second: Some(0),
+            minute: Some(30),
+            hour: Some(7),
+            day: None,
+        };
+        assert_eq!(daily.bits(), Some(0x8007_3000));
+        let monthly = Alarm {
+            day: Some(AlarmDay::Date(25)),
+            ..daily
+        };
+        assert_eq!(monthly.bits(), Some(0x2507_3000));
+        let weekly = Alarm {
+            day: Some(AlarmDay::Weekday(Weekday::Sun)),
+            ..daily
+        };
+        assert_eq!(weekly.bits(), Some(0x4707_3000));
+        let bad = Alarm {
+            hour: Some(24),
+            ..daily
+        };
+        assert_eq!(bad.bits(), None);
+        let bad = Alarm {
+            day: Some(AlarmDay::Date(0)),
+            ..daily
+        };
+        assert_eq!(bad.bits(), None);
+    }
+
+    #[test]
+    fn wakeup_reload() {
+        assert_eq!(wakeup(0), None);
+        // 2048 Hz ticks.
+        assert_eq!(wakeup(1), Some((WUCKSEL_DIV16, 1)));
+        assert_eq!(wakeup(250), Some((WUCKSEL_DIV16, 511)));
+        assert_eq!(wakeup(32_000), Some((WUCKSEL_DIV16, 0xffff)));
+        assert_eq!(wakeup(60_000), Some((WUCKSEL_SPRE, 59)));
+        assert_eq!(wakeup(65_536_000), Some((WUCKSEL_SPRE, 0xffff)));
+        assert_eq!(wakeup(65_537_000), Some((WUCKSEL_SPRE_LONG, 0)));
+        assert_eq!(wakeup(131_072_000), Some((WUCKSEL_SPRE_LONG, 0xffff)));
+        assert_eq!(wakeup(131_073_000), None);
+    }
+
+    #[test]
+    fn smooth_calibration() {
+        assert_eq!(Calibration::correcting(0), Calibration::default());
+        // 20 ppm fast: mask 21 pulses in 2^20.
+        let fast = Calibration::correcting(20_000);
+        assert_eq!(
+            fast,
+            Calibration {
+                plus: false,
+                minus: 21
+            }
+        );
+        assert_eq!(fast.bits(), 21);
+        assert_eq!(fast.ppb(), 20_027);
+        // 20 ppm slow: add 512, mask 491.
+        let slow = Calibration::correcting(-20_000);
+        assert_eq!(
+            slow,
+            Calibration {
+                plus: true,
+                minus: 491
+            }
+        );
+        assert_eq!(Calibration::from_bits(slow.bits()), slow);
+        assert_eq!(slow.ppb(), -20_027);
+        // Within half a step either way.
+        for drift in (-400_000..400_000).step_by(7_919) {
+            assert!(
+                (Calibration::correcting(drift).ppb() - drift).abs() <= 477,
+                "{drift}"
+            );
+        }
+        assert_eq!(Calibration::correcting(1_000_000).minus, 511);
+        assert_eq!(
+            Calibration::correcting(-1_000_000),
+            Calibration {
+                plus: true,
+                minus: 0
+            }
+        );
+    }
+
+    #[test]
+    fn subseconds() {
+        // embassy's default prescalers, 127 and 255.
+        let prer = 0x007f_00ff;
+        assert_eq!(subsecond_us(255, prer), 0);
+        assert_eq!(subsecond_us(127, prer), 500_000);
+        assert_eq!(subsecond_us(0, prer), 996_093);
+        assert_eq!(subsecond_us(300, prer), 0);
+    }
+
+    #[test]
+    fn timestamp_year() {
+        // 23:59:58 on 31 December, a Friday.
+        let (tstr, tsdr) = (0x0023_5958, 0x0000_b231);
+        assert_eq!(
+            timestamp(tstr, tsdr, at(2027, 1, 1, 0, 0, 5)),
+            Some(at(2026, 12, 31, 23, 59, 58))
+        );
+        assert_eq!(
+            timestamp(tstr, tsdr, at(2026, 12, 31, 23, 59, 59)),
+            Some(at(2026, 12, 31, 23, 59, 58))
+        );
+        // 29 February.
+        assert_eq!(
+            timestamp(0x0012_0000, 0x0000_0229, at(2026, 5, 19, 10, 15, 32)),
+            Some(at(2024, 2, 29, 12, 0, 0))
+        );
+        assert_eq!(
+            timestamp(0x0025_0000, 0x0000_0101, at(2026, 5, 19, 0, 0, 0)),
+            None
+        );
+    }
+}
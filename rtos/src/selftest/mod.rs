@@ -0,0 +1,231 @@
+use crate::uart::{ErrorFlags, SerialSettings};
+use alloc::string::String;
+use core::fmt::Write as _;
+use defmt::{info, warn};
+use embassy_embedded_hal::SetConfig;
+use embassy_futures::join::join;
+use embassy_stm32::usart;
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel};
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use embedded_io_async::{Read, Write};
+
+pub mod prbs;
+pub mod report;
+
+use prbs::{Prbs, PrbsChecker, PrbsKind};
+pub use report::Peer;
+use report::{LatencyStats, Limits, RateResult};
+
+pub const DEFAULT_RATES: [u32; 5] = [9_600, 115_200, 460_800, 921_600, 2_000_000];
+
+/// Blocks written before waiting for the first to come back.
+const WINDOW: usize = 4;
+pub const MAX_BLOCK_LEN: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SelfTestConfig {
+    pub peer: Peer,
+    pub kind: PrbsKind,
+    pub rates: &'static [u32],
+    /// Approximate time spent at each rate.
+    pub duration_ms: u64,
+    /// At most [`MAX_BLOCK_LEN`].
+    pub block_len: usize,
+    pub limits: Limits,
+}
+
+impl SelfTestConfig {
+    pub fn new(peer: Peer) -> Self {
+        Self {
+            peer,
+            kind: PrbsKind::Prbs15,
+            rates: &DEFAULT_RATES,
+            duration_ms: 250,
+            block_len: 64,
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// Run the PRBS test at every rate in `config` and write a report to `out`. `set_loopback` is
+/// called after each reconfiguration for [`Peer::Internal`], since reconfiguring the UART can
+/// reset it. `restore` is applied to the UART afterwards. Returns true if every rate passed.
+pub async fn run<R, W, O>(
+    rx: &mut R,
+    tx: &mut W,
+    out: &mut O,
+    config: &SelfTestConfig,
+    set_loopback: &mut impl FnMut(bool),
+    restore: &SerialSettings,
+) -> bool
+where
+    R: Read + SetConfig<Config = usart::Config>,
+    R::Error: Into<ErrorFlags> + Copy,
+    R::ConfigError: core::fmt::Debug,
+    W: Write,
+    O: Write,
+{
+    info!(
+        "selftest: {=str} peer, {} rates",
+        config.peer.as_str(),
+        config.rates.len()
+    );
+
+    let mut line = String::new();
+    let _ = writeln!(line, "selftest: {} peer\r", config.peer.as_str());
+    let _ = writeln!(line, "{}\r", report::HEADER);
+    let _ = out.write_all(line.as_bytes()).await;
+
+    let mut passed = true;
+    for &baudrate in config.rates {
+        let settings = SerialSettings::new(baudrate);
+        if let Err(e) = rx.set_config(&(&settings).into()) {
+            warn!(
+                "selftest: {} baud not supported: {}",
+                baudrate,
+                defmt::Debug2Format(&e)
+            );
+            continue;
+        }
+        if config.peer == Peer::Internal {
+            set_loopback(true);
+        }
+
+        let result = test_rate(rx, tx, &settings, config).await;
+        let verdict = config.limits.check(&result, settings.frame_bits());
+        passed &= verdict.is_ok();
+
+        line.clear();
+        let _ = report::write_row(&mut line, &result, settings.frame_bits(), verdict);
+        info!("selftest: {=str}", line.as_str());
+        line.push_str("\r\n");
+        let _ = out.write_all(line.as_bytes()).await;
+    }
+
+    if config.peer == Peer::Internal {
+        set_loopback(false);
+    }
+    if let Err(e) = rx.set_config(&restore.into()) {
+        warn!(
+            "selftest: failed to restore UART config: {}",
+            defmt::Debug2Format(&e)
+        );
+    }
+
+    let verdict = if passed { "PASS" } else { "FAIL" };
+    info!("selftest: {=str}", verdict);
+    line.clear();
+    let _ = writeln!(line, "selftest: {}\r", verdict);
+    let _ = out.write_all(line.as_bytes()).await;
+    let _ = out.flush().await;
+
+    passed
+}
+
+/// Discard anything left over from before the rate change.
+async fn drain<R: Read>(rx: &mut R) {
+    let mut buf = [0u8; 16];
+    while let Ok(Ok(_)) = with_timeout(Duration::from_millis(5), rx.read(&mut buf)).await {}
+}
+
+async fn test_rate<R, W>(
+    rx: &mut R,
+    tx: &mut W,
+    settings: &SerialSettings,
+    config: &SelfTestConfig,
+) -> RateResult
+where
+    R: Read,
+    R::Error: Into<ErrorFlags> + Copy,
+    W: Write,
+{
+    drain(rx).await;
+    Timer::after_millis(1).await;
+
+    let block_len = config.block_len.clamp(1, MAX_BLOCK_LEN);
+    let chars_per_sec = (settings.baudrate / settings.frame_bits()) as u64;
+    let blocks = (chars_per_sec * config.duration_ms / 1000 / block_len as u64).max(1);
+    // Generous: a full window in flight plus scheduling slack.
+    let idle = Duration::from_micros(
+        settings.chars_time_us((WINDOW * block_len) as u32) as u64 * 2 + 20_000,
+    );
+
+    let in_flight: Channel<NoopRawMutex, Instant, WINDOW> = Channel::new();
+    let start = Instant::now();
+
+    let writer = async {
+        let mut prbs = Prbs::new(config.kind);
+        let mut block = [0u8; MAX_BLOCK_LEN];
+        let block = &mut block[..block_len];
+        let mut sent = 0u64;
+
+        for _ in 0..blocks {
+            if with_timeout(idle, in_flight.send(Instant::now()))
+                .await
+                .is_err()
+            {
+                break;
+            }
+            prbs.fill(block);
+            if tx.write_all(block).await.is_err() {
+                break;
+            }
+            sent += block.len() as u64;
+        }
+        let _ = tx.flush().await;
+
+        sent
+    };
+
+    let reader = async {
+        let mut checker = PrbsChecker::new(Prbs::new(config.kind));
+        let mut latency = LatencyStats::default();
+        let (mut framing_errors, mut overruns) = (0, 0);
+        let mut buf = [0u8; 64];
+        let mut received = 0u64;
+        let mut last = start;
+        let total = blocks * block_len as u64;
+
+        while received < total {
+            match with_timeout(idle, rx.read(&mut buf)).await {
+                Ok(Ok(n)) => {
+                    last = Instant::now();
+                    checker.check(&buf[..n]);
+                    let before = received / block_len as u64;
+                    received += n as u64;
+                    for _ in before..received / block_len as u64 {
+                        if let Ok(sent_at) = in_flight.try_receive() {
+                            latency.record((last - sent_at).as_micros());
+                        }
+                    }
+                }
+                Ok(Err(e)) => {
+                    let flags: ErrorFlags = e.into();
+                    if flags.contains(ErrorFlags::OVERRUN) {
+                        overruns += 1;
+                    }
+                    if flags
+                        .intersects(ErrorFlags::FRAMING | ErrorFlags::NOISE | ErrorFlags::PARITY)
+                    {
+                        framing_errors += 1;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+
+        (checker.counts(), latency, framing_errors, overruns, last)
+    };
+
+    let (sent, (errors, latency, framing_errors, overruns, last)) = join(writer, reader).await;
+
+    RateResult {
+        baudrate: settings.baudrate,
+        sent,
+        errors,
+        framing_errors,
+        overruns,
+        elapsed_us: (last - start).as_micros(),
+        latency,
+    }
+}
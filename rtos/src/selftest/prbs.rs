@@ -0,0 +1,241 @@
+/// Pseudo-random binary sequences from ITU-T O.150.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrbsKind {
+    /// x^7 + x^6 + 1, period 127 bits.
+    Prbs7,
+    /// x^9 + x^5 + 1, period 511 bits.
+    Prbs9,
+    /// x^15 + x^14 + 1, period 32767 bits.
+    Prbs15,
+}
+
+impl PrbsKind {
+    const fn taps(&self) -> (u32, u32) {
+        match self {
+            PrbsKind::Prbs7 => (7, 6),
+            PrbsKind::Prbs9 => (9, 5),
+            PrbsKind::Prbs15 => (15, 14),
+        }
+    }
+
+    pub const fn order(&self) -> u32 {
+        self.taps().0
+    }
+
+    const fn mask(&self) -> u16 {
+        ((1u32 << self.order()) - 1) as u16
+    }
+}
+
+/// Fibonacci LFSR producing the sequence a byte at a time, first bit in the MSB.
+///
+/// Every output bit is also shifted into the register, so the state after a byte is simply the
+/// last `order` bits sent. The checker uses that to resynchronise from received data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prbs {
+    kind: PrbsKind,
+    state: u16,
+}
+
+impl Prbs {
+    pub fn new(kind: PrbsKind) -> Self {
+        Self::with_state(kind, u16::MAX)
+    }
+
+    /// An all-zero state would lock up the register, so it is replaced by all ones.
+    pub fn with_state(kind: PrbsKind, state: u16) -> Self {
+        let state = match state & kind.mask() {
+            0 => kind.mask(),
+            s => s,
+        };
+        Self { kind, state }
+    }
+
+    /// The generator that would produce what follows `recent`, the last bytes of the sequence
+    /// (at least `order` bits, most recent last).
+    pub fn from_recent(kind: PrbsKind, recent: &[u8]) -> Self {
+        let state = recent
+            .iter()
+            .rev()
+            .take(2)
+            .rev()
+            .fold(0u16, |acc, &b| (acc << 8) | b as u16);
+        Self::with_state(kind, state)
+    }
+
+    pub fn kind(&self) -> PrbsKind {
+        self.kind
+    }
+
+    fn next_bit(&mut self) -> u8 {
+        let (a, b) = self.kind.taps();
+        let bit = ((self.state >> (a - 1)) ^ (self.state >> (b - 1))) & 1;
+        self.state = ((self.state << 1) | bit) & self.kind.mask();
+        bit as u8
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        (0..8).fold(0u8, |acc, _| (acc << 1) | self.next_bit())
+    }
+
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for b in buf {
+            *b = self.next_byte();
+        }
+    }
+}
+
+/// Error counts from comparing received data against the expected sequence.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCounts {
+    pub bytes: u64,
+    pub byte_errors: u64,
+    pub bit_errors: u64,
+    /// Times the checker lost lock, e.g. after dropped bytes, and resynchronised.
+    pub resyncs: u32,
+}
+
+impl ErrorCounts {
+    /// Bit error rate over the bytes compared so far.
+    pub fn ber(&self) -> f32 {
+        match self.bytes {
+            0 => 0.0,
+            n => self.bit_errors as f32 / (n * 8) as f32,
+        }
+    }
+}
+
+/// Compares received bytes against a [`Prbs`] started from the same state as the sender.
+///
+/// A run of [`PrbsChecker::RESYNC_AFTER`] bad bytes is taken to be a slip (lost or extra bytes)
+/// rather than noise, and the expected sequence is re-seeded from the received data. Those bytes
+/// are still counted as errors.
+#[derive(Debug, Clone)]
+pub struct PrbsChecker {
+    expected: Prbs,
+    counts: ErrorCounts,
+    bad_run: u32,
+    recent: [u8; 2],
+}
+
+impl PrbsChecker {
+    pub const RESYNC_AFTER: u32 = 4;
+
+    pub fn new(expected: Prbs) -> Self {
+        Self {
+            expected,
+            counts: ErrorCounts::default(),
+            bad_run: 0,
+            recent: [0; 2],
+        }
+    }
+
+    pub fn check(&mut self, data: &[u8]) {
+        for &b in data {
+            let diff = b ^ self.expected.next_byte();
+            self.counts.bytes += 1;
+            self.recent = [self.recent[1], b];
+
+            if diff == 0 {
+                self.bad_run = 0;
+                continue;
+            }
+
+            self.counts.byte_errors += 1;
+            self.counts.bit_errors += diff.count_ones() as u64;
+            self.bad_run += 1;
+            if self.bad_run >= Self::RESYNC_AFTER {
+                self.expected = Prbs::from_recent(self.expected.kind(), &self.recent);
+                self.counts.resyncs += 1;
+                self.bad_run = 0;
+            }
+        }
+    }
+
+    pub fn counts(&self) -> ErrorCounts {
+        self.counts
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn sequence(kind: PrbsKind, len: usize) -> Vec<u8> {
+        let mut prbs = Prbs::new(kind);
+        (0..len).map(|_| prbs.next_byte()).collect()
+    }
+
+    #[test]
+    fn maximal_length_periods() {
+        for (kind, period) in [
+            (PrbsKind::Prbs7, 127),
+            (PrbsKind::Prbs9, 511),
+            (PrbsKind::Prbs15, 32767),
+        ] {
+            let mut prbs = Prbs::new(kind);
+            let start = prbs.clone();
+            let steps = (1..=period).find(|_| {
+                prbs.next_bit();
+                prbs == start
+            });
+            assert_eq!(steps, Some(period), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn state_follows_from_output() {
+        for kind in [PrbsKind::Prbs7, PrbsKind::Prbs9, PrbsKind::Prbs15] {
+            let data = sequence(kind, 40);
+            let mut prbs = Prbs::from_recent(kind, &data[..20]);
+            let rest: Vec<u8> = (0..20).map(|_| prbs.next_byte()).collect();
+            assert_eq!(rest, data[20..], "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn clean_data_has_no_errors() {
+        let mut checker = PrbsChecker::new(Prbs::new(PrbsKind::Prbs15));
+        checker.check(&sequence(PrbsKind::Prbs15, 1000));
+
+        let counts = checker.counts();
+        assert_eq!(counts.bytes, 1000);
+        assert_eq!(counts.byte_errors, 0);
+        assert_eq!(counts.ber(), 0.0);
+    }
+
+    #[test]
+    fn counts_bit_errors() {
+        let mut data = sequence(PrbsKind::Prbs9, 100);
+        data[10] ^= 0x81;
+        data[50] ^= 0x10;
+
+        let mut checker = PrbsChecker::new(Prbs::new(PrbsKind::Prbs9));
+        checker.check(&data);
+
+        let counts = checker.counts();
+        assert_eq!(counts.byte_errors, 2);
+        assert_eq!(counts.bit_errors, 3);
+        assert_eq!(counts.resyncs, 0);
+        assert!((counts.ber() - 3.0 / 800.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn resyncs_after_dropped_bytes() {
+        for kind in [PrbsKind::Prbs7, PrbsKind::Prbs9, PrbsKind::Prbs15] {
+            let mut data = sequence(kind, 200);
+            data.drain(60..63);
+
+            let mut checker = PrbsChecker::new(Prbs::new(kind));
+            checker.check(&data);
+
+            let counts = checker.counts();
+            assert_eq!(counts.resyncs, 1, "{:?}", kind);
+            assert!(counts.byte_errors <= PrbsChecker::RESYNC_AFTER as u64 + 1);
+            assert_eq!(counts.bytes, 197);
+        }
+    }
+}
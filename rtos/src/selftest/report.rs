@@ -0,0 +1,288 @@
+use super::prbs::ErrorCounts;
+use core::fmt::{self, Write};
+
+/// What the self-test talks to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Peer {
+    /// The UART's own receiver, via single-wire half-duplex mode. The RX pin is not used.
+    Internal,
+    /// Anything that echoes the bytes back: a TX-RX jumper or an echo sketch on the other end.
+    External,
+}
+
+impl Peer {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Peer::Internal => "internal",
+            Peer::External => "external",
+        }
+    }
+}
+
+/// Min/max/mean of a series of latency samples.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub samples: u32,
+    pub min_us: u64,
+    pub max_us: u64,
+    sum_us: u64,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, us: u64) {
+        if self.samples == 0 {
+            self.min_us = us;
+            self.max_us = us;
+        } else {
+            self.min_us = self.min_us.min(us);
+            self.max_us = self.max_us.max(us);
+        }
+        self.samples += 1;
+        self.sum_us += us;
+    }
+
+    pub fn mean_us(&self) -> u64 {
+        match self.samples {
+            0 => 0,
+            n => self.sum_us / n as u64,
+        }
+    }
+
+    /// Peak-to-peak spread.
+    pub fn jitter_us(&self) -> u64 {
+        self.max_us - self.min_us
+    }
+}
+
+/// Outcome of the test at one baud rate.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RateResult {
+    pub baudrate: u32,
+    pub sent: u64,
+    pub errors: ErrorCounts,
+    /// Receive errors reported by the UART.
+    pub framing_errors: u32,
+    pub overruns: u32,
+    pub elapsed_us: u64,
+    /// Time from starting to write a block to receiving its last byte.
+    pub latency: LatencyStats,
+}
+
+impl RateResult {
+    pub fn received(&self) -> u64 {
+        self.errors.bytes
+    }
+
+    pub fn missing(&self) -> u64 {
+        self.sent.saturating_sub(self.received())
+    }
+
+    /// Payload bytes per second received.
+    pub fn throughput(&self) -> u32 {
+        match self.elapsed_us {
+            0 => 0,
+            us => (self.received() * 1_000_000 / us) as u32,
+        }
+    }
+
+    /// Throughput as a percentage of the line rate, given `frame_bits` per character.
+    pub fn efficiency_pct(&self, frame_bits: u32) -> u32 {
+        let line_rate = self.baudrate / frame_bits;
+        match line_rate {
+            0 => 0,
+            rate => (self.throughput() as u64 * 100 / rate as u64) as u32,
+        }
+    }
+}
+
+/// Pass/fail thresholds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub max_ber: f32,
+    pub min_efficiency_pct: u32,
+    /// Allowed spread between the fastest and slowest block round trip.
+    pub max_jitter_us: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_ber: 0.0,
+            min_efficiency_pct: 80,
+            max_jitter_us: 2_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    NoData,
+    Missing,
+    BitErrors,
+    UartErrors,
+    Throughput,
+    Jitter,
+}
+
+impl Failure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Failure::NoData => "no data",
+            Failure::Missing => "missing bytes",
+            Failure::BitErrors => "bit errors",
+            Failure::UartErrors => "uart errors",
+            Failure::Throughput => "slow",
+            Failure::Jitter => "jitter",
+        }
+    }
+}
+
+impl Limits {
+    /// First limit `result` violates, if any.
+    pub fn check(&self, result: &RateResult, frame_bits: u32) -> Result<(), Failure> {
+        if result.received() == 0 {
+            return Err(Failure::NoData);
+        }
+        if result.missing() > 0 {
+            return Err(Failure::Missing);
+        }
+        if result.errors.ber() > self.max_ber {
+            return Err(Failure::BitErrors);
+        }
+        if result.framing_errors > 0 || result.overruns > 0 {
+            return Err(Failure::UartErrors);
+        }
+        if result.efficiency_pct(frame_bits) < self.min_efficiency_pct {
+            return Err(Failure::Throughput);
+        }
+        if result.latency.jitter_us() > self.max_jitter_us {
+            return Err(Failure::Jitter);
+        }
+
+        Ok(())
+    }
+}
+
+/// Column headings for [`write_row`].
+pub const HEADER: &str =
+    "   baud     sent  missing  byte err  bit err  BER       uart err  B/s      eff  lat us (min/mean/max)  result";
+
+/// One line of the report, without line terminator.
+pub fn write_row<W: Write>(
+    w: &mut W,
+    result: &RateResult,
+    frame_bits: u32,
+    verdict: Result<(), Failure>,
+) -> fmt::Result {
+    let l = &result.latency;
+    write!(
+        w,
+        "{:>7}  {:>7}  {:>7}  {:>8}  {:>7}  {:<8.2e}  {:>8}  {:>7}  {:>3}%  {}/{}/{}  ",
+        result.baudrate,
+        result.sent,
+        result.missing(),
+        result.errors.byte_errors,
+        result.errors.bit_errors,
+        result.errors.ber(),
+        result.framing_errors + result.overruns,
+        result.throughput(),
+        result.efficiency_pct(frame_bits),
+        l.min_us,
+        l.mean_us(),
+        l.max_us,
+    )?;
+    match verdict {
+        Ok(()) => w.write_str("PASS"),
+        Err(failure) => write!(w, "FAIL ({})", failure.as_str()),
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    fn clean_result() -> RateResult {
+        let mut latency = LatencyStats::default();
+        for us in [600, 650, 700] {
+            latency.record(us);
+        }
+
+        RateResult {
+            baudrate: 115_200,
+            sent: 2304,
+            errors: ErrorCounts {
+                bytes: 2304,
+                ..Default::default()
+            },
+            elapsed_us: 200_000,
+            latency,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn latency_stats() {
+        let latency = clean_result().latency;
+        assert_eq!(latency.min_us, 600);
+        assert_eq!(latency.max_us, 700);
+        assert_eq!(latency.mean_us(), 650);
+        assert_eq!(latency.jitter_us(), 100);
+        assert_eq!(LatencyStats::default().mean_us(), 0);
+    }
+
+    #[test]
+    fn throughput_and_efficiency() {
+        let result = clean_result();
+        assert_eq!(result.throughput(), 11_520);
+        assert_eq!(result.efficiency_pct(10), 100);
+    }
+
+    #[test]
+    fn verdicts() {
+        let limits = Limits::default();
+        let result = clean_result();
+        assert_eq!(limits.check(&result, 10), Ok(()));
+
+        let mut r = result;
+        r.sent += 5;
+        assert_eq!(limits.check(&r, 10), Err(Failure::Missing));
+
+        let mut r = result;
+        r.errors.bit_errors = 1;
+        r.errors.byte_errors = 1;
+        assert_eq!(limits.check(&r, 10), Err(Failure::BitErrors));
+
+        let mut r = result;
+        r.framing_errors = 1;
+        assert_eq!(limits.check(&r, 10), Err(Failure::UartErrors));
+
+        let mut r = result;
+        r.elapsed_us *= 2;
+        assert_eq!(limits.check(&r, 10), Err(Failure::Throughput));
+
+        let mut r = result;
+        r.latency.record(5_000);
+        assert_eq!(limits.check(&r, 10), Err(Failure::Jitter));
+
+        assert_eq!(
+            limits.check(&RateResult::default(), 10),
+            Err(Failure::NoData)
+        );
+    }
+
+    #[test]
+    fn formats_row() {
+        let mut out = String::new();
+        let result = clean_result();
+        write_row(&mut out, &result, 10, Ok(())).unwrap();
+        assert!(out.starts_with(" 115200     2304        0"), "{}", out);
+        assert!(out.ends_with("600/650/700  PASS"), "{}", out);
+
+        out.clear();
+        write_row(&mut out, &result, 10, Err(Failure::Jitter)).unwrap();
+        assert!(out.ends_with("FAIL (jitter)"), "{}", out);
+    }
+}
@@ -0,0 +1,141 @@
+use super::frame::Frame;
+use core::fmt::{self, Write};
+
+pub const BYTES_PER_ROW: usize = 16;
+
+/// `seconds.micros`, e.g. `12.000345`.
+pub struct Timestamp(pub u64);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0 / 1_000_000;
+        let micros = self.0 % 1_000_000;
+        match f.width() {
+            Some(width) => write!(f, "{:>width$}.{:06}", secs, micros, width = width),
+            None => write!(f, "{}.{:06}", secs, micros),
+        }
+    }
+}
+
+/// Write one hex+ASCII row for `chunk` (at most [`BYTES_PER_ROW`] bytes) starting at `offset`.
+pub fn write_row<W: Write>(w: &mut W, offset: usize, chunk: &[u8]) -> fmt::Result {
+    write!(w, "  {:04x} ", offset)?;
+    for i in 0..BYTES_PER_ROW {
+        if i == BYTES_PER_ROW / 2 {
+            w.write_char(' ')?;
+        }
+        match chunk.get(i) {
+            Some(b) => write!(w, " {:02x}", b)?,
+            None => w.write_str("   ")?,
+        }
+    }
+
+    w.write_str("  |")?;
+    for &b in chunk {
+        let c = if (0x20..0x7f).contains(&b) {
+            b as char
+        } else {
+            '.'
+        };
+        w.write_char(c)?;
+    }
+    w.write_char('|')
+}
+
+/// Write `frame` as a header line followed by hex+ASCII rows, each terminated by `\n`:
+///
+/// ```text
+/// [     1.000250] uno +0.000250 4 bytes
+///   0000  41 54 0d 0a                                       |AT..|
+/// ```
+///
+/// The header shows the time since the start of the previous frame, on either line, if known.
+pub fn write_frame<W: Write>(
+    w: &mut W,
+    frame: &Frame,
+    label: &str,
+    prev_start_us: Option<u64>,
+) -> fmt::Result {
+    write!(w, "[{:6}] {}", Timestamp(frame.start_us), label)?;
+    if let Some(prev) = prev_start_us {
+        write!(w, " +{}", Timestamp(frame.start_us.saturating_sub(prev)))?;
+    }
+    write!(w, " {} bytes", frame.data.len())?;
+    if !frame.errors.is_empty() {
+        write!(w, " [{}]", frame.errors)?;
+    }
+    w.write_char('\n')?;
+
+    for (row, chunk) in frame.data.chunks(BYTES_PER_ROW).enumerate() {
+        write_row(w, row * BYTES_PER_ROW, chunk)?;
+        w.write_char('\n')?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sniffer::frame::Line, uart::ErrorFlags};
+    use alloc::{string::String, vec::Vec};
+
+    fn frame(start_us: u64, data: &[u8], errors: ErrorFlags) -> Frame {
+        Frame {
+            line: Line::A,
+            start_us,
+            end_us: start_us + 100,
+            data: Vec::from(data),
+            errors,
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(std::format!("{}", Timestamp(12_000_345)), "12.000345");
+        assert_eq!(std::format!("{:4}", Timestamp(1_500_000)), "   1.500000");
+        assert_eq!(std::format!("{}", Timestamp(42)), "0.000042");
+    }
+
+    #[test]
+    fn short_frame() {
+        let mut out = String::new();
+        let f = frame(1_000_250, b"AT\r\n", ErrorFlags::NONE);
+        write_frame(&mut out, &f, "uno", Some(1_000_000)).unwrap();
+
+        assert_eq!(
+            out,
+            "[     1.000250] uno +0.000250 4 bytes\n\
+             \x20 0000  41 54 0d 0a                                       |AT..|\n"
+        );
+    }
+
+    #[test]
+    fn multi_row_frame_with_errors() {
+        let mut out = String::new();
+        let data: Vec<u8> = (0x30..0x30 + 18).collect();
+        let f = frame(5, &data, ErrorFlags::FRAMING);
+        write_frame(&mut out, &f, "host", None).unwrap();
+
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "[     0.000005] host 18 bytes [framing]");
+        assert_eq!(
+            lines[1],
+            "  0000  30 31 32 33 34 35 36 37  38 39 3a 3b 3c 3d 3e 3f  |0123456789:;<=>?|"
+        );
+        assert_eq!(
+            lines[2],
+            "  0010  40 41                                             |@A|"
+        );
+    }
+
+    #[test]
+    fn non_printable_bytes_are_dotted() {
+        let mut out = String::new();
+        write_row(&mut out, 0, &[0x00, 0x7f, 0xff, b'~', b' ']).unwrap();
+        assert!(out.ends_with("|...~ |"));
+    }
+}
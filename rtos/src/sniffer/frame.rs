@@ -0,0 +1,187 @@
+use crate::uart::ErrorFlags;
+use alloc::vec::Vec;
+
+/// Which of the two sniffed lines a frame was seen on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Line {
+    A,
+    B,
+}
+
+impl Line {
+    pub const fn index(&self) -> usize {
+        match self {
+            Line::A => 0,
+            Line::B => 1,
+        }
+    }
+}
+
+/// A run of bytes on one line, delimited by idle gaps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub line: Line,
+    /// Estimated start of the first byte, in microseconds since boot.
+    pub start_us: u64,
+    /// End of the last byte, in microseconds since boot.
+    pub end_us: u64,
+    pub data: Vec<u8>,
+    pub errors: ErrorFlags,
+}
+
+/// Joins the chunks handed out by the UART driver into frames, closing a frame once the line
+/// has been idle for at least `gap_us`.
+///
+/// The driver only reports when a chunk *ended*, so its start is estimated from the character
+/// time.
+#[derive(Debug)]
+pub struct FrameAssembler {
+    line: Line,
+    gap_us: u64,
+    char_time_us: u64,
+    max_len: usize,
+    current: Option<Frame>,
+}
+
+impl FrameAssembler {
+    pub fn new(line: Line, gap_us: u64, char_time_us: u64, max_len: usize) -> Self {
+        Self {
+            line,
+            gap_us,
+            char_time_us,
+            max_len,
+            current: None,
+        }
+    }
+
+    /// Add `data` received on this line, whose last byte completed at `end_us`. Returns the
+    /// previous frame if this chunk doesn't belong to it.
+    pub fn push(&mut self, data: &[u8], errors: ErrorFlags, end_us: u64) -> Option<Frame> {
+        let start_us = end_us.saturating_sub(data.len() as u64 * self.char_time_us);
+
+        let completed = match self.current.take() {
+            Some(frame)
+                if start_us.saturating_sub(frame.end_us) >= self.gap_us
+                    || frame.data.len() + data.len() > self.max_len =>
+            {
+                Some(frame)
+            }
+            Some(mut frame) => {
+                frame.data.extend_from_slice(data);
+                frame.end_us = end_us;
+                frame.errors.insert(errors);
+                self.current = Some(frame);
+                return None;
+            }
+            None => None,
+        };
+
+        // A chunk can't start before the previous one ended, whatever the estimate says.
+        let start_us = match &completed {
+            Some(prev) => start_us.max(prev.end_us),
+            None => start_us,
+        };
+        self.current = Some(Frame {
+            line: self.line,
+            start_us,
+            end_us,
+            data: Vec::from(data),
+            errors,
+        });
+
+        completed
+    }
+
+    /// Close the current frame if the line has been idle long enough.
+    pub fn poll(&mut self, now_us: u64) -> Option<Frame> {
+        match &self.current {
+            Some(frame) if now_us.saturating_sub(frame.end_us) >= self.gap_us => {
+                self.current.take()
+            }
+            _ => None,
+        }
+    }
+
+    /// Close the current frame regardless of timing.
+    pub fn flush(&mut self) -> Option<Frame> {
+        self.current.take()
+    }
+
+    /// Start of the frame still being assembled, if any; no later frame from this line can
+    /// start before it.
+    pub fn pending_start(&self) -> Option<u64> {
+        self.current.as_ref().map(|frame| frame.start_us)
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAR_US: u64 = 100;
+    const GAP_US: u64 = 350;
+
+    fn assembler() -> FrameAssembler {
+        FrameAssembler::new(Line::A, GAP_US, CHAR_US, 64)
+    }
+
+    #[test]
+    fn joins_chunks_without_gap() {
+        let mut asm = assembler();
+
+        assert_eq!(asm.push(b"AT+", ErrorFlags::NONE, 1_300), None);
+        assert_eq!(asm.pending_start(), Some(1_000));
+        // Starts 100us after the previous chunk ended: same frame.
+        assert_eq!(asm.push(b"GMR\r\n", ErrorFlags::NONE, 1_900), None);
+        assert_eq!(asm.poll(2_000), None);
+
+        let frame = asm.poll(2_250).unwrap();
+        assert_eq!(frame.data, b"AT+GMR\r\n");
+        assert_eq!(frame.start_us, 1_000);
+        assert_eq!(frame.end_us, 1_900);
+        assert_eq!(asm.pending_start(), None);
+    }
+
+    #[test]
+    fn splits_on_idle_gap() {
+        let mut asm = assembler();
+
+        assert_eq!(asm.push(b"AT\r\n", ErrorFlags::NONE, 400), None);
+        let frame = asm.push(b"OK\r\n", ErrorFlags::NONE, 1_200).unwrap();
+        assert_eq!(frame.data, b"AT\r\n");
+        assert_eq!(frame.start_us, 0);
+
+        let frame = asm.flush().unwrap();
+        assert_eq!(frame.data, b"OK\r\n");
+        assert_eq!(frame.start_us, 800);
+    }
+
+    #[test]
+    fn splits_at_max_length() {
+        let mut asm = FrameAssembler::new(Line::B, GAP_US, CHAR_US, 4);
+
+        assert_eq!(asm.push(b"abc", ErrorFlags::NONE, 300), None);
+        let frame = asm.push(b"de", ErrorFlags::NONE, 500).unwrap();
+        assert_eq!(frame.data, b"abc");
+        assert_eq!(frame.line, Line::B);
+
+        let frame = asm.flush().unwrap();
+        assert_eq!(frame.data, b"de");
+        assert_eq!(frame.start_us, 300);
+    }
+
+    #[test]
+    fn accumulates_errors() {
+        let mut asm = assembler();
+
+        asm.push(b"x", ErrorFlags::NONE, 100);
+        asm.push(b"", ErrorFlags::FRAMING, 150);
+        asm.push(b"y", ErrorFlags::OVERRUN, 250);
+
+        let frame = asm.flush().unwrap();
+        assert_eq!(frame.data, b"xy");
+        assert_eq!(frame.errors, ErrorFlags::FRAMING | ErrorFlags::OVERRUN);
+    }
+}
@@ -0,0 +1,162 @@
+use super::frame::{Frame, Line};
+use alloc::collections::VecDeque;
+
+/// Merges the frames of both lines into a single stream ordered by start time.
+///
+/// Frames of one line arrive in order, but a long frame on one line can complete after a short
+/// one on the other line that started later. Each line therefore reports a horizon: a time
+/// before which it will not produce any more frames. A frame is only released once the other
+/// line is known to have nothing earlier.
+#[derive(Debug, Default)]
+pub struct Merger {
+    queues: [VecDeque<Frame>; 2],
+    horizons: [u64; 2],
+}
+
+impl Merger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, frame: Frame) {
+        let i = frame.line.index();
+        self.horizons[i] = self.horizons[i].max(frame.start_us);
+        self.queues[i].push_back(frame);
+    }
+
+    /// No frame starting before `horizon_us` will be pushed for `line` any more.
+    pub fn set_horizon(&mut self, line: Line, horizon_us: u64) {
+        let i = line.index();
+        self.horizons[i] = self.horizons[i].max(horizon_us);
+    }
+
+    /// Earliest time a frame from `line` could still start.
+    fn earliest(&self, i: usize) -> u64 {
+        match self.queues[i].front() {
+            Some(frame) => frame.start_us,
+            None => self.horizons[i],
+        }
+    }
+
+    /// Next frame in start time order, if it is safe to release.
+    pub fn pop(&mut self) -> Option<Frame> {
+        let (a, b) = (self.earliest(0), self.earliest(1));
+        // Ties go to line A, so the order is deterministic.
+        let (i, other) = if a <= b { (0, b) } else { (1, a) };
+
+        match self.queues[i].front() {
+            Some(frame) if frame.start_us <= other => self.queues[i].pop_front(),
+            _ => None,
+        }
+    }
+
+    /// Release everything regardless of horizons, e.g. when sniffing stops.
+    pub fn drain(&mut self) -> Option<Frame> {
+        self.set_horizon(Line::A, u64::MAX);
+        self.set_horizon(Line::B, u64::MAX);
+        self.pop()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues[0].len() + self.queues[1].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uart::ErrorFlags;
+    use alloc::vec::Vec;
+
+    fn frame(line: Line, start_us: u64, end_us: u64) -> Frame {
+        Frame {
+            line,
+            start_us,
+            end_us,
+            data: Vec::from(&b"x"[..]),
+            errors: ErrorFlags::NONE,
+        }
+    }
+
+    fn starts(merger: &mut Merger) -> Vec<(Line, u64)> {
+        core::iter::from_fn(|| merger.pop())
+            .map(|f| (f.line, f.start_us))
+            .collect()
+    }
+
+    #[test]
+    fn holds_frames_until_other_line_catches_up() {
+        let mut merger = Merger::new();
+
+        merger.push(frame(Line::B, 200, 300));
+        assert_eq!(merger.pop(), None);
+
+        // A long frame on A that started earlier but completed later.
+        merger.push(frame(Line::A, 100, 900));
+        assert_eq!(starts(&mut merger), [(Line::A, 100)]);
+
+        // B's frame still waits for A to be known idle past 200.
+        merger.set_horizon(Line::A, 1_000);
+        assert_eq!(starts(&mut merger), [(Line::B, 200)]);
+        assert!(merger.is_empty());
+    }
+
+    #[test]
+    fn interleaves_by_start_time() {
+        let mut merger = Merger::new();
+
+        merger.push(frame(Line::A, 10, 20));
+        merger.push(frame(Line::A, 50, 60));
+        merger.push(frame(Line::B, 30, 40));
+        merger.push(frame(Line::B, 70, 80));
+        merger.set_horizon(Line::A, 100);
+        merger.set_horizon(Line::B, 100);
+
+        assert_eq!(
+            starts(&mut merger),
+            [(Line::A, 10), (Line::B, 30), (Line::A, 50), (Line::B, 70)]
+        );
+    }
+
+    #[test]
+    fn ties_prefer_line_a() {
+        let mut merger = Merger::new();
+
+        merger.push(frame(Line::B, 10, 20));
+        merger.push(frame(Line::A, 10, 20));
+        assert_eq!(starts(&mut merger), [(Line::A, 10)]);
+
+        // A could still start another frame at 10 until its horizon moves on.
+        merger.set_horizon(Line::A, 11);
+        assert_eq!(starts(&mut merger), [(Line::B, 10)]);
+    }
+
+    #[test]
+    fn drain_releases_everything() {
+        let mut merger = Merger::new();
+
+        merger.push(frame(Line::B, 500, 600));
+        assert_eq!(merger.pop(), None);
+        assert_eq!(merger.len(), 1);
+
+        let frame = merger.drain().unwrap();
+        assert_eq!(frame.start_us, 500);
+        assert_eq!(merger.drain(), None);
+    }
+
+    #[test]
+    fn horizon_never_moves_backwards() {
+        let mut merger = Merger::new();
+
+        merger.set_horizon(Line::A, 1_000);
+        merger.set_horizon(Line::A, 10);
+        merger.push(frame(Line::B, 500, 600));
+        assert_eq!(starts(&mut merger), [(Line::B, 500)]);
+    }
+}
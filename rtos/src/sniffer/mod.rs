@@ -0,0 +1,198 @@
+use crate::{
+    board::SnifferResource,
+    uart::{ErrorFlags, SerialSettings},
+};
+use alloc::string::String;
+use defmt::{info, warn};
+use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
+use embassy_stm32::{
+    bind_interrupts, peripherals,
+    usart::{self, BasicInstance, RingBufferedUartRx, UartRx},
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{Instant, Timer};
+
+pub mod format;
+pub mod frame;
+pub mod merge;
+
+use frame::{FrameAssembler, Line};
+use merge::Merger;
+
+bind_interrupts!(struct SnifferIrqs {
+    USART2 => usart::InterruptHandler<peripherals::USART2>;
+    UART4 => usart::InterruptHandler<peripherals::UART4>;
+});
+
+const RING_BUF_SIZE: usize = 256;
+const CHUNK_SIZE: usize = 64;
+const POLL_MS: u64 = 10;
+
+/// Sniffer line settings and labels.
+#[derive(Debug, Clone, Copy)]
+pub struct SnifferConfig {
+    pub line_a: SerialSettings,
+    pub line_b: SerialSettings,
+    pub label_a: &'static str,
+    pub label_b: &'static str,
+    /// Idle time, in characters, that ends a frame.
+    pub gap_chars: u32,
+    /// Frames are split at chunk boundaries beyond this length.
+    pub max_frame_len: usize,
+}
+
+impl Default for SnifferConfig {
+    fn default() -> Self {
+        Self {
+            line_a: SerialSettings::new(115200),
+            line_b: SerialSettings::new(115200),
+            label_a: "A",
+            label_b: "B",
+            gap_chars: 4,
+            max_frame_len: 1024,
+        }
+    }
+}
+
+struct Chunk {
+    line: Line,
+    end_us: u64,
+    data: heapless::Vec<u8, CHUNK_SIZE>,
+    errors: ErrorFlags,
+}
+
+static CHUNKS: Channel<CriticalSectionRawMutex, Chunk, 16> = Channel::new();
+
+/// Start sniffing both lines. Frames are logged over defmt, merged in start time order.
+pub fn spawn(spawner: &Spawner, r: SnifferResource, config: SnifferConfig) {
+    let rx_a = defmt::unwrap!(UartRx::new(
+        r.a_peri,
+        SnifferIrqs,
+        r.a_rx,
+        r.a_rx_dma,
+        (&config.line_a).into(),
+    ));
+    let rx_b = defmt::unwrap!(UartRx::new(
+        r.b_peri,
+        SnifferIrqs,
+        r.b_rx,
+        r.b_rx_dma,
+        (&config.line_b).into(),
+    ));
+
+    // In AXI SRAM, as DMA1 can't reach DTCM. Not StaticCells: the section isn't initialized at
+    // boot, which would leave their flags random.
+    #[link_section = ".axisram"]
+    static mut RING_A: [u8; RING_BUF_SIZE] = [0; RING_BUF_SIZE];
+    #[link_section = ".axisram"]
+    static mut RING_B: [u8; RING_BUF_SIZE] = [0; RING_BUF_SIZE];
+    // SAFETY: `r` holds the only USART2 and UART4, so this runs once.
+    let (ring_a, ring_b) = unsafe {
+        (
+            &mut *core::ptr::addr_of_mut!(RING_A),
+            &mut *core::ptr::addr_of_mut!(RING_B),
+        )
+    };
+    let rx_a = rx_a.into_ring_buffered(ring_a);
+    let rx_b = rx_b.into_ring_buffered(ring_b);
+
+    defmt::unwrap!(spawner.spawn(sniffer_line_a(rx_a)));
+    defmt::unwrap!(spawner.spawn(sniffer_line_b(rx_b)));
+    defmt::unwrap!(spawner.spawn(sniffer_task(config)));
+}
+
+async fn read_line<T: BasicInstance>(line: Line, mut rx: RingBufferedUartRx<'static, T>) -> ! {
+    let mut buf = [0u8; CHUNK_SIZE];
+
+    loop {
+        let (n, errors) = match rx.read(&mut buf).await {
+            Ok(n) => (n, ErrorFlags::NONE),
+            Err(e) => (0, ErrorFlags::from(e)),
+        };
+        let end_us = Instant::now().as_micros();
+
+        let chunk = Chunk {
+            line,
+            end_us,
+            data: defmt::unwrap!(heapless::Vec::from_slice(&buf[..n]).ok()),
+            errors,
+        };
+        if CHUNKS.try_send(chunk).is_err() {
+            warn!("sniffer: dropped {} bytes", n);
+        }
+    }
+}
+
+#[embassy_executor::task]
+async fn sniffer_line_a(rx: RingBufferedUartRx<'static, peripherals::USART2>) {
+    read_line(Line::A, rx).await
+}
+
+#[embassy_executor::task]
+async fn sniffer_line_b(rx: RingBufferedUartRx<'static, peripherals::UART4>) {
+    read_line(Line::B, rx).await
+}
+
+/// How long data can sit in the DMA ring buffer before the driver hands it out: half a ring
+/// (the half-transfer interrupt) plus the idle character.
+fn reporting_latency_us(settings: &SerialSettings) -> u64 {
+    settings.chars_time_us(RING_BUF_SIZE as u32 / 2 + 1) as u64
+}
+
+#[embassy_executor::task]
+async fn sniffer_task(config: SnifferConfig) {
+    info!(
+        "Running task: sniffer_task ({=str} @ {}, {=str} @ {})",
+        config.label_a, config.line_a.baudrate, config.label_b, config.line_b.baudrate
+    );
+
+    let settings = [config.line_a, config.line_b];
+    let mut assemblers = [Line::A, Line::B].map(|line| {
+        let s = &settings[line.index()];
+        FrameAssembler::new(
+            line,
+            s.chars_time_us(config.gap_chars) as u64,
+            s.char_time_us() as u64,
+            config.max_frame_len,
+        )
+    });
+    let latency = settings.map(|s| reporting_latency_us(&s));
+    let labels = [config.label_a, config.label_b];
+    let mut merger = Merger::new();
+    let mut prev_start = None;
+    let mut out = String::new();
+
+    loop {
+        if let Either::First(chunk) = select(CHUNKS.receive(), Timer::after_millis(POLL_MS)).await {
+            let assembler = &mut assemblers[chunk.line.index()];
+            if let Some(frame) = assembler.push(&chunk.data, chunk.errors, chunk.end_us) {
+                merger.push(frame);
+            }
+        }
+
+        let now = Instant::now().as_micros();
+        for (i, assembler) in assemblers.iter_mut().enumerate() {
+            if let Some(frame) = assembler.poll(now) {
+                merger.push(frame);
+            }
+            let line = if i == 0 { Line::A } else { Line::B };
+            let horizon = now.saturating_sub(latency[i]);
+            merger.set_horizon(
+                line,
+                assembler.pending_start().unwrap_or(horizon).min(horizon),
+            );
+        }
+
+        while let Some(frame) = merger.pop() {
+            out.clear();
+            let label = labels[frame.line.index()];
+            if format::write_frame(&mut out, &frame, label, prev_start).is_ok() {
+                for line in out.lines() {
+                    info!("{=str}", line);
+                }
+            }
+            prev_start = Some(frame.start_us);
+        }
+    }
+}
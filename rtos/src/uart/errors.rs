@@ -0,0 +1,99 @@
+/// Receive error flags, independent of the HAL error type so they can be stored with captured
+/// traffic.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorFlags(u8);
+
+impl ErrorFlags {
+    pub const NONE: Self = Self(0);
+    pub const OVERRUN: Self = Self(1 << 0);
+    pub const FRAMING: Self = Self(1 << 1);
+    pub const NOISE: Self = Self(1 << 2);
+    pub const PARITY: Self = Self(1 << 3);
+    /// Data was dropped downstream of the UART, e.g. a full buffer.
+    pub const DROPPED: Self = Self(1 << 4);
+
+    const NAMES: [(Self, &'static str); 5] = [
+        (Self::OVERRUN, "overrun"),
+        (Self::FRAMING, "framing"),
+        (Self::NOISE, "noise"),
+        (Self::PARITY, "parity"),
+        (Self::DROPPED, "dropped"),
+    ];
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn intersects(&self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    /// Names of the flags that are set.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        Self::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+    }
+}
+
+impl core::ops::BitOr for ErrorFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl core::fmt::Display for ErrorFlags {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, name) in self.names().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            f.write_str(name)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn combines_and_names_flags() {
+        let mut flags = ErrorFlags::NONE;
+        assert!(flags.is_empty());
+        assert_eq!(flags.to_string(), "");
+
+        flags.insert(ErrorFlags::FRAMING);
+        let flags = flags | ErrorFlags::OVERRUN;
+        assert!(flags.contains(ErrorFlags::OVERRUN));
+        assert!(!flags.contains(ErrorFlags::PARITY));
+        assert!(flags.intersects(ErrorFlags::FRAMING | ErrorFlags::PARITY));
+        assert!(!flags.intersects(ErrorFlags::NOISE | ErrorFlags::PARITY));
+        assert_eq!(flags.bits(), 0b11);
+        assert_eq!(flags.to_string(), "overrun,framing");
+    }
+}
@@ -0,0 +1,249 @@
+use defmt::warn;
+use embassy_stm32::{
+    gpio::{Level, Output},
+    pac, usart,
+};
+use embassy_time::{Duration, Instant, Timer};
+
+pub mod errors;
+pub mod rs485;
+pub mod settings;
+
+pub use errors::ErrorFlags;
+pub use rs485::{DriverEnable, EchoFilter, HardwareDe, Polarity, Rs485Config, Rs485Rx, Rs485Tx};
+pub use settings::{Parity, SerialSettings, SettingsError, StopBits};
+
+impl From<usart::Error> for ErrorFlags {
+    fn from(e: usart::Error) -> Self {
+        match e {
+            usart::Error::Overrun => ErrorFlags::OVERRUN,
+            usart::Error::Framing => ErrorFlags::FRAMING,
+            usart::Error::Noise => ErrorFlags::NOISE,
+            usart::Error::Parity => ErrorFlags::PARITY,
+            usart::Error::BufferTooLong => ErrorFlags::DROPPED,
+        }
+    }
+}
+
+impl From<&SerialSettings> for usart::Config {
+    fn from(settings: &SerialSettings) -> Self {
+        let mut config = usart::Config::default();
+        config.baudrate = settings.baudrate;
+        // With parity enabled the HAL selects a 9-bit word, so 8 data bits are kept.
+        config.data_bits = usart::DataBits::DataBits8;
+        config.parity = match settings.parity {
+            Parity::None => usart::Parity::ParityNone,
+            Parity::Even => usart::Parity::ParityEven,
+            Parity::Odd => usart::Parity::ParityOdd,
+        };
+        config.stop_bits = match settings.stop_bits {
+            StopBits::One => usart::StopBits::STOP1,
+            StopBits::OneAndHalf => usart::StopBits::STOP1P5,
+            StopBits::Two => usart::StopBits::STOP2,
+        };
+
+        config
+    }
+}
+
+/// Connect the transmitter to the receiver inside the peripheral, using single-wire half-duplex
+/// mode, for self-tests. The RX pin is ignored while enabled and the TX pin keeps driving the
+/// line. Reconfiguring the UART may clear this again.
+pub fn set_internal_loopback(regs: pac::usart::Usart, enable: bool) {
+    set_hdsel(regs, enable);
+}
+
+/// Single-wire half-duplex on the TX pin, pin `tx_pin` of `tx_port`: the pin is switched to
+/// open-drain with its pull-up, so devices can drive the line between our characters, and the
+/// receiver listens on it. What we send is received as well. Reconfiguring the UART may clear
+/// this again; the pin keeps its mode until set up again.
+pub fn set_half_duplex(
+    regs: pac::usart::Usart,
+    tx_port: pac::gpio::Gpio,
+    tx_pin: usize,
+    enable: bool,
+) {
+    use pac::gpio::vals::{Ot, Pupdr};
+    critical_section::with(|_| {
+        let (ot, pupdr) = if enable {
+            (Ot::OPENDRAIN, Pupdr::PULLUP)
+        } else {
+            (Ot::PUSHPULL, Pupdr::FLOATING)
+        };
+        tx_port.otyper().modify(|w| w.set_ot(tx_pin, ot));
+        tx_port.pupdr().modify(|w| w.set_pupdr(tx_pin, pupdr));
+    });
+    set_hdsel(regs, enable);
+}
+
+fn set_hdsel(regs: pac::usart::Usart, enable: bool) {
+    critical_section::with(|_| {
+        // HDSEL can only be written while the UART is disabled.
+        let enabled = regs.cr1().read().ue();
+        regs.cr1().modify(|w| w.set_ue(false));
+        regs.cr3().modify(|w| w.set_hdsel(enable));
+        regs.cr1().modify(|w| w.set_ue(enabled));
+    });
+}
+
+/// Let the USART drive the RS-485 driver enable line on its RTS/DE pin, timed from `config` at
+/// `settings`. The pin must be set to its RTS/DE alternate function. Reconfiguring the UART may
+/// clear this again.
+pub fn set_hardware_de(regs: pac::usart::Usart, settings: &SerialSettings, config: &Rs485Config) {
+    let assert = rs485::sample_times(config.assert_us, settings);
+    let deassert = rs485::sample_times(config.deassert_us, settings);
+    critical_section::with(|_| {
+        // DEM, DEP, DEAT and DEDT can only be written while the UART is disabled.
+        let enabled = regs.cr1().read().ue();
+        regs.cr1().modify(|w| {
+            w.set_ue(false);
+            w.set_deat(assert);
+            w.set_dedt(deassert);
+        });
+        regs.cr3().modify(|w| {
+            w.set_dem(true);
+            w.set_dep(config.polarity == Polarity::ActiveLow);
+        });
+        regs.cr1().modify(|w| w.set_ue(enabled));
+    });
+}
+
+/// A transceiver's driver enable: the USART's own where its RTS/DE pin is free, a GPIO
+/// otherwise.
+pub enum De<'d> {
+    Hardware {
+        regs: pac::usart::Usart,
+        config: Rs485Config,
+    },
+    Gpio(GpioDe<'d>),
+}
+
+impl De<'_> {
+    /// Hand DE to the USART at `settings`, see [`set_hardware_de`].
+    // Unused on the GIGA: USART1's DE pin is PA12, its USB D+ line.
+    #[allow(dead_code)]
+    pub fn hardware(
+        regs: pac::usart::Usart,
+        settings: &SerialSettings,
+        config: &Rs485Config,
+    ) -> Self {
+        set_hardware_de(regs, settings, config);
+        De::Hardware {
+            regs,
+            config: *config,
+        }
+    }
+
+    /// Time the USART's DE for `settings` again, after reconfiguring the UART cleared it.
+    pub fn retime(&self, settings: &SerialSettings) {
+        if let De::Hardware { regs, config } = self {
+            set_hardware_de(*regs, settings, config);
+        }
+    }
+}
+
+impl DriverEnable for De<'_> {
+    fn set_asserted(&mut self, asserted: bool) {
+        match self {
+            De::Hardware { .. } => HardwareDe.set_asserted(asserted),
+            De::Gpio(de) => de.set_asserted(asserted),
+        }
+    }
+
+    async fn wait_idle(&mut self) {
+        match self {
+            De::Hardware { .. } => HardwareDe.wait_idle().await,
+            De::Gpio(de) => de.wait_idle().await,
+        }
+    }
+
+    fn timed(&self) -> bool {
+        match self {
+            De::Hardware { .. } => HardwareDe.timed(),
+            De::Gpio(de) => de.timed(),
+        }
+    }
+}
+
+/// Driver enable on a GPIO, for UARTs whose RTS/DE pin isn't available.
+pub struct GpioDe<'d> {
+    pin: Output<'d>,
+    polarity: Polarity,
+    regs: pac::usart::Usart,
+}
+
+impl<'d> GpioDe<'d> {
+    /// `regs` is the USART the transceiver is on; its transmission complete flag ends the frame.
+    pub fn new(pin: Output<'d>, polarity: Polarity, regs: pac::usart::Usart) -> Self {
+        let mut de = Self {
+            pin,
+            polarity,
+            regs,
+        };
+        de.set_asserted(false);
+        de
+    }
+}
+
+impl DriverEnable for GpioDe<'_> {
+    fn set_asserted(&mut self, asserted: bool) {
+        let high = asserted == (self.polarity == Polarity::ActiveHigh);
+        self.pin
+            .set_level(if high { Level::High } else { Level::Low });
+    }
+
+    async fn wait_idle(&mut self) {
+        // Drop DE anyway; holding the bus forever is worse.
+        if !wait_tx_complete(self.regs).await {
+            warn!("rs485: transmission never completed");
+        }
+    }
+}
+
+/// Two characters of 12 bits at [`SerialSettings::MIN_BAUDRATE`] take 80 ms.
+const TX_COMPLETE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Wait for the last stop bit to leave the shift register, false if it hasn't within
+/// [`TX_COMPLETE_TIMEOUT`]. Buffered writes only wait for the buffer to drain, which leaves up
+/// to two characters. The flag is polled every timer tick, about 30 us, so other tasks run in
+/// the meantime; what follows starts that much after the stop bit at worst, well inside the
+/// turnaround Modbus and DMX512 allow.
+pub async fn wait_tx_complete(regs: pac::usart::Usart) -> bool {
+    let deadline = Instant::now() + TX_COMPLETE_TIMEOUT;
+    while !regs.isr().read().tc() {
+        if Instant::now() > deadline {
+            return false;
+        }
+        Timer::after_ticks(1).await;
+    }
+    true
+}
+
+/// LIN mode: 11-bit break detection, which sets LBDF, and break generation through SBKRQ. Needs
+/// 8N1 with one stop bit. Reconfiguring the UART may clear this again.
+pub fn set_lin_mode(regs: pac::usart::Usart, enable: bool) {
+    critical_section::with(|_| {
+        // LINEN and LBDL can only be written while the UART is disabled.
+        let enabled = regs.cr1().read().ue();
+        regs.cr1().modify(|w| w.set_ue(false));
+        regs.cr2().modify(|w| {
+            w.set_linen(enable);
+            w.set_lbdl(enable);
+        });
+        regs.cr1().modify(|w| w.set_ue(enabled));
+    });
+}
+
+/// Send a break after the character being transmitted, in LIN mode.
+pub fn send_break(regs: pac::usart::Usart) {
+    regs.rqr().write(|w| w.set_sbkrq(true));
+}
+
+/// Whether a break was detected since the last call, in LIN mode.
+pub fn take_break(regs: pac::usart::Usart) -> bool {
+    let detected = regs.isr().read().lbdf();
+    if detected {
+        regs.icr().write(|w| w.set_lbdcf(true));
+    }
+    detected
+}
@@ -0,0 +1,443 @@
+use super::SerialSettings;
+use core::cell::RefCell;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{ErrorType, Read, Write};
+use heapless::Deque;
+
+/// Level of the driver enable line while transmitting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+/// Driver enable timing of an RS-485 transceiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rs485Config {
+    pub polarity: Polarity,
+    /// DE is asserted this long before the start bit of the first character.
+    pub assert_us: u32,
+    /// DE is held this long after the stop bit of the last character.
+    pub deassert_us: u32,
+    /// Drop the transceiver's copy of our own transmission. Only for transceivers whose receiver
+    /// stays enabled while driving (RE tied low); otherwise the reply would be eaten instead.
+    pub suppress_echo: bool,
+}
+
+impl Default for Rs485Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rs485Config {
+    pub const fn new() -> Self {
+        Self {
+            polarity: Polarity::ActiveHigh,
+            assert_us: 10,
+            deassert_us: 10,
+            suppress_echo: false,
+        }
+    }
+}
+
+/// `us` in USART sample times (1/16 bit), as the hardware DE assertion and deassertion fields
+/// take them. They are 5 bits wide, so anything over 31/16 bit is clamped.
+pub fn sample_times(us: u32, settings: &SerialSettings) -> u8 {
+    let samples = (us as u64 * settings.baudrate as u64 * 16).div_ceil(1_000_000);
+    samples.min(31) as u8
+}
+
+/// The driver enable line. `asserted` is the logical state; the implementation applies the
+/// polarity.
+#[allow(async_fn_in_trait)]
+pub trait DriverEnable {
+    fn set_asserted(&mut self, asserted: bool);
+
+    /// Wait until the last stop bit has left the shift register. `flush` only waits for the
+    /// UART's buffer to drain.
+    async fn wait_idle(&mut self) {}
+
+    /// False if the line is switched by the USART itself, or there is none, so [`Rs485Tx`] has
+    /// no turnaround to time.
+    fn timed(&self) -> bool {
+        true
+    }
+}
+
+/// No line: a full-duplex connection.
+impl<P: DriverEnable> DriverEnable for Option<P> {
+    fn set_asserted(&mut self, asserted: bool) {
+        if let Some(de) = self {
+            de.set_asserted(asserted);
+        }
+    }
+
+    async fn wait_idle(&mut self) {
+        if let Some(de) = self {
+            de.wait_idle().await;
+        }
+    }
+
+    fn timed(&self) -> bool {
+        self.as_ref().is_some_and(|de| de.timed())
+    }
+}
+
+impl<P: DriverEnable + ?Sized> DriverEnable for &mut P {
+    fn set_asserted(&mut self, asserted: bool) {
+        (**self).set_asserted(asserted)
+    }
+
+    async fn wait_idle(&mut self) {
+        (**self).wait_idle().await
+    }
+
+    fn timed(&self) -> bool {
+        (**self).timed()
+    }
+}
+
+/// The USART's own DE output, see [`super::set_hardware_de`]. It times assertion and
+/// deassertion itself.
+pub struct HardwareDe;
+
+impl DriverEnable for HardwareDe {
+    fn set_asserted(&mut self, _asserted: bool) {}
+
+    fn timed(&self) -> bool {
+        false
+    }
+}
+
+/// Echoed characters compared against what was sent.
+const ECHO_LEN: usize = 256;
+
+/// Removes our own transmission from what the receiver hears. A character that differs from the
+/// one sent means another driver was on the bus; it is kept and the rest of the echo is no
+/// longer expected.
+#[derive(Debug, Default)]
+pub struct EchoFilter {
+    expected: Deque<u8, ECHO_LEN>,
+    /// Sent after `expected` filled up; dropped without comparing.
+    unchecked: usize,
+    collisions: u32,
+}
+
+impl EchoFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sent(&mut self, data: &[u8]) {
+        for &b in data {
+            if self.unchecked > 0 || self.expected.push_back(b).is_err() {
+                self.unchecked += 1;
+            }
+        }
+    }
+
+    /// Remove the echo from `data`, returning how many characters are left at its start.
+    pub fn filter(&mut self, data: &mut [u8]) -> usize {
+        let mut kept = 0;
+        for i in 0..data.len() {
+            let b = data[i];
+            match self.expected.pop_front() {
+                Some(echo) if echo == b => continue,
+                Some(_) => {
+                    self.collisions += 1;
+                    self.clear();
+                }
+                None if self.unchecked > 0 => {
+                    self.unchecked -= 1;
+                    continue;
+                }
+                None => {}
+            }
+            data[kept] = b;
+            kept += 1;
+        }
+        kept
+    }
+
+    pub fn clear(&mut self) {
+        self.expected.clear();
+        self.unchecked = 0;
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.expected.is_empty() && self.unchecked == 0
+    }
+
+    pub fn collisions(&self) -> u32 {
+        self.collisions
+    }
+}
+
+/// Transmit half of a half-duplex RS-485 link: DE is asserted on the first write and released
+/// on `flush`, once the last character is out, or when it is dropped mid-frame.
+pub struct Rs485Tx<'a, W, P: DriverEnable, D> {
+    tx: W,
+    de: P,
+    delay: D,
+    config: Rs485Config,
+    echo: Option<&'a RefCell<EchoFilter>>,
+    driving: bool,
+}
+
+impl<'a, W: Write, P: DriverEnable, D: DelayNs> Rs485Tx<'a, W, P, D> {
+    /// `echo` is shared with the [`Rs485Rx`] of the same UART when echo suppression is on.
+    pub fn new(
+        tx: W,
+        mut de: P,
+        delay: D,
+        config: Rs485Config,
+        echo: Option<&'a RefCell<EchoFilter>>,
+    ) -> Self {
+        de.set_asserted(false);
+        Self {
+            tx,
+            de,
+            delay,
+            config,
+            echo,
+            driving: false,
+        }
+    }
+
+    async fn release(&mut self) {
+        if !self.driving {
+            return;
+        }
+        self.de.wait_idle().await;
+        if self.de.timed() {
+            self.delay.delay_us(self.config.deassert_us).await;
+        }
+        self.de.set_asserted(false);
+        self.driving = false;
+    }
+}
+
+impl<W, P: DriverEnable, D> Drop for Rs485Tx<'_, W, P, D> {
+    fn drop(&mut self) {
+        if self.driving {
+            self.de.set_asserted(false);
+        }
+    }
+}
+
+impl<W: Write, P: DriverEnable, D> ErrorType for Rs485Tx<'_, W, P, D> {
+    type Error = W::Error;
+}
+
+impl<W: Write, P: DriverEnable, D: DelayNs> Write for Rs485Tx<'_, W, P, D> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, W::Error> {
+        if !self.driving {
+            self.de.set_asserted(true);
+            self.driving = true;
+            if self.de.timed() {
+                self.delay.delay_us(self.config.assert_us).await;
+            }
+        }
+        let result = self.tx.write(buf).await;
+        match result {
+            Ok(n) => {
+                if let Some(echo) = self.echo {
+                    echo.borrow_mut().sent(&buf[..n]);
+                }
+            }
+            // Don't keep the bus after a failure.
+            Err(_) => self.release().await,
+        }
+        result
+    }
+
+    async fn flush(&mut self) -> Result<(), W::Error> {
+        let result = self.tx.flush().await;
+        self.release().await;
+        result
+    }
+}
+
+/// Receive half of a half-duplex RS-485 link, with our own echo removed if `echo` is given.
+pub struct Rs485Rx<'a, R> {
+    rx: R,
+    echo: Option<&'a RefCell<EchoFilter>>,
+}
+
+impl<'a, R: Read> Rs485Rx<'a, R> {
+    pub fn new(rx: R, echo: Option<&'a RefCell<EchoFilter>>) -> Self {
+        Self { rx, echo }
+    }
+}
+
+impl<R: Read> ErrorType for Rs485Rx<'_, R> {
+    type Error = R::Error;
+}
+
+impl<R: Read> Read for Rs485Rx<'_, R> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, R::Error> {
+        loop {
+            let n = self.rx.read(buf).await?;
+            let kept = match self.echo {
+                Some(echo) => echo.borrow_mut().filter(&mut buf[..n]),
+                None => n,
+            };
+            if kept > 0 || n == 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{rc::Rc, vec, vec::Vec};
+    use core::convert::Infallible;
+    use embassy_futures::block_on;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Event {
+        De(bool),
+        Idle,
+        Delay(u32),
+        Write(usize),
+        Flush,
+    }
+
+    type Log = Rc<RefCell<Vec<Event>>>;
+
+    struct Pin(Log);
+
+    impl DriverEnable for Pin {
+        fn set_asserted(&mut self, asserted: bool) {
+            self.0.borrow_mut().push(Event::De(asserted));
+        }
+
+        async fn wait_idle(&mut self) {
+            self.0.borrow_mut().push(Event::Idle);
+        }
+    }
+
+    struct Uart(Log);
+
+    impl ErrorType for Uart {
+        type Error = Infallible;
+    }
+
+    impl Write for Uart {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            self.0.borrow_mut().push(Event::Write(buf.len()));
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), Infallible> {
+            self.0.borrow_mut().push(Event::Flush);
+            Ok(())
+        }
+    }
+
+    struct Delay(Log);
+
+    impl DelayNs for Delay {
+        async fn delay_ns(&mut self, ns: u32) {
+            self.0.borrow_mut().push(Event::Delay(ns / 1000));
+        }
+    }
+
+    #[test]
+    fn drives_only_while_sending() {
+        let log = Log::default();
+        let config = Rs485Config {
+            assert_us: 20,
+            deassert_us: 50,
+            ..Rs485Config::new()
+        };
+        let mut tx = Rs485Tx::new(
+            Uart(log.clone()),
+            Pin(log.clone()),
+            Delay(log.clone()),
+            config,
+            None,
+        );
+        block_on(async {
+            tx.write_all(&[1, 2, 3]).await.unwrap();
+            tx.write_all(&[4]).await.unwrap();
+            tx.flush().await.unwrap();
+            // Nothing sent, nothing to release.
+            tx.flush().await.unwrap();
+        });
+        assert_eq!(
+            *log.borrow(),
+            [
+                Event::De(false),
+                Event::De(true),
+                Event::Delay(20),
+                Event::Write(3),
+                Event::Write(1),
+                Event::Flush,
+                Event::Idle,
+                Event::Delay(50),
+                Event::De(false),
+                Event::Flush,
+            ]
+        );
+
+        // The USART's own DE needs no timing here.
+        let log = Log::default();
+        let mut tx = Rs485Tx::new(
+            Uart(log.clone()),
+            HardwareDe,
+            Delay(log.clone()),
+            config,
+            None,
+        );
+        block_on(async {
+            tx.write_all(&[1]).await.unwrap();
+            tx.flush().await.unwrap();
+        });
+        assert_eq!(*log.borrow(), [Event::Write(1), Event::Flush]);
+    }
+
+    #[test]
+    fn suppresses_echo() {
+        let mut echo = EchoFilter::new();
+        echo.sent(&[0x11, 0x03, 0x00]);
+        let mut data = vec![0x11, 0x03];
+        assert_eq!(echo.filter(&mut data), 0);
+        let mut data = vec![0x00, 0x11, 0x03];
+        assert_eq!(echo.filter(&mut data), 2);
+        assert_eq!(data[..2], [0x11, 0x03]);
+        assert!(echo.is_idle());
+
+        // Someone else drove the bus at the same time.
+        echo.sent(&[1, 2, 3]);
+        let mut data = vec![1, 7, 3];
+        assert_eq!(echo.filter(&mut data), 2);
+        assert_eq!(data[..2], [7, 3]);
+        assert_eq!(echo.collisions(), 1);
+        assert!(echo.is_idle());
+
+        // Past the comparison buffer the echo is only counted.
+        let long = vec![0x55; ECHO_LEN + 2];
+        echo.sent(&long);
+        let mut data = long.clone();
+        data.push(9);
+        assert_eq!(echo.filter(&mut data), 1);
+        assert_eq!(data[0], 9);
+        assert!(echo.is_idle());
+    }
+
+    #[test]
+    fn hardware_times() {
+        let settings = SerialSettings::new(19200);
+        // One bit is 52 us.
+        assert_eq!(sample_times(52, &settings), 16);
+        assert_eq!(sample_times(0, &settings), 0);
+        assert_eq!(sample_times(1000, &settings), 31);
+        assert_eq!(sample_times(1, &SerialSettings::new(115200)), 2);
+    }
+}
@@ -0,0 +1,215 @@
+/// Parity, as supported by the STM32 USART.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    OneAndHalf,
+    Two,
+}
+
+impl StopBits {
+    fn half_bits(&self) -> u32 {
+        match self {
+            StopBits::One => 2,
+            StopBits::OneAndHalf => 3,
+            StopBits::Two => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsError {
+    BaudrateOutOfRange(u32),
+    /// Only 8 data bits are supported, parity is sent as an extra bit.
+    UnsupportedDataBits(u8),
+    /// Mark and space parity have no USART equivalent.
+    UnsupportedParity(u8),
+    UnsupportedStopBits(u8),
+}
+
+/// Line settings of a UART, independent of the HAL so they can be shared between the USB CDC
+/// line coding, protocol timing and the USART configuration.
+///
+/// The data length is always 8 bits; a parity bit comes on top of that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialSettings {
+    pub baudrate: u32,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Default for SerialSettings {
+    fn default() -> Self {
+        Self::new(115200)
+    }
+}
+
+impl SerialSettings {
+    pub const MIN_BAUDRATE: u32 = 300;
+    pub const MAX_BAUDRATE: u32 = 12_500_000;
+    pub const DATA_BITS: u32 = 8;
+
+    /// 8N1 at `baudrate`.
+    pub const fn new(baudrate: u32) -> Self {
+        Self {
+            baudrate,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+        }
+    }
+
+    pub const fn with_parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    pub const fn with_stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    /// Decode a USB CDC `SET_LINE_CODING` request, using the encodings from the CDC PSTN
+    /// specification (table 17): `stop_bits` 0 = 1, 1 = 1.5, 2 = 2 and `parity` 0 = none,
+    /// 1 = odd, 2 = even, 3 = mark, 4 = space.
+    pub fn from_cdc_line_coding(
+        baudrate: u32,
+        stop_bits: u8,
+        parity: u8,
+        data_bits: u8,
+    ) -> Result<Self, SettingsError> {
+        if !(Self::MIN_BAUDRATE..=Self::MAX_BAUDRATE).contains(&baudrate) {
+            return Err(SettingsError::BaudrateOutOfRange(baudrate));
+        }
+        if data_bits as u32 != Self::DATA_BITS {
+            return Err(SettingsError::UnsupportedDataBits(data_bits));
+        }
+        let parity = match parity {
+            0 => Parity::None,
+            1 => Parity::Odd,
+            2 => Parity::Even,
+            other => return Err(SettingsError::UnsupportedParity(other)),
+        };
+        let stop_bits = match stop_bits {
+            0 => StopBits::One,
+            1 => StopBits::OneAndHalf,
+            2 => StopBits::Two,
+            other => return Err(SettingsError::UnsupportedStopBits(other)),
+        };
+
+        Ok(Self {
+            baudrate,
+            parity,
+            stop_bits,
+        })
+    }
+
+    /// Bits on the wire per character, in half bits to account for 1.5 stop bits.
+    fn frame_half_bits(&self) -> u32 {
+        let parity = match self.parity {
+            Parity::None => 0,
+            Parity::Even | Parity::Odd => 1,
+        };
+
+        2 * (1 + Self::DATA_BITS + parity) + self.stop_bits.half_bits()
+    }
+
+    /// Bits on the wire per character, rounded up.
+    pub fn frame_bits(&self) -> u32 {
+        self.frame_half_bits().div_ceil(2)
+    }
+
+    /// Duration of `chars` characters, in microseconds, rounded up.
+    pub fn chars_time_us(&self, chars: u32) -> u32 {
+        let half_bits = self.frame_half_bits() as u64 * chars as u64;
+        let denom = 2 * self.baudrate as u64;
+
+        (half_bits * 1_000_000).div_ceil(denom) as u32
+    }
+
+    /// Duration of a single character, in microseconds, rounded up.
+    pub fn char_time_us(&self) -> u32 {
+        self.chars_time_us(1)
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_cdc_line_coding() {
+        let settings = SerialSettings::from_cdc_line_coding(9600, 2, 2, 8).unwrap();
+        assert_eq!(
+            settings,
+            SerialSettings::new(9600)
+                .with_parity(Parity::Even)
+                .with_stop_bits(StopBits::Two)
+        );
+
+        let settings = SerialSettings::from_cdc_line_coding(115200, 1, 1, 8).unwrap();
+        assert_eq!(settings.parity, Parity::Odd);
+        assert_eq!(settings.stop_bits, StopBits::OneAndHalf);
+    }
+
+    #[test]
+    fn rejects_unsupported_line_coding() {
+        assert_eq!(
+            SerialSettings::from_cdc_line_coding(0, 0, 0, 8),
+            Err(SettingsError::BaudrateOutOfRange(0))
+        );
+        assert_eq!(
+            SerialSettings::from_cdc_line_coding(9600, 0, 0, 7),
+            Err(SettingsError::UnsupportedDataBits(7))
+        );
+        assert_eq!(
+            SerialSettings::from_cdc_line_coding(9600, 0, 3, 8),
+            Err(SettingsError::UnsupportedParity(3))
+        );
+        assert_eq!(
+            SerialSettings::from_cdc_line_coding(9600, 5, 0, 8),
+            Err(SettingsError::UnsupportedStopBits(5))
+        );
+    }
+
+    #[test]
+    fn frame_length() {
+        assert_eq!(SerialSettings::new(9600).frame_bits(), 10);
+        assert_eq!(
+            SerialSettings::new(9600)
+                .with_parity(Parity::Even)
+                .frame_bits(),
+            11
+        );
+        assert_eq!(
+            SerialSettings::new(9600)
+                .with_parity(Parity::Odd)
+                .with_stop_bits(StopBits::Two)
+                .frame_bits(),
+            12
+        );
+        assert_eq!(
+            SerialSettings::new(9600)
+                .with_stop_bits(StopBits::OneAndHalf)
+                .frame_bits(),
+            11
+        );
+    }
+
+    #[test]
+    fn character_time() {
+        // 10 bits at 9600 baud = 1041.67us
+        assert_eq!(SerialSettings::new(9600).char_time_us(), 1042);
+        assert_eq!(SerialSettings::new(115200).char_time_us(), 87);
+        // 3.5 characters of 8E1 at 19200 baud: 11 bits * 3.5 / 19200 = 2005.2us
+        let settings = SerialSettings::new(19200).with_parity(Parity::Even);
+        assert_eq!(settings.chars_time_us(7) / 2, 2005);
+    }
+}
@@ -0,0 +1,80 @@
+/// State of the CDC-ACM `SET_CONTROL_LINE_STATE` bits as last set by the host.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ControlLines {
+    pub dtr: bool,
+    pub rts: bool,
+}
+
+/// Module pins to drive for a given [`ControlLines`] state. `true` means asserted; both pins
+/// are active-low on the module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModulePinState {
+    pub reset: bool,
+    pub boot: bool,
+}
+
+impl ModulePinState {
+    pub const RELEASED: Self = Self {
+        reset: false,
+        boot: false,
+    };
+}
+
+/// Maps DTR to the module reset pin and RTS to its boot pin, cross-coupled the same way as the
+/// usual two-transistor auto-reset circuit: a pin is only asserted while its line is set and
+/// the other one is not.
+///
+/// Terminals raise both lines when opening the port, which therefore leaves the module running,
+/// while flashing tools can still sequence reset and bootloader entry by toggling them apart.
+pub fn module_pins(lines: ControlLines) -> ModulePinState {
+    ModulePinState {
+        reset: lines.dtr && !lines.rts,
+        boot: lines.rts && !lines.dtr,
+    }
+}
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(dtr: bool, rts: bool) -> ControlLines {
+        ControlLines { dtr, rts }
+    }
+
+    #[test]
+    fn opening_a_terminal_leaves_module_running() {
+        assert_eq!(module_pins(lines(false, false)), ModulePinState::RELEASED);
+        assert_eq!(module_pins(lines(true, true)), ModulePinState::RELEASED);
+    }
+
+    #[test]
+    fn single_line_asserts_its_pin() {
+        assert_eq!(
+            module_pins(lines(true, false)),
+            ModulePinState {
+                reset: true,
+                boot: false
+            }
+        );
+        assert_eq!(
+            module_pins(lines(false, true)),
+            ModulePinState {
+                reset: false,
+                boot: true
+            }
+        );
+    }
+
+    #[test]
+    fn bootloader_entry_sequence() {
+        // reset held, then boot held while reset is released, then both released
+        let sequence = [lines(true, false), lines(false, true), lines(false, false)];
+        let pins: std::vec::Vec<_> = sequence.into_iter().map(module_pins).collect();
+
+        assert!(pins[0].reset && !pins[0].boot);
+        assert!(!pins[1].reset && pins[1].boot);
+        assert_eq!(pins[2], ModulePinState::RELEASED);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::{
+    board::UsbResource,
+    bridge::stats::BridgeStats,
+    consts,
+    module::Module,
+    uart::{SerialSettings, SettingsError},
+};
+use alloc::{format, string::String};
+use core::sync::atomic::{AtomicBool, Ordering};
+use defmt::{debug, info, warn};
+use embassy_embedded_hal::SetConfig;
+use embassy_futures::{
+    join::join,
+    select::{select, select4, Either, Either4},
+};
+use embassy_stm32::{bind_interrupts, peripherals, usart, usb_otg};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, pipe::Pipe, signal::Signal};
+use embassy_time::{Instant, Timer};
+use embassy_usb::{
+    class::cdc_acm::{CdcAcmClass, Receiver, Sender, State},
+    driver::{Driver, EndpointError},
+    Builder,
+};
+use embedded_io_async::{Read, Write};
+use static_cell::StaticCell;
+
+pub mod control;
+
+use control::{module_pins, ControlLines, ModulePinState};
+
+bind_interrupts!(struct UsbIrqs {
+    OTG_FS => usb_otg::InterruptHandler<peripherals::USB_OTG_FS>;
+});
+
+// Generic test VID/PID, as used by the embassy examples.
+const USB_VID: u16 = 0xc0de;
+const USB_PID: u16 = 0xcafe;
+const USB_MANUFACTURER: &str = "bsodmike";
+const MAX_PACKET_SIZE: u16 = 64;
+/// embassy-usb does not notify line coding or control line changes, so they are polled.
+const CONTROL_POLL_MS: u64 = 10;
+
+pub const USB_PIPE_SIZE: usize = 512;
+
+/// Bytes received from the USB host, on their way to the module.
+pub static USB_RX: Pipe<CriticalSectionRawMutex, USB_PIPE_SIZE> = Pipe::new();
+/// Bytes from the module, on their way to the USB host.
+pub static USB_TX: Pipe<CriticalSectionRawMutex, USB_PIPE_SIZE> = Pipe::new();
+/// Line coding last set by the host and accepted by [`SerialSettings::from_cdc_line_coding`].
+pub static LINE_CODING: Signal<CriticalSectionRawMutex, SerialSettings> = Signal::new();
+/// DTR/RTS as last set by the host; both are cleared when the host disconnects.
+pub static CONTROL_LINES: Signal<CriticalSectionRawMutex, ControlLines> = Signal::new();
+/// Set while [`run_bridge`] drains [`USB_RX`].
+static BRIDGING: AtomicBool = AtomicBool::new(false);
+
+/// A port is considered open while the host holds either control line.
+fn session_active(lines: ControlLines) -> bool {
+    lines.dtr || lines.rts
+}
+
+#[embassy_executor::task]
+pub async fn usb_task(r: UsbResource) {
+    info!("Running task: usb_task");
+
+    static EP_OUT_BUFFER: StaticCell<[u8; 256]> = StaticCell::new();
+    let ep_out_buffer = &mut EP_OUT_BUFFER.init([0; 256])[..];
+    let mut config = usb_otg::Config::default();
+    // VBUS is not wired to the OTG_FS sense pin (PA9 is USART1 TX on the GIGA R1 WiFi).
+    config.vbus_detection = false;
+    let driver = usb_otg::Driver::new_fs(r.peri, UsbIrqs, r.dp, r.dm, ep_out_buffer, config);
+
+    static PRODUCT: StaticCell<String> = StaticCell::new();
+    let product = PRODUCT.init(format!("STM32H747 UART bridge {}", consts::GIT_DESCRIBE));
+
+    let mut config = embassy_usb::Config::new(USB_VID, USB_PID);
+    config.manufacturer = Some(USB_MANUFACTURER);
+    config.product = Some(product.as_str());
+    config.serial_number = Some(embassy_stm32::uid::uid_hex());
+    config.max_power = 100;
+    config.max_packet_size_0 = 64;
+
+    // Required for Windows support of the CDC-ACM interface association.
+    config.device_class = 0xEF;
+    config.device_sub_class = 0x02;
+    config.device_protocol = 0x01;
+    config.composite_with_iads = true;
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+    let mut state = State::new();
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut [], // no msos descriptors
+        &mut control_buf,
+    );
+    let class = CdcAcmClass::new(&mut builder, &mut state, MAX_PACKET_SIZE);
+    let mut usb = builder.build();
+    let (mut sender, mut receiver) = class.split();
+
+    let cdc_fut = async {
+        loop {
+            receiver.wait_connection().await;
+            info!("usb: configured");
+            let res = select(usb_rx_loop(&mut receiver), usb_tx_loop(&mut sender)).await;
+            if let Either::First(Err(e)) | Either::Second(Err(e)) = res {
+                debug!("usb: endpoint error {}", e);
+            }
+            info!("usb: disconnected");
+            CONTROL_LINES.signal(ControlLines::default());
+        }
+    };
+
+    join(usb.run(), cdc_fut).await;
+}
+
+fn line_coding<'d, D: Driver<'d>>(
+    receiver: &Receiver<'d, D>,
+) -> Result<SerialSettings, SettingsError> {
+    let coding = receiver.line_coding();
+
+    SerialSettings::from_cdc_line_coding(
+        coding.data_rate(),
+        coding.stop_bits() as u8,
+        coding.parity_type() as u8,
+        coding.data_bits(),
+    )
+}
+
+async fn usb_rx_loop<'d, D: Driver<'d>>(
+    receiver: &mut Receiver<'d, D>,
+) -> Result<(), EndpointError> {
+    let mut buf = [0; MAX_PACKET_SIZE as usize];
+    let mut last_coding = None;
+    let mut lines = ControlLines::default();
+
+    loop {
+        let coding = line_coding(receiver);
+        if last_coding != Some(coding) {
+            match coding {
+                Ok(settings) => LINE_CODING.signal(settings),
+                Err(e) => warn!("usb: rejected line coding {}", defmt::Debug2Format(&e)),
+            }
+            last_coding = Some(coding);
+        }
+
+        let current = ControlLines {
+            dtr: receiver.dtr(),
+            rts: receiver.rts(),
+        };
+        if current != lines {
+            debug!("usb: DTR={} RTS={}", current.dtr, current.rts);
+            CONTROL_LINES.signal(current);
+            lines = current;
+        }
+
+        match select(
+            receiver.read_packet(&mut buf),
+            Timer::after_millis(CONTROL_POLL_MS),
+        )
+        .await
+        {
+            Either::First(n) if !session_active(lines) => {
+                debug!("usb: dropped {} bytes, port not open", n?)
+            }
+            Either::First(n) if BRIDGING.load(Ordering::Relaxed) => {
+                USB_RX.write_all(&buf[..n?]).await
+            }
+            // Nobody drains the pipe until the bridge starts, possibly never if another mode
+            // holds USART1. Keep what fits rather than stop polling the control lines.
+            Either::First(n) => {
+                let data = &buf[..n?];
+                let kept = USB_RX.try_write(data).unwrap_or(0);
+                if kept < data.len() {
+                    debug!(
+                        "usb: dropped {} bytes, no bridge running",
+                        data.len() - kept
+                    );
+                }
+            }
+            Either::Second(()) => {}
+        }
+    }
+}
+
+async fn usb_tx_loop<'d, D: Driver<'d>>(sender: &mut Sender<'d, D>) -> Result<(), EndpointError> {
+    let mut buf = [0; MAX_PACKET_SIZE as usize];
+
+    loop {
+        let n = USB_TX.read(&mut buf).await;
+        sender.write_packet(&buf[..n]).await?;
+        if n == buf.len() && USB_TX.is_empty() {
+            // A full packet doesn't end the transfer; tell the host there's nothing more yet.
+            sender.write_packet(&[]).await?;
+        }
+    }
+}
+
+/// Wait for the host to open the virtual serial port.
+pub async fn wait_for_session() -> ControlLines {
+    loop {
+        let lines = CONTROL_LINES.wait().await;
+        if session_active(lines) {
+            return lines;
+        }
+    }
+}
+
+fn apply_module_pins(state: ModulePinState, module: &mut Module<'_>) {
+    module.set_lines(state.reset, state.boot);
+}
+
+/// Pump bytes between the USB host and the module until the host closes the port. Line coding
+/// changes are applied to the module UART and DTR/RTS drive its reset and boot pins, see
+/// [`control::module_pins`]. `restore` is applied to the UART once the session ends.
+pub async fn run_bridge<R, W>(
+    rx: &mut R,
+    tx: &mut W,
+    module: &mut Module<'_>,
+    lines: ControlLines,
+    restore: &SerialSettings,
+) -> BridgeStats
+where
+    R: Read + SetConfig<Config = usart::Config>,
+    R::ConfigError: core::fmt::Debug,
+    W: Write,
+{
+    info!("usb: bridge session started");
+    BRIDGING.store(true, Ordering::Relaxed);
+
+    let mut stats = BridgeStats::new(Instant::now().as_millis());
+    let mut host_buf = [0u8; MAX_PACKET_SIZE as usize];
+    let mut module_buf = [0u8; MAX_PACKET_SIZE as usize];
+    apply_module_pins(module_pins(lines), module);
+
+    loop {
+        match select4(
+            USB_RX.read(&mut host_buf),
+            rx.read(&mut module_buf),
+            LINE_CODING.wait(),
+            CONTROL_LINES.wait(),
+        )
+        .await
+        {
+            Either4::First(n) => {
+                let data = &host_buf[..n];
+                match tx.write_all(data).await {
+                    Ok(()) => stats.host_to_module.record(data),
+                    Err(e) => warn!("usb: module write failed: {}", defmt::Debug2Format(&e)),
+                }
+            }
+            Either4::Second(Ok(n)) => {
+                let data = &module_buf[..n];
+                USB_TX.write_all(data).await;
+                stats.module_to_host.record(data);
+            }
+            Either4::Second(Err(e)) => {
+                warn!("usb: module read error: {}", defmt::Debug2Format(&e));
+                stats.module_to_host.record_error();
+            }
+            Either4::Third(settings) => {
+                info!(
+                    "usb: line coding {} baud, {} bits per character",
+                    settings.baudrate,
+                    settings.frame_bits()
+                );
+                if let Err(e) = rx.set_config(&(&settings).into()) {
+                    warn!(
+                        "usb: failed to apply line coding: {}",
+                        defmt::Debug2Format(&e)
+                    );
+                }
+            }
+            Either4::Fourth(lines) => {
+                apply_module_pins(module_pins(lines), module);
+                if !session_active(lines) {
+                    break;
+                }
+            }
+        }
+    }
+
+    BRIDGING.store(false, Ordering::Relaxed);
+    apply_module_pins(ModulePinState::RELEASED, module);
+    let _ = tx.flush().await;
+    if let Err(e) = rx.set_config(&restore.into()) {
+        warn!(
+            "usb: failed to restore UART config: {}",
+            defmt::Debug2Format(&e)
+        );
+    }
+    stats.ended_ms = Instant::now().as_millis();
+    info!("usb: bridge session closed");
+
+    stats
+}
@@ -0,0 +1,199 @@
+//! Writers for decoded captures: pcap, for Wireshark and the like, and text.
+//!
+//! pcap has no link type for a UART, so packets use `LINKTYPE_USER0` and start with two bytes of
+//! their own: the direction (0 = rx, 1 = tx) and the receive error flags, as in the stream.
+//! Timestamps are the board's uptime, counted from the epoch.
+
+use crate::capture::{
+    export::{DecodeError, Decoder, Record},
+    ring::Direction,
+};
+use std::{fmt::Write as _, io};
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_VERSION: (u16, u16) = (2, 4);
+const LINKTYPE_USER0: u32 = 147;
+/// Direction and error flags in front of each packet's data.
+const PSEUDO_HEADER_LEN: usize = 2;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Decode(DecodeError),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Self {
+        Error::Decode(e)
+    }
+}
+
+/// The stream in `input`, skipping whatever the console sent ahead of it.
+pub fn decoder(input: &[u8]) -> Result<Decoder<'_>, DecodeError> {
+    let magic = &crate::capture::export::MAGIC;
+    let start = input
+        .windows(magic.len())
+        .position(|w| w == magic)
+        .ok_or(DecodeError::BadMagic)?;
+    Decoder::new(&input[start..])
+}
+
+/// Write every record as a pcap packet, returning how many there were.
+pub fn pcap<W: io::Write>(decoder: Decoder<'_>, out: &mut W) -> Result<usize, Error> {
+    out.write_all(&PCAP_MAGIC.to_le_bytes())?;
+    out.write_all(&PCAP_VERSION.0.to_le_bytes())?;
+    out.write_all(&PCAP_VERSION.1.to_le_bytes())?;
+    // Time zone and timestamp accuracy.
+    out.write_all(&[0; 8])?;
+    out.write_all(&(u16::MAX as u32).to_le_bytes())?;
+    out.write_all(&LINKTYPE_USER0.to_le_bytes())?;
+
+    let mut n = 0;
+    for record in decoder {
+        let record = record?;
+        let len = (PSEUDO_HEADER_LEN + record.data.len()) as u32;
+        let secs = (record.timestamp_us / 1_000_000) as u32;
+        let us = (record.timestamp_us % 1_000_000) as u32;
+        for field in [secs, us, len, len] {
+            out.write_all(&field.to_le_bytes())?;
+        }
+        out.write_all(&[direction_byte(record.direction), record.errors.bits()])?;
+        out.write_all(record.data)?;
+        n += 1;
+    }
+    Ok(n)
+}
+
+/// Write every record as a line: time, direction, hex, printable ASCII and any errors.
+pub fn text<W: io::Write>(decoder: Decoder<'_>, out: &mut W) -> Result<usize, Error> {
+    if decoder.dropped() > 0 {
+        writeln!(
+            out,
+            "# {} records overwritten before the export",
+            decoder.dropped()
+        )?;
+    }
+    let mut n = 0;
+    for record in decoder {
+        writeln!(out, "{}", line(&record?))?;
+        n += 1;
+    }
+    Ok(n)
+}
+
+fn direction_byte(direction: Direction) -> u8 {
+    match direction {
+        Direction::Rx => 0,
+        Direction::Tx => 1,
+    }
+}
+
+fn line(record: &Record<'_>) -> String {
+    let mut line = format!(
+        "{}.{:06} {}",
+        record.timestamp_us / 1_000_000,
+        record.timestamp_us % 1_000_000,
+        match record.direction {
+            Direction::Rx => "RX",
+            Direction::Tx => "TX",
+        }
+    );
+    for byte in record.data {
+        let _ = write!(line, " {byte:02x}");
+    }
+    line.push_str("  |");
+    line.extend(record.data.iter().map(|&b| {
+        if b.is_ascii_graphic() || b == b' ' {
+            b as char
+        } else {
+            '.'
+        }
+    }));
+    line.push('|');
+    if !record.errors.is_empty() {
+        let _ = write!(line, " [{}]", record.errors);
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        capture::{export::Encoder, ring::RecordHeader},
+        uart::ErrorFlags,
+    };
+
+    fn stream() -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        let mut out = b"capture export\r\n".to_vec();
+        out.extend_from_slice(encoder.start(3));
+        for (direction, errors, timestamp_us, data) in [
+            (Direction::Tx, ErrorFlags::NONE, 1_500_000, &b"AT\r"[..]),
+            (Direction::Rx, ErrorFlags::FRAMING, 2_000_001, &b"OK"[..]),
+        ] {
+            let header = RecordHeader {
+                direction,
+                errors,
+                timestamp_us,
+                len: data.len() as u16,
+            };
+            out.extend_from_slice(encoder.record(&header));
+            out.extend_from_slice(data);
+        }
+        out.extend_from_slice(encoder.end());
+        out.extend_from_slice(b"OK\r\n");
+        out
+    }
+
+    #[test]
+    fn writes_text() {
+        let input = stream();
+        let mut out = Vec::new();
+        assert_eq!(text(decoder(&input).unwrap(), &mut out).unwrap(), 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "# 3 records overwritten before the export\n\
+             1.500000 TX 41 54 0d  |AT.|\n\
+             2.000001 RX 4f 4b  |OK| [framing]\n"
+        );
+    }
+
+    #[test]
+    fn writes_pcap() {
+        let input = stream();
+        let mut out = Vec::new();
+        assert_eq!(pcap(decoder(&input).unwrap(), &mut out).unwrap(), 2);
+        assert_eq!(out[..4], [0xd4, 0xc3, 0xb2, 0xa1]);
+        assert_eq!(out[20..24], LINKTYPE_USER0.to_le_bytes());
+
+        let packet = &out[24..];
+        let field = |i: usize| u32::from_le_bytes(packet[i * 4..i * 4 + 4].try_into().unwrap());
+        assert_eq!((field(0), field(1), field(2), field(3)), (1, 500_000, 5, 5));
+        assert_eq!(packet[16..21], [1, 0, b'A', b'T', b'\r']);
+
+        let packet = &packet[21..];
+        assert_eq!(packet[..4], 2u32.to_le_bytes());
+        assert_eq!(packet[4..8], 1u32.to_le_bytes());
+        assert_eq!(packet[16..20], [0, ErrorFlags::FRAMING.bits(), b'O', b'K']);
+        assert_eq!(packet.len(), 20);
+    }
+
+    #[test]
+    fn reports_bad_streams() {
+        assert!(matches!(decoder(b"OK\r\n"), Err(DecodeError::BadMagic)));
+        let input = stream();
+        let truncated = &input[..input.len() - 6];
+        let mut out = Vec::new();
+        assert!(matches!(
+            text(decoder(truncated).unwrap(), &mut out),
+            Err(Error::Decode(DecodeError::Truncated))
+        ));
+    }
+}
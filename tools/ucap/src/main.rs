@@ -0,0 +1,84 @@
+//! Converts what `capture export` wrote to the host port into a pcap file or a text log.
+//!
+//! ```text
+//! ucap pcap capture.bin capture.pcap
+//! ucap text capture.bin [capture.txt]
+//! ```
+//!
+//! The stream decoder is the firmware's own, in `rtos/src/capture/export.rs`.
+
+extern crate alloc;
+
+mod convert;
+
+// The firmware's modules, of which only the decoder is needed here.
+#[allow(dead_code)]
+#[path = "../../../rtos/src/capture"]
+mod capture {
+    pub mod export;
+    pub mod ring;
+}
+
+#[allow(dead_code)]
+#[path = "../../../rtos/src/uart"]
+mod uart {
+    pub mod errors;
+
+    pub use errors::ErrorFlags;
+}
+
+use std::{
+    env, fs,
+    io::{self, BufWriter, Write},
+    process::ExitCode,
+};
+
+const USAGE: &str =
+    "usage: ucap pcap <capture.bin> <out.pcap>\n       ucap text <capture.bin> [out.txt]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (format, input, output) = match args.as_slice() {
+        [format, input, output] => (format.as_str(), input, Some(output)),
+        [format, input] if format == "text" => (format.as_str(), input, None),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(format, input, output.map(String::as_str)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("ucap: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(format: &str, input: &str, output: Option<&str>) -> Result<(), String> {
+    let data = fs::read(input).map_err(|e| format!("{input}: {e}"))?;
+    let decoder = convert::decoder(&data).map_err(|e| format!("{input}: {e:?}"))?;
+    let dropped = decoder.dropped();
+
+    let mut out: BufWriter<Box<dyn Write>> = BufWriter::new(match output {
+        Some(path) => Box::new(fs::File::create(path).map_err(|e| format!("{path}: {e}"))?),
+        None => Box::new(io::stdout()),
+    });
+    let result = match format {
+        "pcap" => convert::pcap(decoder, &mut out),
+        "text" => convert::text(decoder, &mut out),
+        _ => return Err(USAGE.into()),
+    };
+    // What was decoded before an error is still written out.
+    out.flush().map_err(|e| e.to_string())?;
+    let records = result.map_err(|e| match e {
+        convert::Error::Io(e) => e.to_string(),
+        convert::Error::Decode(e) => format!("{input}: {e:?}"),
+    })?;
+
+    if output.is_some() {
+        eprintln!("{records} records, {dropped} overwritten before the export");
+    }
+    Ok(())
+}